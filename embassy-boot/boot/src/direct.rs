@@ -0,0 +1,181 @@
+//! Direct boot: both partitions are bootable, and an update is booted where it was written.
+//!
+//! The bootloader records which partition to boot in the preference partition, as a log of
+//! 4 byte records: the last one written is the current one. Writing a record is atomic, so
+//! the preference can't be lost on power failure, except while the full log is erased to
+//! start over.
+
+use crate::{BootError, BootLoader, FlashConfig, FlashProvider, Partition, State, BOOT_MAGIC};
+
+/// Record: boot the active partition.
+const PREFER_ACTIVE: u32 = 0xB007_0000;
+/// Record: boot the DFU partition.
+const PREFER_DFU: u32 = 0xB007_0001;
+/// Set in a record while the other partition is tried.
+const TRIAL: u32 = 0x0000_0100;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) enum Slot {
+    Active,
+    Dfu,
+}
+
+impl Slot {
+    fn other(self) -> Self {
+        match self {
+            Slot::Active => Slot::Dfu,
+            Slot::Dfu => Slot::Active,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub(crate) struct Preference {
+    /// Partition booted unless an update is tried.
+    pub slot: Slot,
+    /// Whether the other partition was booted to try an update.
+    pub trial: bool,
+}
+
+impl Preference {
+    fn encode(self) -> u32 {
+        let record = match self.slot {
+            Slot::Active => PREFER_ACTIVE,
+            Slot::Dfu => PREFER_DFU,
+        };
+        if self.trial {
+            record | TRIAL
+        } else {
+            record
+        }
+    }
+
+    fn decode(record: u32) -> Option<Self> {
+        let slot = match record & !TRIAL {
+            PREFER_ACTIVE => Slot::Active,
+            PREFER_DFU => Slot::Dfu,
+            _ => return None,
+        };
+        Some(Self {
+            slot,
+            trial: record & TRIAL != 0,
+        })
+    }
+}
+
+impl<const PAGE_SIZE: usize> BootLoader<PAGE_SIZE> {
+    pub(crate) fn prepare_direct<P: FlashProvider>(
+        &mut self,
+        preference: Partition,
+        p: &mut P,
+    ) -> Result<State, BootError> {
+        let state = self.read_state(p.state())?;
+        let current = self.read_preference(preference, p.state())?;
+
+        let boot = match (&state, current.trial) {
+            (State::Swap, false) => {
+                trace!("Trying update");
+                self.write_preference(
+                    preference,
+                    Preference {
+                        slot: current.slot,
+                        trial: true,
+                    },
+                    p.state(),
+                )?;
+                current.slot.other()
+            }
+            (State::Swap, true) => {
+                // The update was tried, but the application failed to mark the boot as
+                // successful. Record the revert before resetting the magic: if power fails
+                // in between, the update is tried again rather than taken as booted.
+                trace!("Reverting");
+                self.write_preference(
+                    preference,
+                    Preference {
+                        slot: current.slot,
+                        trial: false,
+                    },
+                    p.state(),
+                )?;
+
+                let mut fstate = self.state.with_flash(p.state().flash());
+                fstate.write(0, &[0, 0, 0, 0])?;
+                fstate.erase(0, self.state.len() as u32)?;
+                fstate.write(0, &BOOT_MAGIC.to_le_bytes())?;
+                current.slot
+            }
+            (State::Boot, true) => {
+                trace!("Keeping update");
+                self.write_preference(
+                    preference,
+                    Preference {
+                        slot: current.slot.other(),
+                        trial: false,
+                    },
+                    p.state(),
+                )?;
+                current.slot.other()
+            }
+            (State::Boot, false) => current.slot,
+        };
+
+        self.boot = match boot {
+            Slot::Active => self.active,
+            Slot::Dfu => self.dfu,
+        };
+        Ok(state)
+    }
+
+    /// Read the current preference. Without any, the active partition is booted.
+    pub(crate) fn read_preference<F: FlashConfig>(
+        &mut self,
+        preference: Partition,
+        p: &mut F,
+    ) -> Result<Preference, BootError> {
+        let mut current = Preference {
+            slot: Slot::Active,
+            trial: false,
+        };
+        let mut flash = preference.with_flash(p.flash());
+        for offset in (0..preference.len()).step_by(4) {
+            let mut buf = [0; 4];
+            flash.read(offset as u32, &mut buf)?;
+            let record = u32::from_le_bytes(buf);
+            if record == 0xFFFF_FFFF {
+                break;
+            }
+            current = Preference::decode(record).ok_or(BootError::BadMagic)?;
+        }
+        Ok(current)
+    }
+
+    /// Append `new` to the log, erasing it first if it's full.
+    pub(crate) fn write_preference<F: FlashConfig>(
+        &mut self,
+        preference: Partition,
+        new: Preference,
+        p: &mut F,
+    ) -> Result<(), BootError> {
+        let mut flash = preference.with_flash(p.flash());
+        let mut next = None;
+        for offset in (0..preference.len()).step_by(4) {
+            let mut buf = [0; 4];
+            flash.read(offset as u32, &mut buf)?;
+            if buf == [0xFF, 0xFF, 0xFF, 0xFF] {
+                next = Some(offset);
+                break;
+            }
+        }
+
+        let offset = match next {
+            Some(offset) => offset,
+            None => {
+                flash.erase(0, preference.len() as u32)?;
+                0
+            }
+        };
+        flash.write(offset as u32, &new.encode().to_le_bytes())?;
+        Ok(())
+    }
+}
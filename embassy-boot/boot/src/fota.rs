@@ -0,0 +1,307 @@
+//! Firmware update over the air: stream a new image from any transport into the DFU partition.
+//!
+//! [`FotaUpdater`] buffers the incoming bytes into whole pages for [`FirmwareUpdater`],
+//! reports the progress, computes a digest of the image, and only marks the image to be
+//! swapped in once its length and digest were verified. Transports that push data (BLE
+//! characteristic writes, CoAP blocks...) call [`FotaUpdater::write`] for each chunk, while
+//! byte streams (TCP sockets, UARTs) implement [`FirmwareSource`] and use
+//! [`FotaUpdater::download`].
+//!
+//! ```ignore
+//! let mut fota = FotaUpdater::<_, 4096>::new(updater, Crc32::new());
+//! fota.set_expected_len(len)?;
+//! fota.download(&mut socket, &mut flash, &mut buf).await?;
+//! fota.verify(&expected_crc.to_le_bytes(), &mut flash).await?;
+//! fota.apply_and_reset(&mut flash, || cortex_m::peripheral::SCB::sys_reset()).await
+//! ```
+
+use core::convert::Infallible;
+use core::future::Future;
+
+use embedded_storage_async::nor_flash::AsyncNorFlash;
+
+use crate::{FirmwareUpdater, FlashError};
+
+/// Stream of bytes of a firmware image.
+pub trait FirmwareSource {
+    type Error;
+
+    type ReadFuture<'a>: Future<Output = Result<usize, Self::Error>> + 'a
+    where
+        Self: 'a;
+
+    /// Read bytes into `buf`, returning how many were read, or 0 at the end of the image.
+    fn read<'a>(&'a mut self, buf: &'a mut [u8]) -> Self::ReadFuture<'a>;
+}
+
+/// Digest of the image, checked before it's applied.
+///
+/// [`Crc32`] is provided. Cryptographic hashes, like SHA-256 from a hardware accelerator or
+/// a software crate, can implement this trait too.
+pub trait Digest {
+    type Output: AsRef<[u8]>;
+
+    fn update(&mut self, data: &[u8]);
+
+    fn finalize(&mut self) -> Self::Output;
+}
+
+/// CRC-32 (IEEE 802.3, as used by zlib and Ethernet), output in little endian.
+pub struct Crc32 {
+    crc: u32,
+}
+
+impl Crc32 {
+    pub const fn new() -> Self {
+        Self { crc: 0xffff_ffff }
+    }
+}
+
+impl Digest for Crc32 {
+    type Output = [u8; 4];
+
+    fn update(&mut self, data: &[u8]) {
+        for b in data {
+            self.crc ^= *b as u32;
+            for _ in 0..8 {
+                let mask = (self.crc & 1).wrapping_neg();
+                self.crc = (self.crc >> 1) ^ (0xedb8_8320 & mask);
+            }
+        }
+    }
+
+    fn finalize(&mut self) -> [u8; 4] {
+        (!self.crc).to_le_bytes()
+    }
+}
+
+/// Progress of an update, passed to the progress callback after each page written.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Progress {
+    /// Bytes received so far.
+    pub received: usize,
+    /// Expected length of the image, if known.
+    pub total: Option<usize>,
+}
+
+/// Error of a firmware update.
+#[derive(Debug)]
+pub enum FotaError<F, S = Infallible> {
+    Flash(FlashError<F>),
+    Source(S),
+    /// The image is larger than the DFU partition.
+    TooLarge,
+    /// The image is shorter or longer than expected.
+    LengthMismatch,
+    /// The digest of the image isn't the expected one.
+    DigestMismatch,
+    /// The image wasn't verified before being applied.
+    NotVerified,
+    /// The image was already finalized by [`FotaUpdater::verify`], it can't be written or
+    /// verified again.
+    Finalized,
+}
+
+impl<F, S> From<FlashError<F>> for FotaError<F, S> {
+    fn from(e: FlashError<F>) -> Self {
+        FotaError::Flash(e)
+    }
+}
+
+#[repr(align(4))]
+struct AlignedPage<const PAGE_SIZE: usize>([u8; PAGE_SIZE]);
+
+/// Writes a firmware image received in chunks of any size to the DFU partition.
+///
+/// `PAGE_SIZE` must be a multiple of the erase size of the flash.
+pub struct FotaUpdater<'a, D: Digest, const PAGE_SIZE: usize> {
+    updater: FirmwareUpdater,
+    digest: D,
+    page: AlignedPage<PAGE_SIZE>,
+    filled: usize,
+    /// Offset of the buffered page in the DFU partition.
+    offset: usize,
+    received: usize,
+    expected_len: Option<usize>,
+    /// The last page was written and the digest finalized.
+    finalized: bool,
+    verified: bool,
+    progress: Option<&'a mut dyn FnMut(Progress)>,
+}
+
+impl<'a, D: Digest, const PAGE_SIZE: usize> FotaUpdater<'a, D, PAGE_SIZE> {
+    pub fn new(updater: FirmwareUpdater, digest: D) -> Self {
+        Self {
+            updater,
+            digest,
+            page: AlignedPage([0; PAGE_SIZE]),
+            filled: 0,
+            offset: 0,
+            received: 0,
+            expected_len: None,
+            finalized: false,
+            verified: false,
+            progress: None,
+        }
+    }
+
+    /// Set the length of the image, when the transport announces it. The image is then
+    /// refused if it has another length.
+    pub fn set_expected_len<F, S>(&mut self, len: usize) -> Result<(), FotaError<F, S>> {
+        if len > self.updater.firmware_len() {
+            return Err(FotaError::TooLarge);
+        }
+        self.expected_len = Some(len);
+        Ok(())
+    }
+
+    /// Call `progress` after each page written to flash.
+    pub fn set_progress_callback(&mut self, progress: &'a mut dyn FnMut(Progress)) {
+        self.progress = Some(progress);
+    }
+
+    /// Bytes received so far.
+    pub fn received(&self) -> usize {
+        self.received
+    }
+
+    /// Write the next chunk of the image.
+    pub async fn write<F: AsyncNorFlash>(
+        &mut self,
+        mut data: &[u8],
+        flash: &mut F,
+    ) -> Result<(), FotaError<F::Error>> {
+        if self.finalized {
+            return Err(FotaError::Finalized);
+        }
+        let limit = self.expected_len.unwrap_or(self.updater.firmware_len());
+        if self.received + data.len() > limit {
+            return Err(if self.expected_len.is_some() {
+                FotaError::LengthMismatch
+            } else {
+                FotaError::TooLarge
+            });
+        }
+        self.digest.update(data);
+        self.received += data.len();
+
+        while !data.is_empty() {
+            let n = data.len().min(PAGE_SIZE - self.filled);
+            self.page.0[self.filled..self.filled + n].copy_from_slice(&data[..n]);
+            self.filled += n;
+            data = &data[n..];
+            if self.filled == PAGE_SIZE {
+                self.flush(flash).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the whole image from `source`, using `buf` for each read.
+    pub async fn download<F: AsyncNorFlash, S: FirmwareSource>(
+        &mut self,
+        source: &mut S,
+        flash: &mut F,
+        buf: &mut [u8],
+    ) -> Result<usize, FotaError<F::Error, S::Error>> {
+        loop {
+            let n = source.read(buf).await.map_err(FotaError::Source)?;
+            if n == 0 {
+                return Ok(self.received);
+            }
+            self.write(&buf[..n], flash)
+                .await
+                .map_err(FotaError::cast_source)?;
+        }
+    }
+
+    /// Write the last partial page, and check the length and the digest of the image.
+    ///
+    /// Once the length matches, the image is finalized: it can't be written to or verified
+    /// again, even if the digest doesn't match.
+    pub async fn verify<F: AsyncNorFlash>(
+        &mut self,
+        expected_digest: &[u8],
+        flash: &mut F,
+    ) -> Result<(), FotaError<F::Error>> {
+        if self.finalized {
+            return Err(FotaError::Finalized);
+        }
+        if let Some(len) = self.expected_len {
+            if self.received != len {
+                return Err(FotaError::LengthMismatch);
+            }
+        }
+        self.finalized = true;
+        if self.filled > 0 {
+            // Pad like erased flash.
+            self.page.0[self.filled..].fill(0xff);
+            self.flush(flash).await?;
+        }
+        if self.digest.finalize().as_ref() != expected_digest {
+            warn!("Firmware digest mismatch");
+            return Err(FotaError::DigestMismatch);
+        }
+        self.verified = true;
+        Ok(())
+    }
+
+    /// Mark the verified image to be swapped in at the next boot.
+    pub async fn apply<F: AsyncNorFlash>(
+        &mut self,
+        flash: &mut F,
+    ) -> Result<(), FotaError<F::Error>> {
+        if !self.verified {
+            return Err(FotaError::NotVerified);
+        }
+        self.updater.mark_update(flash).await?;
+        Ok(())
+    }
+
+    /// Mark the verified image to be swapped in, and reset into the bootloader with `reset`.
+    pub async fn apply_and_reset<F: AsyncNorFlash>(
+        &mut self,
+        flash: &mut F,
+        reset: impl FnOnce() -> !,
+    ) -> FotaError<F::Error> {
+        match self.apply(flash).await {
+            Ok(()) => {
+                info!("Firmware update applied, resetting");
+                reset()
+            }
+            Err(e) => e,
+        }
+    }
+
+    async fn flush<F: AsyncNorFlash>(&mut self, flash: &mut F) -> Result<(), FotaError<F::Error>> {
+        assert!(PAGE_SIZE % F::ERASE_SIZE == 0);
+        self.updater
+            .write_firmware(self.offset, &self.page.0, flash, PAGE_SIZE)
+            .await?;
+        self.offset += PAGE_SIZE;
+        self.filled = 0;
+
+        if let Some(progress) = &mut self.progress {
+            progress(Progress {
+                received: self.received,
+                total: self.expected_len,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<F> FotaError<F> {
+    fn cast_source<S>(self) -> FotaError<F, S> {
+        match self {
+            FotaError::Flash(e) => FotaError::Flash(e),
+            FotaError::Source(e) => match e {},
+            FotaError::TooLarge => FotaError::TooLarge,
+            FotaError::LengthMismatch => FotaError::LengthMismatch,
+            FotaError::DigestMismatch => FotaError::DigestMismatch,
+            FotaError::NotVerified => FotaError::NotVerified,
+            FotaError::Finalized => FotaError::Finalized,
+        }
+    }
+}
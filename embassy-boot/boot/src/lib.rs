@@ -1,6 +1,7 @@
 #![feature(type_alias_impl_trait)]
 #![feature(generic_associated_types)]
 #![no_std]
+mod direct;
 ///! embassy-boot is a bootloader and firmware updater for embedded devices with flash
 ///! storage implemented using embedded-storage
 ///!
@@ -8,14 +9,24 @@
 ///! ability to manage two flash banks with an active and a updatable part. It implements
 ///! a swap algorithm that is power-failure safe, and allows reverting to the previous
 ///! version of the firmware, should the application crash and fail to mark itself as booted.
+///! On parts with enough flash, the swap can be avoided by booting either bank directly, see
+///! [`Strategy::Direct`].
 ///!
 ///! This library is intended to be used by platform-specific bootloaders, such as embassy-boot-nrf,
 ///! which defines the limits and flash type for that particular platform.
 ///!
-mod fmt;
+#[macro_use]
+extern crate embassy_fmt;
+mod fota;
+mod recovery;
 
+use embassy_embedded_hal::flash::Partition as FlashPartition;
 use embedded_storage::nor_flash::{NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash};
-use embedded_storage_async::nor_flash::AsyncNorFlash;
+use embedded_storage_async::nor_flash::{AsyncNorFlash, AsyncReadNorFlash};
+
+pub use embassy_embedded_hal::flash::Error as FlashError;
+pub use fota::{Crc32, Digest, FirmwareSource, FotaError, FotaUpdater, Progress};
+pub use recovery::{RecoveryError, Transport};
 
 pub const BOOT_MAGIC: u32 = 0xD00DF00D;
 pub const SWAP_MAGIC: u32 = 0xF00FDAAD;
@@ -34,6 +45,12 @@ impl Partition {
     pub const fn len(&self) -> usize {
         self.to - self.from
     }
+
+    /// Access this partition of `flash`. Addresses are relative to the start of the partition,
+    /// and accesses outside of it fail.
+    pub fn with_flash<'a, F>(&self, flash: &'a mut F) -> FlashPartition<'a, F> {
+        FlashPartition::new(flash, self.from as u32, self.len() as u32)
+    }
 }
 
 #[derive(PartialEq, Debug)]
@@ -43,6 +60,32 @@ pub enum State {
     Swap,
 }
 
+/// How an update is installed.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Strategy {
+    /// The update is written to the DFU partition, and swapped page by page with the active
+    /// partition at the next boot. Only the active partition is booted.
+    ///
+    /// The DFU partition must be one page larger than the active partition.
+    Swap,
+    /// Both the active and DFU partitions are bootable: the update is written to the one that
+    /// isn't running, and the next boot just switches to it, without copying anything. A
+    /// reset during the update can't leave the device without a bootable image, and booting
+    /// an update is as fast as a normal boot, at the cost of twice the flash for the images.
+    ///
+    /// The application must be able to run from either partition: either position
+    /// independent, or linked twice, once for each partition, with the update built for the
+    /// partition it's written to. It finds which partition it's running from through its
+    /// own address, and creates its [`FirmwareUpdater`] with the other one. Updates are
+    /// marked and confirmed like with [`Strategy::Swap`].
+    ///
+    /// `preference` is where the bootloader records which partition to boot. It's at least
+    /// one page, in the same flash as the state partition, and the application doesn't
+    /// access it.
+    Direct { preference: Partition },
+}
+
 #[derive(PartialEq, Debug)]
 pub enum BootError {
     Flash(NorFlashErrorKind),
@@ -91,25 +134,47 @@ pub struct BootLoader<const PAGE_SIZE: usize> {
     active: Partition,
     // Location of the partition which will be swapped in when requested
     dfu: Partition,
+    strategy: Strategy,
+    // Partition to boot, chosen by prepare_boot
+    boot: Partition,
 }
 
 impl<const PAGE_SIZE: usize> BootLoader<PAGE_SIZE> {
-    pub fn new(active: Partition, dfu: Partition, state: Partition) -> Self {
+    pub fn new(active: Partition, dfu: Partition, state: Partition, strategy: Strategy) -> Self {
         assert_eq!(active.len() % PAGE_SIZE, 0);
         assert_eq!(dfu.len() % PAGE_SIZE, 0);
-        // DFU partition must have an extra page
-        assert!(dfu.len() - active.len() >= PAGE_SIZE);
-        // Ensure we have enough progress pages to store copy progress
-        assert!(active.len() / PAGE_SIZE >= (state.len() - 4) / PAGE_SIZE);
-        Self { active, dfu, state }
+        match strategy {
+            Strategy::Swap => {
+                // DFU partition must have an extra page
+                assert!(dfu.len() - active.len() >= PAGE_SIZE);
+                // Ensure we have enough progress pages to store copy progress
+                assert!(active.len() / PAGE_SIZE >= (state.len() - 4) / PAGE_SIZE);
+            }
+            Strategy::Direct { preference } => {
+                assert!(preference.len() >= PAGE_SIZE);
+                assert_eq!(preference.len() % PAGE_SIZE, 0);
+            }
+        }
+        Self {
+            active,
+            dfu,
+            state,
+            strategy,
+            boot: active,
+        }
     }
 
+    /// Address of the partition to boot, once [`prepare_boot`](Self::prepare_boot) chose it.
     pub fn boot_address(&self) -> usize {
-        self.active.from
+        self.boot.from
     }
 
     /// Perform necessary boot preparations like swapping images.
     ///
+    /// With [`Strategy::Direct`], nothing is copied: this chooses the partition to boot, the
+    /// one with the update if it was marked, and the previous one if the update was booted
+    /// and failed to mark itself as booted. The rest of this describes [`Strategy::Swap`].
+    ///
     /// The DFU partition is assumed to be 1 page bigger than the active partition for the swap
     /// algorithm to work correctly.
     ///
@@ -192,6 +257,10 @@ impl<const PAGE_SIZE: usize> BootLoader<PAGE_SIZE> {
     /// +-----------+--------------+--------+--------+--------+--------+
     ///
     pub fn prepare_boot<P: FlashProvider>(&mut self, p: &mut P) -> Result<State, BootError> {
+        if let Strategy::Direct { preference } = self.strategy {
+            return self.prepare_direct(preference, p);
+        }
+
         // Copy contents from partition N to active
         let state = self.read_state(p.state())?;
         match state {
@@ -208,10 +277,10 @@ impl<const PAGE_SIZE: usize> BootLoader<PAGE_SIZE> {
                     self.revert(p)?;
 
                     // Overwrite magic and reset progress
-                    let fstate = p.state().flash();
-                    fstate.write(self.state.from as u32, &[0, 0, 0, 0])?;
-                    fstate.erase(self.state.from as u32, self.state.to as u32)?;
-                    fstate.write(self.state.from as u32, &BOOT_MAGIC.to_le_bytes())?;
+                    let mut fstate = self.state.with_flash(p.state().flash());
+                    fstate.write(0, &[0, 0, 0, 0])?;
+                    fstate.erase(0, self.state.len() as u32)?;
+                    fstate.write(0, &BOOT_MAGIC.to_le_bytes())?;
                 }
             }
             _ => {}
@@ -228,10 +297,10 @@ impl<const PAGE_SIZE: usize> BootLoader<PAGE_SIZE> {
 
     fn current_progress<P: FlashConfig>(&mut self, p: &mut P) -> Result<usize, BootError> {
         let max_index = ((self.state.len() - 4) / 4) - 1;
-        let flash = p.flash();
+        let mut flash = self.state.with_flash(p.flash());
         for i in 0..max_index {
             let mut buf: [u8; 4] = [0; 4];
-            flash.read((self.state.from + 4 + i * 4) as u32, &mut buf)?;
+            flash.read((4 + i * 4) as u32, &mut buf)?;
             if buf == [0xFF, 0xFF, 0xFF, 0xFF] {
                 return Ok(i);
             }
@@ -240,18 +309,14 @@ impl<const PAGE_SIZE: usize> BootLoader<PAGE_SIZE> {
     }
 
     fn update_progress<P: FlashConfig>(&mut self, idx: usize, p: &mut P) -> Result<(), BootError> {
-        let flash = p.flash();
-        let w = self.state.from + 4 + idx * 4;
+        let mut flash = self.state.with_flash(p.flash());
+        let w = 4 + idx * 4;
         flash.write(w as u32, &[0, 0, 0, 0])?;
         Ok(())
     }
 
-    fn active_addr(&self, n: usize) -> usize {
-        self.active.from + n * PAGE_SIZE
-    }
-
-    fn dfu_addr(&self, n: usize) -> usize {
-        self.dfu.from + n * PAGE_SIZE
+    fn page_offset(n: usize) -> usize {
+        n * PAGE_SIZE
     }
 
     fn copy_page_once_to_active<P: FlashProvider>(
@@ -263,19 +328,19 @@ impl<const PAGE_SIZE: usize> BootLoader<PAGE_SIZE> {
     ) -> Result<(), BootError> {
         let mut buf: [u8; PAGE_SIZE] = [0; PAGE_SIZE];
         if self.current_progress(p.state())? <= idx {
+            let mut dfu = self.dfu.with_flash(p.dfu().flash());
             let mut offset = from_page;
             for chunk in buf.chunks_mut(P::DFU::BLOCK_SIZE) {
-                p.dfu().flash().read(offset as u32, chunk)?;
+                dfu.read(offset as u32, chunk)?;
                 offset += chunk.len();
             }
 
-            p.active()
-                .flash()
-                .erase(to_page as u32, (to_page + PAGE_SIZE) as u32)?;
+            let mut active = self.active.with_flash(p.active().flash());
+            active.erase(to_page as u32, (to_page + PAGE_SIZE) as u32)?;
 
             let mut offset = to_page;
             for chunk in buf.chunks(P::ACTIVE::BLOCK_SIZE) {
-                p.active().flash().write(offset as u32, &chunk)?;
+                active.write(offset as u32, &chunk)?;
                 offset += chunk.len();
             }
             self.update_progress(idx, p.state())?;
@@ -292,19 +357,19 @@ impl<const PAGE_SIZE: usize> BootLoader<PAGE_SIZE> {
     ) -> Result<(), BootError> {
         let mut buf: [u8; PAGE_SIZE] = [0; PAGE_SIZE];
         if self.current_progress(p.state())? <= idx {
+            let mut active = self.active.with_flash(p.active().flash());
             let mut offset = from_page;
             for chunk in buf.chunks_mut(P::ACTIVE::BLOCK_SIZE) {
-                p.active().flash().read(offset as u32, chunk)?;
+                active.read(offset as u32, chunk)?;
                 offset += chunk.len();
             }
 
-            p.dfu()
-                .flash()
-                .erase(to_page as u32, (to_page + PAGE_SIZE) as u32)?;
+            let mut dfu = self.dfu.with_flash(p.dfu().flash());
+            dfu.erase(to_page as u32, (to_page + PAGE_SIZE) as u32)?;
 
             let mut offset = to_page;
             for chunk in buf.chunks(P::DFU::BLOCK_SIZE) {
-                p.dfu().flash().write(offset as u32, chunk)?;
+                dfu.write(offset as u32, chunk)?;
                 offset += chunk.len();
             }
             self.update_progress(idx, p.state())?;
@@ -317,14 +382,14 @@ impl<const PAGE_SIZE: usize> BootLoader<PAGE_SIZE> {
         // trace!("Page count: {}", page_count);
         for page in 0..page_count {
             // Copy active page to the 'next' DFU page.
-            let active_page = self.active_addr(page_count - 1 - page);
-            let dfu_page = self.dfu_addr(page_count - page);
+            let active_page = Self::page_offset(page_count - 1 - page);
+            let dfu_page = Self::page_offset(page_count - page);
             info!("Copy active {} to dfu {}", active_page, dfu_page);
             self.copy_page_once_to_dfu(page * 2, active_page, dfu_page, p)?;
 
             // Copy DFU page to the active page
-            let active_page = self.active_addr(page_count - 1 - page);
-            let dfu_page = self.dfu_addr(page_count - 1 - page);
+            let active_page = Self::page_offset(page_count - 1 - page);
+            let dfu_page = Self::page_offset(page_count - 1 - page);
             info!("Copy dfy {} to active {}", dfu_page, active_page);
             self.copy_page_once_to_active(page * 2 + 1, dfu_page, active_page, p)?;
         }
@@ -336,13 +401,13 @@ impl<const PAGE_SIZE: usize> BootLoader<PAGE_SIZE> {
         let page_count = self.active.len() / PAGE_SIZE;
         for page in 0..page_count {
             // Copy the bad active page to the DFU page
-            let active_page = self.active_addr(page);
-            let dfu_page = self.dfu_addr(page);
+            let active_page = Self::page_offset(page);
+            let dfu_page = Self::page_offset(page);
             self.copy_page_once_to_dfu(page_count * 2 + page * 2, active_page, dfu_page, p)?;
 
             // Copy the DFU page back to the active page
-            let active_page = self.active_addr(page);
-            let dfu_page = self.dfu_addr(page + 1);
+            let active_page = Self::page_offset(page);
+            let dfu_page = Self::page_offset(page + 1);
             self.copy_page_once_to_active(page_count * 2 + page * 2 + 1, dfu_page, active_page, p)?;
         }
 
@@ -351,8 +416,8 @@ impl<const PAGE_SIZE: usize> BootLoader<PAGE_SIZE> {
 
     fn read_state<P: FlashConfig>(&mut self, p: &mut P) -> Result<State, BootError> {
         let mut magic: [u8; 4] = [0; 4];
-        let flash = p.flash();
-        flash.read(self.state.from as u32, &mut magic)?;
+        let mut flash = self.state.with_flash(p.flash());
+        flash.read(0, &mut magic)?;
 
         match u32::from_le_bytes(magic) {
             SWAP_MAGIC => Ok(State::Swap),
@@ -435,53 +500,49 @@ impl FirmwareUpdater {
     }
 
     /// Instruct bootloader that DFU should commence at next boot.
-    pub async fn mark_update<F: AsyncNorFlash>(&mut self, flash: &mut F) -> Result<(), F::Error> {
+    pub async fn mark_update<F: AsyncNorFlash>(
+        &mut self,
+        flash: &mut F,
+    ) -> Result<(), FlashError<F::Error>> {
         #[repr(align(4))]
         struct Aligned([u8; 4]);
 
+        let mut flash = self.state.with_flash(flash);
         let mut magic = Aligned([0; 4]);
-        flash.read(self.state.from as u32, &mut magic.0).await?;
+        flash.read(0, &mut magic.0).await?;
         let magic = u32::from_le_bytes(magic.0);
 
         if magic != SWAP_MAGIC {
-            flash
-                .write(self.state.from as u32, &Aligned([0; 4]).0)
-                .await?;
-            flash
-                .erase(self.state.from as u32, self.state.to as u32)
-                .await?;
+            flash.write(0, &Aligned([0; 4]).0).await?;
+            flash.erase(0, self.state.len() as u32).await?;
             trace!(
                 "Setting swap magic at {} to 0x{:x}, LE: 0x{:x}",
                 self.state.from,
                 &SWAP_MAGIC,
                 &SWAP_MAGIC.to_le_bytes()
             );
-            flash
-                .write(self.state.from as u32, &SWAP_MAGIC.to_le_bytes())
-                .await?;
+            flash.write(0, &SWAP_MAGIC.to_le_bytes()).await?;
         }
         Ok(())
     }
 
     /// Mark firmware boot successfully
-    pub async fn mark_booted<F: AsyncNorFlash>(&mut self, flash: &mut F) -> Result<(), F::Error> {
+    pub async fn mark_booted<F: AsyncNorFlash>(
+        &mut self,
+        flash: &mut F,
+    ) -> Result<(), FlashError<F::Error>> {
         #[repr(align(4))]
         struct Aligned([u8; 4]);
 
+        let mut flash = self.state.with_flash(flash);
         let mut magic = Aligned([0; 4]);
-        flash.read(self.state.from as u32, &mut magic.0).await?;
+        flash.read(0, &mut magic.0).await?;
         let magic = u32::from_le_bytes(magic.0);
 
         if magic != BOOT_MAGIC {
-            flash
-                .write(self.state.from as u32, &Aligned([0; 4]).0)
-                .await?;
-            flash
-                .erase(self.state.from as u32, self.state.to as u32)
-                .await?;
-            flash
-                .write(self.state.from as u32, &BOOT_MAGIC.to_le_bytes())
-                .await?;
+            flash.write(0, &Aligned([0; 4]).0).await?;
+            flash.erase(0, self.state.len() as u32).await?;
+            flash.write(0, &BOOT_MAGIC.to_le_bytes()).await?;
         }
         Ok(())
     }
@@ -493,29 +554,23 @@ impl FirmwareUpdater {
         data: &[u8],
         flash: &mut F,
         block_size: usize,
-    ) -> Result<(), F::Error> {
+    ) -> Result<(), FlashError<F::Error>> {
         assert!(data.len() >= F::ERASE_SIZE);
 
         trace!(
             "Writing firmware at offset 0x{:x} len {}",
-            self.dfu.from + offset,
+            offset,
             data.len()
         );
 
+        let mut flash = self.dfu.with_flash(flash);
         flash
-            .erase(
-                (self.dfu.from + offset) as u32,
-                (self.dfu.from + offset + data.len()) as u32,
-            )
+            .erase(offset as u32, (offset + data.len()) as u32)
             .await?;
 
-        trace!(
-            "Erased from {} to {}",
-            self.dfu.from + offset,
-            self.dfu.from + offset + data.len()
-        );
+        trace!("Erased from {} to {}", offset, offset + data.len());
 
-        let mut write_offset = self.dfu.from + offset;
+        let mut write_offset = offset;
         for chunk in data.chunks(block_size) {
             trace!("Wrote chunk at {}: {:?}", write_offset, chunk);
             flash.write(write_offset as u32, chunk).await?;
@@ -548,6 +603,11 @@ mod tests {
     use embedded_storage_async::nor_flash::AsyncReadNorFlash;
     use futures::executor::block_on;
 
+    use crate::recovery::{crc16, parse_size, ACK, CAN, CRC_MODE, EOT, NAK, SOH, STX};
+
+    extern crate std;
+    use std::vec::Vec;
+
     const STATE: Partition = Partition::new(0, 4096);
     const ACTIVE: Partition = Partition::new(4096, 61440);
     const DFU: Partition = Partition::new(61440, 122880);
@@ -556,7 +616,7 @@ mod tests {
     fn test_bad_magic() {
         let mut flash = MemFlash([0xff; 131072]);
 
-        let mut bootloader = BootLoader::<4096>::new(ACTIVE, DFU, STATE);
+        let mut bootloader = BootLoader::<4096>::new(ACTIVE, DFU, STATE, Strategy::Swap);
 
         assert_eq!(
             bootloader.prepare_boot(&mut flash),
@@ -569,7 +629,7 @@ mod tests {
         let mut flash = MemFlash([0xff; 131072]);
         flash.0[0..4].copy_from_slice(&BOOT_MAGIC.to_le_bytes());
 
-        let mut bootloader = BootLoader::<4096>::new(ACTIVE, DFU, STATE);
+        let mut bootloader = BootLoader::<4096>::new(ACTIVE, DFU, STATE, Strategy::Swap);
 
         assert_eq!(State::Boot, bootloader.prepare_boot(&mut flash).unwrap());
     }
@@ -586,7 +646,7 @@ mod tests {
             flash.0[i] = original[i - ACTIVE.from];
         }
 
-        let mut bootloader = BootLoader::<4096>::new(ACTIVE, DFU, STATE);
+        let mut bootloader = BootLoader::<4096>::new(ACTIVE, DFU, STATE, Strategy::Swap);
         let mut updater = FirmwareUpdater::new(DFU, STATE);
         for i in (DFU.from..DFU.to).step_by(4) {
             let base = i - DFU.from;
@@ -628,6 +688,439 @@ mod tests {
         assert_eq!(State::Boot, bootloader.prepare_boot(&mut flash).unwrap());
     }
 
+    #[test]
+    fn test_direct() {
+        const PREFERENCE: Partition = Partition::new(122880, 126976);
+        let mut flash = MemFlash([0xff; 131072]);
+        flash.0[0..4].copy_from_slice(&BOOT_MAGIC.to_le_bytes());
+
+        let mut bootloader = BootLoader::<4096>::new(
+            ACTIVE,
+            DFU,
+            STATE,
+            Strategy::Direct {
+                preference: PREFERENCE,
+            },
+        );
+        let mut updater = FirmwareUpdater::new(DFU, STATE);
+
+        assert_eq!(State::Boot, bootloader.prepare_boot(&mut flash).unwrap());
+        assert_eq!(ACTIVE.from, bootloader.boot_address());
+
+        // The update is tried
+        block_on(updater.mark_update(&mut flash)).unwrap();
+        assert_eq!(State::Swap, bootloader.prepare_boot(&mut flash).unwrap());
+        assert_eq!(DFU.from, bootloader.boot_address());
+
+        // It wasn't marked as booted, running again should cause a revert
+        assert_eq!(State::Swap, bootloader.prepare_boot(&mut flash).unwrap());
+        assert_eq!(ACTIVE.from, bootloader.boot_address());
+        assert_eq!(State::Boot, bootloader.prepare_boot(&mut flash).unwrap());
+        assert_eq!(ACTIVE.from, bootloader.boot_address());
+
+        // Try again, and mark as booted: the update is kept
+        block_on(updater.mark_update(&mut flash)).unwrap();
+        assert_eq!(State::Swap, bootloader.prepare_boot(&mut flash).unwrap());
+        block_on(updater.mark_booted(&mut flash)).unwrap();
+        assert_eq!(State::Boot, bootloader.prepare_boot(&mut flash).unwrap());
+        assert_eq!(DFU.from, bootloader.boot_address());
+        assert_eq!(State::Boot, bootloader.prepare_boot(&mut flash).unwrap());
+        assert_eq!(DFU.from, bootloader.boot_address());
+    }
+
+    #[test]
+    fn test_crc32() {
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc.finalize(), 0xCBF43926u32.to_le_bytes());
+    }
+
+    fn crc32(data: &[u8]) -> [u8; 4] {
+        let mut crc = Crc32::new();
+        crc.update(data);
+        crc.finalize()
+    }
+
+    #[test]
+    fn test_fota_chunked() {
+        let mut flash = MemFlash([0; 131072]);
+        let mut image = [0u8; 10000];
+        for (i, b) in image.iter_mut().enumerate() {
+            *b = (i ^ (i >> 8)) as u8;
+        }
+
+        let mut calls = [0; 4];
+        let mut n = 0;
+        let mut progress = |p: Progress| {
+            assert_eq!(p.total, Some(image.len()));
+            calls[n] = p.received;
+            n += 1;
+        };
+
+        let mut fota = FotaUpdater::<_, 4096>::new(FirmwareUpdater::new(DFU, STATE), Crc32::new());
+        fota.set_progress_callback(&mut progress);
+        fota.set_expected_len::<Infallible, Infallible>(image.len())
+            .unwrap();
+        // Chunks don't line up with the pages.
+        for chunk in image.chunks(1000) {
+            block_on(fota.write(chunk, &mut flash)).unwrap();
+        }
+        assert_eq!(fota.received(), image.len());
+        block_on(fota.verify(&crc32(&image), &mut flash)).unwrap();
+        block_on(fota.apply(&mut flash)).unwrap();
+        drop(fota);
+
+        // One call per page, the last one for the partial page written by verify.
+        assert_eq!(n, 3);
+        assert_eq!(calls[..3], [5000, 9000, 10000]);
+
+        assert_eq!(flash.0[DFU.from..DFU.from + image.len()], image[..]);
+        // The partial last page is padded like erased flash.
+        assert!(flash.0[DFU.from + image.len()..DFU.from + 3 * 4096]
+            .iter()
+            .all(|b| *b == 0xff));
+        // Nothing was written past it.
+        assert!(flash.0[DFU.from + 3 * 4096..DFU.to].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn test_fota_length() {
+        let mut flash = MemFlash([0xff; 131072]);
+        let mut fota = FotaUpdater::<_, 4096>::new(FirmwareUpdater::new(DFU, STATE), Crc32::new());
+        assert!(matches!(
+            fota.set_expected_len::<Infallible, Infallible>(DFU.len() + 1),
+            Err(FotaError::TooLarge)
+        ));
+
+        // Without an expected length, the image is limited by the DFU partition.
+        let page = [0u8; 4096];
+        for _ in 0..DFU.len() / page.len() {
+            block_on(fota.write(&page, &mut flash)).unwrap();
+        }
+        assert!(matches!(
+            block_on(fota.write(&[0], &mut flash)),
+            Err(FotaError::TooLarge)
+        ));
+
+        // Longer than announced.
+        let mut fota = FotaUpdater::<_, 4096>::new(FirmwareUpdater::new(DFU, STATE), Crc32::new());
+        fota.set_expected_len::<Infallible, Infallible>(100)
+            .unwrap();
+        block_on(fota.write(&[0; 60], &mut flash)).unwrap();
+        assert!(matches!(
+            block_on(fota.write(&[0; 41], &mut flash)),
+            Err(FotaError::LengthMismatch)
+        ));
+
+        // Shorter than announced.
+        assert!(matches!(
+            block_on(fota.verify(&crc32(&[0; 60]), &mut flash)),
+            Err(FotaError::LengthMismatch)
+        ));
+        // It can still be completed.
+        block_on(fota.write(&[0; 40], &mut flash)).unwrap();
+        block_on(fota.verify(&crc32(&[0; 100]), &mut flash)).unwrap();
+    }
+
+    #[test]
+    fn test_fota_digest() {
+        let mut flash = MemFlash([0xff; 131072]);
+        let image = [0x5a; 5000];
+
+        let mut fota = FotaUpdater::<_, 4096>::new(FirmwareUpdater::new(DFU, STATE), Crc32::new());
+        block_on(fota.write(&image, &mut flash)).unwrap();
+        assert!(matches!(
+            block_on(fota.apply(&mut flash)),
+            Err(FotaError::NotVerified)
+        ));
+
+        let mut digest = crc32(&image);
+        digest[0] ^= 1;
+        assert!(matches!(
+            block_on(fota.verify(&digest, &mut flash)),
+            Err(FotaError::DigestMismatch)
+        ));
+        assert!(matches!(
+            block_on(fota.apply(&mut flash)),
+            Err(FotaError::NotVerified)
+        ));
+
+        // The digest was finalized, so the right one can't be checked afterwards.
+        assert!(matches!(
+            block_on(fota.verify(&crc32(&image), &mut flash)),
+            Err(FotaError::Finalized)
+        ));
+        // The update wasn't marked.
+        assert_eq!(flash.0[STATE.from..STATE.from + 4], [0xff; 4]);
+    }
+
+    #[test]
+    fn test_fota_finalized() {
+        let mut flash = MemFlash([0xff; 131072]);
+        let image = [0xa5; 100];
+
+        let mut fota = FotaUpdater::<_, 4096>::new(FirmwareUpdater::new(DFU, STATE), Crc32::new());
+        block_on(fota.write(&image, &mut flash)).unwrap();
+        block_on(fota.verify(&crc32(&image), &mut flash)).unwrap();
+
+        assert!(matches!(
+            block_on(fota.write(&image, &mut flash)),
+            Err(FotaError::Finalized)
+        ));
+        assert!(matches!(
+            block_on(fota.verify(&crc32(&image), &mut flash)),
+            Err(FotaError::Finalized)
+        ));
+        // Still verified.
+        block_on(fota.apply(&mut flash)).unwrap();
+        assert_eq!(flash.0[DFU.from..DFU.from + 100], image[..]);
+    }
+
+    /// Sender replaying a script, whatever the receiver answers.
+    struct Script {
+        input: Vec<u8>,
+        pos: usize,
+        output: Vec<u8>,
+    }
+
+    impl Script {
+        fn new(blocks: &[&[u8]]) -> Self {
+            Self {
+                input: blocks.concat(),
+                pos: 0,
+                output: Vec::new(),
+            }
+        }
+    }
+
+    impl Transport for Script {
+        type Error = Infallible;
+
+        fn read(&mut self, _timeout_ms: u32) -> Result<Option<u8>, Infallible> {
+            let b = self.input.get(self.pos).copied();
+            self.pos += 1;
+            // Past the script, every read times out.
+            assert!(self.pos < self.input.len() + 100, "receiver never gave up");
+            Ok(b)
+        }
+
+        fn write(&mut self, data: &[u8]) -> Result<(), Infallible> {
+            self.output.extend_from_slice(data);
+            Ok(())
+        }
+    }
+
+    fn block(num: u8, data: &[u8]) -> Vec<u8> {
+        let header = if data.len() == 128 { SOH } else { STX };
+        let mut block = Vec::from([header, num, !num]);
+        block.extend_from_slice(data);
+        block.extend_from_slice(&crc16(data).to_be_bytes());
+        block
+    }
+
+    fn ymodem_header(name: &str, size: usize) -> Vec<u8> {
+        let mut data = [0; 128];
+        let info = std::format!("{}\0{} 0", name, size);
+        data[..info.len()].copy_from_slice(info.as_bytes());
+        block(0, &data)
+    }
+
+    fn image(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i ^ (i >> 8)) as u8).collect()
+    }
+
+    fn recover(
+        flash: &mut MemFlash,
+        script: &mut Script,
+    ) -> Result<usize, RecoveryError<Infallible>> {
+        let mut bootloader = BootLoader::<4096>::new(ACTIVE, DFU, STATE, Strategy::Swap);
+        bootloader.recover(&mut SingleFlashProvider::new(flash), script)
+    }
+
+    #[test]
+    fn test_recovery_xmodem() {
+        let mut flash = MemFlash([0xff; 131072]);
+        let image = image(384);
+        let mut script = Script::new(&[
+            &block(1, &image[..128]),
+            &block(2, &image[128..256]),
+            &block(3, &image[256..]),
+            &[EOT, EOT],
+        ]);
+
+        let mut bootloader = BootLoader::<4096>::new(ACTIVE, DFU, STATE, Strategy::Swap);
+        assert!(bootloader
+            .is_active_erased(&mut SingleFlashProvider::new(&mut flash))
+            .unwrap());
+        assert_eq!(
+            bootloader.recover(&mut SingleFlashProvider::new(&mut flash), &mut script),
+            Ok(384)
+        );
+        // The first EOT is NAKed, in case it's noise.
+        assert_eq!(script.output, [CRC_MODE, ACK, ACK, ACK, NAK, ACK]);
+        assert_eq!(flash.0[DFU.from..DFU.from + 384], image[..]);
+        assert!(flash.0[DFU.from + 384..DFU.to].iter().all(|b| *b == 0xff));
+        assert_eq!(
+            flash.0[STATE.from..STATE.from + 4],
+            SWAP_MAGIC.to_le_bytes()
+        );
+
+        // The update is pending: the bootloader must not enter the recovery again.
+        assert!(!bootloader
+            .is_active_erased(&mut SingleFlashProvider::new(&mut flash))
+            .unwrap());
+        assert_eq!(
+            bootloader.prepare_boot(&mut SingleFlashProvider::new(&mut flash)),
+            Ok(State::Swap)
+        );
+        assert_eq!(flash.0[ACTIVE.from..ACTIVE.from + 384], image[..]);
+        assert!(!bootloader
+            .is_active_erased(&mut SingleFlashProvider::new(&mut flash))
+            .unwrap());
+    }
+
+    #[test]
+    fn test_recovery_1k() {
+        let mut flash = MemFlash([0xff; 131072]);
+        let image = image(5 * 1024);
+        let blocks: Vec<_> = image
+            .chunks(1024)
+            .enumerate()
+            .map(|(i, data)| block(i as u8 + 1, data))
+            .collect();
+        let mut input: Vec<&[u8]> = blocks.iter().map(|b| &b[..]).collect();
+        input.push(&[EOT, EOT]);
+        let mut script = Script::new(&input);
+
+        assert_eq!(recover(&mut flash, &mut script), Ok(image.len()));
+        assert_eq!(flash.0[DFU.from..DFU.from + image.len()], image[..]);
+        // Past the image, up to the end of its last page.
+        assert!(flash.0[DFU.from + image.len()..DFU.from + 2 * 4096]
+            .iter()
+            .all(|b| *b == 0xff));
+    }
+
+    #[test]
+    fn test_recovery_ymodem() {
+        let mut flash = MemFlash([0x00; 131072]);
+        let image = image(1500);
+        // The last block is padded with SUB.
+        let mut last = [0x1a; 1024];
+        last[..1500 - 1024].copy_from_slice(&image[1024..]);
+        let mut script = Script::new(&[
+            &ymodem_header("app.bin", image.len()),
+            &block(1, &image[..1024]),
+            &block(2, &last),
+            &[EOT, EOT],
+            // End of the batch.
+            &block(0, &[0; 128]),
+        ]);
+
+        assert_eq!(recover(&mut flash, &mut script), Ok(1500));
+        assert_eq!(
+            script.output,
+            [CRC_MODE, ACK, CRC_MODE, ACK, ACK, NAK, ACK, CRC_MODE, ACK]
+        );
+        assert_eq!(flash.0[DFU.from..DFU.from + 1500], image[..]);
+        // Neither the padding, nor what was in the partition before, is kept.
+        assert!(flash.0[DFU.from + 1500..DFU.from + ACTIVE.len()]
+            .iter()
+            .all(|b| *b == 0xff));
+    }
+
+    #[test]
+    fn test_recovery_duplicate() {
+        let mut flash = MemFlash([0xff; 131072]);
+        let image = image(256);
+        // Our ACK of the first block was lost: the sender repeats it.
+        let mut script = Script::new(&[
+            &block(1, &image[..128]),
+            &block(1, &image[..128]),
+            &block(2, &image[128..]),
+            &[EOT, EOT],
+        ]);
+
+        assert_eq!(recover(&mut flash, &mut script), Ok(256));
+        assert_eq!(script.output, [CRC_MODE, ACK, ACK, ACK, NAK, ACK]);
+        assert_eq!(flash.0[DFU.from..DFU.from + 256], image[..]);
+
+        // Same after a YMODEM header.
+        let mut flash = MemFlash([0xff; 131072]);
+        let header = ymodem_header("app.bin", 128);
+        let mut script = Script::new(&[
+            &header,
+            &header,
+            &block(1, &image[..128]),
+            &[EOT, EOT],
+            &block(0, &[0; 128]),
+        ]);
+        assert_eq!(recover(&mut flash, &mut script), Ok(128));
+        assert_eq!(script.output[..5], [CRC_MODE, ACK, CRC_MODE, ACK, CRC_MODE]);
+
+        // Any other block out of sequence is an error.
+        let mut flash = MemFlash([0xff; 131072]);
+        let mut script = Script::new(&[&block(1, &image[..128]), &block(3, &image[128..])]);
+        assert_eq!(
+            recover(&mut flash, &mut script),
+            Err(RecoveryError::Protocol)
+        );
+        assert!(script.output.ends_with(&[CAN, CAN]));
+    }
+
+    #[test]
+    fn test_recovery_cancel() {
+        let mut flash = MemFlash([0xff; 131072]);
+        let image = image(128);
+        let mut script = Script::new(&[&block(1, &image), &[CAN, CAN]]);
+        assert_eq!(
+            recover(&mut flash, &mut script),
+            Err(RecoveryError::Cancelled)
+        );
+        assert_eq!(script.output, [CRC_MODE, ACK]);
+        // The partial image isn't marked to be swapped in.
+        assert_eq!(flash.0[STATE.from..STATE.from + 4], [0xff; 4]);
+
+        // A lone CAN is taken as noise.
+        let mut script = Script::new(&[&[CAN, 0], &block(1, &image), &[EOT, EOT]]);
+        assert_eq!(recover(&mut flash, &mut script), Ok(128));
+    }
+
+    #[test]
+    fn test_recovery_too_large() {
+        let mut flash = MemFlash([0xff; 131072]);
+        let mut script = Script::new(&[&ymodem_header("app.bin", ACTIVE.len() + 1)]);
+        assert_eq!(
+            recover(&mut flash, &mut script),
+            Err(RecoveryError::TooLarge)
+        );
+        assert_eq!(script.output, [CRC_MODE, CAN, CAN]);
+
+        // Without a size, the image is limited by the active partition.
+        let data = [0x5a; 1024];
+        let blocks: Vec<_> = (0..=ACTIVE.len() / 1024)
+            .map(|i| block(i as u8 + 1, &data))
+            .collect();
+        let input: Vec<&[u8]> = blocks.iter().map(|b| &b[..]).collect();
+        let mut script = Script::new(&input);
+        assert_eq!(
+            recover(&mut flash, &mut script),
+            Err(RecoveryError::TooLarge)
+        );
+        assert!(script.output.ends_with(&[ACK, CAN, CAN]));
+        assert_eq!(flash.0[STATE.from..STATE.from + 4], [0xff; 4]);
+    }
+
+    #[test]
+    fn test_recovery_fields() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(parse_size(b"1500"), Some(1500));
+        assert_eq!(parse_size(b"1500 13774470011 100644"), Some(1500));
+        assert_eq!(parse_size(b""), None);
+        assert_eq!(parse_size(b" 1500"), None);
+        assert_eq!(parse_size(b"99999999999999999999999"), None);
+    }
+
     struct MemFlash([u8; 131072]);
 
     impl NorFlash for MemFlash {
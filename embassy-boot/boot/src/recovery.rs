@@ -0,0 +1,372 @@
+//! Serial recovery: receive a new application image with XMODEM or YMODEM.
+//!
+//! This lets a device be reflashed from any terminal program (`sx`/`sb` from lrzsz, Tera
+//! Term, minicom...) when the application can't update itself anymore, without a debugger.
+//! The platform bootloader decides when to enter it, typically when a button is held at reset
+//! or when the active partition is empty, and provides the serial port with [`Transport`].
+//!
+//! Both XMODEM-CRC with 128 and 1024 byte blocks, and single-file YMODEM batches are
+//! accepted. The image is written to the DFU partition, and marked to be swapped in like an
+//! update from [`FirmwareUpdater`](crate::FirmwareUpdater): it must mark itself as booted, or
+//! the previous image is restored on the next reset.
+//!
+//! With [`Strategy::Direct`](crate::Strategy::Direct), the image is also written to the DFU
+//! partition, and the active partition becomes the one restored if it fails.
+
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+
+use crate::direct::{Preference, Slot};
+use crate::{BootError, BootLoader, FlashConfig, FlashProvider, State, Strategy, SWAP_MAGIC};
+
+pub(crate) const SOH: u8 = 0x01;
+pub(crate) const STX: u8 = 0x02;
+pub(crate) const EOT: u8 = 0x04;
+pub(crate) const ACK: u8 = 0x06;
+pub(crate) const NAK: u8 = 0x15;
+pub(crate) const CAN: u8 = 0x18;
+/// Sent instead of NAK to ask for the CRC variant.
+pub(crate) const CRC_MODE: u8 = b'C';
+
+/// Timeout between the bytes of a block.
+const BYTE_TIMEOUT_MS: u32 = 1_000;
+/// Interval between the requests to start a transfer, sent until the sender starts.
+const START_INTERVAL_MS: u32 = 3_000;
+/// Timeout waiting for the next block once the transfer started.
+const BLOCK_TIMEOUT_MS: u32 = 10_000;
+/// Consecutive errors before the transfer is cancelled.
+const MAX_ERRORS: u8 = 10;
+
+/// Serial port used by the recovery.
+pub trait Transport {
+    type Error;
+
+    /// Receive a byte, waiting at most `timeout_ms` milliseconds. Returns `Ok(None)` on timeout.
+    fn read(&mut self, timeout_ms: u32) -> Result<Option<u8>, Self::Error>;
+
+    /// Send `data`, and wait until it's sent.
+    fn write(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// Error of the serial recovery.
+#[derive(PartialEq, Debug)]
+pub enum RecoveryError<E> {
+    Transport(E),
+    Boot(BootError),
+    /// The sender cancelled the transfer.
+    Cancelled,
+    /// The image is larger than the active partition.
+    TooLarge,
+    /// Too many errors in a row, or blocks out of sequence.
+    Protocol,
+}
+
+impl<E> From<BootError> for RecoveryError<E> {
+    fn from(e: BootError) -> Self {
+        RecoveryError::Boot(e)
+    }
+}
+
+impl<const PAGE_SIZE: usize> BootLoader<PAGE_SIZE> {
+    /// Receive a new image over `serial`, write it to the DFU partition, and mark it to be
+    /// swapped in by the next [`prepare_boot`](Self::prepare_boot). Returns the number of bytes
+    /// received.
+    ///
+    /// This waits for the sender as long as needed. Once the transfer started, it's
+    /// cancelled after repeated errors.
+    pub fn recover<P: FlashProvider, T: Transport>(
+        &mut self,
+        p: &mut P,
+        serial: &mut T,
+    ) -> Result<usize, RecoveryError<T::Error>> {
+        let res = self.receive(p, serial);
+        if let Err(RecoveryError::TooLarge | RecoveryError::Protocol) = res {
+            // Make sure the sender stops too.
+            let _ = serial.write(&[CAN, CAN]);
+        }
+        let len = res?;
+
+        if let Strategy::Direct { preference } = self.strategy {
+            self.write_preference(
+                preference,
+                Preference {
+                    slot: Slot::Active,
+                    trial: false,
+                },
+                p.state(),
+            )?;
+        }
+
+        let mut state = self.state.with_flash(p.state().flash());
+        state.write(0, &[0, 0, 0, 0]).map_err(BootError::from)?;
+        state
+            .erase(0, self.state.len() as u32)
+            .map_err(BootError::from)?;
+        state
+            .write(0, &SWAP_MAGIC.to_le_bytes())
+            .map_err(BootError::from)?;
+        Ok(len)
+    }
+
+    /// Whether the active partition is empty, for example after a failed recovery. The
+    /// platform bootloader can then enter the recovery instead of booting it.
+    ///
+    /// This is `false` while an update is pending, such as the image just received by
+    /// [`recover`](Self::recover): it's only swapped in by [`prepare_boot`](Self::prepare_boot).
+    ///
+    /// With [`Strategy::Direct`], this checks the partition that's booted unless an update
+    /// is tried.
+    pub fn is_active_erased<P: FlashProvider>(&mut self, p: &mut P) -> Result<bool, BootError> {
+        let swap = self.read_state(p.state())? == State::Swap;
+        let slot = match self.strategy {
+            Strategy::Direct { preference } => {
+                let current = self.read_preference(preference, p.state())?;
+                if swap && !current.trial {
+                    return Ok(false);
+                }
+                current.slot
+            }
+            Strategy::Swap => {
+                if swap && !self.is_swapped(p.state())? {
+                    return Ok(false);
+                }
+                Slot::Active
+            }
+        };
+
+        let mut buf = [0; 8];
+        match slot {
+            Slot::Active => self
+                .active
+                .with_flash(p.active().flash())
+                .read(0, &mut buf)?,
+            Slot::Dfu => self.dfu.with_flash(p.dfu().flash()).read(0, &mut buf)?,
+        }
+        Ok(buf.iter().all(|&b| b == 0xFF))
+    }
+
+    fn receive<P: FlashProvider, T: Transport>(
+        &mut self,
+        p: &mut P,
+        serial: &mut T,
+    ) -> Result<usize, RecoveryError<T::Error>> {
+        let mut block = [0; 1024];
+        let mut page = [0xFF; PAGE_SIZE];
+        let mut len = 0;
+        // Length announced by YMODEM: the last block is padded.
+        let mut size = None;
+        let mut expected: u8 = 1;
+        let mut started = false;
+        let mut errors = 0;
+        let mut eot = false;
+
+        serial
+            .write(&[CRC_MODE])
+            .map_err(RecoveryError::Transport)?;
+        loop {
+            let timeout = if started {
+                BLOCK_TIMEOUT_MS
+            } else {
+                START_INTERVAL_MS
+            };
+            let header = match serial.read(timeout).map_err(RecoveryError::Transport)? {
+                Some(b) => b,
+                None if !started => {
+                    serial
+                        .write(&[CRC_MODE])
+                        .map_err(RecoveryError::Transport)?;
+                    continue;
+                }
+                None => {
+                    errors += 1;
+                    if errors >= MAX_ERRORS {
+                        return Err(RecoveryError::Protocol);
+                    }
+                    serial.write(&[NAK]).map_err(RecoveryError::Transport)?;
+                    continue;
+                }
+            };
+
+            let n = match header {
+                SOH => 128,
+                STX => 1024,
+                EOT if started => {
+                    // NAK the first EOT, in case it's line noise: the sender repeats it.
+                    if !eot {
+                        eot = true;
+                        serial.write(&[NAK]).map_err(RecoveryError::Transport)?;
+                        continue;
+                    }
+                    serial.write(&[ACK]).map_err(RecoveryError::Transport)?;
+                    break;
+                }
+                CAN => {
+                    if serial
+                        .read(BYTE_TIMEOUT_MS)
+                        .map_err(RecoveryError::Transport)?
+                        == Some(CAN)
+                    {
+                        return Err(RecoveryError::Cancelled);
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+
+            let num = match read_block(serial, &mut block[..n]).map_err(RecoveryError::Transport)? {
+                Some(num) => num,
+                None => {
+                    errors += 1;
+                    if errors >= MAX_ERRORS {
+                        return Err(RecoveryError::Protocol);
+                    }
+                    purge(serial).map_err(RecoveryError::Transport)?;
+                    serial.write(&[NAK]).map_err(RecoveryError::Transport)?;
+                    continue;
+                }
+            };
+            errors = 0;
+
+            if num == 0 && !started {
+                // YMODEM header: file name, then its size in decimal.
+                let name_len = block[..n].iter().position(|&b| b == 0).unwrap_or(n);
+                if name_len == 0 {
+                    // Empty batch.
+                    serial.write(&[ACK]).map_err(RecoveryError::Transport)?;
+                    return Err(RecoveryError::Cancelled);
+                }
+                size = parse_size(block.get(name_len + 1..n).unwrap_or(&[]));
+                if matches!(size, Some(size) if size > self.active.len()) {
+                    return Err(RecoveryError::TooLarge);
+                }
+                started = true;
+                serial
+                    .write(&[ACK, CRC_MODE])
+                    .map_err(RecoveryError::Transport)?;
+                continue;
+            }
+
+            if num == expected {
+                started = true;
+                let data = match size {
+                    Some(size) => &block[..n.min(size - len)],
+                    None => &block[..n],
+                };
+                if len + data.len() > self.active.len() {
+                    return Err(RecoveryError::TooLarge);
+                }
+                for &b in data {
+                    page[len % PAGE_SIZE] = b;
+                    len += 1;
+                    if len % PAGE_SIZE == 0 {
+                        self.write_dfu_page(p, len - PAGE_SIZE, &page)?;
+                        page = [0xFF; PAGE_SIZE];
+                    }
+                }
+                expected = expected.wrapping_add(1);
+                serial.write(&[ACK]).map_err(RecoveryError::Transport)?;
+            } else if started && num == expected.wrapping_sub(1) {
+                // Our ACK was lost, and the sender repeated the block. After a YMODEM header,
+                // the sender also waits for the request to start the data.
+                let reply: &[u8] = if num == 0 { &[ACK, CRC_MODE] } else { &[ACK] };
+                serial.write(reply).map_err(RecoveryError::Transport)?;
+            } else {
+                return Err(RecoveryError::Protocol);
+            }
+        }
+
+        let mut end = len;
+        if len % PAGE_SIZE != 0 {
+            end += PAGE_SIZE - len % PAGE_SIZE;
+            self.write_dfu_page(p, end - PAGE_SIZE, &page)?;
+        }
+        // Erase what's left of a previous image, it would be swapped in too.
+        if end < self.active.len() {
+            let mut dfu = self.dfu.with_flash(p.dfu().flash());
+            dfu.erase(end as u32, self.active.len() as u32)
+                .map_err(BootError::from)?;
+        }
+
+        if size.is_some() {
+            // End of the YMODEM batch: the sender answers with an empty header. The image is
+            // complete already, so errors don't matter anymore.
+            let _ = serial.write(&[CRC_MODE]);
+            if let Ok(Some(SOH)) = serial.read(BLOCK_TIMEOUT_MS) {
+                if let Ok(Some(_)) = read_block(serial, &mut block[..128]) {
+                    let _ = serial.write(&[ACK]);
+                }
+            }
+        }
+
+        Ok(len)
+    }
+
+    fn write_dfu_page<P: FlashProvider>(
+        &mut self,
+        p: &mut P,
+        offset: usize,
+        page: &[u8; PAGE_SIZE],
+    ) -> Result<(), BootError> {
+        let mut dfu = self.dfu.with_flash(p.dfu().flash());
+        dfu.erase(offset as u32, (offset + PAGE_SIZE) as u32)?;
+
+        let mut offset = offset;
+        for chunk in page.chunks(P::DFU::BLOCK_SIZE) {
+            dfu.write(offset as u32, chunk)?;
+            offset += chunk.len();
+        }
+        Ok(())
+    }
+}
+
+/// Read the rest of a block after its header: block number, its complement, `data` and the
+/// CRC. Returns the block number, or `None` if the block is bad or incomplete.
+fn read_block<T: Transport>(serial: &mut T, data: &mut [u8]) -> Result<Option<u8>, T::Error> {
+    let mut num = [0; 2];
+    let mut crc = [0; 2];
+    for b in num.iter_mut().chain(data.iter_mut()).chain(crc.iter_mut()) {
+        match serial.read(BYTE_TIMEOUT_MS)? {
+            Some(x) => *b = x,
+            None => return Ok(None),
+        }
+    }
+    if num[0] != !num[1] || crc16(data) != u16::from_be_bytes(crc) {
+        return Ok(None);
+    }
+    Ok(Some(num[0]))
+}
+
+/// Discard the input until the line is idle, to resynchronize after a bad block.
+fn purge<T: Transport>(serial: &mut T) -> Result<(), T::Error> {
+    while serial.read(BYTE_TIMEOUT_MS)?.is_some() {}
+    Ok(())
+}
+
+/// Parse the size field of a YMODEM header, which may be followed by other fields.
+pub(crate) fn parse_size(field: &[u8]) -> Option<usize> {
+    let digits = field.iter().take_while(|b| b.is_ascii_digit());
+    let mut size: Option<usize> = None;
+    for &d in digits {
+        size = Some(
+            size.unwrap_or(0)
+                .checked_mul(10)?
+                .checked_add((d - b'0') as usize)?,
+        );
+    }
+    size
+}
+
+/// CRC-16/XMODEM.
+pub(crate) fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
@@ -2,14 +2,21 @@
 #![feature(generic_associated_types)]
 #![feature(type_alias_impl_trait)]
 
-mod fmt;
+#[macro_use]
+extern crate embassy_fmt;
+
+use core::sync::atomic::{compiler_fence, Ordering};
 
 pub use embassy_boot::{
-    FirmwareUpdater, FlashProvider, Partition, SingleFlashProvider, State, BOOT_MAGIC,
+    BootError, Crc32, Digest, FirmwareSource, FirmwareUpdater, FlashProvider, FotaError,
+    FotaUpdater, Partition, Progress, RecoveryError, SingleFlashProvider, State, Strategy,
+    Transport, BOOT_MAGIC,
 };
 use embassy_nrf::{
     nvmc::{Nvmc, PAGE_SIZE},
-    peripherals::WDT,
+    pac,
+    peripherals::{UARTE0, WDT},
+    uarte::{self, Uarte},
     wdt,
 };
 use embedded_storage::nor_flash::{ErrorType, NorFlash, ReadNorFlash};
@@ -53,13 +60,14 @@ impl BootLoader {
         trace!("DFU: 0x{:x} - 0x{:x}", dfu.from, dfu.to);
         trace!("STATE: 0x{:x} - 0x{:x}", state.from, state.to);
 
-        Self::new(active, dfu, state)
+        Self::new(active, dfu, state, Strategy::Swap)
     }
 
-    /// Create a new bootloader instance using the supplied partitions for active, dfu and state.
-    pub fn new(active: Partition, dfu: Partition, state: Partition) -> Self {
+    /// Create a new bootloader instance using the supplied partitions for active, dfu and state,
+    /// installing updates with `strategy`.
+    pub fn new(active: Partition, dfu: Partition, state: Partition, strategy: Strategy) -> Self {
         Self {
-            boot: embassy_boot::BootLoader::new(active, dfu, state),
+            boot: embassy_boot::BootLoader::new(active, dfu, state, strategy),
         }
     }
 
@@ -71,6 +79,24 @@ impl BootLoader {
         }
     }
 
+    /// Receive a new application image over serial with XMODEM or YMODEM, and mark it to be
+    /// swapped in. Reset afterwards: [`prepare`](Self::prepare) then swaps it in.
+    ///
+    /// This is meant for a flash provider without watchdog, since it waits for the sender as
+    /// long as needed.
+    pub fn recover<F: FlashProvider, T: Transport>(
+        &mut self,
+        flash: &mut F,
+        serial: &mut T,
+    ) -> Result<usize, RecoveryError<T::Error>> {
+        self.boot.recover(flash, serial)
+    }
+
+    /// Whether there's no application to boot.
+    pub fn is_active_erased<F: FlashProvider>(&mut self, flash: &mut F) -> Result<bool, BootError> {
+        self.boot.is_active_erased(flash)
+    }
+
     #[cfg(not(feature = "softdevice"))]
     pub unsafe fn load(&mut self, start: usize) -> ! {
         let mut p = cortex_m::Peripherals::steal();
@@ -185,6 +211,60 @@ impl<'d> ReadNorFlash for WatchdogFlash<'d> {
     }
 }
 
+/// Serial port for the recovery, on UARTE0.
+pub struct UarteRecovery<'d> {
+    uarte: Uarte<'d, UARTE0>,
+}
+
+impl<'d> UarteRecovery<'d> {
+    pub fn new(uarte: Uarte<'d, UARTE0>) -> Self {
+        Self { uarte }
+    }
+}
+
+impl<'d> Transport for UarteRecovery<'d> {
+    type Error = uarte::Error;
+
+    fn read(&mut self, timeout_ms: u32) -> Result<Option<u8>, Self::Error> {
+        // The blocking read of the driver can't time out: drive the reception of a single
+        // byte here, and stop it on timeout.
+        let r = unsafe { &*pac::UARTE0::ptr() };
+        let mut byte = [0u8];
+
+        r.rxd
+            .ptr
+            .write(|w| unsafe { w.ptr().bits(byte.as_mut_ptr() as u32) });
+        r.rxd.maxcnt.write(|w| unsafe { w.maxcnt().bits(1) });
+        r.events_endrx.reset();
+        r.events_rxto.reset();
+        compiler_fence(Ordering::SeqCst);
+        r.tasks_startrx.write(|w| unsafe { w.bits(1) });
+
+        // Poll every 10 us, with the CPU running at 64 MHz.
+        for _ in 0..timeout_ms * 100 {
+            if r.events_endrx.read().bits() != 0 {
+                compiler_fence(Ordering::SeqCst);
+                return Ok(Some(byte[0]));
+            }
+            cortex_m::asm::delay(640);
+        }
+
+        r.tasks_stoprx.write(|w| unsafe { w.bits(1) });
+        while r.events_endrx.read().bits() == 0 {}
+        compiler_fence(Ordering::SeqCst);
+        // A byte may have arrived while stopping.
+        if r.rxd.amount.read().amount().bits() == 1 {
+            Ok(Some(byte[0]))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+        self.uarte.blocking_write(data)
+    }
+}
+
 pub mod updater {
     use super::*;
     pub fn new() -> embassy_boot::FirmwareUpdater {
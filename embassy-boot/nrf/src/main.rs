@@ -11,7 +11,8 @@ use embassy_nrf::nvmc::Nvmc;
 
 #[entry]
 fn main() -> ! {
-    let p = embassy_nrf::init(Default::default());
+    #[allow(unused_mut)]
+    let mut p = embassy_nrf::init(Default::default());
 
     // Uncomment this if you are debugging the bootloader with debugger/RTT attached,
     // as it prevents a hard fault when accessing flash 'too early' after boot.
@@ -22,6 +23,34 @@ fn main() -> ! {
     */
 
     let mut bl = BootLoader::default();
+
+    // Serial recovery, entered when button 1 of the nRF52840 DK is held at reset, or when
+    // there's no application. The pins are those of the DK's virtual COM port: adjust them
+    // for your board.
+    #[cfg(feature = "serial-recovery")]
+    {
+        use embassy_nrf::gpio::{Input, Pull};
+        use embassy_nrf::{interrupt, uarte};
+
+        let button = Input::new(&mut p.P0_11, Pull::Up);
+        let mut nvmc = Nvmc::new(&mut p.NVMC);
+        let mut flash = SingleFlashProvider::new(&mut nvmc);
+        if button.is_low() || bl.is_active_erased(&mut flash).unwrap_or(true) {
+            let irq = interrupt::take!(UARTE0_UART0);
+            let uarte = uarte::Uarte::new(
+                &mut p.UARTE0,
+                irq,
+                &mut p.P0_08,
+                &mut p.P0_06,
+                Default::default(),
+            );
+            // The new image is swapped in on the next boot. If the transfer failed, this
+            // starts the recovery over.
+            let _ = bl.recover(&mut flash, &mut UarteRecovery::new(uarte));
+            cortex_m::peripheral::SCB::sys_reset();
+        }
+    }
+
     let start = bl.prepare(&mut SingleFlashProvider::new(&mut WatchdogFlash::start(
         Nvmc::new(p.NVMC),
         p.WDT,
@@ -0,0 +1,193 @@
+//! Adapters between embedded-hal traits.
+
+use core::future::Future;
+use embedded_hal_1::{i2c, serial, spi};
+
+/// Wrapper that implements async traits using blocking implementations.
+///
+/// This allows driver writers to depend on the async traits, while still supporting HALs
+/// (or peripherals) which only implement the blocking embedded-hal 1.0 traits, for example
+/// because they lack DMA support.
+///
+/// BlockingAsync implements every async trait that maps to a blocking trait implemented by
+/// the wrapped driver. The futures complete on their first poll, after blocking for the whole
+/// operation, so they don't let other tasks run in the meantime.
+pub struct BlockingAsync<T> {
+    wrapped: T,
+}
+
+impl<T> BlockingAsync<T> {
+    /// Create a new instance of a wrapper for a given peripheral.
+    pub fn new(wrapped: T) -> Self {
+        Self { wrapped }
+    }
+
+    /// Get a reference to the wrapped driver.
+    pub fn inner(&self) -> &T {
+        &self.wrapped
+    }
+
+    /// Get a mutable reference to the wrapped driver.
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.wrapped
+    }
+
+    /// Return the wrapped driver.
+    pub fn into_inner(self) -> T {
+        self.wrapped
+    }
+}
+
+//
+// I2C implementations
+//
+
+impl<T> i2c::ErrorType for BlockingAsync<T>
+where
+    T: i2c::ErrorType,
+{
+    type Error = T::Error;
+}
+
+impl<T> embedded_hal_async::i2c::I2c for BlockingAsync<T>
+where
+    T: i2c::blocking::I2c,
+{
+    type ReadFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+    fn read<'a>(&'a mut self, address: u8, buffer: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        async move { self.wrapped.read(address, buffer) }
+    }
+
+    type WriteFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+    fn write<'a>(&'a mut self, address: u8, bytes: &'a [u8]) -> Self::WriteFuture<'a> {
+        async move { self.wrapped.write(address, bytes) }
+    }
+
+    type WriteReadFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+    fn write_read<'a>(
+        &'a mut self,
+        address: u8,
+        bytes: &'a [u8],
+        buffer: &'a mut [u8],
+    ) -> Self::WriteReadFuture<'a> {
+        async move { self.wrapped.write_read(address, bytes, buffer) }
+    }
+
+    type TransactionFuture<'a, 'b> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a, 'b: 'a;
+
+    fn transaction<'a, 'b>(
+        &'a mut self,
+        address: u8,
+        operations: &'a mut [embedded_hal_async::i2c::Operation<'b>],
+    ) -> Self::TransactionFuture<'a, 'b> {
+        async move { self.wrapped.transaction(address, operations) }
+    }
+}
+
+//
+// SPI implementations
+//
+
+impl<T> spi::ErrorType for BlockingAsync<T>
+where
+    T: spi::ErrorType,
+{
+    type Error = T::Error;
+}
+
+impl<T> embedded_hal_async::spi::SpiBusFlush for BlockingAsync<T>
+where
+    T: spi::blocking::SpiBusFlush,
+{
+    type FlushFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+    fn flush<'a>(&'a mut self) -> Self::FlushFuture<'a> {
+        async move { self.wrapped.flush() }
+    }
+}
+
+impl<T> embedded_hal_async::spi::SpiBusWrite<u8> for BlockingAsync<T>
+where
+    T: spi::blocking::SpiBusWrite<u8> + spi::blocking::SpiBusFlush,
+{
+    type WriteFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+    fn write<'a>(&'a mut self, data: &'a [u8]) -> Self::WriteFuture<'a> {
+        async move { self.wrapped.write(data) }
+    }
+}
+
+impl<T> embedded_hal_async::spi::SpiBusRead<u8> for BlockingAsync<T>
+where
+    T: spi::blocking::SpiBusRead<u8> + spi::blocking::SpiBusFlush,
+{
+    type ReadFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+    fn read<'a>(&'a mut self, data: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        async move { self.wrapped.read(data) }
+    }
+}
+
+impl<T> embedded_hal_async::spi::SpiBus<u8> for BlockingAsync<T>
+where
+    T: spi::blocking::SpiBus<u8>,
+{
+    type TransferFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+    fn transfer<'a>(&'a mut self, read: &'a mut [u8], write: &'a [u8]) -> Self::TransferFuture<'a> {
+        async move { self.wrapped.transfer(read, write) }
+    }
+
+    type TransferInPlaceFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+    fn transfer_in_place<'a>(&'a mut self, data: &'a mut [u8]) -> Self::TransferInPlaceFuture<'a> {
+        async move { self.wrapped.transfer_in_place(data) }
+    }
+}
+
+//
+// Serial implementations
+//
+
+impl<T> serial::ErrorType for BlockingAsync<T>
+where
+    T: serial::ErrorType,
+{
+    type Error = T::Error;
+}
+
+impl<T> embedded_hal_async::serial::Read for BlockingAsync<T>
+where
+    T: serial::nb::Read<u8>,
+{
+    type ReadFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+    fn read<'a>(&'a mut self, buf: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        async move {
+            for b in buf.iter_mut() {
+                *b = nb::block!(self.wrapped.read())?;
+            }
+            Ok(())
+        }
+    }
+}
+
+impl<T> embedded_hal_async::serial::Write for BlockingAsync<T>
+where
+    T: serial::blocking::Write<u8>,
+{
+    type WriteFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+    fn write<'a>(&'a mut self, buf: &'a [u8]) -> Self::WriteFuture<'a> {
+        async move { self.wrapped.write(buf) }
+    }
+
+    type FlushFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+    fn flush<'a>(&'a mut self) -> Self::FlushFuture<'a> {
+        async move { self.wrapped.flush() }
+    }
+}
@@ -0,0 +1,661 @@
+//! Small key-value store, for settings that must survive a reset.
+//!
+//! The store is an append-only log of records spread over two pages, each taking half of
+//! the flash it's given (typically a [`Partition`](super::Partition)). Setting a key
+//! appends a new record to the active page, so the same flash cells are only erased once
+//! the page is full. The page is then compacted: the latest record of each key is copied
+//! to the other page, which becomes the active one.
+//!
+//! Every record is protected by a CRC, and a page is only marked active once it's complete,
+//! so a reset in the middle of a write or of a compaction loses at most the value being
+//! written.
+//!
+//! Lookups scan the whole active page, so this is meant for a handful of small values (a MAC
+//! address, calibration data, radio keys), not as a general purpose database.
+//!
+//! ```ignore
+//! const KEY_CALIBRATION: u16 = 1;
+//!
+//! let mut store = KvStore::new(Partition::new(&mut flash, 0xFC000, 0x4000)).await?;
+//! let offset: i32 = store.get(KEY_CALIBRATION).await?.unwrap_or(0);
+//! store.set(KEY_CALIBRATION, &(offset + 1)).await?;
+//! ```
+
+use core::fmt::Debug;
+
+use embedded_storage::nor_flash::{NorFlashError, NorFlashErrorKind};
+use embedded_storage_async::nor_flash::AsyncNorFlash;
+
+/// Maximum length of a value, in bytes.
+pub const MAX_VALUE_LEN: usize = 256;
+
+const PAGE_MAGIC: u32 = 0x4B56_5301;
+/// Size of the record headers: key, length and CRC.
+const RECORD_HEADER_LEN: usize = 8;
+/// Length of the records of deleted keys.
+const DELETED: u16 = 0xFFFE;
+/// Keys are written as-is, an erased header reads as this key.
+const ERASED_KEY: u16 = 0xFFFF;
+/// Size of the buffer flash accesses are staged in.
+const CHUNK_LEN: usize = 32;
+
+/// Error returned by [`KvStore`].
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// An operation on the inner flash failed.
+    Flash(E),
+    /// The value is longer than [`MAX_VALUE_LEN`].
+    ValueTooLarge,
+    /// The buffer is too small for the stored value.
+    BufferTooSmall,
+    /// The stored value couldn't be deserialized into the requested type.
+    InvalidValue,
+    /// The store is full, even after compaction.
+    Full,
+}
+
+impl<E> NorFlashError for Error<E>
+where
+    E: NorFlashError + Debug,
+{
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            Self::Flash(e) => e.kind(),
+            _ => NorFlashErrorKind::Other,
+        }
+    }
+}
+
+/// A value that can be stored in a [`KvStore`].
+pub trait Value: Sized {
+    /// Serialize the value into `buf`, and return the number of bytes used, or `None` if it
+    /// doesn't fit.
+    fn serialize(&self, buf: &mut [u8]) -> Option<usize>;
+
+    /// Deserialize a value written by [`Value::serialize`].
+    fn deserialize(buf: &[u8]) -> Option<Self>;
+}
+
+macro_rules! impl_value_int {
+    ($($t:ty),*) => {
+        $(
+            impl Value for $t {
+                fn serialize(&self, buf: &mut [u8]) -> Option<usize> {
+                    let bytes = self.to_le_bytes();
+                    buf.get_mut(..bytes.len())?.copy_from_slice(&bytes);
+                    Some(bytes.len())
+                }
+
+                fn deserialize(buf: &[u8]) -> Option<Self> {
+                    Some(Self::from_le_bytes(buf.try_into().ok()?))
+                }
+            }
+        )*
+    };
+}
+
+impl_value_int!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl Value for bool {
+    fn serialize(&self, buf: &mut [u8]) -> Option<usize> {
+        (*self as u8).serialize(buf)
+    }
+
+    fn deserialize(buf: &[u8]) -> Option<Self> {
+        match u8::deserialize(buf)? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl<const N: usize> Value for [u8; N] {
+    fn serialize(&self, buf: &mut [u8]) -> Option<usize> {
+        buf.get_mut(..N)?.copy_from_slice(self);
+        Some(N)
+    }
+
+    fn deserialize(buf: &[u8]) -> Option<Self> {
+        buf.try_into().ok()
+    }
+}
+
+#[derive(Clone, Copy)]
+struct RecordHeader {
+    key: u16,
+    len: u16,
+    crc: u32,
+}
+
+impl RecordHeader {
+    fn data_len(&self) -> usize {
+        match self.len {
+            DELETED => 0,
+            len => len as usize,
+        }
+    }
+}
+
+/// Key-value store on a NOR flash.
+///
+/// The flash is split in two pages, so its capacity must be a multiple of twice the erase
+/// size. Keys are `u16`, except `0xFFFF`.
+pub struct KvStore<F: AsyncNorFlash> {
+    flash: F,
+    page_size: u32,
+    /// Page the records are appended to.
+    active: u32,
+    /// Sequence number of the active page, incremented at each compaction.
+    seq: u32,
+    /// Offset of the next record in the active page.
+    write_pos: u32,
+}
+
+impl<F: AsyncNorFlash> KvStore<F> {
+    /// Open the store, or format the flash if it doesn't hold one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the capacity of the flash is not a multiple of twice its erase size, if its
+    /// read size is larger than 8 bytes, or if its write size is larger than 32 bytes.
+    pub async fn new(flash: F) -> Result<Self, Error<F::Error>> {
+        let page_size = flash.capacity() / 2;
+        assert!(page_size > 0 && page_size % F::ERASE_SIZE == 0);
+        assert!(RECORD_HEADER_LEN % F::READ_SIZE == 0);
+        assert!(CHUNK_LEN % Self::align() == 0);
+
+        let mut this = Self {
+            flash,
+            page_size: page_size as u32,
+            active: 0,
+            seq: 0,
+            write_pos: 0,
+        };
+
+        let p0 = this.read_page_header(0).await?;
+        let p1 = this.read_page_header(1).await?;
+        let (active, seq) = match (p0, p1) {
+            (Some(s0), Some(s1)) if (s1.wrapping_sub(s0) as i32) > 0 => (1, s1),
+            (Some(s0), _) => (0, s0),
+            (None, Some(s1)) => (1, s1),
+            (None, None) => {
+                this.format_page(0, 0).await?;
+                (0, 0)
+            }
+        };
+        this.active = active;
+        this.seq = seq;
+        this.write_pos = this.scan_end().await?;
+
+        Ok(this)
+    }
+
+    /// Get the value of a key, or `None` if it isn't set.
+    pub async fn get<V: Value>(&mut self, key: u16) -> Result<Option<V>, Error<F::Error>> {
+        let mut buf = [0; MAX_VALUE_LEN];
+        match self.get_bytes(key, &mut buf).await? {
+            Some(len) => match V::deserialize(&buf[..len]) {
+                Some(v) => Ok(Some(v)),
+                None => Err(Error::InvalidValue),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Set the value of a key.
+    pub async fn set<V: Value>(&mut self, key: u16, value: &V) -> Result<(), Error<F::Error>> {
+        let mut buf = [0; MAX_VALUE_LEN];
+        let len = value.serialize(&mut buf).ok_or(Error::ValueTooLarge)?;
+        self.set_bytes(key, &buf[..len]).await
+    }
+
+    /// Read the value of a key into `buf`, and return its length, or `None` if it isn't set.
+    pub async fn get_bytes(
+        &mut self,
+        key: u16,
+        buf: &mut [u8],
+    ) -> Result<Option<usize>, Error<F::Error>> {
+        let (pos, header) = match self.find(key).await? {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+        if header.len == DELETED {
+            return Ok(None);
+        }
+
+        let len = header.data_len();
+        if buf.len() < len {
+            return Err(Error::BufferTooSmall);
+        }
+
+        let mut chunk = [0; CHUNK_LEN];
+        let mut offset = 0;
+        while offset < len {
+            let n = (len - offset).min(CHUNK_LEN);
+            let addr = pos + (RECORD_HEADER_LEN + offset) as u32;
+            self.read(addr, &mut chunk[..Self::read_len(n)]).await?;
+            buf[offset..offset + n].copy_from_slice(&chunk[..n]);
+            offset += n;
+        }
+
+        Ok(Some(len))
+    }
+
+    /// Set the value of a key, from raw bytes.
+    pub async fn set_bytes(&mut self, key: u16, value: &[u8]) -> Result<(), Error<F::Error>> {
+        assert!(key != ERASED_KEY);
+        if value.len() > MAX_VALUE_LEN {
+            return Err(Error::ValueTooLarge);
+        }
+        self.append(key, value.len() as u16, value).await
+    }
+
+    /// Remove a key from the store.
+    pub async fn remove(&mut self, key: u16) -> Result<(), Error<F::Error>> {
+        match self.find(key).await? {
+            Some((_, header)) if header.len != DELETED => self.append(key, DELETED, &[]).await,
+            _ => Ok(()),
+        }
+    }
+
+    /// Return the flash, consuming the store.
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+
+    /// Alignment of the records, so they can be read and written in one go.
+    fn align() -> usize {
+        F::WRITE_SIZE.max(F::READ_SIZE).max(RECORD_HEADER_LEN)
+    }
+
+    fn round_up(len: usize) -> usize {
+        let align = Self::align();
+        (len + align - 1) / align * align
+    }
+
+    /// Length to read for `len` bytes of a value, which may end in the middle of a read unit.
+    fn read_len(len: usize) -> usize {
+        (len + F::READ_SIZE - 1) / F::READ_SIZE * F::READ_SIZE
+    }
+
+    fn record_len(header: &RecordHeader) -> u32 {
+        Self::round_up(RECORD_HEADER_LEN + header.data_len()) as u32
+    }
+
+    fn page_base(&self, page: u32) -> u32 {
+        page * self.page_size
+    }
+
+    async fn read(&mut self, addr: u32, buf: &mut [u8]) -> Result<(), Error<F::Error>> {
+        self.flash.read(addr, buf).await.map_err(Error::Flash)
+    }
+
+    async fn write(&mut self, addr: u32, buf: &[u8]) -> Result<(), Error<F::Error>> {
+        self.flash.write(addr, buf).await.map_err(Error::Flash)
+    }
+
+    async fn read_page_header(&mut self, page: u32) -> Result<Option<u32>, Error<F::Error>> {
+        let mut buf = [0; RECORD_HEADER_LEN];
+        self.read(self.page_base(page), &mut buf).await?;
+        let magic = u32::from_le_bytes(buf[0..4].try_into().unwrap());
+        let seq = u32::from_le_bytes(buf[4..8].try_into().unwrap());
+        Ok(if magic == PAGE_MAGIC { Some(seq) } else { None })
+    }
+
+    /// Erase a page, and mark it as active with the given sequence number.
+    async fn format_page(&mut self, page: u32, seq: u32) -> Result<(), Error<F::Error>> {
+        let base = self.page_base(page);
+        self.flash
+            .erase(base, base + self.page_size)
+            .await
+            .map_err(Error::Flash)?;
+        self.write_page_header(page, seq).await
+    }
+
+    async fn write_page_header(&mut self, page: u32, seq: u32) -> Result<(), Error<F::Error>> {
+        let mut buf = [0xFF; CHUNK_LEN];
+        buf[0..4].copy_from_slice(&PAGE_MAGIC.to_le_bytes());
+        buf[4..8].copy_from_slice(&seq.to_le_bytes());
+        let len = Self::align();
+        self.write(self.page_base(page), &buf[..len]).await
+    }
+
+    /// Read the header of the record at `pos` in the active page, or `None` at the end of
+    /// the log.
+    async fn read_record_header(
+        &mut self,
+        pos: u32,
+    ) -> Result<Option<RecordHeader>, Error<F::Error>> {
+        if pos as usize + RECORD_HEADER_LEN > self.page_size as usize {
+            return Ok(None);
+        }
+
+        let mut buf = [0; RECORD_HEADER_LEN];
+        self.read(self.page_base(self.active) + pos, &mut buf)
+            .await?;
+        let header = RecordHeader {
+            key: u16::from_le_bytes([buf[0], buf[1]]),
+            len: u16::from_le_bytes([buf[2], buf[3]]),
+            crc: u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]),
+        };
+
+        // A record cut by a reset may have a garbage length, stop there.
+        let truncated = pos + Self::record_len(&header) > self.page_size;
+        if header.key == ERASED_KEY
+            || (header.len != DELETED && header.len as usize > MAX_VALUE_LEN)
+            || truncated
+        {
+            Ok(None)
+        } else {
+            Ok(Some(header))
+        }
+    }
+
+    /// Offset of the first free byte of the active page.
+    async fn scan_end(&mut self) -> Result<u32, Error<F::Error>> {
+        let mut pos = Self::align() as u32;
+        while let Some(header) = self.read_record_header(pos).await? {
+            pos += Self::record_len(&header);
+        }
+
+        // If something that's not erased follows the last record, it was cut by a reset,
+        // and the page can't be appended to anymore.
+        if pos < self.page_size {
+            let mut buf = [0; RECORD_HEADER_LEN];
+            self.read(self.page_base(self.active) + pos, &mut buf)
+                .await?;
+            if buf.iter().any(|&b| b != 0xFF) {
+                return Ok(self.page_size);
+            }
+        }
+        Ok(pos)
+    }
+
+    /// Check the CRC of the record at `pos` in the active page.
+    async fn record_valid(
+        &mut self,
+        pos: u32,
+        header: &RecordHeader,
+    ) -> Result<bool, Error<F::Error>> {
+        let mut crc = Crc::new();
+        crc.update(&header.key.to_le_bytes());
+        crc.update(&header.len.to_le_bytes());
+
+        let len = header.data_len();
+        let mut chunk = [0; CHUNK_LEN];
+        let mut offset = 0;
+        while offset < len {
+            let n = (len - offset).min(CHUNK_LEN);
+            let addr = self.page_base(self.active) + pos + (RECORD_HEADER_LEN + offset) as u32;
+            self.read(addr, &mut chunk[..Self::read_len(n)]).await?;
+            crc.update(&chunk[..n]);
+            offset += n;
+        }
+
+        Ok(crc.finish() == header.crc)
+    }
+
+    /// Find the latest valid record of a key in the active page.
+    async fn find(&mut self, key: u16) -> Result<Option<(u32, RecordHeader)>, Error<F::Error>> {
+        let mut found = None;
+        let mut pos = Self::align() as u32;
+        while let Some(header) = self.read_record_header(pos).await? {
+            if header.key == key && self.record_valid(pos, &header).await? {
+                found = Some((self.page_base(self.active) + pos, header));
+            }
+            pos += Self::record_len(&header);
+        }
+        Ok(found)
+    }
+
+    /// Check whether the record at `pos` is superseded by a later valid record of the same key.
+    async fn superseded(&mut self, pos: u32, key: u16) -> Result<bool, Error<F::Error>> {
+        let mut pos = pos;
+        if let Some(header) = self.read_record_header(pos).await? {
+            pos += Self::record_len(&header);
+        }
+        while let Some(header) = self.read_record_header(pos).await? {
+            if header.key == key && self.record_valid(pos, &header).await? {
+                return Ok(true);
+            }
+            pos += Self::record_len(&header);
+        }
+        Ok(false)
+    }
+
+    /// Append a record to the active page, compacting it first if it's full.
+    async fn append(&mut self, key: u16, len: u16, value: &[u8]) -> Result<(), Error<F::Error>> {
+        let mut crc = Crc::new();
+        crc.update(&key.to_le_bytes());
+        crc.update(&len.to_le_bytes());
+        crc.update(value);
+        let header = RecordHeader {
+            key,
+            len,
+            crc: crc.finish(),
+        };
+
+        let record_len = Self::record_len(&header);
+        if self.write_pos + record_len > self.page_size {
+            self.compact().await?;
+            if self.write_pos + record_len > self.page_size {
+                return Err(Error::Full);
+            }
+        }
+
+        // Stage the header, value and padding through a chunk, written in one or more
+        // aligned writes.
+        let mut addr = self.page_base(self.active) + self.write_pos;
+        let mut chunk = [0xFF; CHUNK_LEN];
+        chunk[0..2].copy_from_slice(&header.key.to_le_bytes());
+        chunk[2..4].copy_from_slice(&header.len.to_le_bytes());
+        chunk[4..8].copy_from_slice(&header.crc.to_le_bytes());
+        let mut filled = RECORD_HEADER_LEN;
+        let mut value = value;
+        loop {
+            let n = value.len().min(CHUNK_LEN - filled);
+            chunk[filled..filled + n].copy_from_slice(&value[..n]);
+            filled += n;
+            value = &value[n..];
+
+            if value.is_empty() {
+                let len = Self::round_up(filled);
+                self.write(addr, &chunk[..len]).await?;
+                break;
+            }
+
+            self.write(addr, &chunk).await?;
+            addr += CHUNK_LEN as u32;
+            chunk = [0xFF; CHUNK_LEN];
+            filled = 0;
+        }
+
+        self.write_pos += record_len;
+        Ok(())
+    }
+
+    /// Copy the latest value of every key to the other page, and make it the active page.
+    async fn compact(&mut self) -> Result<(), Error<F::Error>> {
+        let src = self.active;
+        let dst = 1 - src;
+        let dst_base = self.page_base(dst);
+        self.flash
+            .erase(dst_base, dst_base + self.page_size)
+            .await
+            .map_err(Error::Flash)?;
+
+        let mut dst_pos = Self::align() as u32;
+        let mut pos = Self::align() as u32;
+        while let Some(header) = self.read_record_header(pos).await? {
+            let record_len = Self::record_len(&header);
+            let keep = header.len != DELETED
+                && self.record_valid(pos, &header).await?
+                && !self.superseded(pos, header.key).await?;
+
+            if keep {
+                let mut chunk = [0; CHUNK_LEN];
+                let mut offset = 0;
+                while offset < record_len {
+                    let n = (record_len - offset).min(CHUNK_LEN as u32);
+                    let chunk = &mut chunk[..n as usize];
+                    self.read(self.page_base(src) + pos + offset, chunk).await?;
+                    self.write(dst_base + dst_pos + offset, chunk).await?;
+                    offset += n;
+                }
+                dst_pos += record_len;
+            }
+            pos += record_len;
+        }
+
+        // Only mark the new page as valid once it's complete, the old page stays the
+        // active one until then.
+        let seq = self.seq.wrapping_add(1);
+        self.write_page_header(dst, seq).await?;
+
+        self.active = dst;
+        self.seq = seq;
+        self.write_pos = dst_pos;
+        Ok(())
+    }
+}
+
+/// CRC-32 (IEEE 802.3), computed bit by bit to avoid a table in flash.
+struct Crc(u32);
+
+impl Crc {
+    fn new() -> Self {
+        Self(0xFFFF_FFFF)
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.0 ^= b as u32;
+            for _ in 0..8 {
+                let mask = (self.0 & 1).wrapping_neg();
+                self.0 = (self.0 >> 1) ^ (0xEDB8_8320 & mask);
+            }
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+    use core::future::Future;
+    use embedded_storage::nor_flash::ErrorType;
+    use embedded_storage_async::nor_flash::AsyncReadNorFlash;
+    use futures::executor::block_on;
+
+    const PAGE: usize = 256;
+
+    struct MemFlash([u8; 2 * PAGE]);
+
+    impl ErrorType for MemFlash {
+        type Error = Infallible;
+    }
+
+    impl AsyncReadNorFlash for MemFlash {
+        const READ_SIZE: usize = 1;
+
+        type ReadFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+        fn read<'a>(&'a mut self, offset: u32, buf: &'a mut [u8]) -> Self::ReadFuture<'a> {
+            async move {
+                let offset = offset as usize;
+                buf.copy_from_slice(&self.0[offset..offset + buf.len()]);
+                Ok(())
+            }
+        }
+
+        fn capacity(&self) -> usize {
+            2 * PAGE
+        }
+    }
+
+    impl AsyncNorFlash for MemFlash {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = PAGE;
+
+        type EraseFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+        fn erase<'a>(&'a mut self, from: u32, to: u32) -> Self::EraseFuture<'a> {
+            async move {
+                self.0[from as usize..to as usize].fill(0xFF);
+                Ok(())
+            }
+        }
+
+        type WriteFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+        fn write<'a>(&'a mut self, offset: u32, data: &'a [u8]) -> Self::WriteFuture<'a> {
+            async move {
+                assert!(offset as usize % 4 == 0 && data.len() % 4 == 0);
+                for (i, b) in data.iter().enumerate() {
+                    // NOR flash can only clear bits.
+                    self.0[offset as usize + i] &= b;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn test_set_get_remove() {
+        block_on(async {
+            let mut store = KvStore::new(MemFlash([0xFF; 2 * PAGE])).await.unwrap();
+            assert_eq!(store.get::<u32>(1).await.unwrap(), None);
+
+            store.set(1, &0x1234_5678u32).await.unwrap();
+            store.set(2, &[1u8, 2, 3]).await.unwrap();
+            store.set(1, &42u32).await.unwrap();
+            assert_eq!(store.get::<u32>(1).await.unwrap(), Some(42));
+            assert_eq!(store.get::<[u8; 3]>(2).await.unwrap(), Some([1, 2, 3]));
+
+            store.remove(2).await.unwrap();
+            assert_eq!(store.get::<[u8; 3]>(2).await.unwrap(), None);
+
+            // Values survive reopening the store.
+            let mut store = KvStore::new(store.into_inner()).await.unwrap();
+            assert_eq!(store.get::<u32>(1).await.unwrap(), Some(42));
+            assert_eq!(store.get::<[u8; 3]>(2).await.unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn test_compaction() {
+        block_on(async {
+            let mut store = KvStore::new(MemFlash([0xFF; 2 * PAGE])).await.unwrap();
+            store.set(7, &true).await.unwrap();
+            // Many more writes than fit in a page.
+            for i in 0..100u32 {
+                store.set(1, &i).await.unwrap();
+            }
+            assert_eq!(store.get::<u32>(1).await.unwrap(), Some(99));
+            assert_eq!(store.get::<bool>(7).await.unwrap(), Some(true));
+
+            let mut store = KvStore::new(store.into_inner()).await.unwrap();
+            assert_eq!(store.get::<u32>(1).await.unwrap(), Some(99));
+            assert_eq!(store.get::<bool>(7).await.unwrap(), Some(true));
+        });
+    }
+
+    #[test]
+    fn test_corrupted_record() {
+        block_on(async {
+            let mut store = KvStore::new(MemFlash([0xFF; 2 * PAGE])).await.unwrap();
+            store.set(1, &1u32).await.unwrap();
+            store.set(1, &2u32).await.unwrap();
+
+            // Corrupt the value of the last record, as if a reset happened while writing it.
+            let mut flash = store.into_inner();
+            flash.0[8 + 16 + 8] = 0;
+
+            let mut store = KvStore::new(flash).await.unwrap();
+            assert_eq!(store.get::<u32>(1).await.unwrap(), Some(1));
+        });
+    }
+}
@@ -0,0 +1,184 @@
+//! Utilities for `embedded-storage` flash drivers.
+//!
+//! A [`Partition`] is a window over a region of a larger flash, with its own addresses
+//! starting at zero. It implements the same NOR flash traits as the flash it wraps, so a
+//! partition can be handed to a filesystem, a settings store or a firmware updater, which
+//! can't read or write outside of it.
+//!
+//! ```ignore
+//! let mut flash = Nvmc::new(p.NVMC);
+//!
+//! // The last two pages hold the settings.
+//! let mut settings = Partition::new(&mut flash, 0xFE000, 0x2000);
+//! settings.erase(0, 0x1000)?;
+//! settings.write(0, &data)?;
+//! ```
+
+use core::fmt::Debug;
+use core::future::Future;
+
+use embedded_storage::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+use embedded_storage_async::nor_flash::{AsyncNorFlash, AsyncReadNorFlash};
+
+pub mod kv;
+
+/// Error returned by [`Partition`].
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error<E> {
+    /// The operation doesn't fit in the partition.
+    OutOfBounds,
+    /// An operation on the inner flash failed.
+    Flash(E),
+}
+
+impl<E> NorFlashError for Error<E>
+where
+    E: NorFlashError + Debug,
+{
+    fn kind(&self) -> NorFlashErrorKind {
+        match self {
+            Self::OutOfBounds => NorFlashErrorKind::OutOfBounds,
+            Self::Flash(e) => e.kind(),
+        }
+    }
+}
+
+/// A region of a flash, addressed from zero.
+///
+/// The offset and size of the partition should be multiples of the erase size of the flash,
+/// so erasing the partition never touches its neighbours.
+pub struct Partition<'a, F> {
+    flash: &'a mut F,
+    offset: u32,
+    size: u32,
+}
+
+impl<'a, F> Partition<'a, F> {
+    /// Create a partition of `size` bytes, starting at `offset` in `flash`.
+    pub fn new(flash: &'a mut F, offset: u32, size: u32) -> Self {
+        Self {
+            flash,
+            offset,
+            size,
+        }
+    }
+
+    /// Offset of the partition in the inner flash.
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// Size of the partition, in bytes.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Translate a range of the partition to the inner flash.
+    fn translate<E>(&self, offset: u32, len: usize) -> Result<u32, Error<E>> {
+        let end = (offset as u64) + (len as u64);
+        if end > self.size as u64 {
+            return Err(Error::OutOfBounds);
+        }
+        Ok(self.offset + offset)
+    }
+}
+
+impl<'a, F> ErrorType for Partition<'a, F>
+where
+    F: ErrorType,
+{
+    type Error = Error<F::Error>;
+}
+
+impl<'a, F> ReadNorFlash for Partition<'a, F>
+where
+    F: ReadNorFlash,
+{
+    const READ_SIZE: usize = F::READ_SIZE;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let offset = self.translate(offset, bytes.len())?;
+        self.flash.read(offset, bytes).map_err(Error::Flash)
+    }
+
+    fn capacity(&self) -> usize {
+        self.size as usize
+    }
+}
+
+impl<'a, F> NorFlash for Partition<'a, F>
+where
+    F: NorFlash,
+{
+    const WRITE_SIZE: usize = F::WRITE_SIZE;
+    const ERASE_SIZE: usize = F::ERASE_SIZE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        if from > to {
+            return Err(Error::OutOfBounds);
+        }
+        let len = to - from;
+        let from = self.translate(from, len as usize)?;
+        let to = from + len;
+        self.flash.erase(from, to).map_err(Error::Flash)
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let offset = self.translate(offset, bytes.len())?;
+        self.flash.write(offset, bytes).map_err(Error::Flash)
+    }
+}
+
+impl<'a, F> AsyncReadNorFlash for Partition<'a, F>
+where
+    F: AsyncReadNorFlash,
+{
+    const READ_SIZE: usize = <F as AsyncReadNorFlash>::READ_SIZE;
+
+    type ReadFuture<'b> = impl Future<Output = Result<(), Self::Error>> + 'b where Self: 'b;
+
+    fn read<'b>(&'b mut self, offset: u32, bytes: &'b mut [u8]) -> Self::ReadFuture<'b> {
+        async move {
+            let offset = self.translate(offset, bytes.len())?;
+            self.flash.read(offset, bytes).await.map_err(Error::Flash)
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.size as usize
+    }
+}
+
+impl<'a, F> AsyncNorFlash for Partition<'a, F>
+where
+    F: AsyncNorFlash,
+{
+    const WRITE_SIZE: usize = <F as AsyncNorFlash>::WRITE_SIZE;
+    const ERASE_SIZE: usize = <F as AsyncNorFlash>::ERASE_SIZE;
+
+    type EraseFuture<'b> = impl Future<Output = Result<(), Self::Error>> + 'b where Self: 'b;
+
+    fn erase<'b>(&'b mut self, from: u32, to: u32) -> Self::EraseFuture<'b> {
+        async move {
+            if from > to {
+                return Err(Error::OutOfBounds);
+            }
+            let len = to - from;
+            let from = self.translate(from, len as usize)?;
+            let to = from + len;
+            self.flash.erase(from, to).await.map_err(Error::Flash)
+        }
+    }
+
+    type WriteFuture<'b> = impl Future<Output = Result<(), Self::Error>> + 'b where Self: 'b;
+
+    fn write<'b>(&'b mut self, offset: u32, bytes: &'b [u8]) -> Self::WriteFuture<'b> {
+        async move {
+            let offset = self.translate(offset, bytes.len())?;
+            self.flash.write(offset, bytes).await.map_err(Error::Flash)
+        }
+    }
+}
@@ -0,0 +1,21 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+#![feature(generic_associated_types, type_alias_impl_trait)]
+#![warn(missing_docs)]
+
+//! Utilities to use `embedded-hal` traits with Embassy.
+
+pub mod adapter;
+pub mod flash;
+pub mod shared_bus;
+
+/// Set the configuration of a peripheral driver.
+///
+/// This is used by [`shared_bus`] to apply per-device settings (SPI mode, frequency...)
+/// to a bus shared by devices with different needs. HALs implement it for their drivers.
+pub trait SetConfig {
+    /// The configuration type used by this driver.
+    type Config;
+
+    /// Set the configuration of the driver.
+    fn set_config(&mut self, config: &Self::Config);
+}
@@ -0,0 +1,180 @@
+use core::future::Future;
+use embassy::blocking_mutex::raw::RawMutex;
+use embassy::mutex::Mutex;
+use embedded_hal_1::i2c::ErrorType;
+use embedded_hal_async::i2c;
+
+use super::I2cBusDeviceError;
+use crate::SetConfig;
+
+/// I2C device on a shared bus.
+///
+/// Every operation locks the bus for its whole duration.
+pub struct I2cBusDevice<'a, M: RawMutex, BUS> {
+    bus: &'a Mutex<M, BUS>,
+}
+
+impl<'a, M: RawMutex, BUS> I2cBusDevice<'a, M, BUS> {
+    /// Create a new `I2cBusDevice`.
+    pub fn new(bus: &'a Mutex<M, BUS>) -> Self {
+        Self { bus }
+    }
+}
+
+impl<'a, M: RawMutex, BUS> ErrorType for I2cBusDevice<'a, M, BUS>
+where
+    BUS: ErrorType,
+{
+    type Error = I2cBusDeviceError<BUS::Error>;
+}
+
+impl<M, BUS> i2c::I2c for I2cBusDevice<'_, M, BUS>
+where
+    M: RawMutex + 'static,
+    BUS: i2c::I2c + 'static,
+{
+    type ReadFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+    fn read<'a>(&'a mut self, address: u8, buffer: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        async move {
+            let mut bus = self.bus.lock().await;
+            bus.read(address, buffer)
+                .await
+                .map_err(I2cBusDeviceError::I2c)
+        }
+    }
+
+    type WriteFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+    fn write<'a>(&'a mut self, address: u8, bytes: &'a [u8]) -> Self::WriteFuture<'a> {
+        async move {
+            let mut bus = self.bus.lock().await;
+            bus.write(address, bytes)
+                .await
+                .map_err(I2cBusDeviceError::I2c)
+        }
+    }
+
+    type WriteReadFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+    fn write_read<'a>(
+        &'a mut self,
+        address: u8,
+        wr_buffer: &'a [u8],
+        rd_buffer: &'a mut [u8],
+    ) -> Self::WriteReadFuture<'a> {
+        async move {
+            let mut bus = self.bus.lock().await;
+            bus.write_read(address, wr_buffer, rd_buffer)
+                .await
+                .map_err(I2cBusDeviceError::I2c)
+        }
+    }
+
+    type TransactionFuture<'a, 'b> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a, 'b: 'a;
+
+    fn transaction<'a, 'b>(
+        &'a mut self,
+        address: u8,
+        operations: &'a mut [i2c::Operation<'b>],
+    ) -> Self::TransactionFuture<'a, 'b> {
+        async move {
+            let mut bus = self.bus.lock().await;
+            bus.transaction(address, operations)
+                .await
+                .map_err(I2cBusDeviceError::I2c)
+        }
+    }
+}
+
+/// I2C device on a shared bus, with its own configuration.
+///
+/// Every operation locks the bus, and applies this device's configuration (bus speed...)
+/// before using it.
+pub struct I2cBusDeviceWithConfig<'a, M: RawMutex, BUS: SetConfig> {
+    bus: &'a Mutex<M, BUS>,
+    config: BUS::Config,
+}
+
+impl<'a, M: RawMutex, BUS: SetConfig> I2cBusDeviceWithConfig<'a, M, BUS> {
+    /// Create a new `I2cBusDeviceWithConfig`.
+    pub fn new(bus: &'a Mutex<M, BUS>, config: BUS::Config) -> Self {
+        Self { bus, config }
+    }
+
+    /// Change the configuration of this device.
+    pub fn set_config(&mut self, config: BUS::Config) {
+        self.config = config;
+    }
+}
+
+impl<'a, M, BUS> ErrorType for I2cBusDeviceWithConfig<'a, M, BUS>
+where
+    M: RawMutex,
+    BUS: ErrorType + SetConfig,
+{
+    type Error = I2cBusDeviceError<BUS::Error>;
+}
+
+impl<M, BUS> i2c::I2c for I2cBusDeviceWithConfig<'_, M, BUS>
+where
+    M: RawMutex + 'static,
+    BUS: i2c::I2c + SetConfig + 'static,
+{
+    type ReadFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+    fn read<'a>(&'a mut self, address: u8, buffer: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        async move {
+            let mut bus = self.bus.lock().await;
+            bus.set_config(&self.config);
+            bus.read(address, buffer)
+                .await
+                .map_err(I2cBusDeviceError::I2c)
+        }
+    }
+
+    type WriteFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+    fn write<'a>(&'a mut self, address: u8, bytes: &'a [u8]) -> Self::WriteFuture<'a> {
+        async move {
+            let mut bus = self.bus.lock().await;
+            bus.set_config(&self.config);
+            bus.write(address, bytes)
+                .await
+                .map_err(I2cBusDeviceError::I2c)
+        }
+    }
+
+    type WriteReadFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+    fn write_read<'a>(
+        &'a mut self,
+        address: u8,
+        wr_buffer: &'a [u8],
+        rd_buffer: &'a mut [u8],
+    ) -> Self::WriteReadFuture<'a> {
+        async move {
+            let mut bus = self.bus.lock().await;
+            bus.set_config(&self.config);
+            bus.write_read(address, wr_buffer, rd_buffer)
+                .await
+                .map_err(I2cBusDeviceError::I2c)
+        }
+    }
+
+    type TransactionFuture<'a, 'b> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a, 'b: 'a;
+
+    fn transaction<'a, 'b>(
+        &'a mut self,
+        address: u8,
+        operations: &'a mut [i2c::Operation<'b>],
+    ) -> Self::TransactionFuture<'a, 'b> {
+        async move {
+            let mut bus = self.bus.lock().await;
+            bus.set_config(&self.config);
+            bus.transaction(address, operations)
+                .await
+                .map_err(I2cBusDeviceError::I2c)
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Shared bus implementations.
+//!
+//! A bus (SPI, I2C) often has several devices attached to it, each driven by its own
+//! driver, possibly from different tasks. The types in this module wrap a single bus
+//! driver in an async [`Mutex`](embassy::mutex::Mutex), and hand out one device handle per
+//! device. Each handle implements the `embedded-hal-async` traits, and locks the bus for
+//! the duration of each transaction, so transactions of different devices never interleave.
+//!
+//! ```ignore
+//! static SPI_BUS: Forever<Mutex<ThreadModeRawMutex, Spi<...>>> = Forever::new();
+//! let spi_bus = SPI_BUS.put(Mutex::new(spi));
+//!
+//! // Each device gets its own chip select pin.
+//! let display = SpiBusDevice::new(spi_bus, cs_display);
+//! let sensor = SpiBusDevice::new(spi_bus, cs_sensor);
+//! ```
+//!
+//! Since the async mutex is fair, a device doing transactions in a loop can't starve the others.
+
+use core::fmt::Debug;
+
+use embedded_hal_1::{i2c, spi};
+
+mod i2c_bus;
+pub use i2c_bus::*;
+
+mod spi_bus;
+pub use spi_bus::*;
+
+/// Error returned by I2C device implementations in this module.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum I2cBusDeviceError<BUS> {
+    /// An operation on the inner I2C bus failed.
+    I2c(BUS),
+}
+
+impl<BUS> i2c::Error for I2cBusDeviceError<BUS>
+where
+    BUS: i2c::Error + Debug,
+{
+    fn kind(&self) -> i2c::ErrorKind {
+        match self {
+            Self::I2c(e) => e.kind(),
+        }
+    }
+}
+
+/// Error returned by SPI device implementations in this module.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SpiBusDeviceError<BUS, CS> {
+    /// An operation on the inner SPI bus failed.
+    Spi(BUS),
+    /// Setting the value of the Chip Select (CS) pin failed.
+    Cs(CS),
+}
+
+impl<BUS, CS> spi::Error for SpiBusDeviceError<BUS, CS>
+where
+    BUS: spi::Error + Debug,
+    CS: Debug,
+{
+    fn kind(&self) -> spi::ErrorKind {
+        match self {
+            Self::Spi(e) => e.kind(),
+            Self::Cs(_) => spi::ErrorKind::Other,
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! Logging and assertion macros shared by the embassy crates.
+//!
+//! The macros forward to `defmt` or `log`, or do nothing (panicking macros fall back to
+//! `core`), depending on the `defmt` and `log` features of the crate *using* them: the
+//! `#[cfg]`s are expanded in that crate. Each crate thus lets its users pick defmt, log or
+//! neither with its own features, as before, without carrying a copy of the macros.
+//!
+//! [`ensure!`] and [`ensure_eq!`] check input like assertions in debug builds, but return an
+//! error in release builds.
+//!
+//! [`ensure!`] and [`ensure_eq!`] check input like assertions in debug builds, but return an
+//! error in release builds.
+//!
+//! Import them with `#[macro_use] extern crate embassy_fmt;` at the crate root. The
+//! assertion and panicking macros then take precedence over the ones from `core`.
+//!
+//! A crate using them needs optional `defmt` and `log` dependencies, enabled by features of
+//! the same name.
+
+#![no_std]
+
+#[macro_export]
+macro_rules! assert {
+    ($($x:tt)*) => {
+        {
+            #[cfg(not(feature = "defmt"))]
+            ::core::assert!($($x)*);
+            #[cfg(feature = "defmt")]
+            ::defmt::assert!($($x)*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! assert_eq {
+    ($($x:tt)*) => {
+        {
+            #[cfg(not(feature = "defmt"))]
+            ::core::assert_eq!($($x)*);
+            #[cfg(feature = "defmt")]
+            ::defmt::assert_eq!($($x)*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! assert_ne {
+    ($($x:tt)*) => {
+        {
+            #[cfg(not(feature = "defmt"))]
+            ::core::assert_ne!($($x)*);
+            #[cfg(feature = "defmt")]
+            ::defmt::assert_ne!($($x)*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! debug_assert {
+    ($($x:tt)*) => {
+        {
+            #[cfg(not(feature = "defmt"))]
+            ::core::debug_assert!($($x)*);
+            #[cfg(feature = "defmt")]
+            ::defmt::debug_assert!($($x)*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! debug_assert_eq {
+    ($($x:tt)*) => {
+        {
+            #[cfg(not(feature = "defmt"))]
+            ::core::debug_assert_eq!($($x)*);
+            #[cfg(feature = "defmt")]
+            ::defmt::debug_assert_eq!($($x)*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! debug_assert_ne {
+    ($($x:tt)*) => {
+        {
+            #[cfg(not(feature = "defmt"))]
+            ::core::debug_assert_ne!($($x)*);
+            #[cfg(feature = "defmt")]
+            ::defmt::debug_assert_ne!($($x)*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! todo {
+    ($($x:tt)*) => {
+        {
+            #[cfg(not(feature = "defmt"))]
+            ::core::todo!($($x)*);
+            #[cfg(feature = "defmt")]
+            ::defmt::todo!($($x)*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! unreachable {
+    ($($x:tt)*) => {
+        {
+            #[cfg(not(feature = "defmt"))]
+            ::core::unreachable!($($x)*);
+            #[cfg(feature = "defmt")]
+            ::defmt::unreachable!($($x)*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! panic {
+    ($($x:tt)*) => {
+        {
+            #[cfg(not(feature = "defmt"))]
+            ::core::panic!($($x)*);
+            #[cfg(feature = "defmt")]
+            ::defmt::panic!($($x)*);
+        }
+    };
+}
+
+/// Returns `Err($err)` from the enclosing function if `$cond` is false.
+///
+/// Meant for checking input the crate doesn't control, such as packets received from
+/// a host or a radio, where panicking in the field is worse than dropping the input.
+/// Debug builds panic instead, so malformed input is easy to spot during development.
+#[macro_export]
+macro_rules! ensure {
+    ($cond:expr, $err:expr $(,)?) => {
+        if !$cond {
+            #[cfg(debug_assertions)]
+            $crate::panic!("ensure!({}) failed", ::core::stringify!($cond));
+            #[cfg(not(debug_assertions))]
+            return ::core::result::Result::Err($err);
+        }
+    };
+}
+
+/// Like [`ensure!`], checking that two values are equal.
+#[macro_export]
+macro_rules! ensure_eq {
+    ($left:expr, $right:expr, $err:expr $(,)?) => {
+        match (&$left, &$right) {
+            (left_val, right_val) => {
+                if *left_val != *right_val {
+                    #[cfg(debug_assertions)]
+                    $crate::panic!(
+                        "ensure_eq!({}, {}) failed",
+                        ::core::stringify!($left),
+                        ::core::stringify!($right)
+                    );
+                    #[cfg(not(debug_assertions))]
+                    return ::core::result::Result::Err($err);
+                }
+            }
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($s:literal $(, $x:expr)* $(,)?) => {
+        {
+            #[cfg(all(feature = "defmt", feature = "log"))]
+            ::core::compile_error!("You may not enable both `defmt` and `log` features.");
+            #[cfg(feature = "log")]
+            ::log::trace!($s $(, $x)*);
+            #[cfg(feature = "defmt")]
+            ::defmt::trace!($s $(, $x)*);
+            #[cfg(not(any(feature = "log", feature="defmt")))]
+            let _ = ($( & $x ),*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($s:literal $(, $x:expr)* $(,)?) => {
+        {
+            #[cfg(all(feature = "defmt", feature = "log"))]
+            ::core::compile_error!("You may not enable both `defmt` and `log` features.");
+            #[cfg(feature = "log")]
+            ::log::debug!($s $(, $x)*);
+            #[cfg(feature = "defmt")]
+            ::defmt::debug!($s $(, $x)*);
+            #[cfg(not(any(feature = "log", feature="defmt")))]
+            let _ = ($( & $x ),*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($s:literal $(, $x:expr)* $(,)?) => {
+        {
+            #[cfg(all(feature = "defmt", feature = "log"))]
+            ::core::compile_error!("You may not enable both `defmt` and `log` features.");
+            #[cfg(feature = "log")]
+            ::log::info!($s $(, $x)*);
+            #[cfg(feature = "defmt")]
+            ::defmt::info!($s $(, $x)*);
+            #[cfg(not(any(feature = "log", feature="defmt")))]
+            let _ = ($( & $x ),*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($s:literal $(, $x:expr)* $(,)?) => {
+        {
+            #[cfg(all(feature = "defmt", feature = "log"))]
+            ::core::compile_error!("You may not enable both `defmt` and `log` features.");
+            #[cfg(feature = "log")]
+            ::log::warn!($s $(, $x)*);
+            #[cfg(feature = "defmt")]
+            ::defmt::warn!($s $(, $x)*);
+            #[cfg(not(any(feature = "log", feature="defmt")))]
+            let _ = ($( & $x ),*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($s:literal $(, $x:expr)* $(,)?) => {
+        {
+            #[cfg(all(feature = "defmt", feature = "log"))]
+            ::core::compile_error!("You may not enable both `defmt` and `log` features.");
+            #[cfg(feature = "log")]
+            ::log::error!($s $(, $x)*);
+            #[cfg(feature = "defmt")]
+            ::defmt::error!($s $(, $x)*);
+            #[cfg(not(any(feature = "log", feature="defmt")))]
+            let _ = ($( & $x ),*);
+        }
+    };
+}
+
+// The `#[cfg]`s are on tuple elements, since attributes aren't allowed on a bare expression.
+// Unlike a `let`, the tuple keeps the temporaries of `$arg` alive like a plain `match` would.
+#[macro_export]
+macro_rules! unwrap {
+    ($arg:expr) => {
+        (
+            #[cfg(feature = "defmt")]
+            ::defmt::unwrap!($arg),
+            #[cfg(not(feature = "defmt"))]
+            match $crate::Try::into_result($arg) {
+                ::core::result::Result::Ok(t) => t,
+                ::core::result::Result::Err(e) => {
+                    ::core::panic!("unwrap of `{}` failed: {:?}", ::core::stringify!($arg), e);
+                }
+            },
+        ).0
+    };
+    ($arg:expr, $($msg:expr),+ $(,)? ) => {
+        (
+            #[cfg(feature = "defmt")]
+            ::defmt::unwrap!($arg, $($msg),+),
+            #[cfg(not(feature = "defmt"))]
+            match $crate::Try::into_result($arg) {
+                ::core::result::Result::Ok(t) => t,
+                ::core::result::Result::Err(e) => {
+                    ::core::panic!("unwrap of `{}` failed: {}: {:?}", ::core::stringify!($arg), ::core::format_args!($($msg,)*), e);
+                }
+            },
+        ).0
+    };
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct NoneError;
+
+pub trait Try {
+    type Ok;
+    type Error;
+    fn into_result(self) -> Result<Self::Ok, Self::Error>;
+}
+
+impl<T> Try for Option<T> {
+    type Ok = T;
+    type Error = NoneError;
+
+    #[inline]
+    fn into_result(self) -> Result<T, NoneError> {
+        self.ok_or(NoneError)
+    }
+}
+
+impl<T, E> Try for Result<T, E> {
+    type Ok = T;
+    type Error = E;
+
+    #[inline]
+    fn into_result(self) -> Self {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[derive(Debug, PartialEq)]
+    struct Invalid;
+
+    fn parse(data: &[u8]) -> Result<u8, Invalid> {
+        ensure!(data.len() >= 2, Invalid);
+        ensure_eq!(data[0], 0x7e, Invalid);
+        Ok(data[1])
+    }
+
+    #[test]
+    fn ensure_ok() {
+        assert_eq!(parse(&[0x7e, 1]), Ok(1));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "ensure!(data.len() >= 2) failed")]
+    fn ensure_panics_in_debug() {
+        let _ = parse(&[0x7e]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "ensure_eq!(data[0], 0x7e) failed")]
+    fn ensure_eq_panics_in_debug() {
+        let _ = parse(&[0, 1]);
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn ensure_returns_in_release() {
+        assert_eq!(parse(&[0x7e]), Err(Invalid));
+        assert_eq!(parse(&[0, 1]), Err(Invalid));
+    }
+}
@@ -1,8 +1,8 @@
 #![no_std]
 #![allow(clippy::new_without_default)]
 
-// This mod MUST go first, so that the others see its macros.
-pub(crate) mod fmt;
+#[macro_use]
+extern crate embassy_fmt;
 
 pub mod drop;
 pub mod interrupt;
@@ -4,7 +4,8 @@
 //! embassy-lora is a collection of async radio drivers that integrate with the lorawan-device
 //! crate's async LoRaWAN MAC implementation.
 
-pub(crate) mod fmt;
+#[macro_use]
+extern crate embassy_fmt;
 
 #[cfg(feature = "stm32wl")]
 pub mod stm32wl;
@@ -10,6 +10,8 @@ struct Args {
     #[darling(default)]
     pool_size: Option<usize>,
     #[darling(default)]
+    pool_limit: Option<syn::Path>,
+    #[darling(default)]
     send: bool,
     #[darling(default)]
     embassy_prefix: ModulePrefix,
@@ -73,6 +75,13 @@ pub fn run(args: syn::AttributeArgs, mut f: syn::ItemFn) -> Result<TokenStream,
 
     let attrs = &f.attrs;
 
+    // With `pool_limit`, the number of instances is read from a static `AtomicUsize` at spawn
+    // time, and `pool_size` is only the maximum.
+    let pool_limit = match &args.pool_limit {
+        Some(limit) => quote!(#limit.load(::core::sync::atomic::Ordering::Relaxed)),
+        None => quote!(#pool_size),
+    };
+
     let result = quote! {
         #(#attrs)*
         #visibility fn #name(#fargs) -> #embassy_path::executor::SpawnToken<#impl_ty> {
@@ -82,7 +91,7 @@ pub fn run(args: syn::AttributeArgs, mut f: syn::ItemFn) -> Result<TokenStream,
             #[allow(clippy::declare_interior_mutable_const)]
             const NEW_TASK: TaskStorage<F> = TaskStorage::new();
             static POOL: [TaskStorage<F>; #pool_size] = [NEW_TASK; #pool_size];
-            unsafe { TaskStorage::spawn_pool(&POOL, move || task(#arg_names)) }
+            unsafe { TaskStorage::spawn_named_pool(Some(stringify!(#name)), &POOL, #pool_limit, move || task(#arg_names)) }
         }
     };
 
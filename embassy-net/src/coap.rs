@@ -0,0 +1,1119 @@
+//! CoAP (RFC 7252) client and server, over [`UdpSocket`]s.
+//!
+//! The client sends confirmable requests by default, retransmitted with
+//! exponential back-off until they're acknowledged, and accepts piggybacked
+//! and separate responses. It can observe resources (RFC 7641), and download
+//! large resources block by block (RFC 7959), for example a firmware image
+//! into embassy-boot's `FirmwareUpdater`:
+//!
+//! ```ignore
+//! let mut download = Download::new("fw/app.bin", BlockSize::S1024);
+//! let mut page = [0; PAGE_SIZE];
+//! let mut filled = 0;
+//! let mut offset = 0;
+//! while let Some((_, data)) = download.next_block(&mut client, &mut buf).await? {
+//!     // FirmwareUpdater writes whole erase pages.
+//!     for chunk in data.chunks(PAGE_SIZE) {
+//!         let n = chunk.len().min(PAGE_SIZE - filled);
+//!         page[filled..filled + n].copy_from_slice(&chunk[..n]);
+//!         filled += n;
+//!         if filled == PAGE_SIZE {
+//!             updater.write_firmware(offset, &page, &mut flash, 4).await?;
+//!             offset += PAGE_SIZE;
+//!             page[..chunk.len() - n].copy_from_slice(&chunk[n..]);
+//!             filled = chunk.len() - n;
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Messages are encoded and decoded in caller-provided buffers, nothing is allocated.
+
+use embassy::time::{with_timeout, Duration};
+use smoltcp::wire::{IpAddress, IpEndpoint};
+
+use crate::udp_socket::UdpSocket;
+use crate::Error;
+
+pub const COAP_PORT: u16 = 5683;
+
+const VERSION: u8 = 1;
+const PAYLOAD_MARKER: u8 = 0xff;
+
+/// Initial retransmission timeout, randomized up to 1.5 times more.
+const ACK_TIMEOUT_MS: u64 = 2_000;
+const MAX_RETRANSMIT: u32 = 4;
+
+pub const OPTION_OBSERVE: u16 = 6;
+pub const OPTION_URI_PATH: u16 = 11;
+pub const OPTION_CONTENT_FORMAT: u16 = 12;
+pub const OPTION_URI_QUERY: u16 = 15;
+pub const OPTION_BLOCK2: u16 = 23;
+
+/// Errors of the CoAP client and server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CoapError {
+    /// Sending or receiving a datagram failed.
+    Network(Error),
+    /// No response came in time.
+    Timeout,
+    /// The peer rejected the message with a reset.
+    Reset,
+    /// The message isn't valid CoAP.
+    Malformed,
+    /// The message doesn't fit in the buffer.
+    BufferTooSmall,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MessageType {
+    Confirmable = 0,
+    NonConfirmable = 1,
+    Acknowledgement = 2,
+    Reset = 3,
+}
+
+/// Code of a message: a request method, or a response code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Code(pub u8);
+
+impl Code {
+    pub const EMPTY: Code = Code::new(0, 0);
+    pub const GET: Code = Code::new(0, 1);
+    pub const POST: Code = Code::new(0, 2);
+    pub const PUT: Code = Code::new(0, 3);
+    pub const DELETE: Code = Code::new(0, 4);
+    pub const CREATED: Code = Code::new(2, 1);
+    pub const DELETED: Code = Code::new(2, 2);
+    pub const VALID: Code = Code::new(2, 3);
+    pub const CHANGED: Code = Code::new(2, 4);
+    pub const CONTENT: Code = Code::new(2, 5);
+    pub const BAD_REQUEST: Code = Code::new(4, 0);
+    pub const NOT_FOUND: Code = Code::new(4, 4);
+    pub const METHOD_NOT_ALLOWED: Code = Code::new(4, 5);
+    pub const INTERNAL_SERVER_ERROR: Code = Code::new(5, 0);
+
+    /// Code `class.detail`, like 2.05 for Content.
+    pub const fn new(class: u8, detail: u8) -> Self {
+        Self(class << 5 | detail)
+    }
+
+    pub fn class(&self) -> u8 {
+        self.0 >> 5
+    }
+
+    pub fn detail(&self) -> u8 {
+        self.0 & 0x1f
+    }
+
+    pub fn is_request(&self) -> bool {
+        self.class() == 0 && self.detail() != 0
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.class() == 2
+    }
+}
+
+/// Size of the blocks of a block-wise transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BlockSize {
+    S16 = 0,
+    S32 = 1,
+    S64 = 2,
+    S128 = 3,
+    S256 = 4,
+    S512 = 5,
+    S1024 = 6,
+}
+
+impl BlockSize {
+    pub fn bytes(&self) -> usize {
+        16 << (*self as usize)
+    }
+
+    fn from_szx(szx: u8) -> Option<Self> {
+        Some(match szx {
+            0 => BlockSize::S16,
+            1 => BlockSize::S32,
+            2 => BlockSize::S64,
+            3 => BlockSize::S128,
+            4 => BlockSize::S256,
+            5 => BlockSize::S512,
+            6 => BlockSize::S1024,
+            _ => return None,
+        })
+    }
+}
+
+/// Value of a Block1 or Block2 option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Block {
+    pub num: u32,
+    pub more: bool,
+    pub size: BlockSize,
+}
+
+impl Block {
+    fn decode(value: u32) -> Option<Self> {
+        Some(Self {
+            num: value >> 4,
+            more: value & 0x08 != 0,
+            size: BlockSize::from_szx((value & 0x07) as u8)?,
+        })
+    }
+
+    fn encode(&self) -> u32 {
+        self.num << 4 | (self.more as u32) << 3 | self.size as u32
+    }
+}
+
+/// A decoded message.
+pub struct Message<'a> {
+    pub ty: MessageType,
+    pub code: Code,
+    pub message_id: u16,
+    pub token: &'a [u8],
+    options: &'a [u8],
+    pub payload: &'a [u8],
+}
+
+impl<'a> Message<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, CoapError> {
+        if data.len() < 4 || data[0] >> 6 != VERSION {
+            return Err(CoapError::Malformed);
+        }
+        let ty = match (data[0] >> 4) & 0x03 {
+            0 => MessageType::Confirmable,
+            1 => MessageType::NonConfirmable,
+            2 => MessageType::Acknowledgement,
+            _ => MessageType::Reset,
+        };
+        let token_len = (data[0] & 0x0f) as usize;
+        if token_len > 8 || data.len() < 4 + token_len {
+            return Err(CoapError::Malformed);
+        }
+        let token = &data[4..4 + token_len];
+
+        // Find the end of the options, checking them on the way.
+        let options = &data[4 + token_len..];
+        let mut rest = options;
+        let mut number: u16 = 0;
+        while let Some((delta, _, next)) = next_option(rest)? {
+            number = number.checked_add(delta).ok_or(CoapError::Malformed)?;
+            rest = next;
+        }
+        let options = &options[..options.len() - rest.len()];
+        let payload = match rest {
+            [] => rest,
+            [PAYLOAD_MARKER] => return Err(CoapError::Malformed),
+            [_, payload @ ..] => payload,
+        };
+
+        Ok(Self {
+            ty,
+            code: Code(data[1]),
+            message_id: u16::from_be_bytes([data[2], data[3]]),
+            token,
+            options,
+            payload,
+        })
+    }
+
+    /// Iterate over the numbers and values of the options.
+    pub fn options(&self) -> impl Iterator<Item = (u16, &'a [u8])> {
+        let mut rest = self.options;
+        let mut number = 0;
+        core::iter::from_fn(move || {
+            // The options were checked when parsing.
+            let (delta, value, next) = next_option(rest).ok()??;
+            rest = next;
+            number = u16::checked_add(number, delta)?;
+            Some((number, value))
+        })
+    }
+
+    /// Get the value of the first option `number`.
+    pub fn option(&self, number: u16) -> Option<&'a [u8]> {
+        self.options().find(|(n, _)| *n == number).map(|(_, v)| v)
+    }
+
+    /// Get the value of the first option `number`, as an unsigned integer.
+    pub fn uint_option(&self, number: u16) -> Option<u32> {
+        self.option(number).map(decode_uint)
+    }
+
+    /// Sequence number of a notification, or 0 for the registration of an observer.
+    pub fn observe(&self) -> Option<u32> {
+        self.uint_option(OPTION_OBSERVE)
+    }
+
+    pub fn content_format(&self) -> Option<u16> {
+        self.uint_option(OPTION_CONTENT_FORMAT).map(|v| v as u16)
+    }
+
+    pub fn block2(&self) -> Option<Block> {
+        self.uint_option(OPTION_BLOCK2).and_then(Block::decode)
+    }
+}
+
+/// Split the first option off `data`. Returns the delta of its number, its
+/// value and the rest, or `None` at the end of the options.
+fn next_option(data: &[u8]) -> Result<Option<(u16, &[u8], &[u8])>, CoapError> {
+    let (&first, mut rest) = match data.split_first() {
+        None | Some((&PAYLOAD_MARKER, _)) => return Ok(None),
+        Some(split) => split,
+    };
+
+    let mut extended = |nibble: u8| -> Result<usize, CoapError> {
+        Ok(match nibble {
+            13 => {
+                let (&b, r) = rest.split_first().ok_or(CoapError::Malformed)?;
+                rest = r;
+                b as usize + 13
+            }
+            14 => {
+                if rest.len() < 2 {
+                    return Err(CoapError::Malformed);
+                }
+                let v = u16::from_be_bytes([rest[0], rest[1]]) as usize + 269;
+                rest = &rest[2..];
+                v
+            }
+            15 => return Err(CoapError::Malformed),
+            n => n as usize,
+        })
+    };
+    let delta = extended(first >> 4)?;
+    let len = extended(first & 0x0f)?;
+
+    if rest.len() < len || delta > u16::MAX as usize {
+        return Err(CoapError::Malformed);
+    }
+    Ok(Some((delta as u16, &rest[..len], &rest[len..])))
+}
+
+fn decode_uint(value: &[u8]) -> u32 {
+    value.iter().fold(0, |acc, b| acc << 8 | *b as u32)
+}
+
+/// Encodes a message in a buffer. Options must be added in increasing number order.
+struct MessageWriter<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+    last_option: u16,
+}
+
+impl<'b> MessageWriter<'b> {
+    fn new(
+        buf: &'b mut [u8],
+        ty: MessageType,
+        code: Code,
+        message_id: u16,
+        token: &[u8],
+    ) -> Result<Self, CoapError> {
+        let mut w = Self {
+            buf,
+            len: 0,
+            last_option: 0,
+        };
+        let id = message_id.to_be_bytes();
+        w.put(&[
+            VERSION << 6 | (ty as u8) << 4 | token.len() as u8,
+            code.0,
+            id[0],
+            id[1],
+        ])?;
+        w.put(token)?;
+        Ok(w)
+    }
+
+    fn option(&mut self, number: u16, value: &[u8]) -> Result<(), CoapError> {
+        debug_assert!(number >= self.last_option);
+        let (delta, delta_ext, delta_ext_len) = option_nibble((number - self.last_option) as usize);
+        let (len, len_ext, len_ext_len) = option_nibble(value.len());
+        self.last_option = number;
+
+        self.put(&[delta << 4 | len])?;
+        self.put(&delta_ext[..delta_ext_len])?;
+        self.put(&len_ext[..len_ext_len])?;
+        self.put(value)
+    }
+
+    fn uint_option(&mut self, number: u16, value: u32) -> Result<(), CoapError> {
+        let bytes = value.to_be_bytes();
+        // Integers are sent without their leading zero bytes.
+        let skip = (value.leading_zeros() / 8) as usize;
+        self.option(number, &bytes[skip..])
+    }
+
+    fn finish(mut self, payload: &[u8]) -> Result<usize, CoapError> {
+        if !payload.is_empty() {
+            self.put(&[PAYLOAD_MARKER])?;
+            self.put(payload)?;
+        }
+        Ok(self.len)
+    }
+
+    fn put(&mut self, data: &[u8]) -> Result<(), CoapError> {
+        let end = self.len + data.len();
+        if end > self.buf.len() {
+            return Err(CoapError::BufferTooSmall);
+        }
+        self.buf[self.len..end].copy_from_slice(data);
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Encode an option delta or length, as its nibble and extended bytes.
+fn option_nibble(value: usize) -> (u8, [u8; 2], usize) {
+    if value < 13 {
+        (value as u8, [0; 2], 0)
+    } else if value < 269 {
+        (13, [(value - 13) as u8, 0], 1)
+    } else {
+        (14, ((value - 269) as u16).to_be_bytes(), 2)
+    }
+}
+
+/// Everything of a request, but its message id and token.
+struct RequestSpec<'r> {
+    code: Code,
+    path: &'r str,
+    observe: Option<u32>,
+    content_format: Option<u16>,
+    block2: Option<Block>,
+    payload: &'r [u8],
+}
+
+impl<'r> RequestSpec<'r> {
+    fn new(code: Code, path: &'r str) -> Self {
+        Self {
+            code,
+            path,
+            observe: None,
+            content_format: None,
+            block2: None,
+            payload: &[],
+        }
+    }
+
+    fn encode(
+        &self,
+        buf: &mut [u8],
+        ty: MessageType,
+        message_id: u16,
+        token: &[u8],
+    ) -> Result<usize, CoapError> {
+        let mut w = MessageWriter::new(buf, ty, self.code, message_id, token)?;
+        if let Some(observe) = self.observe {
+            w.uint_option(OPTION_OBSERVE, observe)?;
+        }
+        let (path, query) = match self.path.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (self.path, None),
+        };
+        for segment in path.split('/').filter(|s| !s.is_empty()) {
+            w.option(OPTION_URI_PATH, segment.as_bytes())?;
+        }
+        if let Some(content_format) = self.content_format {
+            w.uint_option(OPTION_CONTENT_FORMAT, content_format as u32)?;
+        }
+        if let Some(query) = query {
+            for part in query.split('&') {
+                w.option(OPTION_URI_QUERY, part.as_bytes())?;
+            }
+        }
+        if let Some(block2) = self.block2 {
+            w.uint_option(OPTION_BLOCK2, block2.encode())?;
+        }
+        w.finish(self.payload)
+    }
+}
+
+enum Received {
+    /// A response of the given length is in the buffer.
+    Response(usize),
+    /// The request was acknowledged, the response comes separately.
+    EmptyAck,
+}
+
+/// A resource observed with [`CoapClient::observe`].
+#[derive(Debug, Clone, Copy)]
+pub struct Observation {
+    token: [u8; 4],
+}
+
+/// CoAP client, sending requests to one server.
+pub struct CoapClient<'a> {
+    socket: UdpSocket<'a>,
+    server: IpEndpoint,
+    next_message_id: u16,
+    confirmable: bool,
+    timeout: Duration,
+}
+
+impl<'a> CoapClient<'a> {
+    /// Create a client sending requests to `server` on the CoAP port.
+    ///
+    /// The socket buffers must hold the largest request and response.
+    pub fn new(mut socket: UdpSocket<'a>, server: IpAddress) -> Self {
+        unwrap!(socket.bind(0));
+        let mut id = [0; 2];
+        crate::stack::rand(&mut id);
+        Self {
+            socket,
+            server: IpEndpoint::new(server, COAP_PORT),
+            next_message_id: u16::from_le_bytes(id),
+            confirmable: true,
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    pub fn set_server(&mut self, server: IpEndpoint) {
+        self.server = server;
+    }
+
+    /// Send the next requests as confirmable (the default) or non-confirmable messages.
+    ///
+    /// Non-confirmable requests aren't retransmitted, which suits requests
+    /// that are repeated anyway, like periodic readings.
+    pub fn set_confirmable(&mut self, confirmable: bool) {
+        self.confirmable = confirmable;
+    }
+
+    /// Set how long to wait for a response once the request is acknowledged,
+    /// or sent for non-confirmable requests. 30 seconds by default.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub async fn get<'b>(
+        &mut self,
+        path: &str,
+        buf: &'b mut [u8],
+    ) -> Result<Message<'b>, CoapError> {
+        let spec = RequestSpec::new(Code::GET, path);
+        self.request(&spec, self.new_token(), buf).await
+    }
+
+    pub async fn post<'b>(
+        &mut self,
+        path: &str,
+        content_format: Option<u16>,
+        payload: &[u8],
+        buf: &'b mut [u8],
+    ) -> Result<Message<'b>, CoapError> {
+        let mut spec = RequestSpec::new(Code::POST, path);
+        spec.content_format = content_format;
+        spec.payload = payload;
+        self.request(&spec, self.new_token(), buf).await
+    }
+
+    pub async fn put<'b>(
+        &mut self,
+        path: &str,
+        content_format: Option<u16>,
+        payload: &[u8],
+        buf: &'b mut [u8],
+    ) -> Result<Message<'b>, CoapError> {
+        let mut spec = RequestSpec::new(Code::PUT, path);
+        spec.content_format = content_format;
+        spec.payload = payload;
+        self.request(&spec, self.new_token(), buf).await
+    }
+
+    pub async fn delete<'b>(
+        &mut self,
+        path: &str,
+        buf: &'b mut [u8],
+    ) -> Result<Message<'b>, CoapError> {
+        let spec = RequestSpec::new(Code::DELETE, path);
+        self.request(&spec, self.new_token(), buf).await
+    }
+
+    /// Get block `num` of a resource.
+    pub async fn get_block<'b>(
+        &mut self,
+        path: &str,
+        num: u32,
+        size: BlockSize,
+        buf: &'b mut [u8],
+    ) -> Result<Message<'b>, CoapError> {
+        let mut spec = RequestSpec::new(Code::GET, path);
+        spec.block2 = Some(Block {
+            num,
+            more: false,
+            size,
+        });
+        self.request(&spec, self.new_token(), buf).await
+    }
+
+    /// Start observing a resource. Returns the observation, and the current
+    /// state of the resource.
+    ///
+    /// If the response has no Observe option, the server doesn't support
+    /// observing the resource, and there won't be notifications.
+    pub async fn observe<'b>(
+        &mut self,
+        path: &str,
+        buf: &'b mut [u8],
+    ) -> Result<(Observation, Message<'b>), CoapError> {
+        let observation = Observation {
+            token: self.new_token(),
+        };
+        let mut spec = RequestSpec::new(Code::GET, path);
+        spec.observe = Some(0);
+        let response = self.request(&spec, observation.token, buf).await?;
+        Ok((observation, response))
+    }
+
+    /// Wait for the next notification of an observed resource.
+    ///
+    /// Notifications that come while the client waits for a response to
+    /// another request are lost.
+    pub async fn next_notification<'b>(
+        &mut self,
+        observation: &Observation,
+        buf: &'b mut [u8],
+    ) -> Result<Message<'b>, CoapError> {
+        let len = loop {
+            let (n, from) = self
+                .socket
+                .recv_from(buf)
+                .await
+                .map_err(CoapError::Network)?;
+            if from != self.server {
+                continue;
+            }
+            let msg = match Message::parse(&buf[..n]) {
+                Ok(msg) => msg,
+                Err(_) => continue,
+            };
+            if msg.token != observation.token || msg.code.is_request() {
+                continue;
+            }
+            match msg.ty {
+                MessageType::Confirmable => {
+                    let id = msg.message_id;
+                    self.send_empty(MessageType::Acknowledgement, id).await?;
+                    break n;
+                }
+                MessageType::NonConfirmable => break n,
+                _ => {}
+            }
+        };
+        Message::parse(&buf[..len])
+    }
+
+    /// Stop observing a resource. The server answers with its current state.
+    pub async fn cancel_observe<'b>(
+        &mut self,
+        observation: Observation,
+        path: &str,
+        buf: &'b mut [u8],
+    ) -> Result<Message<'b>, CoapError> {
+        let mut spec = RequestSpec::new(Code::GET, path);
+        spec.observe = Some(1);
+        self.request(&spec, observation.token, buf).await
+    }
+
+    fn new_token(&self) -> [u8; 4] {
+        let mut token = [0; 4];
+        crate::stack::rand(&mut token);
+        token
+    }
+
+    async fn request<'b>(
+        &mut self,
+        spec: &RequestSpec<'_>,
+        token: [u8; 4],
+        buf: &'b mut [u8],
+    ) -> Result<Message<'b>, CoapError> {
+        let len = self.exchange(spec, &token, buf).await?;
+        Message::parse(&buf[..len])
+    }
+
+    /// Send a request and wait for its response, returning the length of the
+    /// response in `buf`.
+    async fn exchange(
+        &mut self,
+        spec: &RequestSpec<'_>,
+        token: &[u8],
+        buf: &mut [u8],
+    ) -> Result<usize, CoapError> {
+        let message_id = self.next_message_id;
+        self.next_message_id = self.next_message_id.wrapping_add(1);
+
+        let (ty, attempts) = if self.confirmable {
+            (MessageType::Confirmable, MAX_RETRANSMIT + 1)
+        } else {
+            (MessageType::NonConfirmable, 1)
+        };
+
+        let mut random = [0; 1];
+        crate::stack::rand(&mut random);
+        let mut timeout =
+            Duration::from_millis(ACK_TIMEOUT_MS + ACK_TIMEOUT_MS * random[0] as u64 / 512);
+
+        for attempt in 0..attempts {
+            let len = spec.encode(buf, ty, message_id, token)?;
+            self.socket
+                .send_to(&buf[..len], self.server)
+                .await
+                .map_err(CoapError::Network)?;
+
+            let wait = if attempt + 1 < attempts {
+                timeout
+            } else {
+                self.timeout
+            };
+            match with_timeout(wait, self.wait_response(message_id, token, buf)).await {
+                Ok(Ok(Received::Response(len))) => return Ok(len),
+                Ok(Ok(Received::EmptyAck)) => break,
+                Ok(Err(e)) => return Err(e),
+                Err(_) if attempt + 1 < attempts => {
+                    debug!("CoAP: retransmitting message {}", message_id);
+                    timeout = timeout * 2;
+                }
+                Err(_) => return Err(CoapError::Timeout),
+            }
+        }
+
+        // The request was acknowledged, wait for the separate response.
+        match with_timeout(self.timeout, self.wait_response(message_id, token, buf)).await {
+            Ok(Ok(Received::Response(len))) => Ok(len),
+            Ok(Ok(Received::EmptyAck)) => Err(CoapError::Malformed),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(CoapError::Timeout),
+        }
+    }
+
+    async fn wait_response(
+        &mut self,
+        message_id: u16,
+        token: &[u8],
+        buf: &mut [u8],
+    ) -> Result<Received, CoapError> {
+        loop {
+            let (n, from) = self
+                .socket
+                .recv_from(buf)
+                .await
+                .map_err(CoapError::Network)?;
+            if from != self.server {
+                continue;
+            }
+            let msg = match Message::parse(&buf[..n]) {
+                Ok(msg) => msg,
+                Err(_) => continue,
+            };
+
+            match msg.ty {
+                MessageType::Acknowledgement | MessageType::Reset
+                    if msg.message_id != message_id => {}
+                MessageType::Reset => return Err(CoapError::Reset),
+                MessageType::Acknowledgement if msg.code == Code::EMPTY => {
+                    return Ok(Received::EmptyAck)
+                }
+                MessageType::Acknowledgement if msg.token == token => {
+                    return Ok(Received::Response(n))
+                }
+                MessageType::Confirmable if msg.token == token => {
+                    let id = msg.message_id;
+                    self.send_empty(MessageType::Acknowledgement, id).await?;
+                    return Ok(Received::Response(n));
+                }
+                MessageType::NonConfirmable if msg.token == token => {
+                    return Ok(Received::Response(n))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    async fn send_empty(&mut self, ty: MessageType, message_id: u16) -> Result<(), CoapError> {
+        let mut buf = [0; 4];
+        let len = MessageWriter::new(&mut buf, ty, Code::EMPTY, message_id, &[])?.finish(&[])?;
+        self.socket
+            .send_to(&buf[..len], self.server)
+            .await
+            .map_err(CoapError::Network)
+    }
+}
+
+/// Block-wise download of a resource, with Block2 options.
+pub struct Download<'p> {
+    path: &'p str,
+    num: u32,
+    size: BlockSize,
+    done: bool,
+}
+
+impl<'p> Download<'p> {
+    /// Download `path` in blocks of `size`, or smaller if the server asks so.
+    pub fn new(path: &'p str, size: BlockSize) -> Self {
+        Self {
+            path,
+            num: 0,
+            size,
+            done: false,
+        }
+    }
+
+    /// Get the next block. Returns its offset in the resource and its data,
+    /// or `None` once the whole resource is downloaded.
+    ///
+    /// A failed block can be retried by calling this again.
+    pub async fn next_block<'b>(
+        &mut self,
+        client: &mut CoapClient<'_>,
+        buf: &'b mut [u8],
+    ) -> Result<Option<(usize, &'b [u8])>, CoapError> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let response = client
+            .get_block(self.path, self.num, self.size, buf)
+            .await?;
+        if !response.code.is_success() {
+            return Err(CoapError::Malformed);
+        }
+        match response.block2() {
+            Some(block) => {
+                let offset = block.num as usize * block.size.bytes();
+                // The server may answer with smaller blocks than asked, keep its size.
+                self.size = block.size;
+                self.num = block.num + 1;
+                self.done = !block.more;
+                Ok(Some((offset, response.payload)))
+            }
+            None => {
+                // The resource fits in one response.
+                self.done = true;
+                Ok(Some((0, response.payload)))
+            }
+        }
+    }
+}
+
+/// A client observing a resource of the [`CoapServer`].
+#[derive(Debug, Clone, Copy)]
+pub struct Observer {
+    endpoint: IpEndpoint,
+    token: [u8; 8],
+    token_len: u8,
+}
+
+/// A request received by the [`CoapServer`].
+pub struct Request<'a> {
+    pub message: Message<'a>,
+    pub from: IpEndpoint,
+}
+
+impl<'a> Request<'a> {
+    /// Returns whether the Uri-Path options of the request are the segments of `path`.
+    pub fn path_matches(&self, path: &str) -> bool {
+        let mut segments = path.split('/').filter(|s| !s.is_empty());
+        let mut options = self
+            .message
+            .options()
+            .filter(|(n, _)| *n == OPTION_URI_PATH)
+            .map(|(_, v)| v);
+        loop {
+            match (segments.next(), options.next()) {
+                (None, None) => return true,
+                (Some(s), Some(o)) if s.as_bytes() == o => {}
+                _ => return false,
+            }
+        }
+    }
+
+    /// Get the observer this request registers, if it's a GET with `Observe: 0`.
+    pub fn observer(&self) -> Option<Observer> {
+        if self.message.code != Code::GET || self.message.observe() != Some(0) {
+            return None;
+        }
+        let mut token = [0; 8];
+        token[..self.message.token.len()].copy_from_slice(self.message.token);
+        Some(Observer {
+            endpoint: self.from,
+            token,
+            token_len: self.message.token.len() as u8,
+        })
+    }
+}
+
+/// CoAP server, answering requests on the CoAP port.
+pub struct CoapServer<'a> {
+    socket: UdpSocket<'a>,
+    tx_buf: &'a mut [u8],
+    next_message_id: u16,
+}
+
+impl<'a> CoapServer<'a> {
+    /// Create a server on `socket`. Responses are encoded in `tx_buf`.
+    pub fn new(mut socket: UdpSocket<'a>, tx_buf: &'a mut [u8]) -> Self {
+        unwrap!(socket.bind(COAP_PORT));
+        let mut id = [0; 2];
+        crate::stack::rand(&mut id);
+        Self {
+            socket,
+            tx_buf,
+            next_message_id: u16::from_le_bytes(id),
+        }
+    }
+
+    /// Wait for the next request, and read it into `buf`.
+    pub async fn next_request<'b>(&mut self, buf: &'b mut [u8]) -> Result<Request<'b>, CoapError> {
+        let (len, from) = loop {
+            let (n, from) = self
+                .socket
+                .recv_from(buf)
+                .await
+                .map_err(CoapError::Network)?;
+            let msg = match Message::parse(&buf[..n]) {
+                Ok(msg) => msg,
+                Err(_) => continue,
+            };
+            match msg.ty {
+                MessageType::Confirmable | MessageType::NonConfirmable if msg.code.is_request() => {
+                    break (n, from)
+                }
+                // Answer pings, and anything else that isn't a request.
+                MessageType::Confirmable => {
+                    let id = msg.message_id;
+                    let len = MessageWriter::new(
+                        &mut *self.tx_buf,
+                        MessageType::Reset,
+                        Code::EMPTY,
+                        id,
+                        &[],
+                    )?
+                    .finish(&[])?;
+                    self.socket
+                        .send_to(&self.tx_buf[..len], from)
+                        .await
+                        .map_err(CoapError::Network)?;
+                }
+                _ => {}
+            }
+        };
+        Ok(Request {
+            message: Message::parse(&buf[..len])?,
+            from,
+        })
+    }
+
+    /// Answer `request`.
+    ///
+    /// Confirmable requests get a piggybacked response. When the request
+    /// registers an observer, the response carries the Observe option, and
+    /// further notifications are sent with [`notify`](Self::notify).
+    pub async fn respond(
+        &mut self,
+        request: &Request<'_>,
+        code: Code,
+        content_format: Option<u16>,
+        payload: &[u8],
+    ) -> Result<(), CoapError> {
+        let (ty, message_id) = match request.message.ty {
+            MessageType::Confirmable => (MessageType::Acknowledgement, request.message.message_id),
+            _ => (MessageType::NonConfirmable, self.new_message_id()),
+        };
+        let observe = if request.observer().is_some() && code.is_success() {
+            Some(0)
+        } else {
+            None
+        };
+        self.send(
+            request.from,
+            ty,
+            message_id,
+            request.message.token,
+            code,
+            observe,
+            content_format,
+            payload,
+        )
+        .await
+    }
+
+    /// Send a notification to an observer, as a non-confirmable message.
+    ///
+    /// `seq` must increase with each notification.
+    pub async fn notify(
+        &mut self,
+        observer: &Observer,
+        seq: u32,
+        code: Code,
+        content_format: Option<u16>,
+        payload: &[u8],
+    ) -> Result<(), CoapError> {
+        let message_id = self.new_message_id();
+        self.send(
+            observer.endpoint,
+            MessageType::NonConfirmable,
+            message_id,
+            &observer.token[..observer.token_len as usize],
+            code,
+            Some(seq & 0xff_ffff),
+            content_format,
+            payload,
+        )
+        .await
+    }
+
+    fn new_message_id(&mut self) -> u16 {
+        let id = self.next_message_id;
+        self.next_message_id = self.next_message_id.wrapping_add(1);
+        id
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn send(
+        &mut self,
+        to: IpEndpoint,
+        ty: MessageType,
+        message_id: u16,
+        token: &[u8],
+        code: Code,
+        observe: Option<u32>,
+        content_format: Option<u16>,
+        payload: &[u8],
+    ) -> Result<(), CoapError> {
+        let mut w = MessageWriter::new(&mut *self.tx_buf, ty, code, message_id, token)?;
+        if let Some(observe) = observe {
+            w.uint_option(OPTION_OBSERVE, observe)?;
+        }
+        if let Some(content_format) = content_format {
+            w.uint_option(OPTION_CONTENT_FORMAT, content_format as u32)?;
+        }
+        let len = w.finish(payload)?;
+        self.socket
+            .send_to(&self.tx_buf[..len], to)
+            .await
+            .map_err(CoapError::Network)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(buf: &mut [u8]) -> usize {
+        let mut spec = RequestSpec::new(Code::GET, "sensors/temp?unit=c");
+        spec.observe = Some(0);
+        spec.block2 = Some(Block {
+            num: 3,
+            more: false,
+            size: BlockSize::S64,
+        });
+        spec.payload = b"hello";
+        unwrap!(spec.encode(buf, MessageType::Confirmable, 0x1234, &[1, 2, 3, 4]))
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut buf = [0; 64];
+        let len = request(&mut buf);
+        let msg = unwrap!(Message::parse(&buf[..len]));
+
+        assert_eq!(msg.ty, MessageType::Confirmable);
+        assert_eq!(msg.code, Code::GET);
+        assert_eq!(msg.message_id, 0x1234);
+        assert_eq!(msg.token, &[1, 2, 3, 4]);
+        assert_eq!(msg.observe(), Some(0));
+        assert_eq!(
+            msg.block2(),
+            Some(Block {
+                num: 3,
+                more: false,
+                size: BlockSize::S64
+            })
+        );
+        assert_eq!(msg.option(OPTION_URI_QUERY), Some(&b"unit=c"[..]));
+        assert_eq!(msg.payload, b"hello");
+
+        let request = Request {
+            message: msg,
+            from: IpEndpoint::new(IpAddress::v4(10, 0, 0, 1), 5683),
+        };
+        assert!(request.path_matches("/sensors/temp"));
+        assert!(!request.path_matches("/sensors"));
+    }
+
+    #[test]
+    fn truncated() {
+        let mut buf = [0; 64];
+        let len = request(&mut buf);
+        // Cutting the message ends up in the middle of the header, the token,
+        // an option or the payload. Only the latter gives a valid message.
+        let payload_start = len - 5;
+        for n in 0..payload_start {
+            if let Ok(msg) = Message::parse(&buf[..n]) {
+                assert!(msg.payload.is_empty());
+                // Every option that made it is complete.
+                assert!(msg.options().count() <= 5);
+            }
+        }
+        assert_eq!(
+            Message::parse(&buf[..payload_start]).err(),
+            Some(CoapError::Malformed)
+        );
+        for n in payload_start + 1..len {
+            assert_eq!(
+                unwrap!(Message::parse(&buf[..n])).payload.len(),
+                n - payload_start
+            );
+        }
+    }
+
+    #[test]
+    fn malformed() {
+        let bad: &[&[u8]] = &[
+            // Version 2.
+            &[0x80, 0x01, 0, 0],
+            // Token longer than 8 bytes.
+            &[0x49, 0x01, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9],
+            // Option delta nibble 15 without payload marker.
+            &[0x40, 0x01, 0, 0, 0xf0],
+            // Length nibble 15.
+            &[0x40, 0x01, 0, 0, 0x1f],
+            // Extended delta missing.
+            &[0x40, 0x01, 0, 0, 0xd0],
+            &[0x40, 0x01, 0, 0, 0xe0, 0x01],
+            // Value longer than the message.
+            &[0x40, 0x01, 0, 0, 0x14, 1, 2],
+            // Payload marker without payload.
+            &[0x40, 0x01, 0, 0, 0xff],
+        ];
+        for data in bad {
+            assert_eq!(Message::parse(data).err(), Some(CoapError::Malformed));
+        }
+    }
+
+    #[test]
+    fn option_number_overflow() {
+        // Options 65000, then 66000, which doesn't fit in a u16.
+        let data = [0x40, 0x01, 0, 0, 0xe0, 0xfc, 0xdb, 0xe0, 0x02, 0xdb];
+        let msg = unwrap!(Message::parse(&data[..7]));
+        assert_eq!(msg.options().next(), Some((65000, &[][..])));
+        assert_eq!(Message::parse(&data).err(), Some(CoapError::Malformed));
+
+        // Up to 65535 is fine.
+        let data = [0x40, 0x01, 0, 0, 0xe0, 0xfe, 0xe5, 0xd0, 0x00];
+        let msg = unwrap!(Message::parse(&data));
+        let mut options = msg.options();
+        assert_eq!(options.next(), Some((65522, &[][..])));
+        assert_eq!(options.next(), Some((65535, &[][..])));
+        assert_eq!(options.next(), None);
+    }
+}
@@ -1,55 +1,841 @@
 use heapless::Vec;
 use smoltcp::iface::SocketHandle;
-use smoltcp::socket::{Dhcpv4Event, Dhcpv4Socket};
-use smoltcp::time::Instant;
+use smoltcp::socket::{RawPacketMetadata, RawSocket, RawSocketBuffer};
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{
+    EthernetAddress, HardwareAddress, IpAddress, IpProtocol, IpVersion, Ipv4Packet, UdpPacket,
+};
 
 use super::*;
-use crate::device::LinkState;
+use crate::device::Device;
+use crate::dhcp_wire::*;
 use crate::Interface;
 
+const OPT_HOSTNAME: u8 = 12;
+const OPT_PARAMETER_REQUEST_LIST: u8 = 55;
+const OPT_RENEWAL_TIME: u8 = 58;
+const OPT_REBINDING_TIME: u8 = 59;
+const OPT_CLIENT_ID: u8 = 61;
+
+const FLAG_BROADCAST: u16 = 0x8000;
+
+const IPV4_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+/// Offset of the DHCP message in the packets of the raw socket.
+const DHCP_OFFSET: usize = IPV4_HEADER_LEN + UDP_HEADER_LEN;
+/// Largest packet handled, the minimum every host must accept. Servers don't
+/// send longer ones unless the client asks for it.
+const MAX_PACKET_LEN: usize = 576;
+
+const DISCOVER_TIMEOUT_SECS: u64 = 10;
+const REQUEST_TIMEOUT_SECS: u64 = 1;
+const REQUEST_RETRIES: u8 = 5;
+const MIN_RENEW_TIMEOUT_SECS: u64 = 60;
+
+const MAX_HOSTNAME_LEN: usize = 63;
+const MAX_CLIENT_ID_LEN: usize = 64;
+
+/// Memory for the socket of a [`DhcpConfigurator`].
+pub struct DhcpResources {
+    rx_meta: [RawPacketMetadata; 2],
+    rx_buffer: [u8; 2 * MAX_PACKET_LEN],
+    tx_meta: [RawPacketMetadata; 1],
+    tx_buffer: [u8; MAX_PACKET_LEN],
+}
+
+impl DhcpResources {
+    pub const fn new() -> Self {
+        Self {
+            rx_meta: [RawPacketMetadata::EMPTY; 2],
+            rx_buffer: [0; 2 * MAX_PACKET_LEN],
+            tx_meta: [RawPacketMetadata::EMPTY; 1],
+            tx_buffer: [0; MAX_PACKET_LEN],
+        }
+    }
+}
+
+/// A lease acquired by a [`DhcpConfigurator`].
+///
+/// Read it with [`Stack::dhcp_lease`](crate::Stack::dhcp_lease).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DhcpLease {
+    /// Identifier of the server that granted the lease, usually its address.
+    pub server: Ipv4Address,
+    pub address: Ipv4Cidr,
+    /// When the lease was granted or last renewed.
+    pub acquired_at: Instant,
+    /// How long the lease is valid for, from `acquired_at`.
+    pub duration: Duration,
+    /// When to start renewing the lease with the server that granted it (T1),
+    /// from `acquired_at`.
+    pub renew_after: Duration,
+    /// When to start asking any server to extend the lease (T2), from `acquired_at`.
+    pub rebind_after: Duration,
+}
+
+impl DhcpLease {
+    fn renew_at(&self) -> Instant {
+        self.acquired_at + self.renew_after
+    }
+
+    fn rebind_at(&self) -> Instant {
+        self.acquired_at + self.rebind_after
+    }
+
+    fn expires_at(&self) -> Instant {
+        self.acquired_at + self.duration
+    }
+}
+
+enum State {
+    /// Broadcasting discovers, waiting for an offer.
+    Discovering { retry_at: Instant },
+    /// Asking `server` for the `address` it offered.
+    Requesting {
+        server: Ipv4Address,
+        address: Ipv4Address,
+        retry_at: Instant,
+        retries: u8,
+    },
+    /// Holding a lease. Once it's time to renew it, requests are sent at `retry_at`.
+    Bound {
+        lease: DhcpLease,
+        config: Config,
+        retry_at: Instant,
+    },
+}
+
+/// Configure the stack through DHCPv4.
+///
+/// The acquired configuration can be read back with [`Stack::config`](crate::Stack::config),
+/// and the lease with [`Stack::dhcp_lease`](crate::Stack::dhcp_lease).
+///
+/// The configurator uses one of the sockets of the stack.
 pub struct DhcpConfigurator {
+    resources: *mut DhcpResources,
     handle: Option<SocketHandle>,
+    state: State,
+    xid: u32,
+    max_lease_duration: Option<Duration>,
+    requested_lease_duration: Option<Duration>,
+    hostname: Vec<u8, MAX_HOSTNAME_LEN>,
+    client_id: Vec<u8, MAX_CLIENT_ID_LEN>,
 }
 
+// Safety: the resources are only accessed through the socket, and the socket
+// through the interface, which the stack guards.
+unsafe impl Send for DhcpConfigurator {}
+
 impl DhcpConfigurator {
-    pub fn new() -> Self {
-        Self { handle: None }
+    pub fn new(resources: &'static mut DhcpResources) -> Self {
+        Self {
+            resources,
+            handle: None,
+            state: State::Discovering {
+                retry_at: Instant::from_millis(0),
+            },
+            xid: 0,
+            max_lease_duration: None,
+            requested_lease_duration: None,
+            hostname: Vec::new(),
+            client_id: Vec::new(),
+        }
+    }
+
+    /// Renew the lease at least this often, even if the server grants a longer one.
+    ///
+    /// This doesn't change the lease duration the server is asked for, see
+    /// [`with_requested_lease_duration`](Self::with_requested_lease_duration).
+    pub fn with_max_lease_duration(mut self, duration: Duration) -> Self {
+        self.max_lease_duration = Some(duration);
+        self
+    }
+
+    /// Ask the server for a lease of this duration. The server may grant another one.
+    pub fn with_requested_lease_duration(mut self, duration: Duration) -> Self {
+        self.requested_lease_duration = Some(duration);
+        self
+    }
+
+    /// Send a hostname (option 12), shown by routers in their list of clients.
+    ///
+    /// Panics if the hostname is longer than 63 bytes.
+    pub fn with_hostname(mut self, hostname: &str) -> Self {
+        self.hostname = unwrap!(Vec::from_slice(hostname.as_bytes()));
+        self
+    }
+
+    /// Send this client identifier (option 61), instead of the MAC address.
+    ///
+    /// `id` is the whole value of the option: the first byte is the type, 0 for
+    /// a free-form identifier.
+    ///
+    /// Panics if `id` is empty or longer than 64 bytes.
+    pub fn with_client_id(mut self, id: &[u8]) -> Self {
+        assert!(!id.is_empty());
+        self.client_id = unwrap!(Vec::from_slice(id));
+        self
+    }
+
+    /// Drop the lease and start over with a discover.
+    fn restart(&mut self, timestamp: Instant) -> Event {
+        let was_bound = matches!(self.state, State::Bound { .. });
+        self.state = State::Discovering {
+            retry_at: timestamp,
+        };
+        if was_bound {
+            Event::Deconfigured
+        } else {
+            Event::NoChange
+        }
+    }
+
+    /// Send the messages that are due.
+    fn send(&mut self, socket: &mut RawSocket, mac: EthernetAddress, timestamp: Instant) {
+        let mut buf = [0; MAX_PACKET_LEN];
+        let unspecified = Ipv4Address::UNSPECIFIED;
+
+        let len = match &mut self.state {
+            State::Discovering { retry_at } => {
+                if timestamp < *retry_at {
+                    return;
+                }
+                *retry_at = timestamp + Duration::from_secs(DISCOVER_TIMEOUT_SECS);
+                self.xid = next_xid(self.xid, mac, timestamp);
+                debug!("DHCP: sending discover");
+                let msg = Message::discover();
+                self.build(&mut buf, &msg, mac, unspecified, Ipv4Address::BROADCAST)
+            }
+            State::Requesting {
+                server,
+                address,
+                retry_at,
+                retries,
+            } => {
+                if timestamp < *retry_at {
+                    return;
+                }
+                if *retries >= REQUEST_RETRIES {
+                    debug!("DHCP: no answer to request, starting over");
+                    self.restart(timestamp);
+                    return;
+                }
+                *retries += 1;
+                *retry_at = timestamp + Duration::from_secs(REQUEST_TIMEOUT_SECS << *retries);
+                debug!("DHCP: requesting {} from {}", address, server);
+                let msg = Message::select(*server, *address);
+                self.build(&mut buf, &msg, mac, unspecified, Ipv4Address::BROADCAST)
+            }
+            State::Bound {
+                lease, retry_at, ..
+            } => {
+                if timestamp < *retry_at {
+                    return;
+                }
+                // Renew with the server that granted the lease, then ask any
+                // server once past T2. Retry after half of the time left.
+                let (dst, next) = if timestamp < lease.rebind_at() {
+                    (lease.server, lease.rebind_at())
+                } else {
+                    (Ipv4Address::BROADCAST, lease.expires_at())
+                };
+                let wait = Duration::from_millis((next - timestamp).total_millis() / 2)
+                    .max(Duration::from_secs(MIN_RENEW_TIMEOUT_SECS));
+                *retry_at = (timestamp + wait).min(next);
+                if *retry_at <= timestamp {
+                    return;
+                }
+                let address = lease.address.address();
+                self.xid = next_xid(self.xid, mac, timestamp);
+                debug!("DHCP: renewing {}", address);
+                let msg = Message::renew(address);
+                self.build(&mut buf, &msg, mac, address, dst)
+            }
+        };
+
+        if socket.send_slice(&buf[..len]).is_err() {
+            warn!("DHCP: tx buffer full");
+        }
+    }
+
+    /// Build an IPv4 packet with a DHCP request in `buf`, returning its length.
+    fn build(
+        &self,
+        buf: &mut [u8],
+        msg: &Message,
+        mac: EthernetAddress,
+        src: Ipv4Address,
+        dst: Ipv4Address,
+    ) -> usize {
+        let dhcp = &mut buf[DHCP_OFFSET..];
+        dhcp[0] = OP_BOOTREQUEST;
+        dhcp[1] = HTYPE_ETHERNET;
+        dhcp[2] = 6;
+        dhcp[4..8].copy_from_slice(&self.xid.to_be_bytes());
+        // Without an address, the replies can't be unicast to us.
+        if msg.ciaddr.is_unspecified() {
+            dhcp[10..12].copy_from_slice(&FLAG_BROADCAST.to_be_bytes());
+        }
+        dhcp[12..16].copy_from_slice(msg.ciaddr.as_bytes());
+        dhcp[28..34].copy_from_slice(mac.as_bytes());
+        dhcp[236..240].copy_from_slice(&MAGIC_COOKIE);
+
+        let mut w = OptionWriter {
+            buf: dhcp,
+            pos: OPTIONS_OFFSET,
+        };
+        w.write(OPT_MESSAGE_TYPE, &[msg.kind]);
+        if self.client_id.is_empty() {
+            let mut id = [HTYPE_ETHERNET, 0, 0, 0, 0, 0, 0];
+            id[1..].copy_from_slice(mac.as_bytes());
+            w.write(OPT_CLIENT_ID, &id);
+        } else {
+            w.write(OPT_CLIENT_ID, &self.client_id);
+        }
+        if !self.hostname.is_empty() {
+            w.write(OPT_HOSTNAME, &self.hostname);
+        }
+        if let Some((server, address)) = msg.selected {
+            w.write(OPT_REQUESTED_IP, address.as_bytes());
+            w.write(OPT_SERVER_ID, server.as_bytes());
+        }
+        // The lease time is only asked for when getting a new lease.
+        if let (Some(d), true) = (self.requested_lease_duration, msg.ciaddr.is_unspecified()) {
+            let secs = d.secs().min(u32::MAX as u64) as u32;
+            w.write(OPT_LEASE_TIME, &secs.to_be_bytes());
+        }
+        w.write(
+            OPT_PARAMETER_REQUEST_LIST,
+            &[
+                OPT_SUBNET_MASK,
+                OPT_ROUTER,
+                OPT_DNS_SERVERS,
+                OPT_RENEWAL_TIME,
+                OPT_REBINDING_TIME,
+            ],
+        );
+        let dhcp_len = w.finish();
+
+        let udp_len = UDP_HEADER_LEN + dhcp_len;
+        let ip_len = IPV4_HEADER_LEN + udp_len;
+
+        let udp = &mut buf[IPV4_HEADER_LEN..ip_len];
+        udp[0..2].copy_from_slice(&CLIENT_PORT.to_be_bytes());
+        udp[2..4].copy_from_slice(&SERVER_PORT.to_be_bytes());
+        udp[4..6].copy_from_slice(&(udp_len as u16).to_be_bytes());
+        UdpPacket::new_unchecked(udp).fill_checksum(&IpAddress::Ipv4(src), &IpAddress::Ipv4(dst));
+
+        let ip = &mut buf[..IPV4_HEADER_LEN];
+        ip[0] = 0x45;
+        ip[2..4].copy_from_slice(&(ip_len as u16).to_be_bytes());
+        ip[8] = 64;
+        ip[9] = IpProtocol::Udp.into();
+        ip[12..16].copy_from_slice(src.as_bytes());
+        ip[16..20].copy_from_slice(dst.as_bytes());
+        Ipv4Packet::new_unchecked(ip).fill_checksum();
+
+        ip_len
+    }
+
+    /// Process a packet received by the socket.
+    fn process(&mut self, packet: &[u8], mac: EthernetAddress, timestamp: Instant) -> Event {
+        let msg = match reply(packet, self.xid, mac) {
+            Some(msg) => msg,
+            None => return Event::NoChange,
+        };
+        if check_options(msg).is_err() {
+            warn!("DHCP: reply with malformed options");
+            return Event::NoChange;
+        }
+        let msg_type = match find_option(msg, OPT_MESSAGE_TYPE) {
+            Some(&[t]) => t,
+            _ => return Event::NoChange,
+        };
+        let server = find_option(msg, OPT_SERVER_ID)
+            .filter(|o| o.len() == 4)
+            .map(Ipv4Address::from_bytes);
+        let yiaddr = Ipv4Address::from_bytes(&msg[16..20]);
+
+        match (&self.state, msg_type) {
+            (State::Discovering { .. }, MSG_OFFER) => {
+                let server = match server {
+                    Some(s) => s,
+                    None => return Event::NoChange,
+                };
+                debug!("DHCP: offer of {} from {}", yiaddr, server);
+                self.state = State::Requesting {
+                    server,
+                    address: yiaddr,
+                    retry_at: timestamp,
+                    retries: 0,
+                };
+                Event::NoChange
+            }
+            (State::Requesting { server: s, .. }, MSG_ACK) if server == Some(*s) => {
+                let server = *s;
+                self.bind(msg, server, yiaddr, timestamp)
+            }
+            (State::Bound { lease, .. }, MSG_ACK) if yiaddr == lease.address.address() => {
+                // Answers while rebinding may come from another server.
+                let server = server.unwrap_or(lease.server);
+                self.bind(msg, server, yiaddr, timestamp)
+            }
+            (State::Requesting { .. }, MSG_NAK) | (State::Bound { .. }, MSG_NAK) => {
+                debug!("DHCP: nak, starting over");
+                self.restart(timestamp)
+            }
+            _ => Event::NoChange,
+        }
+    }
+
+    /// Take the lease granted by an ack.
+    fn bind(
+        &mut self,
+        msg: &[u8],
+        server: Ipv4Address,
+        address: Ipv4Address,
+        timestamp: Instant,
+    ) -> Event {
+        let secs = |kind| {
+            find_option(msg, kind)
+                .filter(|o| o.len() == 4)
+                .map(|o| u32::from_be_bytes([o[0], o[1], o[2], o[3]]) as u64)
+        };
+        let mask = find_option(msg, OPT_SUBNET_MASK).filter(|o| o.len() == 4);
+        let (lease_secs, mask) = match (secs(OPT_LEASE_TIME), mask) {
+            (Some(l), Some(m)) => (l, u32::from_be_bytes([m[0], m[1], m[2], m[3]])),
+            _ => {
+                warn!("DHCP: ack without lease time or subnet mask");
+                return Event::NoChange;
+            }
+        };
+
+        // Fall back to the defaults of RFC 2131 for missing or inconsistent times.
+        let mut t2 = secs(OPT_REBINDING_TIME).unwrap_or(lease_secs * 7 / 8);
+        if t2 > lease_secs {
+            t2 = lease_secs * 7 / 8;
+        }
+        let mut t1 = secs(OPT_RENEWAL_TIME).unwrap_or(lease_secs / 2);
+        if t1 > t2 {
+            t1 = t2 / 2;
+        }
+        let mut renew_after = Duration::from_secs(t1);
+        if let Some(max) = self.max_lease_duration {
+            renew_after = renew_after.min(max);
+        }
+
+        let lease = DhcpLease {
+            server,
+            address: Ipv4Cidr::new(address, mask.leading_ones() as u8),
+            acquired_at: timestamp,
+            duration: Duration::from_secs(lease_secs),
+            renew_after,
+            rebind_after: Duration::from_secs(t2),
+        };
+
+        let mut dns_servers = Vec::new();
+        if let Some(o) = find_option(msg, OPT_DNS_SERVERS) {
+            for s in o.chunks_exact(4).take(dns_servers.capacity()) {
+                unwrap!(dns_servers.push(Ipv4Address::from_bytes(s)));
+            }
+        }
+        let config = Config {
+            address: lease.address,
+            gateway: find_option(msg, OPT_ROUTER)
+                .filter(|o| o.len() >= 4)
+                .map(|o| Ipv4Address::from_bytes(&o[..4])),
+            dns_servers,
+            #[cfg(feature = "proto-ipv6")]
+            ipv6_address: None,
+            #[cfg(feature = "proto-ipv6")]
+            ipv6_gateway: None,
+        };
+
+        debug!(
+            "DHCP: bound to {} for {} s",
+            lease.address,
+            lease.duration.secs()
+        );
+        let changed = !matches!(&self.state, State::Bound { config: c, .. } if *c == config);
+        self.state = State::Bound {
+            retry_at: lease.renew_at(),
+            lease,
+            config: config.clone(),
+        };
+        if changed {
+            Event::Configured(config)
+        } else {
+            Event::NoChange
+        }
     }
 }
 
-impl Configurator for DhcpConfigurator {
-    fn poll(&mut self, iface: &mut Interface, _timestamp: Instant) -> Event {
+impl<D: Device + 'static> Configurator<D> for DhcpConfigurator {
+    fn poll(&mut self, iface: &mut Interface<D>, timestamp: Instant) -> Event {
         if self.handle.is_none() {
-            let handle = iface.add_socket(Dhcpv4Socket::new());
-            self.handle = Some(handle)
+            // Safety: the resources are borrowed for 'static by the configurator, and
+            // only this socket uses them, until `detach` removes it.
+            let r = unsafe { &mut *self.resources };
+            let socket = RawSocket::new(
+                IpVersion::Ipv4,
+                IpProtocol::Udp,
+                RawSocketBuffer::new(&mut r.rx_meta[..], &mut r.rx_buffer[..]),
+                RawSocketBuffer::new(&mut r.tx_meta[..], &mut r.tx_buffer[..]),
+            );
+            self.handle = Some(iface.add_socket(socket));
+            self.state = State::Discovering {
+                retry_at: timestamp,
+            };
         }
 
-        let link_up = iface.device_mut().device.link_state() == LinkState::Up;
+        let mac = match iface.hardware_addr() {
+            HardwareAddress::Ethernet(mac) => mac,
+            #[allow(unreachable_patterns)]
+            _ => return Event::NoChange,
+        };
 
-        let socket = iface.get_socket::<Dhcpv4Socket>(self.handle.unwrap());
+        let socket = iface.get_socket::<RawSocket>(unwrap!(self.handle));
 
-        if !link_up {
-            socket.reset();
-            return Event::Deconfigured;
+        let mut event = Event::NoChange;
+        let mut buf = [0; MAX_PACKET_LEN];
+        while let Ok(n) = socket.recv_slice(&mut buf) {
+            match self.process(&buf[..n], mac, timestamp) {
+                Event::NoChange => {}
+                e => event = e,
+            }
         }
 
-        match socket.poll() {
-            None => Event::NoChange,
-            Some(Dhcpv4Event::Deconfigured) => Event::Deconfigured,
-            Some(Dhcpv4Event::Configured(config)) => {
-                let mut dns_servers = Vec::new();
-                for s in &config.dns_servers {
-                    if let Some(addr) = s {
-                        dns_servers.push(addr.clone()).unwrap();
-                    }
-                }
+        if let State::Bound { lease, .. } = &self.state {
+            if timestamp >= lease.expires_at() {
+                debug!("DHCP: lease of {} expired", lease.address);
+                event = self.restart(timestamp);
+            }
+        }
+
+        self.send(socket, mac, timestamp);
+
+        event
+    }
+
+    fn poll_at(&self) -> Option<Instant> {
+        self.handle?;
+        Some(match &self.state {
+            State::Discovering { retry_at } => *retry_at,
+            State::Requesting { retry_at, .. } => *retry_at,
+            State::Bound {
+                lease, retry_at, ..
+            } => (*retry_at).min(lease.expires_at()),
+        })
+    }
+
+    fn dhcp_lease(&self) -> Option<DhcpLease> {
+        match &self.state {
+            State::Bound { lease, .. } => Some(*lease),
+            _ => None,
+        }
+    }
+
+    fn detach(&mut self, iface: &mut Interface<D>) {
+        if let Some(handle) = self.handle.take() {
+            iface.remove_socket(handle);
+        }
+    }
+}
+
+/// What to put in a request, besides the options of the configurator.
+struct Message {
+    kind: u8,
+    /// Our address, when renewing a lease.
+    ciaddr: Ipv4Address,
+    /// The server and address of the offer we accept.
+    selected: Option<(Ipv4Address, Ipv4Address)>,
+}
+
+impl Message {
+    fn discover() -> Self {
+        Self {
+            kind: MSG_DISCOVER,
+            ciaddr: Ipv4Address::UNSPECIFIED,
+            selected: None,
+        }
+    }
+
+    fn select(server: Ipv4Address, address: Ipv4Address) -> Self {
+        Self {
+            kind: MSG_REQUEST,
+            ciaddr: Ipv4Address::UNSPECIFIED,
+            selected: Some((server, address)),
+        }
+    }
+
+    fn renew(ciaddr: Ipv4Address) -> Self {
+        Self {
+            kind: MSG_REQUEST,
+            ciaddr,
+            selected: None,
+        }
+    }
+}
+
+/// Get the DHCP reply to us in an IPv4 packet, if it is one.
+fn reply(packet: &[u8], xid: u32, mac: EthernetAddress) -> Option<&[u8]> {
+    let ihl = (*packet.first()? & 0x0f) as usize * 4;
+    let udp = packet.get(ihl..)?;
+    if udp.len() < UDP_HEADER_LEN
+        || u16::from_be_bytes([udp[0], udp[1]]) != SERVER_PORT
+        || u16::from_be_bytes([udp[2], udp[3]]) != CLIENT_PORT
+    {
+        return None;
+    }
+    let udp_len = u16::from_be_bytes([udp[4], udp[5]]) as usize;
+    let msg = udp.get(UDP_HEADER_LEN..udp_len)?;
+    if msg.len() < OPTIONS_OFFSET
+        || msg[0] != OP_BOOTREPLY
+        || msg[4..8] != xid.to_be_bytes()
+        || msg[28..34] != *mac.as_bytes()
+        || msg[236..240] != MAGIC_COOKIE
+    {
+        return None;
+    }
+    Some(msg)
+}
+
+/// Pick a new transaction ID. There's no RNG here, so mix the MAC address in
+/// to keep devices booting at the same time apart.
+fn next_xid(xid: u32, mac: EthernetAddress, timestamp: Instant) -> u32 {
+    let m = mac.as_bytes();
+    let seed = u32::from_be_bytes([m[2], m[3], m[4], m[5]]) ^ timestamp.total_millis() as u32;
+    xid.wrapping_mul(1_664_525).wrapping_add(1_013_904_223) ^ seed
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::boxed::Box;
+
+    use super::*;
+
+    const MAC: EthernetAddress = EthernetAddress([2, 0, 0, 0, 0, 1]);
+    const SERVER: Ipv4Address = Ipv4Address([192, 168, 1, 1]);
+    const ADDRESS: Ipv4Address = Ipv4Address([192, 168, 1, 50]);
+
+    fn configurator() -> DhcpConfigurator {
+        let mut c = DhcpConfigurator::new(Box::leak(Box::new(DhcpResources::new())));
+        c.xid = 0x1234_5678;
+        c
+    }
+
+    /// Build a reply from `SERVER` with the given options.
+    fn reply(msg_type: u8, options: &[(u8, &[u8])]) -> std::vec::Vec<u8> {
+        let mut msg = std::vec![0; OPTIONS_OFFSET];
+        msg[0] = OP_BOOTREPLY;
+        msg[1] = HTYPE_ETHERNET;
+        msg[2] = 6;
+        msg[4..8].copy_from_slice(&0x1234_5678u32.to_be_bytes());
+        msg[16..20].copy_from_slice(ADDRESS.as_bytes());
+        msg[28..34].copy_from_slice(MAC.as_bytes());
+        msg[236..240].copy_from_slice(&MAGIC_COOKIE);
+        msg.extend_from_slice(&[OPT_MESSAGE_TYPE, 1, msg_type]);
+        msg.extend_from_slice(&[OPT_SERVER_ID, 4]);
+        msg.extend_from_slice(SERVER.as_bytes());
+        for (kind, data) in options {
+            msg.extend_from_slice(&[*kind, data.len() as u8]);
+            msg.extend_from_slice(data);
+        }
+        msg.push(OPT_END);
+
+        let mut packet = std::vec![0; DHCP_OFFSET];
+        packet[0] = 0x45;
+        packet[IPV4_HEADER_LEN..][..2].copy_from_slice(&SERVER_PORT.to_be_bytes());
+        packet[IPV4_HEADER_LEN + 2..][..2].copy_from_slice(&CLIENT_PORT.to_be_bytes());
+        let udp_len = (UDP_HEADER_LEN + msg.len()) as u16;
+        packet[IPV4_HEADER_LEN + 4..][..2].copy_from_slice(&udp_len.to_be_bytes());
+        packet.extend_from_slice(&msg);
+        packet
+    }
+
+    fn ack(options: &[(u8, &[u8])]) -> std::vec::Vec<u8> {
+        let mut all: std::vec::Vec<(u8, &[u8])> = std::vec![
+            (OPT_SUBNET_MASK, &[255, 255, 255, 0]),
+            (OPT_LEASE_TIME, &[0, 0, 0x0e, 0x10]),
+            (OPT_ROUTER, &[192, 168, 1, 1]),
+        ];
+        all.extend_from_slice(options);
+        reply(MSG_ACK, &all)
+    }
+
+    fn t(secs: u64) -> Instant {
+        Instant::from_secs(secs as i64)
+    }
+
+    fn lease(c: &DhcpConfigurator) -> DhcpLease {
+        match &c.state {
+            State::Bound { lease, .. } => *lease,
+            _ => panic!("not bound"),
+        }
+    }
+
+    fn bound() -> DhcpConfigurator {
+        let mut c = configurator();
+        c.process(&reply(MSG_OFFER, &[]), MAC, t(0));
+        c.process(&ack(&[]), MAC, t(1));
+        c
+    }
+
+    #[test]
+    fn offer_then_ack() {
+        let mut c = configurator();
 
-                Event::Configured(Config {
-                    address: config.address,
-                    gateway: config.router,
-                    dns_servers,
-                })
+        let e = c.process(&reply(MSG_OFFER, &[]), MAC, t(0));
+        assert!(matches!(e, Event::NoChange));
+        assert!(matches!(
+            c.state,
+            State::Requesting {
+                server: SERVER,
+                address: ADDRESS,
+                ..
             }
+        ));
+
+        match c.process(&ack(&[]), MAC, t(1)) {
+            Event::Configured(config) => {
+                assert_eq!(config.address, Ipv4Cidr::new(ADDRESS, 24));
+                assert_eq!(config.gateway, Some(SERVER));
+            }
+            _ => panic!("not configured"),
+        }
+
+        // T1 and T2 default to half and 7/8 of the lease.
+        let lease = lease(&c);
+        assert_eq!(lease.server, SERVER);
+        assert_eq!(lease.acquired_at, t(1));
+        assert_eq!(lease.duration, Duration::from_secs(3600));
+        assert_eq!(lease.renew_after, Duration::from_secs(1800));
+        assert_eq!(lease.rebind_after, Duration::from_secs(3150));
+    }
+
+    #[test]
+    fn renewal_times() {
+        let mut c = configurator().with_max_lease_duration(Duration::from_secs(600));
+        c.process(&reply(MSG_OFFER, &[]), MAC, t(0));
+        c.process(
+            &ack(&[
+                (OPT_RENEWAL_TIME, &[0, 0, 0x07, 0xd0]),
+                (OPT_REBINDING_TIME, &[0, 0, 0x0b, 0xb8]),
+            ]),
+            MAC,
+            t(0),
+        );
+
+        let lease = lease(&c);
+        assert_eq!(lease.renew_after, Duration::from_secs(600));
+        assert_eq!(lease.rebind_after, Duration::from_secs(3000));
+    }
+
+    #[test]
+    fn renewal_ack_without_change() {
+        let mut c = bound();
+        assert!(matches!(
+            c.process(&ack(&[]), MAC, t(2000)),
+            Event::NoChange
+        ));
+        assert_eq!(lease(&c).acquired_at, t(2000));
+    }
+
+    #[test]
+    fn nak_drops_the_lease() {
+        let mut c = bound();
+        assert!(matches!(
+            c.process(&reply(MSG_NAK, &[]), MAC, t(2000)),
+            Event::Deconfigured
+        ));
+        assert!(matches!(c.state, State::Discovering { .. }));
+    }
+
+    #[test]
+    fn ignores_other_replies() {
+        let mut c = configurator();
+        c.process(&reply(MSG_OFFER, &[]), MAC, t(0));
+
+        // Another transaction.
+        let mut other = ack(&[]);
+        other[DHCP_OFFSET + 4] ^= 1;
+        assert!(matches!(c.process(&other, MAC, t(1)), Event::NoChange));
+
+        // Another client.
+        let mac = EthernetAddress([2, 0, 0, 0, 0, 2]);
+        assert!(matches!(c.process(&ack(&[]), mac, t(1)), Event::NoChange));
+
+        // Truncated packets, and an ack without lease time.
+        let ack = ack(&[]);
+        for len in [0, IPV4_HEADER_LEN, DHCP_OFFSET + OPTIONS_OFFSET - 1] {
+            assert!(matches!(c.process(&ack[..len], MAC, t(1)), Event::NoChange));
         }
+        let no_lease = reply(MSG_ACK, &[(OPT_SUBNET_MASK, &[255, 255, 255, 0])]);
+        assert!(matches!(c.process(&no_lease, MAC, t(1)), Event::NoChange));
+
+        assert!(matches!(c.state, State::Requesting { .. }));
+    }
+
+    /// The DHCP message of a request built by `c`.
+    fn request(c: &DhcpConfigurator, msg: &Message) -> std::vec::Vec<u8> {
+        let mut buf = [0; MAX_PACKET_LEN];
+        let unspecified = Ipv4Address::UNSPECIFIED;
+        let len = c.build(&mut buf, msg, MAC, unspecified, Ipv4Address::BROADCAST);
+        buf[DHCP_OFFSET..len].to_vec()
+    }
+
+    #[test]
+    fn request_options() {
+        let c = configurator();
+        let msg = request(&c, &Message::discover());
+        assert_eq!(check_options(&msg), Ok(()));
+        assert_eq!(
+            find_option(&msg, OPT_MESSAGE_TYPE),
+            Some(&[MSG_DISCOVER][..])
+        );
+        assert_eq!(
+            find_option(&msg, OPT_CLIENT_ID),
+            Some(&[HTYPE_ETHERNET, 2, 0, 0, 0, 0, 1][..])
+        );
+        assert_eq!(find_option(&msg, OPT_HOSTNAME), None);
+        assert_eq!(find_option(&msg, OPT_LEASE_TIME), None);
+
+        let c = configurator()
+            .with_hostname("sensor-1")
+            .with_client_id(b"\0board-42")
+            .with_requested_lease_duration(Duration::from_secs(86400));
+        let msg = request(&c, &Message::select(SERVER, ADDRESS));
+        assert_eq!(check_options(&msg), Ok(()));
+        assert_eq!(find_option(&msg, OPT_HOSTNAME), Some(&b"sensor-1"[..]));
+        assert_eq!(find_option(&msg, OPT_CLIENT_ID), Some(&b"\0board-42"[..]));
+        assert_eq!(
+            find_option(&msg, OPT_LEASE_TIME),
+            Some(&86400u32.to_be_bytes()[..])
+        );
+        assert_eq!(
+            find_option(&msg, OPT_REQUESTED_IP),
+            Some(ADDRESS.as_bytes())
+        );
+        assert_eq!(find_option(&msg, OPT_SERVER_ID), Some(SERVER.as_bytes()));
+
+        // Renewals don't ask for a lease time.
+        let msg = request(&c, &Message::renew(ADDRESS));
+        assert_eq!(find_option(&msg, OPT_HOSTNAME), Some(&b"sensor-1"[..]));
+        assert_eq!(find_option(&msg, OPT_LEASE_TIME), None);
+    }
+
+    #[test]
+    fn malformed_offer() {
+        let mut c = configurator();
+        let mut offer = reply(MSG_OFFER, &[(OPT_ROUTER, &[192, 168, 1, 1])]);
+        // Make the router option run past the end of the message.
+        let router = DHCP_OFFSET + OPTIONS_OFFSET + 9;
+        assert_eq!(offer[router], OPT_ROUTER);
+        offer[router + 1] = 10;
+        assert!(matches!(c.process(&offer, MAC, t(0)), Event::NoChange));
+        assert!(matches!(c.state, State::Discovering { .. }));
     }
 }
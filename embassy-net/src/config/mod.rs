@@ -1,7 +1,10 @@
 use heapless::Vec;
 use smoltcp::time::Instant;
 use smoltcp::wire::{Ipv4Address, Ipv4Cidr};
+#[cfg(feature = "proto-ipv6")]
+use smoltcp::wire::{Ipv6Address, Ipv6Cidr};
 
+use crate::device::Device;
 use crate::Interface;
 
 mod statik;
@@ -10,7 +13,7 @@ pub use statik::StaticConfigurator;
 #[cfg(feature = "dhcpv4")]
 mod dhcp;
 #[cfg(feature = "dhcpv4")]
-pub use dhcp::DhcpConfigurator;
+pub use dhcp::{DhcpConfigurator, DhcpLease, DhcpResources};
 
 /// Return value for the `Configurator::poll` function
 #[derive(Debug, Clone)]
@@ -28,8 +31,36 @@ pub struct Config {
     pub address: Ipv4Cidr,
     pub gateway: Option<Ipv4Address>,
     pub dns_servers: Vec<Ipv4Address, 3>,
+    /// Global IPv6 address, in addition to the link-local one and the one
+    /// acquired through SLAAC.
+    #[cfg(feature = "proto-ipv6")]
+    pub ipv6_address: Option<Ipv6Cidr>,
+    /// Default IPv6 gateway. Takes precedence over the router learned through SLAAC.
+    #[cfg(feature = "proto-ipv6")]
+    pub ipv6_gateway: Option<Ipv6Address>,
 }
 
-pub trait Configurator {
-    fn poll(&mut self, iface: &mut Interface, timestamp: Instant) -> Event;
+pub trait Configurator<D: Device + 'static> {
+    /// Called while the link is up. When it goes down, the stack drops the
+    /// configuration by itself.
+    fn poll(&mut self, iface: &mut Interface<D>, timestamp: Instant) -> Event;
+
+    /// Called when the configurator is replaced with
+    /// [`Stack::set_configurator`](crate::Stack::set_configurator), and when the
+    /// link comes back up.
+    ///
+    /// Release anything added to `iface`. The configurator must start over
+    /// from scratch when it's polled again.
+    fn detach(&mut self, _iface: &mut Interface<D>) {}
+
+    /// When to poll the configurator again, if it's waiting for a timeout.
+    fn poll_at(&self) -> Option<Instant> {
+        None
+    }
+
+    /// The DHCP lease the configuration comes from, if any.
+    #[cfg(feature = "dhcpv4")]
+    fn dhcp_lease(&self) -> Option<DhcpLease> {
+        None
+    }
 }
@@ -1,6 +1,7 @@
 use smoltcp::time::Instant;
 
 use super::*;
+use crate::device::Device;
 use crate::Interface;
 
 pub struct StaticConfigurator {
@@ -17,8 +18,8 @@ impl StaticConfigurator {
     }
 }
 
-impl Configurator for StaticConfigurator {
-    fn poll(&mut self, _iface: &mut Interface, _timestamp: Instant) -> Event {
+impl<D: Device + 'static> Configurator<D> for StaticConfigurator {
+    fn poll(&mut self, _iface: &mut Interface<D>, _timestamp: Instant) -> Event {
         if self.returned {
             Event::NoChange
         } else {
@@ -26,4 +27,8 @@ impl Configurator for StaticConfigurator {
             Event::Configured(self.config.clone())
         }
     }
+
+    fn detach(&mut self, _iface: &mut Interface<D>) {
+        self.returned = false;
+    }
 }
@@ -1,11 +1,10 @@
+use core::cell::Cell;
 use core::task::Waker;
 use smoltcp::phy::Device as SmolDevice;
 use smoltcp::phy::DeviceCapabilities;
 use smoltcp::time::Instant as SmolInstant;
 
-use crate::packet_pool::PacketBoxExt;
 use crate::Result;
-use crate::{Packet, PacketBox, PacketBuf};
 
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub enum LinkState {
@@ -13,58 +12,214 @@ pub enum LinkState {
     Up,
 }
 
+/// A hardware timestamp of a frame, taken by the device when the frame was sent or
+/// received, in the time base of the device's PTP (IEEE 1588) clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PtpTimestamp {
+    pub seconds: u64,
+    pub nanoseconds: u32,
+}
+
+/// A network device.
+///
+/// Packets are exchanged through tokens that lend the driver's own buffers to
+/// the stack, so drivers that already own DMA buffers (descriptor rings, for
+/// example) can hand them to smoltcp in place, without copying.
 pub trait Device {
-    fn is_transmit_ready(&mut self) -> bool;
-    fn transmit(&mut self, pkt: PacketBuf);
-    fn receive(&mut self) -> Option<PacketBuf>;
+    type RxToken<'a>: RxToken
+    where
+        Self: 'a;
+    type TxToken<'a>: TxToken
+    where
+        Self: 'a;
+
+    /// Get a received packet, along with a token to transmit a reply.
+    ///
+    /// Returns `None` if no packet is pending, or if there is no room to
+    /// transmit a reply.
+    fn receive(&mut self) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)>;
+
+    /// Get a token to transmit a packet.
+    ///
+    /// Returns `None` if there is no room to transmit.
+    fn transmit(&mut self) -> Option<Self::TxToken<'_>>;
 
     fn register_waker(&mut self, waker: &Waker);
     fn capabilities(&mut self) -> DeviceCapabilities;
     fn link_state(&mut self) -> LinkState;
     fn ethernet_address(&mut self) -> [u8; 6];
+
+    /// Receive the frames sent to the multicast MAC address `addr`.
+    ///
+    /// Called when the stack joins a multicast group. Devices that don't filter
+    /// multicast frames can ignore it.
+    fn add_multicast_address(&mut self, _addr: [u8; 6]) {}
+
+    /// Stop receiving the frames sent to `addr`, added with
+    /// [`add_multicast_address`](Self::add_multicast_address).
+    fn remove_multicast_address(&mut self, _addr: [u8; 6]) {}
+
+    /// Hardware timestamp of the last transmitted frame.
+    ///
+    /// Returns `None` if the device doesn't timestamp frames, or if the frame hasn't
+    /// been sent yet.
+    fn tx_timestamp(&mut self) -> Option<PtpTimestamp> {
+        None
+    }
+
+    /// Extended address of an IEEE 802.15.4 device.
+    ///
+    /// Only called for devices with the [`Medium::Ieee802154`](smoltcp::phy::Medium) medium.
+    #[cfg(feature = "medium-ieee802154")]
+    fn ieee802154_address(&mut self) -> [u8; 8] {
+        panic!("not an IEEE 802.15.4 device")
+    }
+
+    /// PAN identifier of an IEEE 802.15.4 device.
+    ///
+    /// Only called for devices with the [`Medium::Ieee802154`](smoltcp::phy::Medium) medium.
+    #[cfg(feature = "medium-ieee802154")]
+    fn pan_id(&mut self) -> u16 {
+        panic!("not an IEEE 802.15.4 device")
+    }
+}
+
+/// A token to consume a received packet.
+pub trait RxToken {
+    /// Consume the token, passing the received packet to `f`.
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R;
+
+    /// Hardware timestamp of the received packet.
+    ///
+    /// Returns `None` if the device doesn't timestamp frames.
+    fn timestamp(&self) -> Option<PtpTimestamp> {
+        None
+    }
+}
+
+/// A token to transmit a packet.
+pub trait TxToken {
+    /// Consume the token, passing a buffer of `len` bytes to `f` to fill in
+    /// the packet, and transmit it once `f` returns.
+    ///
+    /// `len` is never larger than the MTU reported in the device capabilities.
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R;
+}
+
+/// Direction of a traced frame.
+#[cfg(feature = "packet-trace")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TraceDirection {
+    Rx,
+    Tx,
+}
+
+/// Callback receiving every frame exchanged with the device, see
+/// [`Stack::set_packet_trace`](crate::Stack::set_packet_trace).
+#[cfg(feature = "packet-trace")]
+pub type TraceFn = fn(TraceDirection, &[u8]);
+
+/// Trace callback logging every frame with defmt.
+///
+/// Each frame is logged as `pcap <Rx|Tx> <timestamp in microseconds> <bytes>`.
+/// A host-side script can turn these lines into a pcap file for Wireshark.
+#[cfg(all(feature = "packet-trace", feature = "defmt"))]
+pub fn defmt_trace(direction: TraceDirection, frame: &[u8]) {
+    let timestamp = embassy::time::Instant::now().as_micros();
+    defmt::info!("pcap {} {} {=[u8]:x}", direction, timestamp, frame);
 }
 
-pub struct DeviceAdapter {
-    pub device: &'static mut dyn Device,
+/// Counters of the frames exchanged with the device, see [`Stack::stats`](crate::Stack::stats).
+///
+/// The counters wrap around on overflow.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceStats {
+    pub rx_frames: u32,
+    pub rx_bytes: u32,
+    pub tx_frames: u32,
+    pub tx_bytes: u32,
+    /// Times the stack had a frame to send, but the device had no free TX buffer.
+    ///
+    /// A count growing fast means the device, not the stack, is limiting throughput.
+    pub tx_no_buffer: u32,
+}
+
+fn count(stats: &Cell<DeviceStats>, f: impl FnOnce(&mut DeviceStats)) {
+    let mut s = stats.get();
+    f(&mut s);
+    stats.set(s);
+}
+
+pub struct DeviceAdapter<D: Device + 'static> {
+    pub device: &'static mut D,
     caps: DeviceCapabilities,
+    pub stats: Cell<DeviceStats>,
+    #[cfg(feature = "packet-trace")]
+    pub trace: Option<TraceFn>,
 }
 
-impl DeviceAdapter {
-    pub(crate) fn new(device: &'static mut dyn Device) -> Self {
+impl<D: Device + 'static> DeviceAdapter<D> {
+    pub(crate) fn new(device: &'static mut D) -> Self {
         Self {
             caps: device.capabilities(),
             device,
+            stats: Cell::new(DeviceStats::default()),
+            #[cfg(feature = "packet-trace")]
+            trace: None,
         }
     }
 }
 
-impl<'a> SmolDevice<'a> for DeviceAdapter {
-    type RxToken = RxToken;
-    type TxToken = TxToken<'a>;
+impl<'a, D: Device + 'static> SmolDevice<'a> for DeviceAdapter<D> {
+    type RxToken = RxTokenAdapter<'a, D::RxToken<'a>>;
+    type TxToken = TxTokenAdapter<'a, D::TxToken<'a>>;
 
     fn receive(&'a mut self) -> Option<(Self::RxToken, Self::TxToken)> {
-        let tx_pkt = PacketBox::new(Packet::new())?;
-        let rx_pkt = self.device.receive()?;
-        let rx_token = RxToken { pkt: rx_pkt };
-        let tx_token = TxToken {
-            device: self.device,
-            pkt: tx_pkt,
-        };
-
-        Some((rx_token, tx_token))
+        #[cfg(feature = "packet-trace")]
+        let trace = self.trace;
+        let stats = &self.stats;
+        self.device.receive().map(|(rx, tx)| {
+            (
+                RxTokenAdapter {
+                    token: rx,
+                    stats,
+                    #[cfg(feature = "packet-trace")]
+                    trace,
+                },
+                TxTokenAdapter {
+                    token: tx,
+                    stats,
+                    #[cfg(feature = "packet-trace")]
+                    trace,
+                },
+            )
+        })
     }
 
     /// Construct a transmit token.
     fn transmit(&'a mut self) -> Option<Self::TxToken> {
-        if !self.device.is_transmit_ready() {
-            return None;
+        #[cfg(feature = "packet-trace")]
+        let trace = self.trace;
+        let stats = &self.stats;
+        match self.device.transmit() {
+            Some(tx) => Some(TxTokenAdapter {
+                token: tx,
+                stats,
+                #[cfg(feature = "packet-trace")]
+                trace,
+            }),
+            None => {
+                count(stats, |s| s.tx_no_buffer = s.tx_no_buffer.wrapping_add(1));
+                None
+            }
         }
-
-        let tx_pkt = PacketBox::new(Packet::new())?;
-        Some(TxToken {
-            device: self.device,
-            pkt: tx_pkt,
-        })
     }
 
     /// Get a description of device capabilities.
@@ -73,32 +228,63 @@ impl<'a> SmolDevice<'a> for DeviceAdapter {
     }
 }
 
-pub struct RxToken {
-    pkt: PacketBuf,
+pub struct RxTokenAdapter<'a, T: RxToken> {
+    token: T,
+    stats: &'a Cell<DeviceStats>,
+    #[cfg(feature = "packet-trace")]
+    trace: Option<TraceFn>,
 }
 
-impl smoltcp::phy::RxToken for RxToken {
-    fn consume<R, F>(mut self, _timestamp: SmolInstant, f: F) -> Result<R>
+impl<'a, T: RxToken> smoltcp::phy::RxToken for RxTokenAdapter<'a, T> {
+    fn consume<R, F>(self, _timestamp: SmolInstant, f: F) -> Result<R>
     where
         F: FnOnce(&mut [u8]) -> Result<R>,
     {
-        f(&mut self.pkt)
+        #[cfg(feature = "packet-trace")]
+        let trace = self.trace;
+        let stats = self.stats;
+        self.token.consume(|buf| {
+            count(stats, |s| {
+                s.rx_frames = s.rx_frames.wrapping_add(1);
+                s.rx_bytes = s.rx_bytes.wrapping_add(buf.len() as u32);
+            });
+            #[cfg(feature = "packet-trace")]
+            if let Some(trace) = trace {
+                trace(TraceDirection::Rx, buf);
+            }
+            f(buf)
+        })
     }
 }
 
-pub struct TxToken<'a> {
-    device: &'a mut dyn Device,
-    pkt: PacketBox,
+pub struct TxTokenAdapter<'a, T: TxToken> {
+    token: T,
+    stats: &'a Cell<DeviceStats>,
+    #[cfg(feature = "packet-trace")]
+    trace: Option<TraceFn>,
 }
 
-impl<'a> smoltcp::phy::TxToken for TxToken<'a> {
+impl<'a, T: TxToken> smoltcp::phy::TxToken for TxTokenAdapter<'a, T> {
     fn consume<R, F>(self, _timestamp: SmolInstant, len: usize, f: F) -> Result<R>
     where
         F: FnOnce(&mut [u8]) -> Result<R>,
     {
-        let mut buf = self.pkt.slice(0..len);
-        let r = f(&mut buf)?;
-        self.device.transmit(buf);
-        Ok(r)
+        #[cfg(feature = "packet-trace")]
+        let trace = self.trace;
+        let stats = self.stats;
+        self.token.consume(len, |buf| {
+            let res = f(buf);
+            if res.is_ok() {
+                count(stats, |s| {
+                    s.tx_frames = s.tx_frames.wrapping_add(1);
+                    s.tx_bytes = s.tx_bytes.wrapping_add(buf.len() as u32);
+                });
+            }
+            #[cfg(feature = "packet-trace")]
+            if let (Some(trace), Ok(_)) = (trace, &res) {
+                trace(TraceDirection::Tx, buf);
+            }
+            res
+        })
     }
 }
@@ -0,0 +1,361 @@
+use embassy::time::Duration;
+use heapless::Vec;
+use smoltcp::wire::{IpEndpoint, Ipv4Address, Ipv4Cidr};
+
+use crate::dhcp_wire::*;
+use crate::udp_socket::UdpSocket;
+
+/// Largest message the server handles, the minimum every DHCP client must accept.
+const MAX_MESSAGE_LEN: usize = 576;
+
+/// Configuration of a [`DhcpServer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DhcpServerConfig {
+    /// Address of this device, and the subnet leases are handed out in.
+    ///
+    /// The stack must be configured with this address, usually through a
+    /// [`StaticConfigurator`](crate::StaticConfigurator).
+    pub server_address: Ipv4Cidr,
+    /// Address leased to the client. Must be in the `server_address` subnet.
+    pub lease_address: Ipv4Address,
+    /// How long the client may keep using the lease before renewing it.
+    pub lease_time: Duration,
+    /// Default gateway advertised to the client.
+    ///
+    /// Leave it to `None` for point-to-point links such as USB gadgets, so
+    /// the host doesn't route its traffic through the device.
+    pub router: Option<Ipv4Address>,
+    pub dns_servers: Vec<Ipv4Address, 3>,
+}
+
+impl DhcpServerConfig {
+    /// Serve `lease_address` from `server_address` with a 1 hour lease, no router and no DNS servers.
+    pub fn new(server_address: Ipv4Cidr, lease_address: Ipv4Address) -> Self {
+        Self {
+            server_address,
+            lease_address,
+            lease_time: Duration::from_secs(3600),
+            router: None,
+            dns_servers: Vec::new(),
+        }
+    }
+}
+
+/// A minimal DHCPv4 server handing out a single lease.
+///
+/// This is meant for point-to-point links, where the device is a USB network
+/// gadget for example, and the host expects it to provide an address. Every
+/// client gets offered the same address, so only one client can use it at a time.
+pub struct DhcpServer<'a> {
+    socket: UdpSocket<'a>,
+    config: DhcpServerConfig,
+}
+
+impl<'a> DhcpServer<'a> {
+    /// Create a DHCP server on `socket`.
+    ///
+    /// The socket is bound to the DHCP server port. Its rx and tx buffers must
+    /// each hold at least one message of 576 bytes.
+    pub fn new(mut socket: UdpSocket<'a>, config: DhcpServerConfig) -> Self {
+        unwrap!(socket.bind(SERVER_PORT));
+        Self { socket, config }
+    }
+
+    pub fn config(&self) -> &DhcpServerConfig {
+        &self.config
+    }
+
+    /// Change the configuration. Takes effect for the next message received.
+    pub fn set_config(&mut self, config: DhcpServerConfig) {
+        self.config = config;
+    }
+
+    /// Run the server, answering client requests forever.
+    pub async fn run(&mut self) -> ! {
+        let mut rx = [0; MAX_MESSAGE_LEN];
+        let mut tx = [0; MAX_MESSAGE_LEN];
+        loop {
+            let n = match self.socket.recv_from(&mut rx).await {
+                Ok((n, _)) => n,
+                Err(e) => {
+                    warn!("DHCP server: recv error: {:?}", e);
+                    continue;
+                }
+            };
+
+            let reply_type = match self.config.handle_request(&rx[..n]) {
+                Some(t) => t,
+                None => continue,
+            };
+
+            let len = self.config.build_reply(&rx[..n], reply_type, &mut tx);
+            // The client has no address yet, so always broadcast the reply.
+            let dest = IpEndpoint::new(Ipv4Address::BROADCAST.into(), CLIENT_PORT);
+            if let Err(e) = self.socket.send_to(&tx[..len], dest).await {
+                warn!("DHCP server: send error: {:?}", e);
+            }
+        }
+    }
+}
+
+impl DhcpServerConfig {
+    /// Decide how to answer a client message. Returns `None` if it must be ignored.
+    fn handle_request(&self, msg: &[u8]) -> Option<u8> {
+        if msg.len() < OPTIONS_OFFSET
+            || msg[0] != OP_BOOTREQUEST
+            || msg[1] != HTYPE_ETHERNET
+            || msg[2] != 6
+            || msg[236..240] != MAGIC_COOKIE
+        {
+            return None;
+        }
+        if check_options(msg).is_err() {
+            warn!("DHCP server: message with malformed options");
+            return None;
+        }
+
+        let msg_type = find_option(msg, OPT_MESSAGE_TYPE).and_then(|o| o.first().copied())?;
+        match msg_type {
+            MSG_DISCOVER => {
+                debug!("DHCP server: DISCOVER, offering {}", self.lease_address);
+                Some(MSG_OFFER)
+            }
+            MSG_REQUEST => {
+                // Requests selecting another server's offer are not for us.
+                if let Some(server_id) = find_option(msg, OPT_SERVER_ID) {
+                    if server_id != self.server_address.address().as_bytes() {
+                        return None;
+                    }
+                }
+
+                // The requested address is in the option while selecting an
+                // offer, and in `ciaddr` while renewing.
+                let requested = find_option(msg, OPT_REQUESTED_IP)
+                    .filter(|o| o.len() == 4)
+                    .map(Ipv4Address::from_bytes)
+                    .unwrap_or_else(|| Ipv4Address::from_bytes(&msg[12..16]));
+
+                if requested == self.lease_address {
+                    debug!("DHCP server: REQUEST, acking {}", requested);
+                    Some(MSG_ACK)
+                } else {
+                    debug!("DHCP server: REQUEST for {}, nak", requested);
+                    Some(MSG_NAK)
+                }
+            }
+            // DECLINE, RELEASE and INFORM need no answer from a single-lease server.
+            _ => None,
+        }
+    }
+
+    fn build_reply(&self, req: &[u8], msg_type: u8, buf: &mut [u8]) -> usize {
+        let server = self.server_address.address();
+        let yiaddr = match msg_type {
+            MSG_NAK => Ipv4Address::UNSPECIFIED,
+            _ => self.lease_address,
+        };
+
+        buf[..OPTIONS_OFFSET].fill(0);
+        buf[0] = OP_BOOTREPLY;
+        buf[1] = HTYPE_ETHERNET;
+        buf[2] = 6;
+        // xid
+        buf[4..8].copy_from_slice(&req[4..8]);
+        // flags
+        buf[10..12].copy_from_slice(&req[10..12]);
+        buf[16..20].copy_from_slice(yiaddr.as_bytes());
+        // giaddr, chaddr
+        buf[24..44].copy_from_slice(&req[24..44]);
+        buf[236..240].copy_from_slice(&MAGIC_COOKIE);
+
+        let mut w = OptionWriter {
+            buf,
+            pos: OPTIONS_OFFSET,
+        };
+        w.write(OPT_MESSAGE_TYPE, &[msg_type]);
+        w.write(OPT_SERVER_ID, server.as_bytes());
+        if msg_type != MSG_NAK {
+            let lease_secs = self.lease_time.as_secs().min(u32::MAX as u64) as u32;
+            let mask = netmask(self.server_address.prefix_len());
+            w.write(OPT_LEASE_TIME, &lease_secs.to_be_bytes());
+            w.write(OPT_SUBNET_MASK, &mask.to_be_bytes());
+            if let Some(router) = self.router {
+                w.write(OPT_ROUTER, router.as_bytes());
+            }
+            if !self.dns_servers.is_empty() {
+                let mut dns = [0; 12];
+                for (i, s) in self.dns_servers.iter().enumerate() {
+                    dns[i * 4..][..4].copy_from_slice(s.as_bytes());
+                }
+                w.write(OPT_DNS_SERVERS, &dns[..self.dns_servers.len() * 4]);
+            }
+        }
+        w.finish()
+    }
+}
+
+fn netmask(prefix_len: u8) -> u32 {
+    u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+
+    const SERVER: Ipv4Address = Ipv4Address([192, 168, 7, 1]);
+    const LEASE: Ipv4Address = Ipv4Address([192, 168, 7, 2]);
+    const UNSPECIFIED: Ipv4Address = Ipv4Address::UNSPECIFIED;
+    const MAC: [u8; 6] = [2, 0, 0, 0, 0, 1];
+
+    fn config() -> DhcpServerConfig {
+        let mut config = DhcpServerConfig::new(Ipv4Cidr::new(SERVER, 24), LEASE);
+        config.router = Some(SERVER);
+        unwrap!(config.dns_servers.push(Ipv4Address([8, 8, 8, 8])));
+        unwrap!(config.dns_servers.push(Ipv4Address([1, 1, 1, 1])));
+        config
+    }
+
+    /// A client message with these options.
+    fn request(ciaddr: Ipv4Address, options: &[u8]) -> Vec<u8> {
+        let mut msg = std::vec![0; OPTIONS_OFFSET];
+        msg[0] = OP_BOOTREQUEST;
+        msg[1] = HTYPE_ETHERNET;
+        msg[2] = 6;
+        msg[4..8].copy_from_slice(&0x1234_5678u32.to_be_bytes());
+        msg[10..12].copy_from_slice(&0x8000u16.to_be_bytes());
+        msg[12..16].copy_from_slice(ciaddr.as_bytes());
+        msg[28..34].copy_from_slice(&MAC);
+        msg[236..240].copy_from_slice(&MAGIC_COOKIE);
+        msg.extend_from_slice(options);
+        msg.push(OPT_END);
+        msg
+    }
+
+    /// The reply of the server to `req`, if any.
+    fn answer(config: &DhcpServerConfig, req: &[u8]) -> Option<Vec<u8>> {
+        let msg_type = config.handle_request(req)?;
+        let mut buf = [0; MAX_MESSAGE_LEN];
+        let len = config.build_reply(req, msg_type, &mut buf);
+        Some(buf[..len].to_vec())
+    }
+
+    #[test]
+    fn offer_then_ack() {
+        let mut config = config();
+
+        let discover = request(UNSPECIFIED, &[OPT_MESSAGE_TYPE, 1, MSG_DISCOVER]);
+        let offer = unwrap!(answer(&config, &discover));
+        assert_eq!(offer.len(), MIN_MESSAGE_LEN);
+        assert_eq!(offer[0], OP_BOOTREPLY);
+        // xid, flags, yiaddr and chaddr.
+        assert_eq!(offer[4..8], discover[4..8]);
+        assert_eq!(offer[10..12], discover[10..12]);
+        assert_eq!(offer[16..20], *LEASE.as_bytes());
+        assert_eq!(offer[28..34], MAC);
+        assert_eq!(check_options(&offer), Ok(()));
+        assert_eq!(
+            find_option(&offer, OPT_MESSAGE_TYPE),
+            Some(&[MSG_OFFER][..])
+        );
+        assert_eq!(find_option(&offer, OPT_SERVER_ID), Some(SERVER.as_bytes()));
+        assert_eq!(
+            find_option(&offer, OPT_LEASE_TIME),
+            Some(&3600u32.to_be_bytes()[..])
+        );
+        assert_eq!(
+            find_option(&offer, OPT_SUBNET_MASK),
+            Some(&[255, 255, 255, 0][..])
+        );
+        assert_eq!(find_option(&offer, OPT_ROUTER), Some(SERVER.as_bytes()));
+        assert_eq!(
+            find_option(&offer, OPT_DNS_SERVERS),
+            Some(&[8, 8, 8, 8, 1, 1, 1, 1][..])
+        );
+
+        let mut options = std::vec![OPT_MESSAGE_TYPE, 1, MSG_REQUEST, OPT_SERVER_ID, 4];
+        options.extend_from_slice(SERVER.as_bytes());
+        options.extend_from_slice(&[OPT_REQUESTED_IP, 4]);
+        options.extend_from_slice(LEASE.as_bytes());
+        let ack = unwrap!(answer(&config, &request(UNSPECIFIED, &options)));
+        assert_eq!(find_option(&ack, OPT_MESSAGE_TYPE), Some(&[MSG_ACK][..]));
+        assert_eq!(ack[16..20], *LEASE.as_bytes());
+
+        // Renewals have the address in `ciaddr`. Leases too long for the
+        // option become infinite.
+        config.lease_time = Duration::from_secs(1 << 40);
+        let renew = request(LEASE, &[OPT_MESSAGE_TYPE, 1, MSG_REQUEST]);
+        let ack = unwrap!(answer(&config, &renew));
+        assert_eq!(find_option(&ack, OPT_MESSAGE_TYPE), Some(&[MSG_ACK][..]));
+        assert_eq!(find_option(&ack, OPT_LEASE_TIME), Some(&[0xff; 4][..]));
+    }
+
+    #[test]
+    fn nak() {
+        let config = config();
+        let options = [
+            OPT_MESSAGE_TYPE,
+            1,
+            MSG_REQUEST,
+            OPT_REQUESTED_IP,
+            4,
+            192,
+            168,
+            7,
+            3,
+        ];
+        let nak = unwrap!(answer(&config, &request(UNSPECIFIED, &options)));
+        assert_eq!(find_option(&nak, OPT_MESSAGE_TYPE), Some(&[MSG_NAK][..]));
+        assert_eq!(find_option(&nak, OPT_SERVER_ID), Some(SERVER.as_bytes()));
+        assert_eq!(nak[16..20], [0; 4]);
+        assert_eq!(find_option(&nak, OPT_LEASE_TIME), None);
+        assert_eq!(find_option(&nak, OPT_SUBNET_MASK), None);
+    }
+
+    #[test]
+    fn ignored() {
+        let config = config();
+        let discover = request(UNSPECIFIED, &[OPT_MESSAGE_TYPE, 1, MSG_DISCOVER]);
+
+        // Selecting the offer of another server, and releasing the lease.
+        let mut options = std::vec![OPT_MESSAGE_TYPE, 1, MSG_REQUEST, OPT_SERVER_ID, 4];
+        options.extend_from_slice(&[192, 168, 7, 254]);
+        assert_eq!(config.handle_request(&request(UNSPECIFIED, &options)), None);
+        assert_eq!(
+            config.handle_request(&request(LEASE, &[OPT_MESSAGE_TYPE, 1, 7])),
+            None
+        );
+
+        // Not client requests.
+        let mut reply = discover.clone();
+        reply[0] = OP_BOOTREPLY;
+        let mut cookie = discover.clone();
+        cookie[239] ^= 1;
+        let bad: &[&[u8]] = &[
+            &reply,
+            &cookie,
+            &discover[..OPTIONS_OFFSET - 1],
+            // Without a message type.
+            &request(UNSPECIFIED, &[]),
+            &request(UNSPECIFIED, &[OPT_MESSAGE_TYPE, 0]),
+            // Truncated options.
+            &request(
+                UNSPECIFIED,
+                &[OPT_MESSAGE_TYPE, 1, MSG_DISCOVER, OPT_ROUTER, 10, 1],
+            ),
+        ];
+        for msg in bad {
+            assert_eq!(config.handle_request(msg), None);
+        }
+    }
+
+    #[test]
+    fn netmasks() {
+        assert_eq!(netmask(0), 0);
+        assert_eq!(netmask(24), 0xffff_ff00);
+        assert_eq!(netmask(32), u32::MAX);
+    }
+}
@@ -0,0 +1,271 @@
+//! DHCPv4 message layout, shared by the client and the server.
+
+use core::ops::Range;
+
+pub(crate) const SERVER_PORT: u16 = 67;
+pub(crate) const CLIENT_PORT: u16 = 68;
+
+pub(crate) const OP_BOOTREQUEST: u8 = 1;
+pub(crate) const OP_BOOTREPLY: u8 = 2;
+pub(crate) const HTYPE_ETHERNET: u8 = 1;
+pub(crate) const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+pub(crate) const OPT_PAD: u8 = 0;
+pub(crate) const OPT_SUBNET_MASK: u8 = 1;
+pub(crate) const OPT_ROUTER: u8 = 3;
+pub(crate) const OPT_DNS_SERVERS: u8 = 6;
+pub(crate) const OPT_REQUESTED_IP: u8 = 50;
+pub(crate) const OPT_LEASE_TIME: u8 = 51;
+pub(crate) const OPT_OVERLOAD: u8 = 52;
+pub(crate) const OPT_MESSAGE_TYPE: u8 = 53;
+pub(crate) const OPT_SERVER_ID: u8 = 54;
+pub(crate) const OPT_END: u8 = 255;
+
+pub(crate) const MSG_DISCOVER: u8 = 1;
+pub(crate) const MSG_OFFER: u8 = 2;
+pub(crate) const MSG_REQUEST: u8 = 3;
+pub(crate) const MSG_ACK: u8 = 5;
+pub(crate) const MSG_NAK: u8 = 6;
+
+/// Offset of the options field, right after the magic cookie.
+pub(crate) const OPTIONS_OFFSET: usize = 240;
+/// The `sname` and `file` fields, which hold more options when overloaded.
+const SNAME: Range<usize> = 44..108;
+const FILE: Range<usize> = 108..236;
+/// Minimum BOOTP message length. Some clients and servers drop shorter messages.
+pub(crate) const MIN_MESSAGE_LEN: usize = 300;
+
+pub(crate) struct OptionWriter<'b> {
+    pub buf: &'b mut [u8],
+    pub pos: usize,
+}
+
+impl<'b> OptionWriter<'b> {
+    pub fn write(&mut self, kind: u8, data: &[u8]) {
+        self.buf[self.pos] = kind;
+        self.buf[self.pos + 1] = data.len() as u8;
+        self.buf[self.pos + 2..][..data.len()].copy_from_slice(data);
+        self.pos += 2 + data.len();
+    }
+
+    /// Write the end option, and pad the message to the minimum length.
+    ///
+    /// Returns the length of the message.
+    pub fn finish(self) -> usize {
+        self.buf[self.pos] = OPT_END;
+        let len = self.pos + 1;
+
+        if len < MIN_MESSAGE_LEN {
+            self.buf[len..MIN_MESSAGE_LEN].fill(OPT_PAD);
+            MIN_MESSAGE_LEN
+        } else {
+            len
+        }
+    }
+}
+
+/// The options of a DHCP message run past the end of their field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) struct Malformed;
+
+/// The options in one field of a message, up to the end option.
+#[derive(Clone)]
+struct Options<'m>(&'m [u8]);
+
+impl<'m> Iterator for Options<'m> {
+    type Item = Result<(u8, &'m [u8]), Malformed>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match *self.0.first()? {
+                OPT_END => {
+                    self.0 = &[];
+                    return None;
+                }
+                OPT_PAD => self.0 = &self.0[1..],
+                kind => {
+                    let opts = self.0;
+                    let data = opts.get(1).and_then(|&len| opts.get(2..2 + len as usize));
+                    self.0 = data.map_or(&[][..], |d| &opts[2 + d.len()..]);
+                    return Some(data.map(|d| (kind, d)).ok_or(Malformed));
+                }
+            }
+        }
+    }
+}
+
+/// The options of a DHCP message: the options field, then the `file` and `sname`
+/// fields if the overload option says they hold more.
+fn options(msg: &[u8]) -> impl Iterator<Item = Result<(u8, &[u8]), Malformed>> {
+    let main = Options(msg.get(OPTIONS_OFFSET..).unwrap_or(&[]));
+    let overload = main
+        .clone()
+        .find_map(|o| match o {
+            Ok((OPT_OVERLOAD, &[v])) => Some(v),
+            _ => None,
+        })
+        .unwrap_or(0);
+    let field = |range: Range<usize>, bit: u8| match overload & bit {
+        0 => Options(&[]),
+        _ => Options(msg.get(range).unwrap_or(&[])),
+    };
+    main.chain(field(FILE, 1)).chain(field(SNAME, 2))
+}
+
+/// Check that no option of a DHCP message is truncated.
+pub(crate) fn check_options(msg: &[u8]) -> Result<(), Malformed> {
+    options(msg).try_for_each(|o| o.map(drop))
+}
+
+/// Find the value of option `kind` in a DHCP message.
+///
+/// Truncated options are skipped, use [`check_options`] first to reject the message.
+pub(crate) fn find_option(msg: &[u8], kind: u8) -> Option<&[u8]> {
+    options(msg).find_map(|o| match o {
+        Ok((k, data)) if k == kind => Some(data),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// A message with these options, and the `file` and `sname` fields.
+    fn message(options: &[u8], file: &[u8], sname: &[u8]) -> Vec<u8> {
+        let mut msg = std::vec![0; OPTIONS_OFFSET];
+        msg[FILE][..file.len()].copy_from_slice(file);
+        msg[SNAME][..sname.len()].copy_from_slice(sname);
+        msg[236..240].copy_from_slice(&MAGIC_COOKIE);
+        msg.extend_from_slice(options);
+        msg
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut buf = [0xff; 576];
+        let mut w = OptionWriter {
+            buf: &mut buf,
+            pos: OPTIONS_OFFSET,
+        };
+        w.write(OPT_MESSAGE_TYPE, &[MSG_OFFER]);
+        w.write(OPT_LEASE_TIME, &3600u32.to_be_bytes());
+        w.write(OPT_ROUTER, &[]);
+        w.write(OPT_DNS_SERVERS, &[8, 8, 8, 8, 1, 1, 1, 1]);
+        let len = w.finish();
+        assert_eq!(len, MIN_MESSAGE_LEN);
+        assert!(buf[OPTIONS_OFFSET + 22..len].iter().all(|&b| b == OPT_PAD));
+
+        let msg = &buf[..len];
+        assert_eq!(check_options(msg), Ok(()));
+        assert_eq!(find_option(msg, OPT_MESSAGE_TYPE), Some(&[MSG_OFFER][..]));
+        assert_eq!(
+            find_option(msg, OPT_LEASE_TIME),
+            Some(&[0, 0, 0x0e, 0x10][..])
+        );
+        assert_eq!(find_option(msg, OPT_ROUTER), Some(&[][..]));
+        assert_eq!(
+            find_option(msg, OPT_DNS_SERVERS),
+            Some(&[8, 8, 8, 8, 1, 1, 1, 1][..])
+        );
+        assert_eq!(find_option(msg, OPT_SERVER_ID), None);
+    }
+
+    #[test]
+    fn pad_and_end() {
+        let msg = message(
+            &[
+                OPT_PAD,
+                OPT_PAD,
+                OPT_MESSAGE_TYPE,
+                1,
+                MSG_ACK,
+                OPT_END,
+                // Not options anymore, even truncated.
+                OPT_LEASE_TIME,
+                4,
+                0,
+            ],
+            &[],
+            &[],
+        );
+        assert_eq!(check_options(&msg), Ok(()));
+        assert_eq!(find_option(&msg, OPT_MESSAGE_TYPE), Some(&[MSG_ACK][..]));
+        assert_eq!(find_option(&msg, OPT_LEASE_TIME), None);
+
+        // A missing end option is tolerated, and so is a message without options.
+        let msg = message(&[OPT_MESSAGE_TYPE, 1, MSG_ACK], &[], &[]);
+        assert_eq!(check_options(&msg), Ok(()));
+        assert_eq!(find_option(&msg, OPT_MESSAGE_TYPE), Some(&[MSG_ACK][..]));
+        assert_eq!(check_options(&msg[..OPTIONS_OFFSET]), Ok(()));
+        assert_eq!(find_option(&msg[..OPTIONS_OFFSET], OPT_MESSAGE_TYPE), None);
+        assert_eq!(find_option(&msg[..100], OPT_MESSAGE_TYPE), None);
+    }
+
+    #[test]
+    fn overload() {
+        let file = [
+            OPT_LEASE_TIME,
+            4,
+            0,
+            0,
+            0x0e,
+            0x10,
+            OPT_MESSAGE_TYPE,
+            1,
+            MSG_NAK,
+            OPT_END,
+        ];
+        let sname = [OPT_PAD, OPT_SERVER_ID, 4, 10, 0, 0, 1, OPT_END];
+
+        // Both fields, after the options field.
+        let msg = message(
+            &[OPT_OVERLOAD, 1, 3, OPT_MESSAGE_TYPE, 1, MSG_ACK, OPT_END],
+            &file,
+            &sname,
+        );
+        assert_eq!(check_options(&msg), Ok(()));
+        assert_eq!(find_option(&msg, OPT_MESSAGE_TYPE), Some(&[MSG_ACK][..]));
+        assert_eq!(
+            find_option(&msg, OPT_LEASE_TIME),
+            Some(&[0, 0, 0x0e, 0x10][..])
+        );
+        assert_eq!(find_option(&msg, OPT_SERVER_ID), Some(&[10, 0, 0, 1][..]));
+
+        // Only `sname`.
+        let msg = message(&[OPT_OVERLOAD, 1, 2, OPT_END], &file, &sname);
+        assert_eq!(find_option(&msg, OPT_LEASE_TIME), None);
+        assert_eq!(find_option(&msg, OPT_SERVER_ID), Some(&[10, 0, 0, 1][..]));
+
+        // Not overloaded.
+        let msg = message(&[OPT_END], &file, &sname);
+        assert_eq!(find_option(&msg, OPT_LEASE_TIME), None);
+        assert_eq!(find_option(&msg, OPT_SERVER_ID), None);
+    }
+
+    #[test]
+    fn truncated() {
+        let bad: &[&[u8]] = &[
+            &[OPT_LEASE_TIME],
+            &[OPT_LEASE_TIME, 4, 0, 0, 0x0e],
+            &[OPT_PAD, OPT_MESSAGE_TYPE, 1, MSG_ACK, OPT_SERVER_ID, 255],
+        ];
+        for options in bad {
+            let msg = message(options, &[], &[]);
+            assert_eq!(check_options(&msg), Err(Malformed));
+            assert_eq!(find_option(&msg, OPT_LEASE_TIME), None);
+        }
+
+        // An option running out of the `file` field, into `sname`.
+        let mut file = [OPT_PAD; 128];
+        file[126..].copy_from_slice(&[OPT_LEASE_TIME, 4]);
+        let msg = message(&[OPT_OVERLOAD, 1, 1, OPT_END], &file, &[0, 0, 0x0e, 0x10]);
+        assert_eq!(check_options(&msg), Err(Malformed));
+        assert_eq!(find_option(&msg, OPT_LEASE_TIME), None);
+    }
+}
@@ -0,0 +1,800 @@
+//! Minimal HTTP/1.1 client and server, over [`TcpSocket`]s.
+//!
+//! Nothing is allocated: requests and responses are read into caller-provided
+//! buffers, which must fit the whole head and body.
+//!
+//! The server answers one request per connection, and closes it afterwards.
+//! Requests are dispatched with a [`Router`] to [`Handler`]s:
+//!
+//! ```ignore
+//! struct Status;
+//!
+//! impl Handler for Status {
+//!     type HandleFuture<'a> = impl Future<Output = Result<(), HttpError>> + 'a;
+//!
+//!     fn handle<'a>(&'a mut self, _request: &'a Request<'a>, response: Responder<'a>) -> Self::HandleFuture<'a> {
+//!         async move { response.send(200, "text/plain", b"all good").await }
+//!     }
+//! }
+//!
+//! let mut router = Router::new().route(Method::Get, "/status", Status);
+//! loop {
+//!     socket.accept(80).await?;
+//!     if let Err(e) = http::serve(&mut socket, &mut buf, &mut router).await {
+//!         warn!("http: {:?}", e);
+//!     }
+//!     socket.abort();
+//! }
+//! ```
+
+use core::future::Future;
+use core::pin::Pin;
+use embassy::io::{self, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+use futures::future::{ready, Either, Ready};
+
+use crate::tcp_socket::TcpSocket;
+
+/// Errors of the HTTP client and server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum HttpError {
+    /// The socket failed.
+    Network(io::Error),
+    /// The connection was closed before the end of the message.
+    ConnectionClosed,
+    /// The peer sent something that isn't valid HTTP.
+    Malformed,
+    /// The message doesn't fit in the buffer.
+    BufferTooSmall,
+    /// The message uses a feature this implementation doesn't support, like
+    /// chunked request bodies.
+    Unsupported,
+}
+
+impl From<io::Error> for HttpError {
+    fn from(e: io::Error) -> Self {
+        HttpError::Network(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Patch,
+    Options,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Patch => "PATCH",
+            Method::Options => "OPTIONS",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "GET" => Method::Get,
+            "HEAD" => Method::Head,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "DELETE" => Method::Delete,
+            "PATCH" => Method::Patch,
+            "OPTIONS" => Method::Options,
+            _ => return None,
+        })
+    }
+}
+
+/// Header lines of a message, as received.
+#[derive(Clone, Copy)]
+pub struct Headers<'a> {
+    raw: &'a str,
+}
+
+impl<'a> Headers<'a> {
+    /// Get the value of the first header named `name`, ignoring case.
+    pub fn get(&self, name: &str) -> Option<&'a str> {
+        self.iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v)
+    }
+
+    /// Iterate over the names and values of the headers.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a str)> {
+        self.raw.split("\r\n").filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            Some((name.trim(), value.trim()))
+        })
+    }
+}
+
+/// A response received by the client.
+pub struct Response<'a> {
+    pub status: u16,
+    pub headers: Headers<'a>,
+    pub body: &'a [u8],
+}
+
+/// A request to send with the client.
+pub struct ClientRequest<'a> {
+    method: Method,
+    host: &'a str,
+    path: &'a str,
+    headers: &'a [(&'a str, &'a str)],
+    body: &'a [u8],
+}
+
+impl<'a> ClientRequest<'a> {
+    /// Request `path` from `host`, which goes in the `Host` header.
+    pub fn new(method: Method, host: &'a str, path: &'a str) -> Self {
+        Self {
+            method,
+            host,
+            path,
+            headers: &[],
+            body: &[],
+        }
+    }
+
+    /// Add headers, besides `Host` and `Content-Length` which are always sent.
+    pub fn headers(mut self, headers: &'a [(&'a str, &'a str)]) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    pub fn body(mut self, body: &'a [u8]) -> Self {
+        self.body = body;
+        self
+    }
+
+    /// Send the request on a connected `socket`, and read the response into `buf`.
+    ///
+    /// Responses with a `Content-Length`, a chunked body, or a body ending
+    /// with the connection are supported.
+    pub async fn send<'b>(
+        &self,
+        socket: &mut TcpSocket<'_>,
+        buf: &'b mut [u8],
+    ) -> Result<Response<'b>, HttpError> {
+        let mut len_buf = [0; 10];
+        socket.write_all(self.method.as_str().as_bytes()).await?;
+        socket.write_all(b" ").await?;
+        socket.write_all(self.path.as_bytes()).await?;
+        socket.write_all(b" HTTP/1.1\r\nHost: ").await?;
+        socket.write_all(self.host.as_bytes()).await?;
+        socket.write_all(b"\r\nContent-Length: ").await?;
+        socket
+            .write_all(format_usize(self.body.len(), &mut len_buf))
+            .await?;
+        socket.write_all(b"\r\n").await?;
+        write_headers(socket, self.headers).await?;
+        socket.write_all(b"\r\n").await?;
+        socket.write_all(self.body).await?;
+
+        let (head_len, filled) = read_head(socket, buf).await?;
+        let head = core::str::from_utf8(&buf[..head_len]).map_err(|_| HttpError::Malformed)?;
+        let (status, headers) = parse_response_head(head)?;
+
+        let chunked = headers
+            .get("Transfer-Encoding")
+            .map_or(false, |v| v.eq_ignore_ascii_case("chunked"));
+        let content_length = content_length(&headers)?;
+
+        // Split the buffer, so the head stays borrowed while the body is read.
+        let (head_buf, body_buf) = buf.split_at_mut(head_len);
+        let filled = filled - head_len;
+        let body_len = if self.method == Method::Head || status == 204 || status == 304 {
+            0
+        } else if chunked {
+            read_chunked_body(socket, body_buf, filled).await?
+        } else if let Some(len) = content_length {
+            read_body(socket, body_buf, filled, len).await?
+        } else {
+            read_to_close(socket, body_buf, filled).await?
+        };
+
+        // Safety: checked to be UTF-8 above.
+        let head = unsafe { core::str::from_utf8_unchecked(head_buf) };
+        let (_, headers) = parse_response_head(head)?;
+        Ok(Response {
+            status,
+            headers,
+            body: &body_buf[..body_len],
+        })
+    }
+}
+
+/// A request received by the server.
+pub struct Request<'a> {
+    pub method: Method,
+    /// Path of the request, including the query string.
+    pub path: &'a str,
+    pub headers: Headers<'a>,
+    pub body: &'a [u8],
+}
+
+impl<'a> Request<'a> {
+    /// Path of the request, without the query string.
+    pub fn path_only(&self) -> &'a str {
+        self.path.split('?').next().unwrap_or("")
+    }
+
+    /// Query string of the request, after the `?`.
+    pub fn query(&self) -> Option<&'a str> {
+        self.path.split_once('?').map(|(_, q)| q)
+    }
+}
+
+/// Sends the response to a request, from a [`Handler`].
+pub struct Responder<'a> {
+    writer: Pin<&'a mut dyn AsyncWrite>,
+}
+
+impl<'a> Responder<'a> {
+    /// Send a whole response.
+    pub async fn send(
+        mut self,
+        status: u16,
+        content_type: &str,
+        body: &[u8],
+    ) -> Result<(), HttpError> {
+        let mut len_buf = [0; 10];
+        self.write_status(status).await?;
+        self.writer.write_all(b"Content-Type: ").await?;
+        self.writer.write_all(content_type.as_bytes()).await?;
+        self.writer.write_all(b"\r\nContent-Length: ").await?;
+        self.writer
+            .write_all(format_usize(body.len(), &mut len_buf))
+            .await?;
+        self.writer.write_all(b"\r\n\r\n").await?;
+        self.writer.write_all(body).await?;
+        Ok(())
+    }
+
+    /// Start a response with a chunked body, to send it piece by piece.
+    pub async fn send_chunked(
+        mut self,
+        status: u16,
+        headers: &[(&str, &str)],
+    ) -> Result<ChunkedWriter<'a>, HttpError> {
+        self.write_status(status).await?;
+        write_headers(&mut self.writer, headers).await?;
+        self.writer
+            .write_all(b"Transfer-Encoding: chunked\r\n\r\n")
+            .await?;
+        Ok(ChunkedWriter {
+            writer: self.writer,
+        })
+    }
+
+    async fn write_status(&mut self, status: u16) -> Result<(), HttpError> {
+        let mut status_buf = [0; 10];
+        self.writer.write_all(b"HTTP/1.1 ").await?;
+        self.writer
+            .write_all(format_usize(status as usize, &mut status_buf))
+            .await?;
+        self.writer.write_all(b" ").await?;
+        self.writer.write_all(reason(status).as_bytes()).await?;
+        self.writer.write_all(b"\r\nConnection: close\r\n").await?;
+        Ok(())
+    }
+}
+
+/// Body of a response sent with [`Responder::send_chunked`].
+pub struct ChunkedWriter<'a> {
+    writer: Pin<&'a mut dyn AsyncWrite>,
+}
+
+impl<'a> ChunkedWriter<'a> {
+    /// Send `data` as one chunk.
+    pub async fn write(&mut self, data: &[u8]) -> Result<(), HttpError> {
+        if data.is_empty() {
+            // An empty chunk ends the body.
+            return Ok(());
+        }
+        let mut len_buf = [0; 10];
+        self.writer
+            .write_all(format_hex(data.len(), &mut len_buf))
+            .await?;
+        self.writer.write_all(b"\r\n").await?;
+        self.writer.write_all(data).await?;
+        self.writer.write_all(b"\r\n").await?;
+        Ok(())
+    }
+
+    /// End the body.
+    pub async fn finish(mut self) -> Result<(), HttpError> {
+        self.writer.write_all(b"0\r\n\r\n").await?;
+        Ok(())
+    }
+}
+
+/// Handles requests of the server.
+pub trait Handler {
+    type HandleFuture<'a>: Future<Output = Result<(), HttpError>> + 'a
+    where
+        Self: 'a;
+
+    /// Whether this handler serves `request`. Requests no handler serves get a 404.
+    fn serves(&self, _request: &Request<'_>) -> bool {
+        true
+    }
+
+    /// Serve `request`, sending the response with `response`.
+    fn handle<'a>(
+        &'a mut self,
+        request: &'a Request<'a>,
+        response: Responder<'a>,
+    ) -> Self::HandleFuture<'a>;
+}
+
+/// Dispatches requests to handlers, by method and path.
+pub struct Router<R> {
+    routes: R,
+}
+
+impl Router<NoRoute> {
+    pub fn new() -> Self {
+        Self { routes: NoRoute }
+    }
+}
+
+impl<R: Handler> Router<R> {
+    /// Serve requests for `method` and `path` with `handler`. The query string
+    /// isn't part of the match.
+    ///
+    /// Routes added first take precedence.
+    pub fn route<H: Handler>(
+        self,
+        method: Method,
+        path: &'static str,
+        handler: H,
+    ) -> Router<Route<R, H>> {
+        Router {
+            routes: Route {
+                method,
+                path,
+                handler,
+                previous: self.routes,
+            },
+        }
+    }
+}
+
+impl<R: Handler> Handler for Router<R> {
+    type HandleFuture<'a>
+        = R::HandleFuture<'a>
+    where
+        Self: 'a;
+
+    fn serves(&self, request: &Request<'_>) -> bool {
+        self.routes.serves(request)
+    }
+
+    fn handle<'a>(
+        &'a mut self,
+        request: &'a Request<'a>,
+        response: Responder<'a>,
+    ) -> Self::HandleFuture<'a> {
+        self.routes.handle(request, response)
+    }
+}
+
+/// Empty [`Router`].
+pub struct NoRoute;
+
+impl Handler for NoRoute {
+    type HandleFuture<'a> = Ready<Result<(), HttpError>>;
+
+    fn serves(&self, _request: &Request<'_>) -> bool {
+        false
+    }
+
+    fn handle<'a>(
+        &'a mut self,
+        _request: &'a Request<'a>,
+        _response: Responder<'a>,
+    ) -> Self::HandleFuture<'a> {
+        ready(Ok(()))
+    }
+}
+
+/// A route of a [`Router`], in front of the routes added before it.
+pub struct Route<R, H> {
+    method: Method,
+    path: &'static str,
+    handler: H,
+    previous: R,
+}
+
+impl<R, H> Route<R, H> {
+    fn matches(&self, request: &Request<'_>) -> bool {
+        request.method == self.method && request.path_only() == self.path
+    }
+}
+
+impl<R: Handler, H: Handler> Handler for Route<R, H> {
+    type HandleFuture<'a>
+        = Either<R::HandleFuture<'a>, H::HandleFuture<'a>>
+    where
+        Self: 'a;
+
+    fn serves(&self, request: &Request<'_>) -> bool {
+        (self.matches(request) && self.handler.serves(request)) || self.previous.serves(request)
+    }
+
+    fn handle<'a>(
+        &'a mut self,
+        request: &'a Request<'a>,
+        response: Responder<'a>,
+    ) -> Self::HandleFuture<'a> {
+        // Routes added first are at the end of the chain, try them first.
+        if self.previous.serves(request) {
+            Either::Left(self.previous.handle(request, response))
+        } else {
+            Either::Right(self.handler.handle(request, response))
+        }
+    }
+}
+
+/// Serve one request on an accepted `socket`, reading it into `buf`.
+///
+/// The socket is closed once the response is sent.
+pub async fn serve<H: Handler>(
+    socket: &mut TcpSocket<'_>,
+    buf: &mut [u8],
+    handler: &mut H,
+) -> Result<(), HttpError> {
+    let (head_len, filled) = read_head(socket, buf).await?;
+    let (head_buf, body_buf) = buf.split_at_mut(head_len);
+    let head = core::str::from_utf8(head_buf).map_err(|_| HttpError::Malformed)?;
+    let (method, path, headers) = parse_request_head(head)?;
+
+    if headers.get("Transfer-Encoding").is_some() {
+        return Err(HttpError::Unsupported);
+    }
+    let body_len = match content_length(&headers)? {
+        Some(len) => read_body(socket, body_buf, filled - head_len, len).await?,
+        None => 0,
+    };
+
+    let responder = Responder {
+        writer: Pin::new(&mut *socket),
+    };
+    match method {
+        Some(method) => {
+            let request = Request {
+                method,
+                path,
+                headers,
+                body: &body_buf[..body_len],
+            };
+            if handler.serves(&request) {
+                handler.handle(&request, responder).await?;
+            } else {
+                responder.send(404, "text/plain", b"Not Found").await?;
+            }
+        }
+        None => {
+            responder
+                .send(405, "text/plain", b"Method Not Allowed")
+                .await?
+        }
+    }
+
+    socket.close();
+    socket
+        .flush()
+        .await
+        .map_err(|_| HttpError::ConnectionClosed)
+}
+
+async fn write_headers<W: AsyncWrite + Unpin + ?Sized>(
+    writer: &mut W,
+    headers: &[(&str, &str)],
+) -> Result<(), HttpError> {
+    for (name, value) in headers {
+        writer.write_all(name.as_bytes()).await?;
+        writer.write_all(b": ").await?;
+        writer.write_all(value.as_bytes()).await?;
+        writer.write_all(b"\r\n").await?;
+    }
+    Ok(())
+}
+
+/// Parse the head of a response, returning its status code and headers.
+fn parse_response_head(head: &str) -> Result<(u16, Headers<'_>), HttpError> {
+    let (status_line, raw_headers) = head.split_once("\r\n").unwrap_or((head, ""));
+    let mut parts = status_line.splitn(3, ' ');
+    if !parts.next().unwrap_or("").starts_with("HTTP/1.") {
+        return Err(HttpError::Malformed);
+    }
+    let status = parts
+        .next()
+        .and_then(|s| s.parse().ok())
+        .ok_or(HttpError::Malformed)?;
+    Ok((status, Headers { raw: raw_headers }))
+}
+
+/// Parse the head of a request, returning its method if it's supported, its
+/// path and its headers.
+fn parse_request_head(head: &str) -> Result<(Option<Method>, &str, Headers<'_>), HttpError> {
+    let (request_line, raw_headers) = head.split_once("\r\n").unwrap_or((head, ""));
+    let mut parts = request_line.split(' ');
+    let method = parts.next().and_then(Method::parse);
+    let path = parts.next().ok_or(HttpError::Malformed)?;
+    Ok((method, path, Headers { raw: raw_headers }))
+}
+
+fn content_length(headers: &Headers<'_>) -> Result<Option<usize>, HttpError> {
+    match headers.get("Content-Length") {
+        Some(v) => Ok(Some(v.parse().map_err(|_| HttpError::Malformed)?)),
+        None => Ok(None),
+    }
+}
+
+/// Parse the line starting a chunk, returning the size of the chunk.
+fn parse_chunk_size(line: &[u8]) -> Result<usize, HttpError> {
+    let line = core::str::from_utf8(line).map_err(|_| HttpError::Malformed)?;
+    // Ignore chunk extensions.
+    let size = line.split(';').next().unwrap_or("").trim();
+    usize::from_str_radix(size, 16).map_err(|_| HttpError::Malformed)
+}
+
+/// Read until the end of the head of a message. Returns the length of the
+/// head, including the empty line, and the number of bytes read.
+async fn read_head(
+    socket: &mut TcpSocket<'_>,
+    buf: &mut [u8],
+) -> Result<(usize, usize), HttpError> {
+    let mut filled = 0;
+    loop {
+        if let Some(pos) = find(&buf[..filled], b"\r\n\r\n") {
+            return Ok((pos + 4, filled));
+        }
+        filled += read_more(socket, buf, filled).await?;
+    }
+}
+
+/// Read a body of `len` bytes, of which `filled` are already in `buf`.
+async fn read_body(
+    socket: &mut TcpSocket<'_>,
+    buf: &mut [u8],
+    mut filled: usize,
+    len: usize,
+) -> Result<usize, HttpError> {
+    if len > buf.len() {
+        return Err(HttpError::BufferTooSmall);
+    }
+    while filled < len {
+        filled += read_more(socket, buf, filled).await?;
+    }
+    Ok(len)
+}
+
+/// Read a body until the connection is closed.
+async fn read_to_close(
+    socket: &mut TcpSocket<'_>,
+    buf: &mut [u8],
+    mut filled: usize,
+) -> Result<usize, HttpError> {
+    loop {
+        match read_more(socket, buf, filled).await {
+            Ok(n) => filled += n,
+            Err(HttpError::ConnectionClosed) => return Ok(filled),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Read a chunked body, and decode it in place.
+async fn read_chunked_body(
+    socket: &mut TcpSocket<'_>,
+    buf: &mut [u8],
+    mut filled: usize,
+) -> Result<usize, HttpError> {
+    // Decoded data goes to `buf[..out]`, and `buf[pos..filled]` is still to decode.
+    let mut out = 0;
+    let mut pos = 0;
+    loop {
+        let line_len = loop {
+            if let Some(line_len) = find(&buf[pos..filled], b"\r\n") {
+                break line_len;
+            }
+            compact(buf, out, &mut pos, &mut filled);
+            filled += read_more(socket, buf, filled).await?;
+        };
+        let size = parse_chunk_size(&buf[pos..pos + line_len])?;
+        if size == 0 {
+            // The trailers, if any, are left unread.
+            return Ok(out);
+        }
+        // Also keeps the offsets below from overflowing.
+        if size > buf.len() {
+            return Err(HttpError::BufferTooSmall);
+        }
+
+        let mut data_start = pos + line_len + 2;
+        while filled < data_start + size + 2 {
+            if filled == buf.len() {
+                let shift = pos - out;
+                compact(buf, out, &mut pos, &mut filled);
+                data_start -= shift;
+                if filled == buf.len() {
+                    return Err(HttpError::BufferTooSmall);
+                }
+            }
+            filled += read_more(socket, buf, filled).await?;
+        }
+        buf.copy_within(data_start..data_start + size, out);
+        out += size;
+        pos = data_start + size + 2;
+    }
+}
+
+/// Move the data still to decode in `buf[pos..filled]` right after the decoded data.
+fn compact(buf: &mut [u8], out: usize, pos: &mut usize, filled: &mut usize) {
+    buf.copy_within(*pos..*filled, out);
+    *filled -= *pos - out;
+    *pos = out;
+}
+
+/// Read into `buf[filled..]`, and return the number of bytes read.
+async fn read_more(
+    socket: &mut TcpSocket<'_>,
+    buf: &mut [u8],
+    filled: usize,
+) -> Result<usize, HttpError> {
+    if filled == buf.len() {
+        return Err(HttpError::BufferTooSmall);
+    }
+    match socket.read(&mut buf[filled..]).await? {
+        0 => Err(HttpError::ConnectionClosed),
+        n => Ok(n),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn format_usize(mut value: usize, buf: &mut [u8; 10]) -> &[u8] {
+    let mut i = buf.len();
+    loop {
+        i -= 1;
+        buf[i] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            return &buf[i..];
+        }
+    }
+}
+
+fn format_hex(mut value: usize, buf: &mut [u8; 10]) -> &[u8] {
+    let mut i = buf.len();
+    loop {
+        i -= 1;
+        buf[i] = b"0123456789abcdef"[value % 16];
+        value /= 16;
+        if value == 0 {
+            return &buf[i..];
+        }
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        500 => "Internal Server Error",
+        503 => "Service Unavailable",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn response_head() {
+        let (status, headers) = unwrap!(parse_response_head(
+            "HTTP/1.1 200 OK\r\ncontent-length: 5\r\nX-Empty:\r\n\r\n"
+        ));
+        assert_eq!(status, 200);
+        assert_eq!(headers.get("Content-Length"), Some("5"));
+        assert_eq!(headers.get("x-empty"), Some(""));
+        assert_eq!(headers.get("Host"), None);
+        assert_eq!(content_length(&headers), Ok(Some(5)));
+
+        let (status, headers) = unwrap!(parse_response_head("HTTP/1.0 404 Not Found\r\n\r\n"));
+        assert_eq!(status, 404);
+        assert_eq!(headers.iter().count(), 0);
+    }
+
+    #[test]
+    fn response_head_malformed() {
+        for head in [
+            "",
+            "\r\n\r\n",
+            "HTTP/1.1\r\n\r\n",
+            "HTTP/1.1 \r\n\r\n",
+            "HTTP/1.1 abc OK\r\n\r\n",
+            "HTTP/1.1 70000 OK\r\n\r\n",
+            "HTTP/2 200 OK\r\n\r\n",
+            "RTSP/1.0 200 OK\r\n\r\n",
+        ] {
+            assert_eq!(parse_response_head(head).err(), Some(HttpError::Malformed));
+        }
+    }
+
+    #[test]
+    fn request_head() {
+        let (method, path, headers) = unwrap!(parse_request_head(
+            "POST /led?on=1 HTTP/1.1\r\nHost: device\r\nContent-Length: 0\r\n\r\n"
+        ));
+        assert_eq!(method, Some(Method::Post));
+        assert_eq!(path, "/led?on=1");
+        assert_eq!(headers.get("host"), Some("device"));
+        assert_eq!(content_length(&headers), Ok(Some(0)));
+
+        // Unknown methods get a 405, so they aren't an error.
+        let (method, path, _) = unwrap!(parse_request_head("BREW /pot HTTP/1.1\r\n\r\n"));
+        assert_eq!(method, None);
+        assert_eq!(path, "/pot");
+
+        assert_eq!(parse_request_head("GET").err(), Some(HttpError::Malformed));
+        assert_eq!(parse_request_head("").err(), Some(HttpError::Malformed));
+    }
+
+    #[test]
+    fn bad_content_length() {
+        for value in ["", "abc", "-1", "1 2", "99999999999999999999999"] {
+            let mut raw = heapless::String::<64>::new();
+            unwrap!(raw.push_str("Content-Length: "));
+            unwrap!(raw.push_str(value));
+            let headers = Headers { raw: &raw };
+            assert_eq!(content_length(&headers), Err(HttpError::Malformed));
+        }
+        let headers = Headers { raw: "Host: x" };
+        assert_eq!(content_length(&headers), Ok(None));
+    }
+
+    #[test]
+    fn chunk_size() {
+        assert_eq!(parse_chunk_size(b"0"), Ok(0));
+        assert_eq!(parse_chunk_size(b"1a"), Ok(26));
+        assert_eq!(parse_chunk_size(b"1A ; name=value"), Ok(26));
+        let bad: &[&[u8]] = &[
+            b"",
+            b";ext",
+            b"zz",
+            b"-1",
+            b"1a\xff",
+            b"fffffffffffffffffffffffff",
+        ];
+        for line in bad {
+            assert_eq!(parse_chunk_size(line), Err(HttpError::Malformed));
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use core::marker::PhantomData;
+use core::mem;
+use core::task::Poll;
+use embassy::blocking_mutex::raw::RawMutex;
+use embassy::time::{with_timeout, Duration, Instant};
+use smoltcp::iface::{Context as SmolContext, SocketHandle};
+use smoltcp::socket::IcmpSocket as SyncIcmpSocket;
+use smoltcp::socket::{IcmpEndpoint, IcmpPacketMetadata, IcmpSocketBuffer};
+use smoltcp::wire::{Icmpv4Message, Icmpv4Packet, IpAddress, Ipv4Address};
+
+use super::stack::{SocketError, SocketStack, Stack};
+use crate::device::Device;
+use crate::{Error, Result};
+
+const PING_DATA_LEN: usize = 32;
+const PING_PACKET_LEN: usize = 8 + PING_DATA_LEN;
+
+pub struct IcmpSocket<'a> {
+    stack: &'a dyn SocketStack<SyncIcmpSocket<'static>>,
+    handle: SocketHandle,
+    ident: u16,
+    seq_no: u16,
+    ghost: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> Unpin for IcmpSocket<'a> {}
+
+impl<'a> IcmpSocket<'a> {
+    /// Create an ICMP socket, bound to a random echo identifier.
+    pub fn new<D: Device + 'static, M: RawMutex>(
+        stack: &'a Stack<D, M>,
+        rx_meta: &'a mut [IcmpPacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [IcmpPacketMetadata],
+        tx_buffer: &'a mut [u8],
+    ) -> Self {
+        match Self::try_new(stack, rx_meta, rx_buffer, tx_meta, tx_buffer) {
+            Ok(socket) => socket,
+            Err(_) => panic!("No free socket, increase SOCK in StackResources"),
+        }
+    }
+
+    /// Like [`new`](Self::new), but fails with [`SocketError::NoFreeSocket`]
+    /// instead of panicking when all the sockets of the stack are in use.
+    pub fn try_new<D: Device + 'static, M: RawMutex>(
+        stack: &'a Stack<D, M>,
+        rx_meta: &'a mut [IcmpPacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [IcmpPacketMetadata],
+        tx_buffer: &'a mut [u8],
+    ) -> core::result::Result<Self, SocketError> {
+        let rx_meta: &'static mut [IcmpPacketMetadata] = unsafe { mem::transmute(rx_meta) };
+        let rx_buffer: &'static mut [u8] = unsafe { mem::transmute(rx_buffer) };
+        let tx_meta: &'static mut [IcmpPacketMetadata] = unsafe { mem::transmute(tx_meta) };
+        let tx_buffer: &'static mut [u8] = unsafe { mem::transmute(tx_buffer) };
+        let handle = SocketStack::add_socket(
+            stack,
+            SyncIcmpSocket::new(
+                IcmpSocketBuffer::new(rx_meta, rx_buffer),
+                IcmpSocketBuffer::new(tx_meta, tx_buffer),
+            ),
+        )?;
+
+        let mut ident = [0; 2];
+        crate::stack::rand(&mut ident);
+        let ident = u16::from_le_bytes(ident);
+
+        let this = Self {
+            stack,
+            handle,
+            ident,
+            seq_no: 0,
+            ghost: PhantomData,
+        };
+        unwrap!(this.with(|s, _| s.bind(IcmpEndpoint::Ident(ident))));
+        Ok(this)
+    }
+
+    /// Send a raw ICMP packet to `addr`, waiting for room in the tx buffer if needed.
+    pub async fn send_to(&mut self, buf: &[u8], addr: IpAddress) -> Result<()> {
+        futures::future::poll_fn(|cx| {
+            self.with(|s, _| match s.send_slice(buf, addr) {
+                // No space in the tx buffer
+                Err(Error::Exhausted) => {
+                    s.register_send_waker(cx.waker());
+                    Poll::Pending
+                }
+                r => Poll::Ready(r),
+            })
+        })
+        .await
+    }
+
+    /// Receive an ICMP packet addressed to this socket's echo identifier.
+    pub async fn recv_from(&mut self, buf: &mut [u8]) -> Result<(usize, IpAddress)> {
+        futures::future::poll_fn(|cx| {
+            self.with(|s, _| match s.recv_slice(buf) {
+                // No packet ready
+                Err(Error::Exhausted) => {
+                    s.register_recv_waker(cx.waker());
+                    Poll::Pending
+                }
+                r => Poll::Ready(r),
+            })
+        })
+        .await
+    }
+
+    /// Send an echo request to `addr` and wait for the reply.
+    ///
+    /// Returns the round trip time, or `Ok(None)` if no reply arrived within `timeout`.
+    pub async fn ping(&mut self, addr: Ipv4Address, timeout: Duration) -> Result<Option<Duration>> {
+        self.seq_no = self.seq_no.wrapping_add(1);
+        let seq_no = self.seq_no;
+
+        let mut buf = [0; PING_PACKET_LEN];
+        let mut packet = Icmpv4Packet::new_unchecked(&mut buf[..]);
+        packet.set_msg_type(Icmpv4Message::EchoRequest);
+        packet.set_msg_code(0);
+        packet.set_echo_ident(self.ident);
+        packet.set_echo_seq_no(seq_no);
+        for (i, b) in packet.data_mut().iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        packet.fill_checksum();
+
+        let start = Instant::now();
+        self.send_to(&buf, addr.into()).await?;
+
+        let ident = self.ident;
+        let wait_reply = async {
+            loop {
+                let (n, from) = self.recv_from(&mut buf).await?;
+                if from != IpAddress::Ipv4(addr) {
+                    continue;
+                }
+                let packet = match Icmpv4Packet::new_checked(&buf[..n]) {
+                    Ok(p) => p,
+                    Err(_) => continue,
+                };
+                if packet.msg_type() == Icmpv4Message::EchoReply
+                    && packet.echo_ident() == ident
+                    && packet.echo_seq_no() == seq_no
+                {
+                    return Ok(Instant::now() - start);
+                }
+            }
+        };
+
+        match with_timeout(timeout, wait_reply).await {
+            Ok(Ok(rtt)) => Ok(Some(rtt)),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut SyncIcmpSocket, &mut SmolContext) -> R) -> R {
+        let mut f = Some(f);
+        let mut res = None;
+        self.stack.with_socket(self.handle, &mut |s, cx| {
+            res = Some(unwrap!(f.take())(s, cx));
+        });
+        unwrap!(res)
+    }
+}
+
+impl<'a> Drop for IcmpSocket<'a> {
+    fn drop(&mut self) {
+        self.stack.remove_socket(self.handle)
+    }
+}
+
+impl<D: Device + 'static, M: RawMutex> Stack<D, M> {
+    /// Ping `addr` once, returning the round trip time.
+    ///
+    /// This briefly takes one socket out of the [`StackResources`](crate::StackResources),
+    /// and fails with `Error::Exhausted` if none is free. Returns `Ok(None)` if
+    /// no reply arrived within `timeout`.
+    pub async fn ping(&self, addr: Ipv4Address, timeout: Duration) -> Result<Option<Duration>> {
+        let mut rx_meta = [IcmpPacketMetadata::EMPTY; 1];
+        let mut rx_buffer = [0; PING_PACKET_LEN];
+        let mut tx_meta = [IcmpPacketMetadata::EMPTY; 1];
+        let mut tx_buffer = [0; PING_PACKET_LEN];
+        let mut socket = IcmpSocket::try_new(
+            self,
+            &mut rx_meta,
+            &mut rx_buffer,
+            &mut tx_meta,
+            &mut tx_buffer,
+        )
+        .map_err(|_| Error::Exhausted)?;
+        socket.ping(addr, timeout).await
+    }
+}
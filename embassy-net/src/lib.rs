@@ -1,33 +1,100 @@
 #![cfg_attr(not(feature = "std"), no_std)]
+#![feature(generic_associated_types)]
+#![cfg_attr(feature = "tls", feature(type_alias_impl_trait))]
 #![allow(clippy::new_without_default)]
 
-// This mod MUST go first, so that the others see its macros.
-pub(crate) mod fmt;
+#[macro_use]
+extern crate embassy_fmt;
 
 mod config;
 mod device;
-mod packet_pool;
 mod stack;
 
-#[cfg(feature = "dhcpv4")]
-pub use config::DhcpConfigurator;
+#[cfg(feature = "slaac")]
+mod slaac;
+
 pub use config::{Config, Configurator, Event as ConfigEvent, StaticConfigurator};
+#[cfg(feature = "dhcpv4")]
+pub use config::{DhcpConfigurator, DhcpLease, DhcpResources};
 
-pub use device::{Device, LinkState};
-pub use packet_pool::{Packet, PacketBox, PacketBoxExt, PacketBuf, MTU};
-pub use stack::{init, is_config_up, is_init, is_link_up, run, StackResources};
+#[cfg(all(feature = "packet-trace", feature = "defmt"))]
+pub use device::defmt_trace;
+pub use device::{Device, DeviceStats, LinkState, PtpTimestamp, RxToken, TxToken};
+#[cfg(feature = "packet-trace")]
+pub use device::{TraceDirection, TraceFn};
+#[cfg(feature = "tcp")]
+pub use stack::LinkDownPolicy;
+#[cfg(any(feature = "igmp", feature = "medium-ethernet"))]
+pub use stack::MulticastError;
+pub use stack::{LinkEvents, SocketError, Stack, StackResources};
 
 #[cfg(feature = "tcp")]
 mod tcp_socket;
 #[cfg(feature = "tcp")]
 pub use tcp_socket::TcpSocket;
 
+#[cfg(feature = "tls")]
+mod tls_socket;
+#[cfg(feature = "tls")]
+pub use embedded_tls::TlsError;
+#[cfg(feature = "tls")]
+pub use tls_socket::TlsSocket;
+
+#[cfg(feature = "http")]
+pub mod http;
+
+#[cfg(feature = "mqtt")]
+mod mqtt;
+#[cfg(feature = "mqtt")]
+pub use mqtt::{ConnectOptions, MqttClient, MqttError, MqttMessage, QoS, Will};
+
+#[cfg(feature = "udp")]
+mod udp_socket;
+#[cfg(feature = "udp")]
+pub use smoltcp::socket::UdpPacketMetadata;
+#[cfg(feature = "udp")]
+pub use udp_socket::UdpSocket;
+
+#[cfg(feature = "icmp")]
+mod icmp_socket;
+#[cfg(feature = "icmp")]
+pub use icmp_socket::IcmpSocket;
+#[cfg(feature = "icmp")]
+pub use smoltcp::socket::IcmpPacketMetadata;
+
+#[cfg(feature = "coap")]
+pub mod coap;
+
+#[cfg(feature = "sntp")]
+mod sntp;
+#[cfg(feature = "sntp")]
+pub use sntp::{SntpClient, SntpError};
+
+#[cfg(feature = "ppp")]
+pub mod ppp;
+#[cfg(feature = "slip")]
+pub mod slip;
+
+#[cfg(any(feature = "dhcpv4", feature = "dhcpv4-server"))]
+mod dhcp_wire;
+
+#[cfg(feature = "dhcpv4-server")]
+mod dhcp_server;
+#[cfg(feature = "dhcpv4-server")]
+pub use dhcp_server::{DhcpServer, DhcpServerConfig};
+
 // smoltcp reexports
 pub use smoltcp::phy::{DeviceCapabilities, Medium};
 pub use smoltcp::time::Duration as SmolDuration;
 pub use smoltcp::time::Instant as SmolInstant;
 #[cfg(feature = "medium-ethernet")]
-pub use smoltcp::wire::{EthernetAddress, HardwareAddress};
-pub use smoltcp::wire::{IpAddress, IpCidr, Ipv4Address, Ipv4Cidr};
-pub type Interface = smoltcp::iface::Interface<'static, device::DeviceAdapter>;
+pub use smoltcp::wire::EthernetAddress;
+#[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+pub use smoltcp::wire::HardwareAddress;
+#[cfg(feature = "medium-ieee802154")]
+pub use smoltcp::wire::{Ieee802154Address, Ieee802154Pan};
+pub use smoltcp::wire::{IpAddress, IpCidr, IpEndpoint, Ipv4Address, Ipv4Cidr};
+#[cfg(feature = "proto-ipv6")]
+pub use smoltcp::wire::{Ipv6Address, Ipv6Cidr};
+pub type Interface<D> = smoltcp::iface::Interface<'static, device::DeviceAdapter<D>>;
 pub use smoltcp::{Error, Result};
@@ -0,0 +1,633 @@
+use embassy::io::{self, AsyncBufReadExt, AsyncWriteExt};
+use embassy::time::{with_timeout, Duration, Instant};
+
+use crate::tcp_socket::TcpSocket;
+
+const CONNECT: u8 = 1;
+const CONNACK: u8 = 2;
+const PUBLISH: u8 = 3;
+const PUBACK: u8 = 4;
+const SUBSCRIBE: u8 = 8;
+const SUBACK: u8 = 9;
+const UNSUBSCRIBE: u8 = 10;
+const UNSUBACK: u8 = 11;
+const PINGREQ: u8 = 12;
+const PINGRESP: u8 = 13;
+const DISCONNECT: u8 = 14;
+
+/// Protocol level of MQTT 3.1.1.
+const PROTOCOL_LEVEL: u8 = 4;
+
+/// Errors of an MQTT client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MqttError {
+    /// The client isn't connected, call [`MqttClient::connect`] first.
+    NotConnected,
+    /// The broker refused the connection, with the return code of the CONNACK.
+    ConnectionRefused(u8),
+    /// The broker refused a subscription.
+    SubscriptionRefused,
+    /// The broker didn't answer in time.
+    Timeout,
+    /// The broker sent something that isn't valid MQTT.
+    Protocol,
+    /// An incoming packet doesn't fit in the buffer of the client.
+    BufferTooSmall,
+    /// The socket failed, or was closed by the broker.
+    Network(io::Error),
+}
+
+/// Quality of service of a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum QoS {
+    /// The message is sent once, and lost if the connection drops.
+    AtMostOnce = 0,
+    /// The message is sent until the receiver acknowledges it, and may be received twice.
+    AtLeastOnce = 1,
+}
+
+/// Message the broker publishes when the client disconnects without a DISCONNECT packet.
+pub struct Will<'a> {
+    pub topic: &'a str,
+    pub payload: &'a [u8],
+    pub qos: QoS,
+    pub retain: bool,
+}
+
+/// Parameters of [`MqttClient::connect`].
+pub struct ConnectOptions<'a> {
+    pub client_id: &'a str,
+    /// Maximum time between two packets sent by the client. The client sends
+    /// pings when it has nothing else to send. Zero disables keep-alive.
+    pub keep_alive: Duration,
+    /// Start a new session, instead of resuming the subscriptions and queued
+    /// messages of the previous one.
+    pub clean_session: bool,
+    pub username: Option<&'a str>,
+    pub password: Option<&'a [u8]>,
+    pub will: Option<Will<'a>>,
+}
+
+impl<'a> ConnectOptions<'a> {
+    /// Options with a 60 second keep-alive, a clean session and no credentials.
+    pub fn new(client_id: &'a str) -> Self {
+        Self {
+            client_id,
+            keep_alive: Duration::from_secs(60),
+            clean_session: true,
+            username: None,
+            password: None,
+            will: None,
+        }
+    }
+}
+
+/// A message received from the broker.
+pub struct MqttMessage<'a> {
+    pub topic: &'a str,
+    pub payload: &'a [u8],
+    pub qos: QoS,
+    pub retain: bool,
+}
+
+/// MQTT 3.1.1 client over a [`TcpSocket`], with QoS 0 and 1.
+///
+/// Incoming packets are read into the buffer passed to [`new`](Self::new),
+/// so it must fit the largest message the client subscribes to, plus its
+/// topic. While waiting for the acknowledgement of a publish or a
+/// subscription, one incoming message is kept in the buffer for the next
+/// [`next_message`](Self::next_message), and any other is dropped.
+///
+/// When the connection drops, methods fail with [`MqttError::Network`] or
+/// [`MqttError::Timeout`]. Reconnect the socket with [`socket`](Self::socket),
+/// then call [`connect`](Self::connect) again, and subscribe again if it
+/// returns that the broker didn't keep the session.
+pub struct MqttClient<'a> {
+    socket: TcpSocket<'a>,
+    buf: &'a mut [u8],
+    /// Bytes read into `buf`.
+    rx_len: usize,
+    /// Length of the message at the start of `buf` returned by the last `next_message`.
+    delivered: usize,
+    /// Length of the message at the start of `buf` kept while waiting for an ack.
+    stashed: usize,
+    connected: bool,
+    keep_alive: Duration,
+    last_tx: Instant,
+    ping_pending: bool,
+    next_packet_id: u16,
+    timeout: Duration,
+}
+
+impl<'a> MqttClient<'a> {
+    /// Create a client on an already connected `socket`.
+    ///
+    /// Acknowledgements time out after 10 seconds by default.
+    pub fn new(socket: TcpSocket<'a>, buf: &'a mut [u8]) -> Self {
+        Self {
+            socket,
+            buf,
+            rx_len: 0,
+            delivered: 0,
+            stashed: 0,
+            connected: false,
+            keep_alive: Duration::from_secs(0),
+            last_tx: Instant::now(),
+            ping_pending: false,
+            next_packet_id: 1,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Set how long to wait for the broker to acknowledge a connection, a
+    /// QoS 1 publish or a subscription.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Get the socket, to reconnect it after the connection dropped.
+    pub fn socket(&mut self) -> &mut TcpSocket<'a> {
+        &mut self.socket
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Open an MQTT session on the socket.
+    ///
+    /// Returns whether the broker resumed a previous session, in which case
+    /// the subscriptions of that session are still active.
+    pub async fn connect(&mut self, options: &ConnectOptions<'_>) -> Result<bool, MqttError> {
+        self.connected = false;
+        self.rx_len = 0;
+        self.delivered = 0;
+        self.stashed = 0;
+        self.ping_pending = false;
+        self.keep_alive = options.keep_alive;
+
+        let mut flags = 0;
+        if options.clean_session {
+            flags |= 0x02;
+        }
+        if let Some(will) = &options.will {
+            flags |= 0x04 | (will.qos as u8) << 3;
+            if will.retain {
+                flags |= 0x20;
+            }
+        }
+        if options.password.is_some() {
+            flags |= 0x40;
+        }
+        if options.username.is_some() {
+            flags |= 0x80;
+        }
+        let keep_alive = (options.keep_alive.as_secs().min(u16::MAX as u64) as u16).to_be_bytes();
+        let header = [
+            0,
+            4,
+            b'M',
+            b'Q',
+            b'T',
+            b'T',
+            PROTOCOL_LEVEL,
+            flags,
+            keep_alive[0],
+            keep_alive[1],
+        ];
+
+        let client_id = options.client_id.as_bytes();
+        let (will_topic, will_payload) = match &options.will {
+            Some(will) => (will.topic.as_bytes(), will.payload),
+            None => (&[][..], &[][..]),
+        };
+        let username = options.username.map(|u| u.as_bytes());
+        let password = options.password;
+
+        let mut len = header.len() + 2 + client_id.len();
+        if options.will.is_some() {
+            len += 2 + will_topic.len() + 2 + will_payload.len();
+        }
+        if let Some(username) = username {
+            len += 2 + username.len();
+        }
+        if let Some(password) = password {
+            len += 2 + password.len();
+        }
+
+        self.write_header(CONNECT << 4, len).await?;
+        self.write(&header).await?;
+        self.write_field(client_id).await?;
+        if options.will.is_some() {
+            self.write_field(will_topic).await?;
+            self.write_field(will_payload).await?;
+        }
+        if let Some(username) = username {
+            self.write_field(username).await?;
+        }
+        if let Some(password) = password {
+            self.write_field(password).await?;
+        }
+
+        let len = with_timeout(self.timeout, self.read_packet(0))
+            .await
+            .map_err(|_| MqttError::Timeout)??;
+        let packet = &self.buf[..len];
+        if packet[0] >> 4 != CONNACK || len != 4 {
+            return Err(MqttError::Protocol);
+        }
+        let session_present = packet[2] & 0x01 != 0;
+        let code = packet[3];
+        self.discard(0, len);
+        if code != 0 {
+            return Err(MqttError::ConnectionRefused(code));
+        }
+
+        self.connected = true;
+        Ok(session_present)
+    }
+
+    /// Close the MQTT session. The broker doesn't publish the will.
+    pub async fn disconnect(&mut self) -> Result<(), MqttError> {
+        self.check_connected()?;
+        self.connected = false;
+        self.write(&[DISCONNECT << 4, 0]).await?;
+        self.socket.close();
+        Ok(())
+    }
+
+    /// Publish `payload` on `topic`.
+    ///
+    /// With [`QoS::AtLeastOnce`], this waits until the broker acknowledges the message.
+    pub async fn publish(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        qos: QoS,
+        retain: bool,
+    ) -> Result<(), MqttError> {
+        self.check_connected()?;
+
+        let mut len = 2 + topic.len() + payload.len();
+        if qos == QoS::AtLeastOnce {
+            len += 2;
+        }
+        let header = PUBLISH << 4 | (qos as u8) << 1 | retain as u8;
+        self.write_header(header, len).await?;
+        self.write_field(topic.as_bytes()).await?;
+        if qos == QoS::AtLeastOnce {
+            let id = self.packet_id();
+            self.write(&id.to_be_bytes()).await?;
+            self.write(payload).await?;
+            self.wait_ack(PUBACK, id).await?;
+        } else {
+            self.write(payload).await?;
+        }
+        Ok(())
+    }
+
+    /// Subscribe to the messages of `topic`, which can contain wildcards.
+    ///
+    /// Returns the QoS granted by the broker, which may be lower than `qos`.
+    pub async fn subscribe(&mut self, topic: &str, qos: QoS) -> Result<QoS, MqttError> {
+        self.check_connected()?;
+
+        let id = self.packet_id();
+        self.write_header(SUBSCRIBE << 4 | 0x02, 2 + 2 + topic.len() + 1)
+            .await?;
+        self.write(&id.to_be_bytes()).await?;
+        self.write_field(topic.as_bytes()).await?;
+        self.write(&[qos as u8]).await?;
+
+        match self.wait_ack(SUBACK, id).await? {
+            0 => Ok(QoS::AtMostOnce),
+            1 | 2 => Ok(QoS::AtLeastOnce),
+            _ => Err(MqttError::SubscriptionRefused),
+        }
+    }
+
+    pub async fn unsubscribe(&mut self, topic: &str) -> Result<(), MqttError> {
+        self.check_connected()?;
+
+        let id = self.packet_id();
+        self.write_header(UNSUBSCRIBE << 4 | 0x02, 2 + 2 + topic.len())
+            .await?;
+        self.write(&id.to_be_bytes()).await?;
+        self.write_field(topic.as_bytes()).await?;
+
+        self.wait_ack(UNSUBACK, id).await?;
+        Ok(())
+    }
+
+    /// Wait for the next message on the subscribed topics.
+    ///
+    /// Pings are sent while waiting, to keep the connection alive. Dropping
+    /// the future before it completes doesn't lose any data, so this can be
+    /// raced against other events with `select`, and the client used for
+    /// something else in between.
+    pub async fn next_message(&mut self) -> Result<MqttMessage<'_>, MqttError> {
+        self.check_connected()?;
+        self.discard_delivered();
+
+        let len = if self.stashed != 0 {
+            let len = self.stashed;
+            self.stashed = 0;
+            len
+        } else {
+            loop {
+                let len = self.read_packet(0).await?;
+                match self.buf[0] >> 4 {
+                    PUBLISH => break len,
+                    PINGRESP => self.ping_pending = false,
+                    _ => {}
+                }
+                self.discard(0, len);
+            }
+        };
+
+        // Drop the packet on the next call, even if it's invalid.
+        self.delivered = len;
+        let (qos, id) = parse_publish(&self.buf[..len]).map(|(m, id)| (m.qos, id))?;
+        if qos == QoS::AtLeastOnce {
+            let id = id.to_be_bytes();
+            self.write(&[PUBACK << 4, 2, id[0], id[1]]).await?;
+        }
+        parse_publish(&self.buf[..len]).map(|(m, _)| m)
+    }
+
+    fn check_connected(&self) -> Result<(), MqttError> {
+        if self.connected {
+            Ok(())
+        } else {
+            Err(MqttError::NotConnected)
+        }
+    }
+
+    fn packet_id(&mut self) -> u16 {
+        let id = self.next_packet_id;
+        // Zero isn't a valid packet identifier.
+        self.next_packet_id = self.next_packet_id.checked_add(1).unwrap_or(1);
+        id
+    }
+
+    /// Wait for the acknowledgement `kind` of packet `id`, and return the
+    /// byte that follows the id in the acknowledgement.
+    async fn wait_ack(&mut self, kind: u8, id: u16) -> Result<u8, MqttError> {
+        self.discard_delivered();
+        let timeout = self.timeout;
+        let res = with_timeout(timeout, async {
+            loop {
+                let offset = self.stashed;
+                let len = self.read_packet(offset).await?;
+                let packet = &self.buf[offset..offset + len];
+                match packet[0] >> 4 {
+                    PUBLISH if self.stashed == 0 => {
+                        self.stashed = len;
+                        continue;
+                    }
+                    PUBLISH => warn!("MQTT: dropping message received while waiting for an ack"),
+                    PINGRESP => self.ping_pending = false,
+                    t if t == kind && len >= 4 && packet[2..4] == id.to_be_bytes() => {
+                        let code = packet.get(4).copied().unwrap_or(0);
+                        self.discard(offset, len);
+                        return Ok(code);
+                    }
+                    _ => {}
+                }
+                self.discard(offset, len);
+            }
+        })
+        .await;
+        match res {
+            Ok(res) => res,
+            Err(_) => {
+                self.connected = false;
+                Err(MqttError::Timeout)
+            }
+        }
+    }
+
+    fn discard_delivered(&mut self) {
+        if self.delivered != 0 {
+            self.discard(0, self.delivered);
+            self.delivered = 0;
+        }
+    }
+
+    /// Remove the packet of `len` bytes at `offset` from the buffer.
+    fn discard(&mut self, offset: usize, len: usize) {
+        self.buf.copy_within(offset + len..self.rx_len, offset);
+        self.rx_len -= len;
+    }
+
+    /// Read a whole packet at `offset` in the buffer, and return its length.
+    ///
+    /// Once connected, this sends pings when nothing was sent for the keep-alive interval.
+    async fn read_packet(&mut self, offset: usize) -> Result<usize, MqttError> {
+        loop {
+            if let Some((header_len, remaining_len)) = parse_header(&self.buf[offset..self.rx_len])?
+            {
+                let len = header_len + remaining_len;
+                if offset + len > self.buf.len() {
+                    self.connected = false;
+                    return Err(MqttError::BufferTooSmall);
+                }
+                if offset + len <= self.rx_len {
+                    return Ok(len);
+                }
+            }
+
+            let keep_alive = self.connected && self.keep_alive.as_ticks() != 0;
+            let read = self.socket.read(&mut self.buf[self.rx_len..]);
+            let res = if keep_alive {
+                let deadline = self.last_tx + self.keep_alive;
+                let wait = deadline.saturating_duration_since(Instant::now());
+                with_timeout(wait, read).await.ok()
+            } else {
+                Some(read.await)
+            };
+
+            match res {
+                Some(Ok(0)) => {
+                    self.connected = false;
+                    return Err(MqttError::Network(io::Error::ConnectionReset));
+                }
+                Some(Ok(n)) => self.rx_len += n,
+                Some(Err(e)) => {
+                    self.connected = false;
+                    return Err(MqttError::Network(e));
+                }
+                None if self.ping_pending => {
+                    self.connected = false;
+                    return Err(MqttError::Timeout);
+                }
+                None => {
+                    self.write(&[PINGREQ << 4, 0]).await?;
+                    self.ping_pending = true;
+                }
+            }
+        }
+    }
+
+    async fn write_header(&mut self, header: u8, remaining_len: usize) -> Result<(), MqttError> {
+        let mut buf = [header, 0, 0, 0, 0];
+        let mut len = remaining_len;
+        let mut i = 1;
+        loop {
+            buf[i] = (len % 128) as u8;
+            len /= 128;
+            if len == 0 {
+                break;
+            }
+            buf[i] |= 0x80;
+            i += 1;
+        }
+        self.write(&buf[..i + 1]).await
+    }
+
+    /// Write a field prefixed with its length.
+    async fn write_field(&mut self, data: &[u8]) -> Result<(), MqttError> {
+        self.write(&(data.len() as u16).to_be_bytes()).await?;
+        self.write(data).await
+    }
+
+    async fn write(&mut self, data: &[u8]) -> Result<(), MqttError> {
+        if let Err(e) = self.socket.write_all(data).await {
+            self.connected = false;
+            return Err(MqttError::Network(e));
+        }
+        self.last_tx = Instant::now();
+        Ok(())
+    }
+}
+
+/// Parse a fixed header, returning its length and the remaining length, or
+/// `None` if it isn't complete yet.
+fn parse_header(buf: &[u8]) -> Result<Option<(usize, usize)>, MqttError> {
+    let mut remaining_len = 0;
+    for i in 1..5 {
+        let byte = match buf.get(i) {
+            Some(byte) => *byte,
+            None => return Ok(None),
+        };
+        remaining_len |= ((byte & 0x7f) as usize) << (7 * (i - 1));
+        if byte & 0x80 == 0 {
+            return Ok(Some((i + 1, remaining_len)));
+        }
+    }
+    Err(MqttError::Protocol)
+}
+
+/// Parse a PUBLISH packet, returning the message and its packet id. The id is 0 for QoS 0.
+fn parse_publish(packet: &[u8]) -> Result<(MqttMessage<'_>, u16), MqttError> {
+    let flags = *packet.first().ok_or(MqttError::Protocol)?;
+    let qos = match (flags >> 1) & 0x03 {
+        0 => QoS::AtMostOnce,
+        // QoS 2 isn't supported, the broker downgrades to the QoS of the subscription.
+        1 => QoS::AtLeastOnce,
+        _ => return Err(MqttError::Protocol),
+    };
+    let (header_len, _) = parse_header(packet)?.ok_or(MqttError::Protocol)?;
+    let body = &packet[header_len..];
+    if body.len() < 2 {
+        return Err(MqttError::Protocol);
+    }
+
+    let topic_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+    let topic = body.get(2..2 + topic_len).ok_or(MqttError::Protocol)?;
+    let topic = core::str::from_utf8(topic).map_err(|_| MqttError::Protocol)?;
+    let (id, payload) = match qos {
+        QoS::AtMostOnce => (0, &body[2 + topic_len..]),
+        QoS::AtLeastOnce => {
+            let id = body
+                .get(2 + topic_len..4 + topic_len)
+                .ok_or(MqttError::Protocol)?;
+            (u16::from_be_bytes([id[0], id[1]]), &body[4 + topic_len..])
+        }
+    };
+
+    let message = MqttMessage {
+        topic,
+        payload,
+        qos,
+        retain: flags & 0x01 != 0,
+    };
+    Ok((message, id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header() {
+        assert_eq!(parse_header(&[]), Ok(None));
+        assert_eq!(parse_header(&[0x30]), Ok(None));
+        assert_eq!(parse_header(&[0x30, 0x80]), Ok(None));
+        assert_eq!(parse_header(&[0x30, 0x05]), Ok(Some((2, 5))));
+        assert_eq!(parse_header(&[0x30, 0xc1, 0x02]), Ok(Some((3, 321))));
+        assert_eq!(
+            parse_header(&[0x30, 0xff, 0xff, 0xff, 0x7f]),
+            Ok(Some((5, 268_435_455)))
+        );
+        // At most 4 bytes of remaining length.
+        assert_eq!(
+            parse_header(&[0x30, 0xff, 0xff, 0xff, 0xff, 0x01]),
+            Err(MqttError::Protocol)
+        );
+    }
+
+    #[test]
+    fn publish() {
+        let packet = [0x31, 0x08, 0, 3, b'a', b'/', b'b', b'h', b'e', b'y'];
+        let (msg, id) = unwrap!(parse_publish(&packet));
+        assert_eq!(msg.topic, "a/b");
+        assert_eq!(msg.payload, b"hey");
+        assert_eq!(msg.qos, QoS::AtMostOnce);
+        assert!(msg.retain);
+        assert_eq!(id, 0);
+
+        let packet = [0x32, 0x07, 0, 1, b't', 0x12, 0x34, b'h', b'i'];
+        let (msg, id) = unwrap!(parse_publish(&packet));
+        assert_eq!(msg.topic, "t");
+        assert_eq!(msg.payload, b"hi");
+        assert_eq!(msg.qos, QoS::AtLeastOnce);
+        assert!(!msg.retain);
+        assert_eq!(id, 0x1234);
+    }
+
+    #[test]
+    fn publish_truncated() {
+        let packet = [0x32, 0x07, 0, 1, b't', 0x12, 0x34, b'h', b'i'];
+        // Without its packet id, or cut in the topic or its length.
+        for len in 0..7 {
+            assert_eq!(
+                parse_publish(&packet[..len]).err(),
+                Some(MqttError::Protocol)
+            );
+        }
+        // The payload may be empty.
+        let (msg, _) = unwrap!(parse_publish(&packet[..7]));
+        assert!(msg.payload.is_empty());
+    }
+
+    #[test]
+    fn publish_malformed() {
+        let bad: &[&[u8]] = &[
+            // QoS 2 and 3.
+            &[0x34, 0x05, 0, 1, b't', 0, 1],
+            &[0x36, 0x05, 0, 1, b't', 0, 1],
+            // Topic longer than the packet.
+            &[0x30, 0x04, 0, 9, b'a', b'b'],
+            // Topic isn't UTF-8.
+            &[0x30, 0x03, 0, 1, 0xff],
+            // Remaining length over 4 bytes.
+            &[0x30, 0xff, 0xff, 0xff, 0xff, 0x01],
+        ];
+        for packet in bad {
+            assert_eq!(parse_publish(packet).err(), Some(MqttError::Protocol));
+        }
+    }
+}
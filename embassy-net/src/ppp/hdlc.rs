@@ -0,0 +1,296 @@
+//! HDLC-like framing of PPP over asynchronous serial links, RFC 1662.
+
+use core::pin::Pin;
+use core::task::Poll;
+
+use embassy::io::{self, AsyncBufRead, AsyncWrite, AsyncWriteExt};
+use futures::future::poll_fn;
+
+const FLAG: u8 = 0x7E;
+const ESCAPE: u8 = 0x7D;
+const ADDRESS: u8 = 0xFF;
+const CONTROL: u8 = 0x03;
+
+const FCS_INIT: u16 = 0xFFFF;
+/// Value of the frame check sequence computed over a frame and its own FCS.
+const FCS_GOOD: u16 = 0xF0B8;
+
+/// Longest frame received: address, control, protocol, information and FCS.
+const MAX_FRAME_LEN: usize = 4 + super::MTU + 2;
+
+/// Unescapes the received bytes, and checks the frames.
+pub struct Decoder {
+    buf: [u8; MAX_FRAME_LEN],
+    len: usize,
+    fcs: u16,
+    escaped: bool,
+    overflow: bool,
+    /// A valid frame is in the buffer, the next byte starts a new one.
+    done: bool,
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; MAX_FRAME_LEN],
+            len: 0,
+            fcs: FCS_INIT,
+            escaped: false,
+            overflow: false,
+            done: false,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.len = 0;
+        self.fcs = FCS_INIT;
+        self.escaped = false;
+        self.overflow = false;
+        self.done = false;
+    }
+
+    /// Process received bytes until the end of a valid frame. Returns the number of bytes
+    /// used, and whether a frame is complete.
+    fn feed(&mut self, data: &[u8]) -> (usize, bool) {
+        if self.done {
+            self.reset();
+        }
+        for (i, &b) in data.iter().enumerate() {
+            match b {
+                FLAG => {
+                    // Frames are separated by one or more flags, so most empty or short
+                    // "frames" are just that.
+                    if self.len >= 4 && !self.overflow && !self.escaped && self.fcs == FCS_GOOD {
+                        self.done = true;
+                        return (i + 1, true);
+                    }
+                    self.reset();
+                }
+                ESCAPE => self.escaped = true,
+                // The peer escapes all control characters, the ones received as is were
+                // inserted along the way (XON/XOFF flow control, for example).
+                b if b < 0x20 => {}
+                b => {
+                    let b = if self.escaped { b ^ 0x20 } else { b };
+                    self.escaped = false;
+                    if self.len == self.buf.len() {
+                        self.overflow = true;
+                    } else {
+                        self.buf[self.len] = b;
+                        self.len += 1;
+                        self.fcs = fcs_update(self.fcs, b);
+                    }
+                }
+            }
+        }
+        (data.len(), false)
+    }
+
+    /// The protocol and information of the frame last received by [`read_frame`].
+    pub fn frame(&self) -> Option<(u16, &[u8])> {
+        let mut data = &self.buf[..self.len.checked_sub(2)?];
+        // The address and control fields are omitted with address-and-control-field
+        // compression. Accept it even if not negotiated, as RFC 1661 recommends.
+        if data.starts_with(&[ADDRESS, CONTROL]) {
+            data = &data[2..];
+        }
+        // Protocols are odd: a protocol field compressed to one byte has its lowest bit set.
+        match data {
+            [p, rest @ ..] if p & 1 != 0 => Some((*p as u16, rest)),
+            [p0, p1, rest @ ..] => Some((u16::from_be_bytes([*p0, *p1]), rest)),
+            _ => None,
+        }
+    }
+}
+
+/// Read from `serial` until a valid frame is received, and keep it in `decoder`.
+///
+/// This can be cancelled without losing data: the bytes are taken from `serial` as they're
+/// processed.
+pub async fn read_frame<S>(serial: &mut S, decoder: &mut Decoder) -> io::Result<()>
+where
+    S: AsyncBufRead + Unpin,
+{
+    poll_fn(|cx| loop {
+        let mut serial = Pin::new(&mut *serial);
+        let buf = match serial.as_mut().poll_fill_buf(cx) {
+            Poll::Ready(Ok(buf)) => buf,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        };
+        if buf.is_empty() {
+            return Poll::Ready(Err(io::Error::UnexpectedEof));
+        }
+        let (n, done) = decoder.feed(buf);
+        serial.consume(n);
+        if done {
+            return Poll::Ready(Ok(()));
+        }
+    })
+    .await
+}
+
+/// Send a frame with the given protocol and information.
+///
+/// All control characters are escaped, whatever the character map asked by the peer: this
+/// is always allowed, and LCP needs it anyway.
+pub async fn write_frame<S>(serial: &mut S, protocol: u16, info: &[u8]) -> io::Result<()>
+where
+    S: AsyncWrite + Unpin,
+{
+    let header = [ADDRESS, CONTROL, (protocol >> 8) as u8, protocol as u8];
+    let fcs = !header
+        .iter()
+        .chain(info)
+        .fold(FCS_INIT, |fcs, &b| fcs_update(fcs, b));
+    let fcs = fcs.to_le_bytes();
+
+    // Escaping at most doubles the size, send the frame in chunks.
+    let mut chunk = [0; 64];
+    let mut n = 0;
+    chunk[n] = FLAG;
+    n += 1;
+    for &b in header.iter().chain(info).chain(fcs.iter()) {
+        if n + 2 > chunk.len() {
+            serial.write_all(&chunk[..n]).await?;
+            n = 0;
+        }
+        if b < 0x20 || b == FLAG || b == ESCAPE {
+            chunk[n] = ESCAPE;
+            chunk[n + 1] = b ^ 0x20;
+            n += 2;
+        } else {
+            chunk[n] = b;
+            n += 1;
+        }
+    }
+    if n == chunk.len() {
+        serial.write_all(&chunk[..n]).await?;
+        n = 0;
+    }
+    chunk[n] = FLAG;
+    serial.write_all(&chunk[..n + 1]).await?;
+    serial.flush().await
+}
+
+/// Update the FCS-16 with one byte, least significant bit first.
+fn fcs_update(fcs: u16, byte: u8) -> u16 {
+    let mut fcs = fcs ^ byte as u16;
+    for _ in 0..8 {
+        fcs = if fcs & 1 != 0 {
+            (fcs >> 1) ^ 0x8408
+        } else {
+            fcs >> 1
+        };
+    }
+    fcs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate std;
+    use std::vec::Vec;
+
+    /// Encode a frame like `write_frame` does.
+    fn encode(protocol: u16, info: &[u8]) -> Vec<u8> {
+        let mut raw = Vec::from([ADDRESS, CONTROL, (protocol >> 8) as u8, protocol as u8]);
+        raw.extend_from_slice(info);
+        encode_raw(&raw)
+    }
+
+    /// Add the FCS to `raw`, escape it and put it between flags.
+    fn encode_raw(raw: &[u8]) -> Vec<u8> {
+        let fcs = !raw.iter().fold(FCS_INIT, |fcs, &b| fcs_update(fcs, b));
+        let mut frame = Vec::from([FLAG]);
+        for &b in raw.iter().chain(&fcs.to_le_bytes()) {
+            if b < 0x20 || b == FLAG || b == ESCAPE {
+                frame.extend_from_slice(&[ESCAPE, b ^ 0x20]);
+            } else {
+                frame.push(b);
+            }
+        }
+        frame.push(FLAG);
+        frame
+    }
+
+    #[test]
+    fn decode() {
+        let info = [0x45, FLAG, ESCAPE, 0x00, 0x11];
+        let frame = encode(0x0021, &info);
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.feed(&frame), (frame.len(), true));
+        assert_eq!(decoder.frame(), Some((0x0021, &info[..])));
+
+        // The next frame can share the closing flag, and come in pieces.
+        let frame = encode(0xC021, &[1, 2, 0, 4]);
+        let (first, second) = frame[1..].split_at(3);
+        assert_eq!(decoder.feed(first), (first.len(), false));
+        assert_eq!(decoder.feed(second), (second.len(), true));
+        assert_eq!(decoder.frame(), Some((0xC021, &[1, 2, 0, 4][..])));
+    }
+
+    #[test]
+    fn bad_fcs() {
+        let mut frame = encode(0x0021, &[0x45, 0x46]);
+        let i = unwrap!(frame.iter().position(|&b| b == 0x45));
+        frame[i] = 0x44;
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.feed(&frame), (frame.len(), false));
+
+        // A good frame after it still gets through.
+        let frame = encode(0x0021, &[0x45, 0x46]);
+        assert_eq!(decoder.feed(&frame), (frame.len(), true));
+    }
+
+    #[test]
+    fn truncated() {
+        let frame = encode(0x0021, &[0x45, 0x00]);
+        let mut decoder = Decoder::new();
+        // Cut by the next flag.
+        for n in 1..frame.len() - 1 {
+            decoder.feed(&frame[..n]);
+            assert_eq!(decoder.feed(&[FLAG]), (1, false));
+        }
+        // Escape right before the flag.
+        let mut escaped = frame.clone();
+        let n = escaped.len();
+        escaped.insert(n - 1, ESCAPE);
+        assert_eq!(decoder.feed(&escaped), (escaped.len(), false));
+        // Too short to hold a protocol and an FCS.
+        for short in [
+            &[FLAG, FLAG][..],
+            &[FLAG, 0x21, FLAG],
+            &[FLAG, 0x21, 0x45, 0x46, FLAG],
+        ] {
+            assert_eq!(decoder.feed(short), (short.len(), false));
+        }
+    }
+
+    #[test]
+    fn overflow() {
+        let info = [0x45; MAX_FRAME_LEN];
+        let frame = encode(0x0021, &info);
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.feed(&frame), (frame.len(), false));
+    }
+
+    #[test]
+    fn no_protocol() {
+        let mut decoder = Decoder::new();
+        assert_eq!(decoder.frame(), None);
+        // A valid FCS over the address and control fields only.
+        let frame = encode_raw(&[ADDRESS, CONTROL]);
+        assert_eq!(decoder.feed(&frame), (frame.len(), true));
+        assert_eq!(decoder.frame(), None);
+        // A protocol field compressed to one byte is odd.
+        let frame = encode_raw(&[ADDRESS, CONTROL, 0x20]);
+        assert_eq!(decoder.feed(&frame), (frame.len(), true));
+        assert_eq!(decoder.frame(), None);
+        // With the address and control fields compressed too.
+        let frame = encode_raw(&[0x21, 0x45]);
+        assert_eq!(decoder.feed(&frame), (frame.len(), true));
+        assert_eq!(decoder.frame(), Some((0x0021, &[0x45][..])));
+    }
+}
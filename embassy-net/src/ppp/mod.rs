@@ -0,0 +1,285 @@
+//! PPP over a serial port.
+//!
+//! This is the link used by cellular modems once they're in data mode, and by plain serial
+//! links to a host running `pppd`. The [`Runner`] owns the serial port: it negotiates the link
+//! (LCP), authenticates with PAP if the peer asks for it, gets an IPv4 address and DNS servers
+//! (IPCP), and then moves the IP packets between the serial port and the queues in a
+//! [`State`]. The stack gets these packets through a [`PppDevice`], and its IP configuration
+//! through a [`PppConfigurator`].
+//!
+//! ```ignore
+//! // Dial with AT commands first, until the modem answers `CONNECT`.
+//! static STATE: Forever<ppp::State<4, 4>> = Forever::new();
+//! let state = STATE.put(ppp::State::new());
+//! let config = ppp::Config {
+//!     username: "user",
+//!     password: "pass",
+//! };
+//! let (runner, device, configurator) = ppp::new(serial, state, config);
+//! spawner.spawn(ppp_task(runner)).unwrap();
+//! // `device` and `configurator` are given to the `Stack`.
+//! ```
+
+mod hdlc;
+mod runner;
+
+pub use runner::Runner;
+
+use core::cell::RefCell;
+use core::task::{Context, Waker};
+
+use embassy::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy::blocking_mutex::Mutex;
+use embassy::channel::channel::Channel;
+use embassy::io::{AsyncBufRead, AsyncWrite};
+use embassy::waitqueue::WakerRegistration;
+use smoltcp::phy::{DeviceCapabilities, Medium};
+use smoltcp::time::Instant;
+
+use crate::config::{Config as IpConfig, Configurator, Event};
+use crate::device::{Device, LinkState};
+use crate::Interface;
+
+/// Largest IP packet sent or received. This is the default MRU of PPP.
+pub const MTU: usize = 1500;
+
+/// PPP configuration.
+#[derive(Debug, Clone, Default)]
+pub struct Config<'a> {
+    /// User name sent if the peer asks for PAP authentication.
+    pub username: &'a str,
+    /// Password sent if the peer asks for PAP authentication.
+    pub password: &'a str,
+}
+
+/// PPP error
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// Error of the serial port.
+    Io(embassy::io::Error),
+    /// The peer stopped answering during the negotiation.
+    Timeout,
+    /// The peer refused the username or password.
+    AuthenticationFailed,
+    /// The peer didn't assign an IPv4 address.
+    NoAddress,
+}
+
+impl From<embassy::io::Error> for Error {
+    fn from(e: embassy::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// An IP packet in one of the queues.
+pub struct Packet {
+    len: usize,
+    data: [u8; MTU],
+}
+
+impl Packet {
+    const fn new() -> Self {
+        Self {
+            len: 0,
+            data: [0; MTU],
+        }
+    }
+}
+
+struct Shared {
+    /// IP configuration negotiated with IPCP, while the link is up.
+    config: Option<IpConfig>,
+    waker: WakerRegistration,
+}
+
+/// Queues between the stack and the runner: `RX` received packets, `TX` packets to transmit.
+pub struct State<const RX: usize, const TX: usize> {
+    rx: Channel<CriticalSectionRawMutex, Packet, RX>,
+    tx: Channel<CriticalSectionRawMutex, Packet, TX>,
+    shared: Mutex<CriticalSectionRawMutex, RefCell<Shared>>,
+}
+
+impl<const RX: usize, const TX: usize> State<RX, TX> {
+    pub fn new() -> Self {
+        Self {
+            rx: Channel::new(),
+            tx: Channel::new(),
+            shared: Mutex::new(RefCell::new(Shared {
+                config: None,
+                waker: WakerRegistration::new(),
+            })),
+        }
+    }
+
+    fn config(&self) -> Option<IpConfig> {
+        self.shared.lock(|s| s.borrow().config.clone())
+    }
+
+    fn is_up(&self) -> bool {
+        self.shared.lock(|s| s.borrow().config.is_some())
+    }
+
+    /// Bring the link up or down, and wake the stack to apply the new configuration.
+    fn set_config(&self, config: Option<IpConfig>) {
+        if config.is_none() {
+            // Packets queued for transmission are stale once the link is down.
+            while self.tx.try_recv().is_ok() {}
+        }
+        self.shared.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            s.config = config;
+            s.waker.wake();
+        })
+    }
+}
+
+/// Create the runner driving the serial port, and the device and configurator to give to the
+/// embassy-net stack.
+///
+/// The serial port must already be in data mode: for a modem, the dial-up AT commands are
+/// sent before calling this.
+pub fn new<'a, S, const RX: usize, const TX: usize>(
+    serial: S,
+    state: &'a State<RX, TX>,
+    config: Config<'a>,
+) -> (
+    Runner<'a, S, RX, TX>,
+    PppDevice<'a, RX, TX>,
+    PppConfigurator<'a, RX, TX>,
+)
+where
+    S: AsyncBufRead + AsyncWrite + Unpin,
+{
+    (
+        Runner::new(serial, state, config),
+        PppDevice { state },
+        PppConfigurator {
+            state,
+            applied: None,
+        },
+    )
+}
+
+/// The PPP link, as an embassy-net device.
+///
+/// The link is up once the IP configuration has been negotiated.
+pub struct PppDevice<'a, const RX: usize, const TX: usize> {
+    state: &'a State<RX, TX>,
+}
+
+impl<'a, const RX: usize, const TX: usize> Device for PppDevice<'a, RX, TX> {
+    type RxToken<'b>
+        = RxToken
+    where
+        Self: 'b;
+    type TxToken<'b>
+        = TxToken<'a, TX>
+    where
+        Self: 'b;
+
+    fn receive(&mut self) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        if self.state.tx.is_full() {
+            return None;
+        }
+        let packet = self.state.rx.try_recv().ok()?;
+        Some((RxToken { packet }, TxToken { tx: &self.state.tx }))
+    }
+
+    fn transmit(&mut self) -> Option<Self::TxToken<'_>> {
+        if !self.state.is_up() || self.state.tx.is_full() {
+            return None;
+        }
+        Some(TxToken { tx: &self.state.tx })
+    }
+
+    fn register_waker(&mut self, waker: &Waker) {
+        let mut cx = Context::from_waker(waker);
+        let _ = self.state.rx.poll_ready_to_recv(&mut cx);
+        let _ = self.state.tx.poll_ready_to_send(&mut cx);
+        self.state
+            .shared
+            .lock(|s| s.borrow_mut().waker.register(waker));
+    }
+
+    fn capabilities(&mut self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.medium = Medium::Ip;
+        caps.max_transmission_unit = MTU;
+        caps.max_burst_size = Some(TX);
+        caps
+    }
+
+    fn link_state(&mut self) -> LinkState {
+        if self.state.is_up() {
+            LinkState::Up
+        } else {
+            LinkState::Down
+        }
+    }
+
+    fn ethernet_address(&mut self) -> [u8; 6] {
+        // PPP has no link-layer addresses.
+        [0; 6]
+    }
+}
+
+pub struct RxToken {
+    packet: Packet,
+}
+
+impl crate::device::RxToken for RxToken {
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(&mut self.packet.data[..self.packet.len])
+    }
+}
+
+pub struct TxToken<'a, const TX: usize> {
+    tx: &'a Channel<CriticalSectionRawMutex, Packet, TX>,
+}
+
+impl<'a, const TX: usize> crate::device::TxToken for TxToken<'a, TX> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut packet = Packet::new();
+        packet.len = len;
+        let r = f(&mut packet.data[..len]);
+        // The token is only handed out when there's room in the queue.
+        if self.tx.try_send(packet).is_err() {
+            warn!("PPP tx queue full, dropping packet");
+        }
+        r
+    }
+}
+
+/// Configurator applying the IP configuration negotiated by the [`Runner`].
+pub struct PppConfigurator<'a, const RX: usize, const TX: usize> {
+    state: &'a State<RX, TX>,
+    applied: Option<IpConfig>,
+}
+
+impl<'a, D: Device + 'static, const RX: usize, const TX: usize> Configurator<D>
+    for PppConfigurator<'a, RX, TX>
+{
+    fn poll(&mut self, _iface: &mut Interface<D>, _timestamp: Instant) -> Event {
+        let config = self.state.config();
+        if config == self.applied {
+            return Event::NoChange;
+        }
+        self.applied = config.clone();
+        match config {
+            Some(config) => Event::Configured(config),
+            None => Event::Deconfigured,
+        }
+    }
+
+    fn detach(&mut self, _iface: &mut Interface<D>) {
+        self.applied = None;
+    }
+}
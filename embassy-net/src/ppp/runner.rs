@@ -0,0 +1,689 @@
+use embassy::io::{AsyncBufRead, AsyncWrite};
+use embassy::time::{Duration, Instant, Timer};
+use embassy::util::{select3, Either3};
+use futures::future::pending;
+use heapless::Vec;
+use smoltcp::wire::{Ipv4Address, Ipv4Cidr};
+
+use super::hdlc::{self, Decoder};
+use super::{Config, Error, Packet, State, MTU};
+use crate::config::Config as IpConfig;
+
+const PROTO_IP: u16 = 0x0021;
+const PROTO_IPCP: u16 = 0x8021;
+const PROTO_LCP: u16 = 0xC021;
+const PROTO_PAP: u16 = 0xC023;
+
+// Codes of LCP and IPCP packets.
+const CONFIGURE_REQUEST: u8 = 1;
+const CONFIGURE_ACK: u8 = 2;
+const CONFIGURE_NAK: u8 = 3;
+const CONFIGURE_REJECT: u8 = 4;
+const TERMINATE_REQUEST: u8 = 5;
+const TERMINATE_ACK: u8 = 6;
+const CODE_REJECT: u8 = 7;
+// LCP only.
+const PROTOCOL_REJECT: u8 = 8;
+const ECHO_REQUEST: u8 = 9;
+const ECHO_REPLY: u8 = 10;
+const DISCARD_REQUEST: u8 = 11;
+
+// Codes of PAP packets.
+const AUTHENTICATE_REQUEST: u8 = 1;
+const AUTHENTICATE_ACK: u8 = 2;
+const AUTHENTICATE_NAK: u8 = 3;
+
+// LCP options.
+const LCP_MRU: u8 = 1;
+const LCP_ACCM: u8 = 2;
+const LCP_AUTH: u8 = 3;
+const LCP_MAGIC: u8 = 5;
+
+/// Value of the MRU option matching our MTU, and of the authentication option for PAP.
+const MRU: [u8; 2] = (MTU as u16).to_be_bytes();
+const PAP: [u8; 2] = PROTO_PAP.to_be_bytes();
+
+// IPCP options, DNS servers from RFC 1877.
+const IPCP_ADDRESS: u8 = 3;
+const IPCP_DNS: [u8; 2] = [129, 131];
+
+/// Restart timer, and number of requests sent without an answer before giving up. These are
+/// the defaults of RFC 1661.
+const RESTART: Duration = Duration::from_secs(3);
+const MAX_CONFIGURE: u8 = 10;
+
+/// Largest control packet sent. Longer replies are truncated where allowed, or dropped.
+const CONTROL_LEN: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+enum Phase {
+    /// LCP negotiation.
+    Establish,
+    /// PAP authentication, when asked by the peer.
+    Authenticate,
+    /// IPCP negotiation.
+    Network,
+    Opened,
+}
+
+/// What to answer to an option of a configure request.
+enum Verdict {
+    Ack,
+    Nak(&'static [u8]),
+    Reject,
+}
+
+/// A control packet being built.
+struct ControlPacket {
+    buf: [u8; CONTROL_LEN],
+    len: usize,
+}
+
+impl ControlPacket {
+    fn new(code: u8, id: u8) -> Self {
+        let mut buf = [0; CONTROL_LEN];
+        buf[0] = code;
+        buf[1] = id;
+        Self { buf, len: 4 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 4
+    }
+
+    /// Append `data`, truncated to the room left. Returns `false` if truncated.
+    fn push(&mut self, data: &[u8]) -> bool {
+        let n = data.len().min(CONTROL_LEN - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&data[..n]);
+        self.len += n;
+        n == data.len()
+    }
+
+    fn push_option(&mut self, kind: u8, value: &[u8]) -> bool {
+        self.push(&[kind, value.len() as u8 + 2]) && self.push(value)
+    }
+
+    fn finish(&mut self) -> &[u8] {
+        self.buf[2..4].copy_from_slice(&(self.len as u16).to_be_bytes());
+        &self.buf[..self.len]
+    }
+}
+
+/// Call `f` with the type, the value and the raw bytes of each option. Returns `false` if
+/// the options are malformed.
+fn for_each_option<'p>(mut data: &'p [u8], mut f: impl FnMut(u8, &'p [u8], &'p [u8])) -> bool {
+    while !data.is_empty() {
+        if data.len() < 2 {
+            return false;
+        }
+        let len = data[1] as usize;
+        if len < 2 || len > data.len() {
+            return false;
+        }
+        f(data[0], &data[2..len], &data[..len]);
+        data = &data[len..];
+    }
+    true
+}
+
+/// Split a control packet into its code, identifier and data. Bytes past its length are
+/// padding, and dropped.
+fn parse_control(packet: &[u8]) -> Option<(u8, u8, &[u8])> {
+    match packet {
+        [code, id, l0, l1, ..] => {
+            let len = u16::from_be_bytes([*l0, *l1]) as usize;
+            if len < 4 || len > packet.len() {
+                return None;
+            }
+            Some((*code, *id, &packet[4..len]))
+        }
+        _ => None,
+    }
+}
+
+fn ipv4(value: &[u8]) -> Option<Ipv4Address> {
+    match value {
+        [a, b, c, d] => Some(Ipv4Address::new(*a, *b, *c, *d)),
+        _ => None,
+    }
+}
+
+/// State of one PPP session.
+struct Session {
+    phase: Phase,
+    /// Identifier of our last request.
+    id: u8,
+    /// The peer acked our configure request.
+    acked: bool,
+    /// We acked the configure request of the peer.
+    peer_acked: bool,
+    /// Requests sent without an answer.
+    retries: u8,
+    /// When to send our request again, if it's still unanswered.
+    deadline: Option<Instant>,
+    /// The peer asked for PAP authentication.
+    pap: bool,
+    address: Ipv4Address,
+    /// DNS servers requested, `None` once rejected by the peer.
+    dns: [Option<Ipv4Address>; 2],
+    peer_address: Option<Ipv4Address>,
+}
+
+impl Session {
+    fn new() -> Self {
+        Self {
+            phase: Phase::Establish,
+            id: 0,
+            acked: false,
+            peer_acked: false,
+            retries: 0,
+            deadline: None,
+            pap: false,
+            address: Ipv4Address::UNSPECIFIED,
+            dns: [Some(Ipv4Address::UNSPECIFIED); 2],
+            peer_address: None,
+        }
+    }
+
+    /// Go to `phase`, and start its negotiation.
+    fn enter(&mut self, phase: Phase) {
+        debug!("PPP phase {:?}", phase);
+        self.phase = phase;
+        self.acked = false;
+        self.peer_acked = false;
+        self.retries = 0;
+        self.deadline = None;
+    }
+
+    /// Protocol negotiated in the current phase.
+    fn protocol(&self) -> Option<u16> {
+        match self.phase {
+            Phase::Establish => Some(PROTO_LCP),
+            Phase::Authenticate => Some(PROTO_PAP),
+            Phase::Network => Some(PROTO_IPCP),
+            Phase::Opened => None,
+        }
+    }
+
+    fn ip_config(&self) -> IpConfig {
+        IpConfig {
+            address: Ipv4Cidr::new(self.address, 32),
+            gateway: self.peer_address,
+            dns_servers: self
+                .dns
+                .iter()
+                .flatten()
+                .filter(|a| !a.is_unspecified())
+                .copied()
+                .collect::<Vec<_, 3>>(),
+            #[cfg(feature = "proto-ipv6")]
+            ipv6_address: None,
+            #[cfg(feature = "proto-ipv6")]
+            ipv6_gateway: None,
+        }
+    }
+}
+
+/// Task driving the serial port.
+pub struct Runner<'a, S, const RX: usize, const TX: usize> {
+    serial: S,
+    state: &'a State<RX, TX>,
+    config: Config<'a>,
+    decoder: Decoder,
+}
+
+impl<'a, S, const RX: usize, const TX: usize> Runner<'a, S, RX, TX>
+where
+    S: AsyncBufRead + AsyncWrite + Unpin,
+{
+    pub(super) fn new(serial: S, state: &'a State<RX, TX>, config: Config<'a>) -> Self {
+        Self {
+            serial,
+            state,
+            config,
+            decoder: Decoder::new(),
+        }
+    }
+
+    /// Run a PPP session: negotiate the link, then exchange IP packets until it's closed.
+    ///
+    /// Returns `Ok` when the peer closes the link. The link is down once this returns, `run`
+    /// can be called again to start a new session, after dialing again for a modem.
+    pub async fn run(&mut self) -> Result<(), Error> {
+        let res = self.run_session().await;
+        self.state.set_config(None);
+        res
+    }
+
+    /// Release the serial port, to hang up a modem for example.
+    pub fn into_inner(self) -> S {
+        self.serial
+    }
+
+    async fn run_session(&mut self) -> Result<(), Error> {
+        let Self {
+            serial,
+            state,
+            config,
+            decoder,
+        } = self;
+        let state = *state;
+
+        let mut s = Session::new();
+        s.enter(Phase::Establish);
+        send_request(serial, config, &mut s).await?;
+
+        loop {
+            let event = {
+                let read = hdlc::read_frame(serial, decoder);
+                let opened = s.phase == Phase::Opened;
+                let transmit = async {
+                    if opened {
+                        state.tx.recv().await
+                    } else {
+                        pending().await
+                    }
+                };
+                let deadline = s.deadline;
+                let timeout = async {
+                    match deadline {
+                        Some(deadline) => Timer::at(deadline).await,
+                        None => pending().await,
+                    }
+                };
+                select3(read, transmit, timeout).await
+            };
+
+            match event {
+                Either3::First(res) => {
+                    res?;
+                    let (protocol, info) = match decoder.frame() {
+                        Some(frame) => frame,
+                        None => continue,
+                    };
+                    if handle_frame(serial, state, config, &mut s, protocol, info).await? {
+                        return Ok(());
+                    }
+                }
+                Either3::Second(packet) => {
+                    hdlc::write_frame(serial, PROTO_IP, &packet.data[..packet.len]).await?;
+                }
+                Either3::Third(()) => {
+                    s.retries += 1;
+                    if s.retries >= MAX_CONFIGURE {
+                        return Err(Error::Timeout);
+                    }
+                    send_request(serial, config, &mut s).await?;
+                }
+            }
+        }
+    }
+}
+
+/// Send our request for the current phase.
+async fn send_request<S>(serial: &mut S, config: &Config<'_>, s: &mut Session) -> Result<(), Error>
+where
+    S: AsyncWrite + Unpin,
+{
+    let protocol = match s.protocol() {
+        Some(protocol) => protocol,
+        None => return Ok(()),
+    };
+    s.id = s.id.wrapping_add(1);
+    let mut p = match s.phase {
+        Phase::Authenticate => {
+            let mut p = ControlPacket::new(AUTHENTICATE_REQUEST, s.id);
+            p.push(&[config.username.len() as u8]);
+            p.push(config.username.as_bytes());
+            p.push(&[config.password.len() as u8]);
+            p.push(config.password.as_bytes());
+            p
+        }
+        Phase::Network => {
+            let mut p = ControlPacket::new(CONFIGURE_REQUEST, s.id);
+            p.push_option(IPCP_ADDRESS, s.address.as_bytes());
+            for (kind, dns) in IPCP_DNS.iter().zip(s.dns.iter()) {
+                if let Some(dns) = dns {
+                    p.push_option(*kind, dns.as_bytes());
+                }
+            }
+            p
+        }
+        // Nothing to ask for LCP, the defaults are fine.
+        _ => ControlPacket::new(CONFIGURE_REQUEST, s.id),
+    };
+    hdlc::write_frame(serial, protocol, p.finish()).await?;
+    s.deadline = Some(Instant::now() + RESTART);
+    Ok(())
+}
+
+/// Handle a received frame. Returns `true` if the peer closed the link.
+async fn handle_frame<S, const RX: usize, const TX: usize>(
+    serial: &mut S,
+    state: &State<RX, TX>,
+    config: &Config<'_>,
+    s: &mut Session,
+    protocol: u16,
+    info: &[u8],
+) -> Result<bool, Error>
+where
+    S: AsyncWrite + Unpin,
+{
+    match protocol {
+        PROTO_IP if s.phase == Phase::Opened => {
+            if info.len() > MTU {
+                return Ok(false);
+            }
+            let mut packet = Packet::new();
+            packet.len = info.len();
+            packet.data[..info.len()].copy_from_slice(info);
+            if state.rx.try_send(packet).is_err() {
+                warn!("PPP rx queue full, dropping packet");
+            }
+            Ok(false)
+        }
+        PROTO_LCP => handle_control(serial, state, config, s, protocol, info).await,
+        // The network protocols are only negotiated once the link is established and
+        // authenticated. Until then, their packets are discarded.
+        PROTO_IPCP if matches!(s.phase, Phase::Network | Phase::Opened) => {
+            handle_control(serial, state, config, s, protocol, info).await
+        }
+        PROTO_PAP if s.phase == Phase::Authenticate => {
+            match info {
+                [AUTHENTICATE_ACK, id, ..] if *id == s.id => {
+                    s.enter(Phase::Network);
+                    send_request(serial, config, s).await?;
+                }
+                [AUTHENTICATE_NAK, id, ..] if *id == s.id => {
+                    return Err(Error::AuthenticationFailed);
+                }
+                _ => {}
+            }
+            Ok(false)
+        }
+        PROTO_IP | PROTO_IPCP | PROTO_PAP => Ok(false),
+        _ if s.phase != Phase::Establish => {
+            // Other protocols, IPv6CP or compression for example, are rejected.
+            debug!("PPP rejecting protocol {:04x}", protocol);
+            s.id = s.id.wrapping_add(1);
+            let mut p = ControlPacket::new(PROTOCOL_REJECT, s.id);
+            p.push(&protocol.to_be_bytes());
+            p.push(info);
+            hdlc::write_frame(serial, PROTO_LCP, p.finish()).await?;
+            Ok(false)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Handle an LCP or IPCP packet. Returns `true` if the peer closed the link.
+async fn handle_control<S, const RX: usize, const TX: usize>(
+    serial: &mut S,
+    state: &State<RX, TX>,
+    config: &Config<'_>,
+    s: &mut Session,
+    protocol: u16,
+    packet: &[u8],
+) -> Result<bool, Error>
+where
+    S: AsyncWrite + Unpin,
+{
+    let (code, id, data) = match parse_control(packet) {
+        Some(header) => header,
+        None => return Ok(false),
+    };
+    let lcp = protocol == PROTO_LCP;
+    // Replies to our request of the current phase.
+    let reply = s.protocol() == Some(protocol) && id == s.id && !s.acked;
+
+    match code {
+        CONFIGURE_REQUEST => {
+            // A request once the protocol is open starts its negotiation over. For LCP, this
+            // brings everything above down.
+            let negotiating = if lcp {
+                s.phase == Phase::Establish
+            } else {
+                s.phase == Phase::Network
+            };
+            if !negotiating {
+                state.set_config(None);
+                s.enter(if lcp {
+                    Phase::Establish
+                } else {
+                    Phase::Network
+                });
+                send_request(serial, config, s).await?;
+            }
+
+            let mut ack = ControlPacket::new(CONFIGURE_ACK, id);
+            let mut nak = ControlPacket::new(CONFIGURE_NAK, id);
+            let mut reject = ControlPacket::new(CONFIGURE_REJECT, id);
+            let mut pap = false;
+            let mut peer_address = None;
+            let mut fits = true;
+            let valid = for_each_option(data, |kind, value, raw| {
+                let verdict = if lcp {
+                    match (kind, value) {
+                        (LCP_MRU, [m0, m1]) if u16::from_be_bytes([*m0, *m1]) as usize >= MTU => {
+                            Verdict::Ack
+                        }
+                        // The MTU is fixed, ask for a large enough MRU.
+                        (LCP_MRU, _) => Verdict::Nak(&MRU),
+                        // All control characters are escaped anyway.
+                        (LCP_ACCM, [_, _, _, _]) => Verdict::Ack,
+                        (LCP_AUTH, v) if v == PAP => {
+                            pap = true;
+                            Verdict::Ack
+                        }
+                        (LCP_AUTH, _) => Verdict::Nak(&PAP),
+                        // Loopback isn't detected, the magic number is only echoed back.
+                        (LCP_MAGIC, [_, _, _, _]) => Verdict::Ack,
+                        _ => Verdict::Reject,
+                    }
+                } else {
+                    match (kind, ipv4(value)) {
+                        (IPCP_ADDRESS, Some(address)) => {
+                            peer_address = Some(address);
+                            Verdict::Ack
+                        }
+                        _ => Verdict::Reject,
+                    }
+                };
+                fits &= match verdict {
+                    Verdict::Ack => ack.push(raw),
+                    Verdict::Nak(value) => nak.push_option(kind, value),
+                    Verdict::Reject => reject.push(raw),
+                };
+            });
+            if !valid {
+                return Ok(false);
+            }
+            if !fits {
+                warn!("PPP configure request too long");
+                return Ok(false);
+            }
+
+            let p = if !reject.is_empty() {
+                &mut reject
+            } else if !nak.is_empty() {
+                &mut nak
+            } else {
+                s.peer_acked = true;
+                if lcp {
+                    s.pap = pap;
+                } else {
+                    s.peer_address = peer_address;
+                }
+                &mut ack
+            };
+            hdlc::write_frame(serial, protocol, p.finish()).await?;
+        }
+        CONFIGURE_ACK if reply => {
+            s.acked = true;
+            s.deadline = None;
+        }
+        CONFIGURE_NAK if reply => {
+            // The peer suggests the addresses to ask for.
+            if !lcp {
+                for_each_option(data, |kind, value, _| {
+                    if let Some(address) = ipv4(value) {
+                        match kind {
+                            IPCP_ADDRESS => s.address = address,
+                            k if k == IPCP_DNS[0] => s.dns[0] = Some(address),
+                            k if k == IPCP_DNS[1] => s.dns[1] = Some(address),
+                            _ => {}
+                        }
+                    }
+                });
+            }
+            next_request(serial, config, s).await?;
+        }
+        CONFIGURE_REJECT if reply => {
+            if !lcp {
+                let mut address = false;
+                for_each_option(data, |kind, _, _| match kind {
+                    IPCP_ADDRESS => address = true,
+                    k if k == IPCP_DNS[0] => s.dns[0] = None,
+                    k if k == IPCP_DNS[1] => s.dns[1] = None,
+                    _ => {}
+                });
+                if address {
+                    return Err(Error::NoAddress);
+                }
+            }
+            next_request(serial, config, s).await?;
+        }
+        TERMINATE_REQUEST => {
+            debug!("PPP link closed by the peer");
+            let mut p = ControlPacket::new(TERMINATE_ACK, id);
+            hdlc::write_frame(serial, protocol, p.finish()).await?;
+            return Ok(true);
+        }
+        CONFIGURE_ACK | CONFIGURE_NAK | CONFIGURE_REJECT | TERMINATE_ACK | CODE_REJECT => {}
+        PROTOCOL_REJECT | ECHO_REPLY | DISCARD_REQUEST if lcp => {}
+        ECHO_REQUEST if lcp => {
+            if s.phase != Phase::Establish {
+                // The magic number wasn't negotiated, it's zero.
+                let mut p = ControlPacket::new(ECHO_REPLY, id);
+                p.push(&[0; 4]);
+                p.push(data.get(4..).unwrap_or(&[]));
+                hdlc::write_frame(serial, protocol, p.finish()).await?;
+            }
+        }
+        _ => {
+            let mut p = ControlPacket::new(CODE_REJECT, id);
+            p.push(packet);
+            hdlc::write_frame(serial, protocol, p.finish()).await?;
+        }
+    }
+
+    if s.acked && s.peer_acked {
+        match s.phase {
+            Phase::Establish => {
+                s.enter(if s.pap {
+                    Phase::Authenticate
+                } else {
+                    Phase::Network
+                });
+                send_request(serial, config, s).await?;
+            }
+            Phase::Network => {
+                if s.address.is_unspecified() {
+                    return Err(Error::NoAddress);
+                }
+                s.enter(Phase::Opened);
+                info!("PPP link up, address {}", s.address);
+                state.set_config(Some(s.ip_config()));
+            }
+            _ => {}
+        }
+    }
+    Ok(false)
+}
+
+/// Answer a nak or a reject of our request: send it again, updated.
+async fn next_request<S>(serial: &mut S, config: &Config<'_>, s: &mut Session) -> Result<(), Error>
+where
+    S: AsyncWrite + Unpin,
+{
+    s.retries += 1;
+    if s.retries >= MAX_CONFIGURE {
+        return Err(Error::Timeout);
+    }
+    send_request(serial, config, s).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control() {
+        let packet = [CONFIGURE_REQUEST, 7, 0, 8, LCP_MRU, 4, 0x05, 0xdc, 0, 0];
+        assert_eq!(
+            parse_control(&packet),
+            Some((CONFIGURE_REQUEST, 7, &packet[4..8]))
+        );
+        assert_eq!(
+            parse_control(&[ECHO_REQUEST, 1, 0, 4]),
+            Some((ECHO_REQUEST, 1, &[][..]))
+        );
+    }
+
+    #[test]
+    fn control_malformed() {
+        let bad: &[&[u8]] = &[
+            &[],
+            &[CONFIGURE_REQUEST, 1, 0],
+            &[CONFIGURE_REQUEST, 1, 0, 3],
+            &[CONFIGURE_REQUEST, 1, 0, 5],
+            &[CONFIGURE_REQUEST, 1, 0xff, 0xff, 0, 0],
+        ];
+        for packet in bad {
+            assert_eq!(parse_control(packet), None);
+        }
+    }
+
+    #[test]
+    fn options() {
+        let data = [
+            LCP_MRU, 4, 0x05, 0xdc, LCP_AUTH, 4, 0xc0, 0x23, LCP_MAGIC, 2,
+        ];
+        let mut options = heapless::Vec::<_, 4>::new();
+        assert!(for_each_option(&data, |kind, value, raw| {
+            unwrap!(options.push((kind, value, raw)));
+        }));
+        assert_eq!(
+            options[..],
+            [
+                (LCP_MRU, &data[2..4], &data[0..4]),
+                (LCP_AUTH, &data[6..8], &data[4..8]),
+                (LCP_MAGIC, &data[10..10], &data[8..10]),
+            ]
+        );
+        assert!(for_each_option(&[], |_, _, _| unreachable!()));
+    }
+
+    #[test]
+    fn options_malformed() {
+        let bad: &[&[u8]] = &[
+            // Truncated header.
+            &[LCP_MRU],
+            &[LCP_MRU, 4, 0x05, 0xdc, LCP_AUTH],
+            // Length shorter than the header.
+            &[LCP_MRU, 0],
+            &[LCP_MRU, 1, 0x05],
+            // Length past the end.
+            &[LCP_MRU, 4, 0x05],
+            &[LCP_MRU, 0xff, 0x05, 0xdc],
+        ];
+        for data in bad {
+            let mut calls = 0;
+            assert!(!for_each_option(data, |_, _, _| calls += 1));
+            assert!(calls <= 1);
+        }
+        assert_eq!(ipv4(&[10, 0, 0]), None);
+        assert_eq!(ipv4(&[10, 0, 0, 1, 0]), None);
+    }
+}
@@ -0,0 +1,216 @@
+use smoltcp::iface::SocketHandle;
+use smoltcp::socket::RawSocket;
+use smoltcp::time::{Duration, Instant};
+use smoltcp::wire::{Icmpv6Packet, IpAddress, Ipv6Address, Ipv6Cidr};
+
+use crate::device::Device;
+use crate::Interface;
+
+const IPV6_HEADER_LEN: usize = 40;
+const NEXT_HEADER_ICMPV6: u8 = 58;
+const NDISC_HOP_LIMIT: u8 = 255;
+
+const ICMPV6_ROUTER_SOLICIT: u8 = 133;
+const ICMPV6_ROUTER_ADVERT: u8 = 134;
+const RA_HEADER_LEN: usize = 16;
+
+const NDISC_OPT_PREFIX_INFO: u8 = 3;
+const PREFIX_INFO_LEN: usize = 32;
+const PREFIX_FLAG_AUTONOMOUS: u8 = 0x40;
+
+const ALL_ROUTERS: Ipv6Address =
+    Ipv6Address([0xff, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x02]);
+
+/// Stateless address autoconfiguration (RFC 4862).
+///
+/// Sends a router solicitation once the link is up, then derives a global
+/// address from the first autonomous /64 prefix found in router
+/// advertisements, and picks the advertising router as the default IPv6
+/// gateway. Duplicate address detection is not done.
+pub(crate) struct Slaac {
+    handle: SocketHandle,
+    solicited: bool,
+    address: Option<(Ipv6Cidr, Option<Instant>)>,
+    router: Option<(Ipv6Address, Instant)>,
+}
+
+impl Slaac {
+    pub fn new(handle: SocketHandle) -> Self {
+        Self {
+            handle,
+            solicited: false,
+            address: None,
+            router: None,
+        }
+    }
+
+    pub fn address(&self) -> Option<Ipv6Cidr> {
+        self.address.map(|(a, _)| a)
+    }
+
+    pub fn router(&self) -> Option<Ipv6Address> {
+        self.router.map(|(r, _)| r)
+    }
+
+    /// Forget the acquired address and router, for example when the link goes down.
+    ///
+    /// Returns true if anything was forgotten.
+    pub fn reset(&mut self) -> bool {
+        self.solicited = false;
+        let changed = self.address.is_some() || self.router.is_some();
+        self.address = None;
+        self.router = None;
+        changed
+    }
+
+    /// Process received router advertisements.
+    ///
+    /// Returns true if the address or the router changed.
+    pub fn poll<D: Device + 'static>(
+        &mut self,
+        iface: &mut Interface<D>,
+        link_local: Ipv6Address,
+        timestamp: Instant,
+    ) -> bool {
+        let socket = iface.get_socket::<RawSocket>(self.handle);
+        let mut changed = false;
+
+        if !self.solicited {
+            let mut rs = [0; IPV6_HEADER_LEN + 8];
+            rs[0] = 0x60;
+            rs[4..6].copy_from_slice(&8u16.to_be_bytes());
+            rs[6] = NEXT_HEADER_ICMPV6;
+            rs[7] = NDISC_HOP_LIMIT;
+            rs[8..24].copy_from_slice(link_local.as_bytes());
+            rs[24..40].copy_from_slice(ALL_ROUTERS.as_bytes());
+            rs[IPV6_HEADER_LEN] = ICMPV6_ROUTER_SOLICIT;
+            Icmpv6Packet::new_unchecked(&mut rs[IPV6_HEADER_LEN..])
+                .fill_checksum(&IpAddress::Ipv6(link_local), &IpAddress::Ipv6(ALL_ROUTERS));
+            if socket.send_slice(&rs).is_ok() {
+                debug!("SLAAC: sent router solicitation");
+                self.solicited = true;
+            }
+        }
+
+        let mut buf = [0; 512];
+        while let Ok(n) = socket.recv_slice(&mut buf) {
+            changed |= self.process(&buf[..n], link_local, timestamp);
+        }
+
+        if let Some((addr, Some(expires_at))) = self.address {
+            if timestamp >= expires_at {
+                debug!("SLAAC: address {} expired", addr);
+                self.address = None;
+                changed = true;
+            }
+        }
+        if let Some((router, expires_at)) = self.router {
+            if timestamp >= expires_at {
+                debug!("SLAAC: router {} expired", router);
+                self.router = None;
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    fn process(&mut self, packet: &[u8], link_local: Ipv6Address, timestamp: Instant) -> bool {
+        if packet.len() < IPV6_HEADER_LEN + RA_HEADER_LEN
+            || packet[0] >> 4 != 6
+            || packet[6] != NEXT_HEADER_ICMPV6
+            || packet[7] != NDISC_HOP_LIMIT
+        {
+            return false;
+        }
+
+        let src = Ipv6Address::from_bytes(&packet[8..24]);
+        let dst = Ipv6Address::from_bytes(&packet[24..40]);
+        // Router advertisements always come from the router's link-local address.
+        if src.as_bytes()[0] != 0xfe || src.as_bytes()[1] & 0xc0 != 0x80 {
+            return false;
+        }
+
+        let icmp = &packet[IPV6_HEADER_LEN..];
+        if icmp[0] != ICMPV6_ROUTER_ADVERT
+            || !Icmpv6Packet::new_unchecked(icmp)
+                .verify_checksum(&IpAddress::Ipv6(src), &IpAddress::Ipv6(dst))
+        {
+            return false;
+        }
+
+        let mut changed = false;
+
+        let router_lifetime = u16::from_be_bytes([icmp[6], icmp[7]]);
+        if router_lifetime == 0 {
+            if self.router.map(|(r, _)| r) == Some(src) {
+                debug!("SLAAC: router {} is no longer a default router", src);
+                self.router = None;
+                changed = true;
+            }
+        } else {
+            let expires_at = timestamp + Duration::from_secs(router_lifetime as u64);
+            if self.router.map(|(r, _)| r) != Some(src) {
+                debug!("SLAAC: default router {}", src);
+                changed = true;
+            }
+            self.router = Some((src, expires_at));
+        }
+
+        let mut opts = &icmp[RA_HEADER_LEN..];
+        while opts.len() >= 2 {
+            let len = opts[1] as usize * 8;
+            if len == 0 || len > opts.len() {
+                break;
+            }
+            if opts[0] == NDISC_OPT_PREFIX_INFO && len == PREFIX_INFO_LEN {
+                changed |= self.process_prefix(&opts[..len], link_local, timestamp);
+            }
+            opts = &opts[len..];
+        }
+
+        changed
+    }
+
+    fn process_prefix(&mut self, opt: &[u8], link_local: Ipv6Address, timestamp: Instant) -> bool {
+        let prefix_len = opt[2];
+        let flags = opt[3];
+        let valid_lifetime = u32::from_be_bytes([opt[4], opt[5], opt[6], opt[7]]);
+
+        // Only /64 prefixes can be combined with the 64 bit interface identifier.
+        if flags & PREFIX_FLAG_AUTONOMOUS == 0 || prefix_len != 64 || opt[16] == 0xfe {
+            return false;
+        }
+
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&opt[16..24]);
+        bytes[8..].copy_from_slice(&link_local.as_bytes()[8..]);
+        let addr = Ipv6Cidr::new(Ipv6Address(bytes), 64);
+
+        match self.address {
+            // Only one address is tracked, ignore other prefixes.
+            Some((current, _)) if current != addr => return false,
+            _ => {}
+        }
+
+        if valid_lifetime == 0 {
+            let changed = self.address.is_some();
+            if changed {
+                debug!("SLAAC: prefix of {} withdrawn", addr);
+            }
+            self.address = None;
+            return changed;
+        }
+
+        let expires_at = match valid_lifetime {
+            u32::MAX => None,
+            secs => Some(timestamp + Duration::from_secs(secs as u64)),
+        };
+        let changed = self.address.is_none();
+        if changed {
+            debug!("SLAAC: acquired address {}", addr);
+        }
+        self.address = Some((addr, expires_at));
+        changed
+    }
+}
@@ -0,0 +1,501 @@
+//! SLIP over a serial port, RFC 1055.
+//!
+//! SLIP only delimits IP packets on the serial line: there's no negotiation, so both ends
+//! need a static configuration. It's the quickest way to get a board without USB or Ethernet
+//! on the network during development, with Linux on the other end of the UART:
+//!
+//! ```text
+//! slattach -s 115200 -p slip /dev/ttyUSB0 &
+//! ip addr add 192.168.7.1 peer 192.168.7.2 dev sl0
+//! ip link set sl0 up mtu 1500
+//! ```
+//!
+//! The [`Runner`] owns the serial port, and moves the packets between it and the queues in a
+//! [`State`]. The stack gets these packets through a [`SlipDevice`].
+//!
+//! ```ignore
+//! static STATE: Forever<slip::State<1500, 2, 2>> = Forever::new();
+//! let state = STATE.put(slip::State::new());
+//! let (runner, device) = slip::new(uart, state, slip::Config::default());
+//! spawner.spawn(slip_task(runner)).unwrap();
+//! // `device` is given to the `Stack`, with a `StaticConfigurator`.
+//! ```
+
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use embassy::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy::channel::channel::Channel;
+use embassy::io::{self, AsyncBufRead, AsyncWrite, AsyncWriteExt};
+use embassy::util::{select, Either};
+use futures::future::poll_fn;
+use smoltcp::phy::{DeviceCapabilities, Medium};
+
+use crate::device::{Device, LinkState};
+
+const END: u8 = 0xC0;
+const ESC: u8 = 0xDB;
+const ESC_END: u8 = 0xDC;
+const ESC_ESC: u8 = 0xDD;
+
+/// SLIP configuration.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Config {
+    /// Send an `END` before each packet, in addition to the one after it.
+    ///
+    /// The receiver then discards any line noise received between packets as an empty or
+    /// bad packet, instead of prepending it to the next one. This is what RFC 1055
+    /// recommends, and costs one byte per packet.
+    pub leading_end: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self { leading_end: true }
+    }
+}
+
+/// An IP packet in one of the queues.
+pub struct Packet<const MTU: usize> {
+    len: usize,
+    data: [u8; MTU],
+}
+
+impl<const MTU: usize> Packet<MTU> {
+    const fn new() -> Self {
+        Self {
+            len: 0,
+            data: [0; MTU],
+        }
+    }
+}
+
+/// Queues between the stack and the runner: `RX` received packets, `TX` packets to transmit,
+/// of up to `MTU` bytes.
+///
+/// Both ends must use the same MTU: longer packets received are dropped. The MTU of Linux
+/// SLIP interfaces is 296 by default, set it with `ip link set sl0 mtu ...`.
+pub struct State<const MTU: usize, const RX: usize, const TX: usize> {
+    rx: Channel<CriticalSectionRawMutex, Packet<MTU>, RX>,
+    tx: Channel<CriticalSectionRawMutex, Packet<MTU>, TX>,
+}
+
+impl<const MTU: usize, const RX: usize, const TX: usize> State<MTU, RX, TX> {
+    pub fn new() -> Self {
+        Self {
+            rx: Channel::new(),
+            tx: Channel::new(),
+        }
+    }
+}
+
+/// Create the runner driving the serial port, and the device to give to the embassy-net
+/// stack.
+pub fn new<'a, S, const MTU: usize, const RX: usize, const TX: usize>(
+    serial: S,
+    state: &'a State<MTU, RX, TX>,
+    config: Config,
+) -> (Runner<'a, S, MTU, RX, TX>, SlipDevice<'a, MTU, RX, TX>)
+where
+    S: AsyncBufRead + AsyncWrite + Unpin,
+{
+    (
+        Runner {
+            serial,
+            state,
+            config,
+            rx: Packet::new(),
+            escaped: false,
+            overflow: false,
+        },
+        SlipDevice { state },
+    )
+}
+
+/// Task driving the serial port.
+pub struct Runner<'a, S, const MTU: usize, const RX: usize, const TX: usize> {
+    serial: S,
+    state: &'a State<MTU, RX, TX>,
+    config: Config,
+    /// Packet being received.
+    rx: Packet<MTU>,
+    escaped: bool,
+    overflow: bool,
+}
+
+impl<'a, S, const MTU: usize, const RX: usize, const TX: usize> Runner<'a, S, MTU, RX, TX>
+where
+    S: AsyncBufRead + AsyncWrite + Unpin,
+{
+    /// Exchange packets over the serial port. Returns when the serial port fails, or `Ok` if
+    /// it's closed.
+    pub async fn run(&mut self) -> io::Result<()> {
+        loop {
+            let res = {
+                let Self {
+                    serial,
+                    state,
+                    rx,
+                    escaped,
+                    overflow,
+                    ..
+                } = self;
+                let read =
+                    poll_fn(|cx| read_packet(Pin::new(&mut *serial), cx, rx, escaped, overflow));
+                select(read, state.tx.recv()).await
+            };
+
+            match res {
+                Either::First(Ok(true)) => {
+                    let packet = core::mem::replace(&mut self.rx, Packet::new());
+                    if self.state.rx.try_send(packet).is_err() {
+                        warn!("SLIP rx queue full, dropping packet");
+                    }
+                }
+                Either::First(Ok(false)) => return Ok(()),
+                Either::First(Err(e)) => return Err(e),
+                Either::Second(packet) => self.write_packet(&packet.data[..packet.len]).await?,
+            }
+        }
+    }
+
+    /// Release the serial port.
+    pub fn into_inner(self) -> S {
+        self.serial
+    }
+
+    async fn write_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        // Escaping at most doubles the size, send the packet in chunks.
+        let mut chunk = [0; 64];
+        let mut n = 0;
+        if self.config.leading_end {
+            chunk[n] = END;
+            n += 1;
+        }
+        for &b in data {
+            if n + 2 > chunk.len() {
+                self.serial.write_all(&chunk[..n]).await?;
+                n = 0;
+            }
+            let escaped = match b {
+                END => Some(ESC_END),
+                ESC => Some(ESC_ESC),
+                _ => None,
+            };
+            if let Some(e) = escaped {
+                chunk[n] = ESC;
+                chunk[n + 1] = e;
+                n += 2;
+            } else {
+                chunk[n] = b;
+                n += 1;
+            }
+        }
+        if n == chunk.len() {
+            self.serial.write_all(&chunk[..n]).await?;
+            n = 0;
+        }
+        chunk[n] = END;
+        self.serial.write_all(&chunk[..n + 1]).await?;
+        self.serial.flush().await
+    }
+}
+
+/// Process received bytes into `packet` until the end of a packet. Returns `Ok(false)` if
+/// the serial port is closed.
+///
+/// This can be cancelled without losing data: the bytes are taken from `serial` as they're
+/// processed.
+fn read_packet<S: AsyncBufRead + Unpin, const MTU: usize>(
+    mut serial: Pin<&mut S>,
+    cx: &mut Context<'_>,
+    packet: &mut Packet<MTU>,
+    escaped: &mut bool,
+    overflow: &mut bool,
+) -> Poll<io::Result<bool>> {
+    loop {
+        let buf = match serial.as_mut().poll_fill_buf(cx) {
+            Poll::Ready(Ok(buf)) => buf,
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+            Poll::Pending => return Poll::Pending,
+        };
+        if buf.is_empty() {
+            return Poll::Ready(Ok(false));
+        }
+
+        let mut done = false;
+        let mut n = 0;
+        for &b in buf {
+            n += 1;
+            let b = match (b, *escaped) {
+                (END, _) => {
+                    // Empty packets come from the leading ENDs.
+                    if packet.len > 0 && !*overflow && !*escaped {
+                        done = true;
+                        break;
+                    }
+                    packet.len = 0;
+                    *escaped = false;
+                    *overflow = false;
+                    continue;
+                }
+                (ESC, false) => {
+                    *escaped = true;
+                    continue;
+                }
+                (ESC_END, true) => END,
+                (ESC_ESC, true) => ESC,
+                // Not a valid escape, RFC 1055 keeps the byte.
+                (b, _) => b,
+            };
+            *escaped = false;
+            if packet.len == MTU {
+                *overflow = true;
+            } else {
+                packet.data[packet.len] = b;
+                packet.len += 1;
+            }
+        }
+        serial.as_mut().consume(n);
+        if done {
+            *escaped = false;
+            *overflow = false;
+            return Poll::Ready(Ok(true));
+        }
+    }
+}
+
+/// The SLIP link, as an embassy-net device.
+pub struct SlipDevice<'a, const MTU: usize, const RX: usize, const TX: usize> {
+    state: &'a State<MTU, RX, TX>,
+}
+
+impl<'a, const MTU: usize, const RX: usize, const TX: usize> Device
+    for SlipDevice<'a, MTU, RX, TX>
+{
+    type RxToken<'b>
+        = RxToken<MTU>
+    where
+        Self: 'b;
+    type TxToken<'b>
+        = TxToken<'a, MTU, TX>
+    where
+        Self: 'b;
+
+    fn receive(&mut self) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        if self.state.tx.is_full() {
+            return None;
+        }
+        let packet = self.state.rx.try_recv().ok()?;
+        Some((RxToken { packet }, TxToken { tx: &self.state.tx }))
+    }
+
+    fn transmit(&mut self) -> Option<Self::TxToken<'_>> {
+        if self.state.tx.is_full() {
+            return None;
+        }
+        Some(TxToken { tx: &self.state.tx })
+    }
+
+    fn register_waker(&mut self, waker: &Waker) {
+        let mut cx = Context::from_waker(waker);
+        let _ = self.state.rx.poll_ready_to_recv(&mut cx);
+        let _ = self.state.tx.poll_ready_to_send(&mut cx);
+    }
+
+    fn capabilities(&mut self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.medium = Medium::Ip;
+        caps.max_transmission_unit = MTU;
+        caps.max_burst_size = Some(TX);
+        caps
+    }
+
+    fn link_state(&mut self) -> LinkState {
+        // There's no way to tell whether the other end is listening.
+        LinkState::Up
+    }
+
+    fn ethernet_address(&mut self) -> [u8; 6] {
+        // SLIP has no link-layer addresses.
+        [0; 6]
+    }
+}
+
+pub struct RxToken<const MTU: usize> {
+    packet: Packet<MTU>,
+}
+
+impl<const MTU: usize> crate::device::RxToken for RxToken<MTU> {
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(&mut self.packet.data[..self.packet.len])
+    }
+}
+
+pub struct TxToken<'a, const MTU: usize, const TX: usize> {
+    tx: &'a Channel<CriticalSectionRawMutex, Packet<MTU>, TX>,
+}
+
+impl<'a, const MTU: usize, const TX: usize> crate::device::TxToken for TxToken<'a, MTU, TX> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut packet = Packet::new();
+        packet.len = len;
+        let r = f(&mut packet.data[..len]);
+        // The token is only handed out when there's room in the queue.
+        if self.tx.try_send(packet).is_err() {
+            warn!("SLIP tx queue full, dropping packet");
+        }
+        r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate std;
+    use core::future::Future;
+    use futures::pin_mut;
+    use futures_test::task::noop_context;
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    /// Serial port handing out the received bytes in the given chunks, then closed.
+    struct Serial {
+        chunks: VecDeque<Vec<u8>>,
+        written: Vec<u8>,
+    }
+
+    impl Serial {
+        fn new(chunks: &[&[u8]]) -> Self {
+            Self {
+                chunks: chunks.iter().map(|c| c.to_vec()).collect(),
+                written: Vec::new(),
+            }
+        }
+    }
+
+    impl AsyncBufRead for Serial {
+        fn poll_fill_buf(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+            let this = self.get_mut();
+            while this.chunks.front().map_or(false, |c| c.is_empty()) {
+                this.chunks.pop_front();
+            }
+            Poll::Ready(Ok(this.chunks.front().map_or(&[][..], |c| &c[..])))
+        }
+
+        fn consume(self: Pin<&mut Self>, amt: usize) {
+            unwrap!(self.get_mut().chunks.front_mut()).drain(..amt);
+        }
+    }
+
+    impl AsyncWrite for Serial {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.get_mut().written.extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// Read the packets until the serial port is closed.
+    fn read_all<const MTU: usize>(serial: &mut Serial) -> Vec<Vec<u8>> {
+        let mut packets = Vec::new();
+        let mut packet = Packet::<MTU>::new();
+        let (mut escaped, mut overflow) = (false, false);
+        loop {
+            let poll = read_packet(
+                Pin::new(&mut *serial),
+                &mut noop_context(),
+                &mut packet,
+                &mut escaped,
+                &mut overflow,
+            );
+            match poll {
+                Poll::Ready(Ok(true)) => {
+                    packets.push(packet.data[..packet.len].to_vec());
+                    packet.len = 0;
+                }
+                Poll::Ready(Ok(false)) => return packets,
+                Poll::Ready(Err(e)) => panic!("read failed: {:?}", e),
+                Poll::Pending => panic!("read pending"),
+            }
+        }
+    }
+
+    fn write(config: Config, data: &[u8]) -> Vec<u8> {
+        let state = State::<512, 1, 1>::new();
+        let (mut runner, _device) = new(Serial::new(&[]), &state, config);
+        {
+            let write = runner.write_packet(data);
+            pin_mut!(write);
+            match write.poll(&mut noop_context()) {
+                Poll::Ready(res) => unwrap!(res),
+                Poll::Pending => panic!("write pending"),
+            }
+        }
+        runner.into_inner().written
+    }
+
+    #[test]
+    fn escaping() {
+        let data = [0x45, END, ESC, ESC_END, 0x01];
+        let framed = write(Config::default(), &data);
+        assert_eq!(
+            framed,
+            [END, 0x45, ESC, ESC_END, ESC, ESC_ESC, ESC_END, 0x01, END]
+        );
+        assert_eq!(read_all::<16>(&mut Serial::new(&[&framed])), [data]);
+
+        let framed = write(Config { leading_end: false }, &data);
+        assert_eq!(framed[0], 0x45);
+        assert_eq!(read_all::<16>(&mut Serial::new(&[&framed])), [data]);
+    }
+
+    #[test]
+    fn long_packet() {
+        // Escaped, it takes several chunks of the writer.
+        let data = [END; 300];
+        let framed = write(Config::default(), &data);
+        assert_eq!(framed.len(), 2 + 2 * data.len());
+        assert_eq!(read_all::<300>(&mut Serial::new(&[&framed])), [data]);
+    }
+
+    #[test]
+    fn escape_across_reads() {
+        let mut serial = Serial::new(&[&[END, 0x45, ESC], &[ESC_END], &[ESC], &[ESC_ESC, END]]);
+        assert_eq!(read_all::<16>(&mut serial), [[0x45, END, ESC]]);
+    }
+
+    #[test]
+    fn bad_input() {
+        let mut serial = Serial::new(&[
+            // Empty packets between the leading and trailing ENDs.
+            &[END, END, END],
+            // Aborted by an escaped END.
+            &[0x01, 0x02, ESC, END],
+            // Not a valid escape: the byte is kept.
+            &[ESC, 0x41, END],
+            // Longer than the MTU.
+            &[1, 2, 3, 4, 5, END],
+            &[1, 2, 3, 4, END],
+            // Cut by the end of the input.
+            &[0x03],
+        ]);
+        let expected: &[&[u8]] = &[&[0x41], &[1, 2, 3, 4]];
+        assert_eq!(read_all::<4>(&mut serial), expected);
+    }
+}
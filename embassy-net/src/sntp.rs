@@ -0,0 +1,139 @@
+use embassy::time::{with_timeout, Clock, Duration, Instant, Timer};
+use smoltcp::wire::{IpAddress, IpEndpoint};
+
+use crate::udp_socket::UdpSocket;
+use crate::Error;
+
+const NTP_PORT: u16 = 123;
+const NTP_PACKET_LEN: usize = 48;
+/// Seconds between the NTP epoch (1900) and the Unix epoch (1970).
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+const MODE_CLIENT: u8 = 3;
+const MODE_SERVER: u8 = 4;
+const VERSION: u8 = 4;
+
+/// Errors of an SNTP query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SntpError {
+    /// The server didn't answer in time.
+    Timeout,
+    /// The server answered with an invalid packet, or told us to go away.
+    InvalidResponse,
+    /// Sending the request failed.
+    Network(Error),
+}
+
+/// SNTP (RFC 4330) client, keeping a [`Clock`] synchronized.
+pub struct SntpClient<'a> {
+    socket: UdpSocket<'a>,
+    server: IpEndpoint,
+    clock: &'a Clock,
+    interval: Duration,
+    timeout: Duration,
+}
+
+impl<'a> SntpClient<'a> {
+    /// Create a client querying `server` on `socket`.
+    ///
+    /// The socket buffers must hold at least one 48 byte packet. By default
+    /// the clock is synchronized every hour, and queries time out after 5 seconds.
+    pub fn new(mut socket: UdpSocket<'a>, server: IpAddress, clock: &'a Clock) -> Self {
+        unwrap!(socket.bind(0));
+        Self {
+            socket,
+            server: IpEndpoint::new(server, NTP_PORT),
+            clock,
+            interval: Duration::from_secs(3600),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    pub fn set_server(&mut self, server: IpAddress) {
+        self.server = IpEndpoint::new(server, NTP_PORT);
+    }
+
+    /// Set the time between two synchronizations in [`run`](Self::run).
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Query the server once and update the clock.
+    pub async fn sync(&mut self) -> Result<(), SntpError> {
+        // The transmit timestamp is echoed back by the server in the
+        // originate timestamp, use the local time to match the reply.
+        let t1 = Instant::now();
+        let mut request = [0; NTP_PACKET_LEN];
+        request[0] = VERSION << 3 | MODE_CLIENT;
+        request[40..48].copy_from_slice(&t1.as_micros().to_be_bytes());
+
+        self.socket
+            .send_to(&request, self.server)
+            .await
+            .map_err(SntpError::Network)?;
+
+        let server = self.server;
+        let socket = &mut self.socket;
+        let recv = async {
+            let mut buf = [0; NTP_PACKET_LEN];
+            loop {
+                match socket.recv_from(&mut buf).await {
+                    Ok((NTP_PACKET_LEN, from))
+                        if from == server && buf[24..32] == request[40..48] =>
+                    {
+                        return (buf, Instant::now())
+                    }
+                    _ => {}
+                }
+            }
+        };
+        let (reply, t4) = with_timeout(self.timeout, recv)
+            .await
+            .map_err(|_| SntpError::Timeout)?;
+
+        let stratum = reply[1];
+        if reply[0] & 0x07 != MODE_SERVER || stratum == 0 || stratum > 15 {
+            return Err(SntpError::InvalidResponse);
+        }
+
+        let t2 = ntp_to_unix_micros(&reply[32..40]).ok_or(SntpError::InvalidResponse)?;
+        let t3 = ntp_to_unix_micros(&reply[40..48]).ok_or(SntpError::InvalidResponse)?;
+
+        // Half of the round trip, minus the time the server held the request.
+        let rtt = (t4 - t1).as_micros();
+        let delay = rtt.saturating_sub(t3.saturating_sub(t2)) / 2;
+        let now = t3 + delay;
+
+        debug!("SNTP: synchronized, unix time {} us", now);
+        self.clock.set_unix_micros_at(t4, now);
+        Ok(())
+    }
+
+    /// Keep the clock synchronized forever.
+    ///
+    /// Failed queries are retried after 10 seconds, or the interval if shorter.
+    pub async fn run(&mut self) -> ! {
+        loop {
+            let wait = match self.sync().await {
+                Ok(()) => self.interval,
+                Err(e) => {
+                    warn!("SNTP: sync failed: {:?}", e);
+                    self.interval.min(Duration::from_secs(10))
+                }
+            };
+            Timer::after(wait).await;
+        }
+    }
+}
+
+fn ntp_to_unix_micros(ts: &[u8]) -> Option<u64> {
+    let secs = u32::from_be_bytes([ts[0], ts[1], ts[2], ts[3]]) as u64;
+    let frac = u32::from_be_bytes([ts[4], ts[5], ts[6], ts[7]]) as u64;
+    let unix_secs = secs.checked_sub(NTP_UNIX_OFFSET)?;
+    Some(unix_secs * 1_000_000 + ((frac * 1_000_000) >> 32))
+}
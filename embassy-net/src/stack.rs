@@ -1,74 +1,194 @@
 use core::cell::RefCell;
 use core::future::Future;
+use core::mem;
 use core::task::Context;
 use core::task::Poll;
-use embassy::blocking_mutex::ThreadModeMutex;
+use embassy::blocking_mutex::raw::RawMutex;
+use embassy::blocking_mutex::Mutex;
 use embassy::time::{Instant, Timer};
 use embassy::waitqueue::WakerRegistration;
 use futures::pin_mut;
 use smoltcp::iface::InterfaceBuilder;
-use smoltcp::iface::SocketStorage;
+use smoltcp::iface::{Context as SmolContext, SocketHandle, SocketStorage};
+use smoltcp::socket::AnySocket;
+#[cfg(feature = "tcp")]
+use smoltcp::socket::Socket;
 use smoltcp::time::Instant as SmolInstant;
+#[cfg(any(
+    feature = "igmp",
+    feature = "medium-ethernet",
+    feature = "medium-ieee802154"
+))]
+use smoltcp::wire::IpAddress;
 use smoltcp::wire::{IpCidr, Ipv4Address, Ipv4Cidr};
 
-#[cfg(feature = "medium-ethernet")]
+#[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
 use smoltcp::iface::{Neighbor, NeighborCache, Route, Routes};
-#[cfg(feature = "medium-ethernet")]
+#[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
 use smoltcp::phy::{Device as _, Medium};
 #[cfg(feature = "medium-ethernet")]
-use smoltcp::wire::{EthernetAddress, HardwareAddress, IpAddress};
+use smoltcp::wire::EthernetAddress;
+#[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+use smoltcp::wire::HardwareAddress;
+#[cfg(feature = "medium-ieee802154")]
+use smoltcp::wire::{Ieee802154Address, Ieee802154Pan};
+#[cfg(feature = "proto-ipv6")]
+use smoltcp::wire::{Ipv6Address, Ipv6Cidr};
+#[cfg(feature = "slaac")]
+use smoltcp::{
+    socket::{RawPacketMetadata, RawSocket, RawSocketBuffer},
+    wire::{IpProtocol, IpVersion},
+};
 
-use crate::config::Configurator;
+#[cfg(feature = "dhcpv4")]
+use crate::config::DhcpLease;
 use crate::config::Event;
-use crate::device::{Device, DeviceAdapter, LinkState};
+use crate::config::{Config, Configurator};
+#[cfg(feature = "packet-trace")]
+use crate::device::TraceFn;
+use crate::device::{Device, DeviceAdapter, DeviceStats, LinkState};
+#[cfg(feature = "slaac")]
+use crate::slaac::Slaac;
 use crate::Interface;
 
 const LOCAL_PORT_MIN: u16 = 1025;
 const LOCAL_PORT_MAX: u16 = 65535;
 
-pub struct StackResources<const ADDR: usize, const SOCK: usize, const NEIGHBOR: usize> {
+#[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+const ROUTES: usize = if cfg!(feature = "proto-ipv6") { 2 } else { 1 };
+
+#[cfg(feature = "igmp")]
+const MULTICAST_GROUPS: usize = 4;
+
+/// Memory for a [`Stack`].
+///
+/// `ADDR` is the number of IP addresses. The IPv4 address always takes the
+/// first one. With `proto-ipv6`, the link-local address, the address from the
+/// [`Config`](crate::Config) and the address acquired through SLAAC take one
+/// more each.
+///
+/// `SOCK` is the number of sockets. With `slaac`, one of them is used for
+/// router advertisements.
+///
+/// `TCP` is the number of TCP sockets the stack can hand out with
+/// [`TcpSocket::from_pool`](crate::TcpSocket::from_pool), up to 32. Each of
+/// them has `TCP_BUF` bytes of rx buffer and as many of tx buffer. They also
+/// count in `SOCK`.
+///
+/// With `igmp`, up to 4 IPv4 multicast groups can be joined.
+pub struct StackResources<
+    const ADDR: usize,
+    const SOCK: usize,
+    const NEIGHBOR: usize,
+    const TCP: usize = 0,
+    const TCP_BUF: usize = 0,
+> {
     addresses: [IpCidr; ADDR],
     sockets: [SocketStorage<'static>; SOCK],
+    #[cfg(feature = "tcp")]
+    tcp_buffers: [[[u8; TCP_BUF]; 2]; TCP],
 
-    #[cfg(feature = "medium-ethernet")]
-    routes: [Option<(IpCidr, Route)>; 1],
-    #[cfg(feature = "medium-ethernet")]
+    #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+    routes: [Option<(IpCidr, Route)>; ROUTES],
+    #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
     neighbor_cache: [Option<(IpAddress, Neighbor)>; NEIGHBOR],
+
+    #[cfg(feature = "igmp")]
+    multicast_groups: [Option<(Ipv4Address, ())>; MULTICAST_GROUPS],
+
+    #[cfg(feature = "slaac")]
+    slaac_rx_meta: [RawPacketMetadata; 2],
+    #[cfg(feature = "slaac")]
+    slaac_rx_buffer: [u8; 1024],
+    #[cfg(feature = "slaac")]
+    slaac_tx_meta: [RawPacketMetadata; 1],
+    #[cfg(feature = "slaac")]
+    slaac_tx_buffer: [u8; 64],
 }
 
-impl<const ADDR: usize, const SOCK: usize, const NEIGHBOR: usize>
-    StackResources<ADDR, SOCK, NEIGHBOR>
+impl<
+        const ADDR: usize,
+        const SOCK: usize,
+        const NEIGHBOR: usize,
+        const TCP: usize,
+        const TCP_BUF: usize,
+    > StackResources<ADDR, SOCK, NEIGHBOR, TCP, TCP_BUF>
 {
     pub fn new() -> Self {
         Self {
             addresses: [IpCidr::new(Ipv4Address::UNSPECIFIED.into(), 32); ADDR],
             sockets: [SocketStorage::EMPTY; SOCK],
-            #[cfg(feature = "medium-ethernet")]
-            routes: [None; 1],
-            #[cfg(feature = "medium-ethernet")]
+            #[cfg(feature = "tcp")]
+            tcp_buffers: [[[0; TCP_BUF]; 2]; TCP],
+            #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+            routes: [None; ROUTES],
+            #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
             neighbor_cache: [None; NEIGHBOR],
+            #[cfg(feature = "igmp")]
+            multicast_groups: [None; MULTICAST_GROUPS],
+            #[cfg(feature = "slaac")]
+            slaac_rx_meta: [RawPacketMetadata::EMPTY; 2],
+            #[cfg(feature = "slaac")]
+            slaac_rx_buffer: [0; 1024],
+            #[cfg(feature = "slaac")]
+            slaac_tx_meta: [RawPacketMetadata::EMPTY; 1],
+            #[cfg(feature = "slaac")]
+            slaac_tx_buffer: [0; 64],
         }
     }
 }
 
-static STACK: ThreadModeMutex<RefCell<Option<Stack>>> = ThreadModeMutex::new(RefCell::new(None));
+/// A network stack instance.
+///
+/// Each stack drives one network [`Device`] with its own [`Configurator`].
+/// Sockets are bound to the stack they are created on, so a product with
+/// several interfaces creates one `Stack` per interface and picks the stack
+/// (and thus the interface) to use when creating each socket.
+///
+/// All access to the stack goes through a blocking mutex of type `M`. Use
+/// `ThreadModeRawMutex` if the stack and its sockets are only used from the
+/// thread mode executor, or `CriticalSectionRawMutex` to share them with
+/// tasks running on interrupt executors.
+pub struct Stack<D: Device + 'static, M: RawMutex> {
+    inner: Mutex<M, RefCell<Inner<D>>>,
+}
+
+// Safety: the device, the configurator and the sockets are only ever accessed
+// with the mutex held. With a `Sync` raw mutex that may happen from any thread,
+// so both the device and the configurator must be `Send`.
+unsafe impl<D: Device + Send + 'static, M: RawMutex + Sync> Sync for Stack<D, M> {}
 
-pub(crate) struct Stack {
-    pub iface: Interface,
+pub(crate) struct Inner<D: Device + 'static> {
+    pub iface: Interface<D>,
     link_up: bool,
+    config: Option<Config>,
+    link_changes: u32,
+    link_waker: WakerRegistration,
+    #[cfg(feature = "tcp")]
+    link_down_policy: LinkDownPolicy,
     config_up: bool,
     next_local_port: u16,
-    configurator: &'static mut dyn Configurator,
+    configurator: &'static mut (dyn Configurator<D> + Send),
     waker: WakerRegistration,
+    socket_capacity: usize,
+    #[cfg(feature = "tcp")]
+    tcp_pool: TcpBufferPool,
+    #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+    neighbor_capacity: usize,
+    config_waker: WakerRegistration,
+    #[cfg(feature = "proto-ipv6")]
+    link_local: Option<Ipv6Cidr>,
+    #[cfg(feature = "proto-ipv6")]
+    ipv6_address: Option<Ipv6Cidr>,
+    #[cfg(feature = "proto-ipv6")]
+    ipv6_gateway: Option<Ipv6Address>,
+    #[cfg(all(feature = "proto-ipv6", feature = "medium-ethernet"))]
+    ipv6_groups: [Option<[u8; 6]>; 3],
+    #[cfg(feature = "slaac")]
+    slaac: Slaac,
 }
 
-impl Stack {
-    pub(crate) fn with<R>(f: impl FnOnce(&mut Stack) -> R) -> R {
-        let mut stack = STACK.borrow().borrow_mut();
-        let stack = stack.as_mut().unwrap();
-        f(stack)
-    }
-
+impl<D: Device + 'static> Inner<D> {
     #[allow(clippy::absurd_extreme_comparisons)]
     pub fn get_local_port(&mut self) -> u16 {
         let res = self.next_local_port;
@@ -85,10 +205,30 @@ impl Stack {
     }
 
     fn poll_configurator(&mut self, timestamp: SmolInstant) {
-        #[cfg(feature = "medium-ethernet")]
-        let medium = self.iface.device().capabilities().medium;
+        let event = self.configurator.poll(&mut self.iface, timestamp);
+        self.apply_config_event(event);
+    }
+
+    /// Swap in a new configurator, dropping the configuration of the old one.
+    fn set_configurator(
+        &mut self,
+        configurator: &'static mut (dyn Configurator<D> + Send),
+    ) -> &'static mut (dyn Configurator<D> + Send) {
+        let old = mem::replace(&mut self.configurator, configurator);
+        old.detach(&mut self.iface);
+        if self.config_up {
+            self.apply_config_event(Event::Deconfigured);
+        }
+        // Let the run task poll the new configurator right away.
+        self.wake();
+        old
+    }
 
-        match self.configurator.poll(&mut self.iface, timestamp) {
+    fn apply_config_event(&mut self, event: Event) {
+        #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+        let routes = has_hardware_address(self.iface.device().capabilities().medium);
+
+        match event {
             Event::NoChange => {}
             Event::Configured(config) => {
                 debug!("Acquired IP configuration:");
@@ -96,8 +236,8 @@ impl Stack {
                 debug!("   IP address:      {}", config.address);
                 set_ipv4_addr(&mut self.iface, config.address);
 
-                #[cfg(feature = "medium-ethernet")]
-                if medium == Medium::Ethernet {
+                #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+                if routes {
                     if let Some(gateway) = config.gateway {
                         debug!("   Default gateway: {}", gateway);
                         self.iface
@@ -113,20 +253,117 @@ impl Stack {
                     debug!("   DNS server {}:    {}", i, s);
                 }
 
+                #[cfg(feature = "proto-ipv6")]
+                {
+                    if let Some(address) = config.ipv6_address {
+                        debug!("   IPv6 address:    {}", address);
+                    }
+                    if let Some(gateway) = config.ipv6_gateway {
+                        debug!("   IPv6 gateway:    {}", gateway);
+                    }
+                    self.ipv6_address = config.ipv6_address;
+                    self.ipv6_gateway = config.ipv6_gateway;
+                    self.update_ipv6();
+                }
+
+                self.config = Some(config);
                 self.config_up = true;
+                self.config_waker.wake();
             }
             Event::Deconfigured => {
                 debug!("Lost IP configuration");
                 set_ipv4_addr(&mut self.iface, Ipv4Cidr::new(Ipv4Address::UNSPECIFIED, 0));
-                #[cfg(feature = "medium-ethernet")]
-                if medium == Medium::Ethernet {
+                #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+                if routes {
                     self.iface.routes_mut().remove_default_ipv4_route();
                 }
+                #[cfg(feature = "proto-ipv6")]
+                {
+                    self.ipv6_address = None;
+                    self.ipv6_gateway = None;
+                    self.update_ipv6();
+                }
+                self.config = None;
                 self.config_up = false;
+                self.config_waker.wake();
+            }
+        }
+    }
+
+    /// Apply the IPv6 addresses and default route from the configurator and SLAAC.
+    #[cfg(feature = "proto-ipv6")]
+    fn update_ipv6(&mut self) {
+        #[cfg(feature = "slaac")]
+        let slaac_address = self.slaac.address();
+        #[cfg(not(feature = "slaac"))]
+        let slaac_address = None;
+
+        set_ipv6_addrs(
+            &mut self.iface,
+            &[self.link_local, self.ipv6_address, slaac_address],
+        );
+        #[cfg(feature = "medium-ethernet")]
+        self.update_ipv6_groups([self.link_local, self.ipv6_address, slaac_address]);
+
+        #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+        if has_hardware_address(self.iface.device().capabilities().medium) {
+            #[cfg(feature = "slaac")]
+            let slaac_router = self.slaac.router();
+            #[cfg(not(feature = "slaac"))]
+            let slaac_router = None;
+
+            match self.ipv6_gateway.or(slaac_router) {
+                Some(gateway) => {
+                    self.iface
+                        .routes_mut()
+                        .add_default_ipv6_route(gateway)
+                        .unwrap();
+                }
+                None => {
+                    self.iface.routes_mut().remove_default_ipv6_route();
+                }
+            }
+        }
+    }
+
+    /// Join the solicited-node multicast groups of the IPv6 addresses, so that neighbor
+    /// solicitations reach the stack.
+    #[cfg(all(feature = "proto-ipv6", feature = "medium-ethernet"))]
+    fn update_ipv6_groups(&mut self, cidrs: [Option<Ipv6Cidr>; 3]) {
+        let groups = cidrs.map(|cidr| cidr.map(|cidr| solicited_node_mac(cidr.address())));
+        let old = mem::replace(&mut self.ipv6_groups, groups);
+
+        if let Some(device) = self.ethernet_device() {
+            // Addresses often share their solicited-node group (the link-local and SLAAC ones
+            // do), only add and remove each group once.
+            for (n, mac) in old.iter().enumerate() {
+                if let Some(mac) = mac {
+                    if !old[..n].contains(&Some(*mac)) && !groups.contains(&Some(*mac)) {
+                        device.remove_multicast_address(*mac);
+                    }
+                }
+            }
+            for (n, mac) in groups.iter().enumerate() {
+                if let Some(mac) = mac {
+                    if !groups[..n].contains(&Some(*mac)) && !old.contains(&Some(*mac)) {
+                        device.add_multicast_address(*mac);
+                    }
+                }
             }
         }
     }
 
+    /// The device, if it filters frames by Ethernet multicast address.
+    #[cfg(feature = "medium-ethernet")]
+    fn ethernet_device(&mut self) -> Option<&mut D> {
+        let adapter = self.iface.device_mut();
+        if adapter.capabilities().medium == Medium::Ethernet {
+            Some(&mut *adapter.device)
+        } else {
+            None
+        }
+    }
+
     fn poll(&mut self, cx: &mut Context<'_>) {
         self.iface.device_mut().device.register_waker(cx.waker());
         self.waker.register(cx.waker());
@@ -142,16 +379,57 @@ impl Stack {
         let old_link_up = self.link_up;
         self.link_up = self.iface.device_mut().device.link_state() == LinkState::Up;
 
-        // Print when changed
         if old_link_up != self.link_up {
             info!("link_up = {:?}", self.link_up);
+            self.link_changes = self.link_changes.wrapping_add(1);
+            self.link_waker.wake();
+
+            #[cfg(feature = "tcp")]
+            if !self.link_up && self.link_down_policy == LinkDownPolicy::AbortTcp {
+                for (_, socket) in self.iface.sockets_mut() {
+                    if let Socket::Tcp(s) = socket {
+                        s.abort();
+                    }
+                }
+            }
+
+            if self.link_up {
+                // The configuration may not be valid on this link anymore, the configurator
+                // starts over.
+                self.configurator.detach(&mut self.iface);
+            } else if self.config_up {
+                self.apply_config_event(Event::Deconfigured);
+            }
         }
 
-        if old_link_up || self.link_up {
+        if self.link_up {
             self.poll_configurator(timestamp)
         }
 
-        if let Some(poll_at) = self.iface.poll_at(timestamp) {
+        #[cfg(feature = "slaac")]
+        if let Some(link_local) = self.link_local {
+            let changed = if self.link_up {
+                self.slaac
+                    .poll(&mut self.iface, link_local.address(), timestamp)
+            } else {
+                self.slaac.reset()
+            };
+            if changed {
+                self.update_ipv6();
+            }
+        }
+
+        // The configurator is only polled while the link is up.
+        let configurator_poll_at = if self.link_up {
+            self.configurator.poll_at()
+        } else {
+            None
+        };
+        let poll_at = match (self.iface.poll_at(timestamp), configurator_poll_at) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        if let Some(poll_at) = poll_at {
             let t = Timer::at(instant_from_smoltcp(poll_at));
             pin_mut!(t);
             if t.poll(cx).is_ready() {
@@ -161,82 +439,633 @@ impl Stack {
     }
 }
 
-fn set_ipv4_addr(iface: &mut Interface, cidr: Ipv4Cidr) {
+fn set_ipv4_addr<D: Device + 'static>(iface: &mut Interface<D>, cidr: Ipv4Cidr) {
     iface.update_ip_addrs(|addrs| {
         let dest = addrs.iter_mut().next().unwrap();
         *dest = IpCidr::Ipv4(cidr);
     });
 }
 
-/// Initialize embassy_net.
-/// This function must be called from thread mode.
-pub fn init<const ADDR: usize, const SOCK: usize, const NEIGH: usize>(
-    device: &'static mut dyn Device,
-    configurator: &'static mut dyn Configurator,
-    resources: &'static mut StackResources<ADDR, SOCK, NEIGH>,
-) {
-    #[cfg(feature = "medium-ethernet")]
-    let medium = device.capabilities().medium;
+#[cfg(feature = "proto-ipv6")]
+fn set_ipv6_addrs<D: Device + 'static>(iface: &mut Interface<D>, cidrs: &[Option<Ipv6Cidr>]) {
+    iface.update_ip_addrs(|addrs| {
+        // The first address is the IPv4 one.
+        let mut slots = addrs.iter_mut().skip(1);
+        for cidr in cidrs.iter().flatten() {
+            match slots.next() {
+                Some(slot) => *slot = IpCidr::Ipv6(*cidr),
+                None => warn!("No room for IPv6 address {}, increase ADDR", cidr),
+            }
+        }
+        for slot in slots {
+            *slot = IpCidr::new(Ipv4Address::UNSPECIFIED.into(), 32);
+        }
+    });
+}
 
-    #[cfg(feature = "medium-ethernet")]
-    let ethernet_addr = if medium == Medium::Ethernet {
-        device.ethernet_address()
-    } else {
-        [0, 0, 0, 0, 0, 0]
+/// Whether the medium has link-layer addresses, so the interface needs a neighbor cache
+/// and routes.
+#[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+fn has_hardware_address(medium: Medium) -> bool {
+    match medium {
+        #[cfg(feature = "medium-ethernet")]
+        Medium::Ethernet => true,
+        #[cfg(feature = "medium-ieee802154")]
+        Medium::Ieee802154 => true,
+        #[allow(unreachable_patterns)]
+        _ => false,
+    }
+}
+
+/// Ethernet multicast address of an IP multicast group (RFC 1112, RFC 2464).
+#[cfg(feature = "medium-ethernet")]
+fn multicast_mac(addr: IpAddress) -> Option<[u8; 6]> {
+    match addr {
+        IpAddress::Ipv4(addr) if addr.is_multicast() => {
+            let a = addr.0;
+            Some([0x01, 0x00, 0x5e, a[1] & 0x7f, a[2], a[3]])
+        }
+        #[cfg(feature = "proto-ipv6")]
+        IpAddress::Ipv6(addr) if addr.is_multicast() => {
+            let a = addr.0;
+            Some([0x33, 0x33, a[12], a[13], a[14], a[15]])
+        }
+        _ => None,
+    }
+}
+
+/// Ethernet multicast address of the solicited-node group of `addr` (RFC 4291).
+#[cfg(all(feature = "proto-ipv6", feature = "medium-ethernet"))]
+fn solicited_node_mac(addr: Ipv6Address) -> [u8; 6] {
+    let a = addr.0;
+    [0x33, 0x33, 0xff, a[13], a[14], a[15]]
+}
+
+/// Build the EUI-64 link-local address for a hardware address.
+#[cfg(all(
+    feature = "proto-ipv6",
+    any(feature = "medium-ethernet", feature = "medium-ieee802154")
+))]
+fn link_local_address(addr: HardwareAddress) -> Option<Ipv6Cidr> {
+    let eui64 = match addr {
+        #[cfg(feature = "medium-ethernet")]
+        HardwareAddress::Ethernet(EthernetAddress(mac)) => {
+            [mac[0], mac[1], mac[2], 0xff, 0xfe, mac[3], mac[4], mac[5]]
+        }
+        #[cfg(feature = "medium-ieee802154")]
+        HardwareAddress::Ieee802154(Ieee802154Address::Extended(eui64)) => eui64,
+        #[allow(unreachable_patterns)]
+        _ => return None,
     };
 
-    let mut b = InterfaceBuilder::new(DeviceAdapter::new(device), &mut resources.sockets[..]);
-    b = b.ip_addrs(&mut resources.addresses[..]);
+    let mut addr = [0; 16];
+    addr[0] = 0xfe;
+    addr[1] = 0x80;
+    addr[8..].copy_from_slice(&eui64);
+    // Flip the universal/local bit.
+    addr[8] ^= 0x02;
+    Some(Ipv6Cidr::new(Ipv6Address(addr), 64))
+}
+
+impl<D: Device + 'static, M: RawMutex> Stack<D, M> {
+    /// Create a new network stack.
+    pub fn new<
+        const ADDR: usize,
+        const SOCK: usize,
+        const NEIGH: usize,
+        const TCP: usize,
+        const TCP_BUF: usize,
+    >(
+        device: &'static mut D,
+        configurator: &'static mut (dyn Configurator<D> + Send),
+        resources: &'static mut StackResources<ADDR, SOCK, NEIGH, TCP, TCP_BUF>,
+    ) -> Self {
+        #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+        let medium = device.capabilities().medium;
 
-    #[cfg(feature = "medium-ethernet")]
-    if medium == Medium::Ethernet {
-        b = b.hardware_addr(HardwareAddress::Ethernet(EthernetAddress(ethernet_addr)));
-        b = b.neighbor_cache(NeighborCache::new(&mut resources.neighbor_cache[..]));
-        b = b.routes(Routes::new(&mut resources.routes[..]));
+        #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+        let hardware_addr = match medium {
+            #[cfg(feature = "medium-ethernet")]
+            Medium::Ethernet => Some(HardwareAddress::Ethernet(EthernetAddress(
+                device.ethernet_address(),
+            ))),
+            #[cfg(feature = "medium-ieee802154")]
+            Medium::Ieee802154 => Some(HardwareAddress::Ieee802154(Ieee802154Address::Extended(
+                device.ieee802154_address(),
+            ))),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        };
+        #[cfg(feature = "medium-ieee802154")]
+        let pan_id = if medium == Medium::Ieee802154 {
+            Some(device.pan_id())
+        } else {
+            None
+        };
+
+        let mut b = InterfaceBuilder::new(DeviceAdapter::new(device), &mut resources.sockets[..]);
+        b = b.ip_addrs(&mut resources.addresses[..]);
+
+        #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+        if let Some(hardware_addr) = hardware_addr {
+            b = b.hardware_addr(hardware_addr);
+            b = b.neighbor_cache(NeighborCache::new(&mut resources.neighbor_cache[..]));
+            b = b.routes(Routes::new(&mut resources.routes[..]));
+        }
+        #[cfg(feature = "medium-ieee802154")]
+        if let Some(pan_id) = pan_id {
+            b = b.pan_id(Ieee802154Pan(pan_id));
+        }
+        #[cfg(feature = "igmp")]
+        {
+            b = b.ipv4_multicast_groups(&mut resources.multicast_groups[..]);
+        }
+
+        let iface = b.finalize();
+
+        #[cfg(all(
+            feature = "proto-ipv6",
+            any(feature = "medium-ethernet", feature = "medium-ieee802154")
+        ))]
+        let link_local = hardware_addr.and_then(link_local_address);
+        #[cfg(all(
+            feature = "proto-ipv6",
+            not(any(feature = "medium-ethernet", feature = "medium-ieee802154"))
+        ))]
+        let link_local = None;
+
+        #[cfg(feature = "proto-ipv6")]
+        let mut iface = iface;
+        #[cfg(feature = "proto-ipv6")]
+        set_ipv6_addrs(&mut iface, &[link_local]);
+
+        #[cfg(feature = "slaac")]
+        let slaac = Slaac::new(iface.add_socket(RawSocket::new(
+            IpVersion::Ipv6,
+            IpProtocol::Icmpv6,
+            RawSocketBuffer::new(
+                &mut resources.slaac_rx_meta[..],
+                &mut resources.slaac_rx_buffer[..],
+            ),
+            RawSocketBuffer::new(
+                &mut resources.slaac_tx_meta[..],
+                &mut resources.slaac_tx_buffer[..],
+            ),
+        )));
+
+        #[cfg(feature = "tcp")]
+        let tcp_pool = TcpBufferPool::new(&mut resources.tcp_buffers[..]);
+
+        let local_port = loop {
+            let mut res = [0u8; 2];
+            rand(&mut res);
+            let port = u16::from_le_bytes(res);
+            if (LOCAL_PORT_MIN..=LOCAL_PORT_MAX).contains(&port) {
+                break port;
+            }
+        };
+
+        let inner = Inner {
+            iface,
+            link_up: false,
+            config: None,
+            link_changes: 0,
+            link_waker: WakerRegistration::new(),
+            #[cfg(feature = "tcp")]
+            link_down_policy: LinkDownPolicy::Keep,
+            config_up: false,
+            configurator,
+            next_local_port: local_port,
+            waker: WakerRegistration::new(),
+            socket_capacity: SOCK,
+            #[cfg(feature = "tcp")]
+            tcp_pool,
+            #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+            neighbor_capacity: if has_hardware_address(medium) {
+                NEIGH
+            } else {
+                0
+            },
+            config_waker: WakerRegistration::new(),
+            #[cfg(feature = "proto-ipv6")]
+            link_local,
+            #[cfg(feature = "proto-ipv6")]
+            ipv6_address: None,
+            #[cfg(feature = "proto-ipv6")]
+            ipv6_gateway: None,
+            #[cfg(all(feature = "proto-ipv6", feature = "medium-ethernet"))]
+            ipv6_groups: [None; 3],
+            #[cfg(feature = "slaac")]
+            slaac,
+        };
+
+        #[cfg(all(feature = "proto-ipv6", feature = "medium-ethernet"))]
+        let inner = {
+            let mut inner = inner;
+            // The all-nodes group, for router advertisements.
+            if let Some(device) = inner.ethernet_device() {
+                device.add_multicast_address([0x33, 0x33, 0x00, 0x00, 0x00, 0x01]);
+            }
+            inner.update_ipv6_groups([link_local, None, None]);
+            inner
+        };
+
+        Self {
+            inner: Mutex::new(RefCell::new(inner)),
+        }
+    }
+
+    pub(crate) fn with<R>(&self, f: impl FnOnce(&mut Inner<D>) -> R) -> R {
+        self.inner.lock(|i| f(&mut *i.borrow_mut()))
+    }
+
+    pub fn is_link_up(&self) -> bool {
+        self.with(|i| i.link_up)
+    }
+
+    /// Pass every frame received from and transmitted to the device to `trace`.
+    ///
+    /// `None` stops tracing. The callback runs with the stack locked, so keep
+    /// it short: log the frame, or copy it out to a queue.
+    #[cfg(feature = "packet-trace")]
+    pub fn set_packet_trace(&self, trace: Option<TraceFn>) {
+        self.with(|i| i.iface.device_mut().trace = trace)
+    }
+
+    /// Get the counters of the frames exchanged with the device.
+    ///
+    /// Compare them over time to see where throughput is lost: for example, a
+    /// growing `tx_no_buffer` means the device can't send frames as fast as
+    /// the stack produces them.
+    pub fn stats(&self) -> DeviceStats {
+        self.with(|i| i.iface.device().stats.get())
     }
 
-    let iface = b.finalize();
+    /// Reset the counters returned by [`stats`](Self::stats) to 0.
+    pub fn reset_stats(&self) {
+        self.with(|i| i.iface.device().stats.set(DeviceStats::default()))
+    }
 
-    let local_port = loop {
-        let mut res = [0u8; 2];
-        rand(&mut res);
-        let port = u16::from_le_bytes(res);
-        if (LOCAL_PORT_MIN..=LOCAL_PORT_MAX).contains(&port) {
-            break port;
+    /// Observe link up and down transitions.
+    ///
+    /// On link down the configuration is dropped, and on link up the
+    /// configurator starts over (DHCP sends a new discover, for example).
+    pub fn link_events(&self) -> LinkEvents<'_, D, M> {
+        LinkEvents {
+            stack: self,
+            seen: self.with(|i| i.link_changes),
         }
-    };
+    }
 
-    let stack = Stack {
-        iface,
-        link_up: false,
-        config_up: false,
-        configurator,
-        next_local_port: local_port,
-        waker: WakerRegistration::new(),
-    };
+    /// Choose what happens to TCP connections when the link goes down.
+    #[cfg(feature = "tcp")]
+    pub fn set_link_down_policy(&self, policy: LinkDownPolicy) {
+        self.with(|i| i.link_down_policy = policy)
+    }
+
+    pub fn is_config_up(&self) -> bool {
+        self.with(|i| i.config_up)
+    }
+
+    /// Get the number of sockets in use, out of the `SOCK` of the [`StackResources`].
+    ///
+    /// This includes the sockets used internally, by the DHCP configurator for example.
+    pub fn sockets_in_use(&self) -> usize {
+        self.with(|i| i.iface.sockets().count())
+    }
+
+    /// Get the number of sockets of the [`StackResources`].
+    pub fn socket_capacity(&self) -> usize {
+        self.with(|i| i.socket_capacity)
+    }
+
+    /// Get the number of entries of the neighbor cache, `NEIGHBOR` in the [`StackResources`].
+    ///
+    /// When the cache is full, the entry that expires first is evicted to
+    /// make room for the new one, so a cache too small for the number of
+    /// peers shows up as extra ARP traffic and latency rather than errors.
+    #[cfg(any(feature = "medium-ethernet", feature = "medium-ieee802154"))]
+    pub fn neighbor_cache_capacity(&self) -> usize {
+        self.with(|i| i.neighbor_capacity)
+    }
+
+    /// Get the current IP configuration, as applied from the configurator.
+    pub fn config(&self) -> Option<Config> {
+        self.with(|i| i.config.clone())
+    }
+
+    /// Get the DHCP lease the current configuration comes from, if any.
+    #[cfg(feature = "dhcpv4")]
+    pub fn dhcp_lease(&self) -> Option<DhcpLease> {
+        self.with(|i| {
+            if i.config_up {
+                i.configurator.dhcp_lease()
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Wait until the stack has an IP configuration.
+    pub async fn wait_config_up(&self) {
+        futures::future::poll_fn(|cx| {
+            self.with(|i| {
+                if i.config_up {
+                    Poll::Ready(())
+                } else {
+                    i.config_waker.register(cx.waker());
+                    Poll::Pending
+                }
+            })
+        })
+        .await
+    }
+
+    /// Replace the configurator at runtime, and return the previous one.
+    ///
+    /// The current configuration is dropped right away, and the new
+    /// configurator takes over on the next poll of the stack. This is how to
+    /// switch between DHCP and a static configuration, apply a new static
+    /// configuration (new DNS servers, for example), or restart DHCP from
+    /// scratch by passing the same `DhcpConfigurator` back in.
+    pub fn set_configurator(
+        &self,
+        configurator: &'static mut (dyn Configurator<D> + Send),
+    ) -> &'static mut (dyn Configurator<D> + Send) {
+        self.with(|i| i.set_configurator(configurator))
+    }
+
+    /// Replace the configurator like [`set_configurator`](Self::set_configurator),
+    /// then wait until the new one has configured the stack.
+    ///
+    /// The previous configurator is returned once the stack is configured. If
+    /// the future is dropped before that, it is lost, so use
+    /// `set_configurator` if you need it back in all cases.
+    pub async fn configure(
+        &self,
+        configurator: &'static mut (dyn Configurator<D> + Send),
+    ) -> &'static mut (dyn Configurator<D> + Send) {
+        let old = self.set_configurator(configurator);
+        self.wait_config_up().await;
+        old
+    }
+
+    /// Get the IPv6 link-local address, derived from the MAC address.
+    ///
+    /// Returns `None` if the device is not an Ethernet device.
+    #[cfg(feature = "proto-ipv6")]
+    pub fn link_local_address(&self) -> Option<Ipv6Cidr> {
+        self.with(|i| i.link_local)
+    }
+
+    /// Get the IPv6 address acquired through SLAAC, if any.
+    #[cfg(feature = "slaac")]
+    pub fn slaac_address(&self) -> Option<Ipv6Cidr> {
+        self.with(|i| i.slaac.address())
+    }
+
+    /// Join the multicast group `addr`, to receive the packets sent to it.
+    ///
+    /// The device is told to receive the frames of the group. IPv4 groups need the
+    /// `igmp` feature: joining sends an IGMP membership report so that routers and
+    /// snooping switches forward the group, and takes one of the group slots of the
+    /// [`StackResources`].
+    ///
+    /// The stack joins the IPv6 groups needed for neighbor discovery by itself.
+    #[cfg(any(feature = "igmp", feature = "medium-ethernet"))]
+    pub fn join_multicast_group(&self, addr: IpAddress) -> Result<(), MulticastError> {
+        if !addr.is_multicast() {
+            return Err(MulticastError::NotMulticast);
+        }
+
+        self.with(|i| {
+            if let IpAddress::Ipv4(_) = addr {
+                #[cfg(feature = "igmp")]
+                {
+                    let timestamp = instant_to_smoltcp(Instant::now());
+                    // This also fails if the report can't be sent right away, but the group is
+                    // joined anyway, and reported again on the next query.
+                    let _ = i.iface.join_multicast_group(addr, timestamp);
+                    if !i.iface.has_multicast_group(addr) {
+                        return Err(MulticastError::GroupTableFull);
+                    }
+                }
+                #[cfg(not(feature = "igmp"))]
+                return Err(MulticastError::Unsupported);
+            }
+
+            #[cfg(feature = "medium-ethernet")]
+            if let (Some(device), Some(mac)) = (i.ethernet_device(), multicast_mac(addr)) {
+                device.add_multicast_address(mac);
+            }
+            Ok(())
+        })
+    }
+
+    /// Leave the multicast group `addr`, joined with
+    /// [`join_multicast_group`](Self::join_multicast_group).
+    #[cfg(any(feature = "igmp", feature = "medium-ethernet"))]
+    pub fn leave_multicast_group(&self, addr: IpAddress) {
+        self.with(|i| {
+            #[cfg(feature = "igmp")]
+            if let IpAddress::Ipv4(_) = addr {
+                let timestamp = instant_to_smoltcp(Instant::now());
+                let _ = i.iface.leave_multicast_group(addr, timestamp);
+            }
+
+            #[cfg(feature = "medium-ethernet")]
+            if let (Some(device), Some(mac)) = (i.ethernet_device(), multicast_mac(addr)) {
+                device.remove_multicast_address(mac);
+            }
+        })
+    }
+
+    /// Run the network stack.
+    ///
+    /// This must be running for the stack and its sockets to make progress,
+    /// usually from a dedicated task per stack.
+    pub async fn run(&self) -> ! {
+        futures::future::poll_fn(|cx| {
+            self.with(|i| i.poll(cx));
+            Poll::<()>::Pending
+        })
+        .await;
+        unreachable!()
+    }
+}
+
+/// Errors when creating a socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SocketError {
+    /// All the sockets of the [`StackResources`] are in use.
+    NoFreeSocket,
+    /// All the TCP buffers of the [`StackResources`] are in use.
+    NoFreeBuffer,
+}
+
+/// Errors when joining a multicast group.
+#[cfg(any(feature = "igmp", feature = "medium-ethernet"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MulticastError {
+    /// The address is not a multicast address.
+    NotMulticast,
+    /// IPv4 multicast groups need the `igmp` feature.
+    Unsupported,
+    /// All the multicast group slots of the [`StackResources`] are in use.
+    GroupTableFull,
+}
+
+/// The TCP socket buffers of the [`StackResources`], handed out by the stack.
+#[cfg(feature = "tcp")]
+struct TcpBufferPool {
+    buffers: *mut u8,
+    buffer_len: usize,
+    count: usize,
+    in_use: u32,
+}
+
+// Safety: the buffers are only handed out with the stack's mutex held.
+#[cfg(feature = "tcp")]
+unsafe impl Send for TcpBufferPool {}
+
+#[cfg(feature = "tcp")]
+impl TcpBufferPool {
+    fn new<const N: usize>(buffers: &'static mut [[[u8; N]; 2]]) -> Self {
+        assert!(buffers.len() <= 32, "At most 32 TCP buffers are supported");
+        Self {
+            buffers: buffers.as_mut_ptr() as *mut u8,
+            buffer_len: N,
+            count: buffers.len(),
+            in_use: 0,
+        }
+    }
+
+    /// Take a free pair of buffers. Returns the slot to free them with, and the rx and tx buffers.
+    fn alloc(&mut self) -> Option<(usize, &'static mut [u8], &'static mut [u8])> {
+        let slot = (0..self.count).find(|slot| self.in_use & (1 << slot) == 0)?;
+        self.in_use |= 1 << slot;
 
-    *STACK.borrow().borrow_mut() = Some(stack);
+        // Safety: the slot was free, so nothing else borrows its buffers until it's freed.
+        unsafe {
+            let rx = self.buffers.add(slot * 2 * self.buffer_len);
+            let tx = rx.add(self.buffer_len);
+            Some((
+                slot,
+                core::slice::from_raw_parts_mut(rx, self.buffer_len),
+                core::slice::from_raw_parts_mut(tx, self.buffer_len),
+            ))
+        }
+    }
+
+    /// Give back the buffers of `slot`. The socket using them must be removed first.
+    fn free(&mut self, slot: usize) {
+        self.in_use &= !(1 << slot);
+    }
 }
 
-pub fn is_init() -> bool {
-    STACK.borrow().borrow().is_some()
+/// What to do with TCP connections when the link goes down.
+#[cfg(feature = "tcp")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LinkDownPolicy {
+    /// Leave connections alone. They resume when the link comes back up,
+    /// unless they time out or the address changes in the meantime.
+    Keep,
+    /// Abort all TCP connections, so pending reads and writes fail right away.
+    AbortTcp,
 }
 
-pub fn is_link_up() -> bool {
-    STACK.borrow().borrow().as_ref().unwrap().link_up
+/// Stream of link state changes, created with [`Stack::link_events`].
+pub struct LinkEvents<'a, D: Device + 'static, M: RawMutex> {
+    stack: &'a Stack<D, M>,
+    seen: u32,
+}
+
+impl<'a, D: Device + 'static, M: RawMutex> LinkEvents<'a, D, M> {
+    /// Wait for the link state to change, and return the new state.
+    ///
+    /// If the link bounces several times between two calls, only the latest
+    /// state is returned. Only one task can wait on the events of a stack at a
+    /// time.
+    pub async fn next(&mut self) -> LinkState {
+        futures::future::poll_fn(|cx| {
+            self.stack.with(|i| {
+                if i.link_changes != self.seen {
+                    self.seen = i.link_changes;
+                    Poll::Ready(match i.link_up {
+                        true => LinkState::Up,
+                        false => LinkState::Down,
+                    })
+                } else {
+                    i.link_waker.register(cx.waker());
+                    Poll::Pending
+                }
+            })
+        })
+        .await
+    }
 }
 
-pub fn is_config_up() -> bool {
-    STACK.borrow().borrow().as_ref().unwrap().config_up
+/// Type-erased access to the sockets of a [`Stack`], so that socket types
+/// don't have to be generic over the device and the mutex.
+pub(crate) trait SocketStack<T> {
+    fn add_socket(&self, socket: T) -> Result<SocketHandle, SocketError>;
+    fn remove_socket(&self, handle: SocketHandle);
+    fn with_socket(
+        &self,
+        handle: SocketHandle,
+        f: &mut dyn FnMut(&mut T, &mut SmolContext<'static>),
+    );
+    fn get_local_port(&self) -> u16;
+    #[cfg(feature = "tcp")]
+    fn alloc_tcp_buffers(&self) -> Option<(usize, &'static mut [u8], &'static mut [u8])>;
+    #[cfg(feature = "tcp")]
+    fn free_tcp_buffers(&self, slot: usize);
 }
 
-pub async fn run() -> ! {
-    futures::future::poll_fn(|cx| {
-        Stack::with(|stack| stack.poll(cx));
-        Poll::<()>::Pending
-    })
-    .await;
-    unreachable!()
+impl<D: Device + 'static, M: RawMutex, T: AnySocket<'static>> SocketStack<T> for Stack<D, M> {
+    fn add_socket(&self, socket: T) -> Result<SocketHandle, SocketError> {
+        self.with(|i| {
+            if i.iface.sockets().count() >= i.socket_capacity {
+                return Err(SocketError::NoFreeSocket);
+            }
+            Ok(i.iface.add_socket(socket))
+        })
+    }
+
+    fn remove_socket(&self, handle: SocketHandle) {
+        self.with(|i| {
+            i.iface.remove_socket(handle);
+        })
+    }
+
+    fn with_socket(
+        &self,
+        handle: SocketHandle,
+        f: &mut dyn FnMut(&mut T, &mut SmolContext<'static>),
+    ) {
+        self.with(|i| {
+            let (s, cx) = i.iface.get_socket_and_context::<T>(handle);
+            f(s, cx);
+            i.wake();
+        })
+    }
+
+    fn get_local_port(&self) -> u16 {
+        self.with(|i| i.get_local_port())
+    }
+
+    #[cfg(feature = "tcp")]
+    fn alloc_tcp_buffers(&self) -> Option<(usize, &'static mut [u8], &'static mut [u8])> {
+        self.with(|i| i.tcp_pool.alloc())
+    }
+
+    #[cfg(feature = "tcp")]
+    fn free_tcp_buffers(&self, slot: usize) {
+        self.with(|i| i.tcp_pool.free(slot))
+    }
 }
 
 fn instant_to_smoltcp(instant: Instant) -> SmolInstant {
@@ -251,6 +1080,150 @@ extern "Rust" {
     fn _embassy_rand(buf: &mut [u8]);
 }
 
-fn rand(buf: &mut [u8]) {
+pub(crate) fn rand(buf: &mut [u8]) {
     unsafe { _embassy_rand(buf) }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    extern crate std;
+    use embassy::blocking_mutex::raw::NoopRawMutex;
+    use futures_test::task::noop_context;
+    use smoltcp::phy::DeviceCapabilities;
+    use std::boxed::Box;
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    use crate::config::StaticConfigurator;
+    use crate::device;
+
+    #[no_mangle]
+    fn _embassy_rand(buf: &mut [u8]) {
+        buf.fill(0x5a);
+    }
+
+    /// Device receiving the frames it sends.
+    struct Loopback {
+        queue: VecDeque<Vec<u8>>,
+        link: LinkState,
+    }
+
+    struct LoopRx(Vec<u8>);
+
+    impl device::RxToken for LoopRx {
+        fn consume<R, F>(mut self, f: F) -> R
+        where
+            F: FnOnce(&mut [u8]) -> R,
+        {
+            f(&mut self.0)
+        }
+    }
+
+    struct LoopTx<'a>(&'a mut VecDeque<Vec<u8>>);
+
+    impl<'a> device::TxToken for LoopTx<'a> {
+        fn consume<R, F>(self, len: usize, f: F) -> R
+        where
+            F: FnOnce(&mut [u8]) -> R,
+        {
+            let mut frame = Vec::new();
+            frame.resize(len, 0);
+            let r = f(&mut frame);
+            self.0.push_back(frame);
+            r
+        }
+    }
+
+    impl Device for Loopback {
+        type RxToken<'a>
+            = LoopRx
+        where
+            Self: 'a;
+        type TxToken<'a>
+            = LoopTx<'a>
+        where
+            Self: 'a;
+
+        fn receive(&mut self) -> Option<(LoopRx, LoopTx<'_>)> {
+            let frame = self.queue.pop_front()?;
+            Some((LoopRx(frame), LoopTx(&mut self.queue)))
+        }
+
+        fn transmit(&mut self) -> Option<LoopTx<'_>> {
+            Some(LoopTx(&mut self.queue))
+        }
+
+        fn register_waker(&mut self, _waker: &core::task::Waker) {}
+
+        fn capabilities(&mut self) -> DeviceCapabilities {
+            let mut caps = DeviceCapabilities::default();
+            caps.max_transmission_unit = 1514;
+            caps
+        }
+
+        fn link_state(&mut self) -> LinkState {
+            self.link
+        }
+
+        fn ethernet_address(&mut self) -> [u8; 6] {
+            [0x02, 0, 0, 0, 0, 1]
+        }
+    }
+
+    fn set_link(stack: &Stack<Loopback, NoopRawMutex>, link: LinkState) {
+        stack.with(|i| {
+            i.iface.device_mut().device.link = link;
+            i.poll(&mut noop_context());
+        });
+    }
+
+    fn ipv4_addr(stack: &Stack<Loopback, NoopRawMutex>) -> IpCidr {
+        stack.with(|i| i.iface.ip_addrs()[0])
+    }
+
+    #[test]
+    fn link_bounce_restarts_configuration() {
+        let config = Config {
+            address: Ipv4Cidr::new(Ipv4Address::new(10, 0, 0, 2), 24),
+            gateway: Some(Ipv4Address::new(10, 0, 0, 1)),
+            dns_servers: heapless::Vec::new(),
+            #[cfg(feature = "proto-ipv6")]
+            ipv6_address: None,
+            #[cfg(feature = "proto-ipv6")]
+            ipv6_gateway: None,
+        };
+        let device = Box::leak(Box::new(Loopback {
+            queue: VecDeque::new(),
+            link: LinkState::Down,
+        }));
+        let configurator = Box::leak(Box::new(StaticConfigurator::new(config.clone())));
+        let resources = Box::leak(Box::new(StackResources::<4, 2, 4>::new()));
+        let stack = Stack::<_, NoopRawMutex>::new(device, configurator, resources);
+
+        // Nothing is configured before the link is up.
+        set_link(&stack, LinkState::Down);
+        assert!(!stack.is_config_up());
+
+        set_link(&stack, LinkState::Up);
+        assert!(stack.is_config_up());
+        assert_eq!(stack.config(), Some(config.clone()));
+        assert_eq!(ipv4_addr(&stack), IpCidr::Ipv4(config.address));
+
+        // The static configuration is dropped on link down too.
+        set_link(&stack, LinkState::Down);
+        assert!(!stack.is_config_up());
+        assert_eq!(stack.config(), None);
+        assert_eq!(
+            ipv4_addr(&stack),
+            IpCidr::Ipv4(Ipv4Cidr::new(Ipv4Address::UNSPECIFIED, 0))
+        );
+
+        // And applied again on link up.
+        set_link(&stack, LinkState::Up);
+        assert!(stack.is_config_up());
+        assert_eq!(stack.config(), Some(config));
+        assert_eq!(stack.with(|i| i.link_changes), 3);
+    }
+}
@@ -2,6 +2,7 @@ use core::marker::PhantomData;
 use core::mem;
 use core::pin::Pin;
 use core::task::{Context, Poll};
+use embassy::blocking_mutex::raw::RawMutex;
 use embassy::io;
 use embassy::io::{AsyncBufRead, AsyncWrite};
 use smoltcp::iface::{Context as SmolContext, SocketHandle};
@@ -10,38 +11,93 @@ use smoltcp::socket::{TcpSocketBuffer, TcpState};
 use smoltcp::time::Duration;
 use smoltcp::wire::IpEndpoint;
 
-use super::stack::Stack;
+use super::stack::{SocketError, SocketStack, Stack};
+use crate::device::Device;
 use crate::{Error, Result};
 
 pub struct TcpSocket<'a> {
+    stack: &'a dyn SocketStack<SyncTcpSocket<'static>>,
     handle: SocketHandle,
+    pool_slot: Option<usize>,
     ghost: PhantomData<&'a mut [u8]>,
 }
 
 impl<'a> Unpin for TcpSocket<'a> {}
 
 impl<'a> TcpSocket<'a> {
-    pub fn new(rx_buffer: &'a mut [u8], tx_buffer: &'a mut [u8]) -> Self {
-        let handle = Stack::with(|stack| {
-            let rx_buffer: &'static mut [u8] = unsafe { mem::transmute(rx_buffer) };
-            let tx_buffer: &'static mut [u8] = unsafe { mem::transmute(tx_buffer) };
-            stack.iface.add_socket(SyncTcpSocket::new(
+    pub fn new<D: Device + 'static, M: RawMutex>(
+        stack: &'a Stack<D, M>,
+        rx_buffer: &'a mut [u8],
+        tx_buffer: &'a mut [u8],
+    ) -> Self {
+        match Self::try_new(stack, rx_buffer, tx_buffer) {
+            Ok(socket) => socket,
+            Err(_) => panic!("No free socket, increase SOCK in StackResources"),
+        }
+    }
+
+    /// Like [`new`](Self::new), but fails with [`SocketError::NoFreeSocket`]
+    /// instead of panicking when all the sockets of the stack are in use.
+    pub fn try_new<D: Device + 'static, M: RawMutex>(
+        stack: &'a Stack<D, M>,
+        rx_buffer: &'a mut [u8],
+        tx_buffer: &'a mut [u8],
+    ) -> core::result::Result<Self, SocketError> {
+        let rx_buffer: &'static mut [u8] = unsafe { mem::transmute(rx_buffer) };
+        let tx_buffer: &'static mut [u8] = unsafe { mem::transmute(tx_buffer) };
+        let handle = SocketStack::add_socket(
+            stack,
+            SyncTcpSocket::new(
                 TcpSocketBuffer::new(rx_buffer),
                 TcpSocketBuffer::new(tx_buffer),
-            ))
-        });
+            ),
+        )?;
 
-        Self {
+        Ok(Self {
+            stack,
             handle,
+            pool_slot: None,
             ghost: PhantomData,
-        }
+        })
+    }
+
+    /// Create a socket with buffers taken from the `TCP` pool of the [`StackResources`].
+    ///
+    /// The buffers go back to the pool when the socket is dropped. With a
+    /// `&'static Stack`, this gives a `TcpSocket<'static>` that can be moved
+    /// into a spawned task, for example one task per accepted connection.
+    ///
+    /// [`StackResources`]: crate::StackResources
+    pub fn from_pool<D: Device + 'static, M: RawMutex>(
+        stack: &'a Stack<D, M>,
+    ) -> core::result::Result<Self, SocketError> {
+        let stack: &'a dyn SocketStack<SyncTcpSocket<'static>> = stack;
+        let (slot, rx_buffer, tx_buffer) =
+            stack.alloc_tcp_buffers().ok_or(SocketError::NoFreeBuffer)?;
+        let handle = match stack.add_socket(SyncTcpSocket::new(
+            TcpSocketBuffer::new(rx_buffer),
+            TcpSocketBuffer::new(tx_buffer),
+        )) {
+            Ok(handle) => handle,
+            Err(e) => {
+                stack.free_tcp_buffers(slot);
+                return Err(e);
+            }
+        };
+
+        Ok(Self {
+            stack,
+            handle,
+            pool_slot: Some(slot),
+            ghost: PhantomData,
+        })
     }
 
     pub async fn connect<T>(&mut self, remote_endpoint: T) -> Result<()>
     where
         T: Into<IpEndpoint>,
     {
-        let local_port = Stack::with(|stack| stack.get_local_port());
+        let local_port = self.stack.get_local_port();
         self.with(|s, cx| s.connect(cx, remote_endpoint, local_port))?;
 
         futures::future::poll_fn(|cx| {
@@ -78,14 +134,99 @@ impl<'a> TcpSocket<'a> {
         .await
     }
 
+    /// Listen on `local_endpoint` and wait for a remote peer to connect.
+    ///
+    /// Returns once the connection is established. The socket then behaves like
+    /// one returned by [`connect`](Self::connect). To serve more than one client
+    /// at a time, create one socket per client and call `accept` on each of them
+    /// with the same port.
+    pub async fn accept<T>(&mut self, local_endpoint: T) -> Result<()>
+    where
+        T: Into<IpEndpoint>,
+    {
+        self.with(|s, _| s.listen(local_endpoint))?;
+
+        futures::future::poll_fn(|cx| {
+            self.with(|s, _| match s.state() {
+                TcpState::Closed | TcpState::TimeWait => Poll::Ready(Err(Error::Unaddressable)),
+                TcpState::Listen | TcpState::SynSent | TcpState::SynReceived => {
+                    s.register_send_waker(cx.waker());
+                    Poll::Pending
+                }
+                _ => Poll::Ready(Ok(())),
+            })
+        })
+        .await
+    }
+
+    /// Set the timeout after which the connection is aborted if the peer
+    /// doesn't acknowledge sent data. `None` disables the timeout.
     pub fn set_timeout(&mut self, duration: Option<Duration>) {
         self.with(|s, _| s.set_timeout(duration))
     }
 
+    /// Send a keep-alive packet after `interval` of inactivity. `None` disables keep-alive.
+    ///
+    /// Combine it with [`set_timeout`](Self::set_timeout) to detect dead peers
+    /// on otherwise idle connections.
     pub fn set_keep_alive(&mut self, interval: Option<Duration>) {
         self.with(|s, _| s.set_keep_alive(interval))
     }
 
+    /// Enable or disable Nagle's algorithm. It's enabled by default.
+    ///
+    /// With Nagle, small writes are held back while previously sent data is
+    /// unacknowledged, to be sent together in a full segment. Disable it for
+    /// request/response protocols where each small write must go out at once.
+    pub fn set_nagle_enabled(&mut self, enabled: bool) {
+        self.with(|s, _| s.set_nagle_enabled(enabled))
+    }
+
+    pub fn nagle_enabled(&self) -> bool {
+        self.with(|s, _| s.nagle_enabled())
+    }
+
+    /// Set how long an ACK can be delayed, to be sent along with data or to
+    /// acknowledge several segments at once. `None` acknowledges every
+    /// segment immediately. The default is 10ms.
+    ///
+    /// Delayed ACKs interact badly with Nagle on the peer's side, stalling
+    /// each small write until the delay expires: if the throughput of a bulk
+    /// transfer is poor, try disabling them.
+    pub fn set_ack_delay(&mut self, duration: Option<Duration>) {
+        self.with(|s, _| s.set_ack_delay(duration))
+    }
+
+    pub fn ack_delay(&self) -> Option<Duration> {
+        self.with(|s, _| s.ack_delay())
+    }
+
+    /// Get the size of the receive buffer, which is the largest window
+    /// advertised to the peer.
+    ///
+    /// The window is what bounds throughput on a link with latency: at most one
+    /// window of data can be in flight per round trip. Window scaling is used
+    /// for buffers larger than 64kB. The size is set by the `rx_buffer` given
+    /// when creating the socket.
+    pub fn recv_capacity(&self) -> usize {
+        self.with(|s, _| s.recv_capacity())
+    }
+
+    /// Get the size of the transmit buffer.
+    pub fn send_capacity(&self) -> usize {
+        self.with(|s, _| s.send_capacity())
+    }
+
+    /// Get the number of bytes received and not read yet.
+    pub fn recv_queue(&self) -> usize {
+        self.with(|s, _| s.recv_queue())
+    }
+
+    /// Get the number of bytes written and not acknowledged by the peer yet.
+    pub fn send_queue(&self) -> usize {
+        self.with(|s, _| s.send_queue())
+    }
+
     pub fn set_hop_limit(&mut self, hop_limit: Option<u8>) {
         self.with(|s, _| s.set_hop_limit(hop_limit))
     }
@@ -102,34 +243,59 @@ impl<'a> TcpSocket<'a> {
         self.with(|s, _| s.state())
     }
 
+    /// Gracefully close the sending half of the connection.
+    ///
+    /// Data already written is still sent, followed by a FIN. The socket can
+    /// keep receiving until the peer closes its half too.
     pub fn close(&mut self) {
         self.with(|s, _| s.close())
     }
 
+    /// Immediately abort the connection, discarding any unsent data and
+    /// sending a RST to the peer.
     pub fn abort(&mut self) {
         self.with(|s, _| s.abort())
     }
 
+    /// Returns true if the connection is open in the sending direction.
     pub fn may_send(&self) -> bool {
         self.with(|s, _| s.may_send())
     }
 
+    /// Returns true if the connection is open in the receiving direction.
     pub fn may_recv(&self) -> bool {
         self.with(|s, _| s.may_recv())
     }
 
-    fn with<R>(&self, f: impl FnOnce(&mut SyncTcpSocket, &mut SmolContext) -> R) -> R {
-        Stack::with(|stack| {
-            let res = {
-                let (s, cx) = stack
-                    .iface
-                    .get_socket_and_context::<SyncTcpSocket>(self.handle);
-                f(s, cx)
-            };
-            stack.wake();
-            res
+    /// Wait until all data written so far has been acknowledged by the peer.
+    ///
+    /// Fails if the connection is closed before that.
+    pub async fn flush(&mut self) -> Result<()> {
+        futures::future::poll_fn(|cx| self.poll_flush_acked(cx)).await
+    }
+
+    fn poll_flush_acked(&self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        self.with(|s, _| {
+            if s.send_queue() == 0 {
+                Poll::Ready(Ok(()))
+            } else if !s.is_active() {
+                Poll::Ready(Err(Error::Illegal))
+            } else {
+                // smoltcp wakes the send waker when ACKs free up room in the tx buffer.
+                s.register_send_waker(cx.waker());
+                Poll::Pending
+            }
         })
     }
+
+    fn with<R>(&self, f: impl FnOnce(&mut SyncTcpSocket, &mut SmolContext) -> R) -> R {
+        let mut f = Some(f);
+        let mut res = None;
+        self.stack.with_socket(self.handle, &mut |s, cx| {
+            res = Some(unwrap!(f.take())(s, cx));
+        });
+        unwrap!(res)
+    }
 }
 
 fn to_ioerr(_err: Error) -> io::Error {
@@ -139,9 +305,10 @@ fn to_ioerr(_err: Error) -> io::Error {
 
 impl<'a> Drop for TcpSocket<'a> {
     fn drop(&mut self) {
-        Stack::with(|stack| {
-            stack.iface.remove_socket(self.handle);
-        })
+        self.stack.remove_socket(self.handle);
+        if let Some(slot) = self.pool_slot {
+            self.stack.free_tcp_buffers(slot);
+        }
     }
 }
 
@@ -201,7 +368,7 @@ impl<'a> AsyncWrite for TcpSocket<'a> {
         })
     }
 
-    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
-        Poll::Ready(Ok(())) // TODO: Is there a better implementation for this?
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_flush_acked(cx).map_err(to_ioerr)
     }
 }
@@ -0,0 +1,147 @@
+use core::future::Future;
+use core::pin::Pin;
+use core::task::Poll;
+use embassy::io::{AsyncBufRead, AsyncWrite};
+use embedded_io::ErrorKind;
+use embedded_tls::{Aes128GcmSha256, NoClock, TlsConfig, TlsConnection, TlsContext, TlsError};
+use futures::future::poll_fn;
+use rand_core::{CryptoRng, RngCore};
+
+use crate::tcp_socket::TcpSocket;
+
+/// A TLS 1.3 client connection over a [`TcpSocket`].
+///
+/// The record buffer passed to [`new`](Self::new) holds a full TLS record,
+/// so it should be 16640 bytes to talk to any server. Smaller buffers work
+/// with servers that support the maximum fragment length extension, or that
+/// never send large records.
+///
+/// Entropy for the handshake comes from the `_embassy_rand` hook. The server
+/// certificate is not verified.
+pub struct TlsSocket<'a> {
+    conn: TlsConnection<'a, TcpAdapter<'a>, Aes128GcmSha256>,
+}
+
+impl<'a> TlsSocket<'a> {
+    /// Wrap an already connected `socket`.
+    pub fn new(socket: TcpSocket<'a>, record_buffer: &'a mut [u8]) -> Self {
+        Self {
+            conn: TlsConnection::new(TcpAdapter(socket), record_buffer),
+        }
+    }
+
+    /// Perform the TLS handshake.
+    ///
+    /// `server_name` is sent in the SNI extension, which most servers hosting
+    /// several domains require.
+    pub async fn open(&mut self, server_name: Option<&str>) -> Result<(), TlsError> {
+        let mut config = TlsConfig::new();
+        if let Some(server_name) = server_name {
+            config = config.with_server_name(server_name);
+        }
+        let mut rng = EmbassyRng;
+        self.conn
+            .open::<_, NoClock, 1>(TlsContext::new(&config, &mut rng))
+            .await
+    }
+
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize, TlsError> {
+        self.conn.read(buf).await
+    }
+
+    pub async fn write(&mut self, buf: &[u8]) -> Result<usize, TlsError> {
+        self.conn.write(buf).await
+    }
+
+    /// Send the pending data as a TLS record.
+    pub async fn flush(&mut self) -> Result<(), TlsError> {
+        self.conn.flush().await
+    }
+
+    /// Send a close notification, and return the underlying socket.
+    pub async fn close(self) -> Result<TcpSocket<'a>, (TcpSocket<'a>, TlsError)> {
+        match self.conn.close().await {
+            Ok(adapter) => Ok(adapter.0),
+            Err((adapter, e)) => Err((adapter.0, e)),
+        }
+    }
+}
+
+/// `embedded-io` adapter for the socket, as embedded-tls expects.
+struct TcpAdapter<'a>(TcpSocket<'a>);
+
+impl<'a> embedded_io::Io for TcpAdapter<'a> {
+    type Error = ErrorKind;
+}
+
+impl<'a> embedded_io::asynch::Read for TcpAdapter<'a> {
+    type ReadFuture<'m> = impl Future<Output = Result<usize, ErrorKind>> + 'm where Self: 'm;
+
+    fn read<'m>(&'m mut self, buf: &'m mut [u8]) -> Self::ReadFuture<'m> {
+        async move {
+            let n = poll_fn(|cx| match Pin::new(&mut self.0).poll_fill_buf(cx) {
+                Poll::Ready(Ok(data)) => {
+                    let n = data.len().min(buf.len());
+                    buf[..n].copy_from_slice(&data[..n]);
+                    Poll::Ready(Ok(n))
+                }
+                Poll::Ready(Err(_)) => Poll::Ready(Err(ErrorKind::Other)),
+                Poll::Pending => Poll::Pending,
+            })
+            .await?;
+            Pin::new(&mut self.0).consume(n);
+            Ok(n)
+        }
+    }
+}
+
+impl<'a> embedded_io::asynch::Write for TcpAdapter<'a> {
+    type WriteFuture<'m> = impl Future<Output = Result<usize, ErrorKind>> + 'm where Self: 'm;
+
+    fn write<'m>(&'m mut self, buf: &'m [u8]) -> Self::WriteFuture<'m> {
+        async move {
+            poll_fn(|cx| Pin::new(&mut self.0).poll_write(cx, buf))
+                .await
+                .map_err(|_| ErrorKind::Other)
+        }
+    }
+
+    type FlushFuture<'m> = impl Future<Output = Result<(), ErrorKind>> + 'm where Self: 'm;
+
+    fn flush<'m>(&'m mut self) -> Self::FlushFuture<'m> {
+        async move {
+            poll_fn(|cx| Pin::new(&mut self.0).poll_flush(cx))
+                .await
+                .map_err(|_| ErrorKind::Other)
+        }
+    }
+}
+
+/// Random number generator backed by the `_embassy_rand` hook.
+struct EmbassyRng;
+
+impl RngCore for EmbassyRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut buf = [0; 4];
+        self.fill_bytes(&mut buf);
+        u32::from_le_bytes(buf)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut buf = [0; 8];
+        self.fill_bytes(&mut buf);
+        u64::from_le_bytes(buf)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        crate::stack::rand(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+// `_embassy_rand` must be backed by a cryptographically secure source.
+impl CryptoRng for EmbassyRng {}
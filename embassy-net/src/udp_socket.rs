@@ -0,0 +1,146 @@
+use core::marker::PhantomData;
+use core::mem;
+use core::task::Poll;
+use embassy::blocking_mutex::raw::RawMutex;
+use smoltcp::iface::{Context as SmolContext, SocketHandle};
+use smoltcp::socket::UdpSocket as SyncUdpSocket;
+use smoltcp::socket::{UdpPacketMetadata, UdpSocketBuffer};
+use smoltcp::wire::IpEndpoint;
+
+use super::stack::{SocketError, SocketStack, Stack};
+use crate::device::Device;
+use crate::{Error, Result};
+
+pub struct UdpSocket<'a> {
+    stack: &'a dyn SocketStack<SyncUdpSocket<'static>>,
+    handle: SocketHandle,
+    ghost: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> Unpin for UdpSocket<'a> {}
+
+impl<'a> UdpSocket<'a> {
+    pub fn new<D: Device + 'static, M: RawMutex>(
+        stack: &'a Stack<D, M>,
+        rx_meta: &'a mut [UdpPacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [UdpPacketMetadata],
+        tx_buffer: &'a mut [u8],
+    ) -> Self {
+        match Self::try_new(stack, rx_meta, rx_buffer, tx_meta, tx_buffer) {
+            Ok(socket) => socket,
+            Err(_) => panic!("No free socket, increase SOCK in StackResources"),
+        }
+    }
+
+    /// Like [`new`](Self::new), but fails with [`SocketError::NoFreeSocket`]
+    /// instead of panicking when all the sockets of the stack are in use.
+    pub fn try_new<D: Device + 'static, M: RawMutex>(
+        stack: &'a Stack<D, M>,
+        rx_meta: &'a mut [UdpPacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [UdpPacketMetadata],
+        tx_buffer: &'a mut [u8],
+    ) -> core::result::Result<Self, SocketError> {
+        let rx_meta: &'static mut [UdpPacketMetadata] = unsafe { mem::transmute(rx_meta) };
+        let rx_buffer: &'static mut [u8] = unsafe { mem::transmute(rx_buffer) };
+        let tx_meta: &'static mut [UdpPacketMetadata] = unsafe { mem::transmute(tx_meta) };
+        let tx_buffer: &'static mut [u8] = unsafe { mem::transmute(tx_buffer) };
+        let handle = SocketStack::add_socket(
+            stack,
+            SyncUdpSocket::new(
+                UdpSocketBuffer::new(rx_meta, rx_buffer),
+                UdpSocketBuffer::new(tx_meta, tx_buffer),
+            ),
+        )?;
+
+        Ok(Self {
+            stack,
+            handle,
+            ghost: PhantomData,
+        })
+    }
+
+    pub fn bind<T>(&mut self, endpoint: T) -> Result<()>
+    where
+        T: Into<IpEndpoint>,
+    {
+        let mut endpoint = endpoint.into();
+        if endpoint.port == 0 {
+            endpoint.port = self.stack.get_local_port();
+        }
+        self.with(|s, _| s.bind(endpoint))
+    }
+
+    /// Send a datagram to `remote_endpoint`, waiting for room in the tx buffer if needed.
+    pub async fn send_to<T>(&mut self, buf: &[u8], remote_endpoint: T) -> Result<()>
+    where
+        T: Into<IpEndpoint>,
+    {
+        let remote_endpoint = remote_endpoint.into();
+        futures::future::poll_fn(|cx| {
+            self.with(|s, _| match s.send_slice(buf, remote_endpoint) {
+                // No space in the tx buffer
+                Err(Error::Exhausted) => {
+                    s.register_send_waker(cx.waker());
+                    Poll::Pending
+                }
+                r => Poll::Ready(r),
+            })
+        })
+        .await
+    }
+
+    /// Receive a datagram into `buf`.
+    ///
+    /// Returns the number of bytes received and the endpoint it came from.
+    /// Datagrams longer than `buf` are truncated.
+    pub async fn recv_from(&mut self, buf: &mut [u8]) -> Result<(usize, IpEndpoint)> {
+        futures::future::poll_fn(|cx| {
+            self.with(|s, _| match s.recv_slice(buf) {
+                // No datagram ready
+                Err(Error::Exhausted) => {
+                    s.register_recv_waker(cx.waker());
+                    Poll::Pending
+                }
+                r => Poll::Ready(r),
+            })
+        })
+        .await
+    }
+
+    pub fn endpoint(&self) -> IpEndpoint {
+        self.with(|s, _| s.endpoint())
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.with(|s, _| s.is_open())
+    }
+
+    pub fn close(&mut self) {
+        self.with(|s, _| s.close())
+    }
+
+    pub fn may_send(&self) -> bool {
+        self.with(|s, _| s.can_send())
+    }
+
+    pub fn may_recv(&self) -> bool {
+        self.with(|s, _| s.can_recv())
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&mut SyncUdpSocket, &mut SmolContext) -> R) -> R {
+        let mut f = Some(f);
+        let mut res = None;
+        self.stack.with_socket(self.handle, &mut |s, cx| {
+            res = Some(unwrap!(f.take())(s, cx));
+        });
+        unwrap!(res)
+    }
+}
+
+impl<'a> Drop for UdpSocket<'a> {
+    fn drop(&mut self) {
+        self.stack.remove_socket(self.handle)
+    }
+}
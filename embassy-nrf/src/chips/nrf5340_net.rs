@@ -112,6 +112,9 @@ embassy_hal_common::peripherals! {
     // WDT
     WDT,
 
+    // TEMP
+    TEMP,
+
     // UARTE, TWI & SPI
     UARTETWISPI0,
     UARTETWISPI1,
@@ -142,6 +145,9 @@ embassy_hal_common::peripherals! {
     GPIOTE_CH6,
     GPIOTE_CH7,
 
+    // EGU
+    EGU0,
+
     // PPI
     PPI_CH0,
     PPI_CH1,
@@ -238,11 +244,15 @@ embassy_hal_common::peripherals! {
 
 impl_uarte!(UARTETWISPI0, UARTE0, SERIAL0);
 impl_spim!(UARTETWISPI0, SPIM0, SERIAL0);
+impl_spis!(UARTETWISPI0, SPIS0, SERIAL0);
 impl_twim!(UARTETWISPI0, TWIM0, SERIAL0);
+impl_twis!(UARTETWISPI0, TWIS0, SERIAL0);
+
+impl_timer!(TIMER0, TIMER0, TIMER0, extended);
+impl_timer!(TIMER1, TIMER1, TIMER1, extended);
+impl_timer!(TIMER2, TIMER2, TIMER2, extended);
 
-impl_timer!(TIMER0, TIMER0, TIMER0);
-impl_timer!(TIMER1, TIMER1, TIMER1);
-impl_timer!(TIMER2, TIMER2, TIMER2);
+impl_egu!(EGU0, EGU0, EGU0);
 
 impl_pin!(P0_00, 0, 0);
 impl_pin!(P0_01, 0, 1);
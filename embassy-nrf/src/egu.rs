@@ -0,0 +1,176 @@
+//! Event Generator Unit (EGU) driver.
+//!
+//! The EGU turns tasks into events: triggering task `n` fires event `n`. This gives software
+//! events that can be chained with PPI like the ones of any other peripheral: software can
+//! trigger a PPI chain, and a PPI chain can wake a task, for example
+//! `TIMER compare -> SAADC sample`, then `SAADC end -> EGU trigger` to wake a task waiting
+//! on the EGU once the samples are ready.
+
+#![macro_use]
+
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy::interrupt::{Interrupt, InterruptExt};
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::drop::OnDrop;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::pac;
+use crate::ppi::{Event, Task};
+
+/// Number of channels of each EGU.
+pub const CHANNELS: usize = 16;
+
+pub(crate) mod sealed {
+    use super::*;
+
+    pub trait Instance {
+        fn regs() -> &'static pac::egu0::RegisterBlock;
+        /// Storage for the waker for channel `n`.
+        fn waker(n: usize) -> &'static AtomicWaker;
+    }
+}
+
+pub trait Instance: Unborrow<Target = Self> + sealed::Instance + 'static + Send {
+    type Interrupt: Interrupt;
+}
+
+macro_rules! impl_egu {
+    ($type:ident, $pac_type:ident, $irq:ident) => {
+        impl crate::egu::sealed::Instance for peripherals::$type {
+            fn regs() -> &'static pac::egu0::RegisterBlock {
+                unsafe { &*(pac::$pac_type::ptr() as *const pac::egu0::RegisterBlock) }
+            }
+            fn waker(n: usize) -> &'static ::embassy::waitqueue::AtomicWaker {
+                use ::embassy::waitqueue::AtomicWaker;
+                const NEW_AW: AtomicWaker = AtomicWaker::new();
+                static WAKERS: [AtomicWaker; crate::egu::CHANNELS] = [NEW_AW; crate::egu::CHANNELS];
+                &WAKERS[n]
+            }
+        }
+        impl crate::egu::Instance for peripherals::$type {
+            type Interrupt = crate::interrupt::$irq;
+        }
+    };
+}
+
+/// nRF EGU driver.
+///
+/// The EGU takes over its interrupt, which it shares with a SWI: that SWI can't be used by
+/// an `InterruptExecutor` at the same time.
+pub struct Egu<'d, T: Instance> {
+    phantom: PhantomData<&'d mut T>,
+}
+
+impl<'d, T: Instance> Egu<'d, T> {
+    pub fn new(
+        _egu: impl Unborrow<Target = T> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+    ) -> Self {
+        unborrow!(irq);
+
+        let regs = T::regs();
+        regs.intenclr.write(|w| unsafe { w.bits(0xFFFF) });
+        for event in regs.events_triggered.iter() {
+            event.reset();
+        }
+
+        irq.set_handler(Self::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        Self {
+            phantom: PhantomData,
+        }
+    }
+
+    fn on_interrupt(_: *mut ()) {
+        let regs = T::regs();
+        for n in 0..CHANNELS {
+            if regs.events_triggered[n].read().bits() != 0 {
+                // Clear the interrupt, otherwise the interrupt will be repeatedly raised as soon as the interrupt handler exits.
+                // We can't clear the event, because it's used to poll whether the future is done or still pending.
+                regs.intenclr.write(|w| unsafe { w.bits(1 << n) });
+                T::waker(n).wake();
+            }
+        }
+    }
+
+    /// Returns this EGU's `n`th channel.
+    ///
+    /// # Panics
+    /// Panics if `n` >= [`CHANNELS`].
+    pub fn channel(&mut self, n: usize) -> Channel<T> {
+        if n >= CHANNELS {
+            panic!(
+                "Cannot get channel {} of EGU with {} channels.",
+                n, CHANNELS
+            );
+        }
+        Channel {
+            n,
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// A channel of an EGU: a TRIGGER task, and the TRIGGERED event it fires.
+pub struct Channel<'a, T: Instance> {
+    n: usize,
+    phantom: PhantomData<&'a mut T>,
+}
+
+impl<'a, T: Instance> Channel<'a, T> {
+    /// Fire the TRIGGERED event from software.
+    pub fn trigger(&self) {
+        T::regs().tasks_trigger[self.n].write(|w| unsafe { w.bits(1) })
+    }
+
+    /// Returns this channel's TRIGGER task, for use with PPI.
+    ///
+    /// When triggered, this task fires the TRIGGERED event of the channel.
+    pub fn task(&self) -> Task {
+        Task::from_reg(&T::regs().tasks_trigger[self.n])
+    }
+
+    /// Returns this channel's TRIGGERED event, for use with PPI.
+    pub fn event(&self) -> Event {
+        Event::from_reg(&T::regs().events_triggered[self.n])
+    }
+
+    /// Wait until the TRIGGERED event fires.
+    ///
+    /// Only events fired after this is called are seen.
+    ///
+    /// This requires a mutable reference so that this task's waker cannot be overwritten by a second call to `wait`.
+    pub async fn wait(&mut self) {
+        let regs = T::regs();
+        let n = self.n;
+
+        regs.events_triggered[n].reset();
+        regs.intenset.write(|w| unsafe { w.bits(1 << n) });
+
+        // Disable the interrupt if the future is dropped.
+        let on_drop = OnDrop::new(|| {
+            regs.intenclr.write(|w| unsafe { w.bits(1 << n) });
+        });
+
+        poll_fn(|cx| {
+            T::waker(n).register(cx.waker());
+
+            if regs.events_triggered[n].read().bits() != 0 {
+                regs.events_triggered[n].reset();
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+
+        // The interrupt was already disabled in the interrupt handler, so there's no need to disable it again.
+        on_drop.defuse();
+    }
+}
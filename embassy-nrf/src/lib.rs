@@ -57,33 +57,40 @@
 )))]
 compile_error!("No chip feature activated. You must activate exactly one of the following features: nrf52810, nrf52811, nrf52832, nrf52833, nrf52840");
 
-// This mod MUST go first, so that the others see its macros.
-pub(crate) mod fmt;
+#[macro_use]
+extern crate embassy_fmt;
 pub(crate) mod util;
 
 #[cfg(feature = "_time-driver")]
 mod time_driver;
 
 pub mod buffered_uarte;
+pub mod egu;
 pub mod gpio;
 #[cfg(feature = "gpiote")]
 pub mod gpiote;
 #[cfg(not(any(feature = "_nrf5340", feature = "_nrf9160")))]
 pub mod nvmc;
+#[cfg(not(any(feature = "_nrf5340", feature = "_nrf9160")))]
+pub mod power;
 pub mod ppi;
 #[cfg(not(any(feature = "nrf52805", feature = "nrf52820", feature = "_nrf5340-net")))]
 pub mod pwm;
 #[cfg(feature = "nrf52840")]
 pub mod qspi;
 #[cfg(not(any(feature = "_nrf5340", feature = "_nrf9160")))]
+pub mod radio;
+#[cfg(not(any(feature = "_nrf5340", feature = "_nrf9160")))]
 pub mod rng;
 #[cfg(not(any(feature = "nrf52820", feature = "_nrf5340-net")))]
 pub mod saadc;
 pub mod spim;
-#[cfg(not(any(feature = "_nrf5340", feature = "_nrf9160")))]
+pub mod spis;
+#[cfg(not(any(feature = "_nrf5340-app", feature = "_nrf9160")))]
 pub mod temp;
 pub mod timer;
 pub mod twim;
+pub mod twis;
 pub mod uarte;
 #[cfg(any(
     feature = "_nrf5340-app",
@@ -93,7 +100,6 @@ pub mod uarte;
 ))]
 #[cfg(feature = "nightly")]
 pub mod usb;
-#[cfg(not(feature = "_nrf5340"))]
 pub mod wdt;
 
 // This mod MUST go last, so that it sees all the `impl_foo!` macros
@@ -212,7 +218,7 @@ pub fn init(config: config::Config) -> Peripherals {
             w
         }),
     }
-    #[cfg(feature = "_nrf9160")]
+    #[cfg(any(feature = "_nrf5340", feature = "_nrf9160"))]
     match config.lfclk_source {
         config::LfclkSource::InternalRC => r.lfclksrc.write(|w| w.src().lfrc()),
         config::LfclkSource::ExternalXtal => r.lfclksrc.write(|w| w.src().lfxo()),
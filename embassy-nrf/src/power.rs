@@ -0,0 +1,332 @@
+//! Power management interface.
+//!
+//! Covers entering System OFF and configuring what wakes the chip back up,
+//! the DC/DC regulators and the REG0 output voltage, the power-fail
+//! comparator (POFCON) and decoding of the reset reason.
+//!
+//! Waking up from System OFF always goes through a reset. Use [`reset_reason`]
+//! at boot to find out which wake source triggered it.
+
+use core::marker::PhantomData;
+use core::task::Poll;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::drop::OnDrop;
+use futures::future::poll_fn;
+
+use crate::gpio::sealed::Pin as _;
+use crate::gpio::{Input, Level, Pin as GpioPin};
+use crate::pac;
+
+pub use pac::power::pofcon::THRESHOLD_A as PofThreshold;
+
+#[cfg(any(feature = "nrf52832", feature = "nrf52833", feature = "nrf52840"))]
+pub use pac::lpcomp::{
+    anadetect::ANADETECT_A as LpcompDetect, psel::PSEL_A as LpcompInput,
+    refsel::REFSEL_A as LpcompReference,
+};
+
+fn regs() -> &'static pac::power::RegisterBlock {
+    unsafe { &*pac::POWER::ptr() }
+}
+
+/// Why the chip was last reset.
+///
+/// Several flags can be set at once, since the register accumulates
+/// reasons until it is cleared with [`clear_reset_reason`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ResetReason {
+    /// Reset from the pin reset.
+    pub pin: bool,
+    /// Reset from the watchdog.
+    pub watchdog: bool,
+    /// Soft reset, requested through `SCB::sys_reset`.
+    pub soft_reset: bool,
+    /// Reset from a CPU lock-up.
+    pub lockup: bool,
+    /// Woken up from System OFF by a GPIO DETECT signal.
+    pub off_gpio: bool,
+    /// Woken up from System OFF by the LPCOMP ANADETECT signal.
+    pub off_lpcomp: bool,
+    /// Woken up from System OFF by entering debug interface mode.
+    pub off_debug: bool,
+    /// Woken up from System OFF by the NFC field detector.
+    pub off_nfc: bool,
+    /// Woken up from System OFF by VBUS rising into the valid range.
+    pub off_vbus: bool,
+}
+
+impl ResetReason {
+    /// Returns true if the chip woke up from System OFF.
+    pub fn is_wakeup_from_off(&self) -> bool {
+        self.off_gpio || self.off_lpcomp || self.off_debug || self.off_nfc || self.off_vbus
+    }
+}
+
+/// Read the reset reason register.
+///
+/// If none of the flags are set, the chip was reset by the power-on or
+/// brown-out reset.
+pub fn reset_reason() -> ResetReason {
+    let bits = regs().resetreas.read().bits();
+    let bit = |n: u32| bits & (1 << n) != 0;
+    ResetReason {
+        pin: bit(0),
+        watchdog: bit(1),
+        soft_reset: bit(2),
+        lockup: bit(3),
+        off_gpio: bit(16),
+        off_lpcomp: bit(17),
+        off_debug: bit(18),
+        off_nfc: bit(19),
+        off_vbus: bit(20),
+    }
+}
+
+/// Clear the reset reason register.
+///
+/// The register is retained across resets, so clear it after reading it
+/// if you need to tell apart the reason for the next reset.
+pub fn clear_reset_reason() {
+    regs().resetreas.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+}
+
+/// Enable or disable the DC/DC converter (REG1 stage).
+///
+/// Only enable it if the board has the external LC filter fitted, otherwise
+/// the chip will brown out.
+pub fn set_dcdc(enabled: bool) {
+    regs().dcdcen.write(|w| w.dcdcen().bit(enabled));
+}
+
+/// Enable or disable the DC/DC converter of the high voltage stage (REG0).
+///
+/// Only enable it if the board has the external LC filter fitted on DCCH.
+#[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
+pub fn set_dcdc0(enabled: bool) {
+    regs().dcdcen0.write(|w| w.dcdcen().bit(enabled));
+}
+
+/// Returns true if the chip is supplied through VDDH (high voltage mode).
+#[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
+pub fn is_high_voltage_mode() -> bool {
+    regs().mainregstatus.read().mainregstatus().is_high()
+}
+
+/// Output voltage of the REG0 stage on VDD, in high voltage mode.
+#[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Regout0 {
+    V1_8 = 0,
+    V2_1 = 1,
+    V2_4 = 2,
+    V2_7 = 3,
+    V3_0 = 4,
+    V3_3 = 5,
+}
+
+/// REGOUT0 configuration error.
+#[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Regout0Error {
+    /// UICR.REGOUT0 already holds another voltage. UICR bits can only be
+    /// cleared, so changing it takes a full UICR erase.
+    AlreadyProgrammed,
+}
+
+/// Read the REG0 output voltage from UICR.REGOUT0.
+///
+/// Returns `None` if it is unprogrammed, in which case the default of 1.8 V applies.
+#[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
+pub fn regout0() -> Option<Regout0> {
+    let uicr = unsafe { &*pac::UICR::ptr() };
+    match uicr.regout0.read().bits() & 0b111 {
+        0 => Some(Regout0::V1_8),
+        1 => Some(Regout0::V2_1),
+        2 => Some(Regout0::V2_4),
+        3 => Some(Regout0::V2_7),
+        4 => Some(Regout0::V3_0),
+        5 => Some(Regout0::V3_3),
+        _ => None,
+    }
+}
+
+/// Program the REG0 output voltage into UICR.REGOUT0.
+///
+/// Returns `Ok(true)` if UICR was written. The new voltage only takes
+/// effect after a reset, so call `SCB::sys_reset` in that case. Returns
+/// `Ok(false)` if the voltage was already configured.
+#[cfg(any(feature = "nrf52833", feature = "nrf52840"))]
+pub fn set_regout0(
+    _nvmc: &mut crate::nvmc::Nvmc<'_>,
+    voltage: Regout0,
+) -> Result<bool, Regout0Error> {
+    match regout0() {
+        Some(v) if v == voltage => return Ok(false),
+        Some(_) => return Err(Regout0Error::AlreadyProgrammed),
+        None => {}
+    }
+
+    let uicr = unsafe { &*pac::UICR::ptr() };
+    let nvmc = unsafe { &*pac::NVMC::ptr() };
+    let bits = (uicr.regout0.read().bits() & !0b111) | voltage as u32;
+
+    nvmc.config.write(|w| w.wen().wen());
+    while nvmc.ready.read().ready().is_busy() {}
+    uicr.regout0.write(|w| unsafe { w.bits(bits) });
+    while nvmc.ready.read().ready().is_busy() {}
+    nvmc.config.write(|w| w.wen().ren());
+    while nvmc.ready.read().ready().is_busy() {}
+
+    Ok(true)
+}
+
+/// Configure the pin of `input` to wake the chip from System OFF when it reaches `level`.
+///
+/// The pin keeps its pull configuration, and stays configured as a wake
+/// source until it is reconfigured.
+pub fn wake_on_pin<T: GpioPin>(input: &Input<'_, T>, level: Level) {
+    input.pin.pin.conf().modify(|_, w| match level {
+        Level::Low => w.sense().low(),
+        Level::High => w.sense().high(),
+    });
+}
+
+/// Make the NFC field detector wake the chip from System OFF.
+#[cfg(any(feature = "nrf52832", feature = "nrf52833", feature = "nrf52840"))]
+pub fn wake_on_nfc() {
+    let r = unsafe { &*pac::NFCT::ptr() };
+    r.tasks_sense.write(|w| unsafe { w.bits(1) });
+}
+
+/// Make the low power comparator wake the chip from System OFF.
+///
+/// LPCOMP compares `input` against `reference` and triggers a wake up when
+/// the `detect` condition is met.
+#[cfg(any(feature = "nrf52832", feature = "nrf52833", feature = "nrf52840"))]
+pub fn wake_on_lpcomp(input: LpcompInput, reference: LpcompReference, detect: LpcompDetect) {
+    let r = unsafe { &*pac::LPCOMP::ptr() };
+    r.enable.write(|w| w.enable().disabled());
+    r.psel.write(|w| w.psel().variant(input));
+    r.refsel.write(|w| w.refsel().variant(reference));
+    r.anadetect.write(|w| w.anadetect().variant(detect));
+    r.enable.write(|w| w.enable().enabled());
+    r.tasks_start.write(|w| unsafe { w.bits(1) });
+}
+
+/// Enter System OFF.
+///
+/// Configure the wake sources before calling this. When the chip wakes up,
+/// it goes through a reset and execution restarts from the reset vector.
+///
+/// When a debugger is attached, System OFF is emulated and the CPU keeps
+/// running, so this spins forever instead.
+pub fn system_off() -> ! {
+    regs().systemoff.write(|w| w.systemoff().enter());
+    loop {
+        cortex_m::asm::wfe();
+    }
+}
+
+static POF_WAKER: AtomicWaker = AtomicWaker::new();
+
+/// Handle the power-fail comparator part of the `POWER_CLOCK` interrupt.
+///
+/// `POWER_CLOCK` is shared with other users of the POWER and CLOCK
+/// peripherals, such as USB VBUS detection, so this module doesn't install
+/// a handler of its own. Call this from your `POWER_CLOCK` handler when
+/// using [`PowerFailComparator::wait`]. It only touches the POFWARN event.
+pub fn on_interrupt() {
+    let r = regs();
+    if r.events_pofwarn.read().bits() != 0 {
+        r.intenclr.write(|w| w.pofwarn().clear());
+        POF_WAKER.wake();
+    }
+}
+
+/// Power-fail comparator.
+///
+/// Warns when the supply voltage drops below a threshold, which leaves
+/// some time to save state before the brown-out reset kicks in.
+pub struct PowerFailComparator<'d> {
+    _p: PhantomData<&'d mut ()>,
+}
+
+impl<'d> PowerFailComparator<'d> {
+    /// Enable the comparator.
+    ///
+    /// [`wait`](Self::wait) needs [`on_interrupt`] to be called from the
+    /// `POWER_CLOCK` interrupt handler, and that interrupt to be enabled.
+    pub fn new(threshold: PofThreshold) -> Self {
+        let r = regs();
+        r.pofcon.write(|w| {
+            w.pof().enabled();
+            w.threshold().variant(threshold);
+            w
+        });
+
+        Self { _p: PhantomData }
+    }
+
+    /// Restart the comparator with a cleared POFWARN event.
+    ///
+    /// POFWARN is only generated when the comparator output goes high, so a
+    /// supply that is already below the threshold is signalled again on enable.
+    fn rearm() {
+        let r = regs();
+        r.pofcon.modify(|_, w| w.pof().disabled());
+        r.events_pofwarn.reset();
+        r.pofcon.modify(|_, w| w.pof().enabled());
+    }
+
+    /// Returns true if the supply is currently below the threshold.
+    ///
+    /// This restarts the comparator, so it takes a few microseconds.
+    pub fn is_below_threshold(&self) -> bool {
+        let r = regs();
+        Self::rearm();
+        // Comparator start-up time, with margin for the fastest core clock.
+        cortex_m::asm::delay(1024);
+        r.events_pofwarn.read().bits() != 0
+    }
+
+    /// Wait until the supply voltage is below the threshold.
+    ///
+    /// Completes right away if it already is.
+    pub async fn wait(&mut self) {
+        let r = regs();
+
+        // In case the future is dropped, disable the interrupt again.
+        let on_drop = OnDrop::new(|| {
+            regs().intenclr.write(|w| w.pofwarn().clear());
+        });
+
+        // Enable the interrupt before restarting the comparator, so a supply
+        // that is already low raises it too.
+        r.intenset.write(|w| w.pofwarn().set());
+        Self::rearm();
+
+        poll_fn(|cx| {
+            POF_WAKER.register(cx.waker());
+            if r.events_pofwarn.read().bits() != 0 {
+                r.events_pofwarn.reset();
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await;
+
+        on_drop.defuse();
+    }
+}
+
+impl<'d> Drop for PowerFailComparator<'d> {
+    fn drop(&mut self) {
+        let r = regs();
+        r.intenclr.write(|w| w.pofwarn().clear());
+        r.pofcon.write(|w| w.pof().disabled());
+    }
+}
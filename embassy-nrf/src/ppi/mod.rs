@@ -14,6 +14,9 @@
 //! The DPPI for nRF53 and nRF91 devices works in a different way. Every channel can support infinitely
 //! many tasks and events, but any single task or event can only be coupled with one channel.
 //!
+//! Channels can be put in a [`PpiGroup`], to enable or disable them all at once, from software
+//! or from another PPI channel.
+//!
 
 use crate::peripherals;
 use core::marker::PhantomData;
@@ -35,6 +38,16 @@ pub struct Ppi<'d, C: Channel, const EVENT_COUNT: usize, const TASK_COUNT: usize
     phantom: PhantomData<&'d mut C>,
 }
 
+/// A group of PPI channels, enabled or disabled all at once.
+///
+/// Channels are added by borrowing their [`Ppi`] for as long as the group exists, so a
+/// channel can't be enabled, disabled or reconfigured on its own, or dropped, while the
+/// group controls it: that's checked at compile time.
+pub struct PpiGroup<'d, G: Group> {
+    g: G,
+    phantom: PhantomData<&'d mut G>,
+}
+
 const REGISTER_DPPI_CONFIG_OFFSET: usize = 0x80 / core::mem::size_of::<u32>();
 
 /// Represents a task that a peripheral can do.
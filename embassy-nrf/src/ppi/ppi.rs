@@ -3,7 +3,7 @@ use core::marker::PhantomData;
 use embassy::util::Unborrow;
 use embassy_hal_common::unborrow;
 
-use super::{Channel, ConfigurableChannel, Event, Ppi, StaticChannel, Task};
+use super::{Channel, ConfigurableChannel, Event, Group, Ppi, PpiGroup, StaticChannel, Task};
 use crate::pac;
 
 impl Task {
@@ -105,3 +105,60 @@ impl<'d, C: Channel, const EVENT_COUNT: usize, const TASK_COUNT: usize> Drop
         r.fork[n].tep.write(|w| unsafe { w.bits(0) });
     }
 }
+
+impl<'d, G: Group> PpiGroup<'d, G> {
+    pub fn new(g: impl Unborrow<Target = G> + 'd) -> Self {
+        unborrow!(g);
+
+        let r = regs();
+        let n = g.number();
+        r.chg[n].write(|w| unsafe { w.bits(0) });
+
+        Self {
+            g,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Add a channel to the group.
+    pub fn add_channel<C: Channel, const EVENT_COUNT: usize, const TASK_COUNT: usize>(
+        &mut self,
+        ch: &'d Ppi<'_, C, EVENT_COUNT, TASK_COUNT>,
+    ) {
+        let r = regs();
+        let ng = self.g.number();
+        let nc = ch.ch.number();
+        r.chg[ng].modify(|rr, w| unsafe { w.bits(rr.bits() | 1 << nc) });
+    }
+
+    /// Enables all the channels of the group.
+    pub fn enable_all(&self) {
+        let n = self.g.number();
+        regs().tasks_chg[n].en.write(|w| unsafe { w.bits(1) });
+    }
+
+    /// Disables all the channels of the group.
+    pub fn disable_all(&self) {
+        let n = self.g.number();
+        regs().tasks_chg[n].dis.write(|w| unsafe { w.bits(1) });
+    }
+
+    /// Returns the task enabling all the channels of the group, for use with PPI.
+    pub fn task_enable_all(&self) -> Task {
+        let n = self.g.number();
+        Task::from_reg(&regs().tasks_chg[n].en)
+    }
+
+    /// Returns the task disabling all the channels of the group, for use with PPI.
+    pub fn task_disable_all(&self) -> Task {
+        let n = self.g.number();
+        Task::from_reg(&regs().tasks_chg[n].dis)
+    }
+}
+
+impl<'d, G: Group> Drop for PpiGroup<'d, G> {
+    fn drop(&mut self) {
+        let n = self.g.number();
+        regs().chg[n].write(|w| unsafe { w.bits(0) });
+    }
+}
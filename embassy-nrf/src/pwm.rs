@@ -2,8 +2,12 @@
 
 use core::marker::PhantomData;
 use core::sync::atomic::{compiler_fence, Ordering};
+use core::task::Poll;
+use embassy::interrupt::InterruptExt;
 use embassy::util::Unborrow;
+use embassy_hal_common::drop::OnDrop;
 use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
 
 use crate::gpio::sealed::Pin as _;
 use crate::gpio::{AnyPin, Pin as GpioPin, PselBits};
@@ -43,10 +47,70 @@ pub enum Error {
     SequenceTimesAtLeastOne,
     /// EasyDMA can only read from data memory, read only buffers in flash will fail.
     DMABufferNotInDataMemory,
+    /// `play` can't wait for an infinite sequence to end.
+    SequenceInfinite,
+    /// The buffer is too small to hold the encoded sequence.
+    BufferTooShort,
 }
 
 const MAX_SEQUENCE_LEN: usize = 32767;
 
+/// Polarity bit: when set, the output is high for the duty cycle, then low.
+const POLARITY_INVERTED: u16 = 0x8000;
+
+/// Duty (in ticks of a 20 tick, 1.25us period) of a WS2812 `1` bit.
+const WS2812_T1H: u16 = POLARITY_INVERTED | 13;
+/// Duty (in ticks of a 20 tick, 1.25us period) of a WS2812 `0` bit.
+const WS2812_T0H: u16 = POLARITY_INVERTED | 7;
+/// Output held low, used for the WS2812 reset/latch period.
+const WS2812_RES: u16 = POLARITY_INVERTED;
+
+/// Encode WS2812 (NeoPixel) colors into a sequence.
+///
+/// Colors are given as `[r, g, b]` and sent in the GRB order the LEDs expect,
+/// most significant bit first. Each LED takes 24 words, followed by a single
+/// word that holds the line low. Use [`SequenceConfig::ws2812`] so the line is held
+/// low long enough for the LEDs to latch the data.
+///
+/// The PWM must be configured with [`Config::ws2812`] and [`SequenceLoad::Common`].
+///
+/// Returns the number of words written to `buf`.
+pub fn encode_ws2812(colors: &[[u8; 3]], buf: &mut [u16]) -> Result<usize, Error> {
+    let len = colors.len() * 24 + 1;
+    if buf.len() < len {
+        return Err(Error::BufferTooShort);
+    }
+
+    let mut i = 0;
+    for [r, g, b] in colors {
+        for byte in [g, r, b] {
+            for bit in (0..8).rev() {
+                buf[i] = if byte & (1 << bit) != 0 {
+                    WS2812_T1H
+                } else {
+                    WS2812_T0H
+                };
+                i += 1;
+            }
+        }
+    }
+    buf[i] = WS2812_RES;
+
+    Ok(len)
+}
+
+/// Compute the duty word for a servo pulse of `pulse_us` microseconds.
+///
+/// The PWM must be configured with [`Config::servo`], which gives a 50 Hz
+/// period with an 8 us resolution. Most hobby servos expect pulses between
+/// 1000 and 2000 us.
+pub fn servo_duty(pulse_us: u32) -> u16 {
+    POLARITY_INVERTED | ((pulse_us / 8).min(SERVO_MAX_DUTY as u32) as u16)
+}
+
+/// 20ms period at 125kHz (16MHz / 128).
+const SERVO_MAX_DUTY: u16 = 2500;
+
 impl<'d, T: Instance> SequencePwm<'d, T> {
     /// Create a new 1-channel PWM
     #[allow(unused_unsafe)]
@@ -337,6 +401,32 @@ impl Default for Config {
     }
 }
 
+impl Config {
+    /// Configuration for driving WS2812 LEDs: an 800kHz (1.25us) period with 20 ticks.
+    ///
+    /// See [`encode_ws2812`].
+    pub fn ws2812() -> Config {
+        Config {
+            counter_mode: CounterMode::Up,
+            max_duty: 20,
+            prescaler: Prescaler::Div1,
+            sequence_load: SequenceLoad::Common,
+        }
+    }
+
+    /// Configuration for driving hobby servos: a 50Hz (20ms) period with 8us resolution.
+    ///
+    /// See [`servo_duty`].
+    pub fn servo() -> Config {
+        Config {
+            counter_mode: CounterMode::Up,
+            max_duty: SERVO_MAX_DUTY,
+            prescaler: Prescaler::Div128,
+            sequence_load: SequenceLoad::Common,
+        }
+    }
+}
+
 /// Configuration per sequence
 #[non_exhaustive]
 #[derive(Clone)]
@@ -356,6 +446,19 @@ impl Default for SequenceConfig {
     }
 }
 
+impl SequenceConfig {
+    /// Sequence configuration for WS2812 LEDs.
+    ///
+    /// Holds the line low for 50us after the data so the LEDs latch it.
+    pub fn ws2812() -> SequenceConfig {
+        SequenceConfig {
+            refresh: 0,
+            // 50us (20 ticks * 40) - 1 period, as the sequence already ends with one.
+            end_delay: 39,
+        }
+    }
+}
+
 /// A composition of a sequence buffer and its configuration.
 #[non_exhaustive]
 pub struct Sequence<'s> {
@@ -380,24 +483,41 @@ pub struct SingleSequencer<'d, 's, T: Instance> {
 
 impl<'d, 's, T: Instance> SingleSequencer<'d, 's, T> {
     /// Create a new sequencer
-    pub fn new(pwm: &'s mut SequencePwm<'d, T>, words: &'s [u16], config: SequenceConfig) -> Self {
+    pub fn new(
+        pwm: &'s mut SequencePwm<'d, T>,
+        irq: impl Unborrow<Target = T::Interrupt> + 's,
+        words: &'s [u16],
+        config: SequenceConfig,
+    ) -> Self {
         Self {
-            sequencer: Sequencer::new(pwm, Sequence::new(words, config), None),
+            sequencer: Sequencer::new(pwm, irq, Sequence::new(words, config), None),
         }
     }
 
     /// Start or restart playback.
     #[inline(always)]
     pub fn start(&self, times: SingleSequenceMode) -> Result<(), Error> {
-        let (start_seq, times) = match times {
+        let (start_seq, times) = Self::modes(times);
+        self.sequencer.start(start_seq, times)
+    }
+
+    /// Start or restart playback, and wait for it to finish.
+    ///
+    /// See [`Sequencer::play`].
+    pub async fn play(&self, times: SingleSequenceMode) -> Result<(), Error> {
+        let (start_seq, times) = Self::modes(times);
+        self.sequencer.play(start_seq, times).await
+    }
+
+    fn modes(times: SingleSequenceMode) -> (StartSequence, SequenceMode) {
+        match times {
             SingleSequenceMode::Times(n) if n == 1 => (StartSequence::One, SequenceMode::Loop(1)),
             SingleSequenceMode::Times(n) if n & 1 == 1 => {
                 (StartSequence::One, SequenceMode::Loop((n / 2) + 1))
             }
             SingleSequenceMode::Times(n) => (StartSequence::Zero, SequenceMode::Loop(n / 2)),
             SingleSequenceMode::Infinite => (StartSequence::Zero, SequenceMode::Infinite),
-        };
-        self.sequencer.start(start_seq, times)
+        }
     }
 
     /// Stop playback. Disables the peripheral. Does NOT clear the last duty
@@ -417,6 +537,7 @@ impl<'d, 's, T: Instance> SingleSequencer<'d, 's, T> {
 #[non_exhaustive]
 pub struct Sequencer<'d, 's, T: Instance> {
     _pwm: &'s mut SequencePwm<'d, T>,
+    irq: T::Interrupt,
     sequence0: Sequence<'s>,
     sequence1: Option<Sequence<'s>>,
 }
@@ -424,13 +545,23 @@ pub struct Sequencer<'d, 's, T: Instance> {
 impl<'d, 's, T: Instance> Sequencer<'d, 's, T> {
     /// Create a new double sequence. In the absence of sequence 1, sequence 0
     /// will be used twice in the one loop.
+    ///
+    /// The PWM instance interrupt is used by [`play`](Self::play).
     pub fn new(
         pwm: &'s mut SequencePwm<'d, T>,
+        irq: impl Unborrow<Target = T::Interrupt> + 's,
         sequence0: Sequence<'s>,
         sequence1: Option<Sequence<'s>>,
     ) -> Self {
+        unborrow!(irq);
+
+        irq.set_handler(Self::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
         Sequencer {
             _pwm: pwm,
+            irq,
             sequence0,
             sequence1,
         }
@@ -512,6 +643,51 @@ impl<'d, 's, T: Instance> Sequencer<'d, 's, T> {
         Ok(())
     }
 
+    /// Start or restart playback, and wait for it to finish.
+    ///
+    /// Completes once the last sequence of the last loop has ended. If the
+    /// returned future is dropped, playback is stopped.
+    pub async fn play(&self, start_seq: StartSequence, times: SequenceMode) -> Result<(), Error> {
+        if times == SequenceMode::Infinite {
+            return Err(Error::SequenceInfinite);
+        }
+
+        let r = T::regs();
+
+        r.events_loopsdone.reset();
+        self.start(start_seq, times)?;
+
+        // Enable the interrupt only now: `start` stops the previous playback, which disables it.
+        // If the sequence already ended, the interrupt fires right away.
+        r.intenset.write(|w| w.loopsdone().set());
+
+        let on_drop = OnDrop::new(|| self.stop());
+
+        poll_fn(|cx| {
+            T::state().end_waker.register(cx.waker());
+            if r.events_loopsdone.read().bits() != 0 {
+                return Poll::Ready(());
+            }
+
+            Poll::Pending
+        })
+        .await;
+
+        on_drop.defuse();
+        r.events_loopsdone.reset();
+
+        Ok(())
+    }
+
+    fn on_interrupt(_: *mut ()) {
+        let r = T::regs();
+
+        if r.events_loopsdone.read().bits() != 0 {
+            T::state().end_waker.wake();
+            r.intenclr.write(|w| w.loopsdone().clear());
+        }
+    }
+
     /// Stop playback. Disables the peripheral. Does NOT clear the last duty
     /// cycle from the pin. Returns any sequences previously provided to
     /// `start` so that they may be further mutated.
@@ -519,6 +695,8 @@ impl<'d, 's, T: Instance> Sequencer<'d, 's, T> {
     pub fn stop(&self) {
         let r = T::regs();
 
+        r.intenclr.write(|w| w.loopsdone().clear());
+
         r.shorts.reset();
 
         compiler_fence(Ordering::SeqCst);
@@ -533,6 +711,7 @@ impl<'d, 's, T: Instance> Sequencer<'d, 's, T> {
 impl<'d, 's, T: Instance> Drop for Sequencer<'d, 's, T> {
     fn drop(&mut self) {
         let _ = self.stop();
+        self.irq.disable();
     }
 }
 
@@ -850,10 +1029,25 @@ impl<'a, T: Instance> Drop for SimplePwm<'a, T> {
 }
 
 pub(crate) mod sealed {
+    use embassy::waitqueue::AtomicWaker;
+
     use super::*;
 
+    pub struct State {
+        pub end_waker: AtomicWaker,
+    }
+
+    impl State {
+        pub const fn new() -> Self {
+            Self {
+                end_waker: AtomicWaker::new(),
+            }
+        }
+    }
+
     pub trait Instance {
         fn regs() -> &'static pac::pwm0::RegisterBlock;
+        fn state() -> &'static State;
     }
 }
 
@@ -867,6 +1061,10 @@ macro_rules! impl_pwm {
             fn regs() -> &'static pac::pwm0::RegisterBlock {
                 unsafe { &*pac::$pac_type::ptr() }
             }
+            fn state() -> &'static crate::pwm::sealed::State {
+                static STATE: crate::pwm::sealed::State = crate::pwm::sealed::State::new();
+                &STATE
+            }
         }
         impl crate::pwm::Instance for peripherals::$type {
             type Interrupt = crate::interrupt::$irq;
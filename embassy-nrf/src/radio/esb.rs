@@ -0,0 +1,393 @@
+//! Enhanced ShockBurst.
+//!
+//! Enhanced ShockBurst (ESB) is Nordic's proprietary packet protocol, compatible with the
+//! nRF24L01 family. A primary transmitter (PTX) sends packets to a primary receiver (PRX) on
+//! one of 8 pipes, each with its own address. The PRX acknowledges them, optionally with a
+//! payload of its own, and the PTX retransmits the packets which aren't acknowledged in
+//! time.
+//!
+//! The same [`Esb`] driver can act as a PTX, with [`Esb::send`], or as a PRX, with
+//! [`Esb::receive`]. Packets use dynamic payload lengths, of up to [`MAX_PAYLOAD_LEN`] bytes.
+//!
+//! The acknowledgement is sent by the task receiving the packet: make sure the retransmit
+//! delay of the PTX covers the latency of the PRX task, plus about 150µs for the radio to
+//! switch from receiving to transmitting.
+
+use core::marker::PhantomData;
+
+use embassy::time::{with_timeout, Duration};
+use embassy::util::Unborrow;
+use embassy_hal_common::drop::OnDrop;
+use embassy_hal_common::unborrow;
+
+use super::{disable, init_irq, regs, start, wait_disabled};
+use crate::interrupt;
+use crate::peripherals::RADIO;
+
+/// Maximum length of a payload.
+pub const MAX_PAYLOAD_LEN: usize = 32;
+
+/// Number of pipes.
+pub const PIPES: usize = 8;
+
+/// Air data rate.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Bitrate {
+    Mbps1,
+    Mbps2,
+}
+
+/// Addresses of the pipes.
+///
+/// The address of pipe 0 is its prefix followed by `base0`, the address of the other pipes is
+/// their prefix followed by `base1`. Only the first `length - 1` bytes of the bases are used.
+/// The byte order and the bit order are the ones of the nRF24L01 and of Nordic's ESB library,
+/// so the same addresses can be used on both sides.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Addresses {
+    pub base0: [u8; 4],
+    pub base1: [u8; 4],
+    pub prefixes: [u8; PIPES],
+    /// Total length of the addresses, from 3 to 5 bytes.
+    pub length: u8,
+}
+
+impl Default for Addresses {
+    /// The default addresses of Nordic's ESB library.
+    fn default() -> Self {
+        Self {
+            base0: [0xE7, 0xE7, 0xE7, 0xE7],
+            base1: [0xC2, 0xC2, 0xC2, 0xC2],
+            prefixes: [0xE7, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6, 0xC7, 0xC8],
+            length: 5,
+        }
+    }
+}
+
+#[non_exhaustive]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    pub bitrate: Bitrate,
+    /// RF channel, the frequency is 2400 MHz + `channel` MHz. At most 100.
+    pub channel: u8,
+    /// Output power in dBm. Must be one of the values supported by the chip, see the TXPOWER
+    /// register in the product specification.
+    pub tx_power: i8,
+    pub addresses: Addresses,
+    /// Pipes to receive on, as a bit mask.
+    pub rx_pipes: u8,
+    /// How long to wait for an acknowledgement before retransmitting a packet.
+    pub retransmit_delay: Duration,
+    /// How many times a packet is retransmitted before giving up.
+    pub retransmit_count: u8,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bitrate: Bitrate::Mbps2,
+            channel: 2,
+            tx_power: 0,
+            addresses: Addresses::default(),
+            rx_pipes: 0xFF,
+            retransmit_delay: Duration::from_micros(600),
+            retransmit_count: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// The payload is longer than [`MAX_PAYLOAD_LEN`].
+    PayloadTooLarge,
+    /// The received payload doesn't fit in the buffer.
+    BufferTooSmall,
+    /// The packet wasn't acknowledged, even after retransmitting it.
+    MaxRetransmits,
+}
+
+/// A packet received by a PRX.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Packet {
+    pub pipe: u8,
+    /// Length of the payload.
+    pub len: usize,
+    /// Received signal strength, in dBm.
+    pub rssi: i8,
+}
+
+/// Packet buffer, as read and written by the radio: length, S1 field, payload.
+///
+/// The S1 field is 3 bits long: the packet identifier (PID) in bits 1-2, and in bit 0 whether
+/// an acknowledgement is requested.
+type Buffer = [u8; MAX_PAYLOAD_LEN + 2];
+
+/// Enhanced ShockBurst driver.
+pub struct Esb<'d> {
+    phantom: PhantomData<&'d mut RADIO>,
+    config: Config,
+    buf: Buffer,
+    /// Identifier of the last packet sent on each pipe.
+    tx_pids: [u8; PIPES],
+    /// Identifier and CRC of the last packet received on each pipe, to drop retransmissions.
+    rx_last: [Option<(u8, u32)>; PIPES],
+    ack_payloads: [[u8; MAX_PAYLOAD_LEN]; PIPES],
+    ack_lens: [Option<u8>; PIPES],
+}
+
+impl<'d> Esb<'d> {
+    pub fn new(
+        _radio: impl Unborrow<Target = RADIO> + 'd,
+        irq: impl Unborrow<Target = interrupt::RADIO> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(irq);
+
+        let r = regs();
+        r.power.write(|w| w.power().enabled());
+        disable();
+        init_irq(&irq);
+
+        let mut this = Self {
+            phantom: PhantomData,
+            config,
+            buf: [0; MAX_PAYLOAD_LEN + 2],
+            tx_pids: [0; PIPES],
+            rx_last: [None; PIPES],
+            ack_payloads: [[0; MAX_PAYLOAD_LEN]; PIPES],
+            ack_lens: [None; PIPES],
+        };
+        this.set_config(config);
+        this
+    }
+
+    /// Change the configuration. The radio must not be in use.
+    pub fn set_config(&mut self, config: Config) {
+        assert!(config.channel <= 100);
+        assert!((3..=5).contains(&config.addresses.length));
+
+        let r = regs();
+        let a = &config.addresses;
+        let balen = a.length as u32 - 1;
+
+        r.mode.write(|w| match config.bitrate {
+            Bitrate::Mbps1 => w.mode().nrf_1mbit(),
+            Bitrate::Mbps2 => w.mode().nrf_2mbit(),
+        });
+        r.frequency
+            .write(|w| unsafe { w.bits(config.channel as u32) });
+        r.txpower
+            .write(|w| unsafe { w.bits(config.tx_power as u8 as u32) });
+
+        // 6 bit length field, 3 bit S1 field, no S0 field.
+        r.pcnf0
+            .write(|w| unsafe { w.lflen().bits(6).s0len().clear_bit().s1len().bits(3) });
+        r.pcnf1.write(|w| unsafe {
+            w.maxlen()
+                .bits(MAX_PAYLOAD_LEN as u8)
+                .statlen()
+                .bits(0)
+                .balen()
+                .bits(balen as u8)
+                .endian()
+                .big()
+                .whiteen()
+                .disabled()
+        });
+        r.crccnf.write(|w| w.len().two().skipaddr().include());
+        r.crcinit.write(|w| unsafe { w.bits(0xFFFF) });
+        r.crcpoly.write(|w| unsafe { w.bits(0x1_1021) });
+
+        r.base0
+            .write(|w| unsafe { w.bits(base_address(a.base0, balen)) });
+        r.base1
+            .write(|w| unsafe { w.bits(base_address(a.base1, balen)) });
+        let prefix = |p: &[u8]| u32::from_le_bytes([p[0], p[1], p[2], p[3]].map(u8::reverse_bits));
+        r.prefix0
+            .write(|w| unsafe { w.bits(prefix(&a.prefixes[..4])) });
+        r.prefix1
+            .write(|w| unsafe { w.bits(prefix(&a.prefixes[4..])) });
+
+        self.config = config;
+    }
+
+    /// Queue a payload to send with the next acknowledgement on `pipe`, as a PRX.
+    ///
+    /// It replaces the payload queued before, if it wasn't sent yet.
+    pub fn set_ack_payload(&mut self, pipe: u8, payload: &[u8]) -> Result<(), Error> {
+        if payload.len() > MAX_PAYLOAD_LEN {
+            return Err(Error::PayloadTooLarge);
+        }
+        let pipe = pipe as usize;
+        self.ack_payloads[pipe][..payload.len()].copy_from_slice(payload);
+        self.ack_lens[pipe] = Some(payload.len() as u8);
+        Ok(())
+    }
+
+    /// Send a packet on `pipe`, as a PTX.
+    ///
+    /// If `ack` is set, the packet is retransmitted until it's acknowledged, and the payload
+    /// of the acknowledgement is copied to `ack_payload`. Returns the length of that payload.
+    pub async fn send(
+        &mut self,
+        pipe: u8,
+        payload: &[u8],
+        ack: bool,
+        ack_payload: &mut [u8],
+    ) -> Result<usize, Error> {
+        assert!((pipe as usize) < PIPES);
+        if payload.len() > MAX_PAYLOAD_LEN {
+            return Err(Error::PayloadTooLarge);
+        }
+
+        let r = regs();
+        let _on_drop = OnDrop::new(disable);
+
+        let pid = (self.tx_pids[pipe as usize] + 1) & 0b11;
+        self.tx_pids[pipe as usize] = pid;
+
+        r.txaddress.write(|w| unsafe { w.bits(pipe as u32) });
+        r.rxaddresses.write(|w| unsafe { w.bits(1 << pipe) });
+
+        for _ in 0..=self.config.retransmit_count {
+            // The acknowledgement is received in the same buffer, the packet is rebuilt for
+            // each transmission.
+            self.buf[0] = payload.len() as u8;
+            self.buf[1] = pid << 1 | ack as u8;
+            self.buf[2..][..payload.len()].copy_from_slice(payload);
+            r.packetptr
+                .write(|w| unsafe { w.bits(self.buf.as_ptr() as u32) });
+
+            // Switch to RX right after transmitting, not to miss a fast acknowledgement.
+            r.shorts.write(|w| {
+                w.ready_start().enabled();
+                w.end_disable().enabled();
+                if ack {
+                    w.disabled_rxen().enabled();
+                }
+                w
+            });
+            start(true);
+            wait_disabled().await;
+
+            if !ack {
+                return Ok(0);
+            }
+
+            // Don't loop back to TX after receiving the acknowledgement.
+            r.shorts
+                .write(|w| w.ready_start().enabled().end_disable().enabled());
+            match with_timeout(self.config.retransmit_delay, wait_disabled()).await {
+                Ok(()) if r.crcstatus.read().crcstatus().is_crcok() => {
+                    let len = self.buf[0] as usize;
+                    if len > ack_payload.len() {
+                        return Err(Error::BufferTooSmall);
+                    }
+                    ack_payload[..len].copy_from_slice(&self.buf[2..][..len]);
+                    return Ok(len);
+                }
+                _ => disable(),
+            }
+        }
+
+        Err(Error::MaxRetransmits)
+    }
+
+    /// Receive a packet on one of the enabled pipes into `buf`, as a PRX.
+    ///
+    /// Packets requesting one are acknowledged, with the payload queued for the pipe if any.
+    /// Retransmissions of the last packet received on a pipe are acknowledged again, but not
+    /// returned.
+    pub async fn receive(&mut self, buf: &mut [u8]) -> Result<Packet, Error> {
+        let r = regs();
+        let _on_drop = OnDrop::new(disable);
+
+        loop {
+            r.rxaddresses
+                .write(|w| unsafe { w.bits(self.config.rx_pipes as u32) });
+            r.packetptr
+                .write(|w| unsafe { w.bits(self.buf.as_ptr() as u32) });
+            r.shorts.write(|w| {
+                w.ready_start()
+                    .enabled()
+                    .end_disable()
+                    .enabled()
+                    .address_rssistart()
+                    .enabled()
+                    .disabled_rssistop()
+                    .enabled()
+            });
+            start(false);
+            wait_disabled().await;
+
+            if !r.crcstatus.read().crcstatus().is_crcok() {
+                continue;
+            }
+
+            let pipe = r.rxmatch.read().bits() as u8;
+            let crc = r.rxcrc.read().bits();
+            let rssi = -(r.rssisample.read().bits() as i8);
+            let len = self.buf[0] as usize;
+            let pid = (self.buf[1] >> 1) & 0b11;
+            let ack = self.buf[1] & 1 != 0;
+
+            let duplicate = self.rx_last[pipe as usize] == Some((pid, crc));
+            self.rx_last[pipe as usize] = Some((pid, crc));
+
+            let packet = Packet { pipe, len, rssi };
+            let res = if duplicate {
+                None
+            } else if len > buf.len() {
+                Some(Err(Error::BufferTooSmall))
+            } else {
+                buf[..len].copy_from_slice(&self.buf[2..][..len]);
+                Some(Ok(packet))
+            };
+
+            if ack {
+                self.send_ack(pipe, pid).await;
+            }
+
+            if let Some(res) = res {
+                return res;
+            }
+        }
+    }
+
+    async fn send_ack(&mut self, pipe: u8, pid: u8) {
+        let r = regs();
+        let p = pipe as usize;
+
+        let len = self.ack_lens[p].take().unwrap_or(0) as usize;
+        self.buf[0] = len as u8;
+        self.buf[1] = pid << 1;
+        self.buf[2..][..len].copy_from_slice(&self.ack_payloads[p][..len]);
+
+        r.txaddress.write(|w| unsafe { w.bits(pipe as u32) });
+        r.packetptr
+            .write(|w| unsafe { w.bits(self.buf.as_ptr() as u32) });
+        r.shorts
+            .write(|w| w.ready_start().enabled().end_disable().enabled());
+        start(true);
+        wait_disabled().await;
+    }
+}
+
+impl<'d> Drop for Esb<'d> {
+    fn drop(&mut self) {
+        disable();
+        regs().power.write(|w| w.power().disabled());
+    }
+}
+
+/// Convert a base address to the BASE register value, keeping its first `balen` bytes.
+fn base_address(base: [u8; 4], balen: u32) -> u32 {
+    let base = u32::from_be_bytes(base.map(u8::reverse_bits));
+    base >> (8 * (4 - balen))
+}
@@ -0,0 +1,425 @@
+//! IEEE 802.15.4 radio.
+//!
+//! Sends and receives MAC frames on the 2.4 GHz O-QPSK PHY, channels 11 to 26. The radio
+//! computes and checks the frame check sequence (FCS), the driver does the rest of the
+//! 802.15.4 MAC work which has to happen close to the radio:
+//!
+//! - Clear channel assessment (CCA) before transmitting, with the unslotted CSMA-CA backoffs.
+//! - Acknowledgements: frames requesting one are retransmitted until acknowledged, received
+//!   frames addressed to this device requesting one are acknowledged.
+//! - Address filtering of the received frames, unless in promiscuous mode.
+//!
+//! Acknowledgements are immediate acknowledgements (2003/2006 frames). They're sent by the
+//! task receiving the frame, which must be polled within about 150µs of the end of the frame
+//! to meet the turnaround time: run it in a high priority executor if other tasks are busy.
+//!
+//! With the `ieee802154-net` feature, [`net`] exposes the radio as an embassy-net device,
+//! for IPv6 with 6LoWPAN.
+
+#[cfg(feature = "ieee802154-net")]
+pub mod net;
+
+use core::convert::TryInto;
+use core::marker::PhantomData;
+
+use embassy::time::{with_timeout, Duration, Instant, Timer};
+use embassy::util::Unborrow;
+use embassy_hal_common::drop::OnDrop;
+use embassy_hal_common::unborrow;
+
+use super::{disable, init_irq, regs, start, wait_disabled};
+use crate::interrupt;
+use crate::peripherals::RADIO;
+
+/// Maximum length of a frame, without the FCS.
+pub const MAX_FRAME_LEN: usize = 125;
+
+/// Length of the FCS, appended by the radio.
+const FCS_LEN: usize = 2;
+
+/// Duration of a symbol, and of the CSMA-CA unit backoff period.
+const SYMBOL_US: u64 = 16;
+const UNIT_BACKOFF_US: u64 = 20 * SYMBOL_US;
+
+/// How long to wait for an acknowledgement, `macAckWaitDuration` plus the time for the driver
+/// to switch to RX.
+const ACK_WAIT: Duration = Duration::from_micros(54 * SYMBOL_US + 200);
+
+const MAX_FRAME_RETRIES: u8 = 3;
+const MAX_CSMA_BACKOFFS: u8 = 4;
+const MIN_BE: u8 = 3;
+const MAX_BE: u8 = 5;
+
+const FRAME_TYPE_ACK: u16 = 0b010;
+const FCF_ACK_REQUEST: u16 = 1 << 5;
+
+#[non_exhaustive]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    /// Channel, from 11 to 26.
+    pub channel: u8,
+    /// Output power in dBm. Must be one of the values supported by the chip, see the TXPOWER
+    /// register in the product specification.
+    pub tx_power: i8,
+    pub pan_id: u16,
+    pub short_address: u16,
+    /// Extended address, the EUI-64 of the device, most significant byte first.
+    pub extended_address: [u8; 8],
+    /// Receive all the frames, whatever their destination, and don't acknowledge any.
+    pub promiscuous: bool,
+    /// Energy level above which the channel is considered busy, in dBm.
+    pub cca_threshold: i8,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            channel: 11,
+            tx_power: 0,
+            pan_id: 0xFFFF,
+            short_address: 0xFFFF,
+            extended_address: [0; 8],
+            promiscuous: false,
+            cca_threshold: -75,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// The frame is longer than [`MAX_FRAME_LEN`].
+    FrameTooLarge,
+    /// The received frame doesn't fit in the buffer.
+    BufferTooSmall,
+    /// The channel stayed busy through all the CSMA-CA backoffs.
+    ChannelBusy,
+    /// The frame wasn't acknowledged, even after retransmitting it.
+    NoAck,
+}
+
+/// Information about a received frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RxInfo {
+    /// Length of the frame, without the FCS.
+    pub len: usize,
+    /// Received signal strength, in dBm.
+    pub rssi: i8,
+    /// When the end of the frame was received.
+    ///
+    /// It's taken when the driver handles the frame, so it's late by the interrupt and
+    /// executor latency, usually a few microseconds.
+    pub timestamp: Instant,
+}
+
+/// IEEE 802.15.4 radio driver.
+pub struct Radio<'d> {
+    phantom: PhantomData<&'d mut RADIO>,
+    config: Config,
+    /// Frame buffer, as read and written by the radio: PHR (length including the FCS), then
+    /// the frame.
+    buf: [u8; MAX_FRAME_LEN + FCS_LEN + 1],
+    rng: u32,
+}
+
+impl<'d> Radio<'d> {
+    pub fn new(
+        _radio: impl Unborrow<Target = RADIO> + 'd,
+        irq: impl Unborrow<Target = interrupt::RADIO> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(irq);
+
+        let r = regs();
+        r.power.write(|w| w.power().enabled());
+        disable();
+        init_irq(&irq);
+
+        r.mode.write(|w| w.mode().ieee802154_250kbit());
+        // Fast ramp-up, to switch quickly between TX and RX for the acknowledgements.
+        r.modecnf0.write(|w| w.ru().fast());
+        // 8 bit length field, 32 bit zero preamble, length field including the FCS.
+        r.pcnf0
+            .write(|w| unsafe { w.bits(8 | (2 << 24) | (1 << 26)) });
+        r.pcnf1
+            .write(|w| unsafe { w.bits((MAX_FRAME_LEN + FCS_LEN) as u32) });
+        r.sfd.write(|w| unsafe { w.bits(0xA7) });
+        // 16 bit ITU-T CRC, over the whole frame.
+        r.crccnf.write(|w| w.len().two().skipaddr().ieee802154());
+        r.crcpoly.write(|w| unsafe { w.bits(0x1_1021) });
+        r.crcinit.write(|w| unsafe { w.bits(0) });
+
+        let seed = u32::from_le_bytes(config.extended_address[4..].try_into().unwrap());
+        let mut this = Self {
+            phantom: PhantomData,
+            config,
+            buf: [0; MAX_FRAME_LEN + FCS_LEN + 1],
+            rng: seed | 1,
+        };
+        this.set_config(config);
+        this
+    }
+
+    /// Change the configuration. The radio must not be in use.
+    pub fn set_config(&mut self, config: Config) {
+        assert!((11..=26).contains(&config.channel));
+
+        let r = regs();
+        // Channels are 5 MHz apart, from 2405 MHz.
+        let frequency = 5 + 5 * (config.channel as u32 - 11);
+        r.frequency.write(|w| unsafe { w.bits(frequency) });
+        r.txpower
+            .write(|w| unsafe { w.bits(config.tx_power as u8 as u32) });
+
+        // The energy level is measured in steps of 4 dB, from -92 dBm.
+        let threshold = ((config.cca_threshold as i32 + 92) / 4).clamp(0, 255) as u8;
+        r.ccactrl
+            .write(|w| unsafe { w.ccamode().ed_mode().ccaedthres().bits(threshold) });
+
+        self.config = config;
+    }
+
+    /// Transmit a frame, without its FCS.
+    ///
+    /// The frame is sent once the channel is clear. If it requests an acknowledgement, it's
+    /// retransmitted until it's acknowledged.
+    pub async fn transmit(&mut self, frame: &[u8]) -> Result<(), Error> {
+        assert!(
+            frame.len() >= 3,
+            "a frame has at least a frame control and a sequence number"
+        );
+        if frame.len() > MAX_FRAME_LEN {
+            return Err(Error::FrameTooLarge);
+        }
+
+        let r = regs();
+        let _on_drop = OnDrop::new(disable);
+
+        let fcf = u16::from_le_bytes([frame[0], frame[1]]);
+        let ack = fcf & FCF_ACK_REQUEST != 0;
+
+        for _ in 0..=MAX_FRAME_RETRIES {
+            // The acknowledgement is received in the same buffer, the frame is copied again
+            // for each transmission.
+            self.buf[0] = (frame.len() + FCS_LEN) as u8;
+            self.buf[1..][..frame.len()].copy_from_slice(frame);
+            r.packetptr
+                .write(|w| unsafe { w.bits(self.buf.as_ptr() as u32) });
+
+            self.csma_ca().await?;
+
+            if !ack {
+                return Ok(());
+            }
+
+            r.shorts
+                .write(|w| w.rxready_start().enabled().end_disable().enabled());
+            start(false);
+            let res = with_timeout(ACK_WAIT, async {
+                loop {
+                    wait_disabled().await;
+                    if self.is_ack_for(frame[2]) {
+                        break;
+                    }
+                    start(false);
+                }
+            })
+            .await;
+            match res {
+                Ok(()) => return Ok(()),
+                Err(_) => disable(),
+            }
+        }
+
+        Err(Error::NoAck)
+    }
+
+    /// Send the frame in the buffer with unslotted CSMA-CA: wait for a random backoff, and
+    /// transmit if the channel is clear.
+    async fn csma_ca(&mut self) -> Result<(), Error> {
+        let r = regs();
+        let mut be = MIN_BE;
+
+        for _ in 0..=MAX_CSMA_BACKOFFS {
+            let periods = self.random() & ((1 << be) - 1);
+            Timer::after(Duration::from_micros(periods as u64 * UNIT_BACKOFF_US)).await;
+
+            // CCA as soon as the receiver is ready, then transmit if idle.
+            r.events_ccabusy.reset();
+            r.shorts.write(|w| {
+                w.rxready_ccastart()
+                    .enabled()
+                    .ccaidle_txen()
+                    .enabled()
+                    .ccabusy_disable()
+                    .enabled()
+                    .txready_start()
+                    .enabled()
+                    .end_disable()
+                    .enabled()
+            });
+            start(false);
+            wait_disabled().await;
+
+            if r.events_ccabusy.read().bits() == 0 {
+                return Ok(());
+            }
+            r.events_ccabusy.reset();
+            be = (be + 1).min(MAX_BE);
+        }
+
+        Err(Error::ChannelBusy)
+    }
+
+    fn is_ack_for(&self, seq: u8) -> bool {
+        let fcf = u16::from_le_bytes([self.buf[1], self.buf[2]]);
+        regs().crcstatus.read().crcstatus().is_crcok()
+            && self.buf[0] as usize == 3 + FCS_LEN
+            && fcf & 0b111 == FRAME_TYPE_ACK
+            && self.buf[3] == seq
+    }
+
+    /// Receive a frame into `buf`, without its FCS.
+    ///
+    /// Frames with a wrong FCS, and unless in promiscuous mode frames not addressed to this
+    /// device, are dropped. Frames requesting an acknowledgement are acknowledged.
+    pub async fn receive(&mut self, buf: &mut [u8]) -> Result<RxInfo, Error> {
+        let r = regs();
+        let _on_drop = OnDrop::new(disable);
+
+        loop {
+            r.packetptr
+                .write(|w| unsafe { w.bits(self.buf.as_ptr() as u32) });
+            r.shorts.write(|w| {
+                w.rxready_start()
+                    .enabled()
+                    .end_disable()
+                    .enabled()
+                    .address_rssistart()
+                    .enabled()
+                    .disabled_rssistop()
+                    .enabled()
+            });
+            start(false);
+            wait_disabled().await;
+            let timestamp = Instant::now();
+
+            let len = self.buf[0] as usize;
+            if !r.crcstatus.read().crcstatus().is_crcok() || len < 3 + FCS_LEN {
+                continue;
+            }
+            let len = len - FCS_LEN;
+            let frame = &self.buf[1..][..len];
+
+            let accept = self.config.promiscuous || self.is_for_us(frame);
+            if !accept {
+                continue;
+            }
+
+            let info = RxInfo {
+                len,
+                rssi: -(r.rssisample.read().bits() as i8),
+                timestamp,
+            };
+            let res = if len > buf.len() {
+                Err(Error::BufferTooSmall)
+            } else {
+                buf[..len].copy_from_slice(frame);
+                Ok(info)
+            };
+
+            let fcf = u16::from_le_bytes([frame[0], frame[1]]);
+            let ack =
+                !self.config.promiscuous && fcf & FCF_ACK_REQUEST != 0 && !is_broadcast(frame);
+            let seq = frame[2];
+            if ack {
+                self.send_ack(seq).await;
+            }
+
+            return res;
+        }
+    }
+
+    async fn send_ack(&mut self, seq: u8) {
+        let r = regs();
+
+        self.buf[0] = (3 + FCS_LEN) as u8;
+        self.buf[1..4].copy_from_slice(&[FRAME_TYPE_ACK as u8, 0, seq]);
+        r.shorts
+            .write(|w| w.txready_start().enabled().end_disable().enabled());
+        start(true);
+        wait_disabled().await;
+    }
+
+    /// Check the destination of a frame against the PAN and the addresses of this device.
+    fn is_for_us(&self, frame: &[u8]) -> bool {
+        let c = &self.config;
+        match destination(frame) {
+            // No destination: frames for the PAN coordinator, or beacons.
+            None => true,
+            Some((pan, addr)) => {
+                let pan_ok = pan == 0xFFFF || pan == c.pan_id;
+                let addr_ok = match addr {
+                    Address::Short(a) => a == 0xFFFF || a == c.short_address,
+                    Address::Extended(a) => a == c.extended_address,
+                };
+                pan_ok && addr_ok
+            }
+        }
+    }
+
+    /// The next number of the backoff generator: a xorshift, seeded with the address.
+    fn random(&mut self) -> u32 {
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.rng = x;
+        x
+    }
+
+    /// The current configuration.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+}
+
+impl<'d> Drop for Radio<'d> {
+    fn drop(&mut self) {
+        disable();
+        regs().power.write(|w| w.power().disabled());
+    }
+}
+
+enum Address {
+    Short(u16),
+    /// Most significant byte first: the frames have it the other way around.
+    Extended([u8; 8]),
+}
+
+/// The destination PAN and address of a frame, if it has one.
+fn destination(frame: &[u8]) -> Option<(u16, Address)> {
+    let fcf = u16::from_le_bytes([frame[0], frame[1]]);
+    let get = |range: core::ops::Range<usize>| frame.get(range);
+
+    let pan = u16::from_le_bytes(get(3..5)?.try_into().unwrap());
+    match (fcf >> 10) & 0b11 {
+        0b10 => Some((
+            pan,
+            Address::Short(u16::from_le_bytes(get(5..7)?.try_into().unwrap())),
+        )),
+        0b11 => {
+            let mut a: [u8; 8] = get(5..13)?.try_into().unwrap();
+            a.reverse();
+            Some((pan, Address::Extended(a)))
+        }
+        _ => None,
+    }
+}
+
+fn is_broadcast(frame: &[u8]) -> bool {
+    matches!(destination(frame), Some((_, Address::Short(0xFFFF))))
+}
@@ -0,0 +1,209 @@
+//! embassy-net device on top of the IEEE 802.15.4 radio.
+//!
+//! The stack exchanges frames with a [`Runner`] through queues in a [`State`]: the runner
+//! task owns the radio, and receives frames until the stack has one to transmit.
+//!
+//! ```ignore
+//! static STATE: Forever<State<4, 4>> = Forever::new();
+//! let state = STATE.put(State::new());
+//! let (runner, device) = new(radio, state);
+//! spawner.spawn(radio_task(runner)).unwrap();
+//! // `device` is given to the embassy-net `Stack`.
+//! ```
+
+use core::task::{Context, Waker};
+
+use embassy::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy::channel::channel::Channel;
+use embassy_net::{Device, DeviceCapabilities, LinkState, Medium};
+use futures::future::{select, Either};
+use futures::pin_mut;
+
+use super::{Radio, MAX_FRAME_LEN};
+
+/// A frame in one of the queues, without its FCS.
+pub struct Frame {
+    len: usize,
+    data: [u8; MAX_FRAME_LEN],
+}
+
+impl Frame {
+    const fn new() -> Self {
+        Self {
+            len: 0,
+            data: [0; MAX_FRAME_LEN],
+        }
+    }
+}
+
+/// Queues between the stack and the runner: `RX` received frames, `TX` frames to transmit.
+pub struct State<const RX: usize, const TX: usize> {
+    rx: Channel<CriticalSectionRawMutex, Frame, RX>,
+    tx: Channel<CriticalSectionRawMutex, Frame, TX>,
+}
+
+impl<const RX: usize, const TX: usize> State<RX, TX> {
+    pub fn new() -> Self {
+        Self {
+            rx: Channel::new(),
+            tx: Channel::new(),
+        }
+    }
+}
+
+/// Create the runner driving the radio, and the device to give to the embassy-net stack.
+pub fn new<'d, 'a, const RX: usize, const TX: usize>(
+    radio: Radio<'d>,
+    state: &'a State<RX, TX>,
+) -> (Runner<'d, 'a, RX, TX>, NetDevice<'a, RX, TX>) {
+    let extended_address = radio.config().extended_address;
+    let pan_id = radio.config().pan_id;
+    (
+        Runner { radio, state },
+        NetDevice {
+            state,
+            extended_address,
+            pan_id,
+        },
+    )
+}
+
+/// Task moving the frames between the radio and the queues.
+pub struct Runner<'d, 'a, const RX: usize, const TX: usize> {
+    radio: Radio<'d>,
+    state: &'a State<RX, TX>,
+}
+
+impl<'d, 'a, const RX: usize, const TX: usize> Runner<'d, 'a, RX, TX> {
+    pub async fn run(mut self) -> ! {
+        let mut rx = Frame::new();
+        loop {
+            // Receive until there's a frame to transmit. Cancelling a reception in progress
+            // loses the frame, but the channel is busy anyway: CSMA-CA would delay the
+            // transmission.
+            let res = {
+                let receive = self.radio.receive(&mut rx.data);
+                let transmit = self.state.tx.recv();
+                pin_mut!(receive);
+                pin_mut!(transmit);
+                match select(receive, transmit).await {
+                    Either::Left((res, _)) => Either::Left(res),
+                    Either::Right((frame, _)) => Either::Right(frame),
+                }
+            };
+
+            match res {
+                Either::Left(Ok(info)) => {
+                    rx.len = info.len;
+                    if self.state.rx.try_send(rx).is_err() {
+                        warn!("802.15.4 rx queue full, dropping frame");
+                    }
+                    rx = Frame::new();
+                }
+                Either::Left(Err(e)) => warn!("802.15.4 receive failed: {:?}", e),
+                Either::Right(frame) => {
+                    if let Err(e) = self.radio.transmit(&frame.data[..frame.len]).await {
+                        warn!("802.15.4 transmit failed: {:?}", e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The radio, as an embassy-net device.
+pub struct NetDevice<'a, const RX: usize, const TX: usize> {
+    state: &'a State<RX, TX>,
+    extended_address: [u8; 8],
+    pan_id: u16,
+}
+
+impl<'a, const RX: usize, const TX: usize> Device for NetDevice<'a, RX, TX> {
+    type RxToken<'b>
+        = RxToken
+    where
+        Self: 'b;
+    type TxToken<'b>
+        = TxToken<'a, TX>
+    where
+        Self: 'b;
+
+    fn receive(&mut self) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        if self.state.tx.is_full() {
+            return None;
+        }
+        let frame = self.state.rx.try_recv().ok()?;
+        Some((RxToken { frame }, TxToken { tx: &self.state.tx }))
+    }
+
+    fn transmit(&mut self) -> Option<Self::TxToken<'_>> {
+        if self.state.tx.is_full() {
+            return None;
+        }
+        Some(TxToken { tx: &self.state.tx })
+    }
+
+    fn register_waker(&mut self, waker: &Waker) {
+        let mut cx = Context::from_waker(waker);
+        let _ = self.state.rx.poll_ready_to_recv(&mut cx);
+        let _ = self.state.tx.poll_ready_to_send(&mut cx);
+    }
+
+    fn capabilities(&mut self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        caps.medium = Medium::Ieee802154;
+        caps.max_transmission_unit = MAX_FRAME_LEN;
+        caps.max_burst_size = Some(TX);
+        caps
+    }
+
+    fn link_state(&mut self) -> LinkState {
+        LinkState::Up
+    }
+
+    fn ethernet_address(&mut self) -> [u8; 6] {
+        // Not an Ethernet device, the stack uses `ieee802154_address`.
+        [0; 6]
+    }
+
+    fn ieee802154_address(&mut self) -> [u8; 8] {
+        self.extended_address
+    }
+
+    fn pan_id(&mut self) -> u16 {
+        self.pan_id
+    }
+}
+
+pub struct RxToken {
+    frame: Frame,
+}
+
+impl embassy_net::RxToken for RxToken {
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(&mut self.frame.data[..self.frame.len])
+    }
+}
+
+pub struct TxToken<'a, const TX: usize> {
+    tx: &'a Channel<CriticalSectionRawMutex, Frame, TX>,
+}
+
+impl<'a, const TX: usize> embassy_net::TxToken for TxToken<'a, TX> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut frame = Frame::new();
+        frame.len = len;
+        let r = f(&mut frame.data[..len]);
+        // The token is only handed out when there's room in the queue.
+        if self.tx.try_send(frame).is_err() {
+            warn!("802.15.4 tx queue full, dropping frame");
+        }
+        r
+    }
+}
@@ -0,0 +1,86 @@
+//! 2.4 GHz radio.
+//!
+//! The radio peripheral is used through one of the protocol drivers in the submodules. They
+//! all need the high frequency clock to run from the external crystal: set
+//! [`HfclkSource::ExternalXtal`](crate::config::HfclkSource::ExternalXtal) in the config
+//! passed to [`init`](crate::init).
+
+#[cfg(feature = "_time-driver")]
+pub mod esb;
+#[cfg(all(
+    any(feature = "nrf52820", feature = "nrf52833", feature = "nrf52840"),
+    feature = "_time-driver"
+))]
+pub mod ieee802154;
+
+use core::sync::atomic::{compiler_fence, Ordering};
+use core::task::Poll;
+
+use embassy::interrupt::InterruptExt;
+use embassy::waitqueue::AtomicWaker;
+use futures::future::poll_fn;
+
+use crate::interrupt;
+use crate::pac;
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+fn regs() -> &'static pac::radio::RegisterBlock {
+    unsafe { &*pac::RADIO::ptr() }
+}
+
+/// Set up the radio interrupt. The handler only wakes the driver, which checks the events.
+fn init_irq(irq: &interrupt::RADIO) {
+    irq.disable();
+    irq.set_handler(|_| {
+        regs().intenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        WAKER.wake();
+    });
+    irq.unpend();
+    irq.enable();
+}
+
+/// Start a transmission or a reception with the current shortcuts, by triggering `TXEN` or
+/// `RXEN`.
+fn start(tx: bool) {
+    let r = regs();
+    // The radio reads or writes the packet buffer by DMA.
+    compiler_fence(Ordering::SeqCst);
+    r.events_disabled.reset();
+    if tx {
+        r.tasks_txen.write(|w| unsafe { w.bits(1) });
+    } else {
+        r.tasks_rxen.write(|w| unsafe { w.bits(1) });
+    }
+}
+
+/// Wait for the `DISABLED` event: every operation ends with the `END_DISABLE` shortcut.
+async fn wait_disabled() {
+    let r = regs();
+    poll_fn(|cx| {
+        WAKER.register(cx.waker());
+        if r.events_disabled.read().bits() != 0 {
+            r.events_disabled.reset();
+            compiler_fence(Ordering::SeqCst);
+            Poll::Ready(())
+        } else {
+            r.intenset.write(|w| w.disabled().set());
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+/// Stop any operation in progress, and wait until the radio is disabled.
+fn disable() {
+    let r = regs();
+    r.shorts.reset();
+    r.intenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+    if !r.state.read().state().is_disabled() {
+        r.events_disabled.reset();
+        r.tasks_disable.write(|w| unsafe { w.bits(1) });
+        while r.events_disabled.read().bits() == 0 {}
+    }
+    r.events_disabled.reset();
+    compiler_fence(Ordering::SeqCst);
+}
@@ -0,0 +1,548 @@
+#![macro_use]
+
+//! HAL interface to the SPIS peripheral (SPI slave).
+//!
+//! The SPIS peripheral shares its buffers with the CPU through a hardware semaphore:
+//! the CPU must own the semaphore (ACQUIRE) to update the EasyDMA pointers, and has to
+//! hand it back (RELEASE) for the peripheral to be able to take part in a transaction.
+//! This driver handles the semaphore for you, and keeps it acquired by the CPU between
+//! transactions using the END_ACQUIRE short.
+
+use core::marker::PhantomData;
+use core::sync::atomic::{compiler_fence, Ordering};
+use core::task::Poll;
+use embassy::interrupt::InterruptExt;
+use embassy::util::Unborrow;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::chip::{EASY_DMA_SIZE, FORCE_COPY_BUFFER_SIZE};
+use crate::gpio::sealed::Pin as _;
+use crate::gpio::{self, AnyPin};
+use crate::gpio::{Pin as GpioPin, PselBits};
+use crate::interrupt::Interrupt;
+use crate::util::{slice_ptr_parts, slice_ptr_parts_mut};
+use crate::{pac, util::slice_in_ram_or};
+
+pub use embedded_hal_02::spi::{Mode, Phase, Polarity, MODE_0, MODE_1, MODE_2, MODE_3};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    TxBufferTooLong,
+    RxBufferTooLong,
+    /// EasyDMA can only read from data memory, read only buffers in flash will fail.
+    DMABufferNotInDataMemory,
+}
+
+/// Interface for the SPIS peripheral using EasyDMA to offload the transmission and reception workload.
+///
+/// For more details about EasyDMA, consult the module documentation.
+pub struct Spis<'d, T: Instance> {
+    phantom: PhantomData<&'d mut T>,
+}
+
+#[non_exhaustive]
+pub struct Config {
+    pub mode: Mode,
+    /// Over-read character, clocked out after the TX buffer has been exhausted.
+    pub orc: u8,
+    /// Default character, clocked out when the CPU holds the semaphore
+    /// while the master starts a transaction.
+    pub def: u8,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            mode: MODE_0,
+            orc: 0x00,
+            def: 0x00,
+        }
+    }
+}
+
+/// Status flags reported by the peripheral for the last transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Status {
+    /// The master clocked out more bytes than the TX buffer held. `orc` was sent for the rest.
+    pub overread: bool,
+    /// The master clocked in more bytes than the RX buffer could hold. They were dropped.
+    pub overflow: bool,
+}
+
+impl<'d, T: Instance> Spis<'d, T> {
+    pub fn new(
+        spis: impl Unborrow<Target = T> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        cs: impl Unborrow<Target = impl GpioPin> + 'd,
+        sck: impl Unborrow<Target = impl GpioPin> + 'd,
+        miso: impl Unborrow<Target = impl GpioPin> + 'd,
+        mosi: impl Unborrow<Target = impl GpioPin> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(cs, sck, miso, mosi);
+        Self::new_inner(
+            spis,
+            irq,
+            cs.degrade(),
+            sck.degrade(),
+            Some(miso.degrade()),
+            Some(mosi.degrade()),
+            config,
+        )
+    }
+
+    pub fn new_txonly(
+        spis: impl Unborrow<Target = T> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        cs: impl Unborrow<Target = impl GpioPin> + 'd,
+        sck: impl Unborrow<Target = impl GpioPin> + 'd,
+        miso: impl Unborrow<Target = impl GpioPin> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(cs, sck, miso);
+        Self::new_inner(
+            spis,
+            irq,
+            cs.degrade(),
+            sck.degrade(),
+            Some(miso.degrade()),
+            None,
+            config,
+        )
+    }
+
+    pub fn new_rxonly(
+        spis: impl Unborrow<Target = T> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        cs: impl Unborrow<Target = impl GpioPin> + 'd,
+        sck: impl Unborrow<Target = impl GpioPin> + 'd,
+        mosi: impl Unborrow<Target = impl GpioPin> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(cs, sck, mosi);
+        Self::new_inner(
+            spis,
+            irq,
+            cs.degrade(),
+            sck.degrade(),
+            None,
+            Some(mosi.degrade()),
+            config,
+        )
+    }
+
+    fn new_inner(
+        _spis: impl Unborrow<Target = T> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        cs: AnyPin,
+        sck: AnyPin,
+        miso: Option<AnyPin>,
+        mosi: Option<AnyPin>,
+        config: Config,
+    ) -> Self {
+        unborrow!(irq);
+
+        let r = T::regs();
+
+        // Configure pins. All of them are inputs, the peripheral takes over
+        // the MISO direction only while CSN is asserted.
+        cs.conf().write(|w| w.input().connect().drive().h0h1());
+        sck.conf().write(|w| w.input().connect().drive().h0h1());
+        if let Some(mosi) = &mosi {
+            mosi.conf().write(|w| w.input().connect().drive().h0h1());
+        }
+        if let Some(miso) = &miso {
+            miso.conf().write(|w| w.input().connect().drive().h0h1());
+        }
+
+        // Select pins.
+        r.psel.csn.write(|w| unsafe { w.bits(cs.psel_bits()) });
+        r.psel.sck.write(|w| unsafe { w.bits(sck.psel_bits()) });
+        r.psel.mosi.write(|w| unsafe { w.bits(mosi.psel_bits()) });
+        r.psel.miso.write(|w| unsafe { w.bits(miso.psel_bits()) });
+
+        // Enable SPIS instance.
+        r.enable.write(|w| w.enable().enabled());
+
+        // Configure mode.
+        let mode = config.mode;
+        r.config.write(|w| {
+            match mode {
+                MODE_0 => {
+                    w.order().msb_first();
+                    w.cpol().active_high();
+                    w.cpha().leading();
+                }
+                MODE_1 => {
+                    w.order().msb_first();
+                    w.cpol().active_high();
+                    w.cpha().trailing();
+                }
+                MODE_2 => {
+                    w.order().msb_first();
+                    w.cpol().active_low();
+                    w.cpha().leading();
+                }
+                MODE_3 => {
+                    w.order().msb_first();
+                    w.cpol().active_low();
+                    w.cpha().trailing();
+                }
+            }
+
+            w
+        });
+
+        // Set over-read and default characters.
+        r.orc.write(|w| unsafe { w.orc().bits(config.orc) });
+        r.def.write(|w| unsafe { w.def().bits(config.def) });
+
+        // Hand the semaphore back to the CPU as soon as a transaction ends,
+        // so the buffers can't be used again before we set up new ones.
+        r.shorts.write(|w| w.end_acquire().bit(true));
+
+        // Disable all events interrupts
+        r.intenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+
+        irq.set_handler(Self::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        Self {
+            phantom: PhantomData,
+        }
+    }
+
+    fn on_interrupt(_: *mut ()) {
+        let r = T::regs();
+        let s = T::state();
+
+        if r.events_end.read().bits() != 0 {
+            s.waker.wake();
+            r.intenclr.write(|w| w.end().clear());
+        }
+
+        if r.events_acquired.read().bits() != 0 {
+            s.waker.wake();
+            r.intenclr.write(|w| w.acquired().clear());
+        }
+    }
+
+    /// Returns true if the CPU currently owns the semaphore.
+    fn cpu_owns_semaphore() -> bool {
+        T::regs().semstat.read().bits() == 1
+    }
+
+    fn blocking_acquire(&mut self) {
+        let r = T::regs();
+
+        if Self::cpu_owns_semaphore() {
+            return;
+        }
+
+        r.events_acquired.reset();
+        r.tasks_acquire.write(|w| unsafe { w.bits(1) });
+        while r.events_acquired.read().bits() == 0 {}
+    }
+
+    async fn async_acquire(&mut self) {
+        let r = T::regs();
+
+        if Self::cpu_owns_semaphore() {
+            return;
+        }
+
+        r.events_acquired.reset();
+        r.intenset.write(|w| w.acquired().set());
+        r.tasks_acquire.write(|w| unsafe { w.bits(1) });
+
+        poll_fn(|cx| {
+            T::state().waker.register(cx.waker());
+            if r.events_acquired.read().bits() != 0 {
+                return Poll::Ready(());
+            }
+
+            Poll::Pending
+        })
+        .await;
+    }
+
+    /// Set up the DMA buffers and release the semaphore to the peripheral.
+    ///
+    /// The CPU must own the semaphore when calling this.
+    fn prepare(&mut self, rx: *mut [u8], tx: *const [u8]) -> Result<(), Error> {
+        slice_in_ram_or(tx, Error::DMABufferNotInDataMemory)?;
+        // NOTE: RAM slice check for rx is not necessary, as a mutable
+        // slice can only be built from data located in RAM.
+
+        compiler_fence(Ordering::SeqCst);
+
+        let r = T::regs();
+
+        // Set up the DMA write.
+        let (ptr, len) = slice_ptr_parts(tx);
+        if len > EASY_DMA_SIZE {
+            return Err(Error::TxBufferTooLong);
+        }
+        r.txd.ptr.write(|w| unsafe { w.ptr().bits(ptr as _) });
+        r.txd.maxcnt.write(|w| unsafe { w.maxcnt().bits(len as _) });
+
+        // Set up the DMA read.
+        let (ptr, len) = slice_ptr_parts_mut(rx);
+        if len > EASY_DMA_SIZE {
+            return Err(Error::RxBufferTooLong);
+        }
+        r.rxd.ptr.write(|w| unsafe { w.ptr().bits(ptr as _) });
+        r.rxd.maxcnt.write(|w| unsafe { w.maxcnt().bits(len as _) });
+
+        // Clear the status flags from the previous transaction.
+        r.status.write(|w| w.overflow().clear().overread().clear());
+
+        // Reset and enable the event
+        r.events_end.reset();
+        r.intenset.write(|w| w.end().set());
+
+        // Hand the buffers over to the peripheral.
+        r.tasks_release.write(|w| unsafe { w.bits(1) });
+
+        Ok(())
+    }
+
+    /// Returns the number of bytes received and sent in the last transaction.
+    fn amounts(&self) -> (usize, usize) {
+        let r = T::regs();
+        (
+            r.rxd.amount.read().bits() as usize,
+            r.txd.amount.read().bits() as usize,
+        )
+    }
+
+    fn blocking_inner_from_ram(
+        &mut self,
+        rx: *mut [u8],
+        tx: *const [u8],
+    ) -> Result<(usize, usize), Error> {
+        self.blocking_acquire();
+        self.prepare(rx, tx)?;
+
+        // Wait for 'end' event.
+        while T::regs().events_end.read().bits() == 0 {}
+
+        compiler_fence(Ordering::SeqCst);
+
+        Ok(self.amounts())
+    }
+
+    fn blocking_inner(&mut self, rx: &mut [u8], tx: &[u8]) -> Result<(usize, usize), Error> {
+        match self.blocking_inner_from_ram(rx, tx) {
+            Ok(n) => Ok(n),
+            Err(Error::DMABufferNotInDataMemory) => {
+                trace!("Copying SPIS tx buffer into RAM for DMA");
+                let tx_ram_buf = &mut [0; FORCE_COPY_BUFFER_SIZE][..tx.len()];
+                tx_ram_buf.copy_from_slice(tx);
+                self.blocking_inner_from_ram(rx, tx_ram_buf)
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    async fn async_inner_from_ram(
+        &mut self,
+        rx: *mut [u8],
+        tx: *const [u8],
+    ) -> Result<(usize, usize), Error> {
+        self.async_acquire().await;
+        self.prepare(rx, tx)?;
+
+        // Wait for 'end' event.
+        poll_fn(|cx| {
+            T::state().waker.register(cx.waker());
+            if T::regs().events_end.read().bits() != 0 {
+                return Poll::Ready(());
+            }
+
+            Poll::Pending
+        })
+        .await;
+
+        compiler_fence(Ordering::SeqCst);
+
+        Ok(self.amounts())
+    }
+
+    async fn async_inner(&mut self, rx: &mut [u8], tx: &[u8]) -> Result<(usize, usize), Error> {
+        match self.async_inner_from_ram(rx, tx).await {
+            Ok(n) => Ok(n),
+            Err(Error::DMABufferNotInDataMemory) => {
+                trace!("Copying SPIS tx buffer into RAM for DMA");
+                let tx_ram_buf = &mut [0; FORCE_COPY_BUFFER_SIZE][..tx.len()];
+                tx_ram_buf.copy_from_slice(tx);
+                self.async_inner_from_ram(rx, tx_ram_buf).await
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Returns the status flags of the last transaction.
+    pub fn status(&self) -> Status {
+        let s = T::regs().status.read();
+        Status {
+            overread: s.overread().is_present(),
+            overflow: s.overflow().is_present(),
+        }
+    }
+
+    /// Reads data from the SPI bus without sending anything. Blocks until CS is deasserted.
+    /// Returns number of bytes read.
+    pub fn blocking_read(&mut self, data: &mut [u8]) -> Result<usize, Error> {
+        self.blocking_inner(data, &[]).map(|n| n.0)
+    }
+
+    /// Simultaneously sends and receives data. Blocks until the transmission is completed.
+    /// If necessary, the write buffer will be copied into RAM (see struct description for detail).
+    /// Returns number of bytes transferred `(n_rx, n_tx)`.
+    pub fn blocking_transfer(
+        &mut self,
+        read: &mut [u8],
+        write: &[u8],
+    ) -> Result<(usize, usize), Error> {
+        self.blocking_inner(read, write)
+    }
+
+    /// Same as [`blocking_transfer`](Spis::blocking_transfer) but will fail instead of copying data into RAM. Consult the module level documentation to learn more.
+    pub fn blocking_transfer_from_ram(
+        &mut self,
+        read: &mut [u8],
+        write: &[u8],
+    ) -> Result<(usize, usize), Error> {
+        self.blocking_inner_from_ram(read, write)
+    }
+
+    /// Simultaneously sends and receives data.
+    /// Places the received data into the same buffer and blocks until the transmission is completed.
+    /// Returns number of bytes transferred.
+    pub fn blocking_transfer_in_place(&mut self, data: &mut [u8]) -> Result<usize, Error> {
+        self.blocking_inner_from_ram(data, data).map(|n| n.0)
+    }
+
+    /// Sends data, discarding any received data. Blocks until the transmission is completed.
+    /// If necessary, the write buffer will be copied into RAM (see struct description for detail).
+    /// Returns number of bytes written.
+    pub fn blocking_write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.blocking_inner(&mut [], data).map(|n| n.1)
+    }
+
+    /// Same as [`blocking_write`](Spis::blocking_write) but will fail instead of copying data into RAM. Consult the module level documentation to learn more.
+    pub fn blocking_write_from_ram(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.blocking_inner_from_ram(&mut [], data).map(|n| n.1)
+    }
+
+    /// Reads data from the SPI bus without sending anything.
+    /// Returns number of bytes read.
+    pub async fn read(&mut self, data: &mut [u8]) -> Result<usize, Error> {
+        self.async_inner(data, &[]).await.map(|n| n.0)
+    }
+
+    /// Simultaneously sends and receives data.
+    /// If necessary, the write buffer will be copied into RAM (see struct description for detail).
+    /// Returns number of bytes transferred `(n_rx, n_tx)`.
+    pub async fn transfer(
+        &mut self,
+        read: &mut [u8],
+        write: &[u8],
+    ) -> Result<(usize, usize), Error> {
+        self.async_inner(read, write).await
+    }
+
+    /// Same as [`transfer`](Spis::transfer) but will fail instead of copying data into RAM. Consult the module level documentation to learn more.
+    pub async fn transfer_from_ram(
+        &mut self,
+        read: &mut [u8],
+        write: &[u8],
+    ) -> Result<(usize, usize), Error> {
+        self.async_inner_from_ram(read, write).await
+    }
+
+    /// Simultaneously sends and receives data. Places the received data into the same buffer.
+    /// Returns number of bytes transferred.
+    pub async fn transfer_in_place(&mut self, data: &mut [u8]) -> Result<usize, Error> {
+        self.async_inner_from_ram(data, data).await.map(|n| n.0)
+    }
+
+    /// Sends data, discarding any received data.
+    /// If necessary, the write buffer will be copied into RAM (see struct description for detail).
+    /// Returns number of bytes written.
+    pub async fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.async_inner(&mut [], data).await.map(|n| n.1)
+    }
+
+    /// Same as [`write`](Spis::write) but will fail instead of copying data into RAM. Consult the module level documentation to learn more.
+    pub async fn write_from_ram(&mut self, data: &[u8]) -> Result<usize, Error> {
+        self.async_inner_from_ram(&mut [], data).await.map(|n| n.1)
+    }
+}
+
+impl<'d, T: Instance> Drop for Spis<'d, T> {
+    fn drop(&mut self) {
+        trace!("spis drop");
+
+        // Disable
+        let r = T::regs();
+        r.enable.write(|w| w.enable().disabled());
+
+        gpio::deconfigure_pin(r.psel.sck.read().bits());
+        gpio::deconfigure_pin(r.psel.csn.read().bits());
+        gpio::deconfigure_pin(r.psel.miso.read().bits());
+        gpio::deconfigure_pin(r.psel.mosi.read().bits());
+
+        trace!("spis drop: done");
+    }
+}
+
+pub(crate) mod sealed {
+    use embassy::waitqueue::AtomicWaker;
+
+    use super::*;
+
+    pub struct State {
+        pub waker: AtomicWaker,
+    }
+
+    impl State {
+        pub const fn new() -> Self {
+            Self {
+                waker: AtomicWaker::new(),
+            }
+        }
+    }
+
+    pub trait Instance {
+        fn regs() -> &'static pac::spis0::RegisterBlock;
+        fn state() -> &'static State;
+    }
+}
+
+pub trait Instance: Unborrow<Target = Self> + sealed::Instance + 'static {
+    type Interrupt: Interrupt;
+}
+
+macro_rules! impl_spis {
+    ($type:ident, $pac_type:ident, $irq:ident) => {
+        impl crate::spis::sealed::Instance for peripherals::$type {
+            fn regs() -> &'static pac::spis0::RegisterBlock {
+                unsafe { &*pac::$pac_type::ptr() }
+            }
+            fn state() -> &'static crate::spis::sealed::State {
+                static STATE: crate::spis::sealed::State = crate::spis::sealed::State::new();
+                &STATE
+            }
+        }
+        impl crate::spis::Instance for peripherals::$type {
+            type Interrupt = crate::interrupt::$irq;
+        }
+    };
+}
@@ -1,4 +1,17 @@
 //! Temperature sensor interface.
+//!
+//! Measures the die temperature in steps of 0.25 °C, which is returned as a
+//! fixed-point [`I30F2`] in degrees Celsius.
+//!
+//! # Calibration
+//!
+//! The sensor is factory-calibrated through the `A`/`B`/`T` registers, which
+//! the hardware applies on its own, but it measures the die, not the ambient
+//! temperature. Expect an offset of a couple of degrees while the radio or the
+//! CPU are busy. The nRF52840 product specification gives an accuracy of
+//! ±5 °C over the full range (±2.5 °C between 0 and 60 °C), so for better
+//! absolute readings measure the offset against a reference once and subtract
+//! it from every reading.
 
 use crate::interrupt;
 use crate::pac;
@@ -62,6 +75,7 @@ impl<'d> Temp<'d> {
         });
 
         let t = Self::regs();
+        t.events_datardy.reset();
         t.intenset.write(|w| w.datardy().set());
         unsafe { t.tasks_start.write(|w| w.bits(1)) };
 
@@ -80,6 +94,22 @@ impl<'d> Temp<'d> {
         value
     }
 
+    /// Perform a blocking temperature measurement.
+    ///
+    /// A measurement takes about 36us.
+    pub fn blocking_read(&mut self) -> I30F2 {
+        let t = Self::regs();
+        t.events_datardy.reset();
+        t.intenclr.write(|w| w.datardy().clear());
+        unsafe { t.tasks_start.write(|w| w.bits(1)) };
+
+        while t.events_datardy.read().bits() == 0 {}
+        t.events_datardy.reset();
+
+        let raw = t.temp.read().bits();
+        I30F2::from_bits(raw as i32)
+    }
+
     fn regs() -> &'static pac::temp::RegisterBlock {
         unsafe { &*pac::TEMP::ptr() }
     }
@@ -10,6 +10,12 @@ use embassy::time::driver::{AlarmHandle, Driver};
 use crate::interrupt;
 use crate::pac;
 
+// The RTC always ticks at 32768hz.
+const _: () = assert!(
+    embassy::time::TICKS_PER_SECOND == 32_768,
+    "the nrf time driver only supports time-tick-32768hz"
+);
+
 fn rtc() -> &'static pac::rtc0::RegisterBlock {
     unsafe { &*pac::RTC1::ptr() }
 }
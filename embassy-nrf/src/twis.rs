@@ -0,0 +1,535 @@
+#![macro_use]
+
+//! HAL interface to the TWIS peripheral (I2C slave).
+//!
+//! See product specification:
+//!
+//! - nRF52832: Section 34
+//! - nRF52840: Section 6.32
+use core::future::Future;
+use core::marker::PhantomData;
+use core::sync::atomic::{compiler_fence, Ordering::SeqCst};
+use core::task::Poll;
+use embassy::interrupt::{Interrupt, InterruptExt};
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::chip::{EASY_DMA_SIZE, FORCE_COPY_BUFFER_SIZE};
+use crate::gpio;
+use crate::gpio::Pin as GpioPin;
+use crate::pac;
+use crate::util::slice_in_ram_or;
+
+#[non_exhaustive]
+pub struct Config {
+    /// First address the slave answers to.
+    pub address0: u8,
+    /// Optional second address the slave answers to.
+    pub address1: Option<u8>,
+    /// Over-read character, clocked out after the TX buffer has been exhausted.
+    pub orc: u8,
+    pub sda_pullup: bool,
+    pub scl_pullup: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            address0: 0x55,
+            address1: None,
+            orc: 0x00,
+            sda_pullup: false,
+            scl_pullup: false,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    TxBufferTooLong,
+    RxBufferTooLong,
+    /// The master wrote more bytes than the RX buffer could hold.
+    Overflow,
+    /// The master NACKed a byte while reading.
+    DataNack,
+    /// The master read more bytes than the TX buffer held. `orc` was sent for the rest.
+    OverRead,
+    DMABufferNotInDataMemory,
+}
+
+/// The command issued by the master, as returned by [`Twis::listen`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Command {
+    /// The master wants to read. Respond with [`Twis::respond_to_read`].
+    Read,
+    /// The master wrote `n` bytes and then issued a repeated start to read.
+    /// Respond with [`Twis::respond_to_read`].
+    WriteRead(usize),
+    /// The master wrote `n` bytes.
+    Write(usize),
+}
+
+/// Interface to a TWIS instance using EasyDMA to offload the transmission and reception workload.
+///
+/// For more details about EasyDMA, consult the module documentation.
+pub struct Twis<'d, T: Instance> {
+    phantom: PhantomData<&'d mut T>,
+}
+
+impl<'d, T: Instance> Twis<'d, T> {
+    pub fn new(
+        _twis: impl Unborrow<Target = T> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        sda: impl Unborrow<Target = impl GpioPin> + 'd,
+        scl: impl Unborrow<Target = impl GpioPin> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(irq, sda, scl);
+
+        let r = T::regs();
+
+        // Configure pins
+        sda.conf().write(|w| {
+            w.dir().input();
+            w.input().connect();
+            w.drive().s0d1();
+            if config.sda_pullup {
+                w.pull().pullup();
+            }
+            w
+        });
+        scl.conf().write(|w| {
+            w.dir().input();
+            w.input().connect();
+            w.drive().s0d1();
+            if config.scl_pullup {
+                w.pull().pullup();
+            }
+            w
+        });
+
+        // Select pins.
+        r.psel.sda.write(|w| unsafe { w.bits(sda.psel_bits()) });
+        r.psel.scl.write(|w| unsafe { w.bits(scl.psel_bits()) });
+
+        // Enable TWIS instance.
+        r.enable.write(|w| w.enable().enabled());
+
+        // Configure addresses.
+        r.address[0].write(|w| unsafe { w.address().bits(config.address0) });
+        if let Some(address1) = config.address1 {
+            r.address[1].write(|w| unsafe { w.address().bits(address1) });
+        }
+        r.config.write(|w| {
+            w.address0().enabled();
+            w.address1().bit(config.address1.is_some());
+            w
+        });
+
+        // Set over-read character
+        r.orc.write(|w| unsafe { w.orc().bits(config.orc) });
+
+        // Suspend on READ, so the TX buffer can be prepared once we know what
+        // the master asked for.
+        r.shorts.write(|w| w.read_suspend().enabled());
+
+        // Disable all events interrupts
+        r.intenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+
+        irq.set_handler(Self::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        Self {
+            phantom: PhantomData,
+        }
+    }
+
+    fn on_interrupt(_: *mut ()) {
+        let r = T::regs();
+        let s = T::state();
+
+        if r.events_read.read().bits() != 0 {
+            s.waker.wake();
+            r.intenclr.write(|w| w.read().clear());
+        }
+        if r.events_write.read().bits() != 0 {
+            s.waker.wake();
+            r.intenclr.write(|w| w.write().clear());
+        }
+        if r.events_stopped.read().bits() != 0 {
+            s.waker.wake();
+            r.intenclr.write(|w| w.stopped().clear());
+        }
+        if r.events_error.read().bits() != 0 {
+            s.waker.wake();
+            r.intenclr.write(|w| w.error().clear());
+        }
+    }
+
+    /// Set TX buffer, checking that it is in RAM and has suitable length.
+    unsafe fn set_tx_buffer(&mut self, buffer: &[u8]) -> Result<(), Error> {
+        slice_in_ram_or(buffer, Error::DMABufferNotInDataMemory)?;
+
+        if buffer.len() > EASY_DMA_SIZE {
+            return Err(Error::TxBufferTooLong);
+        }
+
+        let r = T::regs();
+
+        r.txd.ptr.write(|w|
+            // We're giving the register a pointer to the stack. Since we're
+            // waiting for the I2C transaction to end before this stack pointer
+            // becomes invalid, there's nothing wrong here.
+            w.ptr().bits(buffer.as_ptr() as u32));
+        r.txd.maxcnt.write(|w|
+            // We're giving it the length of the buffer, so no danger of
+            // accessing invalid memory.
+            w.maxcnt().bits(buffer.len() as _));
+
+        Ok(())
+    }
+
+    /// Set RX buffer, checking that it has suitable length.
+    unsafe fn set_rx_buffer(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        // NOTE: RAM slice check is not necessary, as a mutable
+        // slice can only be built from data located in RAM.
+
+        if buffer.len() > EASY_DMA_SIZE {
+            return Err(Error::RxBufferTooLong);
+        }
+
+        let r = T::regs();
+
+        r.rxd.ptr.write(|w|
+            // We're giving the register a pointer to the stack. Since we're
+            // waiting for the I2C transaction to end before this stack pointer
+            // becomes invalid, there's nothing wrong here.
+            w.ptr().bits(buffer.as_mut_ptr() as u32));
+        r.rxd.maxcnt.write(|w|
+            // We're giving it the length of the buffer, so no danger of
+            // accessing invalid memory.
+            w.maxcnt().bits(buffer.len() as _));
+
+        Ok(())
+    }
+
+    fn clear_errorsrc(&mut self) {
+        let r = T::regs();
+        r.errorsrc.write(|w| {
+            w.overflow().bit(true);
+            w.overread().bit(true);
+            w.dnack().bit(true);
+            w
+        });
+    }
+
+    /// Get Error instance, if any occurred.
+    fn check_errorsrc(&self) -> Result<(), Error> {
+        let r = T::regs();
+
+        let err = r.errorsrc.read();
+        if err.overflow().is_detected() {
+            return Err(Error::Overflow);
+        }
+        if err.dnack().is_received() {
+            return Err(Error::DataNack);
+        }
+        if err.overread().is_detected() {
+            return Err(Error::OverRead);
+        }
+        Ok(())
+    }
+
+    /// Returns the index of the address that matched in the last transaction.
+    pub fn address_match_index(&self) -> usize {
+        T::regs().match_.read().bits() as usize
+    }
+
+    /// Returns the address that matched in the last transaction.
+    pub fn address_match(&self) -> u8 {
+        let r = T::regs();
+        r.address[self.address_match_index()]
+            .read()
+            .address()
+            .bits()
+    }
+
+    fn setup_listen(&mut self, buffer: &mut [u8], inten: bool) -> Result<(), Error> {
+        let r = T::regs();
+
+        compiler_fence(SeqCst);
+
+        // Set up the DMA read.
+        unsafe { self.set_rx_buffer(buffer)? };
+
+        // Clear events
+        r.events_read.reset();
+        r.events_write.reset();
+        r.events_stopped.reset();
+        r.events_error.reset();
+        self.clear_errorsrc();
+
+        if inten {
+            r.intenset.write(|w| {
+                w.read().set();
+                w.write().set();
+                w.stopped().set();
+                w.error().set();
+                w
+            });
+        } else {
+            r.intenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        }
+
+        r.tasks_preparerx.write(|w| unsafe { w.bits(1) });
+        Ok(())
+    }
+
+    fn setup_respond_from_ram(&mut self, buffer: &[u8], inten: bool) -> Result<(), Error> {
+        let r = T::regs();
+
+        compiler_fence(SeqCst);
+
+        // Set up the DMA write.
+        unsafe { self.set_tx_buffer(buffer)? };
+
+        // Clear events
+        r.events_stopped.reset();
+        r.events_error.reset();
+        self.clear_errorsrc();
+
+        if inten {
+            r.intenset.write(|w| w.stopped().set().error().set());
+        } else {
+            r.intenclr.write(|w| unsafe { w.bits(0xFFFF_FFFF) });
+        }
+
+        r.tasks_preparetx.write(|w| unsafe { w.bits(1) });
+        r.tasks_resume.write(|w| unsafe { w.bits(1) });
+        Ok(())
+    }
+
+    /// Translate the events seen after `setup_listen` into a command.
+    ///
+    /// Returns `None` if the transaction is still in progress.
+    fn poll_command(&self, seen_write: &mut bool) -> Option<Result<Command, Error>> {
+        let r = T::regs();
+
+        if r.events_error.read().bits() != 0 {
+            r.events_error.reset();
+            r.tasks_stop.write(|w| unsafe { w.bits(1) });
+            return Some(self.check_errorsrc().map(|_| Command::Write(0)));
+        }
+        if r.events_write.read().bits() != 0 {
+            r.events_write.reset();
+            *seen_write = true;
+        }
+        if r.events_read.read().bits() != 0 {
+            r.events_read.reset();
+            let n = r.rxd.amount.read().bits() as usize;
+            return Some(Ok(if *seen_write {
+                Command::WriteRead(n)
+            } else {
+                Command::Read
+            }));
+        }
+        if r.events_stopped.read().bits() != 0 {
+            r.events_stopped.reset();
+            let n = r.rxd.amount.read().bits() as usize;
+            return Some(Ok(Command::Write(n)));
+        }
+        None
+    }
+
+    fn blocking_wait_stopped(&mut self) -> Result<usize, Error> {
+        let r = T::regs();
+        loop {
+            if r.events_error.read().bits() != 0 {
+                r.events_error.reset();
+                r.tasks_stop.write(|w| unsafe { w.bits(1) });
+                return self.check_errorsrc().map(|_| 0);
+            }
+            if r.events_stopped.read().bits() != 0 {
+                r.events_stopped.reset();
+                return Ok(r.txd.amount.read().bits() as usize);
+            }
+        }
+    }
+
+    fn async_wait_stopped(&mut self) -> impl Future<Output = Result<usize, Error>> {
+        poll_fn(move |cx| {
+            let r = T::regs();
+            let s = T::state();
+
+            s.waker.register(cx.waker());
+
+            if r.events_error.read().bits() != 0 {
+                r.events_error.reset();
+                r.tasks_stop.write(|w| unsafe { w.bits(1) });
+                return Poll::Ready(self.check_errorsrc().map(|_| 0));
+            }
+            if r.events_stopped.read().bits() != 0 {
+                r.events_stopped.reset();
+                return Poll::Ready(Ok(r.txd.amount.read().bits() as usize));
+            }
+
+            Poll::Pending
+        })
+    }
+
+    /// Wait for the master to address this slave, blocking.
+    ///
+    /// Data written by the master is stored in `buffer`. If the returned command
+    /// is a [`Command::Read`] or [`Command::WriteRead`], the bus is held (clock stretched)
+    /// until [`blocking_respond_to_read`](Twis::blocking_respond_to_read) is called.
+    pub fn blocking_listen(&mut self, buffer: &mut [u8]) -> Result<Command, Error> {
+        self.setup_listen(buffer, false)?;
+        let mut seen_write = false;
+        loop {
+            if let Some(res) = self.poll_command(&mut seen_write) {
+                compiler_fence(SeqCst);
+                return res;
+            }
+        }
+    }
+
+    /// Respond to a read command, blocking. Returns the number of bytes the master read.
+    ///
+    /// If necessary, `buffer` will be copied into RAM (see module description for detail).
+    pub fn blocking_respond_to_read(&mut self, buffer: &[u8]) -> Result<usize, Error> {
+        match self.blocking_respond_to_read_from_ram(buffer) {
+            Err(Error::DMABufferNotInDataMemory) => {
+                trace!("Copying TWIS tx buffer into RAM for DMA");
+                let tx_ram_buf = &mut [0; FORCE_COPY_BUFFER_SIZE][..buffer.len()];
+                tx_ram_buf.copy_from_slice(buffer);
+                self.blocking_respond_to_read_from_ram(tx_ram_buf)
+            }
+            res => res,
+        }
+    }
+
+    /// Same as [`blocking_respond_to_read`](Twis::blocking_respond_to_read) but will fail instead of copying data into RAM. Consult the module level documentation to learn more.
+    pub fn blocking_respond_to_read_from_ram(&mut self, buffer: &[u8]) -> Result<usize, Error> {
+        self.setup_respond_from_ram(buffer, false)?;
+        let res = self.blocking_wait_stopped();
+        compiler_fence(SeqCst);
+        res
+    }
+
+    /// Wait for the master to address this slave.
+    ///
+    /// Data written by the master is stored in `buffer`. If the returned command
+    /// is a [`Command::Read`] or [`Command::WriteRead`], the bus is held (clock stretched)
+    /// until [`respond_to_read`](Twis::respond_to_read) is called.
+    pub async fn listen(&mut self, buffer: &mut [u8]) -> Result<Command, Error> {
+        self.setup_listen(buffer, true)?;
+        let mut seen_write = false;
+        let res = poll_fn(|cx| {
+            T::state().waker.register(cx.waker());
+            match self.poll_command(&mut seen_write) {
+                Some(res) => Poll::Ready(res),
+                None => {
+                    // The interrupt handler disables each event it sees, re-enable them.
+                    T::regs().intenset.write(|w| {
+                        w.read().set();
+                        w.stopped().set();
+                        w.error().set();
+                        w
+                    });
+                    Poll::Pending
+                }
+            }
+        })
+        .await;
+        compiler_fence(SeqCst);
+        res
+    }
+
+    /// Respond to a read command. Returns the number of bytes the master read.
+    ///
+    /// If necessary, `buffer` will be copied into RAM (see module description for detail).
+    pub async fn respond_to_read(&mut self, buffer: &[u8]) -> Result<usize, Error> {
+        match self.respond_to_read_from_ram(buffer).await {
+            Err(Error::DMABufferNotInDataMemory) => {
+                trace!("Copying TWIS tx buffer into RAM for DMA");
+                let tx_ram_buf = &mut [0; FORCE_COPY_BUFFER_SIZE][..buffer.len()];
+                tx_ram_buf.copy_from_slice(buffer);
+                self.respond_to_read_from_ram(tx_ram_buf).await
+            }
+            res => res,
+        }
+    }
+
+    /// Same as [`respond_to_read`](Twis::respond_to_read) but will fail instead of copying data into RAM. Consult the module level documentation to learn more.
+    pub async fn respond_to_read_from_ram(&mut self, buffer: &[u8]) -> Result<usize, Error> {
+        self.setup_respond_from_ram(buffer, true)?;
+        let res = self.async_wait_stopped().await;
+        compiler_fence(SeqCst);
+        res
+    }
+}
+
+impl<'a, T: Instance> Drop for Twis<'a, T> {
+    fn drop(&mut self) {
+        trace!("twis drop");
+
+        // TODO: check for abort
+
+        // disable!
+        let r = T::regs();
+        r.enable.write(|w| w.enable().disabled());
+
+        gpio::deconfigure_pin(r.psel.sda.read().bits());
+        gpio::deconfigure_pin(r.psel.scl.read().bits());
+
+        trace!("twis drop: done");
+    }
+}
+
+pub(crate) mod sealed {
+    use super::*;
+
+    pub struct State {
+        pub waker: AtomicWaker,
+    }
+
+    impl State {
+        pub const fn new() -> Self {
+            Self {
+                waker: AtomicWaker::new(),
+            }
+        }
+    }
+
+    pub trait Instance {
+        fn regs() -> &'static pac::twis0::RegisterBlock;
+        fn state() -> &'static State;
+    }
+}
+
+pub trait Instance: Unborrow<Target = Self> + sealed::Instance + 'static {
+    type Interrupt: Interrupt;
+}
+
+macro_rules! impl_twis {
+    ($type:ident, $pac_type:ident, $irq:ident) => {
+        impl crate::twis::sealed::Instance for peripherals::$type {
+            fn regs() -> &'static pac::twis0::RegisterBlock {
+                unsafe { &*pac::$pac_type::ptr() }
+            }
+            fn state() -> &'static crate::twis::sealed::State {
+                static STATE: crate::twis::sealed::State = crate::twis::sealed::State::new();
+                &STATE
+            }
+        }
+        impl crate::twis::Instance for peripherals::$type {
+            type Interrupt = crate::interrupt::$irq;
+        }
+    };
+}
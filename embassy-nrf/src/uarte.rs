@@ -198,6 +198,19 @@ impl<'d, T: Instance> Uarte<'d, T> {
         (self.tx, self.rx)
     }
 
+    /// Change the baud rate.
+    ///
+    /// No transfer is in progress, as they borrow the Uarte, so this takes effect
+    /// from the next one.
+    pub fn set_baudrate(&mut self, baudrate: Baudrate) {
+        T::regs().baudrate.write(|w| w.baudrate().variant(baudrate));
+    }
+
+    /// Change the parity.
+    pub fn set_parity(&mut self, parity: Parity) {
+        T::regs().config.modify(|_, w| w.parity().variant(parity));
+    }
+
     /// Return the endtx event for use with PPI
     pub fn event_endtx(&self) -> Event {
         let r = T::regs();
@@ -25,6 +25,8 @@ use crate::util::slice_in_ram;
 const NEW_AW: AtomicWaker = AtomicWaker::new();
 static BUS_WAKER: AtomicWaker = NEW_AW;
 static EP0_WAKER: AtomicWaker = NEW_AW;
+static SOF_WAKER: AtomicWaker = NEW_AW;
+static REGULATOR_WAKER: AtomicWaker = NEW_AW;
 static EP_IN_WAKERS: [AtomicWaker; 8] = [NEW_AW; 8];
 static EP_OUT_WAKERS: [AtomicWaker; 8] = [NEW_AW; 8];
 static READY_ENDPOINTS: AtomicU32 = AtomicU32::new(0);
@@ -36,6 +38,10 @@ pub struct Driver<'d, T: Instance> {
 }
 
 impl<'d, T: Instance> Driver<'d, T> {
+    /// Create the driver.
+    ///
+    /// Enabling the bus waits for the USB regulator: call [`on_power_interrupt`] from the
+    /// `POWER_CLOCK` interrupt handler (`USBREGULATOR` on nRF5340), and enable that interrupt.
     pub fn new(
         _usb: impl Unborrow<Target = T> + 'd,
         irq: impl Unborrow<Target = T::Interrupt> + 'd,
@@ -52,6 +58,16 @@ impl<'d, T: Instance> Driver<'d, T> {
         }
     }
 
+    /// Get a handle to the start-of-frame events of the bus.
+    ///
+    /// Call this before handing the driver to the `UsbDeviceBuilder`. Only one task
+    /// should wait for start-of-frame events at a time.
+    pub fn sof(&mut self) -> Sof<'d, T> {
+        Sof {
+            phantom: PhantomData,
+        }
+    }
+
     fn on_interrupt(_: *mut ()) {
         let regs = T::regs();
 
@@ -71,6 +87,11 @@ impl<'d, T: Instance> Driver<'d, T> {
             EP0_WAKER.wake();
         }
 
+        if regs.events_sof.read().bits() != 0 {
+            regs.intenclr.write(|w| w.sof().clear());
+            SOF_WAKER.wake();
+        }
+
         // USBEVENT and EPDATA events are weird. They're the "aggregate"
         // of individual bits in EVENTCAUSE and EPDATASTATUS. We handle them
         // differently than events normally.
@@ -116,6 +137,14 @@ impl<'d, T: Instance> Driver<'d, T> {
                     w.io().bit(ep_addr.is_in());
                     w.stall().bit(stalled)
                 });
+                if !stalled {
+                    // Clearing the halt resets the data toggle.
+                    regs.dtoggle.write(|w| {
+                        w.ep().bits(ep_addr.index() as u8 & 0b111);
+                        w.io().bit(ep_addr.is_in());
+                        w.value().data0()
+                    });
+                }
             }
         }
 
@@ -179,6 +208,27 @@ impl<'d, T: Instance> driver::Driver<'d> for Driver<'d, T> {
         }))
     }
 
+    /// The USBD holds a packet in its own buffer: an OUT endpoint is ready for the next packet
+    /// as soon as the previous one is copied out by `read`, so regular endpoints are already
+    /// double-buffered.
+    fn alloc_endpoint_out_double_buffered(
+        &mut self,
+        ep_addr: Option<EndpointAddress>,
+        max_packet_size: u16,
+    ) -> Result<Self::EndpointOut, driver::EndpointAllocError> {
+        self.alloc_endpoint_out(ep_addr, EndpointType::Bulk, max_packet_size, 0)
+    }
+
+    /// `write` returns once the packet is copied to the buffer of the USBD, and the next one
+    /// can be prepared while it's sent, so regular endpoints are already double-buffered.
+    fn alloc_endpoint_in_double_buffered(
+        &mut self,
+        ep_addr: Option<EndpointAddress>,
+        max_packet_size: u16,
+    ) -> Result<Self::EndpointIn, driver::EndpointAllocError> {
+        self.alloc_endpoint_in(ep_addr, EndpointType::Bulk, max_packet_size, 0)
+    }
+
     fn alloc_control_pipe(
         &mut self,
         max_packet_size: u16,
@@ -216,6 +266,21 @@ impl<'d, T: Instance> driver::Bus for Bus<'d, T> {
         async move {
             let regs = T::regs();
 
+            // Wait until the USB regulator has VBUS and its output is stable.
+            // Enabling USBD before that leaves it stuck waiting for READY.
+            poll_fn(|cx| {
+                REGULATOR_WAKER.register(cx.waker());
+                // Listen before checking, so the event can't be missed in between.
+                usbreg::listen();
+                if usbreg::output_ready() {
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            })
+            .await;
+            usbreg::unlisten();
+
             errata::pre_enable();
 
             regs.enable.write(|w| w.enable().enabled());
@@ -264,6 +329,7 @@ impl<'d, T: Instance> driver::Bus for Bus<'d, T> {
             if regs.events_usbreset.read().bits() != 0 {
                 regs.events_usbreset.reset();
                 regs.intenset.write(|w| w.usbreset().set());
+                exit_low_power(regs);
                 self.set_configured(false);
                 return Poll::Ready(Event::Reset);
             }
@@ -280,11 +346,17 @@ impl<'d, T: Instance> driver::Bus for Bus<'d, T> {
             }
             if r.suspend().bit() {
                 regs.eventcause.write(|w| w.suspend().set_bit());
-                regs.lowpower.write(|w| w.lowpower().low_power());
+                // Low power mode stops the USBD clock until resume, a reset or a remote
+                // wakeup, so that the device fits in the suspend current budget. Don't
+                // enter it if the host already resumed the bus.
+                if !regs.eventcause.read().resume().bit() {
+                    regs.lowpower.write(|w| w.lowpower().low_power());
+                }
                 return Poll::Ready(Event::Suspend);
             }
             if r.resume().bit() {
                 regs.eventcause.write(|w| w.resume().set_bit());
+                exit_low_power(regs);
                 return Poll::Ready(Event::Resume);
             }
             if r.ready().bit() {
@@ -391,6 +463,63 @@ impl<'d, T: Instance> driver::Bus for Bus<'d, T> {
     }
 }
 
+/// Handle the USB regulator part of the `POWER_CLOCK` interrupt, or of the `USBREGULATOR`
+/// interrupt on nRF5340.
+///
+/// Enabling the bus waits for the regulator output, which is only ready once VBUS is
+/// present. Like [`power::on_interrupt`](crate::power::on_interrupt), the driver doesn't
+/// install a handler for this shared interrupt: call this from yours, and enable the
+/// interrupt. It only touches the USBPWRRDY event.
+pub fn on_power_interrupt() {
+    if usbreg::pending() {
+        usbreg::unlisten();
+        REGULATOR_WAKER.wake();
+    }
+}
+
+/// Leave the low power mode entered on suspend, if the peripheral is in it.
+fn exit_low_power(regs: &RegisterBlock) {
+    if regs.lowpower.read().lowpower().is_low_power() {
+        errata::pre_wakeup();
+        regs.lowpower.write(|w| w.lowpower().force_normal());
+        errata::post_wakeup();
+    }
+}
+
+/// Start-of-frame events of the bus, created with [`Driver::sof`].
+///
+/// The host starts a frame every millisecond while the bus isn't suspended, which makes
+/// it a clock to measure feedback for isochronous endpoints against.
+pub struct Sof<'d, T: Instance> {
+    phantom: PhantomData<&'d mut T>,
+}
+
+impl<'d, T: Instance> Sof<'d, T> {
+    /// Number of the last frame started by the host, 11 bits wide.
+    pub fn frame_number(&self) -> u16 {
+        T::regs().framecntr.read().framecntr().bits()
+    }
+
+    /// Wait for the host to start a frame, and return its number.
+    pub async fn wait(&mut self) -> u16 {
+        let regs = T::regs();
+
+        regs.events_sof.reset();
+        regs.intenset.write(|w| w.sof().set());
+
+        poll_fn(|cx| {
+            SOF_WAKER.register(cx.waker());
+            if regs.events_sof.read().bits() != 0 {
+                regs.events_sof.reset();
+                Poll::Ready(self.frame_number())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
 pub enum Out {}
 pub enum In {}
 
@@ -864,6 +993,44 @@ macro_rules! impl_usb {
     };
 }
 
+mod usbreg {
+    use crate::pac;
+
+    // On nRF52 the regulator lives in the POWER peripheral, while the nRF5340 application
+    // core has a separate USBREGULATOR peripheral. Both have the same registers.
+    #[cfg(not(feature = "_nrf5340-app"))]
+    fn regs() -> &'static pac::power::RegisterBlock {
+        unsafe { &*pac::POWER::ptr() }
+    }
+
+    #[cfg(feature = "_nrf5340-app")]
+    fn regs() -> &'static pac::usbregulator::RegisterBlock {
+        unsafe { &*pac::USBREGULATOR::ptr() }
+    }
+
+    /// Returns true if the USB regulator output is ready.
+    pub fn output_ready() -> bool {
+        regs().usbregstatus.read().outputrdy().is_ready()
+    }
+
+    /// Clear the USBPWRRDY event, and enable its interrupt.
+    pub fn listen() {
+        let r = regs();
+        r.events_usbpwrrdy.reset();
+        r.intenset.write(|w| w.usbpwrrdy().set());
+    }
+
+    pub fn unlisten() {
+        regs().intenclr.write(|w| w.usbpwrrdy().clear());
+    }
+
+    /// Returns true if the USBPWRRDY event is set and its interrupt enabled.
+    pub fn pending() -> bool {
+        let r = regs();
+        r.events_usbpwrrdy.read().bits() != 0 && r.intenset.read().usbpwrrdy().is_enabled()
+    }
+}
+
 mod errata {
 
     /// Writes `val` to `addr`. Used to apply Errata workarounds.
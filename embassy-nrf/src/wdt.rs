@@ -2,8 +2,16 @@
 //!
 //! This HAL implements a basic watchdog timer with 1..=8 handles.
 //! Once the watchdog has been started, it cannot be stopped.
+//!
+//! Each handle maps to one reload request register. The watchdog only gets
+//! reloaded once *all* handles have been pet in the current period, so giving
+//! one handle to each task that must make progress makes the chip reset as
+//! soon as any of them stalls.
 
+#[cfg(not(feature = "_nrf5340-app"))]
 use crate::pac::WDT;
+#[cfg(feature = "_nrf5340-app")]
+use crate::pac::WDT0 as WDT;
 use crate::peripherals;
 
 const MIN_TICKS: u32 = 15;
@@ -58,9 +66,9 @@ impl Watchdog {
         let crv = config.timeout_ticks.max(MIN_TICKS);
         let rren = (1u32 << N) - 1;
 
-        #[cfg(not(feature = "_nrf9160"))]
+        #[cfg(not(any(feature = "_nrf5340", feature = "_nrf9160")))]
         let runstatus = r.runstatus.read().runstatus().bit();
-        #[cfg(feature = "_nrf9160")]
+        #[cfg(any(feature = "_nrf5340", feature = "_nrf9160"))]
         let runstatus = r.runstatus.read().runstatuswdt().bit();
 
         if runstatus {
@@ -124,10 +132,19 @@ impl Watchdog {
     /// handles to prevent a reset this time period.
     #[inline(always)]
     pub fn awaiting_pets(&self) -> bool {
+        self.pending_handles() != 0
+    }
+
+    /// Bitmask of the handles that haven't been pet yet in the current period.
+    ///
+    /// Bit `n` is set if the handle with index `n` still has to be pet. Reading
+    /// this from the watchdog interrupt tells which task stalled.
+    #[inline(always)]
+    pub fn pending_handles(&self) -> u32 {
         let r = unsafe { &*WDT::ptr() };
         let enabled = r.rren.read().bits();
         let status = r.reqstatus.read().bits();
-        (status & enabled) == 0
+        status & enabled
     }
 }
 
@@ -156,6 +173,11 @@ impl WatchdogHandle {
         ((rd >> idx) & 0x1) == 0
     }
 
+    /// Index of the reload request register this handle pets.
+    pub fn index(&self) -> u8 {
+        self.index
+    }
+
     /// Steal a watchdog handle by index.
     ///
     /// Safety: watchdog must be initialized, index must be between 0 and N-1 where
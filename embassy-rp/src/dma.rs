@@ -1,7 +1,23 @@
+use core::marker::PhantomData;
+use core::pin::Pin;
 use core::sync::atomic::{compiler_fence, Ordering};
+use core::task::{Context, Poll};
+
+use embassy::interrupt::{Interrupt, InterruptExt};
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::{unborrow, unsafe_impl_unborrow};
+use futures::Future;
 
 use crate::pac::dma::vals;
-use crate::{pac, peripherals};
+use crate::{interrupt, pac, peripherals};
+
+const CHANNEL_COUNT: usize = 12;
+const NEW_AW: AtomicWaker = AtomicWaker::new();
+static CHANNEL_WAKERS: [AtomicWaker; CHANNEL_COUNT] = [NEW_AW; CHANNEL_COUNT];
+
+/// DREQ to pace the transfers at the speed of the CPU, for memory to memory copies.
+const TREQ_PERMANENT: u8 = 0x3f;
 
 pub struct Dma<T: Channel> {
     _inner: T,
@@ -35,7 +51,228 @@ impl<T: Channel> Dma<T> {
     }
 }
 
-mod sealed {
+/// safety: must be called exactly once at bootup
+pub(crate) unsafe fn init() {
+    let irq = interrupt::DMA_IRQ_0::steal();
+    irq.disable();
+    pac::DMA
+        .inte0()
+        .write(|w| w.set_inte0((1 << CHANNEL_COUNT) - 1));
+    irq.enable();
+}
+
+#[interrupt]
+unsafe fn DMA_IRQ_0() {
+    let ints0 = pac::DMA.ints0().read().ints0();
+    for channel in 0..CHANNEL_COUNT {
+        if ints0 & (1 << channel) != 0 {
+            if pac::DMA.ch(channel).ctrl_trig().read().ahb_error() {
+                panic!("DMA: bus error on channel {}", channel);
+            }
+            CHANNEL_WAKERS[channel].wake();
+        }
+    }
+    pac::DMA.ints0().write(|w| w.set_ints0(ints0));
+}
+
+/// Read from a peripheral register into a buffer, paced by `dreq`.
+///
+/// Safety: `from` must stay valid for the whole transfer.
+pub unsafe fn read<'a, C: Channel, W: Word>(
+    ch: impl Unborrow<Target = C> + 'a,
+    from: *const W,
+    to: &'a mut [W],
+    dreq: u8,
+) -> Transfer<'a, C> {
+    let len = to.len();
+    copy_inner(
+        ch,
+        from as *const u32,
+        to.as_mut_ptr() as *mut u32,
+        len,
+        W::size(),
+        false,
+        true,
+        dreq,
+    )
+}
+
+/// Read `len` words from a peripheral register and discard them, paced by `dreq`.
+///
+/// Safety: `from` must stay valid for the whole transfer.
+pub unsafe fn read_repeated<'a, C: Channel, W: Word>(
+    ch: impl Unborrow<Target = C> + 'a,
+    from: *const W,
+    len: usize,
+    dreq: u8,
+) -> Transfer<'a, C> {
+    static mut DUMMY: u32 = 0;
+    copy_inner(
+        ch,
+        from as *const u32,
+        &mut DUMMY as *mut u32,
+        len,
+        W::size(),
+        false,
+        false,
+        dreq,
+    )
+}
+
+/// Write a buffer to a peripheral register, paced by `dreq`.
+///
+/// Safety: `to` must stay valid for the whole transfer.
+pub unsafe fn write<'a, C: Channel, W: Word>(
+    ch: impl Unborrow<Target = C> + 'a,
+    from: &'a [W],
+    to: *mut W,
+    dreq: u8,
+) -> Transfer<'a, C> {
+    copy_inner(
+        ch,
+        from.as_ptr() as *const u32,
+        to as *mut u32,
+        from.len(),
+        W::size(),
+        true,
+        false,
+        dreq,
+    )
+}
+
+/// Write `len` zero words to a peripheral register, paced by `dreq`.
+///
+/// Safety: `to` must stay valid for the whole transfer.
+pub unsafe fn write_repeated<'a, C: Channel, W: Word>(
+    ch: impl Unborrow<Target = C> + 'a,
+    to: *mut W,
+    len: usize,
+    dreq: u8,
+) -> Transfer<'a, C> {
+    static DUMMY: u32 = 0;
+    copy_inner(
+        ch,
+        &DUMMY as *const u32,
+        to as *mut u32,
+        len,
+        W::size(),
+        false,
+        false,
+        dreq,
+    )
+}
+
+/// Copy a buffer to another, in memory. The CPU is free during the copy, which completes
+/// with an interrupt.
+pub fn copy<'a, C: Channel, W: Word>(
+    ch: impl Unborrow<Target = C> + 'a,
+    from: &'a [W],
+    to: &'a mut [W],
+) -> Transfer<'a, C> {
+    assert!(from.len() == to.len());
+    unsafe {
+        copy_inner(
+            ch,
+            from.as_ptr() as *const u32,
+            to.as_mut_ptr() as *mut u32,
+            from.len(),
+            W::size(),
+            true,
+            true,
+            TREQ_PERMANENT,
+        )
+    }
+}
+
+unsafe fn copy_inner<'a, C: Channel>(
+    ch: impl Unborrow<Target = C> + 'a,
+    from: *const u32,
+    to: *mut u32,
+    len: usize,
+    data_size: vals::DataSize,
+    incr_read: bool,
+    incr_write: bool,
+    dreq: u8,
+) -> Transfer<'a, C> {
+    unborrow!(ch);
+
+    let p = ch.regs();
+
+    p.read_addr().write_value(from as u32);
+    p.write_addr().write_value(to as u32);
+    p.trans_count().write_value(len as u32);
+
+    compiler_fence(Ordering::SeqCst);
+
+    p.ctrl_trig().write(|w| {
+        w.set_treq_sel(vals::TreqSel(dreq));
+        w.set_data_size(data_size);
+        w.set_incr_read(incr_read);
+        w.set_incr_write(incr_write);
+        w.set_chain_to(ch.number());
+        w.set_en(true);
+    });
+
+    compiler_fence(Ordering::SeqCst);
+
+    Transfer {
+        channel: ch,
+        phantom: PhantomData,
+    }
+}
+
+/// A DMA transfer in progress. It completes when awaited to the end, and is aborted when
+/// dropped.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Transfer<'a, C: Channel> {
+    channel: C,
+    phantom: PhantomData<&'a mut C>,
+}
+
+impl<'a, C: Channel> Transfer<'a, C> {
+    /// Whether the transfer is still running.
+    pub fn is_running(&self) -> bool {
+        unsafe { self.channel.regs().ctrl_trig().read().busy() }
+    }
+
+    /// Stop the transfer before its end. Returns the number of words left to transfer.
+    pub fn abort(&mut self) -> usize {
+        unsafe {
+            let p = self.channel.regs();
+            pac::DMA
+                .chan_abort()
+                .write(|w| w.set_chan_abort(1 << self.channel.number()));
+            while p.ctrl_trig().read().busy() {}
+            p.trans_count().read() as usize
+        }
+    }
+}
+
+impl<'a, C: Channel> Drop for Transfer<'a, C> {
+    fn drop(&mut self) {
+        if self.is_running() {
+            self.abort();
+        }
+        compiler_fence(Ordering::SeqCst);
+    }
+}
+
+impl<'a, C: Channel> Unpin for Transfer<'a, C> {}
+impl<'a, C: Channel> Future for Transfer<'a, C> {
+    type Output = ();
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        CHANNEL_WAKERS[self.channel.number() as usize].register(cx.waker());
+
+        if self.is_running() {
+            Poll::Pending
+        } else {
+            compiler_fence(Ordering::SeqCst);
+            Poll::Ready(())
+        }
+    }
+}
+
+pub(crate) mod sealed {
     use super::*;
 
     pub trait Channel {
@@ -45,14 +282,55 @@ mod sealed {
             pac::DMA.ch(self.number() as _)
         }
     }
+
+    pub trait Word {}
 }
 
-pub trait Channel: sealed::Channel {}
+pub trait Channel: sealed::Channel + Unborrow<Target = Self> + Sized {
+    fn degrade(self) -> AnyChannel {
+        AnyChannel {
+            number: self.number(),
+        }
+    }
+}
+
+/// Size of the elements a transfer moves at a time.
+pub trait Word: sealed::Word {
+    fn size() -> vals::DataSize;
+}
+
+impl sealed::Word for u8 {}
+impl Word for u8 {
+    fn size() -> vals::DataSize {
+        vals::DataSize::SIZE_BYTE
+    }
+}
+
+impl sealed::Word for u16 {}
+impl Word for u16 {
+    fn size() -> vals::DataSize {
+        vals::DataSize::SIZE_HALFWORD
+    }
+}
+
+impl sealed::Word for u32 {}
+impl Word for u32 {
+    fn size() -> vals::DataSize {
+        vals::DataSize::SIZE_WORD
+    }
+}
+
+/// Placeholder for drivers used without a DMA channel, which only support blocking
+/// operations.
+pub struct NoDma;
+
+unsafe_impl_unborrow!(NoDma);
 
 pub struct AnyChannel {
     number: u8,
 }
 
+unsafe_impl_unborrow!(AnyChannel);
 impl Channel for AnyChannel {}
 impl sealed::Channel for AnyChannel {
     fn number(&self) -> u8 {
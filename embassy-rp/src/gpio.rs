@@ -1,14 +1,23 @@
 use core::convert::Infallible;
+use core::future::Future;
 use core::marker::PhantomData;
+use core::pin::Pin as FuturePin;
+use core::task::{Context, Poll};
 
 use crate::pac;
 use crate::pac::common::{Reg, RW};
 use crate::pac::SIO;
-use crate::peripherals;
+use crate::{interrupt, peripherals};
 
+use embassy::interrupt::{Interrupt, InterruptExt};
 use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
 use embassy_hal_common::{unborrow, unsafe_impl_unborrow};
 
+const PIN_COUNT: usize = 30;
+const NEW_AW: AtomicWaker = AtomicWaker::new();
+static INTERRUPT_WAKERS: [AtomicWaker; PIN_COUNT] = [NEW_AW; PIN_COUNT];
+
 /// Represents a digital input or output level.
 #[derive(Debug, Eq, PartialEq)]
 pub enum Level {
@@ -72,6 +81,34 @@ impl<'d, T: Pin> Input<'d, T> {
         let val = 1 << self.pin.pin();
         unsafe { self.pin.sio_in().read() & val == 0 }
     }
+
+    /// Wait until the pin is high. Returns immediately if it's already high.
+    ///
+    /// Waiting is only supported on the pins of bank 0, not on the QSPI pins.
+    pub async fn wait_for_high(&mut self) {
+        if self.is_low() {
+            InputFuture::new(&mut self.pin, InterruptTrigger::LevelHigh).await;
+        }
+    }
+
+    /// Wait until the pin is low. Returns immediately if it's already low.
+    pub async fn wait_for_low(&mut self) {
+        if self.is_high() {
+            InputFuture::new(&mut self.pin, InterruptTrigger::LevelLow).await;
+        }
+    }
+
+    pub async fn wait_for_rising_edge(&mut self) {
+        InputFuture::new(&mut self.pin, InterruptTrigger::EdgeHigh).await;
+    }
+
+    pub async fn wait_for_falling_edge(&mut self) {
+        InputFuture::new(&mut self.pin, InterruptTrigger::EdgeLow).await;
+    }
+
+    pub async fn wait_for_any_edge(&mut self) {
+        InputFuture::new(&mut self.pin, InterruptTrigger::AnyEdge).await;
+    }
 }
 
 impl<'d, T: Pin> Drop for Input<'d, T> {
@@ -80,6 +117,135 @@ impl<'d, T: Pin> Drop for Input<'d, T> {
     }
 }
 
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+enum InterruptTrigger {
+    LevelLow,
+    LevelHigh,
+    EdgeLow,
+    EdgeHigh,
+    AnyEdge,
+}
+
+/// safety: must be called exactly once at bootup
+pub(crate) unsafe fn init() {
+    let irq = interrupt::IO_IRQ_BANK0::steal();
+    irq.disable();
+    irq.enable();
+}
+
+#[interrupt]
+unsafe fn IO_IRQ_BANK0() {
+    // Each core has its own set of interrupt registers.
+    let cpu = SIO.cpuid().read() as usize;
+    let proc_int = pac::IO_BANK0.int_proc(cpu);
+
+    for pin in 0..PIN_COUNT {
+        // The INTS registers hold the LEVEL_LOW, LEVEL_HIGH, EDGE_LOW and EDGE_HIGH bits of 8
+        // pins each.
+        let group = pin % 8;
+        let ints = proc_int.ints(pin / 8).read();
+        if (ints.0 >> (group * 4)) & 0xf != 0 {
+            // Disable the interrupt of the pin: this is how the future knows it fired, and
+            // level interrupts would keep firing otherwise.
+            critical_section::with(|_| {
+                proc_int.inte(pin / 8).modify(|w| {
+                    w.set_level_low(group, false);
+                    w.set_level_high(group, false);
+                    w.set_edge_low(group, false);
+                    w.set_edge_high(group, false);
+                });
+            });
+            INTERRUPT_WAKERS[pin].wake();
+        }
+    }
+}
+
+struct InputFuture<'a, T: Pin> {
+    pin: &'a mut T,
+    trigger: InterruptTrigger,
+}
+
+impl<'a, T: Pin> InputFuture<'a, T> {
+    fn new(pin: &'a mut T, trigger: InterruptTrigger) -> Self {
+        assert!(
+            pin.bank() == Bank::Bank0,
+            "waiting is not supported on QSPI pins"
+        );
+
+        let group = (pin.pin() % 8) as usize;
+        unsafe {
+            // Clear a stale edge, so only the edges from now on are seen.
+            pac::IO_BANK0.intr((pin.pin() / 8) as usize).write(|w| {
+                w.set_edge_low(group, true);
+                w.set_edge_high(group, true);
+            });
+
+            critical_section::with(|_| {
+                pin.int_proc()
+                    .inte((pin.pin() / 8) as usize)
+                    .modify(|w| match trigger {
+                        InterruptTrigger::LevelLow => w.set_level_low(group, true),
+                        InterruptTrigger::LevelHigh => w.set_level_high(group, true),
+                        InterruptTrigger::EdgeLow => w.set_edge_low(group, true),
+                        InterruptTrigger::EdgeHigh => w.set_edge_high(group, true),
+                        InterruptTrigger::AnyEdge => {
+                            w.set_edge_low(group, true);
+                            w.set_edge_high(group, true);
+                        }
+                    });
+            });
+        }
+
+        Self { pin, trigger }
+    }
+}
+
+impl<'a, T: Pin> Drop for InputFuture<'a, T> {
+    fn drop(&mut self) {
+        let group = (self.pin.pin() % 8) as usize;
+        critical_section::with(|_| unsafe {
+            self.pin
+                .int_proc()
+                .inte((self.pin.pin() / 8) as usize)
+                .modify(|w| {
+                    w.set_level_low(group, false);
+                    w.set_level_high(group, false);
+                    w.set_edge_low(group, false);
+                    w.set_edge_high(group, false);
+                });
+        });
+    }
+}
+
+impl<'a, T: Pin> Future for InputFuture<'a, T> {
+    type Output = ();
+
+    fn poll(self: FuturePin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        INTERRUPT_WAKERS[self.pin.pin() as usize].register(cx.waker());
+
+        // The interrupt handler disables the interrupt of the pin when it fires.
+        let group = (self.pin.pin() % 8) as usize;
+        let inte = unsafe {
+            self.pin
+                .int_proc()
+                .inte((self.pin.pin() / 8) as usize)
+                .read()
+        };
+        let enabled = match self.trigger {
+            InterruptTrigger::LevelLow => inte.level_low(group),
+            InterruptTrigger::LevelHigh => inte.level_high(group),
+            InterruptTrigger::EdgeLow => inte.edge_low(group),
+            InterruptTrigger::EdgeHigh => inte.edge_high(group),
+            InterruptTrigger::AnyEdge => inte.edge_low(group) || inte.edge_high(group),
+        };
+        if enabled {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    }
+}
+
 pub struct Output<'d, T: Pin> {
     pin: T,
     phantom: PhantomData<&'d mut T>,
@@ -182,6 +348,16 @@ pub(crate) mod sealed {
         fn sio_in(&self) -> Reg<u32, RW> {
             SIO.gpio_in(self.bank() as _)
         }
+
+        /// Interrupt registers of the core this runs on.
+        fn int_proc(&self) -> pac::io::Int {
+            let block = match self.bank() {
+                Bank::Bank0 => crate::pac::IO_BANK0,
+                Bank::Qspi => crate::pac::IO_QSPI,
+            };
+            let cpu = unsafe { SIO.cpuid().read() };
+            block.int_proc(cpu as _)
+        }
     }
 }
 
@@ -338,3 +514,42 @@ mod eh1 {
         }
     }
 }
+
+#[cfg(all(feature = "unstable-traits", feature = "nightly"))]
+mod eha {
+    use futures::FutureExt;
+
+    use super::*;
+
+    impl<'d, T: Pin> embedded_hal_async::digital::Wait for Input<'d, T> {
+        type WaitForHighFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+        fn wait_for_high<'a>(&'a mut self) -> Self::WaitForHighFuture<'a> {
+            self.wait_for_high().map(Ok)
+        }
+
+        type WaitForLowFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+        fn wait_for_low<'a>(&'a mut self) -> Self::WaitForLowFuture<'a> {
+            self.wait_for_low().map(Ok)
+        }
+
+        type WaitForRisingEdgeFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+        fn wait_for_rising_edge<'a>(&'a mut self) -> Self::WaitForRisingEdgeFuture<'a> {
+            self.wait_for_rising_edge().map(Ok)
+        }
+
+        type WaitForFallingEdgeFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+        fn wait_for_falling_edge<'a>(&'a mut self) -> Self::WaitForFallingEdgeFuture<'a> {
+            self.wait_for_falling_edge().map(Ok)
+        }
+
+        type WaitForAnyEdgeFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
+
+        fn wait_for_any_edge<'a>(&'a mut self) -> Self::WaitForAnyEdgeFuture<'a> {
+            self.wait_for_any_edge().map(Ok)
+        }
+    }
+}
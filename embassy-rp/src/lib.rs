@@ -10,14 +10,17 @@ pub(crate) use rp2040_pac2 as pac;
 pub use embassy::util::Unborrow;
 pub use embassy_hal_common::unborrow;
 
-// This mod MUST go first, so that the others see its macros.
-pub(crate) mod fmt;
+#[macro_use]
+extern crate embassy_fmt;
 
 pub mod interrupt;
 pub use embassy_macros::interrupt;
 
 pub mod dma;
 pub mod gpio;
+pub mod i2c;
+pub mod multicore;
+pub mod pio;
 pub mod spi;
 pub mod timer;
 pub mod uart;
@@ -69,6 +72,12 @@ embassy_hal_common::peripherals! {
     SPI0,
     SPI1,
 
+    I2C0,
+    I2C1,
+
+    PIO0,
+    PIO1,
+
     DMA_CH0,
     DMA_CH1,
     DMA_CH2,
@@ -81,6 +90,8 @@ embassy_hal_common::peripherals! {
     DMA_CH9,
     DMA_CH10,
     DMA_CH11,
+
+    CORE1,
 }
 
 #[link_section = ".boot2"]
@@ -106,6 +117,9 @@ pub fn init(_config: config::Config) -> Peripherals {
     unsafe {
         clocks::init();
         timer::init();
+        dma::init();
+        gpio::init();
+        pio::init();
     }
 
     peripherals
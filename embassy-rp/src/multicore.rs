@@ -0,0 +1,252 @@
+//! Second core and inter-core FIFOs.
+//!
+//! Each core has a FIFO to send 32-bit words to the other one, 8 words deep. They're used to
+//! start core 1, and can then carry anything: commands, or pointers to shared data.
+//!
+//! Core 1 is started with [`spawn_core1`], on its own stack. It can run its own executor:
+//!
+//! ```ignore
+//! static mut CORE1_STACK: Stack<4096> = Stack::new();
+//! static EXECUTOR1: Forever<Executor> = Forever::new();
+//!
+//! spawn_core1(p.CORE1, unsafe { &mut CORE1_STACK }, move || {
+//!     let executor1 = EXECUTOR1.put(Executor::new());
+//!     executor1.run(|spawner| unwrap!(spawner.spawn(core1_task())));
+//! });
+//! ```
+//!
+//! The executors of each core must be distinct: tasks can't move from a core to the other.
+//!
+//! Critical sections only mask the interrupts of the core they run on, they don't protect
+//! the data shared with the other core.
+
+use core::mem::ManuallyDrop;
+use core::sync::atomic::{compiler_fence, Ordering};
+use core::task::Poll;
+
+use embassy::interrupt::{Interrupt, InterruptExt};
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::pac::SIO;
+use crate::{interrupt, pac, peripherals};
+
+const NEW_AW: AtomicWaker = AtomicWaker::new();
+/// Wakers of the tasks reading the FIFO, per core.
+static FIFO_WAKERS: [AtomicWaker; 2] = [NEW_AW; 2];
+
+/// Stack of core 1, in words.
+#[repr(C, align(8))]
+pub struct Stack<const SIZE: usize> {
+    pub mem: [usize; SIZE],
+}
+
+impl<const SIZE: usize> Stack<SIZE> {
+    pub const fn new() -> Self {
+        Self { mem: [0; SIZE] }
+    }
+}
+
+/// Start core 1, running `entry` on `stack`.
+///
+/// Core 1 is reset first, so this can be called again to restart it. It must not run any
+/// code from flash that's being written.
+pub fn spawn_core1<F, const SIZE: usize>(
+    core1: impl Unborrow<Target = peripherals::CORE1>,
+    stack: &'static mut Stack<SIZE>,
+    entry: F,
+) where
+    F: FnOnce() + Send + 'static,
+{
+    unborrow!(core1);
+    let _ = core1;
+
+    // Run on core 1, from the boot ROM, with the arguments passed in registers.
+    extern "C" fn core1_startup<F: FnOnce()>(
+        _: u64,
+        _: u64,
+        entry: &mut ManuallyDrop<F>,
+        _stack_bottom: *mut usize,
+    ) -> ! {
+        // Both u64 fill r0 to r3, so the last two arguments are read from the stack, where
+        // core 0 has put them.
+        let entry = unsafe { ManuallyDrop::take(entry) };
+
+        // Tell core 0 it can drop its copy of the closure.
+        fifo_write(1);
+
+        entry();
+
+        loop {
+            cortex_m::asm::wfe();
+        }
+    }
+
+    unsafe {
+        // Reset core 1.
+        pac::PSM.frce_off().modify(|w| w.set_proc1(true));
+        while !pac::PSM.frce_off().read().proc1() {
+            cortex_m::asm::nop();
+        }
+        pac::PSM.frce_off().modify(|w| w.set_proc1(false));
+    }
+
+    // Put the arguments of `core1_startup` on the new stack.
+    let mut stack_ptr = unsafe { stack.mem.as_mut_ptr().add(SIZE) };
+    let mut entry = ManuallyDrop::new(entry);
+    unsafe {
+        stack_ptr = stack_ptr.sub(1);
+        stack_ptr.cast::<*mut usize>().write(stack.mem.as_mut_ptr());
+        stack_ptr = stack_ptr.sub(1);
+        stack_ptr.cast::<&mut ManuallyDrop<F>>().write(&mut entry);
+    }
+
+    // The writes to the stack must be done before core 1 sees the pointer to it.
+    compiler_fence(Ordering::Release);
+
+    // Core 1 runs with the same vector table as core 0.
+    let vector_table = unsafe { (*cortex_m::peripheral::SCB::ptr()).vtor.read() };
+
+    // The boot ROM of core 1 waits for this sequence on the FIFO, echoing each word back. It
+    // starts over from the beginning when a word doesn't match.
+    let cmd_seq = [
+        0,
+        0,
+        1,
+        vector_table as usize,
+        stack_ptr as usize,
+        core1_startup::<F> as usize,
+    ];
+
+    // Don't let the FIFO interrupt of core 0 take the echoes.
+    let irq = unsafe { interrupt::SIO_IRQ_PROC0::steal() };
+    irq.disable();
+
+    let mut seq = 0;
+    let mut fails = 0;
+    loop {
+        let cmd = cmd_seq[seq] as u32;
+        if cmd == 0 {
+            fifo_drain();
+            cortex_m::asm::sev();
+        }
+        fifo_write(cmd);
+
+        let response = fifo_read_blocking();
+        if cmd == response {
+            seq += 1;
+        } else {
+            seq = 0;
+            fails += 1;
+            if fails > 16 {
+                panic!("failed to start core 1");
+            }
+        }
+        if seq >= cmd_seq.len() {
+            break;
+        }
+    }
+
+    // Wait until core 1 has moved the closure out, before `entry` goes out of scope.
+    fifo_read_blocking();
+
+    irq.enable();
+}
+
+/// Push a word to the FIFO of the other core, waiting while it's full.
+pub fn fifo_write(value: u32) {
+    unsafe {
+        while !SIO.fifo().st().read().rdy() {
+            cortex_m::asm::nop();
+        }
+        SIO.fifo().wr().write_value(value);
+    }
+    // Wake the other core if it's sleeping in WFE.
+    cortex_m::asm::sev();
+}
+
+/// Pop a word from the FIFO of this core, if there's one.
+pub fn fifo_read() -> Option<u32> {
+    unsafe {
+        if SIO.fifo().st().read().vld() {
+            Some(SIO.fifo().rd().read())
+        } else {
+            None
+        }
+    }
+}
+
+/// Pop a word from the FIFO of this core, waiting until there's one.
+pub fn fifo_read_blocking() -> u32 {
+    loop {
+        if let Some(value) = fifo_read() {
+            return value;
+        }
+        cortex_m::asm::wfe();
+    }
+}
+
+/// Pop a word from the FIFO of this core, waiting until there's one.
+///
+/// The FIFO has a single reader: only one task per core can wait on it at a time.
+pub async fn fifo_read_async() -> u32 {
+    let core = current_core();
+    poll_fn(|cx| {
+        FIFO_WAKERS[core].register(cx.waker());
+        match fifo_read() {
+            Some(value) => Poll::Ready(value),
+            None => {
+                // The interrupt is disabled when it fires, since it stays pending as long
+                // as the FIFO isn't empty.
+                set_fifo_irq(core, true);
+                Poll::Pending
+            }
+        }
+    })
+    .await
+}
+
+fn fifo_drain() {
+    while fifo_read().is_some() {}
+}
+
+fn current_core() -> usize {
+    unsafe { SIO.cpuid().read() as usize }
+}
+
+fn set_fifo_irq(core: usize, enabled: bool) {
+    unsafe {
+        match (core, enabled) {
+            (0, true) => interrupt::SIO_IRQ_PROC0::steal().enable(),
+            (0, false) => interrupt::SIO_IRQ_PROC0::steal().disable(),
+            (_, true) => interrupt::SIO_IRQ_PROC1::steal().enable(),
+            (_, false) => interrupt::SIO_IRQ_PROC1::steal().disable(),
+        }
+    }
+}
+
+fn on_fifo_irq(core: usize) {
+    unsafe {
+        // Clear the overflow and underflow flags, which also raise the interrupt.
+        SIO.fifo().st().write(|w| {
+            w.set_wof(true);
+            w.set_roe(true);
+        });
+        if SIO.fifo().st().read().vld() {
+            set_fifo_irq(core, false);
+            FIFO_WAKERS[core].wake();
+        }
+    }
+}
+
+#[interrupt]
+unsafe fn SIO_IRQ_PROC0() {
+    on_fifo_irq(0)
+}
+
+#[interrupt]
+unsafe fn SIO_IRQ_PROC1() {
+    on_fifo_irq(1)
+}
@@ -0,0 +1,760 @@
+//! Programmable I/O.
+//!
+//! Each PIO block has four state machines, which run programs from a shared instruction
+//! memory of 32 instructions. The programs are assembled beforehand, for example with
+//! `pioasm` or the `pio-proc` crate, and loaded with [`Common::load_program`].
+//!
+//! ```ignore
+//! let Pio { mut common, mut sm0, .. } = Pio::new(p.PIO0);
+//! let program = common.load_program(&program)?;
+//! let out_pin = common.make_pio_pin(p.PIN_16);
+//!
+//! let mut config = Config::default();
+//! config.use_program(&program, &[]);
+//! config.set_out_pins(&[&out_pin]);
+//! config.set_frequency(8_000_000);
+//! sm0.set_config(&config);
+//! sm0.set_pin_dirs(Direction::Out, &[&out_pin]);
+//! sm0.set_enable(true);
+//!
+//! sm0.dma_push(&mut p.DMA_CH0, &data).await;
+//! ```
+
+use core::future::Future;
+use core::marker::PhantomData;
+use core::pin::Pin as FuturePin;
+use core::task::{Context, Poll};
+
+use embassy::interrupt::{Interrupt, InterruptExt};
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::unborrow;
+
+use crate::dma::{self, Transfer};
+use crate::gpio::sealed::Pin as _;
+use crate::gpio::Pin as GpioPin;
+use crate::{interrupt, pac, peripherals};
+
+const INSTRUCTION_COUNT: usize = 32;
+const SM_COUNT: usize = 4;
+
+struct PioWakers {
+    rx: [AtomicWaker; SM_COUNT],
+    tx: [AtomicWaker; SM_COUNT],
+    irq: [AtomicWaker; SM_COUNT],
+}
+
+const NEW_AW: AtomicWaker = AtomicWaker::new();
+const NEW_PIO_WAKERS: PioWakers = PioWakers {
+    rx: [NEW_AW; SM_COUNT],
+    tx: [NEW_AW; SM_COUNT],
+    irq: [NEW_AW; SM_COUNT],
+};
+static WAKERS: [PioWakers; 2] = [NEW_PIO_WAKERS; 2];
+
+/// safety: must be called exactly once at bootup
+pub(crate) unsafe fn init() {
+    let irq = interrupt::PIO0_IRQ_0::steal();
+    irq.disable();
+    irq.enable();
+    let irq = interrupt::PIO1_IRQ_0::steal();
+    irq.disable();
+    irq.enable();
+}
+
+unsafe fn on_irq(pio: pac::pio::Pio, wakers: &PioWakers) {
+    let ints = pio.irqs(0).ints().read();
+
+    // The FIFO interrupts stay asserted as long as their condition holds, so they're
+    // disabled once fired. The futures re-enable them if they need to wait again.
+    critical_section::with(|_| {
+        pio.irqs(0).inte().modify(|w| {
+            for sm in 0..SM_COUNT {
+                if ints.sm_rxnempty(sm) {
+                    w.set_sm_rxnempty(sm, false);
+                }
+                if ints.sm_txnfull(sm) {
+                    w.set_sm_txnfull(sm, false);
+                }
+                if ints.sm(sm) {
+                    w.set_sm(sm, false);
+                }
+            }
+        });
+    });
+
+    for sm in 0..SM_COUNT {
+        if ints.sm_rxnempty(sm) {
+            wakers.rx[sm].wake();
+        }
+        if ints.sm_txnfull(sm) {
+            wakers.tx[sm].wake();
+        }
+        if ints.sm(sm) {
+            wakers.irq[sm].wake();
+        }
+    }
+}
+
+#[interrupt]
+unsafe fn PIO0_IRQ_0() {
+    on_irq(pac::PIO0, &WAKERS[0])
+}
+
+#[interrupt]
+unsafe fn PIO1_IRQ_0() {
+    on_irq(pac::PIO1, &WAKERS[1])
+}
+
+/// A PIO block, split in its shared resources and its state machines.
+pub struct Pio<'d, PIO: Instance> {
+    pub common: Common<'d, PIO>,
+    pub sm0: StateMachine<'d, PIO, 0>,
+    pub sm1: StateMachine<'d, PIO, 1>,
+    pub sm2: StateMachine<'d, PIO, 2>,
+    pub sm3: StateMachine<'d, PIO, 3>,
+}
+
+impl<'d, PIO: Instance> Pio<'d, PIO> {
+    pub fn new(pio: impl Unborrow<Target = PIO> + 'd) -> Self {
+        unborrow!(pio);
+        let _ = pio;
+
+        unsafe {
+            let p = PIO::regs();
+            // Start from a clean state: state machines stopped, interrupts disabled.
+            p.ctrl().write(|w| {
+                w.set_sm_enable(0);
+                w.set_sm_restart(0xf);
+                w.set_clkdiv_restart(0xf);
+            });
+            p.irqs(0).inte().write_value(pac::pio::regs::Intr(0));
+            p.irq().write(|w| w.set_irq(0xff));
+        }
+
+        Self {
+            common: Common {
+                instructions_used: 0,
+                phantom: PhantomData,
+            },
+            sm0: StateMachine::new(),
+            sm1: StateMachine::new(),
+            sm2: StateMachine::new(),
+            sm3: StateMachine::new(),
+        }
+    }
+}
+
+/// Where a program jumps from its end, and back to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Wrap {
+    /// Instruction after which the program wraps (`.wrap`).
+    pub source: u8,
+    /// Instruction it wraps to (`.wrap_target`).
+    pub target: u8,
+}
+
+/// Side-set settings of a program (`.side_set`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SideSet {
+    /// Number of side-set bits, including the enable bit if optional.
+    pub bits: u8,
+    pub optional: bool,
+    /// Side-set sets the pin directions instead of the pin values.
+    pub pindirs: bool,
+}
+
+/// An assembled program.
+pub struct Program<'a> {
+    pub code: &'a [u16],
+    /// Address the program must be loaded at, or `None` to load it anywhere.
+    pub origin: Option<u8>,
+    /// Relative to the start of the program.
+    pub wrap: Wrap,
+    pub side_set: SideSet,
+}
+
+/// A program loaded in the instruction memory.
+pub struct LoadedProgram<'d, PIO: Instance> {
+    /// Address of the first instruction.
+    pub origin: u8,
+    len: u8,
+    wrap: Wrap,
+    side_set: SideSet,
+    phantom: PhantomData<&'d PIO>,
+}
+
+impl<'d, PIO: Instance> LoadedProgram<'d, PIO> {
+    fn mask(&self) -> u32 {
+        (((1u64 << self.len) - 1) << self.origin) as u32
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LoadError {
+    /// There is no free space for the program, or at its origin.
+    InsufficientSpace,
+}
+
+/// A GPIO pin given to a PIO block.
+pub struct PioPin<'d, PIO: Instance> {
+    pin: u8,
+    phantom: PhantomData<&'d mut PIO>,
+}
+
+impl<'d, PIO: Instance> PioPin<'d, PIO> {
+    pub fn pin(&self) -> u8 {
+        self.pin
+    }
+}
+
+/// Resources shared by the state machines of a PIO block.
+pub struct Common<'d, PIO: Instance> {
+    instructions_used: u32,
+    phantom: PhantomData<&'d mut PIO>,
+}
+
+impl<'d, PIO: Instance> Common<'d, PIO> {
+    /// Load a program in the instruction memory, at its origin or in the first free space.
+    ///
+    /// The targets of the `JMP` instructions are relocated to where the program is loaded.
+    pub fn load_program(&mut self, program: &Program) -> Result<LoadedProgram<'d, PIO>, LoadError> {
+        let len = program.code.len();
+        if len == 0 || len > INSTRUCTION_COUNT {
+            return Err(LoadError::InsufficientSpace);
+        }
+        let mask = ((1u64 << len) - 1) as u32;
+
+        let fits = |origin: usize| {
+            origin + len <= INSTRUCTION_COUNT && self.instructions_used & (mask << origin) == 0
+        };
+        let origin = match program.origin {
+            Some(origin) if fits(origin as usize) => origin as usize,
+            Some(_) => return Err(LoadError::InsufficientSpace),
+            // Like the SDK, fill the memory from the end.
+            None => (0..=INSTRUCTION_COUNT - len)
+                .rev()
+                .find(|&origin| fits(origin))
+                .ok_or(LoadError::InsufficientSpace)?,
+        };
+
+        let p = PIO::regs();
+        for (i, &instr) in program.code.iter().enumerate() {
+            // JMP has the opcode 0b000, with the target in the 5 low bits.
+            let instr = if instr & 0xe000 == 0 {
+                instr + origin as u16
+            } else {
+                instr
+            };
+            unsafe {
+                p.instr_mem(origin + i).write(|w| w.set_instr_mem(instr));
+            }
+        }
+        self.instructions_used |= mask << origin;
+
+        Ok(LoadedProgram {
+            origin: origin as u8,
+            len: len as u8,
+            wrap: program.wrap,
+            side_set: program.side_set,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Free the instruction memory of a program. No state machine must be running it.
+    pub fn free_program(&mut self, program: LoadedProgram<'d, PIO>) {
+        self.instructions_used &= !program.mask();
+    }
+
+    /// Connect a pin to the PIO block.
+    pub fn make_pio_pin(
+        &mut self,
+        pin: impl Unborrow<Target = impl GpioPin> + 'd,
+    ) -> PioPin<'d, PIO> {
+        unborrow!(pin);
+        unsafe {
+            pin.io().ctrl().write(|w| w.set_funcsel(PIO::FUNCSEL));
+            // The input must be enabled for `WAIT` and `IN` to see the pin.
+            pin.pad_ctrl().modify(|w| w.set_ie(true));
+        }
+        PioPin {
+            pin: pin.pin(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Skip the synchronizers of the inputs of some pins, for less latency. The inputs must
+    /// already be synchronous to the system clock.
+    pub fn set_input_sync_bypass(&mut self, bypass: u32, mask: u32) {
+        unsafe {
+            let p = PIO::regs();
+            let value = p.input_sync_bypass().read();
+            p.input_sync_bypass()
+                .write_value((value & !mask) | (bypass & mask));
+        }
+    }
+
+    /// Wait for a state machine to raise IRQ flag `irq_no`, with `IRQ` or `IRQ WAIT`, and
+    /// clear it. Only the flags 0 to 3 can be waited on.
+    pub fn wait_irq(&mut self, irq_no: u8) -> IrqFuture<'_, PIO> {
+        assert!(irq_no < 4);
+        IrqFuture {
+            irq_no,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Raise IRQ flags from the CPU, as a state machine waiting on them would see them.
+    pub fn force_irq(&mut self, irq_flags: u8) {
+        unsafe {
+            PIO::regs()
+                .irq_force()
+                .write(|w| w.set_irq_force(irq_flags))
+        }
+    }
+}
+
+/// Future for [`Common::wait_irq`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct IrqFuture<'a, PIO: Instance> {
+    irq_no: u8,
+    phantom: PhantomData<&'a mut PIO>,
+}
+
+impl<'a, PIO: Instance> Future for IrqFuture<'a, PIO> {
+    type Output = ();
+
+    fn poll(self: FuturePin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let n = self.irq_no as usize;
+        WAKERS[PIO::PIO_NO as usize].irq[n].register(cx.waker());
+
+        unsafe {
+            let p = PIO::regs();
+            if p.irq().read().irq() & (1 << n) != 0 {
+                p.irq().write(|w| w.set_irq(1 << n));
+                return Poll::Ready(());
+            }
+            critical_section::with(|_| p.irqs(0).inte().modify(|w| w.set_sm(n, true)));
+        }
+        Poll::Pending
+    }
+}
+
+impl<'a, PIO: Instance> Drop for IrqFuture<'a, PIO> {
+    fn drop(&mut self) {
+        let n = self.irq_no as usize;
+        critical_section::with(|_| unsafe {
+            PIO::regs().irqs(0).inte().modify(|w| w.set_sm(n, false))
+        });
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ShiftDirection {
+    Left,
+    Right,
+}
+
+/// Settings of the input or output shift register.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ShiftConfig {
+    pub direction: ShiftDirection,
+    /// Push or pull automatically when `threshold` bits are shifted.
+    pub auto_fill: bool,
+    /// In bits, 1 to 32.
+    pub threshold: u8,
+}
+
+impl Default for ShiftConfig {
+    fn default() -> Self {
+        Self {
+            direction: ShiftDirection::Right,
+            auto_fill: false,
+            threshold: 32,
+        }
+    }
+}
+
+/// Joins the two FIFOs of a state machine in a single one, twice as deep.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum FifoJoin {
+    Duplex,
+    RxOnly,
+    TxOnly,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    In,
+    Out,
+}
+
+/// State machine configuration, applied with [`StateMachine::set_config`].
+#[non_exhaustive]
+pub struct Config<'d, PIO: Instance> {
+    /// Integer part of the clock divider, 0 meaning 65536.
+    pub clock_divider_int: u16,
+    /// Fractional part of the clock divider, in 1/256.
+    pub clock_divider_frac: u8,
+    pub shift_in: ShiftConfig,
+    pub shift_out: ShiftConfig,
+    pub fifo_join: FifoJoin,
+    /// Pin tested by `JMP PIN`.
+    pub jmp_pin: u8,
+    /// Keep driving the last `OUT` or `SET` value on the pins.
+    pub out_sticky: bool,
+    origin: u8,
+    wrap: Wrap,
+    side_set: SideSet,
+    in_base: u8,
+    out_base: u8,
+    out_count: u8,
+    set_base: u8,
+    set_count: u8,
+    side_set_base: u8,
+    phantom: PhantomData<&'d PIO>,
+}
+
+impl<'d, PIO: Instance> Default for Config<'d, PIO> {
+    fn default() -> Self {
+        Self {
+            clock_divider_int: 1,
+            clock_divider_frac: 0,
+            shift_in: ShiftConfig::default(),
+            shift_out: ShiftConfig::default(),
+            fifo_join: FifoJoin::Duplex,
+            jmp_pin: 0,
+            out_sticky: false,
+            origin: 0,
+            wrap: Wrap {
+                source: 31,
+                target: 0,
+            },
+            side_set: SideSet::default(),
+            in_base: 0,
+            out_base: 0,
+            out_count: 0,
+            set_base: 0,
+            set_count: 0,
+            side_set_base: 0,
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Base and count of a set of consecutive pins.
+fn pin_range<PIO: Instance>(pins: &[&PioPin<PIO>]) -> (u8, u8) {
+    let base = pins.first().map_or(0, |p| p.pin);
+    for (i, pin) in pins.iter().enumerate() {
+        assert!(pin.pin == base + i as u8, "pins must be consecutive");
+    }
+    (base, pins.len() as u8)
+}
+
+impl<'d, PIO: Instance> Config<'d, PIO> {
+    /// Run `program`, with its side-set on `side_set_pins`.
+    pub fn use_program(
+        &mut self,
+        program: &LoadedProgram<'d, PIO>,
+        side_set_pins: &[&PioPin<PIO>],
+    ) {
+        let (base, count) = pin_range(side_set_pins);
+        let data_bits = program.side_set.bits - program.side_set.optional as u8;
+        assert!(count == data_bits, "side-set pins don't match the program");
+        self.side_set_base = base;
+        self.side_set = program.side_set;
+        self.origin = program.origin;
+        self.wrap = Wrap {
+            source: program.origin + program.wrap.source,
+            target: program.origin + program.wrap.target,
+        };
+    }
+
+    /// Pins written by `OUT PINS`.
+    pub fn set_out_pins(&mut self, pins: &[&PioPin<PIO>]) {
+        let (base, count) = pin_range(pins);
+        self.out_base = base;
+        self.out_count = count;
+    }
+
+    /// Pins written by `SET PINS`, up to 5.
+    pub fn set_set_pins(&mut self, pins: &[&PioPin<PIO>]) {
+        let (base, count) = pin_range(pins);
+        assert!(count <= 5);
+        self.set_base = base;
+        self.set_count = count;
+    }
+
+    /// First pin read by `IN PINS` and `WAIT PIN`.
+    pub fn set_in_pins(&mut self, base: &PioPin<PIO>) {
+        self.in_base = base.pin;
+    }
+
+    /// Run the state machine at `freq` instructions per second.
+    pub fn set_frequency(&mut self, freq: u32) {
+        let sys = crate::clocks::clk_sys_freq() as u64;
+        let div = (sys * 256 + freq as u64 / 2) / freq as u64;
+        assert!(div >= 256 && div <= 65536 * 256, "frequency out of range");
+        self.clock_divider_int = (div >> 8) as u16;
+        self.clock_divider_frac = div as u8;
+    }
+}
+
+/// One of the four state machines of a PIO block.
+pub struct StateMachine<'d, PIO: Instance, const SM: usize> {
+    phantom: PhantomData<&'d mut PIO>,
+}
+
+impl<'d, PIO: Instance, const SM: usize> StateMachine<'d, PIO, SM> {
+    fn new() -> Self {
+        Self {
+            phantom: PhantomData,
+        }
+    }
+
+    fn regs(&self) -> pac::pio::StateMachine {
+        PIO::regs().sm(SM)
+    }
+
+    /// Apply a configuration. The state machine is stopped first, and is then ready to run
+    /// from the start of its program.
+    pub fn set_config(&mut self, config: &Config<'d, PIO>) {
+        self.set_enable(false);
+        let sm = self.regs();
+        unsafe {
+            sm.clkdiv().write(|w| {
+                w.set_int(config.clock_divider_int);
+                w.set_frac(config.clock_divider_frac);
+            });
+            sm.execctrl().write(|w| {
+                w.set_side_en(config.side_set.optional);
+                w.set_side_pindir(config.side_set.pindirs);
+                w.set_jmp_pin(config.jmp_pin);
+                w.set_out_sticky(config.out_sticky);
+                w.set_wrap_top(config.wrap.source);
+                w.set_wrap_bottom(config.wrap.target);
+            });
+            sm.shiftctrl().write(|w| {
+                w.set_fjoin_rx(config.fifo_join == FifoJoin::RxOnly);
+                w.set_fjoin_tx(config.fifo_join == FifoJoin::TxOnly);
+                // A threshold of 32 is encoded as 0.
+                w.set_pull_thresh(config.shift_out.threshold & 0x1f);
+                w.set_push_thresh(config.shift_in.threshold & 0x1f);
+                w.set_out_shiftdir(config.shift_out.direction == ShiftDirection::Right);
+                w.set_in_shiftdir(config.shift_in.direction == ShiftDirection::Right);
+                w.set_autopull(config.shift_out.auto_fill);
+                w.set_autopush(config.shift_in.auto_fill);
+            });
+            sm.pinctrl().write(|w| {
+                w.set_sideset_count(config.side_set.bits);
+                w.set_set_count(config.set_count);
+                w.set_out_count(config.out_count);
+                w.set_in_base(config.in_base);
+                w.set_sideset_base(config.side_set_base);
+                w.set_set_base(config.set_base);
+                w.set_out_base(config.out_base);
+            });
+        }
+        self.clear_fifos();
+        self.restart();
+        // JMP to the start of the program.
+        self.exec_instr(config.origin as u16);
+    }
+
+    pub fn set_enable(&mut self, enable: bool) {
+        let mask = 1 << SM;
+        critical_section::with(|_| unsafe {
+            PIO::regs().ctrl().modify(|w| {
+                let value = w.sm_enable();
+                w.set_sm_enable(if enable { value | mask } else { value & !mask });
+            });
+        });
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        unsafe { PIO::regs().ctrl().read().sm_enable() & (1 << SM) != 0 }
+    }
+
+    /// Clear the internal state of the state machine: shift registers, delays, stalls. The
+    /// program counter and the FIFOs are kept.
+    pub fn restart(&mut self) {
+        // The restart bits clear themselves.
+        critical_section::with(|_| unsafe {
+            PIO::regs().ctrl().modify(|w| {
+                w.set_sm_restart(1 << SM);
+                w.set_clkdiv_restart(1 << SM);
+            });
+        });
+    }
+
+    /// Run an instruction right away, between the instructions of the program.
+    pub fn exec_instr(&mut self, instr: u16) {
+        unsafe { self.regs().instr().write(|w| w.set_instr(instr)) }
+    }
+
+    /// Set the direction of pins, by running `SET PINDIRS` instructions.
+    pub fn set_pin_dirs(&mut self, dir: Direction, pins: &[&PioPin<PIO>]) {
+        let value = match dir {
+            Direction::In => 0,
+            Direction::Out => 1,
+        };
+        // SET PINDIRS, with the destination 0b100.
+        self.set_pins_with(0xe080 | value, pins);
+    }
+
+    /// Set the level of pins, by running `SET PINS` instructions.
+    pub fn set_pins(&mut self, level: bool, pins: &[&PioPin<PIO>]) {
+        self.set_pins_with(0xe000 | level as u16, pins);
+    }
+
+    fn set_pins_with(&mut self, instr: u16, pins: &[&PioPin<PIO>]) {
+        let sm = self.regs();
+        unsafe {
+            let pinctrl = sm.pinctrl().read();
+            // Without side-set pins, so the instruction only touches the pin.
+            for pin in pins {
+                sm.pinctrl().write(|w| {
+                    w.set_set_base(pin.pin);
+                    w.set_set_count(1);
+                });
+                self.exec_instr(instr);
+            }
+            sm.pinctrl().write_value(pinctrl);
+        }
+    }
+
+    /// Drop the content of both FIFOs.
+    pub fn clear_fifos(&mut self) {
+        // Changing the join setting clears the FIFOs.
+        unsafe {
+            let shiftctrl = self.regs().shiftctrl();
+            shiftctrl.modify(|w| w.set_fjoin_rx(!w.fjoin_rx()));
+            shiftctrl.modify(|w| w.set_fjoin_rx(!w.fjoin_rx()));
+        }
+    }
+
+    pub fn is_tx_full(&self) -> bool {
+        unsafe { PIO::regs().fstat().read().txfull() & (1 << SM) != 0 }
+    }
+
+    pub fn is_rx_empty(&self) -> bool {
+        unsafe { PIO::regs().fstat().read().rxempty() & (1 << SM) != 0 }
+    }
+
+    /// Push a word to the TX FIFO. Returns `false` if it was full.
+    pub fn try_push_tx(&mut self, value: u32) -> bool {
+        if self.is_tx_full() {
+            return false;
+        }
+        unsafe { PIO::regs().txf(SM).write_value(value) };
+        true
+    }
+
+    /// Pull a word from the RX FIFO, if there's one.
+    pub fn try_pull_rx(&mut self) -> Option<u32> {
+        if self.is_rx_empty() {
+            return None;
+        }
+        Some(unsafe { PIO::regs().rxf(SM).read() })
+    }
+
+    /// Push a word to the TX FIFO, waiting for room.
+    pub async fn wait_push(&mut self, value: u32) {
+        futures::future::poll_fn(|cx| {
+            WAKERS[PIO::PIO_NO as usize].tx[SM].register(cx.waker());
+            if self.try_push_tx(value) {
+                return Poll::Ready(());
+            }
+            critical_section::with(|_| unsafe {
+                PIO::regs()
+                    .irqs(0)
+                    .inte()
+                    .modify(|w| w.set_sm_txnfull(SM, true));
+            });
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Pull a word from the RX FIFO, waiting for one.
+    pub async fn wait_pull(&mut self) -> u32 {
+        futures::future::poll_fn(|cx| {
+            WAKERS[PIO::PIO_NO as usize].rx[SM].register(cx.waker());
+            if let Some(value) = self.try_pull_rx() {
+                return Poll::Ready(value);
+            }
+            critical_section::with(|_| unsafe {
+                PIO::regs()
+                    .irqs(0)
+                    .inte()
+                    .modify(|w| w.set_sm_rxnempty(SM, true));
+            });
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Push words to the TX FIFO with DMA, at the pace the state machine consumes them.
+    pub fn dma_push<'a, C: dma::Channel>(
+        &'a mut self,
+        ch: impl Unborrow<Target = C> + 'a,
+        data: &'a [u32],
+    ) -> Transfer<'a, C> {
+        let dreq = PIO::PIO_NO * 8 + SM as u8;
+        unsafe {
+            let txf = PIO::regs().txf(SM).ptr() as *mut u32;
+            dma::write(ch, data, txf, dreq)
+        }
+    }
+
+    /// Pull words from the RX FIFO with DMA, as the state machine produces them.
+    pub fn dma_pull<'a, C: dma::Channel>(
+        &'a mut self,
+        ch: impl Unborrow<Target = C> + 'a,
+        data: &'a mut [u32],
+    ) -> Transfer<'a, C> {
+        let dreq = PIO::PIO_NO * 8 + 4 + SM as u8;
+        unsafe {
+            let rxf = PIO::regs().rxf(SM).ptr() as *const u32;
+            dma::read(ch, rxf, data, dreq)
+        }
+    }
+}
+
+pub(crate) mod sealed {
+    use super::*;
+
+    pub trait Instance {
+        const PIO_NO: u8;
+        /// Function of the GPIOs connected to the block.
+        const FUNCSEL: u8;
+
+        fn regs() -> pac::pio::Pio;
+    }
+}
+
+pub trait Instance: sealed::Instance + Unborrow<Target = Self> + 'static {}
+
+macro_rules! impl_instance {
+    ($type:ident, $no:expr, $funcsel:expr) => {
+        impl sealed::Instance for peripherals::$type {
+            const PIO_NO: u8 = $no;
+            const FUNCSEL: u8 = $funcsel;
+
+            fn regs() -> pac::pio::Pio {
+                pac::$type
+            }
+        }
+        impl Instance for peripherals::$type {}
+    };
+}
+
+impl_instance!(PIO0, 0, 6);
+impl_instance!(PIO1, 1, 7);
@@ -1,8 +1,9 @@
 use core::marker::PhantomData;
 
-use embassy::util::Unborrow;
+use embassy::util::{join, Unborrow};
 use embassy_hal_common::unborrow;
 
+use crate::dma::{self, NoDma};
 use crate::gpio::sealed::Pin as _;
 use crate::gpio::{AnyPin, Pin as GpioPin};
 use crate::{pac, peripherals};
@@ -33,8 +34,10 @@ impl Default for Config {
     }
 }
 
-pub struct Spi<'d, T: Instance> {
+pub struct Spi<'d, T: Instance, Tx = NoDma, Rx = NoDma> {
     inner: T,
+    tx_dma: Tx,
+    rx_dma: Rx,
     phantom: PhantomData<&'d mut T>,
 }
 
@@ -80,6 +83,8 @@ impl<'d, T: Instance> Spi<'d, T> {
             Some(mosi.degrade()),
             Some(miso.degrade()),
             None,
+            NoDma,
+            NoDma,
             config,
         )
     }
@@ -97,6 +102,8 @@ impl<'d, T: Instance> Spi<'d, T> {
             Some(mosi.degrade()),
             None,
             None,
+            NoDma,
+            NoDma,
             config,
         )
     }
@@ -114,6 +121,34 @@ impl<'d, T: Instance> Spi<'d, T> {
             None,
             Some(miso.degrade()),
             None,
+            NoDma,
+            NoDma,
+            config,
+        )
+    }
+}
+
+impl<'d, T: Instance, Tx, Rx> Spi<'d, T, Tx, Rx> {
+    /// Create the driver with DMA channels, which enable the async [`write`](Self::write),
+    /// [`read`](Self::read) and [`transfer`](Self::transfer).
+    pub fn new_with_dma(
+        inner: impl Unborrow<Target = T> + 'd,
+        clk: impl Unborrow<Target = impl ClkPin<T>> + 'd,
+        mosi: impl Unborrow<Target = impl MosiPin<T>> + 'd,
+        miso: impl Unborrow<Target = impl MisoPin<T>> + 'd,
+        tx_dma: impl Unborrow<Target = Tx> + 'd,
+        rx_dma: impl Unborrow<Target = Rx> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(clk, mosi, miso);
+        Self::new_inner(
+            inner,
+            Some(clk.degrade()),
+            Some(mosi.degrade()),
+            Some(miso.degrade()),
+            None,
+            tx_dma,
+            rx_dma,
             config,
         )
     }
@@ -124,9 +159,11 @@ impl<'d, T: Instance> Spi<'d, T> {
         mosi: Option<AnyPin>,
         miso: Option<AnyPin>,
         cs: Option<AnyPin>,
+        tx_dma: impl Unborrow<Target = Tx> + 'd,
+        rx_dma: impl Unborrow<Target = Rx> + 'd,
         config: Config,
     ) -> Self {
-        unborrow!(inner);
+        unborrow!(inner, tx_dma, rx_dma);
 
         unsafe {
             let p = inner.regs();
@@ -158,6 +195,8 @@ impl<'d, T: Instance> Spi<'d, T> {
         }
         Self {
             inner,
+            tx_dma,
+            rx_dma,
             phantom: PhantomData,
         }
     }
@@ -250,10 +289,101 @@ impl<'d, T: Instance> Spi<'d, T> {
     }
 }
 
+impl<'d, T: Instance, Tx: dma::Channel, Rx: dma::Channel> Spi<'d, T, Tx, Rx> {
+    pub async fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        let p = self.inner.regs();
+        unsafe {
+            p.dmacr().write(|w| {
+                w.set_txdmae(true);
+                w.set_rxdmae(true);
+            });
+            // The received bytes are discarded, but they must still be read out.
+            let tx = dma::write(&mut self.tx_dma, data, p.dr().ptr() as *mut u8, T::TX_DREQ);
+            let rx = dma::read_repeated(
+                &mut self.rx_dma,
+                p.dr().ptr() as *const u8,
+                data.len(),
+                T::RX_DREQ,
+            );
+            join(tx, rx).await;
+        }
+        self.finish_dma()
+    }
+
+    pub async fn read(&mut self, data: &mut [u8]) -> Result<(), Error> {
+        let p = self.inner.regs();
+        unsafe {
+            p.dmacr().write(|w| {
+                w.set_txdmae(true);
+                w.set_rxdmae(true);
+            });
+            // Send zeros to clock the data in.
+            let len = data.len();
+            let rx = dma::read(
+                &mut self.rx_dma,
+                p.dr().ptr() as *const u8,
+                data,
+                T::RX_DREQ,
+            );
+            let tx =
+                dma::write_repeated(&mut self.tx_dma, p.dr().ptr() as *mut u8, len, T::TX_DREQ);
+            join(tx, rx).await;
+        }
+        self.finish_dma()
+    }
+
+    /// Send `write` while receiving into `read`. If the buffers have different lengths, the
+    /// rest of the longest one is transferred alone, like [`write`](Self::write) or
+    /// [`read`](Self::read).
+    pub async fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+        let len = read.len().min(write.len());
+        let (read, read_rest) = read.split_at_mut(len);
+        let (write, write_rest) = write.split_at(len);
+
+        let p = self.inner.regs();
+        unsafe {
+            p.dmacr().write(|w| {
+                w.set_txdmae(true);
+                w.set_rxdmae(true);
+            });
+            let rx = dma::read(
+                &mut self.rx_dma,
+                p.dr().ptr() as *const u8,
+                read,
+                T::RX_DREQ,
+            );
+            let tx = dma::write(&mut self.tx_dma, write, p.dr().ptr() as *mut u8, T::TX_DREQ);
+            join(tx, rx).await;
+        }
+        self.finish_dma()?;
+
+        if !write_rest.is_empty() {
+            self.write(write_rest).await
+        } else if !read_rest.is_empty() {
+            self.read(read_rest).await
+        } else {
+            Ok(())
+        }
+    }
+
+    fn finish_dma(&mut self) -> Result<(), Error> {
+        unsafe {
+            self.inner.regs().dmacr().write(|w| {
+                w.set_txdmae(false);
+                w.set_rxdmae(false);
+            });
+        }
+        self.flush()
+    }
+}
+
 mod sealed {
     use super::*;
 
     pub trait Instance {
+        const TX_DREQ: u8;
+        const RX_DREQ: u8;
+
         fn regs(&self) -> pac::spi::Spi;
     }
 }
@@ -261,8 +391,11 @@ mod sealed {
 pub trait Instance: sealed::Instance {}
 
 macro_rules! impl_instance {
-    ($type:ident, $irq:ident) => {
+    ($type:ident, $irq:ident, $tx_dreq:expr, $rx_dreq:expr) => {
         impl sealed::Instance for peripherals::$type {
+            const TX_DREQ: u8 = $tx_dreq;
+            const RX_DREQ: u8 = $rx_dreq;
+
             fn regs(&self) -> pac::spi::Spi {
                 pac::$type
             }
@@ -271,8 +404,8 @@ macro_rules! impl_instance {
     };
 }
 
-impl_instance!(SPI0, Spi0);
-impl_instance!(SPI1, Spi1);
+impl_instance!(SPI0, Spi0, 16, 17);
+impl_instance!(SPI1, Spi1, 18, 19);
 
 pub trait ClkPin<T: Instance>: GpioPin {}
 pub trait CsPin<T: Instance>: GpioPin {}
@@ -311,7 +444,7 @@ impl_pin!(PIN_19, SPI0, MosiPin);
 mod eh02 {
     use super::*;
 
-    impl<'d, T: Instance> embedded_hal_02::blocking::spi::Transfer<u8> for Spi<'d, T> {
+    impl<'d, T: Instance, Tx, Rx> embedded_hal_02::blocking::spi::Transfer<u8> for Spi<'d, T, Tx, Rx> {
         type Error = Error;
         fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
             self.blocking_transfer_in_place(words)?;
@@ -319,7 +452,7 @@ mod eh02 {
         }
     }
 
-    impl<'d, T: Instance> embedded_hal_02::blocking::spi::Write<u8> for Spi<'d, T> {
+    impl<'d, T: Instance, Tx, Rx> embedded_hal_02::blocking::spi::Write<u8> for Spi<'d, T, Tx, Rx> {
         type Error = Error;
 
         fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
@@ -338,29 +471,31 @@ mod eh1 {
         }
     }
 
-    impl<'d, T: Instance> embedded_hal_1::spi::ErrorType for Spi<'d, T> {
+    impl<'d, T: Instance, Tx, Rx> embedded_hal_1::spi::ErrorType for Spi<'d, T, Tx, Rx> {
         type Error = Error;
     }
 
-    impl<'d, T: Instance> embedded_hal_1::spi::blocking::SpiBusFlush for Spi<'d, T> {
+    impl<'d, T: Instance, Tx, Rx> embedded_hal_1::spi::blocking::SpiBusFlush for Spi<'d, T, Tx, Rx> {
         fn flush(&mut self) -> Result<(), Self::Error> {
             Ok(())
         }
     }
 
-    impl<'d, T: Instance> embedded_hal_1::spi::blocking::SpiBusRead<u8> for Spi<'d, T> {
+    impl<'d, T: Instance, Tx, Rx> embedded_hal_1::spi::blocking::SpiBusRead<u8> for Spi<'d, T, Tx, Rx> {
         fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
             self.blocking_transfer(words, &[])
         }
     }
 
-    impl<'d, T: Instance> embedded_hal_1::spi::blocking::SpiBusWrite<u8> for Spi<'d, T> {
+    impl<'d, T: Instance, Tx, Rx> embedded_hal_1::spi::blocking::SpiBusWrite<u8>
+        for Spi<'d, T, Tx, Rx>
+    {
         fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
             self.blocking_write(words)
         }
     }
 
-    impl<'d, T: Instance> embedded_hal_1::spi::blocking::SpiBus<u8> for Spi<'d, T> {
+    impl<'d, T: Instance, Tx, Rx> embedded_hal_1::spi::blocking::SpiBus<u8> for Spi<'d, T, Tx, Rx> {
         fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
             self.blocking_transfer(read, write)
         }
@@ -8,6 +8,12 @@ use embassy::time::driver::{AlarmHandle, Driver};
 
 use crate::{interrupt, pac};
 
+// The TIMER peripheral always ticks at 1Mhz.
+const _: () = assert!(
+    embassy::time::TICKS_PER_SECOND == 1_000_000,
+    "the rp time driver only supports time-tick-1mhz"
+);
+
 struct AlarmState {
     timestamp: Cell<u64>,
     callback: Cell<Option<(fn(*mut ()), *mut ())>>,
@@ -4,6 +4,7 @@ use embassy::util::Unborrow;
 use embassy_hal_common::unborrow;
 use gpio::Pin;
 
+use crate::dma::{self, NoDma};
 use crate::{gpio, pac, peripherals};
 
 #[non_exhaustive]
@@ -23,8 +24,23 @@ impl Default for Config {
     }
 }
 
-pub struct Uart<'d, T: Instance> {
+/// Read error
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// Data was received while the receive FIFO was full, and was lost.
+    Overrun,
+    /// The RX line was held low for longer than a character.
+    Break,
+    Parity,
+    Framing,
+}
+
+pub struct Uart<'d, T: Instance, TxDma = NoDma, RxDma = NoDma> {
     inner: T,
+    tx_dma: TxDma,
+    rx_dma: RxDma,
     phantom: PhantomData<&'d mut T>,
 }
 
@@ -37,7 +53,24 @@ impl<'d, T: Instance> Uart<'d, T> {
         rts: impl Unborrow<Target = impl RtsPin<T>> + 'd,
         config: Config,
     ) -> Self {
-        unborrow!(inner, tx, rx, cts, rts);
+        Self::new_with_dma(inner, tx, rx, cts, rts, NoDma, NoDma, config)
+    }
+}
+
+impl<'d, T: Instance, TxDma, RxDma> Uart<'d, T, TxDma, RxDma> {
+    /// Create the driver with DMA channels, which enable the async [`write`](Self::write)
+    /// and [`read`](Self::read). Pass [`NoDma`] for a direction which doesn't need it.
+    pub fn new_with_dma(
+        inner: impl Unborrow<Target = T> + 'd,
+        tx: impl Unborrow<Target = impl TxPin<T>> + 'd,
+        rx: impl Unborrow<Target = impl RxPin<T>> + 'd,
+        cts: impl Unborrow<Target = impl CtsPin<T>> + 'd,
+        rts: impl Unborrow<Target = impl RtsPin<T>> + 'd,
+        tx_dma: impl Unborrow<Target = TxDma> + 'd,
+        rx_dma: impl Unborrow<Target = RxDma> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(inner, tx, rx, cts, rts, tx_dma, rx_dma);
 
         unsafe {
             let p = inner.regs();
@@ -83,10 +116,13 @@ impl<'d, T: Instance> Uart<'d, T> {
         }
         Self {
             inner,
+            tx_dma,
+            rx_dma,
             phantom: PhantomData,
         }
     }
 
+    /// Send data, dropping the bytes which don't fit in the TX FIFO.
     pub fn send(&mut self, data: &[u8]) {
         unsafe {
             let p = self.inner.regs();
@@ -98,12 +134,105 @@ impl<'d, T: Instance> Uart<'d, T> {
             }
         }
     }
+
+    pub fn blocking_write(&mut self, buffer: &[u8]) -> Result<(), Error> {
+        unsafe {
+            let p = self.inner.regs();
+            for &b in buffer {
+                while p.uartfr().read().txff() {}
+                p.uartdr().write(|w| w.set_data(b));
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait until all the data is sent.
+    pub fn blocking_flush(&mut self) -> Result<(), Error> {
+        unsafe { while self.inner.regs().uartfr().read().busy() {} }
+        Ok(())
+    }
+
+    pub fn blocking_read(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        unsafe {
+            let p = self.inner.regs();
+            for b in buffer {
+                while p.uartfr().read().rxfe() {}
+                let dr = p.uartdr().read();
+                if dr.oe() {
+                    return Err(Error::Overrun);
+                } else if dr.be() {
+                    return Err(Error::Break);
+                } else if dr.pe() {
+                    return Err(Error::Parity);
+                } else if dr.fe() {
+                    return Err(Error::Framing);
+                }
+                *b = dr.data();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'d, T: Instance, TxDma: dma::Channel, RxDma> Uart<'d, T, TxDma, RxDma> {
+    pub async fn write(&mut self, buffer: &[u8]) -> Result<(), Error> {
+        let p = self.inner.regs();
+        unsafe {
+            p.uartdmacr().modify(|w| w.set_txdmae(true));
+            let transfer = dma::write(
+                &mut self.tx_dma,
+                buffer,
+                p.uartdr().ptr() as *mut u8,
+                T::TX_DREQ,
+            );
+            transfer.await;
+            p.uartdmacr().modify(|w| w.set_txdmae(false));
+        }
+        Ok(())
+    }
+}
+
+impl<'d, T: Instance, TxDma, RxDma: dma::Channel> Uart<'d, T, TxDma, RxDma> {
+    /// Read exactly `buffer.len()` bytes.
+    pub async fn read(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        let p = self.inner.regs();
+        unsafe {
+            // The DMA only reads the data bits of the data register, the error flags are
+            // checked in the sticky receive status register afterwards.
+            p.uartrsr().write_value(pac::uart::regs::Uartrsr(0));
+            p.uartdmacr().modify(|w| w.set_rxdmae(true));
+            let transfer = dma::read(
+                &mut self.rx_dma,
+                p.uartdr().ptr() as *const u8,
+                buffer,
+                T::RX_DREQ,
+            );
+            transfer.await;
+            p.uartdmacr().modify(|w| w.set_rxdmae(false));
+
+            let rsr = p.uartrsr().read();
+            if rsr.oe() {
+                Err(Error::Overrun)
+            } else if rsr.be() {
+                Err(Error::Break)
+            } else if rsr.pe() {
+                Err(Error::Parity)
+            } else if rsr.fe() {
+                Err(Error::Framing)
+            } else {
+                Ok(())
+            }
+        }
+    }
 }
 
 mod sealed {
     use super::*;
 
     pub trait Instance {
+        const TX_DREQ: u8;
+        const RX_DREQ: u8;
+
         fn regs(&self) -> pac::uart::Uart;
     }
     pub trait TxPin<T: Instance> {}
@@ -115,8 +244,11 @@ mod sealed {
 pub trait Instance: sealed::Instance {}
 
 macro_rules! impl_instance {
-    ($type:ident, $irq:ident) => {
+    ($type:ident, $irq:ident, $tx_dreq:expr, $rx_dreq:expr) => {
         impl sealed::Instance for peripherals::$type {
+            const TX_DREQ: u8 = $tx_dreq;
+            const RX_DREQ: u8 = $rx_dreq;
+
             fn regs(&self) -> pac::uart::Uart {
                 pac::$type
             }
@@ -125,8 +257,8 @@ macro_rules! impl_instance {
     };
 }
 
-impl_instance!(UART0, UART0);
-impl_instance!(UART1, UART1);
+impl_instance!(UART0, UART0, 20, 21);
+impl_instance!(UART1, UART1, 22, 23);
 
 pub trait TxPin<T: Instance>: sealed::TxPin<T> + Pin {}
 pub trait RxPin<T: Instance>: sealed::RxPin<T> + Pin {}
@@ -170,3 +302,21 @@ impl_pin!(PIN_26, UART1, CtsPin);
 impl_pin!(PIN_27, UART1, RtsPin);
 impl_pin!(PIN_28, UART0, TxPin);
 impl_pin!(PIN_29, UART0, RxPin);
+
+mod eh02 {
+    use super::*;
+
+    impl<'d, T: Instance, TxDma, RxDma> embedded_hal_02::blocking::serial::Write<u8>
+        for Uart<'d, T, TxDma, RxDma>
+    {
+        type Error = Error;
+
+        fn bwrite_all(&mut self, buffer: &[u8]) -> Result<(), Self::Error> {
+            self.blocking_write(buffer)
+        }
+
+        fn bflush(&mut self) -> Result<(), Self::Error> {
+            self.blocking_flush()
+        }
+    }
+}
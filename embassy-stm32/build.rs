@@ -56,11 +56,18 @@ fn main() {
                     }
                     singletons.push(p.name.to_string());
                 }
+                // One singleton per request generator, on top of the DMAMUX itself.
+                "dmamux" => {
+                    singletons.push(p.name.to_string());
+                    for gen_num in 0..dmamux_generator_count() {
+                        singletons.push(format!("{}_RG{}", p.name, gen_num));
+                    }
+                }
+
                 //"dbgmcu" => {}
                 //"syscfg" => {}
                 //"dma" => {}
                 //"bdma" => {}
-                //"dmamux" => {}
 
                 // For other peripherals, one singleton per peri
                 _ => singletons.push(p.name.to_string()),
@@ -102,6 +109,29 @@ fn main() {
         }
     });
 
+    // ========
+    // Generate DMAMUX request generators
+
+    for p in METADATA.peripherals {
+        if let Some(r) = &p.registers {
+            if r.kind == "dmamux" {
+                let mux = format_ident!("{}", p.name);
+                for gen_num in 0..dmamux_generator_count() {
+                    let gen = format_ident!("{}_RG{}", p.name, gen_num);
+                    g.extend(quote! {
+                        impl crate::dma::dmamux::sealed::Generator for peripherals::#gen {
+                            const GEN_NUM: u8 = #gen_num;
+                            const DMAMUX_REGS: crate::pac::dmamux::Dmamux = crate::pac::#mux;
+                        }
+                        impl crate::dma::Generator for peripherals::#gen {
+                            type Mux = crate::dma::#mux;
+                        }
+                    });
+                }
+            }
+        }
+    }
+
     // ========
     // Generate DMA IRQs.
 
@@ -144,6 +174,20 @@ fn main() {
     // ========
     // Generate RccPeripheral impls
 
+    let rcc_version = METADATA
+        .peripherals
+        .iter()
+        .find(|p| p.name == "RCC")
+        .and_then(|p| p.registers.as_ref())
+        .map(|r| r.version);
+
+    // Peripherals whose kernel clock is selected in the RCC config. Their frequency is
+    // kept in the `Clocks` field named after them, instead of their bus clock.
+    let kernel_clocks: &[&str] = match rcc_version {
+        Some("l4") => &["USART1", "USART2", "USART3", "I2C1", "I2C2", "I2C3"],
+        _ => &[],
+    };
+
     for p in METADATA.peripherals {
         // generating RccPeripheral impl for H7 ADC3 would result in bad frequency
         if !singletons.contains(&p.name.to_string())
@@ -179,7 +223,11 @@ fn main() {
             };
 
             let pname = format_ident!("{}", p.name);
-            let clk = format_ident!("{}", rcc.clock.to_ascii_lowercase());
+            let clk = if kernel_clocks.contains(&p.name) {
+                format_ident!("{}", p.name.to_ascii_lowercase())
+            } else {
+                format_ident!("{}", rcc.clock.to_ascii_lowercase())
+            };
             let en_reg = format_ident!("{}", en.register.to_ascii_lowercase());
             let set_en_field = format_ident!("set_{}", en.field.to_ascii_lowercase());
 
@@ -250,11 +298,9 @@ fn main() {
         (("usart", "CTS"), (quote!(crate::usart::CtsPin), quote!())),
         (("usart", "RTS"), (quote!(crate::usart::RtsPin), quote!())),
         (("usart", "CK"), (quote!(crate::usart::CkPin), quote!())),
-        (("usart", "TX"), (quote!(crate::usart::TxPin), quote!())),
-        (("usart", "RX"), (quote!(crate::usart::RxPin), quote!())),
-        (("usart", "CTS"), (quote!(crate::usart::CtsPin), quote!())),
-        (("usart", "RTS"), (quote!(crate::usart::RtsPin), quote!())),
-        (("usart", "CK"), (quote!(crate::usart::CkPin), quote!())),
+        (("usart", "DE"), (quote!(crate::usart::DePin), quote!())),
+        (("lpuart", "TX"), (quote!(crate::usart::lpuart::TxPin), quote!())),
+        (("lpuart", "RX"), (quote!(crate::usart::lpuart::RxPin), quote!())),
         (("spi", "SCK"), (quote!(crate::spi::SckPin), quote!())),
         (("spi", "MOSI"), (quote!(crate::spi::MosiPin), quote!())),
         (("spi", "MISO"), (quote!(crate::spi::MisoPin), quote!())),
@@ -262,6 +308,11 @@ fn main() {
         (("i2c", "SCL"), (quote!(crate::i2c::SclPin), quote!())),
         (("rcc", "MCO_1"), (quote!(crate::rcc::McoPin), quote!())),
         (("rcc", "MCO_2"), (quote!(crate::rcc::McoPin), quote!())),
+        (("cec", "CEC"), (quote!(crate::cec::CecPin), quote!())),
+        (("spdifrx", "IN0"), (quote!(crate::spdifrx::In0Pin), quote!())),
+        (("spdifrx", "IN1"), (quote!(crate::spdifrx::In1Pin), quote!())),
+        (("spdifrx", "IN2"), (quote!(crate::spdifrx::In2Pin), quote!())),
+        (("spdifrx", "IN3"), (quote!(crate::spdifrx::In3Pin), quote!())),
         (("dcmi", "D0"), (quote!(crate::dcmi::D0Pin), quote!())),
         (("dcmi", "D1"), (quote!(crate::dcmi::D1Pin), quote!())),
         (("dcmi", "D2"), (quote!(crate::dcmi::D2Pin), quote!())),
@@ -279,6 +330,36 @@ fn main() {
         (("dcmi", "HSYNC"), (quote!(crate::dcmi::HSyncPin), quote!())),
         (("dcmi", "VSYNC"), (quote!(crate::dcmi::VSyncPin), quote!())),
         (("dcmi", "PIXCLK"), (quote!(crate::dcmi::PixClkPin), quote!())),
+        (("ltdc", "CLK"), (quote!(crate::ltdc::ClkPin), quote!())),
+        (("ltdc", "HSYNC"), (quote!(crate::ltdc::HSyncPin), quote!())),
+        (("ltdc", "VSYNC"), (quote!(crate::ltdc::VSyncPin), quote!())),
+        (("ltdc", "DE"), (quote!(crate::ltdc::DePin), quote!())),
+        (("ltdc", "R0"), (quote!(crate::ltdc::R0Pin), quote!())),
+        (("ltdc", "R1"), (quote!(crate::ltdc::R1Pin), quote!())),
+        (("ltdc", "R2"), (quote!(crate::ltdc::R2Pin), quote!())),
+        (("ltdc", "R3"), (quote!(crate::ltdc::R3Pin), quote!())),
+        (("ltdc", "R4"), (quote!(crate::ltdc::R4Pin), quote!())),
+        (("ltdc", "R5"), (quote!(crate::ltdc::R5Pin), quote!())),
+        (("ltdc", "R6"), (quote!(crate::ltdc::R6Pin), quote!())),
+        (("ltdc", "R7"), (quote!(crate::ltdc::R7Pin), quote!())),
+        (("ltdc", "G0"), (quote!(crate::ltdc::G0Pin), quote!())),
+        (("ltdc", "G1"), (quote!(crate::ltdc::G1Pin), quote!())),
+        (("ltdc", "G2"), (quote!(crate::ltdc::G2Pin), quote!())),
+        (("ltdc", "G3"), (quote!(crate::ltdc::G3Pin), quote!())),
+        (("ltdc", "G4"), (quote!(crate::ltdc::G4Pin), quote!())),
+        (("ltdc", "G5"), (quote!(crate::ltdc::G5Pin), quote!())),
+        (("ltdc", "G6"), (quote!(crate::ltdc::G6Pin), quote!())),
+        (("ltdc", "G7"), (quote!(crate::ltdc::G7Pin), quote!())),
+        (("ltdc", "B0"), (quote!(crate::ltdc::B0Pin), quote!())),
+        (("ltdc", "B1"), (quote!(crate::ltdc::B1Pin), quote!())),
+        (("ltdc", "B2"), (quote!(crate::ltdc::B2Pin), quote!())),
+        (("ltdc", "B3"), (quote!(crate::ltdc::B3Pin), quote!())),
+        (("ltdc", "B4"), (quote!(crate::ltdc::B4Pin), quote!())),
+        (("ltdc", "B5"), (quote!(crate::ltdc::B5Pin), quote!())),
+        (("ltdc", "B6"), (quote!(crate::ltdc::B6Pin), quote!())),
+        (("ltdc", "B7"), (quote!(crate::ltdc::B7Pin), quote!())),
+        (("usb", "DP"), (quote!(crate::usb::DpPin), quote!())),
+        (("usb", "DM"), (quote!(crate::usb::DmPin), quote!())),
         (("otgfs", "DP"), (quote!(crate::usb_otg::DpPin), quote!(#[cfg(feature="usb-otg")]))),
         (("otgfs", "DM"), (quote!(crate::usb_otg::DmPin), quote!(#[cfg(feature="usb-otg")]))),
         (("otghs", "DP"), (quote!(crate::usb_otg::DpPin), quote!(#[cfg(feature="usb-otg")]))),
@@ -502,14 +583,18 @@ fn main() {
         // (kind, signal) => trait
         (("usart", "RX"), quote!(crate::usart::RxDma)),
         (("usart", "TX"), quote!(crate::usart::TxDma)),
+        (("lpuart", "RX"), quote!(crate::usart::lpuart::RxDma)),
+        (("lpuart", "TX"), quote!(crate::usart::lpuart::TxDma)),
         (("spi", "RX"), quote!(crate::spi::RxDma)),
         (("spi", "TX"), quote!(crate::spi::TxDma)),
         (("i2c", "RX"), quote!(crate::i2c::RxDma)),
         (("i2c", "TX"), quote!(crate::i2c::TxDma)),
+        (("spdifrx", "RX"), quote!(crate::spdifrx::DataDma)),
         (("dcmi", "DCMI"), quote!(crate::dcmi::FrameDma)),
         (("dcmi", "PSSI"), quote!(crate::dcmi::FrameDma)),
         // SDMMCv1 uses the same channel for both directions, so just implement for RX
         (("sdmmc", "RX"), quote!(crate::sdmmc::SdmmcDma)),
+        (("timer", "UP"), quote!(crate::timer::UpDma)),
     ]
     .into();
 
@@ -818,3 +903,12 @@ macro_rules! {} {{
     )
     .unwrap();
 }
+
+/// Number of request generators of each DMAMUX.
+fn dmamux_generator_count() -> u8 {
+    if METADATA.line.starts_with("STM32H7") {
+        8
+    } else {
+        4
+    }
+}
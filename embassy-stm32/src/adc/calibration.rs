@@ -0,0 +1,193 @@
+//! Factory calibration of the internal voltage reference and temperature sensor.
+//!
+//! Each chip is measured at the factory: the raw readings of the internal reference (VREFINT)
+//! and of the temperature sensor at two temperatures are stored in system memory. This
+//! module reads them, and converts raw samples to millivolts and degrees with the formulas of
+//! the reference manuals.
+//!
+//! The calibration values are ADC readings at [`RESOLUTION_BITS`] bits, with VDDA at
+//! [`VREFINT_CAL_MV`]. The samples given to the functions here must have the same
+//! resolution. VDDA rarely matches the calibration voltage exactly, measure it first:
+//!
+//! ```ignore
+//! let vdda = calibration::vdda_millivolts(adc.read(&mut vref));
+//! let temp = calibration::to_degrees_centigrade(adc.read(&mut temp_sensor), vdda);
+//! let mv = calibration::to_millivolts(adc.read(&mut pin), vdda);
+//! ```
+
+/// Addresses and conditions of the calibration values, per family.
+struct Cal {
+    vrefint: usize,
+    ts_cal1: usize,
+    ts_cal2: usize,
+    /// VDDA during the calibration, in millivolts.
+    vdda_mv: u32,
+    /// Temperatures of the two sensor calibration points, in degrees Celsius.
+    ts_cal1_temp: i32,
+    ts_cal2_temp: i32,
+}
+
+#[cfg(any(stm32f0, stm32f3))]
+const CAL: Cal = Cal {
+    vrefint: 0x1FFF_F7BA,
+    ts_cal1: 0x1FFF_F7B8,
+    ts_cal2: 0x1FFF_F7C2,
+    vdda_mv: 3300,
+    ts_cal1_temp: 30,
+    ts_cal2_temp: 110,
+};
+
+#[cfg(stm32f4)]
+const CAL: Cal = Cal {
+    vrefint: 0x1FFF_7A2A,
+    ts_cal1: 0x1FFF_7A2C,
+    ts_cal2: 0x1FFF_7A2E,
+    vdda_mv: 3300,
+    ts_cal1_temp: 30,
+    ts_cal2_temp: 110,
+};
+
+#[cfg(stm32f7)]
+const CAL: Cal = Cal {
+    vrefint: 0x1FF0_F44A,
+    ts_cal1: 0x1FF0_F44C,
+    ts_cal2: 0x1FF0_F44E,
+    vdda_mv: 3300,
+    ts_cal1_temp: 30,
+    ts_cal2_temp: 110,
+};
+
+#[cfg(stm32l0)]
+const CAL: Cal = Cal {
+    vrefint: 0x1FF8_0078,
+    ts_cal1: 0x1FF8_007A,
+    ts_cal2: 0x1FF8_007E,
+    vdda_mv: 3000,
+    ts_cal1_temp: 30,
+    ts_cal2_temp: 130,
+};
+
+#[cfg(any(stm32l4, stm32g0, stm32g4))]
+const CAL: Cal = Cal {
+    vrefint: 0x1FFF_75AA,
+    ts_cal1: 0x1FFF_75A8,
+    ts_cal2: 0x1FFF_75CA,
+    vdda_mv: 3000,
+    ts_cal1_temp: 30,
+    // The first L4 lines are calibrated at 110 °C, the later ones at 130 °C.
+    #[cfg(any(stm32l47x, stm32l48x))]
+    ts_cal2_temp: 110,
+    #[cfg(not(any(stm32l47x, stm32l48x)))]
+    ts_cal2_temp: 130,
+};
+
+#[cfg(stm32l5)]
+const CAL: Cal = Cal {
+    vrefint: 0x0BFA_05AA,
+    ts_cal1: 0x0BFA_05A8,
+    ts_cal2: 0x0BFA_05CA,
+    vdda_mv: 3000,
+    ts_cal1_temp: 30,
+    ts_cal2_temp: 130,
+};
+
+#[cfg(stm32wb)]
+const CAL: Cal = Cal {
+    vrefint: 0x1FFF_75AA,
+    ts_cal1: 0x1FFF_75A8,
+    ts_cal2: 0x1FFF_75CA,
+    vdda_mv: 3600,
+    ts_cal1_temp: 30,
+    ts_cal2_temp: 130,
+};
+
+#[cfg(stm32wl)]
+const CAL: Cal = Cal {
+    vrefint: 0x1FFF_75AA,
+    ts_cal1: 0x1FFF_75A8,
+    ts_cal2: 0x1FFF_75C8,
+    vdda_mv: 3300,
+    ts_cal1_temp: 30,
+    ts_cal2_temp: 130,
+};
+
+#[cfg(all(stm32h7, not(any(stm32h7ax, stm32h7bx))))]
+const CAL: Cal = Cal {
+    vrefint: 0x1FF1_E860,
+    ts_cal1: 0x1FF1_E820,
+    ts_cal2: 0x1FF1_E840,
+    vdda_mv: 3300,
+    ts_cal1_temp: 30,
+    ts_cal2_temp: 110,
+};
+
+#[cfg(any(stm32h7ax, stm32h7bx))]
+const CAL: Cal = Cal {
+    vrefint: 0x08FF_F810,
+    ts_cal1: 0x08FF_F814,
+    ts_cal2: 0x08FF_F818,
+    vdda_mv: 3300,
+    ts_cal1_temp: 30,
+    ts_cal2_temp: 130,
+};
+
+/// Resolution of the calibration values.
+#[cfg(not(stm32h7))]
+pub const RESOLUTION_BITS: u32 = 12;
+/// Resolution of the calibration values.
+#[cfg(stm32h7)]
+pub const RESOLUTION_BITS: u32 = 16;
+
+const MAX_COUNT: u32 = (1 << RESOLUTION_BITS) - 1;
+
+/// VDDA during the calibration, in millivolts.
+pub const VREFINT_CAL_MV: u32 = CAL.vdda_mv;
+/// Temperature of the first calibration point of the temperature sensor, in °C.
+pub const TS_CAL1_TEMP: i32 = CAL.ts_cal1_temp;
+/// Temperature of the second calibration point of the temperature sensor, in °C.
+pub const TS_CAL2_TEMP: i32 = CAL.ts_cal2_temp;
+
+fn read(addr: usize) -> u16 {
+    // Safety: the address is in the read-only system memory of this family.
+    unsafe { core::ptr::read_volatile(addr as *const u16) }
+}
+
+/// Raw reading of VREFINT at the factory, VREFINT_CAL.
+pub fn vrefint_cal() -> u16 {
+    read(CAL.vrefint)
+}
+
+/// Raw reading of the temperature sensor at [`TS_CAL1_TEMP`], TS_CAL1.
+pub fn ts_cal1() -> u16 {
+    read(CAL.ts_cal1)
+}
+
+/// Raw reading of the temperature sensor at [`TS_CAL2_TEMP`], TS_CAL2.
+pub fn ts_cal2() -> u16 {
+    read(CAL.ts_cal2)
+}
+
+/// Compute VDDA from a sample of the internal voltage reference.
+pub fn vdda_millivolts(vrefint_sample: u16) -> u32 {
+    if vrefint_sample == 0 {
+        return 0;
+    }
+    CAL.vdda_mv * u32::from(vrefint_cal()) / u32::from(vrefint_sample)
+}
+
+/// Convert a sample to millivolts, with VDDA at `vdda_mv`.
+pub fn to_millivolts(sample: u16, vdda_mv: u32) -> u32 {
+    u32::from(sample) * vdda_mv / MAX_COUNT
+}
+
+/// Convert a sample of the temperature sensor to degrees Celsius, with VDDA at `vdda_mv`.
+///
+/// The sensor is linear between the two calibration points.
+pub fn to_degrees_centigrade(sample: u16, vdda_mv: u32) -> f32 {
+    // What the sample would have been with VDDA at the calibration voltage.
+    let sample = sample as f32 * vdda_mv as f32 / CAL.vdda_mv as f32;
+    let cal1 = ts_cal1() as f32;
+    let cal2 = ts_cal2() as f32;
+    (CAL.ts_cal2_temp - CAL.ts_cal1_temp) as f32 / (cal2 - cal1) * (sample - cal1)
+        + CAL.ts_cal1_temp as f32
+}
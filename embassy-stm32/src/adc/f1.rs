@@ -1,4 +1,4 @@
-use crate::adc::{AdcPin, Instance};
+use crate::adc::{AdcPin, Instance, InternalChannel};
 use crate::rcc::get_freqs;
 use crate::time::Hertz;
 use core::marker::PhantomData;
@@ -12,16 +12,16 @@ pub const ADC_MAX: u32 = (1 << 12) - 1;
 pub const VREF_INT: u32 = 1200;
 
 pub struct Vref;
-impl<T: Instance> AdcPin<T> for Vref {}
-impl<T: Instance> super::sealed::AdcPin<T> for Vref {
+impl<T: Instance> InternalChannel<T> for Vref {}
+impl<T: Instance> super::sealed::InternalChannel<T> for Vref {
     fn channel(&self) -> u8 {
         17
     }
 }
 
 pub struct Temperature;
-impl<T: Instance> AdcPin<T> for Temperature {}
-impl<T: Instance> super::sealed::AdcPin<T> for Temperature {
+impl<T: Instance> InternalChannel<T> for Temperature {}
+impl<T: Instance> super::sealed::InternalChannel<T> for Temperature {
     fn channel(&self) -> u8 {
         16
     }
@@ -142,21 +142,30 @@ impl<'d, T: Instance> Adc<'d, T> {
         }
     }
 
-    pub fn enable_vref(&self, _delay: &mut impl DelayUs<u32>) -> Vref {
+    pub fn enable_vref(&self, delay: &mut impl DelayUs<u32>) -> Vref {
         unsafe {
             T::regs().cr2().modify(|reg| {
                 reg.set_tsvrefe(true);
             })
         }
+
+        delay.delay_us(10);
+
         Vref {}
     }
 
-    pub fn enable_temperature(&self) -> Temperature {
+    /// Enable the temperature sensor. Its samples must last at least 17.1 us, see
+    /// [`set_sample_time`](Self::set_sample_time).
+    pub fn enable_temperature(&self, delay: &mut impl DelayUs<u32>) -> Temperature {
         unsafe {
             T::regs().cr2().modify(|reg| {
                 reg.set_tsvrefe(true);
             })
         }
+
+        // The sensor takes up to 10 us to start ("t_START" in the datasheet).
+        delay.delay_us(10);
+
         Temperature {}
     }
 
@@ -167,7 +176,7 @@ impl<'d, T: Instance> Adc<'d, T> {
         let old_sample_time = self.sample_time;
         self.sample_time = SampleTime::Cycles239_5;
 
-        let vref_samp = self.read(vref);
+        let vref_samp = self.read_internal(vref);
         self.sample_time = old_sample_time;
 
         self.calibrated_vdda = (ADC_MAX * VREF_INT) / u32::from(vref_samp);
@@ -198,8 +207,16 @@ impl<'d, T: Instance> Adc<'d, T> {
     }
 
     pub fn read(&mut self, pin: &mut impl AdcPin<T>) -> u16 {
+        self.read_channel(pin.channel())
+    }
+
+    pub fn read_internal(&mut self, channel: &mut impl InternalChannel<T>) -> u16 {
+        self.read_channel(channel.channel())
+    }
+
+    fn read_channel(&mut self, channel: u8) -> u16 {
         unsafe {
-            Self::set_channel_sample_time(pin.channel(), self.sample_time);
+            Self::set_channel_sample_time(channel, self.sample_time);
             T::regs().cr1().modify(|reg| {
                 reg.set_scan(false);
                 reg.set_discen(false);
@@ -215,7 +232,7 @@ impl<'d, T: Instance> Adc<'d, T> {
         }
 
         // Configure the channel to sample
-        unsafe { T::regs().sqr3().write(|reg| reg.set_sq(0, pin.channel())) }
+        unsafe { T::regs().sqr3().write(|reg| reg.set_sq(0, channel)) }
         self.convert()
     }
 
@@ -0,0 +1,225 @@
+//! Conversions triggered by a PWM timer, for motor control.
+//!
+//! Field oriented control needs the phase currents sampled at the same point of each PWM
+//! period, usually while the low side switches are on, to measure them through low side
+//! shunts. [`CurrentSampler`] uses the injected conversions of the ADC, started in hardware
+//! by the TRGO output of the timer: there's no software latency between the PWM and the
+//! sampling.
+
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy::interrupt::{Interrupt, InterruptExt};
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::drop::OnDrop;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::adc::sealed::AdcPin as _;
+use crate::adc::{Adc, AdcPin, Instance};
+use crate::peripherals;
+use crate::pwm::complementary_pwm::ComplementaryPwm;
+use crate::pwm::ComplementaryCaptureCompare16bitInstance;
+
+/// Samples of the channels given to [`CurrentSampler::new`], at each PWM period.
+///
+/// Up to 4 channels can be sampled, converted one after the other. The ADC is dedicated to
+/// the sampler while it exists.
+pub struct CurrentSampler<'d, T: InjectedInstance, const N: usize> {
+    _adc: Adc<'d, T>,
+    phantom: PhantomData<&'d mut T>,
+}
+
+impl<'d, T: InjectedInstance, const N: usize> CurrentSampler<'d, T, N> {
+    /// Sample `channels` each time `pwm` counts up past its trigger point, set to just below
+    /// the maximum duty. Move it with [`ComplementaryPwm::set_trigger_point`].
+    pub fn new<TIM: InjectedTrigger<T>>(
+        adc: Adc<'d, T>,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        pwm: &mut ComplementaryPwm<'_, TIM>,
+        channels: [&mut dyn AdcPin<T>; N],
+    ) -> Self {
+        assert!((1..=4).contains(&N));
+        unborrow!(irq);
+
+        pwm.set_trigger_point(pwm.get_max_duty() - 1);
+
+        let r = T::regs();
+        unsafe {
+            while r.cr().read().addis() {
+                // spin
+            }
+            r.isr().modify(|w| w.set_adrdy(true));
+            r.cr().modify(|w| w.set_aden(true));
+            while !r.isr().read().adrdy() {
+                // spin
+            }
+
+            r.cfgr().modify(|w| w.set_res(adc.resolution.res()));
+            r.jsqr().write(|w| {
+                w.set_jl(N as u8 - 1);
+                w.set_jextsel(TIM::JEXTSEL);
+                w.set_jexten(crate::pac::adc::vals::Exten::RISINGEDGE);
+                for (i, ch) in channels.iter().enumerate() {
+                    w.set_jsq(i, ch.channel());
+                }
+            });
+            for ch in channels.iter() {
+                Adc::<T>::set_channel_sample_time(ch.channel(), adc.sample_time);
+            }
+        }
+
+        irq.set_handler(Self::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        // Arm the trigger.
+        unsafe { r.cr().modify(|w| w.set_jadstart(true)) };
+
+        Self {
+            _adc: adc,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Wait for the samples of the next PWM period.
+    ///
+    /// Samples of the periods that end before this is called again are dropped, so it has
+    /// to be called at least once per period to see them all.
+    pub async fn next(&mut self) -> [u16; N] {
+        let r = T::regs();
+        let on_drop = OnDrop::new(|| unsafe { r.ier().modify(|w| w.set_jeosie(false)) });
+
+        unsafe { r.isr().write(|w| w.set_jeos(true)) };
+
+        poll_fn(|cx| {
+            T::state().waker.register(cx.waker());
+            if unsafe { r.isr().read().jeos() } {
+                Poll::Ready(())
+            } else {
+                unsafe { r.ier().modify(|w| w.set_jeosie(true)) };
+                Poll::Pending
+            }
+        })
+        .await;
+
+        drop(on_drop);
+        let mut samples = [0; N];
+        for (i, sample) in samples.iter_mut().enumerate() {
+            *sample = unsafe { r.jdr(i).read().jdata() };
+        }
+        samples
+    }
+
+    unsafe fn on_interrupt(_: *mut ()) {
+        let r = T::regs();
+        if r.isr().read().jeos() {
+            r.ier().modify(|w| w.set_jeosie(false));
+            T::state().waker.wake();
+        }
+    }
+}
+
+impl<'d, T: InjectedInstance, const N: usize> Drop for CurrentSampler<'d, T, N> {
+    fn drop(&mut self) {
+        let r = T::regs();
+        unsafe {
+            r.cr().modify(|w| w.set_jadstp(true));
+            while r.cr().read().jadstart() {
+                // spin
+            }
+            r.cr().modify(|w| w.set_addis(true));
+        }
+    }
+}
+
+pub(crate) mod sealed {
+    use super::*;
+
+    pub struct State {
+        pub waker: AtomicWaker,
+    }
+
+    impl State {
+        pub const fn new() -> Self {
+            Self {
+                waker: AtomicWaker::new(),
+            }
+        }
+    }
+
+    pub trait InjectedInstance {
+        fn state() -> &'static State;
+    }
+
+    pub trait InjectedTrigger<T> {
+        /// Value of JEXTSEL selecting the TRGO output of the timer.
+        const JEXTSEL: u8;
+    }
+}
+
+/// An ADC with its interrupt, to use for injected conversions.
+///
+/// ADCs sharing an interrupt can't do injected conversions at the same time.
+pub trait InjectedInstance: sealed::InjectedInstance + Instance {
+    type Interrupt: Interrupt;
+}
+
+/// A timer whose TRGO output can start injected conversions of ADC `T`.
+pub trait InjectedTrigger<T: Instance>:
+    sealed::InjectedTrigger<T> + ComplementaryCaptureCompare16bitInstance
+{
+}
+
+foreach_interrupt!(
+    ($inst:ident, adc, $block:ident, GLOBAL, $irq:ident) => {
+        impl sealed::InjectedInstance for peripherals::$inst {
+            fn state() -> &'static sealed::State {
+                static STATE: sealed::State = sealed::State::new();
+                &STATE
+            }
+        }
+
+        impl InjectedInstance for peripherals::$inst {
+            type Interrupt = crate::interrupt::$irq;
+        }
+    };
+);
+
+macro_rules! impl_injected_trigger {
+    ($adc:ident, $tim:ident, $jextsel:expr) => {
+        impl sealed::InjectedTrigger<peripherals::$adc> for peripherals::$tim {
+            const JEXTSEL: u8 = $jextsel;
+        }
+
+        impl InjectedTrigger<peripherals::$adc> for peripherals::$tim {}
+    };
+}
+
+// All the ADCs of the L4, and ADC1/ADC2 of the G4, share the same trigger table.
+#[cfg(stm32l4)]
+foreach_peripheral!(
+    (adc, $adc:ident) => {
+        foreach_peripheral!(
+            (timer, TIM1) => { impl_injected_trigger!($adc, TIM1, 0b0000); };
+            (timer, TIM8) => { impl_injected_trigger!($adc, TIM8, 0b1001); };
+        );
+    };
+);
+
+#[cfg(stm32g4)]
+foreach_peripheral!(
+    (adc, ADC1) => {
+        impl_injected_trigger!(ADC1, TIM1, 0b00000);
+        foreach_peripheral!(
+            (timer, TIM8) => { impl_injected_trigger!(ADC1, TIM8, 0b01001); };
+        );
+    };
+    (adc, ADC2) => {
+        impl_injected_trigger!(ADC2, TIM1, 0b00000);
+        foreach_peripheral!(
+            (timer, TIM8) => { impl_injected_trigger!(ADC2, TIM8, 0b01001); };
+        );
+    };
+);
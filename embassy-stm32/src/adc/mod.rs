@@ -11,6 +11,15 @@ mod _version;
 #[allow(unused)]
 pub use _version::*;
 
+#[cfg(adc_v3)]
+pub mod injected;
+
+#[cfg(any(
+    stm32f0, stm32f3, stm32f4, stm32f7, stm32l0, stm32l4, stm32l5, stm32g0, stm32g4, stm32wb,
+    stm32wl, stm32h7
+))]
+pub mod calibration;
+
 use crate::peripherals;
 
 pub(crate) mod sealed {
@@ -28,6 +37,10 @@ pub(crate) mod sealed {
     pub trait AdcPin<T: Instance> {
         fn channel(&self) -> u8;
     }
+
+    pub trait InternalChannel<T> {
+        fn channel(&self) -> u8;
+    }
 }
 
 #[cfg(not(adc_f1))]
@@ -37,6 +50,10 @@ pub trait Instance: sealed::Instance + crate::rcc::RccPeripheral + 'static {}
 #[cfg(all(not(adc_f1), not(adc_v1)))]
 pub trait Common: sealed::Common + 'static {}
 pub trait AdcPin<T: Instance>: sealed::AdcPin<T> {}
+/// A channel connected to a signal inside the chip, like the internal voltage reference or the
+/// temperature sensor. They're returned by the `enable_*` methods of the ADC once the signal
+/// is switched on.
+pub trait InternalChannel<T>: sealed::InternalChannel<T> {}
 
 #[cfg(not(stm32h7))]
 foreach_peripheral!(
@@ -1,4 +1,4 @@
-use crate::adc::{AdcPin, Instance};
+use crate::adc::{AdcPin, Instance, InternalChannel};
 use core::marker::PhantomData;
 use embassy::util::Unborrow;
 use embassy_hal_common::unborrow;
@@ -54,25 +54,39 @@ impl Resolution {
     }
 }
 
+// NOTE: the internal channels are only connected to ADC1.
 pub struct Vref;
-impl<T: Instance> AdcPin<T> for Vref {}
-impl<T: Instance> super::sealed::AdcPin<T> for Vref {
+impl<T: Instance> InternalChannel<T> for Vref {}
+impl<T: Instance> super::sealed::InternalChannel<T> for Vref {
     fn channel(&self) -> u8 {
         17
     }
 }
 
+/// The temperature sensor. On chips other than the F2, F40x and F41x, it shares its channel
+/// with VBAT, which takes precedence: enabling one disables the other.
 pub struct Temperature;
-impl<T: Instance> AdcPin<T> for Temperature {}
-impl<T: Instance> super::sealed::AdcPin<T> for Temperature {
+impl<T: Instance> InternalChannel<T> for Temperature {}
+impl<T: Instance> super::sealed::InternalChannel<T> for Temperature {
     fn channel(&self) -> u8 {
-        16
+        #[cfg(any(stm32f2, stm32f40x, stm32f41x))]
+        let val = 16;
+        #[cfg(not(any(stm32f2, stm32f40x, stm32f41x)))]
+        let val = 18;
+        val
     }
 }
 
+/// VBAT, measured through a bridge that divides it by [`Vbat::DIVIDER`].
 pub struct Vbat;
-impl<T: Instance> AdcPin<T> for Vbat {}
-impl<T: Instance> super::sealed::AdcPin<T> for Vbat {
+impl Vbat {
+    #[cfg(any(stm32f2, stm32f40x, stm32f41x))]
+    pub const DIVIDER: u32 = 2;
+    #[cfg(not(any(stm32f2, stm32f40x, stm32f41x)))]
+    pub const DIVIDER: u32 = 4;
+}
+impl<T: Instance> InternalChannel<T> for Vbat {}
+impl<T: Instance> super::sealed::InternalChannel<T> for Vbat {
     fn channel(&self) -> u8 {
         18
     }
@@ -145,6 +159,45 @@ where
         }
     }
 
+    pub fn enable_vref(&self, delay: &mut impl DelayUs<u32>) -> Vref {
+        unsafe {
+            T::common_regs().ccr().modify(|reg| {
+                reg.set_tsvrefe(true);
+            });
+        }
+
+        // The reference and the sensor take at most 10 us to start ("t_START" in the datasheet).
+        delay.delay_us(10);
+
+        Vref {}
+    }
+
+    /// Enable the temperature sensor. Its samples must last at least 10 us, see
+    /// [`set_sample_time`](Self::set_sample_time).
+    pub fn enable_temperature(&self, delay: &mut impl DelayUs<u32>) -> Temperature {
+        unsafe {
+            T::common_regs().ccr().modify(|reg| {
+                #[cfg(not(any(stm32f2, stm32f40x, stm32f41x)))]
+                reg.set_vbate(false);
+                reg.set_tsvrefe(true);
+            });
+        }
+
+        delay.delay_us(10);
+
+        Temperature {}
+    }
+
+    pub fn enable_vbat(&self) -> Vbat {
+        unsafe {
+            T::common_regs().ccr().modify(|reg| {
+                reg.set_vbate(true);
+            });
+        }
+
+        Vbat {}
+    }
+
     pub fn set_sample_time(&mut self, sample_time: SampleTime) {
         self.sample_time = sample_time;
     }
@@ -188,44 +241,52 @@ where
         P: crate::gpio::sealed::Pin,
     {
         unsafe {
-            // dissable ADC
-            T::regs().cr2().modify(|reg| {
-                reg.set_swstart(false);
-            });
-            T::regs().cr2().modify(|reg| {
-                reg.set_adon(crate::pac::adc::vals::Adon::DISABLED);
-            });
-
             pin.set_as_analog();
 
-            // Configure ADC
-            T::regs()
-                .cr1()
-                .modify(|reg| reg.set_res(self.resolution.res()));
-
-            // Select channel
-            T::regs().sqr3().write(|reg| reg.set_sq(0, pin.channel()));
-
-            // Configure channel
-            Self::set_channel_sample_time(pin.channel(), self.sample_time);
-
-            // enable adc
-            T::regs().cr2().modify(|reg| {
-                reg.set_adon(crate::pac::adc::vals::Adon::ENABLED);
-            });
-
-            let val = self.convert();
+            self.read_channel(pin.channel())
+        }
+    }
 
-            // dissable ADC
-            T::regs().cr2().modify(|reg| {
-                reg.set_swstart(false);
-            });
-            T::regs().cr2().modify(|reg| {
-                reg.set_adon(crate::pac::adc::vals::Adon::DISABLED);
-            });
+    pub fn read_internal(&mut self, channel: &mut impl InternalChannel<T>) -> u16 {
+        unsafe { self.read_channel(channel.channel()) }
+    }
 
-            val
-        }
+    unsafe fn read_channel(&mut self, channel: u8) -> u16 {
+        // dissable ADC
+        T::regs().cr2().modify(|reg| {
+            reg.set_swstart(false);
+        });
+        T::regs().cr2().modify(|reg| {
+            reg.set_adon(crate::pac::adc::vals::Adon::DISABLED);
+        });
+
+        // Configure ADC
+        T::regs()
+            .cr1()
+            .modify(|reg| reg.set_res(self.resolution.res()));
+
+        // Select channel
+        T::regs().sqr3().write(|reg| reg.set_sq(0, channel));
+
+        // Configure channel
+        Self::set_channel_sample_time(channel, self.sample_time);
+
+        // enable adc
+        T::regs().cr2().modify(|reg| {
+            reg.set_adon(crate::pac::adc::vals::Adon::ENABLED);
+        });
+
+        let val = self.convert();
+
+        // dissable ADC
+        T::regs().cr2().modify(|reg| {
+            reg.set_swstart(false);
+        });
+        T::regs().cr2().modify(|reg| {
+            reg.set_adon(crate::pac::adc::vals::Adon::DISABLED);
+        });
+
+        val
     }
 
     unsafe fn set_channel_sample_time(ch: u8, sample_time: SampleTime) {
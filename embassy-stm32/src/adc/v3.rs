@@ -1,4 +1,4 @@
-use crate::adc::{AdcPin, Instance};
+use crate::adc::{AdcPin, Instance, InternalChannel};
 use core::marker::PhantomData;
 use embassy::util::Unborrow;
 use embassy_hal_common::unborrow;
@@ -33,7 +33,7 @@ impl Default for Resolution {
 }
 
 impl Resolution {
-    fn res(&self) -> crate::pac::adc::vals::Res {
+    pub(super) fn res(&self) -> crate::pac::adc::vals::Res {
         match self {
             Resolution::TwelveBit => crate::pac::adc::vals::Res::TWELVEBIT,
             Resolution::TenBit => crate::pac::adc::vals::Res::TENBIT,
@@ -53,8 +53,8 @@ impl Resolution {
 }
 
 pub struct Vref;
-impl<T: Instance> AdcPin<T> for Vref {}
-impl<T: Instance> super::sealed::AdcPin<T> for Vref {
+impl<T: Instance> InternalChannel<T> for Vref {}
+impl<T: Instance> super::sealed::InternalChannel<T> for Vref {
     fn channel(&self) -> u8 {
         #[cfg(not(stm32g0))]
         let val = 0;
@@ -65,8 +65,8 @@ impl<T: Instance> super::sealed::AdcPin<T> for Vref {
 }
 
 pub struct Temperature;
-impl<T: Instance> AdcPin<T> for Temperature {}
-impl<T: Instance> super::sealed::AdcPin<T> for Temperature {
+impl<T: Instance> InternalChannel<T> for Temperature {}
+impl<T: Instance> super::sealed::InternalChannel<T> for Temperature {
     fn channel(&self) -> u8 {
         #[cfg(not(stm32g0))]
         let val = 17;
@@ -76,9 +76,13 @@ impl<T: Instance> super::sealed::AdcPin<T> for Temperature {
     }
 }
 
+/// VBAT, measured through a bridge that divides it by [`Vbat::DIVIDER`].
 pub struct Vbat;
-impl<T: Instance> AdcPin<T> for Vbat {}
-impl<T: Instance> super::sealed::AdcPin<T> for Vbat {
+impl Vbat {
+    pub const DIVIDER: u32 = 3;
+}
+impl<T: Instance> InternalChannel<T> for Vbat {}
+impl<T: Instance> super::sealed::InternalChannel<T> for Vbat {
     fn channel(&self) -> u8 {
         #[cfg(not(stm32g0))]
         let val = 18;
@@ -199,9 +203,9 @@ mod sample_time {
 pub use sample_time::SampleTime;
 
 pub struct Adc<'d, T: Instance> {
-    sample_time: SampleTime,
+    pub(super) sample_time: SampleTime,
     calibrated_vdda: u32,
-    resolution: Resolution,
+    pub(super) resolution: Resolution,
     phantom: PhantomData<&'d mut T>,
 }
 
@@ -260,13 +264,18 @@ impl<'d, T: Instance> Adc<'d, T> {
         Vref {}
     }
 
-    pub fn enable_temperature(&self) -> Temperature {
+    /// Enable the temperature sensor. Its samples must last at least 5 us, see
+    /// [`set_sample_time`](Self::set_sample_time).
+    pub fn enable_temperature(&self, delay: &mut impl DelayUs<u32>) -> Temperature {
         unsafe {
             T::common_regs().ccr().modify(|reg| {
                 reg.set_ch17sel(true);
             });
         }
 
+        // The sensor takes up to 120 us to start ("t_START" in the datasheets).
+        delay.delay_us(120);
+
         Temperature {}
     }
 
@@ -286,10 +295,6 @@ impl<'d, T: Instance> Adc<'d, T> {
     #[cfg(not(stm32g0))] // TODO is this supposed to be public?
     #[allow(unused)] // TODO is this supposed to be public?
     fn calibrate(&mut self, vref: &mut Vref) {
-        #[cfg(stm32l5)]
-        let vref_cal: u32 = todo!();
-        #[cfg(not(stm32l5))]
-        let vref_cal = unsafe { crate::pac::VREFINTCAL.data().read().value() };
         let old_sample_time = self.sample_time;
 
         // "Table 24. Embedded internal voltage reference" states that the sample time needs to be
@@ -298,11 +303,11 @@ impl<'d, T: Instance> Adc<'d, T> {
         self.sample_time = SampleTime::Cycles640_5;
 
         // This can't actually fail, it's just in a result to satisfy hal trait
-        let vref_samp = self.read(vref);
+        let vref_samp = self.read_internal(vref);
 
         self.sample_time = old_sample_time;
 
-        self.calibrated_vdda = (VDDA_CALIB_MV * u32::from(vref_cal)) / u32::from(vref_samp);
+        self.calibrated_vdda = super::calibration::vdda_millivolts(vref_samp);
     }
 
     pub fn set_sample_time(&mut self, sample_time: SampleTime) {
@@ -349,60 +354,66 @@ impl<'d, T: Instance> Adc<'d, T> {
     }
 
     pub fn read(&mut self, pin: &mut impl AdcPin<T>) -> u16 {
-        unsafe {
-            // Make sure bits are off
-            while T::regs().cr().read().addis() {
-                // spin
-            }
+        unsafe { self.read_channel(pin.channel()) }
+    }
 
-            // Enable ADC
-            T::regs().isr().modify(|reg| {
-                reg.set_adrdy(true);
-            });
-            T::regs().cr().modify(|reg| {
-                reg.set_aden(true);
-            });
+    pub fn read_internal(&mut self, channel: &mut impl InternalChannel<T>) -> u16 {
+        unsafe { self.read_channel(channel.channel()) }
+    }
 
-            while !T::regs().isr().read().adrdy() {
-                // spin
-            }
+    unsafe fn read_channel(&mut self, channel: u8) -> u16 {
+        // Make sure bits are off
+        while T::regs().cr().read().addis() {
+            // spin
+        }
 
-            // Configure ADC
-            #[cfg(not(stm32g0))]
-            T::regs()
-                .cfgr()
-                .modify(|reg| reg.set_res(self.resolution.res()));
-            #[cfg(stm32g0)]
-            T::regs()
-                .cfgr1()
-                .modify(|reg| reg.set_res(self.resolution.res()));
+        // Enable ADC
+        T::regs().isr().modify(|reg| {
+            reg.set_adrdy(true);
+        });
+        T::regs().cr().modify(|reg| {
+            reg.set_aden(true);
+        });
 
-            // Configure channel
-            Self::set_channel_sample_time(pin.channel(), self.sample_time);
+        while !T::regs().isr().read().adrdy() {
+            // spin
+        }
 
-            // Select channel
-            #[cfg(not(stm32g0))]
-            T::regs().sqr1().write(|reg| reg.set_sq(0, pin.channel()));
-            #[cfg(stm32g0)]
-            T::regs()
-                .chselr()
-                .write(|reg| reg.set_chsel(pin.channel() as u32));
+        // Configure ADC
+        #[cfg(not(stm32g0))]
+        T::regs()
+            .cfgr()
+            .modify(|reg| reg.set_res(self.resolution.res()));
+        #[cfg(stm32g0)]
+        T::regs()
+            .cfgr1()
+            .modify(|reg| reg.set_res(self.resolution.res()));
 
-            // Some models are affected by an erratum:
-            // If we perform conversions slower than 1 kHz, the first read ADC value can be
-            // corrupted, so we discard it and measure again.
-            //
-            // STM32L471xx: Section 2.7.3
-            // STM32G4: Section 2.7.3
-            #[cfg(any(rcc_l4, rcc_g4))]
-            let _ = self.convert();
+        // Configure channel
+        Self::set_channel_sample_time(channel, self.sample_time);
 
-            let val = self.convert();
+        // Select channel
+        #[cfg(not(stm32g0))]
+        T::regs().sqr1().write(|reg| reg.set_sq(0, channel));
+        #[cfg(stm32g0)]
+        T::regs()
+            .chselr()
+            .write(|reg| reg.set_chsel(channel as u32));
 
-            T::regs().cr().modify(|reg| reg.set_addis(true));
+        // Some models are affected by an erratum:
+        // If we perform conversions slower than 1 kHz, the first read ADC value can be
+        // corrupted, so we discard it and measure again.
+        //
+        // STM32L471xx: Section 2.7.3
+        // STM32G4: Section 2.7.3
+        #[cfg(any(rcc_l4, rcc_g4))]
+        let _ = self.convert();
 
-            val
-        }
+        let val = self.convert();
+
+        T::regs().cr().modify(|reg| reg.set_addis(true));
+
+        val
     }
 
     #[cfg(stm32g0)]
@@ -413,7 +424,7 @@ impl<'d, T: Instance> Adc<'d, T> {
     }
 
     #[cfg(not(stm32g0))]
-    unsafe fn set_channel_sample_time(ch: u8, sample_time: SampleTime) {
+    pub(super) unsafe fn set_channel_sample_time(ch: u8, sample_time: SampleTime) {
         if ch <= 9 {
             T::regs()
                 .smpr1()
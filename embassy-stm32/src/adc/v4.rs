@@ -10,7 +10,7 @@ use pac::adccommon::vals::Presc;
 
 use crate::pac;
 
-use super::{AdcPin, Instance};
+use super::{AdcPin, Instance, InternalChannel};
 
 pub enum Resolution {
     SixteenBit,
@@ -48,18 +48,10 @@ impl Resolution {
     }
 }
 
-pub trait InternalChannel<T>: sealed::InternalChannel<T> {}
-
-mod sealed {
-    pub trait InternalChannel<T> {
-        fn channel(&self) -> u8;
-    }
-}
-
 // NOTE: Vref/Temperature/Vbat are only available on ADC3 on H7, this currently cannot be modeled with stm32-data, so these are available from the software on all ADCs
 pub struct Vref;
 impl<T: Instance> InternalChannel<T> for Vref {}
-impl<T: Instance> sealed::InternalChannel<T> for Vref {
+impl<T: Instance> super::sealed::InternalChannel<T> for Vref {
     fn channel(&self) -> u8 {
         19
     }
@@ -67,15 +59,19 @@ impl<T: Instance> sealed::InternalChannel<T> for Vref {
 
 pub struct Temperature;
 impl<T: Instance> InternalChannel<T> for Temperature {}
-impl<T: Instance> sealed::InternalChannel<T> for Temperature {
+impl<T: Instance> super::sealed::InternalChannel<T> for Temperature {
     fn channel(&self) -> u8 {
         18
     }
 }
 
+/// VBAT, measured through a bridge that divides it by [`Vbat::DIVIDER`].
 pub struct Vbat;
+impl Vbat {
+    pub const DIVIDER: u32 = 4;
+}
 impl<T: Instance> InternalChannel<T> for Vbat {}
-impl<T: Instance> sealed::InternalChannel<T> for Vbat {
+impl<T: Instance> super::sealed::InternalChannel<T> for Vbat {
     fn channel(&self) -> u8 {
         // TODO this should be 14 for H7a/b/35
         17
@@ -437,13 +433,16 @@ impl<'d, T: Instance + crate::rcc::RccPeripheral> Adc<'d, T> {
         Vref {}
     }
 
-    pub fn enable_temperature(&self) -> Temperature {
+    pub fn enable_temperature(&self, delay: &mut impl DelayUs<u16>) -> Temperature {
         unsafe {
             T::common_regs().ccr().modify(|reg| {
                 reg.set_vsenseen(true);
             });
         }
 
+        // Wait for the sensor to start up ("t_start_run" in the datasheet).
+        delay.delay_us(40);
+
         Temperature {}
     }
 
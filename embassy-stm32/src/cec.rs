@@ -0,0 +1,372 @@
+#![macro_use]
+
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy::interrupt::{Interrupt, InterruptExt};
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::drop::OnDrop;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::gpio::sealed::{AFType, Pin as _};
+use crate::gpio::{AnyPin, Pin};
+use crate::pac::cec::regs;
+use crate::peripherals;
+
+/// Maximum length of a frame, header block included.
+pub const MAX_FRAME_LEN: usize = 16;
+
+/// Logical address 15. As a destination it broadcasts the message to all devices, as an
+/// initiator it's used by devices that don't have a logical address allocated yet.
+pub const BROADCAST: u8 = 15;
+
+// ISR bits
+const RXBR: u32 = 1 << 0;
+const RXEND: u32 = 1 << 1;
+const RXOVR: u32 = 1 << 2;
+const BRE: u32 = 1 << 3;
+const SBPE: u32 = 1 << 4;
+const LBPE: u32 = 1 << 5;
+const RXACKE: u32 = 1 << 6;
+const ARBLST: u32 = 1 << 7;
+const TXBR: u32 = 1 << 8;
+const TXEND: u32 = 1 << 9;
+const TXUDR: u32 = 1 << 10;
+const TXERR: u32 = 1 << 11;
+const TXACKE: u32 = 1 << 12;
+
+const RX_FLAGS: u32 = RXBR | RXEND | RXOVR | BRE | SBPE | LBPE | RXACKE;
+const TX_FLAGS: u32 = ARBLST | TXBR | TXEND | TXUDR | TXERR | TXACKE;
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// Another initiator won arbitration. The frame wasn't sent, try again later.
+    ArbitrationLost,
+    /// A block wasn't acknowledged: the destination isn't there, or for broadcast
+    /// messages, a follower rejected it.
+    Nack,
+    /// The line was pulled low by someone else while we were transmitting.
+    Collision,
+    /// The next byte wasn't written in time, the frame was cut short.
+    Underrun,
+    /// A byte wasn't read in time and was lost.
+    Overrun,
+    /// A bit rising edge came outside its allowed window.
+    BitRising,
+    /// A bit period was shorter than allowed.
+    ShortBitPeriod,
+    /// A bit period was longer than allowed.
+    LongBitPeriod,
+}
+
+#[non_exhaustive]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Config {
+    /// Receive all frames on the bus, not only the ones addressed to us or broadcast.
+    /// Frames for other devices aren't acknowledged.
+    pub listen: bool,
+    /// Use the extended tolerance of the bit timings (±300µs / ±500µs for rising
+    /// edges) instead of the standard one (±200µs / ±350µs).
+    pub extended_tolerance: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            listen: false,
+            extended_tolerance: false,
+        }
+    }
+}
+
+/// HDMI-CEC driver.
+///
+/// The kernel clock has to be around 32kHz: LSE, or HSI divided down, depending on the
+/// chip. The driver doesn't select it, the reset default is used.
+pub struct Cec<'d, T: Instance> {
+    phantom: PhantomData<&'d mut T>,
+    pin: AnyPin,
+    address: u8,
+}
+
+impl<'d, T: Instance> Cec<'d, T> {
+    pub fn new(
+        _peri: impl Unborrow<Target = T> + 'd,
+        pin: impl Unborrow<Target = impl CecPin<T>> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(pin, irq);
+
+        T::enable();
+        T::reset();
+
+        unsafe {
+            pin.set_as_af(pin.af_num(), AFType::OutputOpenDrain);
+
+            T::regs().cfgr().write(|w| {
+                w.set_lstn(config.listen);
+                w.set_rxtol(config.extended_tolerance);
+            });
+            T::regs().cr().write(|w| w.set_cecen(true));
+        }
+
+        irq.set_handler(Self::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        Self {
+            phantom: PhantomData,
+            pin: pin.degrade(),
+            address: BROADCAST,
+        }
+    }
+
+    /// Set our logical address. Frames sent to it are received and acknowledged, and
+    /// it's used as the initiator of the frames we send.
+    ///
+    /// Pass [`BROADCAST`] to stop responding to any address, for example while
+    /// allocating one by polling the candidates.
+    pub fn set_logical_address(&mut self, address: u8) {
+        assert!(address <= BROADCAST);
+        self.address = address;
+
+        let oar = if address == BROADCAST {
+            0
+        } else {
+            1 << address
+        };
+        // CFGR can only be written while the peripheral is disabled.
+        unsafe {
+            T::regs().cr().write(|w| w.set_cecen(false));
+            T::regs().cfgr().modify(|w| w.set_oar(oar));
+            T::regs().cr().write(|w| w.set_cecen(true));
+        }
+    }
+
+    /// Get our logical address.
+    pub fn logical_address(&self) -> u8 {
+        self.address
+    }
+
+    /// Send a frame to `destination`, with `data` as the opcode and operands.
+    ///
+    /// An empty `data` sends a polling message, which checks if `destination` is present:
+    /// it fails with [`Error::Nack`] if it isn't.
+    pub async fn write(&mut self, destination: u8, data: &[u8]) -> Result<(), Error> {
+        assert!(destination <= BROADCAST);
+        assert!(data.len() < MAX_FRAME_LEN);
+
+        let r = T::regs();
+        let header = (self.address << 4) | destination;
+
+        // If we're cancelled, abort the transmission. Disabling the peripheral is the only
+        // way to do it.
+        let on_drop = OnDrop::new(|| unsafe {
+            r.ier().write(|_| {});
+            r.cr().write(|w| w.set_cecen(false));
+            r.cr().write(|w| w.set_cecen(true));
+        });
+
+        unsafe {
+            r.isr().write_value(regs::Isr(TX_FLAGS));
+            r.txdr().write(|w| w.set_txd(header));
+            r.cr().modify(|w| {
+                w.set_txeom(data.is_empty());
+                w.set_txsom(true);
+            });
+        }
+
+        let mut sent = 0;
+        let res = poll_fn(|cx| {
+            T::state().waker.register(cx.waker());
+
+            let isr = unsafe { r.isr().read() };
+            let res = if isr.arblst() {
+                Some(Err(Error::ArbitrationLost))
+            } else if isr.txacke() {
+                Some(Err(Error::Nack))
+            } else if isr.txerr() {
+                Some(Err(Error::Collision))
+            } else if isr.txudr() {
+                Some(Err(Error::Underrun))
+            } else if isr.txend() {
+                Some(Ok(()))
+            } else {
+                if isr.txbr() && sent < data.len() {
+                    unsafe {
+                        r.isr().write_value(regs::Isr(TXBR));
+                        if sent == data.len() - 1 {
+                            r.cr().modify(|w| w.set_txeom(true));
+                        }
+                        r.txdr().write(|w| w.set_txd(data[sent]));
+                    }
+                    sent += 1;
+                }
+                None
+            };
+
+            match res {
+                Some(res) => {
+                    unsafe { r.isr().write_value(regs::Isr(TX_FLAGS)) };
+                    Poll::Ready(res)
+                }
+                None => {
+                    unsafe {
+                        r.ier().write(|w| {
+                            w.set_arblstie(true);
+                            w.set_txbrie(true);
+                            w.set_txendie(true);
+                            w.set_txudrie(true);
+                            w.set_txerrie(true);
+                            w.set_txackeie(true);
+                        })
+                    };
+                    Poll::Pending
+                }
+            }
+        })
+        .await;
+
+        on_drop.defuse();
+        res
+    }
+
+    /// Receive a frame into `buf`, returning its length, header block included.
+    ///
+    /// The initiator is in the high nibble of `buf[0]` and the destination in the low
+    /// one. Frames are only received while this is being awaited; bytes of a frame that
+    /// was already underway when it's called are returned as a frame of their own.
+    pub async fn read(&mut self, buf: &mut [u8; MAX_FRAME_LEN]) -> Result<usize, Error> {
+        let r = T::regs();
+
+        let on_drop = OnDrop::new(|| unsafe { r.ier().write(|_| {}) });
+
+        unsafe { r.isr().write_value(regs::Isr(RX_FLAGS)) };
+
+        let mut len = 0;
+        let res = poll_fn(|cx| {
+            T::state().waker.register(cx.waker());
+
+            let isr = unsafe { r.isr().read() };
+
+            // The last byte sets both RXBR and RXEND, so take it before checking for the end.
+            if isr.rxbr() {
+                let byte = unsafe { r.rxdr().read().rxd() };
+                unsafe { r.isr().write_value(regs::Isr(RXBR)) };
+                if len < MAX_FRAME_LEN {
+                    buf[len] = byte;
+                    len += 1;
+                }
+            }
+
+            let res = if isr.rxovr() {
+                Some(Err(Error::Overrun))
+            } else if isr.bre() {
+                Some(Err(Error::BitRising))
+            } else if isr.sbpe() {
+                Some(Err(Error::ShortBitPeriod))
+            } else if isr.lbpe() {
+                Some(Err(Error::LongBitPeriod))
+            } else if isr.rxacke() {
+                Some(Err(Error::Nack))
+            } else if isr.rxend() {
+                Some(Ok(len))
+            } else {
+                None
+            };
+
+            match res {
+                Some(res) => {
+                    unsafe { r.isr().write_value(regs::Isr(RX_FLAGS)) };
+                    Poll::Ready(res)
+                }
+                None => {
+                    unsafe {
+                        r.ier().write(|w| {
+                            w.set_rxbrie(true);
+                            w.set_rxendie(true);
+                            w.set_rxovrie(true);
+                            w.set_breie(true);
+                            w.set_sbpeie(true);
+                            w.set_lbpeie(true);
+                            w.set_rxackie(true);
+                        })
+                    };
+                    Poll::Pending
+                }
+            }
+        })
+        .await;
+
+        on_drop.defuse();
+        unsafe { r.ier().write(|_| {}) };
+        res
+    }
+
+    unsafe fn on_interrupt(_: *mut ()) {
+        // Flags are cleared by the task, mask them until it gets to run.
+        T::regs().ier().write(|_| {});
+        T::state().waker.wake();
+    }
+}
+
+impl<'d, T: Instance> Drop for Cec<'d, T> {
+    fn drop(&mut self) {
+        unsafe {
+            T::regs().cr().write(|w| w.set_cecen(false));
+            self.pin.set_as_disconnected();
+        }
+        T::disable();
+    }
+}
+
+pub(crate) mod sealed {
+    use super::*;
+
+    pub struct State {
+        pub waker: AtomicWaker,
+    }
+
+    impl State {
+        pub const fn new() -> Self {
+            Self {
+                waker: AtomicWaker::new(),
+            }
+        }
+    }
+
+    pub trait Instance {
+        fn regs() -> crate::pac::cec::Cec;
+        fn state() -> &'static State;
+    }
+}
+
+pub trait Instance: sealed::Instance + crate::rcc::RccPeripheral + 'static {
+    type Interrupt: Interrupt;
+}
+
+pin_trait!(CecPin, Instance);
+
+foreach_interrupt!(
+    ($inst:ident, cec, $block:ident, $signal_name:ident, $irq:ident) => {
+        impl sealed::Instance for peripherals::$inst {
+            fn regs() -> crate::pac::cec::Cec {
+                crate::pac::$inst
+            }
+
+            fn state() -> &'static sealed::State {
+                static STATE: sealed::State = sealed::State::new();
+                &STATE
+            }
+        }
+
+        impl Instance for peripherals::$inst {
+            type Interrupt = crate::interrupt::$irq;
+        }
+    };
+);
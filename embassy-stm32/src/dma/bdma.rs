@@ -67,6 +67,7 @@ foreach_dma_channel! {
                     ptr as *mut u32,
                     len,
                     true,
+                    false,
                     vals::Size::from(W::bits()),
                     options,
                     #[cfg(dmamux)]
@@ -89,6 +90,7 @@ foreach_dma_channel! {
                     buf.as_ptr() as *mut u32,
                     count,
                     false,
+                    false,
                     vals::Size::from(W::bits()),
                     options,
                     #[cfg(dmamux)]
@@ -110,6 +112,31 @@ foreach_dma_channel! {
                     ptr as *mut u32,
                     len,
                     true,
+                    false,
+                    vals::Size::from(W::bits()),
+                    options,
+                    #[cfg(dmamux)]
+                    <Self as super::dmamux::sealed::MuxChannel>::DMAMUX_REGS,
+                    #[cfg(dmamux)]
+                    <Self as super::dmamux::sealed::MuxChannel>::DMAMUX_CH_NUM,
+                );
+            }
+
+            unsafe fn start_copy<W: Word>(&mut self, src: *const [W], dst: *mut [W], options: TransferOptions) {
+                let (src_ptr, _) = super::slice_ptr_parts(src);
+                let (dst_ptr, len) = super::slice_ptr_parts_mut(dst);
+                // In memory to memory mode, the channel reads from the peripheral address.
+                low_level_api::start_transfer(
+                    pac::$dma_peri,
+                    $channel_num,
+                    #[cfg(any(bdma_v2, dmamux))]
+                    Request::default(),
+                    vals::Dir::FROMPERIPHERAL,
+                    src_ptr as *const u32,
+                    dst_ptr as *mut u32,
+                    len,
+                    true,
+                    true,
                     vals::Size::from(W::bits()),
                     options,
                     #[cfg(dmamux)]
@@ -157,6 +184,7 @@ mod low_level_api {
         mem_addr: *mut u32,
         mem_len: usize,
         incr_mem: bool,
+        mem2mem: bool,
         data_size: vals::Size,
         options: TransferOptions,
         #[cfg(dmamux)] dmamux_regs: pac::dmamux::Dmamux,
@@ -174,13 +202,15 @@ mod low_level_api {
             options.flow_ctrl == crate::dma::FlowControl::Dma,
             "Peripheral flow control not supported"
         );
+        #[cfg(dma)]
+        assert!(options.fifo_threshold.is_none(), "FIFO not supported");
 
         let ch = dma.ch(channel_number as _);
 
         reset_status(dma, channel_number);
 
         #[cfg(dmamux)]
-        super::super::dmamux::configure_dmamux(dmamux_regs, dmamux_ch_num, request);
+        super::super::dmamux::configure_dmamux(dmamux_regs, dmamux_ch_num, request, options.sync);
 
         #[cfg(bdma_v2)]
         critical_section::with(|_| {
@@ -202,6 +232,12 @@ mod low_level_api {
             } else {
                 w.set_minc(vals::Inc::DISABLED);
             }
+            w.set_pinc(if mem2mem {
+                vals::Inc::ENABLED
+            } else {
+                vals::Inc::DISABLED
+            });
+            w.set_mem2mem(mem2mem);
             w.set_dir(dir);
             w.set_teie(true);
             w.set_tcie(true);
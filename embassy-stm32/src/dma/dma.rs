@@ -9,7 +9,7 @@ use crate::interrupt;
 use crate::pac;
 use crate::pac::dma::{regs, vals};
 
-use super::{Burst, FlowControl, Request, TransferOptions, Word, WordSize};
+use super::{Burst, FifoThreshold, FlowControl, Request, TransferOptions, Word, WordSize};
 
 impl From<WordSize> for vals::Size {
     fn from(raw: WordSize) -> Self {
@@ -41,6 +41,17 @@ impl From<FlowControl> for vals::Pfctrl {
     }
 }
 
+impl From<FifoThreshold> for vals::Fth {
+    fn from(value: FifoThreshold) -> Self {
+        match value {
+            FifoThreshold::Quarter => vals::Fth::QUARTER,
+            FifoThreshold::Half => vals::Fth::HALF,
+            FifoThreshold::ThreeQuarters => vals::Fth::THREEQUARTERS,
+            FifoThreshold::Full => vals::Fth::FULL,
+        }
+    }
+}
+
 struct State {
     ch_wakers: [AtomicWaker; DMA_CHANNEL_COUNT],
 }
@@ -129,6 +140,28 @@ foreach_dma_channel! {
                 );
             }
 
+            unsafe fn start_copy<W: Word>(&mut self, src: *const [W], dst: *mut [W], options: TransferOptions) {
+                let (src_ptr, _) = super::slice_ptr_parts(src);
+                let (dst_ptr, len) = super::slice_ptr_parts_mut(dst);
+                // In memory to memory mode, the peripheral port reads the source.
+                low_level_api::start_transfer(
+                    pac::$dma_peri,
+                    $channel_num,
+                    Request::default(),
+                    vals::Dir::MEMORYTOMEMORY,
+                    src_ptr as *const u32,
+                    dst_ptr as *mut u32,
+                    len,
+                    true,
+                    vals::Size::from(W::bits()),
+                    options,
+                    #[cfg(dmamux)]
+                    <Self as super::dmamux::sealed::MuxChannel>::DMAMUX_REGS,
+                    #[cfg(dmamux)]
+                    <Self as super::dmamux::sealed::MuxChannel>::DMAMUX_CH_NUM,
+                );
+            }
+
             fn request_stop(&mut self) {
                 unsafe {low_level_api::request_stop(pac::$dma_peri, $channel_num);}
             }
@@ -174,7 +207,7 @@ mod low_level_api {
         #[cfg(dmamux)] dmamux_ch_num: u8,
     ) {
         #[cfg(dmamux)]
-        super::super::dmamux::configure_dmamux(dmamux_regs, dmamux_ch_num, request);
+        super::super::dmamux::configure_dmamux(dmamux_regs, dmamux_ch_num, request, options.sync);
 
         // "Preceding reads and writes cannot be moved past subsequent writes."
         fence(Ordering::SeqCst);
@@ -185,6 +218,21 @@ mod low_level_api {
         ch.par().write_value(peri_addr as u32);
         ch.m0ar().write_value(mem_addr as u32);
         ch.ndtr().write_value(regs::Ndtr(mem_len as _));
+
+        // Direct mode can't be used for memory to memory copies.
+        let fifo_threshold = match options.fifo_threshold {
+            None if dir == vals::Dir::MEMORYTOMEMORY => Some(FifoThreshold::Full),
+            fifo_threshold => fifo_threshold,
+        };
+        ch.fcr().write(|w| {
+            if let Some(fth) = fifo_threshold {
+                w.set_dmdis(vals::Dmdis::DISABLED);
+                w.set_fth(fth.into());
+            } else {
+                w.set_dmdis(vals::Dmdis::ENABLED);
+            }
+        });
+
         ch.cr().write(|w| {
             w.set_dir(dir);
             w.set_msize(data_size);
@@ -195,7 +243,11 @@ mod low_level_api {
             } else {
                 w.set_minc(vals::Inc::FIXED);
             }
-            w.set_pinc(vals::Inc::FIXED);
+            if dir == vals::Dir::MEMORYTOMEMORY {
+                w.set_pinc(vals::Inc::INCREMENTED);
+            } else {
+                w.set_pinc(vals::Inc::FIXED);
+            }
             w.set_teie(true);
             w.set_tcie(true);
             #[cfg(dma_v1)]
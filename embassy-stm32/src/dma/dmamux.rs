@@ -1,12 +1,67 @@
 #![macro_use]
 
+use core::marker::PhantomData;
+
+use embassy::util::Unborrow;
+use embassy_hal_common::unborrow;
+
 use crate::pac;
+use crate::pac::dmamux::vals;
 use crate::peripherals;
 
+use super::{MuxChannel, Request, Transfer, TransferOptions, Word};
+
+/// Edge of a signal that synchronizes a channel, or triggers a request generator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edge {
+    Rising,
+    Falling,
+    Both,
+}
+
+impl From<Edge> for vals::Pol {
+    fn from(edge: Edge) -> Self {
+        match edge {
+            Edge::Rising => vals::Pol::RISINGEDGE,
+            Edge::Falling => vals::Pol::FALLINGEDGE,
+            Edge::Both => vals::Pol::BOTHEDGES,
+        }
+    }
+}
+
+/// Synchronization of a DMAMUX channel.
+///
+/// The requests of the peripheral are held until an edge on the synchronization input, then
+/// `requests` of them are forwarded to the DMA.
+#[derive(Debug, Clone, Copy)]
+pub struct SyncConfig {
+    /// Synchronization input. The inputs (EXTI lines, timer and LPTIM outputs, event outputs
+    /// of the other channels) depend on the chip, see the DMAMUX chapter of its reference manual.
+    pub input: u8,
+    /// Edge of the synchronization input to wait for
+    pub edge: Edge,
+    /// Number of requests forwarded after each synchronization event, from 1 to 32
+    pub requests: u8,
+    /// Generate an event on the channel's event output once `requests` requests are forwarded
+    pub event: bool,
+}
+
+/// Configuration of a request generator.
+#[derive(Debug, Clone, Copy)]
+pub struct GeneratorConfig {
+    /// Trigger input. The inputs depend on the chip, like the synchronization inputs.
+    pub input: u8,
+    /// Edge of the trigger input that starts the requests
+    pub edge: Edge,
+    /// Number of requests generated after each trigger event, from 1 to 32
+    pub requests: u8,
+}
+
 pub(crate) unsafe fn configure_dmamux(
     dmamux_regs: pac::dmamux::Dmamux,
     dmamux_ch_num: u8,
     request: u8,
+    sync: Option<SyncConfig>,
 ) {
     let ch_mux_regs = dmamux_regs.ccr(dmamux_ch_num as _);
     ch_mux_regs.write(|reg| {
@@ -14,9 +69,23 @@ pub(crate) unsafe fn configure_dmamux(
         reg.set_dmareq_id(request);
     });
 
-    ch_mux_regs.modify(|reg| {
-        reg.set_ege(true);
-    });
+    match sync {
+        Some(sync) => {
+            assert!(sync.requests >= 1 && sync.requests <= 32);
+            ch_mux_regs.modify(|reg| {
+                reg.set_nbreq(sync.requests - 1);
+                reg.set_sync_id(sync.input);
+                reg.set_spol(sync.edge.into());
+                reg.set_ege(sync.event);
+                reg.set_se(true);
+            });
+        }
+        None => {
+            ch_mux_regs.modify(|reg| {
+                reg.set_ege(true);
+            });
+        }
+    }
 }
 
 pub(crate) mod sealed {
@@ -25,6 +94,11 @@ pub(crate) mod sealed {
         const DMAMUX_CH_NUM: u8;
         const DMAMUX_REGS: pac::dmamux::Dmamux;
     }
+
+    pub trait Generator {
+        const GEN_NUM: u8;
+        const DMAMUX_REGS: pac::dmamux::Dmamux;
+    }
 }
 
 pub struct DMAMUX1;
@@ -35,6 +109,11 @@ pub trait MuxChannel: sealed::MuxChannel + super::Channel {
     type Mux;
 }
 
+/// Request generator of a DMAMUX, such as `DMAMUX1_RG0`.
+pub trait Generator: sealed::Generator + Unborrow<Target = Self> + 'static {
+    type Mux;
+}
+
 foreach_dma_channel! {
     ($channel_peri:ident, $dma_peri:ident, $version:ident, $channel_num:expr, $index:expr, {dmamux: $dmamux:ident, dmamux_channel: $dmamux_channel:expr}) => {
         impl sealed::MuxChannel for peripherals::$channel_peri {
@@ -47,6 +126,101 @@ foreach_dma_channel! {
     };
 }
 
+/// DMA requests generated from a trigger input, without any peripheral.
+///
+/// This paces DMA transfers by external events, like moving a buffer to GPIOs on each edge
+/// of an EXTI line. The transfers use channels of the same DMAMUX as the generator.
+pub struct RequestGenerator<'d, G: Generator> {
+    _gen: G,
+    phantom: PhantomData<&'d mut G>,
+}
+
+impl<'d, G: Generator> RequestGenerator<'d, G> {
+    pub fn new(gen: impl Unborrow<Target = G> + 'd, config: GeneratorConfig) -> Self {
+        unborrow!(gen);
+        assert!(config.requests >= 1 && config.requests <= 32);
+
+        let regs = G::DMAMUX_REGS;
+        unsafe {
+            regs.rgcfr().write(|w| w.set_cof(G::GEN_NUM as _, true));
+            regs.rgcr(G::GEN_NUM as _).write(|w| {
+                w.set_sig_id(config.input);
+                w.set_gpol(config.edge.into());
+                w.set_gnbreq(config.requests - 1);
+                w.set_ge(true);
+            });
+        }
+
+        Self {
+            _gen: gen,
+            phantom: PhantomData,
+        }
+    }
+
+    /// DMA request of this generator.
+    pub fn request(&self) -> Request {
+        // Request 0 is "no request", the generators come right after it.
+        G::GEN_NUM + 1
+    }
+
+    /// Returns whether a trigger event came while the previous requests weren't all served,
+    /// and clears the flag.
+    pub fn check_overrun(&mut self) -> bool {
+        let regs = G::DMAMUX_REGS;
+        unsafe {
+            let overrun = regs.rgsr().read().of(G::GEN_NUM as _);
+            if overrun {
+                regs.rgcfr().write(|w| w.set_cof(G::GEN_NUM as _, true));
+            }
+            overrun
+        }
+    }
+
+    /// Write `buf` to `reg_addr`, one word per generated request.
+    ///
+    /// Safety: `reg_addr` must be a valid peripheral register address to write to.
+    pub unsafe fn write<'a, C, W>(
+        &'a mut self,
+        channel: impl Unborrow<Target = C> + 'a,
+        buf: &'a [W],
+        reg_addr: *mut W,
+        options: TransferOptions,
+    ) -> Transfer<'a, C>
+    where
+        C: MuxChannel<Mux = G::Mux>,
+        W: Word,
+    {
+        super::write_with_options(channel, self.request(), buf, reg_addr, options)
+    }
+
+    /// Read `buf` from `reg_addr`, one word per generated request.
+    ///
+    /// Safety: `reg_addr` must be a valid peripheral register address to read from.
+    pub unsafe fn read<'a, C, W>(
+        &'a mut self,
+        channel: impl Unborrow<Target = C> + 'a,
+        reg_addr: *mut W,
+        buf: &'a mut [W],
+        options: TransferOptions,
+    ) -> Transfer<'a, C>
+    where
+        C: MuxChannel<Mux = G::Mux>,
+        W: Word,
+    {
+        super::read_with_options(channel, self.request(), reg_addr, buf, options)
+    }
+}
+
+impl<'d, G: Generator> Drop for RequestGenerator<'d, G> {
+    fn drop(&mut self) {
+        unsafe {
+            G::DMAMUX_REGS
+                .rgcr(G::GEN_NUM as _)
+                .write(|w| w.set_ge(false));
+        }
+    }
+}
+
 /// safety: must be called only once
 pub(crate) unsafe fn init() {
     crate::_generated::init_dmamux();
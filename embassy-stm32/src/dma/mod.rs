@@ -3,7 +3,7 @@ pub(crate) mod bdma;
 #[cfg(dma)]
 pub(crate) mod dma;
 #[cfg(dmamux)]
-mod dmamux;
+pub(crate) mod dmamux;
 
 #[cfg(dmamux)]
 pub use dmamux::*;
@@ -12,6 +12,7 @@ use core::future::Future;
 use core::marker::PhantomData;
 use core::mem;
 use core::pin::Pin;
+use core::ptr;
 use core::task::Waker;
 use core::task::{Context, Poll};
 use embassy::util::Unborrow;
@@ -23,6 +24,7 @@ pub mod low_level {
 }
 
 pub(crate) use transfers::*;
+pub use transfers::{copy, copy_with_options, Transfer};
 
 #[cfg(any(bdma_v2, dma_v2, dmamux))]
 pub type Request = u8;
@@ -76,6 +78,19 @@ pub(crate) mod sealed {
             options: TransferOptions,
         );
 
+        /// Starts this channel for copying a buffer to another, in memory.
+        ///
+        /// Safety:
+        /// - `src` must point to a valid buffer for DMA reading.
+        /// - `dst` must point to a valid buffer for DMA writing, of the same length as `src`.
+        /// - Both buffers must be alive for the entire duration of the DMA transfer.
+        unsafe fn start_copy<W: super::Word>(
+            &mut self,
+            src: *const [W],
+            dst: *mut [W],
+            options: TransferOptions,
+        );
+
         /// Requests the channel to stop.
         /// NOTE: The channel does not immediately stop, you have to wait
         /// for `is_running() = false`.
@@ -129,7 +144,7 @@ impl Word for u32 {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Burst {
     /// Single transfer
     Single,
@@ -141,7 +156,7 @@ pub enum Burst {
     Incr16,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FlowControl {
     /// Flow control by DMA
     Dma,
@@ -149,6 +164,20 @@ pub enum FlowControl {
     Peripheral,
 }
 
+#[cfg(dma)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FifoThreshold {
+    /// 1/4 full FIFO
+    Quarter,
+    /// 1/2 full FIFO
+    Half,
+    /// 3/4 full FIFO
+    ThreeQuarters,
+    /// Full FIFO
+    Full,
+}
+
+#[derive(Clone, Copy)]
 pub struct TransferOptions {
     /// Peripheral burst transfer configuration
     pub pburst: Burst,
@@ -156,6 +185,14 @@ pub struct TransferOptions {
     pub mburst: Burst,
     /// Flow control configuration
     pub flow_ctrl: FlowControl,
+    /// Synchronization of the requests forwarded by the DMAMUX
+    #[cfg(dmamux)]
+    pub sync: Option<SyncConfig>,
+    /// FIFO threshold for DMA streams. `None` keeps the stream in direct mode, where bursts
+    /// aren't possible. Memory to memory copies always go through the FIFO, and use `Full`
+    /// when this is `None`.
+    #[cfg(dma)]
+    pub fifo_threshold: Option<FifoThreshold>,
 }
 
 impl Default for TransferOptions {
@@ -164,68 +201,221 @@ impl Default for TransferOptions {
             pburst: Burst::Single,
             mburst: Burst::Single,
             flow_ctrl: FlowControl::Dma,
+            #[cfg(dmamux)]
+            sync: None,
+            #[cfg(dma)]
+            fifo_threshold: None,
         }
     }
 }
 
+/// Maximum number of words a channel moves in one go. [`Transfer`]s of more words are split,
+/// and the channel is restarted for each part.
+pub(crate) const MAX_TRANSFER: usize = 0xFFFF;
+
 mod transfers {
     use super::*;
 
     #[allow(unused)]
-    pub fn read<'a, W: Word>(
-        channel: impl Unborrow<Target = impl Channel> + 'a,
+    pub fn read<'a, C: Channel, W: Word>(
+        channel: impl Unborrow<Target = C> + 'a,
         request: Request,
         reg_addr: *mut W,
         buf: &'a mut [W],
-    ) -> impl Future<Output = ()> + 'a {
-        assert!(buf.len() > 0 && buf.len() <= 0xFFFF);
-        unborrow!(channel);
-
-        unsafe { channel.start_read::<W>(request, reg_addr, buf, Default::default()) };
+    ) -> Transfer<'a, C> {
+        read_with_options(channel, request, reg_addr, buf, Default::default())
+    }
 
-        Transfer::new(channel)
+    #[allow(unused)]
+    pub fn read_with_options<'a, C: Channel, W: Word>(
+        channel: impl Unborrow<Target = C> + 'a,
+        request: Request,
+        reg_addr: *mut W,
+        buf: &'a mut [W],
+        options: TransferOptions,
+    ) -> Transfer<'a, C> {
+        assert!(buf.len() > 0);
+
+        Transfer::start(
+            channel,
+            Continuation {
+                start: continue_read::<C, W>,
+                request,
+                options,
+                src: reg_addr as usize,
+                dst: buf.as_mut_ptr() as usize,
+                remaining: buf.len(),
+                repeated: [0; 4],
+            },
+        )
     }
 
     #[allow(unused)]
-    pub fn write<'a, W: Word>(
-        channel: impl Unborrow<Target = impl Channel> + 'a,
+    pub fn write<'a, C: Channel, W: Word>(
+        channel: impl Unborrow<Target = C> + 'a,
         request: Request,
         buf: &'a [W],
         reg_addr: *mut W,
-    ) -> impl Future<Output = ()> + 'a {
-        assert!(buf.len() > 0 && buf.len() <= 0xFFFF);
-        unborrow!(channel);
-
-        unsafe { channel.start_write::<W>(request, buf, reg_addr, Default::default()) };
+    ) -> Transfer<'a, C> {
+        write_with_options(channel, request, buf, reg_addr, Default::default())
+    }
 
-        Transfer::new(channel)
+    #[allow(unused)]
+    pub fn write_with_options<'a, C: Channel, W: Word>(
+        channel: impl Unborrow<Target = C> + 'a,
+        request: Request,
+        buf: &'a [W],
+        reg_addr: *mut W,
+        options: TransferOptions,
+    ) -> Transfer<'a, C> {
+        assert!(buf.len() > 0);
+
+        Transfer::start(
+            channel,
+            Continuation {
+                start: continue_write::<C, W>,
+                request,
+                options,
+                src: buf.as_ptr() as usize,
+                dst: reg_addr as usize,
+                remaining: buf.len(),
+                repeated: [0; 4],
+            },
+        )
     }
 
     #[allow(unused)]
-    pub fn write_repeated<'a, W: Word>(
-        channel: impl Unborrow<Target = impl Channel> + 'a,
+    pub fn write_repeated<'a, C: Channel, W: Word>(
+        channel: impl Unborrow<Target = C> + 'a,
         request: Request,
         repeated: W,
         count: usize,
         reg_addr: *mut W,
-    ) -> impl Future<Output = ()> + 'a {
-        unborrow!(channel);
+    ) -> Transfer<'a, C> {
+        assert!(count > 0);
 
+        let mut bytes = [0; 4];
         unsafe {
-            channel.start_write_repeated::<W>(
-                request,
-                repeated,
-                count,
-                reg_addr,
-                Default::default(),
+            ptr::copy_nonoverlapping(
+                &repeated as *const W as *const u8,
+                bytes.as_mut_ptr(),
+                mem::size_of::<W>(),
             )
         };
 
-        Transfer::new(channel)
+        Transfer::start(
+            channel,
+            Continuation {
+                start: continue_write_repeated::<C, W>,
+                request,
+                options: Default::default(),
+                src: 0,
+                dst: reg_addr as usize,
+                remaining: count,
+                repeated: bytes,
+            },
+        )
     }
 
-    pub(crate) struct Transfer<'a, C: Channel> {
+    /// Copy `src` to `dst` in the background. The returned [`Transfer`] completes when the
+    /// copy is done.
+    ///
+    /// On chips with DMA streams (F2, F4, F7, H7), only DMA2 can do memory to memory copies.
+    pub fn copy<'a, C: Channel, W: Word>(
+        channel: impl Unborrow<Target = C> + 'a,
+        src: &'a [W],
+        dst: &'a mut [W],
+    ) -> Transfer<'a, C> {
+        copy_with_options(channel, src, dst, Default::default())
+    }
+
+    /// Like [`copy`], with the bursts and FIFO threshold of `options`.
+    pub fn copy_with_options<'a, C: Channel, W: Word>(
+        channel: impl Unborrow<Target = C> + 'a,
+        src: &'a [W],
+        dst: &'a mut [W],
+        options: TransferOptions,
+    ) -> Transfer<'a, C> {
+        assert!(src.len() == dst.len());
+        assert!(src.len() > 0);
+
+        Transfer::start(
+            channel,
+            Continuation {
+                start: continue_copy::<C, W>,
+                request: Default::default(),
+                options,
+                src: src.as_ptr() as usize,
+                dst: dst.as_mut_ptr() as usize,
+                remaining: src.len(),
+                repeated: [0; 4],
+            },
+        )
+    }
+
+    /// What's left of a transfer longer than [`MAX_TRANSFER`] words.
+    struct Continuation<C> {
+        /// Starts the channel for the next part, and moves past it.
+        start: unsafe fn(&mut C, &mut Continuation<C>),
+        request: Request,
+        options: TransferOptions,
+        /// Address of the next source word, or of the register to read from.
+        src: usize,
+        /// Address of the next destination word, or of the register to write to.
+        dst: usize,
+        /// Words not started yet.
+        remaining: usize,
+        /// Bytes of the word of `write_repeated`.
+        repeated: [u8; 4],
+    }
+
+    fn next_part<C>(c: &mut Continuation<C>) -> usize {
+        let n = c.remaining.min(MAX_TRANSFER);
+        c.remaining -= n;
+        n
+    }
+
+    unsafe fn continue_read<C: Channel, W: Word>(channel: &mut C, c: &mut Continuation<C>) {
+        let n = next_part(c);
+        let buf = ptr::slice_from_raw_parts_mut(c.dst as *mut W, n);
+        channel.start_read::<W>(c.request, c.src as *const W, buf, c.options);
+        c.dst += n * mem::size_of::<W>();
+    }
+
+    unsafe fn continue_write<C: Channel, W: Word>(channel: &mut C, c: &mut Continuation<C>) {
+        let n = next_part(c);
+        let buf = ptr::slice_from_raw_parts(c.src as *const W, n);
+        channel.start_write::<W>(c.request, buf, c.dst as *mut W, c.options);
+        c.src += n * mem::size_of::<W>();
+    }
+
+    unsafe fn continue_write_repeated<C: Channel, W: Word>(
+        channel: &mut C,
+        c: &mut Continuation<C>,
+    ) {
+        let n = next_part(c);
+        let repeated = ptr::read_unaligned(c.repeated.as_ptr() as *const W);
+        channel.start_write_repeated::<W>(c.request, repeated, n, c.dst as *mut W, c.options);
+    }
+
+    unsafe fn continue_copy<C: Channel, W: Word>(channel: &mut C, c: &mut Continuation<C>) {
+        let n = next_part(c);
+        let src = ptr::slice_from_raw_parts(c.src as *const W, n);
+        let dst = ptr::slice_from_raw_parts_mut(c.dst as *mut W, n);
+        channel.start_copy::<W>(src, dst, c.options);
+        c.src += n * mem::size_of::<W>();
+        c.dst += n * mem::size_of::<W>();
+    }
+
+    /// A DMA transfer in progress. It completes when awaited, and is stopped when dropped.
+    ///
+    /// Transfers longer than the 65535 words a channel can move at once are split. The next
+    /// part is started when the transfer is polled after the end of the previous one, so the
+    /// peripheral briefly isn't served between parts.
+    #[must_use = "futures do nothing unless you `.await` or poll them"]
+    pub struct Transfer<'a, C: Channel> {
         channel: C,
+        rest: Option<Continuation<C>>,
         _phantom: PhantomData<&'a mut C>,
     }
 
@@ -234,9 +424,39 @@ mod transfers {
             unborrow!(channel);
             Self {
                 channel,
+                rest: None,
+                _phantom: PhantomData,
+            }
+        }
+
+        fn start(channel: impl Unborrow<Target = C> + 'a, mut rest: Continuation<C>) -> Self {
+            unborrow!(channel);
+            unsafe { (rest.start)(&mut channel, &mut rest) };
+            Self {
+                channel,
+                rest: (rest.remaining > 0).then(|| rest),
                 _phantom: PhantomData,
             }
         }
+
+        /// Returns whether the transfer is still running.
+        pub fn is_running(&self) -> bool {
+            self.channel.is_running() || self.rest.is_some()
+        }
+
+        /// Stops the transfer before its end, and waits until the channel is stopped.
+        pub fn request_stop(&mut self) {
+            self.rest = None;
+            self.channel.request_stop();
+            while self.channel.is_running() {}
+        }
+
+        /// Returns the number of words left to transfer. This is zero once a transfer has
+        /// completed without being stopped.
+        pub fn remaining_transfers(&mut self) -> usize {
+            let rest = self.rest.as_ref().map_or(0, |rest| rest.remaining);
+            self.channel.remaining_transfers() as usize + rest
+        }
     }
 
     impl<'a, C: Channel> Drop for Transfer<'a, C> {
@@ -250,11 +470,21 @@ mod transfers {
     impl<'a, C: Channel> Future for Transfer<'a, C> {
         type Output = ();
         fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-            self.channel.set_waker(cx.waker());
-            if self.channel.is_running() {
-                Poll::Pending
-            } else {
-                Poll::Ready(())
+            let this = &mut *self;
+            this.channel.set_waker(cx.waker());
+            if this.channel.is_running() {
+                return Poll::Pending;
+            }
+            match &mut this.rest {
+                Some(rest) => {
+                    unsafe { (rest.start)(&mut this.channel, rest) };
+                    if rest.remaining == 0 {
+                        this.rest = None;
+                    }
+                    this.channel.set_waker(cx.waker());
+                    Poll::Pending
+                }
+                None => Poll::Ready(()),
             }
         }
     }
@@ -0,0 +1,561 @@
+//! Chrom-ART accelerator (DMA2D)
+//!
+//! The DMA2D is a DMA specialized in 2D graphics: it fills rectangles with a color, copies
+//! images between buffers with a different line length, converts between pixel formats and
+//! blends images on top of each other, without using the CPU.
+//!
+//! The buffers must be in memory the DMA2D can access: on H7, that excludes the DTCM.
+
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy::interrupt::{Interrupt, InterruptExt};
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::drop::OnDrop;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::pac::dma2d::regs;
+
+/// Pixel formats supported as input and output.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PixelFormat {
+    Argb8888 = 0,
+    Rgb888 = 1,
+    Rgb565 = 2,
+    Argb1555 = 3,
+    Argb4444 = 4,
+}
+
+impl PixelFormat {
+    /// Size of a pixel, in bytes.
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            PixelFormat::Argb8888 => 4,
+            PixelFormat::Rgb888 => 3,
+            PixelFormat::Rgb565 | PixelFormat::Argb1555 | PixelFormat::Argb4444 => 2,
+        }
+    }
+
+    /// Convert an ARGB8888 color to this format, dropping the least significant bits.
+    pub fn encode(&self, argb: u32) -> u32 {
+        let [b, g, r, a] = argb.to_le_bytes();
+        let (a, r, g, b) = (a as u32, r as u32, g as u32, b as u32);
+        match self {
+            PixelFormat::Argb8888 => argb,
+            PixelFormat::Rgb888 => argb & 0xff_ffff,
+            PixelFormat::Rgb565 => (r >> 3) << 11 | (g >> 2) << 5 | b >> 3,
+            PixelFormat::Argb1555 => (a >> 7) << 15 | (r >> 3) << 10 | (g >> 3) << 5 | b >> 3,
+            PixelFormat::Argb4444 => (a >> 4) << 12 | (r >> 4) << 8 | (g >> 4) << 4 | b >> 4,
+        }
+    }
+}
+
+/// A rectangle, in pixels.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+/// An image the DMA2D reads from.
+pub struct Image<'a> {
+    data: &'a [u8],
+    width: u16,
+    height: u16,
+    format: PixelFormat,
+}
+
+impl<'a> Image<'a> {
+    /// # Panics
+    ///
+    /// Panics if `data` is smaller than the image.
+    pub fn new(data: &'a [u8], width: u16, height: u16, format: PixelFormat) -> Self {
+        assert!(data.len() >= width as usize * height as usize * format.bytes_per_pixel());
+        Self {
+            data,
+            width,
+            height,
+            format,
+        }
+    }
+}
+
+/// A buffer the DMA2D draws into.
+pub struct Framebuffer<'a> {
+    data: &'a mut [u8],
+    width: u16,
+    height: u16,
+    format: PixelFormat,
+}
+
+impl<'a> Framebuffer<'a> {
+    /// # Panics
+    ///
+    /// Panics if `data` is smaller than the framebuffer.
+    pub fn new(data: &'a mut [u8], width: u16, height: u16, format: PixelFormat) -> Self {
+        assert!(data.len() >= width as usize * height as usize * format.bytes_per_pixel());
+        Self {
+            data,
+            width,
+            height,
+            format,
+        }
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// Set a single pixel with the CPU, to an ARGB8888 color.
+    ///
+    /// Pixels outside of the framebuffer are ignored.
+    pub fn set_pixel(&mut self, x: u16, y: u16, argb: u32) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let bpp = self.format.bytes_per_pixel();
+        let offset = (y as usize * self.width as usize + x as usize) * bpp;
+        let encoded = self.format.encode(argb).to_le_bytes();
+        self.data[offset..offset + bpp].copy_from_slice(&encoded[..bpp]);
+    }
+
+    fn area(&mut self, area: Rect) -> (*mut u8, u16) {
+        assert!(area.x + area.width <= self.width && area.y + area.height <= self.height);
+        let offset = (area.y as usize * self.width as usize + area.x as usize)
+            * self.format.bytes_per_pixel();
+        (self.data[offset..].as_mut_ptr(), self.width - area.width)
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// A bus error happened while accessing a buffer.
+    Transfer,
+    /// The DMA2D rejected the configuration, for example because of misaligned buffers.
+    Configuration,
+}
+
+#[derive(Clone, Copy)]
+enum Mode {
+    MemoryToMemory = 0b00,
+    MemoryToMemoryPfc = 0b01,
+    MemoryToMemoryBlend = 0b10,
+    RegisterToMemory = 0b11,
+}
+
+struct State {
+    waker: AtomicWaker,
+}
+
+impl State {
+    const fn new() -> State {
+        State {
+            waker: AtomicWaker::new(),
+        }
+    }
+}
+
+static STATE: State = State::new();
+
+pub struct Dma2d<'d, T: Instance> {
+    phantom: PhantomData<&'d mut T>,
+}
+
+impl<'d, T: Instance> Dma2d<'d, T> {
+    pub fn new(
+        _peri: impl Unborrow<Target = T> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+    ) -> Self {
+        unborrow!(irq);
+
+        T::enable();
+        T::reset();
+
+        irq.set_handler(Self::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        Self {
+            phantom: PhantomData,
+        }
+    }
+
+    unsafe fn on_interrupt(_: *mut ()) {
+        let r = T::regs();
+        let isr = r.isr().read();
+        if isr.tcif() || isr.teif() || isr.ceif() {
+            // Leave the flags set, they're checked and cleared by the waiting future.
+            r.cr().modify(|w| {
+                w.set_tcie(false);
+                w.set_teie(false);
+                w.set_ceie(false);
+            });
+            STATE.waker.wake();
+        }
+    }
+
+    /// Fill an area of the framebuffer with an ARGB8888 color.
+    pub async fn fill(
+        &mut self,
+        dst: &mut Framebuffer<'_>,
+        area: Rect,
+        argb: u32,
+    ) -> Result<(), Error> {
+        self.setup_fill(dst, area, argb);
+        self.run().await
+    }
+
+    /// Copy an image into the framebuffer, at the given position, converting the pixel
+    /// format if needed.
+    pub async fn copy(
+        &mut self,
+        src: &Image<'_>,
+        dst: &mut Framebuffer<'_>,
+        x: u16,
+        y: u16,
+    ) -> Result<(), Error> {
+        self.setup_copy(src, dst, x, y);
+        self.run().await
+    }
+
+    /// Blend an image over the framebuffer, at the given position.
+    ///
+    /// The alpha of each pixel of the image is multiplied by `alpha`, 255 uses the pixel
+    /// alpha as is.
+    pub async fn blend(
+        &mut self,
+        src: &Image<'_>,
+        dst: &mut Framebuffer<'_>,
+        x: u16,
+        y: u16,
+        alpha: u8,
+    ) -> Result<(), Error> {
+        self.setup_blend(src, dst, x, y, alpha);
+        self.run().await
+    }
+
+    /// Fill an area of the framebuffer with an ARGB8888 color, blocking until done.
+    pub fn blocking_fill(
+        &mut self,
+        dst: &mut Framebuffer<'_>,
+        area: Rect,
+        argb: u32,
+    ) -> Result<(), Error> {
+        self.setup_fill(dst, area, argb);
+        self.blocking_run()
+    }
+
+    /// Copy an image into the framebuffer, blocking until done.
+    pub fn blocking_copy(
+        &mut self,
+        src: &Image<'_>,
+        dst: &mut Framebuffer<'_>,
+        x: u16,
+        y: u16,
+    ) -> Result<(), Error> {
+        self.setup_copy(src, dst, x, y);
+        self.blocking_run()
+    }
+
+    /// Blend an image over the framebuffer, blocking until done.
+    pub fn blocking_blend(
+        &mut self,
+        src: &Image<'_>,
+        dst: &mut Framebuffer<'_>,
+        x: u16,
+        y: u16,
+        alpha: u8,
+    ) -> Result<(), Error> {
+        self.setup_blend(src, dst, x, y, alpha);
+        self.blocking_run()
+    }
+
+    fn setup_fill(&mut self, dst: &mut Framebuffer<'_>, area: Rect, argb: u32) {
+        let r = T::regs();
+        unsafe {
+            r.ocolr().write_value(regs::Ocolr(dst.format.encode(argb)));
+        }
+        Self::setup_output(Mode::RegisterToMemory, dst, area);
+    }
+
+    fn setup_copy(&mut self, src: &Image<'_>, dst: &mut Framebuffer<'_>, x: u16, y: u16) {
+        let r = T::regs();
+        unsafe {
+            r.fgmar().write(|w| w.set_ma(src.data.as_ptr() as u32));
+            r.fgor().write(|w| w.set_lo(0));
+            r.fgpfccr().write(|w| w.set_cm(src.format as u8));
+        }
+
+        let mode = if src.format == dst.format {
+            Mode::MemoryToMemory
+        } else {
+            Mode::MemoryToMemoryPfc
+        };
+        Self::setup_output(mode, dst, Self::image_area(src, x, y));
+    }
+
+    fn setup_blend(
+        &mut self,
+        src: &Image<'_>,
+        dst: &mut Framebuffer<'_>,
+        x: u16,
+        y: u16,
+        alpha: u8,
+    ) {
+        let r = T::regs();
+        let area = Self::image_area(src, x, y);
+        // The background is the area of the framebuffer being drawn over.
+        let (bg, bg_offset) = dst.area(area);
+
+        unsafe {
+            r.fgmar().write(|w| w.set_ma(src.data.as_ptr() as u32));
+            r.fgor().write(|w| w.set_lo(0));
+            r.fgpfccr().write(|w| {
+                w.set_cm(src.format as u8);
+                // Multiply the pixel alpha with the constant alpha.
+                w.set_am(0b10);
+                w.set_alpha(alpha);
+            });
+            r.bgmar().write(|w| w.set_ma(bg as u32));
+            r.bgor().write(|w| w.set_lo(bg_offset));
+            r.bgpfccr().write(|w| w.set_cm(dst.format as u8));
+        }
+
+        Self::setup_output(Mode::MemoryToMemoryBlend, dst, area);
+    }
+
+    fn image_area(src: &Image<'_>, x: u16, y: u16) -> Rect {
+        Rect {
+            x,
+            y,
+            width: src.width,
+            height: src.height,
+        }
+    }
+
+    fn setup_output(mode: Mode, dst: &mut Framebuffer<'_>, area: Rect) {
+        let r = T::regs();
+        let (out, offset) = dst.area(area);
+        unsafe {
+            r.ifcr().write(|w| {
+                w.set_cteif(true);
+                w.set_ctcif(true);
+                w.set_cceif(true);
+            });
+            r.opfccr().write(|w| w.set_cm(dst.format as u8));
+            r.omar().write(|w| w.set_ma(out as u32));
+            r.oor().write(|w| w.set_lo(offset));
+            r.nlr().write(|w| {
+                w.set_pl(area.width);
+                w.set_nl(area.height);
+            });
+            r.cr().write(|w| w.set_mode(mode as u8));
+        }
+    }
+
+    fn check_done() -> Poll<Result<(), Error>> {
+        let r = T::regs();
+        unsafe {
+            let isr = r.isr().read();
+            if isr.ceif() {
+                r.ifcr().write(|w| w.set_cceif(true));
+                Poll::Ready(Err(Error::Configuration))
+            } else if isr.teif() {
+                r.ifcr().write(|w| w.set_cteif(true));
+                Poll::Ready(Err(Error::Transfer))
+            } else if isr.tcif() {
+                r.ifcr().write(|w| w.set_ctcif(true));
+                Poll::Ready(Ok(()))
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    async fn run(&mut self) -> Result<(), Error> {
+        let r = T::regs();
+
+        // If the future is dropped, abort the transfer so the DMA2D doesn't
+        // keep accessing the buffers.
+        let on_drop = OnDrop::new(|| unsafe {
+            r.cr().modify(|w| w.set_abort(true));
+            while r.cr().read().start() {}
+        });
+
+        unsafe {
+            r.cr().modify(|w| {
+                w.set_tcie(true);
+                w.set_teie(true);
+                w.set_ceie(true);
+                w.set_start(true);
+            });
+        }
+
+        let res = poll_fn(|cx| {
+            STATE.waker.register(cx.waker());
+            Self::check_done()
+        })
+        .await;
+
+        on_drop.defuse();
+        res
+    }
+
+    fn blocking_run(&mut self) -> Result<(), Error> {
+        let r = T::regs();
+        unsafe { r.cr().modify(|w| w.set_start(true)) };
+        loop {
+            if let Poll::Ready(res) = Self::check_done() {
+                return res;
+            }
+        }
+    }
+}
+
+impl<'d, T: Instance> Drop for Dma2d<'d, T> {
+    fn drop(&mut self) {
+        T::disable();
+    }
+}
+
+#[cfg(feature = "embedded-graphics-core")]
+pub use graphics::Display;
+
+#[cfg(feature = "embedded-graphics-core")]
+mod graphics {
+    use core::convert::TryFrom;
+    use core::marker::PhantomData;
+
+    use embedded_graphics_core::draw_target::DrawTarget;
+    use embedded_graphics_core::geometry::{OriginDimensions, Size};
+    use embedded_graphics_core::pixelcolor::{IntoStorage, PixelColor, Rgb888};
+    use embedded_graphics_core::primitives::Rectangle;
+    use embedded_graphics_core::Pixel;
+
+    use super::*;
+
+    /// An embedded-graphics [`DrawTarget`] drawing into a framebuffer, with the solid fills
+    /// done by the DMA2D.
+    ///
+    /// Individual pixels are drawn by the CPU.
+    pub struct Display<'a, 'd, T: Instance, C> {
+        dma2d: &'a mut Dma2d<'d, T>,
+        framebuffer: Framebuffer<'a>,
+        phantom: PhantomData<C>,
+    }
+
+    impl<'a, 'd, T: Instance, C> Display<'a, 'd, T, C> {
+        pub fn new(dma2d: &'a mut Dma2d<'d, T>, framebuffer: Framebuffer<'a>) -> Self {
+            Self {
+                dma2d,
+                framebuffer,
+                phantom: PhantomData,
+            }
+        }
+
+        /// Get back the framebuffer.
+        pub fn release(self) -> Framebuffer<'a> {
+            self.framebuffer
+        }
+    }
+
+    fn argb<C: Into<Rgb888>>(color: C) -> u32 {
+        0xff00_0000 | color.into().into_storage()
+    }
+
+    impl<'a, 'd, T: Instance, C> OriginDimensions for Display<'a, 'd, T, C> {
+        fn size(&self) -> Size {
+            Size::new(
+                self.framebuffer.width as u32,
+                self.framebuffer.height as u32,
+            )
+        }
+    }
+
+    impl<'a, 'd, T: Instance, C> DrawTarget for Display<'a, 'd, T, C>
+    where
+        C: PixelColor + Into<Rgb888>,
+    {
+        type Color = C;
+        type Error = Error;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<Self::Color>>,
+        {
+            for Pixel(point, color) in pixels {
+                if let (Ok(x), Ok(y)) = (u16::try_from(point.x), u16::try_from(point.y)) {
+                    self.framebuffer.set_pixel(x, y, argb(color));
+                }
+            }
+            Ok(())
+        }
+
+        fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+            let area = area.intersection(&self.bounding_box());
+            let bottom_right = match area.bottom_right() {
+                Some(p) => p,
+                None => return Ok(()),
+            };
+            let rect = Rect {
+                x: area.top_left.x as u16,
+                y: area.top_left.y as u16,
+                width: (bottom_right.x - area.top_left.x + 1) as u16,
+                height: (bottom_right.y - area.top_left.y + 1) as u16,
+            };
+            self.dma2d
+                .blocking_fill(&mut self.framebuffer, rect, argb(color))
+        }
+
+        fn clear(&mut self, color: Self::Color) -> Result<(), Self::Error> {
+            let rect = Rect {
+                x: 0,
+                y: 0,
+                width: self.framebuffer.width,
+                height: self.framebuffer.height,
+            };
+            self.dma2d
+                .blocking_fill(&mut self.framebuffer, rect, argb(color))
+        }
+    }
+}
+
+pub(crate) mod sealed {
+    pub trait Instance: crate::rcc::RccPeripheral {
+        fn regs() -> crate::pac::dma2d::Dma2d;
+    }
+}
+
+pub trait Instance: sealed::Instance + 'static {
+    type Interrupt: Interrupt;
+}
+
+foreach_interrupt!(
+    ($inst:ident, dma2d, $block:ident, GLOBAL, $irq:ident) => {
+        impl sealed::Instance for crate::peripherals::$inst {
+            fn regs() -> crate::pac::dma2d::Dma2d {
+                crate::pac::$inst
+            }
+        }
+
+        impl Instance for crate::peripherals::$inst {
+            type Interrupt = crate::interrupt::$irq;
+        }
+    };
+);
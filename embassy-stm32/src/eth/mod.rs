@@ -8,6 +8,72 @@ pub mod lan8742a;
 
 pub use _version::*;
 
+#[allow(unused)]
+const MTU: usize = 1516;
+
+/// A DMA buffer for one Ethernet frame, owned by a descriptor ring.
+#[allow(unused)]
+#[repr(align(4))]
+pub(crate) struct Packet(pub [u8; MTU]);
+
+#[allow(unused)]
+impl Packet {
+    pub const fn new() -> Self {
+        Self([0; MTU])
+    }
+}
+
+/// Multicast addresses accepted through the 64-bin hash filter of the MAC.
+///
+/// Several addresses can share a bin, so each bin counts the addresses using it, and is only
+/// cleared once all of them are removed.
+#[allow(unused)]
+pub(crate) struct MulticastFilter {
+    refs: [u8; 64],
+}
+
+#[allow(unused)]
+impl MulticastFilter {
+    pub const fn new() -> Self {
+        Self { refs: [0; 64] }
+    }
+
+    pub fn add(&mut self, addr: &[u8; 6]) {
+        let bin = &mut self.refs[Self::bin(addr)];
+        *bin = bin.saturating_add(1);
+    }
+
+    pub fn remove(&mut self, addr: &[u8; 6]) {
+        let bin = &mut self.refs[Self::bin(addr)];
+        *bin = bin.saturating_sub(1);
+    }
+
+    /// Value of the hash table registers, high register in the upper 32 bits
+    pub fn table(&self) -> u64 {
+        self.refs
+            .iter()
+            .enumerate()
+            .filter(|(_, refs)| **refs != 0)
+            .fold(0, |table, (bin, _)| table | 1 << bin)
+    }
+
+    /// The bin is given by the upper 6 bits of the bit-reversed Ethernet CRC of the address.
+    fn bin(addr: &[u8; 6]) -> usize {
+        let mut crc = 0xFFFF_FFFFu32;
+        for byte in addr {
+            crc ^= *byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 {
+                    (crc >> 1) ^ 0xEDB8_8320
+                } else {
+                    crc >> 1
+                };
+            }
+        }
+        ((!crc).reverse_bits() >> 26) as usize
+    }
+}
+
 /// Station Management Interface (SMI) on an ethernet PHY
 ///
 /// # Safety
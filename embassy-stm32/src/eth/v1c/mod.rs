@@ -4,11 +4,11 @@ use core::marker::PhantomData;
 use core::sync::atomic::{fence, Ordering};
 use core::task::Waker;
 
+use embassy::interrupt::InterruptExt;
 use embassy::util::Unborrow;
 use embassy::waitqueue::AtomicWaker;
-use embassy_hal_common::peripheral::{PeripheralMutex, PeripheralState, StateStorage};
 use embassy_hal_common::unborrow;
-use embassy_net::{Device, DeviceCapabilities, LinkState, PacketBuf, MTU};
+use embassy_net::{Device, DeviceCapabilities, LinkState};
 
 use crate::gpio::sealed::Pin as __GpioPin;
 use crate::gpio::{sealed::AFType, AnyPin, Speed};
@@ -20,26 +20,35 @@ mod tx_desc;
 
 use super::*;
 use descriptors::DescriptorRing;
+use rx_desc::RDesRing;
 use stm32_metapac::eth::vals::{
     Apcs, Cr, Dm, DmaomrSr, Fes, Ftf, Ifg, MbProgress, Mw, Pbl, Rsf, St, Tsf,
 };
+use tx_desc::TDesRing;
 
-pub struct State<'d, T: Instance, const TX: usize, const RX: usize>(
-    StateStorage<Inner<'d, T, TX, RX>>,
-);
+pub struct State<'d, T: Instance, const TX: usize, const RX: usize> {
+    _peri: PhantomData<&'d mut T>,
+    desc_ring: DescriptorRing<TX, RX>,
+}
 impl<'d, T: Instance, const TX: usize, const RX: usize> State<'d, T, TX, RX> {
     pub fn new() -> Self {
-        Self(StateStorage::new())
+        Self {
+            _peri: PhantomData,
+            desc_ring: DescriptorRing::new(),
+        }
     }
 }
 
 pub struct Ethernet<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> {
-    state: PeripheralMutex<'d, Inner<'d, T, TX, RX>>,
+    _peri: PhantomData<&'d mut T>,
+    desc_ring: &'d mut DescriptorRing<TX, RX>,
+    interrupt: crate::interrupt::ETH,
     pins: [AnyPin; 9],
     _phy: P,
     clock_range: Cr,
     phy_addr: u8,
     mac_addr: [u8; 6],
+    multicast: MulticastFilter,
 }
 
 macro_rules! config_pins {
@@ -58,7 +67,7 @@ impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> Ethernet<'d, T,
     /// safety: the returned instance is not leak-safe
     pub unsafe fn new(
         state: &'d mut State<'d, T, TX, RX>,
-        peri: impl Unborrow<Target = T> + 'd,
+        _peri: impl Unborrow<Target = T> + 'd,
         interrupt: impl Unborrow<Target = crate::interrupt::ETH> + 'd,
         ref_clk: impl Unborrow<Target = impl RefClkPin<T>> + 'd,
         mdio: impl Unborrow<Target = impl MDIOPin<T>> + 'd,
@@ -91,9 +100,6 @@ impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> Ethernet<'d, T,
 
         config_pins!(ref_clk, mdio, mdc, crs, rx_d0, rx_d1, tx_d0, tx_d1, tx_en);
 
-        // NOTE(unsafe) We are ourselves not leak-safe.
-        let state = PeripheralMutex::new_unchecked(interrupt, &mut state.0, || Inner::new(peri));
-
         // NOTE(unsafe) We have exclusive access to the registers
         let dma = ETH.ethernet_dma();
         let mac = ETH.ethernet_mac();
@@ -123,6 +129,9 @@ impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> Ethernet<'d, T,
             )
         });
 
+        // Filter multicast frames with the hash table, which starts empty
+        mac.macffr().modify(|w| w.set_hm(true));
+
         // pause time
         mac.macfcr().modify(|w| w.set_pt(0x100));
 
@@ -168,44 +177,94 @@ impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> Ethernet<'d, T,
         ];
 
         let mut this = Self {
-            state,
+            _peri: PhantomData,
+            desc_ring: &mut state.desc_ring,
+            interrupt,
             pins,
             _phy: phy,
             clock_range,
             phy_addr,
             mac_addr,
+            multicast: MulticastFilter::new(),
         };
 
-        this.state.with(|s| {
-            s.desc_ring.init();
-
-            fence(Ordering::SeqCst);
+        this.desc_ring.init();
 
-            let mac = ETH.ethernet_mac();
-            let dma = ETH.ethernet_dma();
+        fence(Ordering::SeqCst);
 
-            mac.maccr().modify(|w| {
-                w.set_re(true);
-                w.set_te(true);
-            });
-            dma.dmaomr().modify(|w| {
-                w.set_ftf(Ftf::FLUSH); // flush transmit fifo (queue)
-                w.set_st(St::STARTED); // start transmitting channel
-                w.set_sr(DmaomrSr::STARTED); // start receiving channel
-            });
+        mac.maccr().modify(|w| {
+            w.set_re(true);
+            w.set_te(true);
+        });
+        dma.dmaomr().modify(|w| {
+            w.set_ftf(Ftf::FLUSH); // flush transmit fifo (queue)
+            w.set_st(St::STARTED); // start transmitting channel
+            w.set_sr(DmaomrSr::STARTED); // start receiving channel
+        });
 
-            // Enable interrupts
-            dma.dmaier().modify(|w| {
-                w.set_nise(true);
-                w.set_rie(true);
-                w.set_tie(true);
-            });
+        // Enable interrupts
+        dma.dmaier().modify(|w| {
+            w.set_nise(true);
+            w.set_rie(true);
+            w.set_tie(true);
         });
+
+        this.interrupt.set_handler(Self::on_interrupt);
+        this.interrupt.unpend();
+        this.interrupt.enable();
+
         P::phy_reset(&mut this);
         P::phy_init(&mut this);
 
         this
     }
+
+    /// Receive all frames, whatever their destination address.
+    pub fn set_promiscuous(&mut self, enabled: bool) {
+        // NOTE(unsafe) These registers aren't used in the interrupt and we have `&mut self`
+        unsafe {
+            ETH.ethernet_mac().macffr().modify(|w| w.set_pm(enabled));
+        }
+    }
+
+    /// Receive all multicast frames, not only the ones sent to the addresses added with
+    /// [`Device::add_multicast_address`].
+    pub fn set_pass_all_multicast(&mut self, enabled: bool) {
+        // NOTE(unsafe) These registers aren't used in the interrupt and we have `&mut self`
+        unsafe {
+            ETH.ethernet_mac().macffr().modify(|w| w.set_pam(enabled));
+        }
+    }
+
+    fn update_multicast_filter(&mut self) {
+        let table = self.multicast.table();
+
+        // NOTE(unsafe) These registers aren't used in the interrupt and we have `&mut self`
+        unsafe {
+            let mac = ETH.ethernet_mac();
+
+            mac.machthr().write(|w| w.0 = (table >> 32) as u32);
+            mac.machtlr().write(|w| w.0 = table as u32);
+        }
+    }
+
+    fn on_interrupt(_: *mut ()) {
+        WAKER.wake();
+
+        // TODO: Check and clear more flags
+        unsafe {
+            let dma = ETH.ethernet_dma();
+
+            dma.dmasr().modify(|w| {
+                w.set_ts(true);
+                w.set_rs(true);
+                w.set_nis(true);
+            });
+            // Delay two peripheral's clock
+            dma.dmasr().read();
+            dma.dmasr().read();
+        }
+    }
 }
 
 unsafe impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> StationManagement
@@ -249,16 +308,31 @@ unsafe impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> StationMa
 impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> Device
     for Ethernet<'d, T, P, TX, RX>
 {
-    fn is_transmit_ready(&mut self) -> bool {
-        self.state.with(|s| s.desc_ring.tx.available())
-    }
-
-    fn transmit(&mut self, pkt: PacketBuf) {
-        self.state.with(|s| unwrap!(s.desc_ring.tx.transmit(pkt)));
+    type RxToken<'a>
+        = RxToken<'a, RX>
+    where
+        Self: 'a;
+    type TxToken<'a>
+        = TxToken<'a, TX>
+    where
+        Self: 'a;
+
+    fn receive(&mut self) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let ring = &mut *self.desc_ring;
+        if ring.rx.available().is_some() && ring.tx.available().is_some() {
+            Some((RxToken { rx: &mut ring.rx }, TxToken { tx: &mut ring.tx }))
+        } else {
+            None
+        }
     }
 
-    fn receive(&mut self) -> Option<PacketBuf> {
-        self.state.with(|s| s.desc_ring.rx.pop_packet())
+    fn transmit(&mut self) -> Option<Self::TxToken<'_>> {
+        let ring = &mut *self.desc_ring;
+        if ring.tx.available().is_some() {
+            Some(TxToken { tx: &mut ring.tx })
+        } else {
+            None
+        }
     }
 
     fn register_waker(&mut self, waker: &Waker) {
@@ -283,12 +357,25 @@ impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> Device
     fn ethernet_address(&mut self) -> [u8; 6] {
         self.mac_addr
     }
+
+    fn add_multicast_address(&mut self, addr: [u8; 6]) {
+        self.multicast.add(&addr);
+        self.update_multicast_filter();
+    }
+
+    fn remove_multicast_address(&mut self, addr: [u8; 6]) {
+        self.multicast.remove(&addr);
+        self.update_multicast_filter();
+    }
 }
 
 impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> Drop
     for Ethernet<'d, T, P, TX, RX>
 {
     fn drop(&mut self) {
+        self.interrupt.disable();
+        self.interrupt.remove_handler();
+
         // NOTE(unsafe) We have `&mut self` and the interrupt doesn't use this registers
         unsafe {
             let dma = ETH.ethernet_dma();
@@ -317,42 +404,37 @@ impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> Drop
 
 //----------------------------------------------------------------------
 
-struct Inner<'d, T: Instance, const TX: usize, const RX: usize> {
-    _peri: PhantomData<&'d mut T>,
-    desc_ring: DescriptorRing<TX, RX>,
+pub struct RxToken<'a, const RX: usize> {
+    rx: &'a mut RDesRing<RX>,
 }
 
-impl<'d, T: Instance, const TX: usize, const RX: usize> Inner<'d, T, TX, RX> {
-    pub fn new(_peri: impl Unborrow<Target = T> + 'd) -> Self {
-        Self {
-            _peri: PhantomData,
-            desc_ring: DescriptorRing::new(),
-        }
+impl<'a, const RX: usize> embassy_net::RxToken for RxToken<'a, RX> {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        // NOTE(unwrap): we checked the queue wasn't empty when creating the token.
+        let pkt = unwrap!(self.rx.available());
+        let r = f(pkt);
+        self.rx.pop_packet();
+        r
     }
 }
 
-impl<'d, T: Instance, const TX: usize, const RX: usize> PeripheralState for Inner<'d, T, TX, RX> {
-    type Interrupt = crate::interrupt::ETH;
-
-    fn on_interrupt(&mut self) {
-        unwrap!(self.desc_ring.tx.on_interrupt());
-        self.desc_ring.rx.on_interrupt();
-
-        WAKER.wake();
-
-        // TODO: Check and clear more flags
-        unsafe {
-            let dma = ETH.ethernet_dma();
+pub struct TxToken<'a, const TX: usize> {
+    tx: &'a mut TDesRing<TX>,
+}
 
-            dma.dmasr().modify(|w| {
-                w.set_ts(true);
-                w.set_rs(true);
-                w.set_nis(true);
-            });
-            // Delay two peripheral's clock
-            dma.dmasr().read();
-            dma.dmasr().read();
-        }
+impl<'a, const TX: usize> embassy_net::TxToken for TxToken<'a, TX> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        // NOTE(unwrap): we checked the queue wasn't full when creating the token.
+        let pkt = unwrap!(self.tx.available());
+        let r = f(&mut pkt[..len]);
+        self.tx.transmit(len);
+        r
     }
 }
 
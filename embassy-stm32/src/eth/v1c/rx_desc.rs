@@ -1,9 +1,9 @@
 use core::sync::atomic::{compiler_fence, fence, Ordering};
 
-use embassy_net::{Packet, PacketBox, PacketBoxExt, PacketBuf};
 use stm32_metapac::eth::vals::{DmaomrSr, Rpd, Rps};
 use vcell::VolatileCell;
 
+use crate::eth::Packet;
 use crate::pac::ETH;
 
 mod rx_consts {
@@ -134,66 +134,28 @@ impl RunningState {
 
 /// Rx ring of descriptors and packets
 ///
-/// This ring has three major locations that work in lock-step. The DMA will never write to the tail
-/// index, so the `read_index` must never pass the tail index. The `next_tail_index` is always 1
-/// slot ahead of the real tail index, and it must never pass the `read_index` or it could overwrite
-/// a packet still to be passed to the application.
-///
-///                                                                   nt can't pass r (no alloc)
-/// +---+---+---+---+  Read ok       +---+---+---+---+ No Read       +---+---+---+---+
-/// |   |   |   |   |  ------------> |   |   |   |   | ------------> |   |   |   |   |
-/// +---+---+---+---+  Allocation ok +---+---+---+---+               +---+---+---+---+
-///   ^           ^t                   ^t  ^                           ^t  ^
-///   |r                                   |r                              |r
-///   |nt                                  |nt                             |nt
-///
-///
-/// +---+---+---+---+  Read ok         +---+---+---+---+ Can't read    +---+---+---+---+
-/// |   |   |   |   |  ------------>   |   |   |   |   | ------------> |   |   |   |   |
-/// +---+---+---+---+  Allocation fail +---+---+---+---+ Allocation ok +---+---+---+---+
-///       ^   ^t  ^                              ^t  ^                   ^       ^   ^t
-///       |r      |                              |r  |                   |       |r
-///               |nt                                |nt                 |nt
-///
+/// Every descriptor owns one buffer, which is handed to the DMA while the descriptor is ready,
+/// and lent to the stack while it holds a received packet.
 pub(crate) struct RDesRing<const N: usize> {
     descriptors: [RDes; N],
-    buffers: [Option<PacketBox>; N],
+    buffers: [Packet; N],
     read_index: usize,
-    next_tail_index: usize,
 }
 
 impl<const N: usize> RDesRing<N> {
     pub const fn new() -> Self {
         const RDES: RDes = RDes::new();
-        const BUFFERS: Option<PacketBox> = None;
+        const PACKET: Packet = Packet::new();
 
         Self {
             descriptors: [RDES; N],
-            buffers: [BUFFERS; N],
+            buffers: [PACKET; N],
             read_index: 0,
-            next_tail_index: 0,
         }
     }
 
     pub(crate) fn init(&mut self) {
         assert!(N > 1);
-        let mut last_index = 0;
-        for (index, buf) in self.buffers.iter_mut().enumerate() {
-            let pkt = match PacketBox::new(Packet::new()) {
-                Some(p) => p,
-                None => {
-                    if index == 0 {
-                        panic!("Could not allocate at least one buffer for Ethernet receiving");
-                    } else {
-                        break;
-                    }
-                }
-            };
-            self.descriptors[index].set_ready(pkt.as_ptr() as u32, pkt.len());
-            *buf = Some(pkt);
-            last_index = index;
-        }
-        self.next_tail_index = (last_index + 1) % N;
 
         // not sure if this is supposed to span all of the descriptor or just those that contain buffers
         {
@@ -210,14 +172,19 @@ impl<const N: usize> RDesRing<N> {
             }
         }
 
-        // Register txdescriptor start
+        for (desc, buf) in self.descriptors.iter_mut().zip(self.buffers.iter_mut()) {
+            desc.set_ready(buf.0.as_mut_ptr() as u32, buf.0.len());
+        }
+        self.read_index = 0;
+
+        // Register rx descriptor start
         // NOTE (unsafe) Used for atomic writes
         unsafe {
             ETH.ethernet_dma()
                 .dmardlar()
                 .write(|w| w.0 = &self.descriptors as *const _ as u32);
         };
-        // We already have fences in `set_owned`, which is called in `setup`
+        // We already have fences in `set_ready`
 
         // Start receive
         unsafe {
@@ -233,12 +200,6 @@ impl<const N: usize> RDesRing<N> {
         unsafe { ETH.ethernet_dma().dmarpdr().write(|w| w.set_rpd(Rpd::POLL)) };
     }
 
-    pub(crate) fn on_interrupt(&mut self) {
-        // XXX: Do we need to do anything here ? Maybe we should try to advance the tail ptr, but it
-        // would soon hit the read ptr anyway, and we will wake smoltcp's stack on the interrupt
-        // which should try to pop a packet...
-    }
-
     /// Get current `RunningState`
     fn running_state(&self) -> RunningState {
         match unsafe { ETH.ethernet_dma().dmasr().read().rps() } {
@@ -258,7 +219,8 @@ impl<const N: usize> RDesRing<N> {
         }
     }
 
-    pub(crate) fn pop_packet(&mut self) -> Option<PacketBuf> {
+    /// Return the next received packet, if any, without removing it from the ring
+    pub(crate) fn available(&mut self) -> Option<&mut [u8]> {
         if !self.running_state().is_running() {
             self.demand_poll();
         }
@@ -267,43 +229,29 @@ impl<const N: usize> RDesRing<N> {
         // buffer (I think .-.)
         fence(Ordering::SeqCst);
 
-        let read_available = self.descriptors[self.read_index].available();
-        let tail_index = (self.next_tail_index + N - 1) % N;
-
-        let pkt = if read_available && self.read_index != tail_index {
-            let pkt = self.buffers[self.read_index].take();
-            let len = self.descriptors[self.read_index].packet_len();
-
-            assert!(pkt.is_some());
-            let valid = self.descriptors[self.read_index].valid();
-
-            self.read_index = (self.read_index + 1) % N;
-            if valid {
-                pkt.map(|p| p.slice(0..len))
-            } else {
-                None
+        // Skip over packets with errors, there might be valid ones after them.
+        loop {
+            let descriptor = &self.descriptors[self.read_index];
+            if !descriptor.available() {
+                return None;
             }
-        } else {
-            None
-        };
+            if descriptor.valid() {
+                break;
+            }
+            self.pop_packet();
+        }
 
-        // Try to advance the tail_index
-        if self.next_tail_index != self.read_index {
-            match PacketBox::new(Packet::new()) {
-                Some(b) => {
-                    let addr = b.as_ptr() as u32;
-                    let buffer_len = b.len();
-                    self.buffers[self.next_tail_index].replace(b);
-                    self.descriptors[self.next_tail_index].set_ready(addr, buffer_len);
+        let len = self.descriptors[self.read_index].packet_len();
+        Some(&mut self.buffers[self.read_index].0[..len])
+    }
 
-                    // "Preceding reads and writes cannot be moved past subsequent writes."
-                    fence(Ordering::Release);
+    /// Give the buffer of the packet returned by `available` back to the DMA
+    pub(crate) fn pop_packet(&mut self) {
+        let buf = &mut self.buffers[self.read_index].0;
+        let addr = buf.as_mut_ptr() as u32;
+        let len = buf.len();
+        self.descriptors[self.read_index].set_ready(addr, len);
 
-                    self.next_tail_index = (self.next_tail_index + 1) % N;
-                }
-                None => {}
-            }
-        }
-        pkt
+        self.read_index = (self.read_index + 1) % N;
     }
 }
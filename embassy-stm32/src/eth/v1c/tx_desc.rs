@@ -1,20 +1,11 @@
 use core::sync::atomic::{compiler_fence, fence, Ordering};
 
-use embassy_net::PacketBuf;
 use stm32_metapac::eth::vals::St;
 use vcell::VolatileCell;
 
+use crate::eth::Packet;
 use crate::pac::ETH;
 
-#[non_exhaustive]
-#[derive(Debug, Copy, Clone)]
-#[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub enum Error {
-    NoBufferAvailable,
-    // TODO: Break down this error into several others
-    TransmissionError,
-}
-
 /// Transmit and Receive Descriptor fields
 #[allow(dead_code)]
 mod tx_consts {
@@ -114,18 +105,18 @@ impl TDes {
 
 pub(crate) struct TDesRing<const N: usize> {
     descriptors: [TDes; N],
-    buffers: [Option<PacketBuf>; N],
+    buffers: [Packet; N],
     next_entry: usize,
 }
 
 impl<const N: usize> TDesRing<N> {
     pub const fn new() -> Self {
         const TDES: TDes = TDes::new();
-        const BUFFERS: Option<PacketBuf> = None;
+        const PACKET: Packet = Packet::new();
 
         Self {
             descriptors: [TDES; N],
-            buffers: [BUFFERS; N],
+            buffers: [PACKET; N],
             next_entry: 0,
         }
     }
@@ -176,25 +167,24 @@ impl<const N: usize> TDesRing<N> {
         };
     }
 
-    /// Return true if a TDes is available for use
-    pub(crate) fn available(&self) -> bool {
-        self.descriptors[self.next_entry].available()
-    }
-
-    pub(crate) fn transmit(&mut self, pkt: PacketBuf) -> Result<(), Error> {
-        if !self.available() {
-            return Err(Error::NoBufferAvailable);
+    /// Return the buffer of the next TDes, if it is available for use
+    pub(crate) fn available(&mut self) -> Option<&mut [u8]> {
+        if self.descriptors[self.next_entry].available() {
+            Some(&mut self.buffers[self.next_entry].0)
+        } else {
+            None
         }
+    }
 
+    /// Transmit the first `len` bytes of the buffer returned by `available`
+    pub(crate) fn transmit(&mut self, len: usize) {
         let descriptor = &mut self.descriptors[self.next_entry];
+        assert!(descriptor.available());
 
-        let pkt_len = pkt.len();
-        let address = pkt.as_ptr() as *const u8;
+        let address = self.buffers[self.next_entry].0.as_ptr() as *const u8;
 
         descriptor.set_buffer1(address);
-        descriptor.set_buffer1_len(pkt_len);
-
-        self.buffers[self.next_entry].replace(pkt);
+        descriptor.set_buffer1_len(len);
 
         descriptor.set_owned();
 
@@ -208,31 +198,5 @@ impl<const N: usize> TDesRing<N> {
 
         // Request the DMA engine to poll the latest tx descriptor
         unsafe { ETH.ethernet_dma().dmatpdr().modify(|w| w.0 = 1) }
-        Ok(())
-    }
-
-    pub(crate) fn on_interrupt(&mut self) -> Result<(), Error> {
-        let previous = (self.next_entry + N - 1) % N;
-        let td = &self.descriptors[previous];
-
-        // DMB to ensure that we are reading an updated value, probably not needed at the hardware
-        // level, but this is also a hint to the compiler that we're syncing on the buffer.
-        fence(Ordering::SeqCst);
-
-        let tdes0 = td.tdes0.get();
-
-        if tdes0 & TXDESC_0_OWN != 0 {
-            // Transmission isn't done yet, probably a receive interrupt that fired this
-            return Ok(());
-        }
-
-        // Release the buffer
-        self.buffers[previous].take();
-
-        if tdes0 & TXDESC_0_ES != 0 {
-            Err(Error::TransmissionError)
-        } else {
-            Ok(())
-        }
     }
 }
@@ -1,19 +1,11 @@
 use core::sync::atomic::{fence, Ordering};
 
-use embassy_net::{Packet, PacketBox, PacketBoxExt, PacketBuf};
+use embassy_net::PtpTimestamp;
 use vcell::VolatileCell;
 
+use crate::eth::Packet;
 use crate::pac::ETH;
 
-#[non_exhaustive]
-#[derive(Debug, Copy, Clone)]
-#[cfg_attr(feature = "defmt", derive(defmt::Format))]
-pub enum Error {
-    NoBufferAvailable,
-    // TODO: Break down this error into several others
-    TransmissionError,
-}
-
 /// Transmit and Receive Descriptor fields
 #[allow(dead_code)]
 mod emac_consts {
@@ -25,7 +17,11 @@ mod emac_consts {
     pub const EMAC_DES0_BUF1AP: u32 = 0xFFFF_FFFF;
 
     pub const EMAC_TDES2_IOC: u32 = 0x8000_0000;
+    pub const EMAC_TDES2_TTSE: u32 = 0x4000_0000;
     pub const EMAC_TDES2_B1L: u32 = 0x0000_3FFF;
+    pub const EMAC_TDES3_TTSS: u32 = 0x0002_0000;
+
+    pub const EMAC_RDES1_TSA: u32 = 0x0000_4000;
 
     pub const EMAC_RDES3_IOC: u32 = 0x4000_0000;
     pub const EMAC_RDES3_PL: u32 = 0x0000_7FFF;
@@ -36,8 +32,8 @@ use emac_consts::*;
 
 /// Transmit Descriptor representation
 ///
-/// * tdes0: transmit buffer address
-/// * tdes1:
+/// * tdes0: transmit buffer address, timestamp nanoseconds on write-back
+/// * tdes1: timestamp seconds on write-back
 /// * tdes2: buffer lengths
 /// * tdes3: control and payload/frame length
 #[repr(C)]
@@ -66,19 +62,21 @@ impl TDes {
 
 pub(crate) struct TDesRing<const N: usize> {
     td: [TDes; N],
-    buffers: [Option<PacketBuf>; N],
+    buffers: [Packet; N],
     tdidx: usize,
+    last: Option<usize>,
 }
 
 impl<const N: usize> TDesRing<N> {
     pub const fn new() -> Self {
         const TDES: TDes = TDes::new();
-        const BUFFERS: Option<PacketBuf> = None;
+        const PACKET: Packet = Packet::new();
 
         Self {
             td: [TDES; N],
-            buffers: [BUFFERS; N],
+            buffers: [PACKET; N],
             tdidx: 0,
+            last: None,
         }
     }
 
@@ -94,6 +92,7 @@ impl<const N: usize> TDesRing<N> {
             *td = TDes::new();
         }
         self.tdidx = 0;
+        self.last = None;
 
         // Initialize the pointers in the DMA engine. (There will be a memory barrier later
         // before the DMA engine is enabled.)
@@ -109,34 +108,35 @@ impl<const N: usize> TDesRing<N> {
         }
     }
 
-    /// Return true if a TDes is available for use
-    pub(crate) fn available(&self) -> bool {
-        self.td[self.tdidx].available()
+    /// Return the buffer of the next TDes, if it is available for use
+    pub(crate) fn available(&mut self) -> Option<&mut [u8]> {
+        if self.td[self.tdidx].available() {
+            Some(&mut self.buffers[self.tdidx].0)
+        } else {
+            None
+        }
     }
 
-    pub(crate) fn transmit(&mut self, pkt: PacketBuf) -> Result<(), Error> {
-        if !self.available() {
-            return Err(Error::NoBufferAvailable);
-        }
+    /// Transmit the first `len` bytes of the buffer returned by `available`
+    pub(crate) fn transmit(&mut self, len: usize) {
         let x = self.tdidx;
         let td = &mut self.td[x];
+        assert!(td.available());
+        assert!(len as u32 <= EMAC_TDES2_B1L);
 
-        let pkt_len = pkt.len();
-        assert!(pkt_len as u32 <= EMAC_TDES2_B1L);
-        let address = pkt.as_ptr() as u32;
+        let address = self.buffers[x].0.as_ptr() as u32;
 
         // Read format
         td.tdes0.set(address);
+        // TTSE is ignored by the MAC unless the timestamping unit is enabled.
         td.tdes2
-            .set(pkt_len as u32 & EMAC_TDES2_B1L | EMAC_TDES2_IOC);
+            .set(len as u32 & EMAC_TDES2_B1L | EMAC_TDES2_IOC | EMAC_TDES2_TTSE);
 
         // FD: Contains first buffer of packet
         // LD: Contains last buffer of packet
         // Give the DMA engine ownership
         td.tdes3.set(EMAC_DES3_FD | EMAC_DES3_LD | EMAC_DES3_OWN);
 
-        self.buffers[x].replace(pkt);
-
         // Ensure changes to the descriptor are committed before DMA engine sees tail pointer store.
         // This will generate an DMB instruction.
         // "Preceding reads and writes cannot be moved past subsequent writes."
@@ -150,41 +150,28 @@ impl<const N: usize> TDesRing<N> {
                 .dmactx_dtpr()
                 .write(|w| w.0 = &self.td[x] as *const _ as u32);
         }
+        self.last = Some(self.tdidx);
         self.tdidx = x;
-        Ok(())
     }
 
-    pub(crate) fn on_interrupt(&mut self) -> Result<(), Error> {
-        let previous = (self.tdidx + N - 1) % N;
-        let td = &self.td[previous];
-
-        // DMB to ensure that we are reading an updated value, probably not needed at the hardware
-        // level, but this is also a hint to the compiler that we're syncing on the buffer.
-        fence(Ordering::SeqCst);
-
-        let tdes3 = td.tdes3.get();
-
-        if tdes3 & EMAC_DES3_OWN != 0 {
-            // Transmission isn't done yet, probably a receive interrupt that fired this
-            return Ok(());
-        }
-        assert!(tdes3 & EMAC_DES3_CTXT == 0);
-
-        // Release the buffer
-        self.buffers[previous].take();
-
-        if tdes3 & EMAC_DES3_ES != 0 {
-            Err(Error::TransmissionError)
+    /// Return the timestamp of the last transmitted packet, once the DMA has written it back
+    pub(crate) fn timestamp(&self) -> Option<PtpTimestamp> {
+        let td = &self.td[self.last?];
+        if td.available() && td.tdes3.get() & EMAC_TDES3_TTSS != 0 {
+            Some(PtpTimestamp {
+                seconds: td.tdes1.get() as u64,
+                nanoseconds: td.tdes0.get(),
+            })
         } else {
-            Ok(())
+            None
         }
     }
 }
 
 /// Receive Descriptor representation
 ///
-/// * rdes0: recieve buffer address
-/// * rdes1:
+/// * rdes0: recieve buffer address, timestamp nanoseconds in a context descriptor
+/// * rdes1: extended status, timestamp seconds in a context descriptor
 /// * rdes2:
 /// * rdes3: OWN and Status
 #[repr(C)]
@@ -232,72 +219,36 @@ impl RDes {
 
 /// Rx ring of descriptors and packets
 ///
-/// This ring has three major locations that work in lock-step. The DMA will never write to the tail
-/// index, so the `read_index` must never pass the tail index. The `next_tail_index` is always 1
-/// slot ahead of the real tail index, and it must never pass the `read_index` or it could overwrite
-/// a packet still to be passed to the application.
-///
-///                                                                   nt can't pass r (no alloc)
-/// +---+---+---+---+  Read ok       +---+---+---+---+ No Read       +---+---+---+---+
-/// |   |   |   |   |  ------------> |   |   |   |   | ------------> |   |   |   |   |
-/// +---+---+---+---+  Allocation ok +---+---+---+---+               +---+---+---+---+
-///   ^           ^t                   ^t  ^                           ^t  ^          
-///   |r                                   |r                              |r         
-///   |nt                                  |nt                             |nt        
-///
-///
-/// +---+---+---+---+  Read ok         +---+---+---+---+ Can't read    +---+---+---+---+
-/// |   |   |   |   |  ------------>   |   |   |   |   | ------------> |   |   |   |   |
-/// +---+---+---+---+  Allocation fail +---+---+---+---+ Allocation ok +---+---+---+---+
-///       ^   ^t  ^                              ^t  ^                   ^       ^   ^t
-///       |r      |                              |r  |                   |       |r     
-///               |nt                                |nt                 |nt            
-///
+/// Every descriptor owns one buffer, which is handed to the DMA while the descriptor is ready,
+/// and lent to the stack while it holds a received packet. The DMA will never write to the
+/// descriptor at the tail index, which is always the last one given back to the DMA, so one
+/// descriptor of the ring is always unused.
 pub(crate) struct RDesRing<const N: usize> {
     rd: [RDes; N],
-    buffers: [Option<PacketBox>; N],
+    buffers: [Packet; N],
     read_idx: usize,
-    next_tail_idx: usize,
 }
 
 impl<const N: usize> RDesRing<N> {
     pub const fn new() -> Self {
         const RDES: RDes = RDes::new();
-        const BUFFERS: Option<PacketBox> = None;
+        const PACKET: Packet = Packet::new();
 
         Self {
             rd: [RDES; N],
-            buffers: [BUFFERS; N],
+            buffers: [PACKET; N],
             read_idx: 0,
-            next_tail_idx: 0,
         }
     }
 
     pub(crate) fn init(&mut self) {
         assert!(N > 1);
 
-        for desc in self.rd.iter_mut() {
+        for (desc, buf) in self.rd.iter_mut().zip(self.buffers.iter_mut()) {
             *desc = RDes::new();
+            desc.set_ready(buf.0.as_mut_ptr() as u32);
         }
-
-        let mut last_index = 0;
-        for (index, buf) in self.buffers.iter_mut().enumerate() {
-            let pkt = match PacketBox::new(Packet::new()) {
-                Some(p) => p,
-                None => {
-                    if index == 0 {
-                        panic!("Could not allocate at least one buffer for Ethernet receiving");
-                    } else {
-                        break;
-                    }
-                }
-            };
-            let addr = pkt.as_ptr() as u32;
-            *buf = Some(pkt);
-            self.rd[index].set_ready(addr);
-            last_index = index;
-        }
-        self.next_tail_idx = (last_index + 1) % N;
+        self.read_idx = 0;
 
         unsafe {
             let dma = ETH.ethernet_dma();
@@ -305,73 +256,76 @@ impl<const N: usize> RDesRing<N> {
             dma.dmacrx_dlar().write(|w| w.0 = self.rd.as_ptr() as u32);
             dma.dmacrx_rlr().write(|w| w.set_rdrl((N as u16) - 1));
 
-            // We manage to allocate all buffers, set the index to the last one, that means
+            // All the descriptors are ready, set the tail pointer to the last one, that means
             // that the DMA won't consider the last one as ready, because it (unfortunately)
             // stops at the tail ptr and wraps at the end of the ring, which means that we
             // can't tell it to stop after the last buffer.
-            let tail_ptr = &self.rd[last_index] as *const _ as u32;
+            let tail_ptr = &self.rd[N - 1] as *const _ as u32;
             fence(Ordering::Release);
 
             dma.dmacrx_dtpr().write(|w| w.0 = tail_ptr);
         }
     }
 
-    pub(crate) fn on_interrupt(&mut self) {
-        // XXX: Do we need to do anything here ? Maybe we should try to advance the tail ptr, but it
-        // would soon hit the read ptr anyway, and we will wake smoltcp's stack on the interrupt
-        // which should try to pop a packet...
-    }
-
-    pub(crate) fn pop_packet(&mut self) -> Option<PacketBuf> {
+    /// Return the next received packet, if any, without removing it from the ring
+    pub(crate) fn available(&mut self) -> Option<&mut [u8]> {
         // Not sure if the contents of the write buffer on the M7 can affects reads, so we are using
         // a DMB here just in case, it also serves as a hint to the compiler that we're syncing the
         // buffer (I think .-.)
         fence(Ordering::SeqCst);
 
-        let read_available = self.rd[self.read_idx].available();
-        let tail_index = (self.next_tail_idx + N - 1) % N;
+        // Skip over packets with errors, there might be valid ones after them.
+        loop {
+            let rd = &self.rd[self.read_idx];
+            if !rd.available() {
+                return None;
+            }
+            if rd.valid() {
+                break;
+            }
+            self.pop_packet();
+        }
 
-        let pkt = if read_available && self.read_idx != tail_index {
-            let pkt = self.buffers[self.read_idx].take();
-            let len = (self.rd[self.read_idx].rdes3.get() & EMAC_RDES3_PKTLEN) as usize;
+        let len = (self.rd[self.read_idx].rdes3.get() & EMAC_RDES3_PKTLEN) as usize;
+        Some(&mut self.buffers[self.read_idx].0[..len])
+    }
 
-            assert!(pkt.is_some());
-            let valid = self.rd[self.read_idx].valid();
+    /// Return the timestamp of the packet returned by `available`, if it has one
+    pub(crate) fn timestamp(&self) -> Option<PtpTimestamp> {
+        if self.rd[self.read_idx].rdes1.get() & EMAC_RDES1_TSA == 0 {
+            return None;
+        }
 
-            self.read_idx = (self.read_idx + 1) % N;
-            if valid {
-                pkt.map(|p| p.slice(0..len))
-            } else {
-                None
-            }
+        // The timestamp is written back in a context descriptor following the last descriptor of
+        // the packet. The context descriptor is skipped by `available` once the packet is popped.
+        let ctxt = &self.rd[(self.read_idx + 1) % N];
+        if ctxt.available() && ctxt.rdes3.get() & EMAC_DES3_CTXT != 0 {
+            Some(PtpTimestamp {
+                seconds: ctxt.rdes1.get() as u64,
+                nanoseconds: ctxt.rdes0.get(),
+            })
         } else {
             None
-        };
-
-        // Try to advance the tail_idx
-        if self.next_tail_idx != self.read_idx {
-            match PacketBox::new(Packet::new()) {
-                Some(b) => {
-                    let addr = b.as_ptr() as u32;
-                    self.buffers[self.next_tail_idx].replace(b);
-                    self.rd[self.next_tail_idx].set_ready(addr);
-
-                    // "Preceding reads and writes cannot be moved past subsequent writes."
-                    fence(Ordering::Release);
-
-                    // NOTE(unsafe) atomic write
-                    unsafe {
-                        ETH.ethernet_dma()
-                            .dmacrx_dtpr()
-                            .write(|w| w.0 = &self.rd[self.next_tail_idx] as *const _ as u32);
-                    }
-
-                    self.next_tail_idx = (self.next_tail_idx + 1) % N;
-                }
-                None => {}
-            }
         }
-        pkt
+    }
+
+    /// Give the buffer of the packet returned by `available` back to the DMA
+    pub(crate) fn pop_packet(&mut self) {
+        let x = self.read_idx;
+        let addr = self.buffers[x].0.as_mut_ptr() as u32;
+        self.rd[x].set_ready(addr);
+
+        // "Preceding reads and writes cannot be moved past subsequent writes."
+        fence(Ordering::Release);
+
+        // NOTE(unsafe) atomic write
+        unsafe {
+            ETH.ethernet_dma()
+                .dmacrx_dtpr()
+                .write(|w| w.0 = &self.rd[x] as *const _ as u32);
+        }
+
+        self.read_idx = (x + 1) % N;
     }
 }
 
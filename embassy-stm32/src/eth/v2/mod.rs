@@ -2,11 +2,11 @@ use core::marker::PhantomData;
 use core::sync::atomic::{fence, Ordering};
 use core::task::Waker;
 
+use embassy::interrupt::InterruptExt;
 use embassy::util::Unborrow;
 use embassy::waitqueue::AtomicWaker;
-use embassy_hal_common::peripheral::{PeripheralMutex, PeripheralState, StateStorage};
 use embassy_hal_common::unborrow;
-use embassy_net::{Device, DeviceCapabilities, LinkState, PacketBuf, MTU};
+use embassy_net::{Device, DeviceCapabilities, LinkState, PtpTimestamp};
 
 use crate::gpio::sealed::Pin as _;
 use crate::gpio::{sealed::AFType, AnyPin, Speed};
@@ -14,23 +14,31 @@ use crate::pac::{ETH, RCC, SYSCFG};
 
 mod descriptors;
 use super::*;
-use descriptors::DescriptorRing;
+use descriptors::{DescriptorRing, RDesRing, TDesRing};
 
-pub struct State<'d, T: Instance, const TX: usize, const RX: usize>(
-    StateStorage<Inner<'d, T, TX, RX>>,
-);
+pub struct State<'d, T: Instance, const TX: usize, const RX: usize> {
+    _peri: PhantomData<&'d mut T>,
+    desc_ring: DescriptorRing<TX, RX>,
+}
 impl<'d, T: Instance, const TX: usize, const RX: usize> State<'d, T, TX, RX> {
     pub fn new() -> Self {
-        Self(StateStorage::new())
+        Self {
+            _peri: PhantomData,
+            desc_ring: DescriptorRing::new(),
+        }
     }
 }
 pub struct Ethernet<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> {
-    state: PeripheralMutex<'d, Inner<'d, T, TX, RX>>,
+    _peri: PhantomData<&'d mut T>,
+    desc_ring: &'d mut DescriptorRing<TX, RX>,
+    interrupt: crate::interrupt::ETH,
     pins: [AnyPin; 9],
     _phy: P,
     clock_range: u8,
     phy_addr: u8,
     mac_addr: [u8; 6],
+    multicast: MulticastFilter,
+    ptp_addend: u32,
 }
 
 macro_rules! config_pins {
@@ -49,7 +57,7 @@ impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> Ethernet<'d, T,
     /// safety: the returned instance is not leak-safe
     pub unsafe fn new(
         state: &'d mut State<'d, T, TX, RX>,
-        peri: impl Unborrow<Target = T> + 'd,
+        _peri: impl Unborrow<Target = T> + 'd,
         interrupt: impl Unborrow<Target = crate::interrupt::ETH> + 'd,
         ref_clk: impl Unborrow<Target = impl RefClkPin<T>> + 'd,
         mdio: impl Unborrow<Target = impl MDIOPin<T>> + 'd,
@@ -82,9 +90,6 @@ impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> Ethernet<'d, T,
 
         config_pins!(ref_clk, mdio, mdc, crs, rx_d0, rx_d1, tx_d0, tx_d1, tx_en);
 
-        // NOTE(unsafe) We are ourselves not leak-safe.
-        let state = PeripheralMutex::new_unchecked(interrupt, &mut state.0, || Inner::new(peri));
-
         // NOTE(unsafe) We have exclusive access to the registers
         let dma = ETH.ethernet_dma();
         let mac = ETH.ethernet_mac();
@@ -115,6 +120,9 @@ impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> Ethernet<'d, T,
             )
         });
 
+        // Filter multicast frames with the hash table, which starts empty
+        mac.macpfr().modify(|w| w.set_hmc(true));
+
         mac.macqtx_fcr().modify(|w| w.set_pt(0x100));
 
         mtl.mtlrx_qomr().modify(|w| w.set_rsf(true));
@@ -156,44 +164,231 @@ impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> Ethernet<'d, T,
         ];
 
         let mut this = Self {
-            state,
+            _peri: PhantomData,
+            desc_ring: &mut state.desc_ring,
+            interrupt,
             pins,
             _phy: phy,
             clock_range,
             phy_addr,
             mac_addr,
+            multicast: MulticastFilter::new(),
+            ptp_addend: 0,
         };
 
-        this.state.with(|s| {
-            s.desc_ring.init();
-
-            fence(Ordering::SeqCst);
+        this.desc_ring.init();
 
-            let mac = ETH.ethernet_mac();
-            let mtl = ETH.ethernet_mtl();
-            let dma = ETH.ethernet_dma();
+        fence(Ordering::SeqCst);
 
-            mac.maccr().modify(|w| {
-                w.set_re(true);
-                w.set_te(true);
-            });
-            mtl.mtltx_qomr().modify(|w| w.set_ftq(true));
+        mac.maccr().modify(|w| {
+            w.set_re(true);
+            w.set_te(true);
+        });
+        mtl.mtltx_qomr().modify(|w| w.set_ftq(true));
 
-            dma.dmactx_cr().modify(|w| w.set_st(true));
-            dma.dmacrx_cr().modify(|w| w.set_sr(true));
+        dma.dmactx_cr().modify(|w| w.set_st(true));
+        dma.dmacrx_cr().modify(|w| w.set_sr(true));
 
-            // Enable interrupts
-            dma.dmacier().modify(|w| {
-                w.set_nie(true);
-                w.set_rie(true);
-                w.set_tie(true);
-            });
+        // Enable interrupts
+        dma.dmacier().modify(|w| {
+            w.set_nie(true);
+            w.set_rie(true);
+            w.set_tie(true);
         });
+
+        this.interrupt.set_handler(Self::on_interrupt);
+        this.interrupt.unpend();
+        this.interrupt.enable();
+
         P::phy_reset(&mut this);
         P::phy_init(&mut this);
 
         this
     }
+
+    /// Enable the PTP (IEEE 1588) timestamping unit.
+    ///
+    /// Every frame sent and received afterwards is timestamped, see
+    /// [`RxToken::timestamp`](embassy_net::RxToken::timestamp) and
+    /// [`Device::tx_timestamp`]. The PTP clock starts at zero, and runs from HCLK with fine
+    /// correction, so its frequency can be trimmed with [`Self::set_ptp_addend`].
+    pub fn enable_ptp(&mut self) {
+        // NOTE(unsafe) We got the peripheral singleton, which means that `rcc::init` was called
+        let hclk = unsafe { crate::rcc::get_freqs() }.ahb1.0;
+
+        // Tick at no more than half of HCLK, so the accumulator has room to speed the clock up.
+        let increment = (2_000_000_000 + hclk - 1) / hclk;
+        let addend = ((1u64 << 32) * 1_000_000_000 / (increment as u64 * hclk as u64)) as u32;
+
+        // NOTE(unsafe) These registers aren't used in the interrupt and we have `&mut self`
+        unsafe {
+            let mac = ETH.ethernet_mac();
+
+            mac.mactscr().modify(|w| {
+                w.set_tsena(true);
+                w.set_tsenall(true);
+                // Subseconds count nanoseconds, rolling over at 10^9.
+                w.set_tsctrlssr(true);
+            });
+            mac.macssir().write(|w| w.set_ssinc(increment as u8));
+        }
+
+        self.ptp_addend = addend;
+        self.set_ptp_addend(addend);
+
+        // NOTE(unsafe) These registers aren't used in the interrupt and we have `&mut self`
+        unsafe {
+            ETH.ethernet_mac()
+                .mactscr()
+                .modify(|w| w.set_tscfupdt(true));
+        }
+
+        self.set_ptp_time(PtpTimestamp {
+            seconds: 0,
+            nanoseconds: 0,
+        });
+    }
+
+    /// Current time of the PTP clock.
+    pub fn ptp_now(&self) -> PtpTimestamp {
+        // NOTE(unsafe) Read-only access to registers not used in the interrupt
+        unsafe {
+            let mac = ETH.ethernet_mac();
+
+            // Read the seconds again, in case the nanoseconds rolled over in between.
+            loop {
+                let seconds = mac.macstsr().read().tss();
+                let nanoseconds = mac.macstnr().read().tsss();
+                if mac.macstsr().read().tss() == seconds {
+                    return PtpTimestamp {
+                        seconds: seconds as u64,
+                        nanoseconds,
+                    };
+                }
+            }
+        }
+    }
+
+    /// Set the PTP clock.
+    ///
+    /// The clock only counts 32 bits of seconds, the upper bits of `time.seconds` are ignored.
+    pub fn set_ptp_time(&mut self, time: PtpTimestamp) {
+        assert!(time.nanoseconds < 1_000_000_000);
+
+        // NOTE(unsafe) These registers aren't used in the interrupt and we have `&mut self`
+        unsafe {
+            let mac = ETH.ethernet_mac();
+
+            mac.macstsur().write(|w| w.set_tss(time.seconds as u32));
+            mac.macstnur().write(|w| w.set_tsss(time.nanoseconds));
+            mac.mactscr().modify(|w| w.set_tsinit(true));
+            while mac.mactscr().read().tsinit() {}
+        }
+    }
+
+    /// Step the PTP clock by `offset` nanoseconds.
+    pub fn adjust_ptp_offset(&mut self, offset: i64) {
+        let magnitude = offset.unsigned_abs();
+        let seconds = (magnitude / 1_000_000_000) as u32;
+        let nanoseconds = (magnitude % 1_000_000_000) as u32;
+
+        // NOTE(unsafe) These registers aren't used in the interrupt and we have `&mut self`
+        unsafe {
+            let mac = ETH.ethernet_mac();
+
+            if offset < 0 {
+                // Subtracting takes the complement of both fields.
+                mac.macstsur().write(|w| w.set_tss(seconds.wrapping_neg()));
+                mac.macstnur().write(|w| {
+                    w.set_addsub(true);
+                    w.set_tsss(1_000_000_000 - nanoseconds);
+                });
+            } else {
+                mac.macstsur().write(|w| w.set_tss(seconds));
+                mac.macstnur().write(|w| {
+                    w.set_addsub(false);
+                    w.set_tsss(nanoseconds);
+                });
+            }
+            mac.mactscr().modify(|w| w.set_tsupdt(true));
+            while mac.mactscr().read().tsupdt() {}
+        }
+    }
+
+    /// Addend that makes the PTP clock run at its nominal rate, as computed by
+    /// [`Self::enable_ptp`].
+    pub fn ptp_nominal_addend(&self) -> u32 {
+        self.ptp_addend
+    }
+
+    /// Set the addend of the PTP clock accumulator.
+    ///
+    /// The clock advances every time the accumulator overflows, so its rate scales
+    /// linearly with `addend`.
+    pub fn set_ptp_addend(&mut self, addend: u32) {
+        // NOTE(unsafe) These registers aren't used in the interrupt and we have `&mut self`
+        unsafe {
+            let mac = ETH.ethernet_mac();
+
+            mac.mactsar().write(|w| w.set_tsar(addend));
+            mac.mactscr().modify(|w| w.set_tsaddreg(true));
+            while mac.mactscr().read().tsaddreg() {}
+        }
+    }
+
+    /// Trim the rate of the PTP clock by `ppb` parts per billion from its nominal rate.
+    pub fn adjust_ptp_frequency(&mut self, ppb: i32) {
+        let nominal = self.ptp_addend as i64;
+        let addend = nominal + nominal * ppb as i64 / 1_000_000_000;
+        self.set_ptp_addend(addend as u32);
+    }
+
+    /// Receive all frames, whatever their destination address.
+    pub fn set_promiscuous(&mut self, enabled: bool) {
+        // NOTE(unsafe) These registers aren't used in the interrupt and we have `&mut self`
+        unsafe {
+            ETH.ethernet_mac().macpfr().modify(|w| w.set_pr(enabled));
+        }
+    }
+
+    /// Receive all multicast frames, not only the ones sent to the addresses added with
+    /// [`Device::add_multicast_address`].
+    pub fn set_pass_all_multicast(&mut self, enabled: bool) {
+        // NOTE(unsafe) These registers aren't used in the interrupt and we have `&mut self`
+        unsafe {
+            ETH.ethernet_mac().macpfr().modify(|w| w.set_pm(enabled));
+        }
+    }
+
+    fn update_multicast_filter(&mut self) {
+        let table = self.multicast.table();
+
+        // NOTE(unsafe) These registers aren't used in the interrupt and we have `&mut self`
+        unsafe {
+            let mac = ETH.ethernet_mac();
+
+            mac.macht1r().write(|w| w.0 = (table >> 32) as u32);
+            mac.macht0r().write(|w| w.0 = table as u32);
+        }
+    }
+
+    fn on_interrupt(_: *mut ()) {
+        WAKER.wake();
+
+        // TODO: Check and clear more flags
+        unsafe {
+            let dma = ETH.ethernet_dma();
+
+            dma.dmacsr().modify(|w| {
+                w.set_ti(true);
+                w.set_ri(true);
+                w.set_nis(true);
+            });
+            // Delay two peripheral's clock
+            dma.dmacsr().read();
+            dma.dmacsr().read();
+        }
+    }
 }
 
 unsafe impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> StationManagement
@@ -237,16 +432,31 @@ unsafe impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> StationMa
 impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> Device
     for Ethernet<'d, T, P, TX, RX>
 {
-    fn is_transmit_ready(&mut self) -> bool {
-        self.state.with(|s| s.desc_ring.tx.available())
-    }
-
-    fn transmit(&mut self, pkt: PacketBuf) {
-        self.state.with(|s| unwrap!(s.desc_ring.tx.transmit(pkt)));
+    type RxToken<'a>
+        = RxToken<'a, RX>
+    where
+        Self: 'a;
+    type TxToken<'a>
+        = TxToken<'a, TX>
+    where
+        Self: 'a;
+
+    fn receive(&mut self) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let ring = &mut *self.desc_ring;
+        if ring.rx.available().is_some() && ring.tx.available().is_some() {
+            Some((RxToken { rx: &mut ring.rx }, TxToken { tx: &mut ring.tx }))
+        } else {
+            None
+        }
     }
 
-    fn receive(&mut self) -> Option<PacketBuf> {
-        self.state.with(|s| s.desc_ring.rx.pop_packet())
+    fn transmit(&mut self) -> Option<Self::TxToken<'_>> {
+        let ring = &mut *self.desc_ring;
+        if ring.tx.available().is_some() {
+            Some(TxToken { tx: &mut ring.tx })
+        } else {
+            None
+        }
     }
 
     fn register_waker(&mut self, waker: &Waker) {
@@ -271,12 +481,29 @@ impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> Device
     fn ethernet_address(&mut self) -> [u8; 6] {
         self.mac_addr
     }
+
+    fn add_multicast_address(&mut self, addr: [u8; 6]) {
+        self.multicast.add(&addr);
+        self.update_multicast_filter();
+    }
+
+    fn remove_multicast_address(&mut self, addr: [u8; 6]) {
+        self.multicast.remove(&addr);
+        self.update_multicast_filter();
+    }
+
+    fn tx_timestamp(&mut self) -> Option<PtpTimestamp> {
+        self.desc_ring.tx.timestamp()
+    }
 }
 
 impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> Drop
     for Ethernet<'d, T, P, TX, RX>
 {
     fn drop(&mut self) {
+        self.interrupt.disable();
+        self.interrupt.remove_handler();
+
         // NOTE(unsafe) We have `&mut self` and the interrupt doesn't use this registers
         unsafe {
             let dma = ETH.ethernet_dma();
@@ -315,42 +542,41 @@ impl<'d, T: Instance, P: PHY, const TX: usize, const RX: usize> Drop
 
 //----------------------------------------------------------------------
 
-struct Inner<'d, T: Instance, const TX: usize, const RX: usize> {
-    _peri: PhantomData<&'d mut T>,
-    desc_ring: DescriptorRing<TX, RX>,
+pub struct RxToken<'a, const RX: usize> {
+    rx: &'a mut RDesRing<RX>,
 }
 
-impl<'d, T: Instance, const TX: usize, const RX: usize> Inner<'d, T, TX, RX> {
-    pub fn new(_peri: impl Unborrow<Target = T> + 'd) -> Self {
-        Self {
-            _peri: PhantomData,
-            desc_ring: DescriptorRing::new(),
-        }
+impl<'a, const RX: usize> embassy_net::RxToken for RxToken<'a, RX> {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        // NOTE(unwrap): we checked the queue wasn't empty when creating the token.
+        let pkt = unwrap!(self.rx.available());
+        let r = f(pkt);
+        self.rx.pop_packet();
+        r
     }
-}
 
-impl<'d, T: Instance, const TX: usize, const RX: usize> PeripheralState for Inner<'d, T, TX, RX> {
-    type Interrupt = crate::interrupt::ETH;
-
-    fn on_interrupt(&mut self) {
-        unwrap!(self.desc_ring.tx.on_interrupt());
-        self.desc_ring.rx.on_interrupt();
-
-        WAKER.wake();
+    fn timestamp(&self) -> Option<PtpTimestamp> {
+        self.rx.timestamp()
+    }
+}
 
-        // TODO: Check and clear more flags
-        unsafe {
-            let dma = ETH.ethernet_dma();
+pub struct TxToken<'a, const TX: usize> {
+    tx: &'a mut TDesRing<TX>,
+}
 
-            dma.dmacsr().modify(|w| {
-                w.set_ti(true);
-                w.set_ri(true);
-                w.set_nis(true);
-            });
-            // Delay two peripheral's clock
-            dma.dmacsr().read();
-            dma.dmacsr().read();
-        }
+impl<'a, const TX: usize> embassy_net::TxToken for TxToken<'a, TX> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        // NOTE(unwrap): we checked the queue wasn't full when creating the token.
+        let pkt = unwrap!(self.tx.available());
+        let r = f(&mut pkt[..len]);
+        self.tx.transmit(len);
+        r
     }
 }
 
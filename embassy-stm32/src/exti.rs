@@ -1,10 +1,15 @@
+use core::cell::Cell;
 use core::future::Future;
 use core::marker::PhantomData;
 use core::pin::Pin;
+use core::sync::atomic::{AtomicU32, Ordering};
 use core::task::{Context, Poll};
+use embassy::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy::blocking_mutex::Mutex;
 use embassy::util::Unborrow;
 use embassy::waitqueue::AtomicWaker;
 use embassy_hal_common::unsafe_impl_unborrow;
+use futures::future::poll_fn;
 
 use crate::gpio::{AnyPin, Input, Pin as GpioPin};
 use crate::interrupt;
@@ -17,6 +22,62 @@ const EXTI_COUNT: usize = 16;
 const NEW_AW: AtomicWaker = AtomicWaker::new();
 static EXTI_WAKERS: [AtomicWaker; EXTI_COUNT] = [NEW_AW; EXTI_COUNT];
 
+/// Lines read through an [`EdgeStream`]: they stay unmasked, and their edges are queued.
+static EXTI_STREAMS: AtomicU32 = AtomicU32::new(0);
+const NEW_QUEUE: Mutex<CriticalSectionRawMutex, Cell<EdgeQueue>> =
+    Mutex::const_new(CriticalSectionRawMutex::new(), Cell::new(EdgeQueue::new()));
+static EXTI_QUEUES: [Mutex<CriticalSectionRawMutex, Cell<EdgeQueue>>; EXTI_COUNT] =
+    [NEW_QUEUE; EXTI_COUNT];
+
+/// Number of edges an [`EdgeStream`] holds until they're read.
+pub const EDGE_QUEUE_LEN: usize = 32;
+
+/// Edges of a line, one bit each: set for a rising edge.
+#[derive(Clone, Copy)]
+struct EdgeQueue {
+    edges: u32,
+    head: u8,
+    len: u8,
+    overflow: bool,
+}
+
+impl EdgeQueue {
+    const fn new() -> Self {
+        Self {
+            edges: 0,
+            head: 0,
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    fn push(&mut self, edge: Edge) {
+        if self.len as usize == EDGE_QUEUE_LEN {
+            self.overflow = true;
+            return;
+        }
+        let bit = (self.head + self.len) as usize % EDGE_QUEUE_LEN;
+        self.edges &= !(1 << bit);
+        self.edges |= ((edge == Edge::Rising) as u32) << bit;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<Result<Edge, Overflow>> {
+        if self.len == 0 {
+            // The edges lost come after the ones queued.
+            if self.overflow {
+                self.overflow = false;
+                return Some(Err(Overflow));
+            }
+            return None;
+        }
+        let rising = self.edges & (1 << self.head) != 0;
+        self.head = (self.head + 1) % EDGE_QUEUE_LEN as u8;
+        self.len -= 1;
+        Some(Ok(if rising { Edge::Rising } else { Edge::Falling }))
+    }
+}
+
 #[cfg(exti_w)]
 fn cpu_regs() -> pac::exti::Cpu {
     EXTI.cpu(crate::pac::CORE_INDEX)
@@ -46,8 +107,17 @@ pub unsafe fn on_irq() {
     #[cfg(any(exti_g0, exti_l5, exti_u5))]
     let bits = EXTI.rpr(0).read().0 | EXTI.fpr(0).read().0;
 
-    // Mask all the channels that fired.
-    cpu_regs().imr(0).modify(|w| w.0 &= !bits);
+    // Queue the edges of the streamed channels, and mask all the others that fired.
+    let streams = bits & EXTI_STREAMS.load(Ordering::Relaxed);
+    cpu_regs().imr(0).modify(|w| w.0 &= !(bits & !streams));
+    for pin in BitIter(streams) {
+        let edge = edge_of(pin as usize);
+        EXTI_QUEUES[pin as usize].lock(|q| {
+            let mut queue = q.get();
+            queue.push(edge);
+            q.set(queue);
+        });
+    }
 
     // Wake the tasks
     for pin in BitIter(bits) {
@@ -64,6 +134,21 @@ pub unsafe fn on_irq() {
     }
 }
 
+/// The edge that just happened on a line, from the level of the pin it's connected to.
+///
+/// If the pin toggled again since, before the interrupt was handled, this is the last of the
+/// edges: a pulse shorter than the interrupt latency is seen as two edges in the same
+/// direction.
+unsafe fn edge_of(pin: usize) -> Edge {
+    let port = exticr_regs().exticr(pin / 4).read().exti(pin % 4);
+    let level = pac::GPIO(port as _).idr().read().idr(pin);
+    if level == pac::gpio::vals::Idr::LOW {
+        Edge::Falling
+    } else {
+        Edge::Rising
+    }
+}
+
 struct BitIter(u32);
 
 impl Iterator for BitIter {
@@ -127,6 +212,124 @@ impl<'d, T: GpioPin> ExtiInput<'d, T> {
     pub async fn wait_for_any_edge<'a>(&'a mut self) {
         ExtiInputFuture::new(self.pin.pin.pin(), self.pin.pin.port(), true, true).await
     }
+
+    /// Wait for an edge, for at most `timeout`.
+    #[cfg(feature = "_time-driver")]
+    pub async fn wait_for_any_edge_with_timeout<'a>(
+        &'a mut self,
+        timeout: embassy::time::Duration,
+    ) -> Result<(), embassy::time::TimeoutError> {
+        embassy::time::with_timeout(timeout, self.wait_for_any_edge()).await
+    }
+
+    /// Future completing on the next rising edge.
+    ///
+    /// Unlike the `wait_for_*` methods, the futures of all the inputs have the same type, and
+    /// can be waited for together with [`select_all`](embassy::util::select_all), which
+    /// returns the index of the input that fired first:
+    ///
+    /// ```ignore
+    /// let (_, i) = select_all([a.rising_edge(), b.rising_edge(), c.falling_edge()]).await;
+    /// ```
+    pub fn rising_edge(&mut self) -> ExtiInputFuture<'_> {
+        ExtiInputFuture::new(self.pin.pin.pin(), self.pin.pin.port(), true, false)
+    }
+
+    /// Future completing on the next falling edge. See [`rising_edge`](Self::rising_edge).
+    pub fn falling_edge(&mut self) -> ExtiInputFuture<'_> {
+        ExtiInputFuture::new(self.pin.pin.pin(), self.pin.pin.port(), false, true)
+    }
+
+    /// Future completing on the next edge. See [`rising_edge`](Self::rising_edge).
+    pub fn any_edge(&mut self) -> ExtiInputFuture<'_> {
+        ExtiInputFuture::new(self.pin.pin.pin(), self.pin.pin.port(), true, true)
+    }
+
+    /// Stream of all the edges of the input.
+    ///
+    /// The line stays enabled while the stream exists, so no edge is missed between two
+    /// reads, as long as the queue of [`EDGE_QUEUE_LEN`] edges doesn't fill up. This is what
+    /// debouncing a button or decoding a quadrature encoder needs.
+    pub fn edge_stream(&mut self) -> EdgeStream<'_, 'd, T> {
+        EdgeStream::new(self)
+    }
+}
+
+/// Direction of an edge.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Edge {
+    Rising,
+    Falling,
+}
+
+/// The edge queue of an [`EdgeStream`] was full, and edges were lost.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Overflow;
+
+/// Stream of the edges of an [`ExtiInput`], see [`ExtiInput::edge_stream`].
+///
+/// Edges are reported in order. When edges were lost because the queue was full,
+/// [`Overflow`] is reported once, after the edges received before it.
+pub struct EdgeStream<'a, 'd, T: GpioPin> {
+    input: &'a mut ExtiInput<'d, T>,
+}
+
+impl<'a, 'd, T: GpioPin> EdgeStream<'a, 'd, T> {
+    fn new(input: &'a mut ExtiInput<'d, T>) -> Self {
+        let pin = input.pin.pin.pin() as usize;
+        let port = input.pin.pin.port();
+        critical_section::with(|_| unsafe {
+            EXTI_QUEUES[pin].lock(|q| q.set(EdgeQueue::new()));
+            EXTI_STREAMS.fetch_or(1 << pin, Ordering::Relaxed);
+            configure(pin, port, true, true);
+        });
+        Self { input }
+    }
+
+    /// Wait for the next edge.
+    pub async fn next(&mut self) -> Result<Edge, Overflow> {
+        poll_fn(|cx| self.poll_edge(cx)).await
+    }
+
+    /// Take the next edge if there's one already.
+    pub fn try_next(&mut self) -> Option<Result<Edge, Overflow>> {
+        let pin = self.input.pin.pin.pin() as usize;
+        EXTI_QUEUES[pin].lock(|q| {
+            let mut queue = q.get();
+            let edge = queue.pop();
+            q.set(queue);
+            edge
+        })
+    }
+
+    fn poll_edge(&mut self, cx: &mut Context<'_>) -> Poll<Result<Edge, Overflow>> {
+        let pin = self.input.pin.pin.pin() as usize;
+        EXTI_WAKERS[pin].register(cx.waker());
+        match self.try_next() {
+            Some(edge) => Poll::Ready(edge),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<'a, 'd, T: GpioPin> Drop for EdgeStream<'a, 'd, T> {
+    fn drop(&mut self) {
+        let pin = self.input.pin.pin.pin() as usize;
+        critical_section::with(|_| unsafe {
+            cpu_regs().imr(0).modify(|w| w.set_line(pin, false));
+            EXTI_STREAMS.fetch_and(!(1 << pin), Ordering::Relaxed);
+        });
+    }
+}
+
+impl<'a, 'd, T: GpioPin> futures::Stream for EdgeStream<'a, 'd, T> {
+    type Item = Result<Edge, Overflow>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().poll_edge(cx).map(Some)
+    }
 }
 
 mod eh02 {
@@ -203,32 +406,37 @@ cfg_if::cfg_if! {
     }
 }
 
-struct ExtiInputFuture<'a> {
+/// Connect a line to `port`, select its edges, and unmask it.
+///
+/// Must be called in a critical section.
+unsafe fn configure(pin: usize, port: u8, rising: bool, falling: bool) {
+    exticr_regs()
+        .exticr(pin / 4)
+        .modify(|w| w.set_exti(pin % 4, port));
+    EXTI.rtsr(0).modify(|w| w.set_line(pin, rising));
+    EXTI.ftsr(0).modify(|w| w.set_line(pin, falling));
+
+    // clear pending bit
+    #[cfg(not(any(exti_g0, exti_l5, exti_u5)))]
+    EXTI.pr(0).write(|w| w.set_line(pin, true));
+    #[cfg(any(exti_g0, exti_l5, exti_u5))]
+    {
+        EXTI.rpr(0).write(|w| w.set_line(pin, true));
+        EXTI.fpr(0).write(|w| w.set_line(pin, true));
+    }
+
+    cpu_regs().imr(0).modify(|w| w.set_line(pin, true));
+}
+
+/// Future waiting for an edge on an [`ExtiInput`], see [`ExtiInput::rising_edge`].
+pub struct ExtiInputFuture<'a> {
     pin: u8,
     phantom: PhantomData<&'a mut AnyPin>,
 }
 
 impl<'a> ExtiInputFuture<'a> {
     fn new(pin: u8, port: u8, rising: bool, falling: bool) -> Self {
-        critical_section::with(|_| unsafe {
-            let pin = pin as usize;
-            exticr_regs()
-                .exticr(pin / 4)
-                .modify(|w| w.set_exti(pin % 4, port));
-            EXTI.rtsr(0).modify(|w| w.set_line(pin, rising));
-            EXTI.ftsr(0).modify(|w| w.set_line(pin, falling));
-
-            // clear pending bit
-            #[cfg(not(any(exti_g0, exti_l5, exti_u5)))]
-            EXTI.pr(0).write(|w| w.set_line(pin, true));
-            #[cfg(any(exti_g0, exti_l5, exti_u5))]
-            {
-                EXTI.rpr(0).write(|w| w.set_line(pin, true));
-                EXTI.fpr(0).write(|w| w.set_line(pin, true));
-            }
-
-            cpu_regs().imr(0).modify(|w| w.set_line(pin, true));
-        });
+        critical_section::with(|_| unsafe { configure(pin as usize, port, rising, falling) });
 
         Self {
             pin,
@@ -7,6 +7,7 @@ use crate::gpio::{Pull, Speed};
 
 mod pins;
 pub use pins::*;
+pub mod sram;
 
 pub struct Fmc<'d, T: Instance> {
     peri: PhantomData<&'d mut T>,
@@ -85,6 +86,38 @@ macro_rules! fmc_sdram_constructor {
     };
 }
 
+macro_rules! fmc_sram_constructor {
+    ($name:ident: (
+        bank: $bank:expr,
+        width: $width:expr,
+        addr: [$(($addr_pin_name:ident: $addr_signal:ident)),*],
+        d: [$(($d_pin_name:ident: $d_signal:ident)),*],
+        nbl: [$(($nbl_pin_name:ident: $nbl_signal:ident)),*],
+        ctrl: [$(($ctrl_pin_name:ident: $ctrl_signal:ident)),*]
+    )) => {
+        pub fn $name(
+            _instance: impl Unborrow<Target = T> + 'd,
+            $($addr_pin_name: impl Unborrow<Target = impl $addr_signal<T>> + 'd),*,
+            $($d_pin_name: impl Unborrow<Target = impl $d_signal<T>> + 'd),*,
+            $($nbl_pin_name: impl Unborrow<Target = impl $nbl_signal<T>> + 'd),*,
+            $($ctrl_pin_name: impl Unborrow<Target = impl $ctrl_signal<T>> + 'd),*,
+            config: sram::Config
+        ) -> Result<sram::Sram<'d, T>, sram::Error> {
+
+        critical_section::with(|_| unsafe {
+            config_pins!(
+                $($addr_pin_name),*,
+                $($d_pin_name),*,
+                $($nbl_pin_name),*,
+                $($ctrl_pin_name),*
+            );
+        });
+
+            sram::Sram::new_inner($bank, $width, config)
+        }
+    };
+}
+
 impl<'d, T: Instance> Fmc<'d, T> {
     fmc_sdram_constructor!(sdram_a12bits_d32bits_4banks_bank1: (
         bank: stm32_fmc::SdramTargetBank::Bank1,
@@ -125,6 +158,66 @@ impl<'d, T: Instance> Fmc<'d, T> {
             (sdcke: SDCKE1Pin), (sdclk: SDCLKPin), (sdncas: SDNCASPin), (sdne: SDNE1Pin), (sdnras: SDNRASPin), (sdnwe: SDNWEPin)
         ]
     ));
+
+    fmc_sram_constructor!(sram_a19bits_d16bits_bank1: (
+        bank: sram::Bank::Bank1,
+        width: sram::DataWidth::Bits16,
+        addr: [
+            (a0: A0Pin), (a1: A1Pin), (a2: A2Pin), (a3: A3Pin), (a4: A4Pin), (a5: A5Pin), (a6: A6Pin), (a7: A7Pin), (a8: A8Pin), (a9: A9Pin),
+            (a10: A10Pin), (a11: A11Pin), (a12: A12Pin), (a13: A13Pin), (a14: A14Pin), (a15: A15Pin), (a16: A16Pin), (a17: A17Pin), (a18: A18Pin)
+        ],
+        d: [
+            (d0: D0Pin), (d1: D1Pin), (d2: D2Pin), (d3: D3Pin), (d4: D4Pin), (d5: D5Pin), (d6: D6Pin), (d7: D7Pin),
+            (d8: D8Pin), (d9: D9Pin), (d10: D10Pin), (d11: D11Pin), (d12: D12Pin), (d13: D13Pin), (d14: D14Pin), (d15: D15Pin)
+        ],
+        nbl: [(nbl0: NBL0Pin), (nbl1: NBL1Pin)],
+        ctrl: [(noe: NOEPin), (nwe: NWEPin), (ne: NE1Pin)]
+    ));
+
+    fmc_sram_constructor!(sram_a19bits_d16bits_bank2: (
+        bank: sram::Bank::Bank2,
+        width: sram::DataWidth::Bits16,
+        addr: [
+            (a0: A0Pin), (a1: A1Pin), (a2: A2Pin), (a3: A3Pin), (a4: A4Pin), (a5: A5Pin), (a6: A6Pin), (a7: A7Pin), (a8: A8Pin), (a9: A9Pin),
+            (a10: A10Pin), (a11: A11Pin), (a12: A12Pin), (a13: A13Pin), (a14: A14Pin), (a15: A15Pin), (a16: A16Pin), (a17: A17Pin), (a18: A18Pin)
+        ],
+        d: [
+            (d0: D0Pin), (d1: D1Pin), (d2: D2Pin), (d3: D3Pin), (d4: D4Pin), (d5: D5Pin), (d6: D6Pin), (d7: D7Pin),
+            (d8: D8Pin), (d9: D9Pin), (d10: D10Pin), (d11: D11Pin), (d12: D12Pin), (d13: D13Pin), (d14: D14Pin), (d15: D15Pin)
+        ],
+        nbl: [(nbl0: NBL0Pin), (nbl1: NBL1Pin)],
+        ctrl: [(noe: NOEPin), (nwe: NWEPin), (ne: NE2Pin)]
+    ));
+
+    fmc_sram_constructor!(sram_a19bits_d16bits_bank3: (
+        bank: sram::Bank::Bank3,
+        width: sram::DataWidth::Bits16,
+        addr: [
+            (a0: A0Pin), (a1: A1Pin), (a2: A2Pin), (a3: A3Pin), (a4: A4Pin), (a5: A5Pin), (a6: A6Pin), (a7: A7Pin), (a8: A8Pin), (a9: A9Pin),
+            (a10: A10Pin), (a11: A11Pin), (a12: A12Pin), (a13: A13Pin), (a14: A14Pin), (a15: A15Pin), (a16: A16Pin), (a17: A17Pin), (a18: A18Pin)
+        ],
+        d: [
+            (d0: D0Pin), (d1: D1Pin), (d2: D2Pin), (d3: D3Pin), (d4: D4Pin), (d5: D5Pin), (d6: D6Pin), (d7: D7Pin),
+            (d8: D8Pin), (d9: D9Pin), (d10: D10Pin), (d11: D11Pin), (d12: D12Pin), (d13: D13Pin), (d14: D14Pin), (d15: D15Pin)
+        ],
+        nbl: [(nbl0: NBL0Pin), (nbl1: NBL1Pin)],
+        ctrl: [(noe: NOEPin), (nwe: NWEPin), (ne: NE3Pin)]
+    ));
+
+    fmc_sram_constructor!(sram_a19bits_d16bits_bank4: (
+        bank: sram::Bank::Bank4,
+        width: sram::DataWidth::Bits16,
+        addr: [
+            (a0: A0Pin), (a1: A1Pin), (a2: A2Pin), (a3: A3Pin), (a4: A4Pin), (a5: A5Pin), (a6: A6Pin), (a7: A7Pin), (a8: A8Pin), (a9: A9Pin),
+            (a10: A10Pin), (a11: A11Pin), (a12: A12Pin), (a13: A13Pin), (a14: A14Pin), (a15: A15Pin), (a16: A16Pin), (a17: A17Pin), (a18: A18Pin)
+        ],
+        d: [
+            (d0: D0Pin), (d1: D1Pin), (d2: D2Pin), (d3: D3Pin), (d4: D4Pin), (d5: D5Pin), (d6: D6Pin), (d7: D7Pin),
+            (d8: D8Pin), (d9: D9Pin), (d10: D10Pin), (d11: D11Pin), (d12: D12Pin), (d13: D13Pin), (d14: D14Pin), (d15: D15Pin)
+        ],
+        nbl: [(nbl0: NBL0Pin), (nbl1: NBL1Pin)],
+        ctrl: [(noe: NOEPin), (nwe: NWEPin), (ne: NE4Pin)]
+    ));
 }
 
 foreach_peripheral!(
@@ -0,0 +1,312 @@
+//! Asynchronous SRAM, PSRAM and NOR flash on the NOR/PSRAM controller banks.
+//!
+//! Each of the four banks is selected by its own NEx pin, and maps 64 MiB at
+//! `0x6000_0000 + bank * 0x0400_0000`. Timings are given in nanoseconds, as found in the
+//! memory datasheet, and converted to FMC kernel clock cycles when the bank is configured.
+//!
+//! Displays with an Intel 8080 style parallel interface can be driven as a SRAM as well: the
+//! data/command line is wired to one of the address lines.
+
+use core::marker::PhantomData;
+
+use super::Instance;
+
+/// One of the four NOR/PSRAM banks, selected by the matching NEx pin.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Bank {
+    Bank1,
+    Bank2,
+    Bank3,
+    Bank4,
+}
+
+impl Bank {
+    fn index(&self) -> usize {
+        match self {
+            Bank::Bank1 => 0,
+            Bank::Bank2 => 1,
+            Bank::Bank3 => 2,
+            Bank::Bank4 => 3,
+        }
+    }
+
+    /// Address the bank is mapped at.
+    pub fn base_address(&self) -> usize {
+        0x6000_0000 + self.index() * BANK_SIZE
+    }
+}
+
+/// Size of the region mapped for each bank.
+pub const BANK_SIZE: usize = 0x0400_0000;
+
+/// Kind of memory connected to a bank.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MemoryType {
+    Sram,
+    Psram,
+    /// NOR flash. Writes are commands to the flash, the memory can't be written like RAM.
+    Nor,
+}
+
+/// Width of the data bus.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataWidth {
+    Bits8,
+    Bits16,
+    Bits32,
+}
+
+/// Extended access modes, which mostly differ by how NOE and NADV are driven.
+///
+/// See the reference manual for the waveforms. [`AccessMode::A`] fits most SRAMs and displays.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AccessMode {
+    A,
+    B,
+    C,
+    D,
+}
+
+/// Access timings, in nanoseconds.
+///
+/// Each value is rounded up to a whole number of FMC kernel clock cycles.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timings {
+    address_setup: u32,
+    address_hold: u32,
+    data_setup: u32,
+    bus_turnaround: u32,
+}
+
+impl Timings {
+    /// Timings with the given address setup and data setup times, and no address hold or
+    /// bus turnaround time.
+    pub const fn new(address_setup_ns: u32, data_setup_ns: u32) -> Self {
+        Self {
+            address_setup: address_setup_ns,
+            address_hold: 0,
+            data_setup: data_setup_ns,
+            bus_turnaround: 0,
+        }
+    }
+
+    /// Set the address hold time, only used by the access mode D.
+    pub const fn address_hold(mut self, ns: u32) -> Self {
+        self.address_hold = ns;
+        self
+    }
+
+    /// Set the time between two accesses, for memories which are slow to release the bus.
+    pub const fn bus_turnaround(mut self, ns: u32) -> Self {
+        self.bus_turnaround = ns;
+        self
+    }
+
+    fn to_cycles(&self, kernel_clock: u32) -> Result<Cycles, Error> {
+        let cycles = |ns: u32, min: u32, max: u32| {
+            let c = ns_to_cycles(ns, kernel_clock).max(min);
+            if c > max {
+                Err(Error::TimingOutOfRange)
+            } else {
+                Ok(c as u8)
+            }
+        };
+
+        Ok(Cycles {
+            address_setup: cycles(self.address_setup, 0, 15)?,
+            address_hold: cycles(self.address_hold, 1, 15)?,
+            data_setup: cycles(self.data_setup, 1, 255)?,
+            bus_turnaround: cycles(self.bus_turnaround, 0, 15)?,
+        })
+    }
+}
+
+fn ns_to_cycles(ns: u32, hz: u32) -> u32 {
+    let cycles = (ns as u64 * hz as u64 + 999_999_999) / 1_000_000_000;
+    cycles.min(u32::MAX as u64) as u32
+}
+
+struct Cycles {
+    address_setup: u8,
+    address_hold: u8,
+    data_setup: u8,
+    bus_turnaround: u8,
+}
+
+#[non_exhaustive]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    pub memory_type: MemoryType,
+    /// Size of the memory, in bytes. At most [`BANK_SIZE`].
+    pub size: usize,
+    pub access_mode: AccessMode,
+    pub read_timings: Timings,
+    /// Separate timings for writes. If `None`, the read timings are used for writes too.
+    pub write_timings: Option<Timings>,
+    /// Allow writes to the bank. Writes to a write protected bank cause a bus fault.
+    pub write_enable: bool,
+}
+
+impl Default for Config {
+    /// A 1 MiB, 10ns SRAM.
+    fn default() -> Self {
+        Self {
+            memory_type: MemoryType::Sram,
+            size: 1024 * 1024,
+            access_mode: AccessMode::A,
+            read_timings: Timings::new(0, 10),
+            write_timings: None,
+            write_enable: true,
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// A timing doesn't fit in the FMC registers at the current kernel clock.
+    TimingOutOfRange,
+    /// The memory is larger than the bank.
+    SizeTooLarge,
+}
+
+/// A memory mapped in one of the NOR/PSRAM banks.
+pub struct Sram<'d, T: Instance> {
+    bank: Bank,
+    size: usize,
+    phantom: PhantomData<&'d mut T>,
+}
+
+impl<'d, T: Instance> Sram<'d, T> {
+    /// Configure and enable a bank. The pins must have been configured already.
+    pub(super) fn new_inner(bank: Bank, width: DataWidth, config: Config) -> Result<Self, Error> {
+        if config.size > BANK_SIZE {
+            return Err(Error::SizeTooLarge);
+        }
+
+        // The FMC may already be running an SDRAM, don't reset it.
+        <T as crate::rcc::sealed::RccPeripheral>::enable();
+        let kernel_clock = <T as crate::rcc::sealed::RccPeripheral>::frequency().0;
+
+        let read = config.read_timings.to_cycles(kernel_clock)?;
+        let write = match config.write_timings {
+            Some(t) => Some(t.to_cycles(kernel_clock)?),
+            None => None,
+        };
+
+        let mtyp = match config.memory_type {
+            MemoryType::Sram => 0b00,
+            MemoryType::Psram => 0b01,
+            MemoryType::Nor => 0b10,
+        };
+        let mwid = match width {
+            DataWidth::Bits8 => 0b00,
+            DataWidth::Bits16 => 0b01,
+            DataWidth::Bits32 => 0b10,
+        };
+        let accmod = match config.access_mode {
+            AccessMode::A => 0b00,
+            AccessMode::B => 0b01,
+            AccessMode::C => 0b10,
+            AccessMode::D => 0b11,
+        };
+
+        let r = T::regs();
+        let i = bank.index();
+
+        macro_rules! write_bcr {
+            ($w:ident) => {
+                $w.set_mbken(true);
+                $w.set_muxen(false);
+                $w.set_mtyp(mtyp);
+                $w.set_mwid(mwid);
+                $w.set_faccen(config.memory_type == MemoryType::Nor);
+                $w.set_bursten(false);
+                $w.set_waiten(false);
+                $w.set_asyncwait(false);
+                $w.set_wren(config.write_enable);
+                $w.set_extmod(write.is_some());
+            };
+        }
+
+        unsafe {
+            r.btr(i).write(|w| {
+                w.set_addset(read.address_setup);
+                w.set_addhld(read.address_hold);
+                w.set_datast(read.data_setup);
+                w.set_busturn(read.bus_turnaround);
+                w.set_accmod(accmod);
+            });
+            if let Some(write) = &write {
+                r.bwtr(i).write(|w| {
+                    w.set_addset(write.address_setup);
+                    w.set_addhld(write.address_hold);
+                    w.set_datast(write.data_setup);
+                    w.set_busturn(write.bus_turnaround);
+                    w.set_accmod(accmod);
+                });
+            }
+
+            // BCR1 has the global FMC enable bit, the other banks have their own register type.
+            match bank {
+                Bank::Bank1 => r.bcr1().modify(|w| {
+                    write_bcr!(w);
+                }),
+                _ => r.bcr(i - 1).modify(|w| {
+                    write_bcr!(w);
+                }),
+            }
+            r.bcr1().modify(|w| w.set_fmcen(true));
+        }
+
+        Ok(Self {
+            bank,
+            size: config.size,
+            phantom: PhantomData,
+        })
+    }
+
+    /// The bank the memory is mapped in.
+    pub fn bank(&self) -> Bank {
+        self.bank
+    }
+
+    /// Size of the memory, in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Pointer to the start of the memory.
+    ///
+    /// Displays should be accessed through this pointer with volatile reads and writes.
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.bank.base_address() as *mut u8
+    }
+
+    /// The whole memory, as a slice.
+    ///
+    /// The contents are whatever the memory held, nothing is cleared.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        // Safety: the bank is configured and owned by `self`, so nothing else accesses it.
+        unsafe { core::slice::from_raw_parts_mut(self.as_ptr(), self.size) }
+    }
+}
+
+impl<'d, T: Instance> Drop for Sram<'d, T> {
+    fn drop(&mut self) {
+        let r = T::regs();
+        unsafe {
+            match self.bank {
+                Bank::Bank1 => r.bcr1().modify(|w| w.set_mbken(false)),
+                _ => r.bcr(self.bank.index() - 1).modify(|w| w.set_mbken(false)),
+            }
+        }
+    }
+}
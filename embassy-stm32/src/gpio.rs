@@ -599,6 +599,59 @@ foreach_pin!(
     };
 );
 
+/// Set the pull applied to `pin` in STANDBY and SHUTDOWN modes, where the GPIO registers
+/// lose their content and the pins are otherwise floating.
+///
+/// The pulls of all pins only apply once enabled with [`enable_standby_pulls`]. This doesn't
+/// affect the pin in the other modes: configure it before giving the pin to a driver.
+#[cfg(any(stm32l4, stm32l5, stm32g0, stm32g4, stm32wb, stm32wl))]
+pub fn set_standby_pull(pin: &impl Pin, pull: Pull) {
+    let port = pin.port() as usize;
+    let n = pin.pin() as usize;
+    unsafe {
+        pac::PWR
+            .pucr(port)
+            .modify(|w| w.set_pu(n, pull == Pull::Up));
+        pac::PWR
+            .pdcr(port)
+            .modify(|w| w.set_pd(n, pull == Pull::Down));
+    }
+}
+
+/// Apply the pulls set with [`set_standby_pull`] in STANDBY and SHUTDOWN modes.
+#[cfg(any(stm32l4, stm32l5, stm32g0, stm32g4, stm32wb, stm32wl))]
+pub fn enable_standby_pulls(enabled: bool) {
+    unsafe { pac::PWR.cr3().modify(|w| w.set_apc(enabled)) }
+}
+
+/// Pads with an analog-only twin, named `Pxy_C`, on some H7 packages.
+#[cfg(stm32h7)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AnalogPad {
+    PA0,
+    PA1,
+    PC2,
+    PC3,
+}
+
+/// Open or close the analog switch between a pad and its `Pxy_C` twin.
+///
+/// Closed, the two pads are connected, and the analog inputs of `Pxy_C` can also be reached
+/// through `Pxy`. Open, `Pxy_C` is only an analog input, and `Pxy` can be used as a GPIO
+/// independently. The switches are closed at reset.
+#[cfg(stm32h7)]
+pub fn set_analog_switch(pad: AnalogPad, closed: bool) {
+    unsafe {
+        pac::SYSCFG.pmcr().modify(|w| match pad {
+            AnalogPad::PA0 => w.set_pa0so(!closed),
+            AnalogPad::PA1 => w.set_pa1so(!closed),
+            AnalogPad::PC2 => w.set_pc2so(!closed),
+            AnalogPad::PC3 => w.set_pc3so(!closed),
+        })
+    }
+}
+
 pub(crate) unsafe fn init() {
     crate::_generated::init_gpio();
 }
@@ -5,6 +5,9 @@ use embassy::interrupt::Interrupt;
 #[cfg_attr(i2c_v1, path = "v1.rs")]
 #[cfg_attr(i2c_v2, path = "v2.rs")]
 mod _version;
+use crate::gpio::sealed::Pin as _;
+use crate::gpio::AnyPin;
+use crate::pac::gpio::vals as gpio_vals;
 use crate::peripherals;
 pub use _version::*;
 
@@ -14,12 +17,81 @@ pub enum Error {
     Bus,
     Arbitration,
     Nack,
+    /// The transaction didn't complete in time.
     Timeout,
+    /// A slave held SCL low for longer than the SMBus timeout.
+    SmbusTimeout,
+    /// SDA is still held low after trying to recover the bus.
+    BusStuck,
     Crc,
     Overrun,
     ZeroLengthTransfer,
 }
 
+/// Default timeout of a transaction.
+#[cfg(feature = "_time-driver")]
+const DEFAULT_TIMEOUT: embassy::time::Duration = embassy::time::Duration::from_millis(1000);
+
+/// Deadline of a transaction. Without a time driver, transactions never time out.
+#[derive(Clone, Copy)]
+struct Deadline {
+    #[cfg(feature = "_time-driver")]
+    at: embassy::time::Instant,
+}
+
+impl Deadline {
+    fn check(&self) -> Result<(), Error> {
+        #[cfg(feature = "_time-driver")]
+        if embassy::time::Instant::now() > self.at {
+            return Err(Error::Timeout);
+        }
+        Ok(())
+    }
+
+    #[cfg(all(i2c_v2, feature = "_time-driver"))]
+    async fn wait<F: core::future::Future>(&self, fut: F) -> Result<F::Output, Error> {
+        let remaining = self
+            .at
+            .saturating_duration_since(embassy::time::Instant::now());
+        embassy::time::with_timeout(remaining, fut)
+            .await
+            .map_err(|_| Error::Timeout)
+    }
+
+    #[cfg(all(i2c_v2, not(feature = "_time-driver")))]
+    async fn wait<F: core::future::Future>(&self, fut: F) -> Result<F::Output, Error> {
+        Ok(fut.await)
+    }
+}
+
+/// Drive `pin` as an open drain GPIO output, released high, to recover the bus by hand.
+unsafe fn set_as_open_drain_output(pin: &AnyPin) {
+    let n = pin._pin() as usize;
+    let block = pin.block();
+    pin.set_high();
+    #[cfg(gpio_v1)]
+    {
+        let crlh = if n < 8 { 0 } else { 1 };
+        block.cr(crlh).modify(|w| {
+            w.set_mode(n % 8, gpio_vals::Mode::OUTPUT50MHZ);
+            w.set_cnf_out(n % 8, gpio_vals::CnfOut::OPENDRAIN);
+        });
+    }
+    #[cfg(gpio_v2)]
+    {
+        block
+            .otyper()
+            .modify(|w| w.set_ot(n, gpio_vals::Ot::OPENDRAIN));
+        block
+            .moder()
+            .modify(|w| w.set_moder(n, gpio_vals::Moder::OUTPUT));
+    }
+}
+
+fn is_high(pin: &AnyPin) -> bool {
+    unsafe { pin.block().idr().read().idr(pin._pin() as _) == gpio_vals::Idr::HIGH }
+}
+
 pub(crate) mod sealed {
     use super::*;
     pub trait Instance: crate::rcc::RccPeripheral {
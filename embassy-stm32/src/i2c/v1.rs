@@ -2,8 +2,11 @@ use core::marker::PhantomData;
 use embassy::util::Unborrow;
 use embassy_hal_common::unborrow;
 
-use crate::gpio::sealed::AFType;
-use crate::i2c::{Error, Instance, SclPin, SdaPin};
+use crate::gpio::sealed::{AFType, Pin as _};
+use crate::gpio::{AnyPin, Pin};
+#[cfg(feature = "_time-driver")]
+use crate::i2c::DEFAULT_TIMEOUT;
+use crate::i2c::{is_high, set_as_open_drain_output, Deadline, Error, Instance, SclPin, SdaPin};
 use crate::pac::i2c;
 use crate::time::Hertz;
 
@@ -17,6 +20,12 @@ impl State {
 
 pub struct I2c<'d, T: Instance> {
     phantom: PhantomData<&'d mut T>,
+    scl: AnyPin,
+    sda: AnyPin,
+    scl_af: u8,
+    sda_af: u8,
+    #[cfg(feature = "_time-driver")]
+    timeout: embassy::time::Duration,
 }
 
 impl<'d, T: Instance> I2c<'d, T> {
@@ -34,9 +43,11 @@ impl<'d, T: Instance> I2c<'d, T> {
         T::enable();
         T::reset();
 
+        let scl_af = scl.af_num();
+        let sda_af = sda.af_num();
         unsafe {
-            scl.set_as_af(scl.af_num(), AFType::OutputOpenDrain);
-            sda.set_as_af(sda.af_num(), AFType::OutputOpenDrain);
+            scl.set_as_af(scl_af, AFType::OutputOpenDrain);
+            sda.set_as_af(sda_af, AFType::OutputOpenDrain);
         }
 
         unsafe {
@@ -70,9 +81,102 @@ impl<'d, T: Instance> I2c<'d, T> {
 
         Self {
             phantom: PhantomData,
+            scl: scl.degrade(),
+            sda: sda.degrade(),
+            scl_af,
+            sda_af,
+            #[cfg(feature = "_time-driver")]
+            timeout: DEFAULT_TIMEOUT,
         }
     }
 
+    /// Set the timeout of the transactions, 1 second by default.
+    ///
+    /// A transaction taking longer, for example because a slave holds SCL low, fails with
+    /// [`Error::Timeout`], and the bus is recovered.
+    #[cfg(feature = "_time-driver")]
+    pub fn set_timeout(&mut self, timeout: embassy::time::Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Free the bus from a slave holding SDA low.
+    ///
+    /// A slave reset or interrupted in the middle of a transaction can wait for clock pulses
+    /// to finish sending a byte, holding SDA low meanwhile. This clocks SCL until the slave
+    /// releases SDA, at most 9 times, then sends a STOP. Returns [`Error::BusStuck`] if SDA
+    /// is still low.
+    ///
+    /// This is done automatically after a transaction times out.
+    pub fn recover_bus(&mut self) -> Result<(), Error> {
+        let regs = T::regs();
+        // Half period of a 100 kHz clock.
+        let half_period = || cortex_m::asm::delay(crate::rcc::get_freqs().sys.0 / 200_000);
+
+        unsafe {
+            regs.cr1().modify(|w| w.set_pe(false));
+
+            set_as_open_drain_output(&self.scl);
+            set_as_open_drain_output(&self.sda);
+            half_period();
+
+            for _ in 0..9 {
+                if is_high(&self.sda) {
+                    break;
+                }
+                self.scl.set_low();
+                half_period();
+                self.scl.set_high();
+                half_period();
+            }
+
+            // STOP: SDA rises while SCL is high.
+            self.scl.set_low();
+            self.sda.set_low();
+            half_period();
+            self.scl.set_high();
+            half_period();
+            self.sda.set_high();
+            half_period();
+
+            let released = is_high(&self.sda);
+
+            self.scl.set_as_af(self.scl_af, AFType::OutputOpenDrain);
+            self.sda.set_as_af(self.sda_af, AFType::OutputOpenDrain);
+            regs.cr1().modify(|w| w.set_pe(true));
+
+            if released {
+                Ok(())
+            } else {
+                Err(Error::BusStuck)
+            }
+        }
+    }
+
+    fn deadline(&self) -> Deadline {
+        Deadline {
+            #[cfg(feature = "_time-driver")]
+            at: embassy::time::Instant::now() + self.timeout,
+        }
+    }
+
+    /// Bring the peripheral and the bus back to idle after a failed transaction.
+    fn handle_error<R>(&mut self, res: Result<R, Error>) -> Result<R, Error> {
+        if let Err(Error::Timeout | Error::Bus | Error::Arbitration) = res {
+            // Clearing PE releases the lines and drops the transfer in progress.
+            unsafe {
+                T::regs().cr1().modify(|w| w.set_pe(false));
+                T::regs().cr1().modify(|w| w.set_pe(true));
+            }
+            if !is_high(&self.sda) {
+                warn!("I2C: SDA held low, recovering the bus");
+                if self.recover_bus().is_err() {
+                    warn!("I2C: bus still stuck");
+                }
+            }
+        }
+        res
+    }
+
     unsafe fn check_and_clear_error_flags(&self) -> Result<i2c::regs::Sr1, Error> {
         // Note that flags should only be cleared once they have been registered. If flags are
         // cleared otherwise, there may be an inherent race condition and flags may be missed.
@@ -112,7 +216,12 @@ impl<'d, T: Instance> I2c<'d, T> {
         Ok(sr1)
     }
 
-    unsafe fn write_bytes(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Error> {
+    unsafe fn write_bytes(
+        &mut self,
+        addr: u8,
+        bytes: &[u8],
+        deadline: Deadline,
+    ) -> Result<(), Error> {
         // Send a START condition
 
         T::regs().cr1().modify(|reg| {
@@ -120,7 +229,9 @@ impl<'d, T: Instance> I2c<'d, T> {
         });
 
         // Wait until START condition was generated
-        while !self.check_and_clear_error_flags()?.start() {}
+        while !self.check_and_clear_error_flags()?.start() {
+            deadline.check()?;
+        }
 
         // Also wait until signalled we're master and everything is waiting for us
         while {
@@ -128,7 +239,9 @@ impl<'d, T: Instance> I2c<'d, T> {
 
             let sr2 = T::regs().sr2().read();
             !sr2.msl() && !sr2.busy()
-        } {}
+        } {
+            deadline.check()?;
+        }
 
         // Set up current address, we're trying to talk to
         T::regs().dr().write(|reg| reg.set_dr(addr << 1));
@@ -136,26 +249,30 @@ impl<'d, T: Instance> I2c<'d, T> {
         // Wait until address was sent
         // Wait for the address to be acknowledged
         // Check for any I2C errors. If a NACK occurs, the ADDR bit will never be set.
-        while !self.check_and_clear_error_flags()?.addr() {}
+        while !self.check_and_clear_error_flags()?.addr() {
+            deadline.check()?;
+        }
 
         // Clear condition by reading SR2
         let _ = T::regs().sr2().read();
 
         // Send bytes
         for c in bytes {
-            self.send_byte(*c)?;
+            self.send_byte(*c, deadline)?;
         }
 
         // Fallthrough is success
         Ok(())
     }
 
-    unsafe fn send_byte(&self, byte: u8) -> Result<(), Error> {
+    unsafe fn send_byte(&self, byte: u8, deadline: Deadline) -> Result<(), Error> {
         // Wait until we're ready for sending
         while {
             // Check for any I2C errors. If a NACK occurs, the ADDR bit will never be set.
             !self.check_and_clear_error_flags()?.txe()
-        } {}
+        } {
+            deadline.check()?;
+        }
 
         // Push out a byte of data
         T::regs().dr().write(|reg| reg.set_dr(byte));
@@ -164,24 +281,40 @@ impl<'d, T: Instance> I2c<'d, T> {
         while {
             // Check for any potential error conditions.
             !self.check_and_clear_error_flags()?.btf()
-        } {}
+        } {
+            deadline.check()?;
+        }
 
         Ok(())
     }
 
-    unsafe fn recv_byte(&self) -> Result<u8, Error> {
+    unsafe fn recv_byte(&self, deadline: Deadline) -> Result<u8, Error> {
         while {
             // Check for any potential error conditions.
             self.check_and_clear_error_flags()?;
 
             !T::regs().sr1().read().rxne()
-        } {}
+        } {
+            deadline.check()?;
+        }
 
         let value = T::regs().dr().read().dr();
         Ok(value)
     }
 
-    pub fn blocking_read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Error> {
+    unsafe fn wait_stop(&self, deadline: Deadline) -> Result<(), Error> {
+        while T::regs().cr1().read().stop() {
+            deadline.check()?;
+        }
+        Ok(())
+    }
+
+    fn read_internal(
+        &mut self,
+        addr: u8,
+        buffer: &mut [u8],
+        deadline: Deadline,
+    ) -> Result<(), Error> {
         if let Some((last, buffer)) = buffer.split_last_mut() {
             // Send a START condition and set ACK bit
             unsafe {
@@ -192,27 +325,33 @@ impl<'d, T: Instance> I2c<'d, T> {
             }
 
             // Wait until START condition was generated
-            while unsafe { !T::regs().sr1().read().start() } {}
+            while unsafe { !T::regs().sr1().read().start() } {
+                deadline.check()?;
+            }
 
             // Also wait until signalled we're master and everything is waiting for us
             while {
                 let sr2 = unsafe { T::regs().sr2().read() };
                 !sr2.msl() && !sr2.busy()
-            } {}
+            } {
+                deadline.check()?;
+            }
 
             // Set up current address, we're trying to talk to
             unsafe { T::regs().dr().write(|reg| reg.set_dr((addr << 1) + 1)) }
 
             // Wait until address was sent
             // Wait for the address to be acknowledged
-            while unsafe { !self.check_and_clear_error_flags()?.addr() } {}
+            while unsafe { !self.check_and_clear_error_flags()?.addr() } {
+                deadline.check()?;
+            }
 
             // Clear condition by reading SR2
             let _ = unsafe { T::regs().sr2().read() };
 
             // Receive bytes into buffer
             for c in buffer {
-                *c = unsafe { self.recv_byte()? };
+                *c = unsafe { self.recv_byte(deadline)? };
             }
 
             // Prepare to send NACK then STOP after next byte
@@ -224,29 +363,33 @@ impl<'d, T: Instance> I2c<'d, T> {
             }
 
             // Receive last byte
-            *last = unsafe { self.recv_byte()? };
+            *last = unsafe { self.recv_byte(deadline)? };
 
             // Wait for the STOP to be sent.
-            while unsafe { T::regs().cr1().read().stop() } {}
-
-            // Fallthrough is success
-            Ok(())
+            unsafe { self.wait_stop(deadline) }
         } else {
             Err(Error::Overrun)
         }
     }
 
-    pub fn blocking_write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Error> {
+    fn write_internal(&mut self, addr: u8, bytes: &[u8], deadline: Deadline) -> Result<(), Error> {
         unsafe {
-            self.write_bytes(addr, bytes)?;
+            self.write_bytes(addr, bytes, deadline)?;
             // Send a STOP condition
             T::regs().cr1().modify(|reg| reg.set_stop(true));
             // Wait for STOP condition to transmit.
-            while T::regs().cr1().read().stop() {}
-        };
+            self.wait_stop(deadline)
+        }
+    }
 
-        // Fallthrough is success
-        Ok(())
+    pub fn blocking_read(&mut self, addr: u8, buffer: &mut [u8]) -> Result<(), Error> {
+        let res = self.read_internal(addr, buffer, self.deadline());
+        self.handle_error(res)
+    }
+
+    pub fn blocking_write(&mut self, addr: u8, bytes: &[u8]) -> Result<(), Error> {
+        let res = self.write_internal(addr, bytes, self.deadline());
+        self.handle_error(res)
     }
 
     pub fn blocking_write_read(
@@ -255,10 +398,11 @@ impl<'d, T: Instance> I2c<'d, T> {
         bytes: &[u8],
         buffer: &mut [u8],
     ) -> Result<(), Error> {
-        unsafe { self.write_bytes(addr, bytes)? };
-        self.blocking_read(addr, buffer)?;
-
-        Ok(())
+        let deadline = self.deadline();
+        let res = unsafe { self.write_bytes(addr, bytes, deadline) };
+        self.handle_error(res)?;
+        let res = self.read_internal(addr, buffer, deadline);
+        self.handle_error(res)
     }
 }
 
@@ -11,8 +11,11 @@ use embassy_hal_common::unborrow;
 use futures::future::poll_fn;
 
 use crate::dma::NoDma;
-use crate::gpio::sealed::AFType;
-use crate::i2c::{Error, Instance, SclPin, SdaPin};
+use crate::gpio::sealed::{AFType, Pin as _};
+use crate::gpio::{AnyPin, Pin};
+#[cfg(feature = "_time-driver")]
+use crate::i2c::DEFAULT_TIMEOUT;
+use crate::i2c::{is_high, set_as_open_drain_output, Deadline, Error, Instance, SclPin, SdaPin};
 use crate::pac::i2c;
 use crate::time::Hertz;
 
@@ -35,6 +38,12 @@ pub struct I2c<'d, T: Instance, TXDMA = NoDma, RXDMA = NoDma> {
     tx_dma: TXDMA,
     #[allow(dead_code)]
     rx_dma: RXDMA,
+    scl: AnyPin,
+    sda: AnyPin,
+    scl_af: u8,
+    sda_af: u8,
+    #[cfg(feature = "_time-driver")]
+    timeout: embassy::time::Duration,
 }
 
 impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
@@ -55,9 +64,11 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
         T::enable();
         T::reset();
 
+        let scl_af = scl.af_num();
+        let sda_af = sda.af_num();
         unsafe {
-            scl.set_as_af(scl.af_num(), AFType::OutputOpenDrain);
-            sda.set_as_af(sda.af_num(), AFType::OutputOpenDrain);
+            scl.set_as_af(scl_af, AFType::OutputOpenDrain);
+            sda.set_as_af(sda_af, AFType::OutputOpenDrain);
         }
 
         unsafe {
@@ -93,9 +104,123 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
             phantom: PhantomData,
             tx_dma,
             rx_dma,
+            scl: scl.degrade(),
+            sda: sda.degrade(),
+            scl_af,
+            sda_af,
+            #[cfg(feature = "_time-driver")]
+            timeout: DEFAULT_TIMEOUT,
         }
     }
 
+    /// Set the timeout of the transactions, 1 second by default.
+    ///
+    /// A transaction taking longer, for example because a slave holds SCL low, fails with
+    /// [`Error::Timeout`], and the bus is recovered.
+    #[cfg(feature = "_time-driver")]
+    pub fn set_timeout(&mut self, timeout: embassy::time::Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Enable the SMBus clock low timeout, or disable it with `None`.
+    ///
+    /// A transaction during which SCL is held low for longer than `timeout_us` microseconds
+    /// (25 ms for SMBus) fails with [`Error::SmbusTimeout`]. The limit depends on the kernel
+    /// clock, about 130 ms at 16 MHz.
+    pub fn set_smbus_timeout(&mut self, timeout_us: Option<u32>) {
+        let regs = T::regs();
+        unsafe {
+            regs.timeoutr().modify(|w| w.set_timouten(false));
+            if let Some(timeout_us) = timeout_us {
+                // tTIMEOUT = (TIMEOUTA + 1) * 2048 * tI2CCLK
+                let ticks = T::frequency().0 as u64 * timeout_us as u64 / 1_000_000 / 2048;
+                assert!(ticks >= 1 && ticks <= 0x1000, "SMBus timeout out of range");
+                regs.timeoutr().write(|w| {
+                    w.set_timeouta((ticks - 1) as u16);
+                    w.set_timouten(true);
+                });
+            }
+        }
+    }
+
+    /// Free the bus from a slave holding SDA low.
+    ///
+    /// A slave reset or interrupted in the middle of a transaction can wait for clock pulses
+    /// to finish sending a byte, holding SDA low meanwhile. This clocks SCL until the slave
+    /// releases SDA, at most 9 times, then sends a STOP. Returns [`Error::BusStuck`] if SDA
+    /// is still low.
+    ///
+    /// This is done automatically after a transaction times out.
+    pub fn recover_bus(&mut self) -> Result<(), Error> {
+        let regs = T::regs();
+        // Half period of a 100 kHz clock.
+        let half_period = || cortex_m::asm::delay(crate::rcc::get_freqs().sys.0 / 200_000);
+
+        unsafe {
+            regs.cr1().modify(|w| w.set_pe(false));
+
+            set_as_open_drain_output(&self.scl);
+            set_as_open_drain_output(&self.sda);
+            half_period();
+
+            for _ in 0..9 {
+                if is_high(&self.sda) {
+                    break;
+                }
+                self.scl.set_low();
+                half_period();
+                self.scl.set_high();
+                half_period();
+            }
+
+            // STOP: SDA rises while SCL is high.
+            self.scl.set_low();
+            self.sda.set_low();
+            half_period();
+            self.scl.set_high();
+            half_period();
+            self.sda.set_high();
+            half_period();
+
+            let released = is_high(&self.sda);
+
+            self.scl.set_as_af(self.scl_af, AFType::OutputOpenDrain);
+            self.sda.set_as_af(self.sda_af, AFType::OutputOpenDrain);
+            regs.cr1().modify(|w| w.set_pe(true));
+
+            if released {
+                Ok(())
+            } else {
+                Err(Error::BusStuck)
+            }
+        }
+    }
+
+    fn deadline(&self) -> Deadline {
+        Deadline {
+            #[cfg(feature = "_time-driver")]
+            at: embassy::time::Instant::now() + self.timeout,
+        }
+    }
+
+    /// Bring the peripheral and the bus back to idle after a failed transaction.
+    fn handle_error<R>(&mut self, res: Result<R, Error>) -> Result<R, Error> {
+        if let Err(Error::Timeout | Error::SmbusTimeout | Error::Bus | Error::Arbitration) = res {
+            // Clearing PE resets the state machine and releases the lines.
+            unsafe {
+                T::regs().cr1().modify(|w| w.set_pe(false));
+                T::regs().cr1().modify(|w| w.set_pe(true));
+            }
+            if !is_high(&self.sda) {
+                warn!("I2C: SDA held low, recovering the bus");
+                if self.recover_bus().is_err() {
+                    warn!("I2C: bus still stuck");
+                }
+            }
+        }
+        res
+    }
+
     unsafe fn on_interrupt(_: *mut ()) {
         let regs = T::regs();
         let isr = regs.isr().read();
@@ -118,14 +243,23 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
         }
     }
 
-    unsafe fn master_read(address: u8, length: usize, stop: Stop, reload: bool, restart: bool) {
+    unsafe fn master_read(
+        address: u8,
+        length: usize,
+        stop: Stop,
+        reload: bool,
+        restart: bool,
+        deadline: Deadline,
+    ) -> Result<(), Error> {
         assert!(length < 256);
 
         if !restart {
             // Wait for any previous address sequence to end
             // automatically. This could be up to 50% of a bus
             // cycle (ie. up to 0.5/freq)
-            while T::regs().cr2().read().start() {}
+            while T::regs().cr2().read().start() {
+                deadline.check()?;
+            }
         }
 
         // Set START and prepare to receive bytes into
@@ -147,15 +281,24 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
             w.set_autoend(stop.autoend());
             w.set_reload(reload);
         });
+        Ok(())
     }
 
-    unsafe fn master_write(address: u8, length: usize, stop: Stop, reload: bool) {
+    unsafe fn master_write(
+        address: u8,
+        length: usize,
+        stop: Stop,
+        reload: bool,
+        deadline: Deadline,
+    ) -> Result<(), Error> {
         assert!(length < 256);
 
         // Wait for any previous address sequence to end
         // automatically. This could be up to 50% of a bus
         // cycle (ie. up to 0.5/freq)
-        while T::regs().cr2().read().start() {}
+        while T::regs().cr2().read().start() {
+            deadline.check()?;
+        }
 
         let reload = if reload {
             i2c::vals::Reload::NOTCOMPLETED
@@ -175,12 +318,19 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
             w.set_autoend(stop.autoend());
             w.set_reload(reload);
         });
+        Ok(())
     }
 
-    unsafe fn master_continue(length: usize, reload: bool) {
+    unsafe fn master_continue(
+        length: usize,
+        reload: bool,
+        deadline: Deadline,
+    ) -> Result<(), Error> {
         assert!(length < 256 && length > 0);
 
-        while !T::regs().isr().read().tcr() {}
+        while !T::regs().isr().read().tcr() {
+            deadline.check()?;
+        }
 
         let reload = if reload {
             i2c::vals::Reload::NOTCOMPLETED
@@ -192,6 +342,7 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
             w.set_nbytes(length as u8);
             w.set_reload(reload);
         });
+        Ok(())
     }
 
     fn flush_txdr(&self) {
@@ -214,66 +365,57 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
         //}
     }
 
-    fn wait_txe(&self) -> Result<(), Error> {
+    fn check_errors(&self, isr: i2c::regs::Isr) -> Result<(), Error> {
+        unsafe {
+            if isr.berr() {
+                T::regs().icr().write(|reg| reg.set_berrcf(true));
+                Err(Error::Bus)
+            } else if isr.arlo() {
+                T::regs().icr().write(|reg| reg.set_arlocf(true));
+                Err(Error::Arbitration)
+            } else if isr.nackf() {
+                T::regs().icr().write(|reg| reg.set_nackcf(true));
+                self.flush_txdr();
+                Err(Error::Nack)
+            } else if isr.timeout() {
+                T::regs().icr().write(|reg| reg.set_timoutcf(true));
+                Err(Error::SmbusTimeout)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    fn wait_txe(&self, deadline: Deadline) -> Result<(), Error> {
         loop {
-            unsafe {
-                let isr = T::regs().isr().read();
-                if isr.txe() {
-                    return Ok(());
-                } else if isr.berr() {
-                    T::regs().icr().write(|reg| reg.set_berrcf(true));
-                    return Err(Error::Bus);
-                } else if isr.arlo() {
-                    T::regs().icr().write(|reg| reg.set_arlocf(true));
-                    return Err(Error::Arbitration);
-                } else if isr.nackf() {
-                    T::regs().icr().write(|reg| reg.set_nackcf(true));
-                    self.flush_txdr();
-                    return Err(Error::Nack);
-                }
+            let isr = unsafe { T::regs().isr().read() };
+            if isr.txe() {
+                return Ok(());
             }
+            self.check_errors(isr)?;
+            deadline.check()?;
         }
     }
 
-    fn wait_rxne(&self) -> Result<(), Error> {
+    fn wait_rxne(&self, deadline: Deadline) -> Result<(), Error> {
         loop {
-            unsafe {
-                let isr = T::regs().isr().read();
-                if isr.rxne() {
-                    return Ok(());
-                } else if isr.berr() {
-                    T::regs().icr().write(|reg| reg.set_berrcf(true));
-                    return Err(Error::Bus);
-                } else if isr.arlo() {
-                    T::regs().icr().write(|reg| reg.set_arlocf(true));
-                    return Err(Error::Arbitration);
-                } else if isr.nackf() {
-                    T::regs().icr().write(|reg| reg.set_nackcf(true));
-                    self.flush_txdr();
-                    return Err(Error::Nack);
-                }
+            let isr = unsafe { T::regs().isr().read() };
+            if isr.rxne() {
+                return Ok(());
             }
+            self.check_errors(isr)?;
+            deadline.check()?;
         }
     }
 
-    fn wait_tc(&self) -> Result<(), Error> {
+    fn wait_tc(&self, deadline: Deadline) -> Result<(), Error> {
         loop {
-            unsafe {
-                let isr = T::regs().isr().read();
-                if isr.tc() {
-                    return Ok(());
-                } else if isr.berr() {
-                    T::regs().icr().write(|reg| reg.set_berrcf(true));
-                    return Err(Error::Bus);
-                } else if isr.arlo() {
-                    T::regs().icr().write(|reg| reg.set_arlocf(true));
-                    return Err(Error::Arbitration);
-                } else if isr.nackf() {
-                    T::regs().icr().write(|reg| reg.set_nackcf(true));
-                    self.flush_txdr();
-                    return Err(Error::Nack);
-                }
+            let isr = unsafe { T::regs().isr().read() };
+            if isr.tc() {
+                return Ok(());
             }
+            self.check_errors(isr)?;
+            deadline.check()?;
         }
     }
 
@@ -282,6 +424,7 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
         address: u8,
         buffer: &mut [u8],
         restart: bool,
+        deadline: Deadline,
     ) -> Result<(), Error> {
         let completed_chunks = buffer.len() / 255;
         let total_chunks = if completed_chunks * 255 == buffer.len() {
@@ -298,20 +441,21 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
                 Stop::Automatic,
                 last_chunk_idx != 0,
                 restart,
-            );
+                deadline,
+            )?;
         }
 
         for (number, chunk) in buffer.chunks_mut(255).enumerate() {
             if number != 0 {
                 // NOTE(unsafe) We have &mut self
                 unsafe {
-                    Self::master_continue(chunk.len(), number != last_chunk_idx);
+                    Self::master_continue(chunk.len(), number != last_chunk_idx, deadline)?;
                 }
             }
 
             for byte in chunk {
                 // Wait until we have received something
-                self.wait_rxne()?;
+                self.wait_rxne(deadline)?;
 
                 unsafe {
                     *byte = T::regs().rxdr().read().rxdata();
@@ -321,7 +465,13 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
         Ok(())
     }
 
-    fn write_internal(&mut self, address: u8, bytes: &[u8], send_stop: bool) -> Result<(), Error> {
+    fn write_internal(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        send_stop: bool,
+        deadline: Deadline,
+    ) -> Result<(), Error> {
         let completed_chunks = bytes.len() / 255;
         let total_chunks = if completed_chunks * 255 == bytes.len() {
             completed_chunks
@@ -340,14 +490,15 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
                 bytes.len().min(255),
                 Stop::Software,
                 last_chunk_idx != 0,
-            );
+                deadline,
+            )?;
         }
 
         for (number, chunk) in bytes.chunks(255).enumerate() {
             if number != 0 {
                 // NOTE(unsafe) We have &mut self
                 unsafe {
-                    Self::master_continue(chunk.len(), number != last_chunk_idx);
+                    Self::master_continue(chunk.len(), number != last_chunk_idx, deadline)?;
                 }
             }
 
@@ -355,7 +506,7 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
                 // Wait until we are allowed to send data
                 // (START has been ACKed or last byte when
                 // through)
-                self.wait_txe()?;
+                self.wait_txe(deadline)?;
 
                 unsafe {
                     T::regs().txdr().write(|w| w.set_txdata(*byte));
@@ -363,7 +514,7 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
             }
         }
         // Wait until the write finishes
-        self.wait_tc()?;
+        self.wait_tc(deadline)?;
 
         if send_stop {
             self.master_stop();
@@ -377,6 +528,7 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
         bytes: &[u8],
         first_slice: bool,
         last_slice: bool,
+        deadline: Deadline,
     ) -> Result<(), Error>
     where
         TXDMA: crate::i2c::TxDma<T>,
@@ -428,40 +580,50 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
                     total_len.min(255),
                     Stop::Software,
                     (total_chunks != 1) || !last_slice,
-                );
+                    deadline,
+                )?;
             }
         } else {
             unsafe {
-                Self::master_continue(total_len.min(255), (total_chunks != 1) || !last_slice);
+                Self::master_continue(
+                    total_len.min(255),
+                    (total_chunks != 1) || !last_slice,
+                    deadline,
+                )?;
                 T::regs().cr1().modify(|w| w.set_tcie(true));
             }
         }
 
-        poll_fn(|cx| {
-            state.waker.register(cx.waker());
-            let chunks_transferred = state.chunks_transferred.load(Ordering::Relaxed);
+        deadline
+            .wait(poll_fn(|cx| {
+                state.waker.register(cx.waker());
+                let chunks_transferred = state.chunks_transferred.load(Ordering::Relaxed);
 
-            if chunks_transferred == total_chunks {
-                return Poll::Ready(());
-            } else if chunks_transferred != 0 {
-                remaining_len = remaining_len.saturating_sub(255);
-                let last_piece = (chunks_transferred + 1 == total_chunks) && last_slice;
+                if chunks_transferred == total_chunks {
+                    return Poll::Ready(Ok(()));
+                } else if chunks_transferred != 0 {
+                    remaining_len = remaining_len.saturating_sub(255);
+                    let last_piece = (chunks_transferred + 1 == total_chunks) && last_slice;
 
-                // NOTE(unsafe) self.tx_dma does not fiddle with the i2c registers
-                unsafe {
-                    Self::master_continue(remaining_len.min(255), !last_piece);
-                    T::regs().cr1().modify(|w| w.set_tcie(true));
+                    // NOTE(unsafe) self.tx_dma does not fiddle with the i2c registers
+                    unsafe {
+                        if let Err(e) =
+                            Self::master_continue(remaining_len.min(255), !last_piece, deadline)
+                        {
+                            return Poll::Ready(Err(e));
+                        }
+                        T::regs().cr1().modify(|w| w.set_tcie(true));
+                    }
                 }
-            }
-            Poll::Pending
-        })
-        .await;
+                Poll::Pending
+            }))
+            .await??;
 
-        dma_transfer.await;
+        deadline.wait(dma_transfer).await?;
 
         if last_slice {
             // This should be done already
-            self.wait_tc()?;
+            self.wait_tc(deadline)?;
             self.master_stop();
         }
         Ok(())
@@ -472,6 +634,7 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
         address: u8,
         buffer: &mut [u8],
         restart: bool,
+        deadline: Deadline,
     ) -> Result<(), Error>
     where
         RXDMA: crate::i2c::RxDma<T>,
@@ -519,33 +682,39 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
                 Stop::Software,
                 total_chunks != 1,
                 restart,
-            );
+                deadline,
+            )?;
         }
 
-        poll_fn(|cx| {
-            state.waker.register(cx.waker());
-            let chunks_transferred = state.chunks_transferred.load(Ordering::Relaxed);
+        deadline
+            .wait(poll_fn(|cx| {
+                state.waker.register(cx.waker());
+                let chunks_transferred = state.chunks_transferred.load(Ordering::Relaxed);
 
-            if chunks_transferred == total_chunks {
-                return Poll::Ready(());
-            } else if chunks_transferred != 0 {
-                remaining_len = remaining_len.saturating_sub(255);
-                let last_piece = chunks_transferred + 1 == total_chunks;
+                if chunks_transferred == total_chunks {
+                    return Poll::Ready(Ok(()));
+                } else if chunks_transferred != 0 {
+                    remaining_len = remaining_len.saturating_sub(255);
+                    let last_piece = chunks_transferred + 1 == total_chunks;
 
-                // NOTE(unsafe) self.rx_dma does not fiddle with the i2c registers
-                unsafe {
-                    Self::master_continue(remaining_len.min(255), !last_piece);
-                    T::regs().cr1().modify(|w| w.set_tcie(true));
+                    // NOTE(unsafe) self.rx_dma does not fiddle with the i2c registers
+                    unsafe {
+                        if let Err(e) =
+                            Self::master_continue(remaining_len.min(255), !last_piece, deadline)
+                        {
+                            return Poll::Ready(Err(e));
+                        }
+                        T::regs().cr1().modify(|w| w.set_tcie(true));
+                    }
                 }
-            }
-            Poll::Pending
-        })
-        .await;
+                Poll::Pending
+            }))
+            .await??;
 
-        dma_transfer.await;
+        deadline.wait(dma_transfer).await?;
 
         // This should be done already
-        self.wait_tc()?;
+        self.wait_tc(deadline)?;
         self.master_stop();
         Ok(())
     }
@@ -557,11 +726,14 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
     where
         TXDMA: crate::i2c::TxDma<T>,
     {
-        if bytes.is_empty() {
-            self.write_internal(address, bytes, true)
+        let deadline = self.deadline();
+        let res = if bytes.is_empty() {
+            self.write_internal(address, bytes, true, deadline)
         } else {
-            self.write_dma_internal(address, bytes, true, true).await
-        }
+            self.write_dma_internal(address, bytes, true, true, deadline)
+                .await
+        };
+        self.handle_error(res)
     }
 
     pub async fn write_vectored(&mut self, address: u8, bytes: &[&[u8]]) -> Result<(), Error>
@@ -571,6 +743,7 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
         if bytes.is_empty() {
             return Err(Error::ZeroLengthTransfer);
         }
+        let deadline = self.deadline();
         let mut iter = bytes.iter();
 
         let mut first = true;
@@ -579,7 +752,10 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
             let next = iter.next();
             let is_last = next.is_none();
 
-            self.write_dma_internal(address, c, first, is_last).await?;
+            let res = self
+                .write_dma_internal(address, c, first, is_last, deadline)
+                .await;
+            self.handle_error(res)?;
             first = false;
             current = next;
         }
@@ -590,11 +766,14 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
     where
         RXDMA: crate::i2c::RxDma<T>,
     {
-        if buffer.is_empty() {
-            self.read_internal(address, buffer, false)
+        let deadline = self.deadline();
+        let res = if buffer.is_empty() {
+            self.read_internal(address, buffer, false, deadline)
         } else {
-            self.read_dma_internal(address, buffer, false).await
-        }
+            self.read_dma_internal(address, buffer, false, deadline)
+                .await
+        };
+        self.handle_error(res)
     }
 
     pub async fn write_read(
@@ -607,31 +786,37 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
         TXDMA: super::TxDma<T>,
         RXDMA: super::RxDma<T>,
     {
-        if bytes.is_empty() {
-            self.write_internal(address, bytes, false)?;
-        } else {
-            self.write_dma_internal(address, bytes, true, true).await?;
-        }
+        let deadline = self.deadline();
 
-        if buffer.is_empty() {
-            self.read_internal(address, buffer, true)?;
+        let res = if bytes.is_empty() {
+            self.write_internal(address, bytes, false, deadline)
         } else {
-            self.read_dma_internal(address, buffer, true).await?;
-        }
+            self.write_dma_internal(address, bytes, true, true, deadline)
+                .await
+        };
+        self.handle_error(res)?;
 
-        Ok(())
+        let res = if buffer.is_empty() {
+            self.read_internal(address, buffer, true, deadline)
+        } else {
+            self.read_dma_internal(address, buffer, true, deadline)
+                .await
+        };
+        self.handle_error(res)
     }
 
     // =========================
     //  Blocking public API
 
     pub fn blocking_read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Error> {
-        self.read_internal(address, buffer, false)
+        let res = self.read_internal(address, buffer, false, self.deadline());
+        self.handle_error(res)
         // Automatic Stop
     }
 
     pub fn blocking_write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Error> {
-        self.write_internal(address, bytes, true)
+        let res = self.write_internal(address, bytes, true, self.deadline());
+        self.handle_error(res)
     }
 
     pub fn blocking_write_read(
@@ -640,12 +825,25 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
         bytes: &[u8],
         buffer: &mut [u8],
     ) -> Result<(), Error> {
-        self.write_internal(address, bytes, false)?;
-        self.read_internal(address, buffer, true)
+        let deadline = self.deadline();
+        let res = self.write_internal(address, bytes, false, deadline);
+        self.handle_error(res)?;
+        let res = self.read_internal(address, buffer, true, deadline);
+        self.handle_error(res)
         // Automatic Stop
     }
 
     pub fn blocking_write_vectored(&mut self, address: u8, bytes: &[&[u8]]) -> Result<(), Error> {
+        let res = self.write_vectored_internal(address, bytes, self.deadline());
+        self.handle_error(res)
+    }
+
+    fn write_vectored_internal(
+        &mut self,
+        address: u8,
+        bytes: &[&[u8]],
+        deadline: Deadline,
+    ) -> Result<(), Error> {
         if bytes.is_empty() {
             return Err(Error::ZeroLengthTransfer);
         }
@@ -659,7 +857,8 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
                 first_length.min(255),
                 Stop::Software,
                 (first_length > 255) || (last_slice_index != 0),
-            );
+                deadline,
+            )?;
         }
 
         for (idx, slice) in bytes.iter().enumerate() {
@@ -678,7 +877,8 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
                     Self::master_continue(
                         slice_len.min(255),
                         (idx != last_slice_index) || (slice_len > 255),
-                    );
+                        deadline,
+                    )?;
                 }
             }
 
@@ -689,7 +889,8 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
                         Self::master_continue(
                             chunk.len(),
                             (number != last_chunk_idx) || (idx != last_slice_index),
-                        );
+                            deadline,
+                        )?;
                     }
                 }
 
@@ -697,7 +898,7 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
                     // Wait until we are allowed to send data
                     // (START has been ACKed or last byte when
                     // through)
-                    self.wait_txe()?;
+                    self.wait_txe(deadline)?;
 
                     // Put byte on the wire
                     //self.i2c.txdr.write(|w| w.txdata().bits(*byte));
@@ -708,7 +909,7 @@ impl<'d, T: Instance, TXDMA, RXDMA> I2c<'d, T, TXDMA, RXDMA> {
             }
         }
         // Wait until the write finishes
-        self.wait_tc()?;
+        self.wait_tc(deadline)?;
         self.master_stop();
 
         Ok(())
@@ -904,6 +1105,8 @@ mod eh1 {
                     embedded_hal_1::i2c::NoAcknowledgeSource::Unknown,
                 ),
                 Self::Timeout => embedded_hal_1::i2c::ErrorKind::Other,
+                Self::SmbusTimeout => embedded_hal_1::i2c::ErrorKind::Other,
+                Self::BusStuck => embedded_hal_1::i2c::ErrorKind::Bus,
                 Self::Crc => embedded_hal_1::i2c::ErrorKind::Other,
                 Self::Overrun => embedded_hal_1::i2c::ErrorKind::Overrun,
                 Self::ZeroLengthTransfer => embedded_hal_1::i2c::ErrorKind::Other,
@@ -921,7 +1124,6 @@ mod eh1 {
 cfg_if::cfg_if! {
     if #[cfg(all(feature = "unstable-traits", feature = "nightly"))] {
         use super::{RxDma, TxDma};
-        use core::future::Future;
 
         impl<'d, T: Instance, TXDMA: TxDma<T>, RXDMA: RxDma<T>> embedded_hal_async::i2c::I2c
             for I2c<'d, T, TXDMA, RXDMA>
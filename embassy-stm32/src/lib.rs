@@ -12,8 +12,8 @@ pub(crate) use stm32_metapac as pac;
 pub use embassy::util::Unborrow;
 pub use embassy_hal_common::unborrow;
 
-// This must go FIRST so that all the other modules see its macros.
-pub mod fmt;
+#[macro_use]
+extern crate embassy_fmt;
 include!(concat!(env!("OUT_DIR"), "/_macros.rs"));
 
 // Utilities
@@ -35,10 +35,14 @@ pub mod timer;
 pub mod adc;
 #[cfg(can)]
 pub mod can;
+#[cfg(cec)]
+pub mod cec;
 #[cfg(dac)]
 pub mod dac;
 #[cfg(dcmi)]
 pub mod dcmi;
+#[cfg(dma2d)]
+pub mod dma2d;
 #[cfg(all(eth, feature = "net"))]
 pub mod eth;
 #[cfg(feature = "exti")]
@@ -47,20 +51,30 @@ pub mod exti;
 pub mod fmc;
 #[cfg(i2c)]
 pub mod i2c;
+#[cfg(ltdc)]
+pub mod ltdc;
 
 #[cfg(crc)]
 pub mod crc;
 pub mod pwm;
 #[cfg(rng)]
 pub mod rng;
+#[cfg(any(rtc_v2l4, rtc_v2wb))]
+pub mod rtc;
 #[cfg(sdmmc)]
 pub mod sdmmc;
+#[cfg(spdifrx)]
+pub mod spdifrx;
 #[cfg(spi)]
 pub mod spi;
 #[cfg(usart)]
 pub mod usart;
+#[cfg(usb)]
+pub mod usb;
 #[cfg(feature = "usb-otg")]
 pub mod usb_otg;
+#[cfg(vrefbuf)]
+pub mod vrefbuf;
 
 #[cfg(feature = "subghz")]
 pub mod subghz;
@@ -0,0 +1,656 @@
+//! LCD-TFT display controller (LTDC)
+//!
+//! The LTDC continuously reads up to two layers from framebuffers in memory, blends them, and
+//! sends the result to a display, either through a parallel RGB interface or through the DSI
+//! host.
+//!
+//! The pixel clock is not configured by this driver: it comes from a dedicated PLL output
+//! (PLLSAI R on F4/F7, PLL3 R on H7), which must be set up with the RCC before creating the
+//! [`Ltdc`].
+//!
+//! Layer changes are written to shadow registers, and only take effect when reloaded with
+//! [`Ltdc::reload`] or [`Ltdc::reload_immediate`].
+
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy::interrupt::{Interrupt, InterruptExt};
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::gpio::{sealed::AFType, Speed};
+
+/// Polarity of the synchronization signals.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Polarity {
+    ActiveLow,
+    ActiveHigh,
+}
+
+/// Polarity of the pixel clock.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PixelClockPolarity {
+    /// Data changes on the falling edge, and is sampled by the panel on the rising edge.
+    Normal,
+    /// Data changes on the rising edge.
+    Inverted,
+}
+
+/// Pixel formats of the layer framebuffers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PixelFormat {
+    Argb8888 = 0,
+    Rgb888 = 1,
+    Rgb565 = 2,
+    Argb1555 = 3,
+    Argb4444 = 4,
+    L8 = 5,
+    Al44 = 6,
+    Al88 = 7,
+}
+
+impl PixelFormat {
+    /// Size of a pixel, in bytes.
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            PixelFormat::Argb8888 => 4,
+            PixelFormat::Rgb888 => 3,
+            PixelFormat::Rgb565 | PixelFormat::Argb1555 | PixelFormat::Argb4444 => 2,
+            PixelFormat::Al88 => 2,
+            PixelFormat::L8 | PixelFormat::Al44 => 1,
+        }
+    }
+}
+
+/// Display timings, as found in the panel datasheet.
+///
+/// Horizontal values are in pixel clocks, vertical values in lines.
+#[non_exhaustive]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Config {
+    pub active_width: u16,
+    pub active_height: u16,
+    pub h_sync: u16,
+    pub h_back_porch: u16,
+    pub h_front_porch: u16,
+    pub v_sync: u16,
+    pub v_back_porch: u16,
+    pub v_front_porch: u16,
+
+    pub h_sync_polarity: Polarity,
+    pub v_sync_polarity: Polarity,
+    pub data_enable_polarity: Polarity,
+    pub pixel_clock_polarity: PixelClockPolarity,
+
+    /// Color shown where no layer is displayed, as RGB888.
+    pub background_color: u32,
+}
+
+impl Default for Config {
+    /// Timings of the 480x272 panel found on many ST discovery kits.
+    fn default() -> Self {
+        Self {
+            active_width: 480,
+            active_height: 272,
+            h_sync: 41,
+            h_back_porch: 13,
+            h_front_porch: 32,
+            v_sync: 10,
+            v_back_porch: 2,
+            v_front_porch: 2,
+            h_sync_polarity: Polarity::ActiveLow,
+            v_sync_polarity: Polarity::ActiveLow,
+            data_enable_polarity: Polarity::ActiveLow,
+            pixel_clock_polarity: PixelClockPolarity::Normal,
+            background_color: 0x000000,
+        }
+    }
+}
+
+/// One of the two layers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Layer {
+    /// The bottom layer.
+    L1,
+    /// The top layer, blended over L1.
+    L2,
+}
+
+/// How a layer is blended with what's below it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Blending {
+    /// Only use the constant alpha of the layer.
+    ConstantAlpha,
+    /// Multiply the alpha of each pixel with the constant alpha of the layer.
+    PixelAlpha,
+}
+
+/// Position and format of a layer.
+#[non_exhaustive]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LayerConfig {
+    /// Position of the layer window in the active area, in pixels.
+    pub x: u16,
+    pub y: u16,
+    /// Size of the layer window, which is also the size of the framebuffer.
+    pub width: u16,
+    pub height: u16,
+
+    pub pixel_format: PixelFormat,
+    /// Constant alpha, 255 is opaque.
+    pub alpha: u8,
+    pub blending: Blending,
+    /// Color shown outside of the window, as ARGB8888.
+    pub default_color: u32,
+}
+
+impl LayerConfig {
+    /// A full-screen, opaque layer.
+    pub fn new(width: u16, height: u16, pixel_format: PixelFormat) -> Self {
+        Self {
+            x: 0,
+            y: 0,
+            width,
+            height,
+            pixel_format,
+            alpha: 255,
+            blending: Blending::PixelAlpha,
+            default_color: 0,
+        }
+    }
+
+    /// Size the framebuffer for this layer must have, in bytes.
+    pub fn framebuffer_len(&self) -> usize {
+        self.width as usize * self.height as usize * self.pixel_format.bytes_per_pixel()
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// The LTDC couldn't read the framebuffers fast enough.
+    FifoUnderrun,
+    /// A bus error happened while reading a framebuffer.
+    TransferError,
+}
+
+struct State {
+    waker: AtomicWaker,
+}
+
+impl State {
+    const fn new() -> State {
+        State {
+            waker: AtomicWaker::new(),
+        }
+    }
+}
+
+static STATE: State = State::new();
+
+macro_rules! config_pins {
+    ($($pin:ident),*) => {
+        unborrow!($($pin),*);
+        // NOTE(unsafe) Exclusive access to the registers
+        critical_section::with(|_| unsafe {
+            $(
+                $pin.set_as_af($pin.af_num(), AFType::OutputPushPull);
+                $pin.set_speed(Speed::VeryHigh);
+            )*
+        })
+    };
+}
+
+pub struct Ltdc<'d, T: Instance> {
+    /// Line at which the vertical blanking starts.
+    vblank_line: u16,
+    /// Accumulated horizontal and vertical back porch, to position the layer windows.
+    h_start: u16,
+    v_start: u16,
+    phantom: PhantomData<&'d mut T>,
+}
+
+impl<'d, T: Instance> Ltdc<'d, T> {
+    /// Create a LTDC without pins, for displays driven through the DSI host.
+    pub fn new_internal(
+        peri: impl Unborrow<Target = T> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        config: Config,
+    ) -> Self {
+        Self::new_inner(peri, irq, config)
+    }
+
+    /// Create a LTDC driving a parallel RGB565 panel, using the 5 or 6 most significant bits of each color.
+    pub fn new_rgb565(
+        peri: impl Unborrow<Target = T> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        clk: impl Unborrow<Target = impl ClkPin<T>> + 'd,
+        hsync: impl Unborrow<Target = impl HSyncPin<T>> + 'd,
+        vsync: impl Unborrow<Target = impl VSyncPin<T>> + 'd,
+        de: impl Unborrow<Target = impl DePin<T>> + 'd,
+        r3: impl Unborrow<Target = impl R3Pin<T>> + 'd,
+        r4: impl Unborrow<Target = impl R4Pin<T>> + 'd,
+        r5: impl Unborrow<Target = impl R5Pin<T>> + 'd,
+        r6: impl Unborrow<Target = impl R6Pin<T>> + 'd,
+        r7: impl Unborrow<Target = impl R7Pin<T>> + 'd,
+        g2: impl Unborrow<Target = impl G2Pin<T>> + 'd,
+        g3: impl Unborrow<Target = impl G3Pin<T>> + 'd,
+        g4: impl Unborrow<Target = impl G4Pin<T>> + 'd,
+        g5: impl Unborrow<Target = impl G5Pin<T>> + 'd,
+        g6: impl Unborrow<Target = impl G6Pin<T>> + 'd,
+        g7: impl Unborrow<Target = impl G7Pin<T>> + 'd,
+        b3: impl Unborrow<Target = impl B3Pin<T>> + 'd,
+        b4: impl Unborrow<Target = impl B4Pin<T>> + 'd,
+        b5: impl Unborrow<Target = impl B5Pin<T>> + 'd,
+        b6: impl Unborrow<Target = impl B6Pin<T>> + 'd,
+        b7: impl Unborrow<Target = impl B7Pin<T>> + 'd,
+        config: Config,
+    ) -> Self {
+        config_pins!(clk, hsync, vsync, de);
+        config_pins!(r3, r4, r5, r6, r7, g2, g3, g4, g5, g6, g7, b3, b4, b5, b6, b7);
+
+        Self::new_inner(peri, irq, config)
+    }
+
+    /// Create a LTDC driving a parallel RGB666 panel, using the 6 most significant bits of each color.
+    pub fn new_rgb666(
+        peri: impl Unborrow<Target = T> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        clk: impl Unborrow<Target = impl ClkPin<T>> + 'd,
+        hsync: impl Unborrow<Target = impl HSyncPin<T>> + 'd,
+        vsync: impl Unborrow<Target = impl VSyncPin<T>> + 'd,
+        de: impl Unborrow<Target = impl DePin<T>> + 'd,
+        r2: impl Unborrow<Target = impl R2Pin<T>> + 'd,
+        r3: impl Unborrow<Target = impl R3Pin<T>> + 'd,
+        r4: impl Unborrow<Target = impl R4Pin<T>> + 'd,
+        r5: impl Unborrow<Target = impl R5Pin<T>> + 'd,
+        r6: impl Unborrow<Target = impl R6Pin<T>> + 'd,
+        r7: impl Unborrow<Target = impl R7Pin<T>> + 'd,
+        g2: impl Unborrow<Target = impl G2Pin<T>> + 'd,
+        g3: impl Unborrow<Target = impl G3Pin<T>> + 'd,
+        g4: impl Unborrow<Target = impl G4Pin<T>> + 'd,
+        g5: impl Unborrow<Target = impl G5Pin<T>> + 'd,
+        g6: impl Unborrow<Target = impl G6Pin<T>> + 'd,
+        g7: impl Unborrow<Target = impl G7Pin<T>> + 'd,
+        b2: impl Unborrow<Target = impl B2Pin<T>> + 'd,
+        b3: impl Unborrow<Target = impl B3Pin<T>> + 'd,
+        b4: impl Unborrow<Target = impl B4Pin<T>> + 'd,
+        b5: impl Unborrow<Target = impl B5Pin<T>> + 'd,
+        b6: impl Unborrow<Target = impl B6Pin<T>> + 'd,
+        b7: impl Unborrow<Target = impl B7Pin<T>> + 'd,
+        config: Config,
+    ) -> Self {
+        config_pins!(clk, hsync, vsync, de);
+        config_pins!(r2, r3, r4, r5, r6, r7, g2, g3, g4, g5, g6, g7, b2, b3, b4, b5, b6, b7);
+
+        Self::new_inner(peri, irq, config)
+    }
+
+    /// Create a LTDC driving a parallel RGB888 panel.
+    pub fn new_rgb888(
+        peri: impl Unborrow<Target = T> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        clk: impl Unborrow<Target = impl ClkPin<T>> + 'd,
+        hsync: impl Unborrow<Target = impl HSyncPin<T>> + 'd,
+        vsync: impl Unborrow<Target = impl VSyncPin<T>> + 'd,
+        de: impl Unborrow<Target = impl DePin<T>> + 'd,
+        r0: impl Unborrow<Target = impl R0Pin<T>> + 'd,
+        r1: impl Unborrow<Target = impl R1Pin<T>> + 'd,
+        r2: impl Unborrow<Target = impl R2Pin<T>> + 'd,
+        r3: impl Unborrow<Target = impl R3Pin<T>> + 'd,
+        r4: impl Unborrow<Target = impl R4Pin<T>> + 'd,
+        r5: impl Unborrow<Target = impl R5Pin<T>> + 'd,
+        r6: impl Unborrow<Target = impl R6Pin<T>> + 'd,
+        r7: impl Unborrow<Target = impl R7Pin<T>> + 'd,
+        g0: impl Unborrow<Target = impl G0Pin<T>> + 'd,
+        g1: impl Unborrow<Target = impl G1Pin<T>> + 'd,
+        g2: impl Unborrow<Target = impl G2Pin<T>> + 'd,
+        g3: impl Unborrow<Target = impl G3Pin<T>> + 'd,
+        g4: impl Unborrow<Target = impl G4Pin<T>> + 'd,
+        g5: impl Unborrow<Target = impl G5Pin<T>> + 'd,
+        g6: impl Unborrow<Target = impl G6Pin<T>> + 'd,
+        g7: impl Unborrow<Target = impl G7Pin<T>> + 'd,
+        b0: impl Unborrow<Target = impl B0Pin<T>> + 'd,
+        b1: impl Unborrow<Target = impl B1Pin<T>> + 'd,
+        b2: impl Unborrow<Target = impl B2Pin<T>> + 'd,
+        b3: impl Unborrow<Target = impl B3Pin<T>> + 'd,
+        b4: impl Unborrow<Target = impl B4Pin<T>> + 'd,
+        b5: impl Unborrow<Target = impl B5Pin<T>> + 'd,
+        b6: impl Unborrow<Target = impl B6Pin<T>> + 'd,
+        b7: impl Unborrow<Target = impl B7Pin<T>> + 'd,
+        config: Config,
+    ) -> Self {
+        config_pins!(clk, hsync, vsync, de);
+        config_pins!(
+            r0, r1, r2, r3, r4, r5, r6, r7, g0, g1, g2, g3, g4, g5, g6, g7, b0, b1, b2, b3, b4, b5,
+            b6, b7
+        );
+
+        Self::new_inner(peri, irq, config)
+    }
+
+    fn new_inner(
+        _peri: impl Unborrow<Target = T> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(irq);
+
+        T::enable();
+        T::reset();
+
+        let r = T::regs();
+
+        // The timing registers hold accumulated values, minus one.
+        let h_sync = config.h_sync;
+        let h_bp = h_sync + config.h_back_porch;
+        let h_active = h_bp + config.active_width;
+        let h_total = h_active + config.h_front_porch;
+        let v_sync = config.v_sync;
+        let v_bp = v_sync + config.v_back_porch;
+        let v_active = v_bp + config.active_height;
+        let v_total = v_active + config.v_front_porch;
+
+        unsafe {
+            r.sscr().write(|w| {
+                w.set_hsw(h_sync - 1);
+                w.set_vsh(v_sync - 1);
+            });
+            r.bpcr().write(|w| {
+                w.set_ahbp(h_bp - 1);
+                w.set_avbp(v_bp - 1);
+            });
+            r.awcr().write(|w| {
+                w.set_aaw(h_active - 1);
+                w.set_aah(v_active - 1);
+            });
+            r.twcr().write(|w| {
+                w.set_totalw(h_total - 1);
+                w.set_totalh(v_total - 1);
+            });
+
+            let [blue, green, red, _] = config.background_color.to_le_bytes();
+            r.bccr().write(|w| {
+                w.set_bcred(red);
+                w.set_bcgreen(green);
+                w.set_bcblue(blue);
+            });
+
+            r.ier().write(|_| {});
+            r.icr().write(|w| {
+                w.set_clif(true);
+                w.set_cfuif(true);
+                w.set_cterrif(true);
+                w.set_crrif(true);
+            });
+
+            r.gcr().write(|w| {
+                w.set_hspol(config.h_sync_polarity == Polarity::ActiveHigh);
+                w.set_vspol(config.v_sync_polarity == Polarity::ActiveHigh);
+                w.set_depol(config.data_enable_polarity == Polarity::ActiveHigh);
+                w.set_pcpol(config.pixel_clock_polarity == PixelClockPolarity::Inverted);
+                w.set_ltdcen(true);
+            });
+        }
+
+        irq.set_handler(Self::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        Self {
+            vblank_line: v_active,
+            h_start: h_bp,
+            v_start: v_bp,
+            phantom: PhantomData,
+        }
+    }
+
+    unsafe fn on_interrupt(_: *mut ()) {
+        let r = T::regs();
+        let isr = r.isr().read();
+        // Leave the flags set, they're checked and cleared by the waiting future.
+        r.ier().modify(|w| {
+            if isr.lif() {
+                w.set_lie(false);
+            }
+            if isr.rrif() {
+                w.set_rrie(false);
+            }
+        });
+        STATE.waker.wake();
+    }
+
+    fn layer_index(layer: Layer) -> usize {
+        match layer {
+            Layer::L1 => 0,
+            Layer::L2 => 1,
+        }
+    }
+
+    /// Configure the window, format and blending of a layer.
+    ///
+    /// This doesn't enable the layer. The change takes effect after the next reload.
+    pub fn configure_layer(&mut self, layer: Layer, config: &LayerConfig) {
+        let l = T::regs().layer(Self::layer_index(layer));
+        let bpp = config.pixel_format.bytes_per_pixel() as u16;
+        let line_len = config.width * bpp;
+
+        // The framebuffer line length has extra bytes for the bus width.
+        #[cfg(stm32h7)]
+        const LINE_LEN_EXTRA: u16 = 7;
+        #[cfg(not(stm32h7))]
+        const LINE_LEN_EXTRA: u16 = 3;
+
+        let (bf1, bf2) = match config.blending {
+            Blending::ConstantAlpha => (0b100, 0b101),
+            Blending::PixelAlpha => (0b110, 0b111),
+        };
+
+        unsafe {
+            l.whpcr().write(|w| {
+                w.set_whstpos(self.h_start + config.x);
+                w.set_whsppos(self.h_start + config.x + config.width - 1);
+            });
+            l.wvpcr().write(|w| {
+                w.set_wvstpos(self.v_start + config.y);
+                w.set_wvsppos(self.v_start + config.y + config.height - 1);
+            });
+            l.pfcr().write(|w| w.set_pf(config.pixel_format as u8));
+            l.cacr().write(|w| w.set_consta(config.alpha));
+            let [blue, green, red, alpha] = config.default_color.to_le_bytes();
+            l.dccr().write(|w| {
+                w.set_dcalpha(alpha);
+                w.set_dcred(red);
+                w.set_dcgreen(green);
+                w.set_dcblue(blue);
+            });
+            l.bfcr().write(|w| {
+                w.set_bf1(bf1);
+                w.set_bf2(bf2);
+            });
+            l.cfblr().write(|w| {
+                w.set_cfbp(line_len);
+                w.set_cfbll(line_len + LINE_LEN_EXTRA);
+            });
+            l.cfblnr().write(|w| w.set_cfblnbr(config.height));
+        }
+    }
+
+    /// Set the framebuffer a layer is read from.
+    ///
+    /// The change takes effect after the next reload, so swapping between two framebuffers
+    /// with [`Ltdc::reload`] doesn't tear.
+    ///
+    /// # Safety
+    ///
+    /// `framebuffer` must point to at least [`LayerConfig::framebuffer_len`] bytes, which must
+    /// stay valid as long as the layer is enabled. The LTDC reads them continuously, behind
+    /// the back of the compiler.
+    pub unsafe fn set_framebuffer(&mut self, layer: Layer, framebuffer: *const u8) {
+        let l = T::regs().layer(Self::layer_index(layer));
+        l.cfbar().write(|w| w.set_cfbadd(framebuffer as u32));
+    }
+
+    /// Enable or disable a layer. The change takes effect after the next reload.
+    pub fn set_layer_enabled(&mut self, layer: Layer, enabled: bool) {
+        let l = T::regs().layer(Self::layer_index(layer));
+        unsafe { l.cr().modify(|w| w.set_len(enabled)) }
+    }
+
+    /// Set the constant alpha of a layer, to fade it in or out.
+    /// The change takes effect after the next reload.
+    pub fn set_layer_alpha(&mut self, layer: Layer, alpha: u8) {
+        let l = T::regs().layer(Self::layer_index(layer));
+        unsafe { l.cacr().write(|w| w.set_consta(alpha)) }
+    }
+
+    /// Apply the layer changes immediately, which may tear the current frame.
+    pub fn reload_immediate(&mut self) {
+        unsafe { T::regs().srcr().write(|w| w.set_imr(true)) }
+    }
+
+    /// Apply the layer changes at the next vertical blanking, and wait for it.
+    pub async fn reload(&mut self) {
+        let r = T::regs();
+        unsafe {
+            r.icr().write(|w| w.set_crrif(true));
+            r.ier().modify(|w| w.set_rrie(true));
+            r.srcr().write(|w| w.set_vbr(true));
+        }
+
+        poll_fn(|cx| {
+            STATE.waker.register(cx.waker());
+            if unsafe { r.isr().read().rrif() } {
+                unsafe { r.icr().write(|w| w.set_crrif(true)) };
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Wait until the LTDC reaches the given line.
+    ///
+    /// Lines are counted from the start of the vertical synchronization, so the first
+    /// active line is `v_sync + v_back_porch`.
+    pub async fn wait_line(&mut self, line: u16) {
+        let r = T::regs();
+        unsafe {
+            r.icr().write(|w| w.set_clif(true));
+            r.lipcr().write(|w| w.set_lipos(line));
+            r.ier().modify(|w| w.set_lie(true));
+        }
+
+        poll_fn(|cx| {
+            STATE.waker.register(cx.waker());
+            if unsafe { r.isr().read().lif() } {
+                unsafe { r.icr().write(|w| w.set_clif(true)) };
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Wait for the start of the vertical blanking, when the last active line was sent.
+    ///
+    /// This is the time to draw into a framebuffer without tearing.
+    pub async fn wait_vsync(&mut self) {
+        self.wait_line(self.vblank_line).await
+    }
+
+    /// Check and clear the error flags.
+    ///
+    /// A FIFO underrun usually means the memory holding the framebuffers is too slow for
+    /// the pixel clock, or too busy.
+    pub fn check_errors(&mut self) -> Result<(), Error> {
+        let r = T::regs();
+        unsafe {
+            let isr = r.isr().read();
+            if isr.terrif() {
+                r.icr().write(|w| w.set_cterrif(true));
+                Err(Error::TransferError)
+            } else if isr.fuif() {
+                r.icr().write(|w| w.set_cfuif(true));
+                Err(Error::FifoUnderrun)
+            } else {
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<'d, T: Instance> Drop for Ltdc<'d, T> {
+    fn drop(&mut self) {
+        let r = T::regs();
+        unsafe {
+            r.ier().write(|_| {});
+            r.gcr().modify(|w| w.set_ltdcen(false));
+        }
+        T::disable();
+    }
+}
+
+pub(crate) mod sealed {
+    pub trait Instance: crate::rcc::RccPeripheral {
+        fn regs() -> crate::pac::ltdc::Ltdc;
+    }
+}
+
+pub trait Instance: sealed::Instance + 'static {
+    type Interrupt: Interrupt;
+}
+
+pin_trait!(ClkPin, Instance);
+pin_trait!(HSyncPin, Instance);
+pin_trait!(VSyncPin, Instance);
+pin_trait!(DePin, Instance);
+pin_trait!(R0Pin, Instance);
+pin_trait!(R1Pin, Instance);
+pin_trait!(R2Pin, Instance);
+pin_trait!(R3Pin, Instance);
+pin_trait!(R4Pin, Instance);
+pin_trait!(R5Pin, Instance);
+pin_trait!(R6Pin, Instance);
+pin_trait!(R7Pin, Instance);
+pin_trait!(G0Pin, Instance);
+pin_trait!(G1Pin, Instance);
+pin_trait!(G2Pin, Instance);
+pin_trait!(G3Pin, Instance);
+pin_trait!(G4Pin, Instance);
+pin_trait!(G5Pin, Instance);
+pin_trait!(G6Pin, Instance);
+pin_trait!(G7Pin, Instance);
+pin_trait!(B0Pin, Instance);
+pin_trait!(B1Pin, Instance);
+pin_trait!(B2Pin, Instance);
+pin_trait!(B3Pin, Instance);
+pin_trait!(B4Pin, Instance);
+pin_trait!(B5Pin, Instance);
+pin_trait!(B6Pin, Instance);
+pin_trait!(B7Pin, Instance);
+foreach_interrupt!(
+    ($inst:ident, ltdc, $block:ident, GLOBAL, $irq:ident) => {
+        impl sealed::Instance for crate::peripherals::$inst {
+            fn regs() -> crate::pac::ltdc::Ltdc {
+                crate::pac::$inst
+            }
+        }
+
+        impl Instance for crate::peripherals::$inst {
+            type Interrupt = crate::interrupt::$irq;
+        }
+    };
+);
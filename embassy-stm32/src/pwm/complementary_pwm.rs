@@ -0,0 +1,253 @@
+//! PWM with complementary outputs, for the advanced-control timers (TIM1, TIM8...).
+//!
+//! Each channel drives a pair of outputs, typically the high and low side switches of a
+//! half bridge: the complementary output is the inverse of the main one, with a dead time
+//! inserted at each transition so both switches are never on together. All the channels
+//! share the counter of the timer, which can be center-aligned to center the pulses of the
+//! three phases of a motor on each other.
+//!
+//! The break input cuts all the outputs at once in hardware, without any software latency,
+//! when the power stage signals a fault.
+
+use core::marker::PhantomData;
+use embassy::util::Unborrow;
+use embassy_hal_common::unborrow;
+use stm32_metapac::timer::vals::Ckd;
+
+use super::*;
+#[allow(unused_imports)]
+use crate::gpio::sealed::{AFType, Pin};
+use crate::time::Hertz;
+
+pub struct ComplementaryPwm<'d, T> {
+    phantom: PhantomData<&'d mut T>,
+    inner: T,
+    counting_mode: CountingMode,
+    freq: Hertz,
+}
+
+impl<'d, T: ComplementaryCaptureCompare16bitInstance> ComplementaryPwm<'d, T> {
+    pub fn new_1ch<F: Into<Hertz>>(
+        tim: impl Unborrow<Target = T> + 'd,
+        ch1: impl Unborrow<Target = impl Channel1Pin<T>> + 'd,
+        ch1n: impl Unborrow<Target = impl Channel1ComplementaryPin<T>> + 'd,
+        freq: F,
+    ) -> Self {
+        Self::new_inner(tim, freq, move || {
+            config_pins!(ch1, ch1n);
+        })
+    }
+
+    pub fn new_2ch<F: Into<Hertz>>(
+        tim: impl Unborrow<Target = T> + 'd,
+        ch1: impl Unborrow<Target = impl Channel1Pin<T>> + 'd,
+        ch1n: impl Unborrow<Target = impl Channel1ComplementaryPin<T>> + 'd,
+        ch2: impl Unborrow<Target = impl Channel2Pin<T>> + 'd,
+        ch2n: impl Unborrow<Target = impl Channel2ComplementaryPin<T>> + 'd,
+        freq: F,
+    ) -> Self {
+        Self::new_inner(tim, freq, move || {
+            config_pins!(ch1, ch1n, ch2, ch2n);
+        })
+    }
+
+    /// Three half bridges, for a three-phase motor.
+    pub fn new_3ch<F: Into<Hertz>>(
+        tim: impl Unborrow<Target = T> + 'd,
+        ch1: impl Unborrow<Target = impl Channel1Pin<T>> + 'd,
+        ch1n: impl Unborrow<Target = impl Channel1ComplementaryPin<T>> + 'd,
+        ch2: impl Unborrow<Target = impl Channel2Pin<T>> + 'd,
+        ch2n: impl Unborrow<Target = impl Channel2ComplementaryPin<T>> + 'd,
+        ch3: impl Unborrow<Target = impl Channel3Pin<T>> + 'd,
+        ch3n: impl Unborrow<Target = impl Channel3ComplementaryPin<T>> + 'd,
+        freq: F,
+    ) -> Self {
+        Self::new_inner(tim, freq, move || {
+            config_pins!(ch1, ch1n, ch2, ch2n, ch3, ch3n);
+        })
+    }
+
+    fn new_inner<F: Into<Hertz>>(
+        tim: impl Unborrow<Target = T> + 'd,
+        freq: F,
+        configure_pins: impl FnOnce(),
+    ) -> Self {
+        unborrow!(tim);
+        let freq = freq.into();
+
+        T::enable();
+        <T as crate::rcc::sealed::RccPeripheral>::reset();
+
+        configure_pins();
+
+        let mut this = Self {
+            inner: tim,
+            phantom: PhantomData,
+            counting_mode: CountingMode::EdgeAligned,
+            freq,
+        };
+
+        this.inner.set_frequency(freq);
+        this.inner.start();
+
+        unsafe {
+            for channel in [Channel::Ch1, Channel::Ch2, Channel::Ch3, Channel::Ch4] {
+                this.inner
+                    .set_output_compare_mode(channel, OutputCompareMode::PwmMode1);
+                // New duty cycles are applied at the start of a period, for all the channels
+                // at once.
+                this.inner.set_output_compare_preload(channel, true);
+            }
+            this.inner.enable_outputs(true);
+        }
+        this
+    }
+
+    /// Enable both outputs of a channel.
+    pub fn enable(&mut self, channel: Channel) {
+        unsafe {
+            self.inner.enable_channel(channel, true);
+            self.inner.enable_complementary_channel(channel, true);
+        }
+    }
+
+    /// Disable both outputs of a channel.
+    pub fn disable(&mut self, channel: Channel) {
+        unsafe {
+            self.inner.enable_channel(channel, false);
+            self.inner.enable_complementary_channel(channel, false);
+        }
+    }
+
+    /// Set the PWM frequency. In center-aligned mode, the counter counts up and down in a
+    /// period, so it runs twice as fast.
+    pub fn set_freq<F: Into<Hertz>>(&mut self, freq: F) {
+        let freq = freq.into();
+        self.freq = freq;
+        if self.counting_mode.is_center_aligned() {
+            self.inner.set_frequency(Hertz(freq.0 * 2));
+        } else {
+            self.inner.set_frequency(freq);
+        }
+    }
+
+    /// Select edge or center alignment. This keeps the current PWM frequency.
+    pub fn set_counting_mode(&mut self, mode: CountingMode) {
+        self.counting_mode = mode;
+        unsafe { self.inner.set_counting_mode(mode) }
+        self.set_freq(self.freq);
+    }
+
+    pub fn get_max_duty(&self) -> u16 {
+        unsafe { self.inner.get_max_compare_value() }
+    }
+
+    pub fn set_duty(&mut self, channel: Channel, duty: u16) {
+        assert!(duty < self.get_max_duty());
+        unsafe { self.inner.set_compare_value(channel, duty) }
+    }
+
+    /// Set the duty cycles of several channels, applied in the same period.
+    pub fn set_duties(&mut self, duties: &[(Channel, u16)]) {
+        let max = self.get_max_duty();
+        unsafe {
+            // Don't let a period start halfway through.
+            self.inner.set_update_disable(true);
+            for &(channel, duty) in duties {
+                assert!(duty < max);
+                self.inner.set_compare_value(channel, duty);
+            }
+            self.inner.set_update_disable(false);
+        }
+    }
+
+    /// Set the dead time inserted before each output turns on, in nanoseconds.
+    ///
+    /// The dead time is rounded up to what the timer can generate, up to 1008 ticks of four
+    /// times the timer clock period.
+    pub fn set_dead_time(&mut self, ns: u32) {
+        let ticks = (u64::from(ns) * u64::from(T::frequency().0) + 999_999_999) / 1_000_000_000;
+        let (ckd, value) = compute_dead_time_value(ticks.min(u32::MAX as u64) as u32);
+        unsafe {
+            self.inner.set_dead_time_clock_division(ckd);
+            self.inner.set_dead_time_value(value);
+        }
+    }
+
+    /// Enable the break input: while it's active, all the outputs are cut.
+    ///
+    /// The outputs stay disabled after a break, until [`clear_break`](Self::clear_break) is
+    /// called.
+    pub fn enable_break(
+        &mut self,
+        pin: impl Unborrow<Target = impl BreakInputPin<T>> + 'd,
+        polarity: BreakPolarity,
+    ) {
+        unborrow!(pin);
+        critical_section::with(|_| unsafe {
+            pin.set_as_af(pin.af_num(), AFType::Input);
+        });
+        unsafe {
+            self.inner.clear_break_flag();
+            self.inner.set_break(Some(polarity));
+        }
+    }
+
+    pub fn disable_break(&mut self) {
+        unsafe { self.inner.set_break(None) }
+    }
+
+    /// Whether the outputs were cut by the break input.
+    pub fn is_broken(&self) -> bool {
+        unsafe { !self.inner.outputs_enabled() }
+    }
+
+    /// Re-enable the outputs after a break. Returns `false` if the break input is still
+    /// active: the outputs stay disabled.
+    pub fn clear_break(&mut self) -> bool {
+        unsafe {
+            self.inner.clear_break_flag();
+            self.inner.enable_outputs(true);
+            self.inner.outputs_enabled()
+        }
+    }
+
+    /// Select the event output on TRGO, to trigger other peripherals.
+    pub fn set_trigger_output(&mut self, trigger: TriggerOutput) {
+        unsafe { self.inner.set_trigger_output(trigger) }
+    }
+
+    /// Output a trigger on TRGO once per period, when the counter counts up past `point`.
+    ///
+    /// This uses channel 4, whose outputs must stay disabled. Close to the maximum duty,
+    /// the trigger comes while the low side switches are on, which is when the current in
+    /// low side shunts can be measured.
+    pub fn set_trigger_point(&mut self, point: u16) {
+        unsafe {
+            self.inner
+                .set_output_compare_mode(Channel::Ch4, OutputCompareMode::PwmMode2);
+            self.inner.set_compare_value(Channel::Ch4, point);
+            self.inner.set_trigger_output(TriggerOutput::Compare4);
+        }
+    }
+}
+
+/// Dead time generator setting for `ticks` of the timer clock: the clock division, and the
+/// DTG value.
+///
+/// The DTG encodes the dead time in four ranges, with a coarser step in each: up to 127
+/// clock periods by 1, 254 by 2, 504 by 8 and 1008 by 16.
+fn compute_dead_time_value(ticks: u32) -> (Ckd, u8) {
+    for (ckd, div) in [(Ckd::DIV1, 1), (Ckd::DIV2, 2), (Ckd::DIV4, 4)] {
+        let t = (ticks + div - 1) / div;
+        let value = match t {
+            0..=127 => t,
+            128..=254 => 0x80 | ((t + 1) / 2 - 64),
+            255..=504 => 0xC0 | ((t + 7) / 8 - 32),
+            505..=1008 => 0xE0 | ((t + 15) / 16 - 32),
+            _ => continue,
+        };
+        return (ckd, value as u8);
+    }
+    (Ckd::DIV4, 0xFF)
+}
@@ -1,3 +1,19 @@
+macro_rules! config_pins {
+    ($($pin:ident),*) => {
+        unborrow!($($pin),*);
+        // NOTE(unsafe) Exclusive access to the registers
+        critical_section::with(|_| unsafe {
+            $(
+                $pin.set_low();
+                $pin.set_as_af($pin.af_num(), AFType::OutputPushPull);
+                #[cfg(gpio_v2)]
+                $pin.set_speed(crate::gpio::Speed::VeryHigh);
+            )*
+        })
+    };
+}
+
+pub mod complementary_pwm;
 pub mod simple_pwm;
 
 #[cfg(feature = "unstable-pac")]
@@ -51,6 +67,76 @@ impl From<OutputCompareMode> for stm32_metapac::timer::vals::Ocm {
     }
 }
 
+/// Alignment of the counter, for the advanced-control timers.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CountingMode {
+    /// The counter counts up to the maximum, then restarts from 0.
+    EdgeAligned,
+    /// The counter counts up then down. The compare interrupt flags are set when counting
+    /// down.
+    CenterAlignedDown,
+    /// The counter counts up then down. The compare interrupt flags are set when counting up.
+    CenterAlignedUp,
+    /// The counter counts up then down. The compare interrupt flags are set both ways.
+    CenterAlignedBoth,
+}
+
+impl CountingMode {
+    pub fn is_center_aligned(&self) -> bool {
+        *self != CountingMode::EdgeAligned
+    }
+}
+
+impl From<CountingMode> for stm32_metapac::timer::vals::Cms {
+    fn from(mode: CountingMode) -> Self {
+        match mode {
+            CountingMode::EdgeAligned => stm32_metapac::timer::vals::Cms::EDGEALIGNED,
+            CountingMode::CenterAlignedDown => stm32_metapac::timer::vals::Cms::CENTERALIGNED1,
+            CountingMode::CenterAlignedUp => stm32_metapac::timer::vals::Cms::CENTERALIGNED2,
+            CountingMode::CenterAlignedBoth => stm32_metapac::timer::vals::Cms::CENTERALIGNED3,
+        }
+    }
+}
+
+/// Event output on TRGO, to trigger other peripherals like an ADC.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TriggerOutput {
+    /// Counter reset, by software or a slave mode trigger.
+    Reset,
+    /// Counter enable.
+    Enable,
+    /// Update event, at the end of each period.
+    Update,
+    /// Capture or compare match on channel 1.
+    ComparePulse,
+    Compare1,
+    Compare2,
+    Compare3,
+    Compare4,
+}
+
+impl From<TriggerOutput> for stm32_metapac::timer::vals::Mms {
+    fn from(trigger: TriggerOutput) -> Self {
+        match trigger {
+            TriggerOutput::Reset => stm32_metapac::timer::vals::Mms::RESET,
+            TriggerOutput::Enable => stm32_metapac::timer::vals::Mms::ENABLE,
+            TriggerOutput::Update => stm32_metapac::timer::vals::Mms::UPDATE,
+            TriggerOutput::ComparePulse => stm32_metapac::timer::vals::Mms::COMPAREPULSE,
+            TriggerOutput::Compare1 => stm32_metapac::timer::vals::Mms::COMPAREOC1,
+            TriggerOutput::Compare2 => stm32_metapac::timer::vals::Mms::COMPAREOC2,
+            TriggerOutput::Compare3 => stm32_metapac::timer::vals::Mms::COMPAREOC3,
+            TriggerOutput::Compare4 => stm32_metapac::timer::vals::Mms::COMPAREOC4,
+        }
+    }
+}
+
+/// Level of the break input which disables the outputs.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BreakPolarity {
+    ActiveLow,
+    ActiveHigh,
+}
+
 pub(crate) mod sealed {
     use super::*;
 
@@ -75,12 +161,48 @@ pub(crate) mod sealed {
 
         unsafe fn get_max_compare_value(&self) -> u32;
     }
+
+    pub trait ComplementaryCaptureCompare16bitInstance:
+        CaptureCompare16bitInstance + crate::timer::sealed::AdvancedControlInstance
+    {
+        unsafe fn set_dead_time_clock_division(&mut self, value: stm32_metapac::timer::vals::Ckd);
+
+        unsafe fn set_dead_time_value(&mut self, value: u8);
+
+        unsafe fn enable_complementary_channel(&mut self, channel: Channel, enable: bool);
+
+        unsafe fn set_output_compare_preload(&mut self, channel: Channel, enable: bool);
+
+        unsafe fn set_counting_mode(&mut self, mode: CountingMode);
+
+        unsafe fn set_update_disable(&mut self, disable: bool);
+
+        /// Main output enable: the outputs are only driven while it's set.
+        unsafe fn enable_outputs(&mut self, enable: bool);
+
+        unsafe fn outputs_enabled(&self) -> bool;
+
+        /// Enable the break input with the given polarity, or disable it.
+        unsafe fn set_break(&mut self, polarity: Option<BreakPolarity>);
+
+        /// Read and clear the break flag.
+        unsafe fn clear_break_flag(&mut self) -> bool;
+
+        unsafe fn set_trigger_output(&mut self, trigger: TriggerOutput);
+    }
 }
 
 pub trait CaptureCompare16bitInstance:
     sealed::CaptureCompare16bitInstance + crate::timer::Basic16bitInstance + 'static
 {
 }
+pub trait ComplementaryCaptureCompare16bitInstance:
+    sealed::ComplementaryCaptureCompare16bitInstance
+    + CaptureCompare16bitInstance
+    + crate::timer::AdvancedControlInstance
+    + 'static
+{
+}
 pub trait CaptureCompare32bitInstance:
     sealed::CaptureCompare32bitInstance
     + CaptureCompare16bitInstance
@@ -240,6 +362,88 @@ foreach_interrupt! {
         impl CaptureCompare16bitInstance for crate::peripherals::$inst {
 
         }
+
+        impl crate::pwm::sealed::ComplementaryCaptureCompare16bitInstance for crate::peripherals::$inst {
+            unsafe fn set_dead_time_clock_division(
+                &mut self,
+                value: stm32_metapac::timer::vals::Ckd,
+            ) {
+                use crate::timer::sealed::AdvancedControlInstance;
+                Self::regs_advanced().cr1().modify(|w| w.set_ckd(value));
+            }
+
+            unsafe fn set_dead_time_value(&mut self, value: u8) {
+                use crate::timer::sealed::AdvancedControlInstance;
+                Self::regs_advanced().bdtr().modify(|w| w.set_dtg(value));
+            }
+
+            unsafe fn enable_complementary_channel(&mut self, channel: Channel, enable: bool) {
+                use crate::timer::sealed::AdvancedControlInstance;
+                Self::regs_advanced()
+                    .ccer()
+                    .modify(|w| w.set_ccne(channel.raw(), enable));
+            }
+
+            unsafe fn set_output_compare_preload(&mut self, channel: Channel, enable: bool) {
+                use crate::timer::sealed::AdvancedControlInstance;
+                let raw_channel: usize = channel.raw();
+                Self::regs_advanced()
+                    .ccmr_output(raw_channel / 2)
+                    .modify(|w| w.set_ocpe(raw_channel % 2, enable));
+            }
+
+            unsafe fn set_counting_mode(&mut self, mode: CountingMode) {
+                use crate::timer::sealed::AdvancedControlInstance;
+                let r = Self::regs_advanced();
+                // The mode can only be changed while the counter is stopped.
+                let cen = r.cr1().read().cen();
+                r.cr1().modify(|w| w.set_cen(false));
+                r.cr1().modify(|w| w.set_cms(mode.into()));
+                r.cr1().modify(|w| w.set_cen(cen));
+            }
+
+            unsafe fn set_update_disable(&mut self, disable: bool) {
+                use crate::timer::sealed::AdvancedControlInstance;
+                Self::regs_advanced().cr1().modify(|w| w.set_udis(disable));
+            }
+
+            unsafe fn enable_outputs(&mut self, enable: bool) {
+                use crate::timer::sealed::AdvancedControlInstance;
+                Self::regs_advanced().bdtr().modify(|w| w.set_moe(enable));
+            }
+
+            unsafe fn outputs_enabled(&self) -> bool {
+                use crate::timer::sealed::AdvancedControlInstance;
+                Self::regs_advanced().bdtr().read().moe()
+            }
+
+            unsafe fn set_break(&mut self, polarity: Option<BreakPolarity>) {
+                use crate::timer::sealed::AdvancedControlInstance;
+                Self::regs_advanced().bdtr().modify(|w| {
+                    w.set_bke(polarity.is_some());
+                    w.set_bkp(polarity == Some(BreakPolarity::ActiveHigh));
+                });
+            }
+
+            unsafe fn clear_break_flag(&mut self) -> bool {
+                use crate::timer::sealed::AdvancedControlInstance;
+                let r = Self::regs_advanced();
+                let broken = r.sr().read().bif();
+                if broken {
+                    r.sr().modify(|w| w.set_bif(false));
+                }
+                broken
+            }
+
+            unsafe fn set_trigger_output(&mut self, trigger: TriggerOutput) {
+                use crate::timer::sealed::AdvancedControlInstance;
+                Self::regs_advanced().cr2().modify(|w| w.set_mms(trigger.into()));
+            }
+        }
+
+        impl ComplementaryCaptureCompare16bitInstance for crate::peripherals::$inst {
+
+        }
     };
 }
 
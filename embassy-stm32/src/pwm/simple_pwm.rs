@@ -12,21 +12,6 @@ pub struct SimplePwm<'d, T> {
     inner: T,
 }
 
-macro_rules! config_pins {
-    ($($pin:ident),*) => {
-        unborrow!($($pin),*);
-        // NOTE(unsafe) Exclusive access to the registers
-        critical_section::with(|_| unsafe {
-            $(
-                $pin.set_low();
-                $pin.set_as_af($pin.af_num(), AFType::OutputPushPull);
-                #[cfg(gpio_v2)]
-                $pin.set_speed(crate::gpio::Speed::VeryHigh);
-            )*
-        })
-    };
-}
-
 impl<'d, T: CaptureCompare16bitInstance> SimplePwm<'d, T> {
     pub fn new_1ch<F: Into<Hertz>>(
         tim: impl Unborrow<Target = T> + 'd,
@@ -131,4 +116,35 @@ impl<'d, T: CaptureCompare16bitInstance> SimplePwm<'d, T> {
         assert!(duty < self.get_max_duty());
         unsafe { self.inner.set_compare_value(channel, duty) }
     }
+
+    pub fn set_output_compare_mode(&mut self, channel: Channel, mode: OutputCompareMode) {
+        unsafe { self.inner.set_output_compare_mode(channel, mode) }
+    }
+
+    /// In one-pulse mode, the counter stops at the end of the period instead of restarting:
+    /// each [`trigger`](Self::trigger) generates a single pulse on the enabled channels.
+    ///
+    /// With [`OutputCompareMode::PwmMode2`], a channel is inactive until the counter reaches
+    /// its duty, and active until the end of the period: the pulse starts after a delay of
+    /// `duty` ticks, and lasts `max_duty - duty` ticks.
+    pub fn set_one_pulse_mode(&mut self, enable: bool) {
+        self.inner.stop();
+        unsafe {
+            T::regs().cr1().modify(|w| w.set_opm(enable));
+        }
+        if !enable {
+            self.inner.start();
+        }
+    }
+
+    /// Start a period from the beginning. In one-pulse mode, this generates one pulse.
+    pub fn trigger(&mut self) {
+        self.inner.reset();
+        self.inner.start();
+    }
+
+    /// Whether the counter is running. In one-pulse mode, it stops after the pulse.
+    pub fn is_running(&self) -> bool {
+        unsafe { T::regs().cr1().read().cen() }
+    }
 }
@@ -36,6 +36,65 @@ pub enum VoltageScale {
     Scale3,
 }
 
+impl Default for VoltageScale {
+    fn default() -> Self {
+        Self::Scale1
+    }
+}
+
+/// Power supply configuration
+///
+/// This must match the way VCORE is supplied on the board. It can only be set once after
+/// a power-on reset, and a configuration that doesn't match the board leaves `init` stuck
+/// waiting for the supply to be ready, or can damage the chip.
+#[derive(Copy, Clone, PartialEq)]
+pub enum SupplyConfig {
+    /// The LDO supplies VCORE
+    Ldo,
+    /// VCORE is supplied externally, the LDO and the SMPS are off
+    Bypass,
+    /// The SMPS supplies VCORE, the LDO is off
+    #[cfg(pwr_h7smps)]
+    DirectSmps,
+    /// The SMPS supplies the LDO, which supplies VCORE
+    #[cfg(pwr_h7smps)]
+    SmpsLdo(SmpsVoltage),
+    /// The SMPS supplies the LDO and external circuits, the LDO supplies VCORE
+    #[cfg(pwr_h7smps)]
+    SmpsExternalLdo(SmpsVoltage),
+}
+
+impl Default for SupplyConfig {
+    #[cfg(pwr_h7)]
+    fn default() -> Self {
+        Self::Ldo
+    }
+
+    /// Direct SMPS, which is how the Nucleo boards are wired with the default solder bridges
+    #[cfg(pwr_h7smps)]
+    fn default() -> Self {
+        Self::DirectSmps
+    }
+}
+
+/// Output voltage of the SMPS when it supplies the LDO
+#[cfg(pwr_h7smps)]
+#[derive(Copy, Clone, PartialEq)]
+pub enum SmpsVoltage {
+    V1_8,
+    V2_5,
+}
+
+#[cfg(pwr_h7smps)]
+impl SmpsVoltage {
+    fn sdlevel(self) -> u8 {
+        match self {
+            SmpsVoltage::V1_8 => 0b01,
+            SmpsVoltage::V2_5 => 0b10,
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 pub enum AdcClockSource {
     Pll2PCk,
@@ -110,6 +169,9 @@ pub struct Config {
     pub pll2: PllConfig,
     pub pll3: PllConfig,
     pub adc_clock_source: AdcClockSource,
+    pub supply_config: SupplyConfig,
+    /// Scale0 is reached with the overdrive, which needs the LDO
+    pub voltage_scale: VoltageScale,
 }
 
 /// Setup traceclk
@@ -398,10 +460,8 @@ impl<'d, T: McoInstance> Mco<'d, T> {
     }
 }
 
-pub(crate) unsafe fn init(mut config: Config) {
-    // TODO make configurable?
-    let enable_overdrive = false;
-
+/// Write the supply configuration in CR3
+unsafe fn supply_setup(supply: SupplyConfig) {
     // NB. The lower bytes of CR3 can only be written once after
     // POR, and must be written with a valid combination. Refer to
     // RM0433 Rev 7 6.8.4. This is partially enforced by dropping
@@ -410,17 +470,64 @@ pub(crate) unsafe fn init(mut config: Config) {
     #[cfg(pwr_h7)]
     PWR.cr3().modify(|w| {
         w.set_scuen(true);
-        w.set_ldoen(true);
-        w.set_bypass(false);
+        w.set_ldoen(supply == SupplyConfig::Ldo);
+        w.set_bypass(supply == SupplyConfig::Bypass);
     });
 
     #[cfg(pwr_h7smps)]
-    PWR.cr3().modify(|w| {
-        // hardcode "Direct SPMS" for now, this is what works on nucleos with the
-        // default solderbridge configuration.
-        w.set_sden(true);
-        w.set_ldoen(false);
+    PWR.cr3().modify(|w| match supply {
+        SupplyConfig::Ldo => {
+            w.set_sden(false);
+            w.set_ldoen(true);
+            w.set_bypass(false);
+        }
+        SupplyConfig::Bypass => {
+            w.set_sden(false);
+            w.set_ldoen(false);
+            w.set_bypass(true);
+        }
+        SupplyConfig::DirectSmps => {
+            w.set_sden(true);
+            w.set_ldoen(false);
+            w.set_bypass(false);
+        }
+        SupplyConfig::SmpsLdo(voltage) => {
+            w.set_sden(true);
+            w.set_sdlevel(voltage.sdlevel());
+            w.set_smpsexthp(false);
+            w.set_ldoen(true);
+            w.set_bypass(false);
+        }
+        SupplyConfig::SmpsExternalLdo(voltage) => {
+            w.set_sden(true);
+            w.set_sdlevel(voltage.sdlevel());
+            w.set_smpsexthp(true);
+            w.set_ldoen(true);
+            w.set_bypass(false);
+        }
     });
+}
+
+pub(crate) unsafe fn init(mut config: Config) {
+    let enable_overdrive = config.voltage_scale == VoltageScale::Scale0;
+
+    // The overdrive needs the LDO to regulate VCORE.
+    #[cfg(pwr_h7smps)]
+    assert!(
+        !enable_overdrive
+            || !matches!(
+                config.supply_config,
+                SupplyConfig::DirectSmps | SupplyConfig::Bypass
+            ),
+        "VOS0 needs the LDO"
+    );
+    #[cfg(pwr_h7)]
+    assert!(
+        !enable_overdrive || config.supply_config == SupplyConfig::Ldo,
+        "VOS0 needs the LDO"
+    );
+
+    supply_setup(config.supply_config);
 
     // Validate the supply configuration. If you are stuck here, it is
     // because the voltages on your board do not match those specified
@@ -429,21 +536,25 @@ pub(crate) unsafe fn init(mut config: Config) {
     // 1.0V.
     while !PWR.csr1().read().actvosrdy() {}
 
-    // Go to Scale 1
-    PWR.d3cr().modify(|w| w.set_vos(0b11));
+    // VOS0 is VOS1 with the overdrive
+    PWR.d3cr().modify(|w| {
+        w.set_vos(match config.voltage_scale {
+            VoltageScale::Scale0 | VoltageScale::Scale1 => 0b11,
+            VoltageScale::Scale2 => 0b10,
+            VoltageScale::Scale3 => 0b01,
+        })
+    });
     while !PWR.d3cr().read().vosrdy() {}
 
-    let pwr_vos = if !enable_overdrive {
-        VoltageScale::Scale1
-    } else {
+    if enable_overdrive {
         critical_section::with(|_| {
             RCC.apb4enr().modify(|w| w.set_syscfgen(true));
 
             SYSCFG.pwrcr().modify(|w| w.set_oden(1));
         });
         while !PWR.d3cr().read().vosrdy() {}
-        VoltageScale::Scale0
-    };
+    }
+    let pwr_vos = config.voltage_scale;
 
     // Freeze the core clocks, returning a Core Clocks Distribution
     // and Reset (CCDR) structure. The actual frequency of the clocks
@@ -513,7 +624,10 @@ pub(crate) unsafe fn init(mut config: Config) {
         VoltageScale::Scale2 => (300_000_000, 150_000_000, 75_000_000),
         _ => (200_000_000, 100_000_000, 50_000_000),
     };
-    assert!(sys_d1cpre_ck <= sys_d1cpre_ck_max);
+    assert!(
+        sys_d1cpre_ck <= sys_d1cpre_ck_max,
+        "sys_ck is too high for the voltage scale"
+    );
 
     let rcc_hclk = config.hclk.map(|v| v.0).unwrap_or(sys_d1cpre_ck / 2);
     assert!(rcc_hclk <= rcc_hclk_max);
@@ -1,5 +1,5 @@
 use crate::pac::rcc::vals::{Hpre, Msirange, Pllsrc, Ppre, Sw};
-use crate::pac::{FLASH, RCC};
+use crate::pac::{FLASH, PWR, RCC};
 use crate::rcc::{set_freqs, Clocks};
 use crate::time::Hertz;
 use crate::time::U32Ext;
@@ -7,6 +7,9 @@ use crate::time::U32Ext;
 /// HSI16 speed
 pub const HSI16_FREQ: u32 = 16_000_000;
 
+/// LSE speed
+pub const LSE_FREQ: u32 = 32_768;
+
 /// System clock mux source
 #[derive(Clone, Copy)]
 pub enum ClockSrc {
@@ -99,6 +102,68 @@ pub enum PLLSource {
     MSI(MSIRange),
 }
 
+/// Kernel clock of a USART
+#[derive(Clone, Copy, PartialEq)]
+pub enum UsartClockSrc {
+    PCLK,
+    SYSCLK,
+    HSI16,
+    LSE,
+}
+
+/// Kernel clock of an I2C
+#[derive(Clone, Copy, PartialEq)]
+pub enum I2cClockSrc {
+    PCLK,
+    SYSCLK,
+    HSI16,
+}
+
+/// Kernel clock of the ADCs
+#[derive(Clone, Copy, PartialEq)]
+pub enum AdcClockSrc {
+    /// The R output of PLLSAI1, which must be enabled in [`Config::pllsai1`]
+    PLLSAI1R,
+    SYSCLK,
+}
+
+impl UsartClockSrc {
+    /// Start the clock, and return its frequency and its `USARTxSEL` value
+    unsafe fn enable(self, pclk: u32, sys_clk: u32) -> (u32, u8) {
+        match self {
+            UsartClockSrc::PCLK => (pclk, 0b00),
+            UsartClockSrc::SYSCLK => (sys_clk, 0b01),
+            UsartClockSrc::HSI16 => (enable_hsi16(), 0b10),
+            UsartClockSrc::LSE => (enable_lse(), 0b11),
+        }
+    }
+}
+
+impl I2cClockSrc {
+    /// Start the clock, and return its frequency and its `I2CxSEL` value
+    unsafe fn enable(self, pclk: u32, sys_clk: u32) -> (u32, u8) {
+        match self {
+            I2cClockSrc::PCLK => (pclk, 0b00),
+            I2cClockSrc::SYSCLK => (sys_clk, 0b01),
+            I2cClockSrc::HSI16 => (enable_hsi16(), 0b10),
+        }
+    }
+}
+
+unsafe fn enable_hsi16() -> u32 {
+    RCC.cr().modify(|w| w.set_hsion(true));
+    while !RCC.cr().read().hsirdy() {}
+    HSI16_FREQ
+}
+
+unsafe fn enable_lse() -> u32 {
+    // The LSE is in the backup domain, which is write protected.
+    PWR.cr1().modify(|w| w.set_dbp(true));
+    RCC.bdcr().modify(|w| w.set_lseon(true));
+    while !RCC.bdcr().read().lserdy() {}
+    LSE_FREQ
+}
+
 seq_macro::seq!(N in 8..=86 {
     #[derive(Clone, Copy)]
     pub enum PLLMul {
@@ -279,6 +344,14 @@ pub struct Config {
     )>,
     #[cfg(not(any(stm32l471, stm32l475, stm32l476, stm32l486)))]
     pub hsi48: bool,
+    pub usart1_clock: UsartClockSrc,
+    pub usart2_clock: UsartClockSrc,
+    pub usart3_clock: UsartClockSrc,
+    pub i2c1_clock: I2cClockSrc,
+    pub i2c2_clock: I2cClockSrc,
+    pub i2c3_clock: I2cClockSrc,
+    /// The ADCs have no clock when `None`
+    pub adc_clock: Option<AdcClockSrc>,
 }
 
 impl Default for Config {
@@ -292,11 +365,20 @@ impl Default for Config {
             pllsai1: None,
             #[cfg(not(any(stm32l471, stm32l475, stm32l476, stm32l486)))]
             hsi48: false,
+            usart1_clock: UsartClockSrc::PCLK,
+            usart2_clock: UsartClockSrc::PCLK,
+            usart3_clock: UsartClockSrc::PCLK,
+            i2c1_clock: I2cClockSrc::PCLK,
+            i2c2_clock: I2cClockSrc::PCLK,
+            i2c3_clock: I2cClockSrc::PCLK,
+            adc_clock: None,
         }
     }
 }
 
 pub(crate) unsafe fn init(config: Config) {
+    let mut pllsai1_r_freq = None;
+
     let (sys_clk, sw) = match config.mux {
         ClockSrc::MSI(range) => {
             // Enable MSI
@@ -391,6 +473,9 @@ pub(crate) unsafe fn init(config: Config) {
             }
 
             if let Some((mul, prediv, r_div, q_div, p_div)) = config.pllsai1 {
+                pllsai1_r_freq =
+                    r_div.map(|r_div| (src_freq / prediv.to_div() * mul.to_mul()) / r_div.to_div());
+
                 RCC.pllsai1cfgr().write(move |w| {
                     w.set_pllsai1n(mul.into());
                     w.set_pllsai1m(prediv.into());
@@ -486,6 +571,34 @@ pub(crate) unsafe fn init(config: Config) {
         }
     };
 
+    let (usart1_freq, usart1_sel) = config.usart1_clock.enable(apb2_freq, sys_clk);
+    let (usart2_freq, usart2_sel) = config.usart2_clock.enable(apb1_freq, sys_clk);
+    let (usart3_freq, usart3_sel) = config.usart3_clock.enable(apb1_freq, sys_clk);
+    let (i2c1_freq, i2c1_sel) = config.i2c1_clock.enable(apb1_freq, sys_clk);
+    let (i2c2_freq, i2c2_sel) = config.i2c2_clock.enable(apb1_freq, sys_clk);
+    let (i2c3_freq, i2c3_sel) = config.i2c3_clock.enable(apb1_freq, sys_clk);
+    let (adc_freq, adc_sel) = match config.adc_clock {
+        None => (None, 0b00),
+        Some(AdcClockSrc::PLLSAI1R) => (
+            Some(unwrap!(
+                pllsai1_r_freq,
+                "The R output of PLLSAI1 must be enabled to clock the ADCs"
+            )),
+            0b01,
+        ),
+        Some(AdcClockSrc::SYSCLK) => (Some(sys_clk), 0b11),
+    };
+
+    RCC.ccipr().modify(|w| {
+        w.set_usart1sel(usart1_sel);
+        w.set_usart2sel(usart2_sel);
+        w.set_usart3sel(usart3_sel);
+        w.set_i2c1sel(i2c1_sel);
+        w.set_i2c2sel(i2c2_sel);
+        w.set_i2c3sel(i2c3_sel);
+        w.set_adcsel(adc_sel);
+    });
+
     set_freqs(Clocks {
         sys: sys_clk.hz(),
         ahb1: ahb_freq.hz(),
@@ -495,5 +608,12 @@ pub(crate) unsafe fn init(config: Config) {
         apb2: apb2_freq.hz(),
         apb1_tim: apb1_tim_freq.hz(),
         apb2_tim: apb2_tim_freq.hz(),
+        usart1: usart1_freq.hz(),
+        usart2: usart2_freq.hz(),
+        usart3: usart3_freq.hz(),
+        i2c1: i2c1_freq.hz(),
+        i2c2: i2c2_freq.hz(),
+        i2c3: i2c3_freq.hz(),
+        adc: adc_freq.map(|f| f.hz()),
     });
 }
@@ -59,8 +59,22 @@ pub struct Clocks {
     #[cfg(rcc_f1)]
     pub adc: Hertz,
 
-    #[cfg(any(rcc_h7, rcc_h7ab))]
+    #[cfg(any(rcc_h7, rcc_h7ab, rcc_l4))]
     pub adc: Option<Hertz>,
+
+    // Kernel clocks selected in the config, used by `RccPeripheral::frequency`
+    #[cfg(rcc_l4)]
+    pub usart1: Hertz,
+    #[cfg(rcc_l4)]
+    pub usart2: Hertz,
+    #[cfg(rcc_l4)]
+    pub usart3: Hertz,
+    #[cfg(rcc_l4)]
+    pub i2c1: Hertz,
+    #[cfg(rcc_l4)]
+    pub i2c2: Hertz,
+    #[cfg(rcc_l4)]
+    pub i2c3: Hertz,
 }
 
 /// Frozen clock frequencies
@@ -1,4 +1,4 @@
-use crate::pac::RCC;
+use crate::pac::{FLASH, PWR, RCC};
 use crate::rcc::{set_freqs, Clocks};
 use crate::time::U32Ext;
 
@@ -19,6 +19,17 @@ pub enum ClockSrc {
     HSI16,
 }
 
+/// Voltage range of the main regulator
+///
+/// The maximum system clock frequency depends on this value.
+#[derive(Clone, Copy, PartialEq)]
+pub enum VoltageScale {
+    /// 1.2 V, up to 48 MHz
+    Range1,
+    /// 1.0 V, up to 16 MHz
+    Range2,
+}
+
 /// AHB prescaler
 #[derive(Clone, Copy, PartialEq)]
 pub enum AHBPrescaler {
@@ -88,6 +99,10 @@ pub struct Config {
     pub apb1_pre: APBPrescaler,
     pub apb2_pre: APBPrescaler,
     pub enable_lsi: bool,
+    pub voltage_scale: VoltageScale,
+    /// Supply the main regulator from the SMPS instead of the LDO, which needs the SMPS
+    /// inductor to be fitted on the board
+    pub enable_smps: bool,
 }
 
 impl Default for Config {
@@ -99,11 +114,48 @@ impl Default for Config {
             apb1_pre: APBPrescaler::NotDivided,
             apb2_pre: APBPrescaler::NotDivided,
             enable_lsi: false,
+            voltage_scale: VoltageScale::Range1,
+            enable_smps: false,
         }
     }
 }
 
 pub(crate) unsafe fn init(config: Config) {
+    let sys_clk = match config.mux {
+        ClockSrc::HSI16 => HSI_FREQ,
+        ClockSrc::HSE32 => HSE32_FREQ,
+    };
+    let max_sys_clk = match config.voltage_scale {
+        VoltageScale::Range1 => 48_000_000,
+        VoltageScale::Range2 => 16_000_000,
+    };
+    assert!(
+        sys_clk <= max_sys_clk,
+        "The system clock is too high for the voltage scale"
+    );
+
+    if config.enable_smps {
+        PWR.cr5().modify(|w| w.set_smpsen(true));
+        while !PWR.sr2().read().smpsrdy() {}
+    }
+
+    // Raise the voltage before speeding the clock up
+    if config.voltage_scale == VoltageScale::Range1 {
+        PWR.cr1().modify(|w| w.set_vos(0b01));
+        while PWR.sr2().read().vosf() {}
+    }
+
+    // Set flash wait states for the new voltage range, they also work for the old one
+    FLASH.acr().modify(|w| {
+        w.set_latency(match config.voltage_scale {
+            VoltageScale::Range1 if sys_clk <= 18_000_000 => 0,
+            VoltageScale::Range1 if sys_clk <= 36_000_000 => 1,
+            VoltageScale::Range2 if sys_clk <= 6_000_000 => 0,
+            VoltageScale::Range2 if sys_clk <= 12_000_000 => 1,
+            _ => 2,
+        })
+    });
+
     let (sys_clk, sw) = match config.mux {
         ClockSrc::HSI16 => {
             // Enable HSI16
@@ -164,6 +216,12 @@ pub(crate) unsafe fn init(config: Config) {
         }
     };
 
+    // Lower the voltage once the clock is slow enough
+    if config.voltage_scale == VoltageScale::Range2 {
+        PWR.cr1().modify(|w| w.set_vos(0b10));
+        while PWR.sr2().read().vosf() {}
+    }
+
     // TODO: completely untested
     let apb3_freq = ahb_freq;
 
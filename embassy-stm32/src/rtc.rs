@@ -0,0 +1,555 @@
+//! Real-time clock, with tamper detection and timestamps.
+//!
+//! Besides keeping the calendar, the RTC watches the tamper inputs (`RTC_TAMPx`, PC13, PA0 and
+//! PE6 on the L4) even in STOP and STANDBY mode. A tamper event can erase the backup registers
+//! holding secrets, and both tamper events and the `RTC_TS` input (PC13) can capture the time
+//! at which they happened. Meters and other security devices use them to record when their
+//! enclosure was opened.
+//!
+//! The tamper and timestamp flags are only cleared when read with [`Rtc::wait_for_tamper`] and
+//! [`Rtc::wait_for_timestamp`], so events that happened while nobody was waiting aren't lost.
+
+use core::marker::PhantomData;
+use core::task::Poll;
+use embassy::interrupt::InterruptExt;
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::interrupt;
+use crate::pac;
+use crate::peripherals;
+
+#[cfg(rtc_v2l4)]
+type TampStampInterrupt = interrupt::TAMP_STAMP;
+#[cfg(rtc_v2wb)]
+type TampStampInterrupt = interrupt::TAMP_STAMP_LSECSS;
+
+/// EXTI line of the tamper and timestamp events, which must be enabled for their interrupt.
+#[cfg(rtc_v2l4)]
+const EXTI_LINE: usize = 19;
+#[cfg(rtc_v2wb)]
+const EXTI_LINE: usize = 18;
+
+const TAMPER_COUNT: usize = 3;
+
+static WAKER: AtomicWaker = AtomicWaker::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// A field of the date and time is out of range.
+    InvalidDateTime,
+    /// The calendar wasn't set since the backup domain was reset.
+    NotInitialized,
+}
+
+/// Clock of the RTC. It's kept across resets: changing it requires a backup domain reset.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RtcClock {
+    /// 32.768 kHz crystal.
+    Lse,
+    /// 32 kHz internal RC oscillator. It isn't accurate enough for keeping time over days.
+    Lsi,
+}
+
+/// Detection of the tamper inputs. It's shared by all of them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TamperFilter {
+    /// Trigger on an edge of the input.
+    Edge,
+    /// Trigger when the input stays active for a number of consecutive samples. The input is
+    /// pulled up during `precharge` before each sample unless `pull_up` is false, so that a
+    /// switch to ground can be watched without any external resistor.
+    Level {
+        samples: FilterSamples,
+        frequency: SampleFrequency,
+        precharge: Precharge,
+        pull_up: bool,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FilterSamples {
+    Two,
+    Four,
+    Eight,
+}
+
+/// Sampling frequency of the tamper inputs, as a division of the RTC clock.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SampleFrequency {
+    Div32768,
+    Div16384,
+    Div8192,
+    Div4096,
+    Div2048,
+    Div1024,
+    Div512,
+    Div256,
+}
+
+/// Duration of the precharge, in RTC clock cycles.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Precharge {
+    Cycles1,
+    Cycles2,
+    Cycles4,
+    Cycles8,
+}
+
+#[non_exhaustive]
+pub struct Config {
+    pub clock: RtcClock,
+    pub tamper_filter: TamperFilter,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            clock: RtcClock::Lse,
+            tamper_filter: TamperFilter::Edge,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Tamper {
+    Tamper1,
+    Tamper2,
+    Tamper3,
+}
+
+impl Tamper {
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn from_index(i: usize) -> Self {
+        match i {
+            0 => Tamper::Tamper1,
+            1 => Tamper::Tamper2,
+            _ => Tamper::Tamper3,
+        }
+    }
+}
+
+/// Active state of a tamper input. With [`TamperFilter::Edge`], `High` triggers on the rising
+/// edge and `Low` on the falling edge.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TamperLevel {
+    High,
+    Low,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TamperConfig {
+    pub active: TamperLevel,
+    /// Capture a [`Timestamp`] on tamper events. This applies to all the tamper inputs.
+    pub timestamp: bool,
+    /// Erase the backup registers on tamper events.
+    pub erase_backup: bool,
+}
+
+impl Default for TamperConfig {
+    fn default() -> Self {
+        Self {
+            active: TamperLevel::High,
+            timestamp: true,
+            erase_backup: true,
+        }
+    }
+}
+
+/// Edge of the `RTC_TS` input that captures a timestamp.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimestampEdge {
+    Rising,
+    Falling,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DateTime {
+    /// 2000 to 2099.
+    pub year: u16,
+    /// 1 to 12.
+    pub month: u8,
+    /// 1 to 31.
+    pub day: u8,
+    /// 1 (monday) to 7 (sunday).
+    pub day_of_week: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+/// Time of a tamper or timestamp event. The RTC doesn't capture the year.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timestamp {
+    pub month: u8,
+    pub day: u8,
+    pub day_of_week: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    /// Subsecond counter, counting down each second from 255 with the LSE, or from 249 with
+    /// the LSI.
+    pub subsecond: u16,
+    /// Another event happened before this timestamp was read, and its time was lost.
+    pub overflow: bool,
+}
+
+pub struct Rtc<'d> {
+    _peri: PhantomData<&'d mut peripherals::RTC>,
+}
+
+impl<'d> Rtc<'d> {
+    pub fn new(
+        _peri: impl Unborrow<Target = peripherals::RTC> + 'd,
+        irq: impl Unborrow<Target = TampStampInterrupt> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(_peri, irq);
+
+        unsafe {
+            enable_clock(config.clock);
+
+            let r = pac::RTC;
+            // The filter can't change while a tamper input is enabled.
+            r.tampcr().modify(|w| {
+                for i in 0..TAMPER_COUNT {
+                    w.set_tampe(i, false);
+                }
+            });
+            r.tampcr().modify(|w| match config.tamper_filter {
+                TamperFilter::Edge => w.set_tampflt(0),
+                TamperFilter::Level {
+                    samples,
+                    frequency,
+                    precharge,
+                    pull_up,
+                } => {
+                    w.set_tampflt(match samples {
+                        FilterSamples::Two => 1,
+                        FilterSamples::Four => 2,
+                        FilterSamples::Eight => 3,
+                    });
+                    w.set_tampfreq(match frequency {
+                        SampleFrequency::Div32768 => 0,
+                        SampleFrequency::Div16384 => 1,
+                        SampleFrequency::Div8192 => 2,
+                        SampleFrequency::Div4096 => 3,
+                        SampleFrequency::Div2048 => 4,
+                        SampleFrequency::Div1024 => 5,
+                        SampleFrequency::Div512 => 6,
+                        SampleFrequency::Div256 => 7,
+                    });
+                    w.set_tampprch(match precharge {
+                        Precharge::Cycles1 => 0,
+                        Precharge::Cycles2 => 1,
+                        Precharge::Cycles4 => 2,
+                        Precharge::Cycles8 => 3,
+                    });
+                    w.set_tamppudis(!pull_up);
+                }
+            });
+
+            // The RTC events reach the NVIC through the EXTI, which also lets them wake up
+            // the chip from STOP.
+            exti_cpu_regs()
+                .imr(0)
+                .modify(|w| w.set_line(EXTI_LINE, true));
+            pac::EXTI.rtsr(0).modify(|w| w.set_line(EXTI_LINE, true));
+        }
+
+        irq.set_handler(Self::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        Self { _peri: PhantomData }
+    }
+
+    fn on_interrupt(_: *mut ()) {
+        unsafe {
+            let r = pac::RTC;
+            let isr = r.isr().read();
+            if isr.tsf() {
+                write_protected(|| r.cr().modify(|w| w.set_tsie(false)));
+            }
+            if (0..TAMPER_COUNT).any(|i| isr.tampf(i)) {
+                r.tampcr().modify(|w| w.set_tampie(false));
+            }
+            pac::EXTI.pr(0).write(|w| w.set_line(EXTI_LINE, true));
+        }
+        WAKER.wake();
+    }
+
+    pub fn set_datetime(&mut self, t: DateTime) -> Result<(), Error> {
+        if !(2000..=2099).contains(&t.year)
+            || !(1..=12).contains(&t.month)
+            || !(1..=31).contains(&t.day)
+            || !(1..=7).contains(&t.day_of_week)
+            || t.hour > 23
+            || t.minute > 59
+            || t.second > 59
+        {
+            return Err(Error::InvalidDateTime);
+        }
+
+        let r = pac::RTC;
+        unsafe {
+            write_protected(|| {
+                r.isr().modify(|w| w.set_init(true));
+                while !r.isr().read().initf() {}
+
+                let (prediv_a, prediv_s) = prescalers();
+                r.prer().modify(|w| {
+                    w.set_prediv_s(prediv_s);
+                    w.set_prediv_a(prediv_a);
+                });
+
+                let (yt, yu) = bcd2((t.year - 2000) as u8);
+                let (mt, mu) = bcd2(t.month);
+                let (dt, du) = bcd2(t.day);
+                r.dr().write(|w| {
+                    w.set_yt(yt);
+                    w.set_yu(yu);
+                    w.set_mt(mt > 0);
+                    w.set_mu(mu);
+                    w.set_dt(dt);
+                    w.set_du(du);
+                    w.set_wdu(t.day_of_week);
+                });
+
+                let (ht, hu) = bcd2(t.hour);
+                let (mnt, mnu) = bcd2(t.minute);
+                let (st, su) = bcd2(t.second);
+                r.tr().write(|w| {
+                    w.set_ht(ht);
+                    w.set_hu(hu);
+                    w.set_mnt(mnt);
+                    w.set_mnu(mnu);
+                    w.set_st(st);
+                    w.set_su(su);
+                    w.set_pm(false);
+                });
+
+                r.cr().modify(|w| w.set_fmt(false));
+                r.isr().modify(|w| w.set_init(false));
+            });
+        }
+        Ok(())
+    }
+
+    pub fn now(&self) -> Result<DateTime, Error> {
+        let r = pac::RTC;
+        unsafe {
+            if !r.isr().read().inits() {
+                return Err(Error::NotInitialized);
+            }
+
+            // Reading TR locks the shadow registers until DR is read.
+            let tr = r.tr().read();
+            let dr = r.dr().read();
+            Ok(DateTime {
+                year: 2000 + from_bcd2(dr.yt(), dr.yu()) as u16,
+                month: from_bcd2(dr.mt() as u8, dr.mu()),
+                day: from_bcd2(dr.dt(), dr.du()),
+                day_of_week: dr.wdu(),
+                hour: from_bcd2(tr.ht(), tr.hu()),
+                minute: from_bcd2(tr.mnt(), tr.mnu()),
+                second: from_bcd2(tr.st(), tr.su()),
+            })
+        }
+    }
+
+    /// Enable a tamper input. Its events are returned by [`wait_for_tamper`](Self::wait_for_tamper).
+    pub fn enable_tamper(&mut self, tamper: Tamper, config: TamperConfig) {
+        let i = tamper.index();
+        unsafe {
+            let r = pac::RTC;
+            let level = r.tampcr().read().tampflt() != 0;
+            r.tampcr().modify(|w| {
+                // The trigger bit selects the rising edge or the low level when cleared.
+                w.set_tamptrg(i, (config.active == TamperLevel::High) == level);
+                w.set_tampnoerase(i, !config.erase_backup);
+                if config.timestamp {
+                    w.set_tampts(true);
+                }
+                w.set_tampe(i, true);
+            });
+        }
+    }
+
+    pub fn disable_tamper(&mut self, tamper: Tamper) {
+        unsafe {
+            pac::RTC
+                .tampcr()
+                .modify(|w| w.set_tampe(tamper.index(), false));
+        }
+    }
+
+    /// Wait for a tamper event, and return the input it happened on.
+    pub async fn wait_for_tamper(&mut self) -> Tamper {
+        poll_fn(|cx| unsafe {
+            WAKER.register(cx.waker());
+
+            let r = pac::RTC;
+            let isr = r.isr().read();
+            if let Some(i) = (0..TAMPER_COUNT).find(|&i| isr.tampf(i)) {
+                clear_flags(|w| w.set_tampf(i, false));
+                return Poll::Ready(Tamper::from_index(i));
+            }
+
+            r.tampcr().modify(|w| w.set_tampie(true));
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Capture a timestamp on the given edge of the `RTC_TS` input.
+    pub fn enable_timestamp(&mut self, edge: TimestampEdge) {
+        let r = pac::RTC;
+        unsafe {
+            write_protected(|| {
+                // The edge can't change while timestamps are enabled.
+                r.cr().modify(|w| w.set_tse(false));
+                r.cr()
+                    .modify(|w| w.set_tsedge(edge == TimestampEdge::Falling));
+                r.cr().modify(|w| w.set_tse(true));
+            });
+        }
+    }
+
+    pub fn disable_timestamp(&mut self) {
+        unsafe { write_protected(|| pac::RTC.cr().modify(|w| w.set_tse(false))) }
+    }
+
+    /// Wait for the next timestamp, captured by the `RTC_TS` input or by a tamper event.
+    pub async fn wait_for_timestamp(&mut self) -> Timestamp {
+        poll_fn(|cx| unsafe {
+            WAKER.register(cx.waker());
+
+            let r = pac::RTC;
+            let isr = r.isr().read();
+            if isr.tsf() {
+                let tr = r.tstr().read();
+                let dr = r.tsdr().read();
+                let ss = r.tsssr().read().ss();
+                clear_flags(|w| {
+                    w.set_tsf(false);
+                    w.set_tsovf(false);
+                });
+                return Poll::Ready(Timestamp {
+                    month: from_bcd2(dr.mt() as u8, dr.mu()),
+                    day: from_bcd2(dr.dt(), dr.du()),
+                    day_of_week: dr.wdu(),
+                    hour: from_bcd2(tr.ht(), tr.hu()),
+                    minute: from_bcd2(tr.mnt(), tr.mnu()),
+                    second: from_bcd2(tr.st(), tr.su()),
+                    subsecond: ss,
+                    overflow: isr.tsovf(),
+                });
+            }
+
+            write_protected(|| r.cr().modify(|w| w.set_tsie(true)));
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Read a backup register. They're kept in STANDBY and VBAT mode, and erased by tamper
+    /// events unless [`TamperConfig::erase_backup`] is false.
+    pub fn read_backup(&self, register: usize) -> u32 {
+        unsafe { pac::RTC.bkpr(register).read().bkp() }
+    }
+
+    pub fn write_backup(&mut self, register: usize, value: u32) {
+        unsafe { pac::RTC.bkpr(register).write(|w| w.set_bkp(value)) }
+    }
+}
+
+/// Runs `f` with the write protection of the RTC registers lifted.
+unsafe fn write_protected<R>(f: impl FnOnce() -> R) -> R {
+    let r = pac::RTC;
+    r.wpr().write(|w| w.set_key(0xca));
+    r.wpr().write(|w| w.set_key(0x53));
+    let res = f();
+    r.wpr().write(|w| w.set_key(0xff));
+    res
+}
+
+/// Clears the ISR flags cleared by `f`.
+///
+/// The flags are rc_w0, so the others are written as 1, which leaves them untouched. A
+/// read-modify-write would clear a flag set between the read and the write.
+unsafe fn clear_flags(f: impl FnOnce(&mut pac::rtc::regs::Isr)) {
+    let mut w = pac::rtc::regs::Isr(!0);
+    // INIT is a plain read-write bit, writing 1 would enter the initialization mode.
+    w.set_init(false);
+    f(&mut w);
+    write_protected(|| pac::RTC.isr().write_value(w));
+}
+
+/// Asynchronous and synchronous prescalers dividing the RTC clock down to 1 Hz, for the clock
+/// the RTC actually runs from. It may have been selected before this boot.
+unsafe fn prescalers() -> (u8, u16) {
+    match pac::RCC.bdcr().read().rtcsel() {
+        // 32000 Hz / 128 / 250 = 1 Hz
+        pac::rcc::vals::Rtcsel::LSI => (127, 249),
+        // 32768 Hz / 128 / 256 = 1 Hz
+        _ => (127, 255),
+    }
+}
+
+unsafe fn enable_clock(clock: RtcClock) {
+    let rcc = pac::RCC;
+    // The RTC is in the backup domain, which is write protected.
+    pac::PWR.cr1().modify(|w| w.set_dbp(true));
+
+    #[cfg(rtc_v2wb)]
+    rcc.apb1enr1().modify(|w| w.set_rtcapben(true));
+
+    if rcc.bdcr().read().rtcen() {
+        return;
+    }
+
+    let sel = match clock {
+        RtcClock::Lse => {
+            rcc.bdcr().modify(|w| w.set_lseon(true));
+            while !rcc.bdcr().read().lserdy() {}
+            pac::rcc::vals::Rtcsel::LSE
+        }
+        RtcClock::Lsi => {
+            rcc.csr().modify(|w| w.set_lsion(true));
+            while !rcc.csr().read().lsirdy() {}
+            pac::rcc::vals::Rtcsel::LSI
+        }
+    };
+    rcc.bdcr().modify(|w| {
+        w.set_rtcsel(sel);
+        w.set_rtcen(true);
+    });
+}
+
+#[cfg(exti_w)]
+fn exti_cpu_regs() -> pac::exti::Cpu {
+    pac::EXTI.cpu(pac::CORE_INDEX)
+}
+
+#[cfg(not(exti_w))]
+fn exti_cpu_regs() -> pac::exti::Exti {
+    pac::EXTI
+}
+
+fn bcd2(v: u8) -> (u8, u8) {
+    (v / 10, v % 10)
+}
+
+fn from_bcd2(tens: u8, units: u8) -> u8 {
+    tens * 10 + units
+}
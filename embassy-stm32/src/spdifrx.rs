@@ -0,0 +1,474 @@
+#![macro_use]
+
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use embassy::interrupt::{Interrupt, InterruptExt};
+use embassy::util::{select, Either, Unborrow};
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::drop::OnDrop;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::gpio::sealed::{AFType, Pin as _};
+use crate::gpio::{AnyPin, Pin};
+use crate::peripherals;
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// A subframe had a parity error.
+    Parity,
+    /// Samples weren't read in time and were lost.
+    Overrun,
+    /// Couldn't synchronize to the stream, even after retrying.
+    Sync,
+    /// The stream had a transition at an unexpected place. The receiver has lost lock.
+    Framing,
+    /// The stream stopped having transitions for too long. The receiver has lost lock.
+    Timeout,
+}
+
+/// How samples are laid out in the words received.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataFormat {
+    /// One 24-bit sample per word, in the low bits.
+    RightAligned,
+    /// One 24-bit sample per word, in the high bits.
+    LeftAligned,
+    /// Two 16-bit samples per word, channel A in the low half.
+    Packed16,
+}
+
+#[non_exhaustive]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Config {
+    pub data_format: DataFormat,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            data_format: DataFormat::RightAligned,
+        }
+    }
+}
+
+/// Channel status block: the 192 channel status bits of channel A, first bit in the
+/// lowest bit of the first byte.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelStatus(pub [u8; 24]);
+
+impl ChannelStatus {
+    /// Whether the block uses the professional (AES3) format, instead of the consumer one.
+    pub fn is_professional(&self) -> bool {
+        self.0[0] & 0x01 != 0
+    }
+
+    /// Whether the samples are linear PCM audio, as opposed to compressed data.
+    pub fn is_pcm(&self) -> bool {
+        self.0[0] & 0x02 == 0
+    }
+
+    /// Sample rate indicated by a consumer format block, in Hz.
+    pub fn sample_rate(&self) -> Option<u32> {
+        if self.is_professional() {
+            return None;
+        }
+        match self.0[3] & 0x0F {
+            0b0000 => Some(44_100),
+            0b0010 => Some(48_000),
+            0b0011 => Some(32_000),
+            0b1000 => Some(88_200),
+            0b1010 => Some(96_000),
+            0b1100 => Some(176_400),
+            0b1110 => Some(192_000),
+            _ => None,
+        }
+    }
+}
+
+const SPDIFEN_IDLE: u8 = 0b00;
+const SPDIFEN_SYNC: u8 = 0b01;
+const SPDIFEN_RCV: u8 = 0b11;
+
+/// SPDIF receiver.
+///
+/// Samples are received by DMA, one block per call to [`read`](Self::read). Samples
+/// arriving between calls are dropped.
+pub struct SpdifRx<'d, T: Instance, Dma> {
+    phantom: PhantomData<&'d mut T>,
+    pin: AnyPin,
+    dma: Dma,
+    locked: bool,
+}
+
+impl<'d, T: Instance, Dma: DataDma<T>> SpdifRx<'d, T, Dma> {
+    /// Create a receiver on the `IN0` input.
+    pub fn new_in0(
+        peri: impl Unborrow<Target = T> + 'd,
+        pin: impl Unborrow<Target = impl In0Pin<T>> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        dma: impl Unborrow<Target = Dma> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(pin);
+        let af = pin.af_num();
+        Self::new_inner(peri, pin.degrade(), af, 0, irq, dma, config)
+    }
+
+    /// Create a receiver on the `IN1` input.
+    pub fn new_in1(
+        peri: impl Unborrow<Target = T> + 'd,
+        pin: impl Unborrow<Target = impl In1Pin<T>> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        dma: impl Unborrow<Target = Dma> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(pin);
+        let af = pin.af_num();
+        Self::new_inner(peri, pin.degrade(), af, 1, irq, dma, config)
+    }
+
+    /// Create a receiver on the `IN2` input.
+    pub fn new_in2(
+        peri: impl Unborrow<Target = T> + 'd,
+        pin: impl Unborrow<Target = impl In2Pin<T>> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        dma: impl Unborrow<Target = Dma> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(pin);
+        let af = pin.af_num();
+        Self::new_inner(peri, pin.degrade(), af, 2, irq, dma, config)
+    }
+
+    /// Create a receiver on the `IN3` input.
+    pub fn new_in3(
+        peri: impl Unborrow<Target = T> + 'd,
+        pin: impl Unborrow<Target = impl In3Pin<T>> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        dma: impl Unborrow<Target = Dma> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(pin);
+        let af = pin.af_num();
+        Self::new_inner(peri, pin.degrade(), af, 3, irq, dma, config)
+    }
+
+    fn new_inner(
+        _peri: impl Unborrow<Target = T> + 'd,
+        pin: AnyPin,
+        af: u8,
+        input: u8,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        dma: impl Unborrow<Target = Dma> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(irq, dma);
+
+        T::enable();
+        T::reset();
+
+        unsafe {
+            pin.set_as_af(af, AFType::Input);
+
+            T::regs().cr().write(|w| {
+                w.set_insel(input);
+                w.set_drfmt(match config.data_format {
+                    DataFormat::RightAligned => 0b00,
+                    DataFormat::LeftAligned => 0b01,
+                    DataFormat::Packed16 => 0b10,
+                });
+                // Only keep the samples, not the parity, validity, user, channel status
+                // and preamble bits that can come along with them.
+                w.set_pmsk(true);
+                w.set_vmsk(true);
+                w.set_cumsk(true);
+                w.set_ptmsk(true);
+                // Retry synchronizing up to 63 times, and don't start until there's
+                // activity on the input.
+                w.set_nbtr(0b11);
+                w.set_wfa(true);
+                w.set_spdifen(SPDIFEN_IDLE);
+            });
+        }
+
+        irq.set_handler(Self::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        Self {
+            phantom: PhantomData,
+            pin,
+            dma,
+            locked: false,
+        }
+    }
+
+    /// Whether the receiver is synchronized to the incoming stream.
+    pub fn is_locked(&self) -> bool {
+        self.locked && unsafe { T::regs().cr().read().spdifen() } != SPDIFEN_IDLE
+    }
+
+    /// Synchronize to the incoming stream, and start receiving.
+    ///
+    /// This waits for activity on the input, so it doesn't complete until a source is
+    /// connected. It's done automatically by [`read`](Self::read) when needed.
+    pub async fn wait_for_lock(&mut self) -> Result<(), Error> {
+        let r = T::regs();
+        self.locked = false;
+
+        unsafe {
+            r.cr().modify(|w| w.set_spdifen(SPDIFEN_IDLE));
+            r.ifcr().write(|w| {
+                w.set_perrcf(true);
+                w.set_ovrcf(true);
+                w.set_sbdcf(true);
+                w.set_syncdcf(true);
+            });
+            r.cr().modify(|w| w.set_spdifen(SPDIFEN_SYNC));
+        }
+
+        let on_drop = OnDrop::new(|| unsafe {
+            r.imr().write(|_| {});
+            r.cr().modify(|w| w.set_spdifen(SPDIFEN_IDLE));
+        });
+
+        poll_fn(|cx| {
+            T::state().waker.register(cx.waker());
+
+            let sr = unsafe { r.sr().read() };
+            if sr.syncd() {
+                unsafe { r.ifcr().write(|w| w.set_syncdcf(true)) };
+                Poll::Ready(Ok(()))
+            } else if sr.serr() {
+                Poll::Ready(Err(Error::Sync))
+            } else if sr.ferr() {
+                Poll::Ready(Err(Error::Framing))
+            } else if sr.terr() {
+                Poll::Ready(Err(Error::Timeout))
+            } else {
+                unsafe {
+                    r.imr().write(|w| {
+                        w.set_syncdie(true);
+                        w.set_ifeie(true);
+                    })
+                };
+                Poll::Pending
+            }
+        })
+        .await?;
+
+        on_drop.defuse();
+        unsafe { r.cr().modify(|w| w.set_spdifen(SPDIFEN_RCV)) };
+        self.locked = true;
+        Ok(())
+    }
+
+    /// Sample rate of the incoming stream, measured from its symbol timing.
+    ///
+    /// It's an estimate, depending on the accuracy of the kernel clock. Prefer the
+    /// rate from the [`channel_status`](Self::channel_status) when it's given.
+    pub fn measured_sample_rate(&self) -> Option<u32> {
+        if !self.is_locked() {
+            return None;
+        }
+        // WIDTH5 is the duration of 5 symbols in kernel clock cycles, with 64 symbols per frame.
+        let width5 = unsafe { T::regs().sr().read().width5() } as u32;
+        if width5 == 0 {
+            return None;
+        }
+        Some(5 * T::frequency().0 / (width5 * 64))
+    }
+
+    /// Receive samples into `buf`, synchronizing to the stream first if needed.
+    ///
+    /// Channel A and B samples alternate, unless using [`DataFormat::Packed16`]. On
+    /// [`Error::Framing`] or [`Error::Timeout`], lock was lost, and the next call
+    /// synchronizes again.
+    pub async fn read(&mut self, buf: &mut [u32]) -> Result<(), Error> {
+        if !self.is_locked() {
+            self.wait_for_lock().await?;
+        }
+
+        let r = T::regs();
+        let request = self.dma.request();
+        let src = r.dr().ptr() as *mut u32;
+
+        unsafe {
+            r.ifcr().write(|w| {
+                w.set_perrcf(true);
+                w.set_ovrcf(true);
+            });
+            r.cr().modify(|w| w.set_rxdmaen(true));
+        }
+
+        let on_drop = OnDrop::new(|| unsafe {
+            r.imr().write(|_| {});
+            r.cr().modify(|w| w.set_rxdmaen(false));
+        });
+
+        let dma_read = crate::dma::read(&mut self.dma, request, src, buf);
+
+        let errors = poll_fn(|cx| {
+            T::state().waker.register(cx.waker());
+
+            let sr = unsafe { r.sr().read() };
+            if sr.perr() {
+                Poll::Ready(Error::Parity)
+            } else if sr.ovr() {
+                Poll::Ready(Error::Overrun)
+            } else if sr.ferr() {
+                Poll::Ready(Error::Framing)
+            } else if sr.terr() {
+                Poll::Ready(Error::Timeout)
+            } else {
+                unsafe {
+                    r.imr().write(|w| {
+                        w.set_perrie(true);
+                        w.set_ovrie(true);
+                        w.set_ifeie(true);
+                    })
+                };
+                Poll::Pending
+            }
+        });
+
+        let res = match select(dma_read, errors).await {
+            Either::First(_) => Ok(()),
+            Either::Second(err) => Err(err),
+        };
+
+        drop(on_drop);
+        if let Err(Error::Framing | Error::Timeout) = res {
+            self.locked = false;
+        }
+        res
+    }
+
+    /// Capture a whole channel status block.
+    ///
+    /// This takes up to two blocks of 192 frames, 8ms at 48kHz.
+    pub async fn channel_status(&mut self) -> Result<ChannelStatus, Error> {
+        if !self.is_locked() {
+            self.wait_for_lock().await?;
+        }
+
+        let r = T::regs();
+        let on_drop = OnDrop::new(|| unsafe { r.imr().write(|_| {}) });
+
+        let mut status = [0; 24];
+        let mut n = 0;
+        let res = poll_fn(|cx| {
+            T::state().waker.register(cx.waker());
+
+            let sr = unsafe { r.sr().read() };
+            if sr.ferr() {
+                return Poll::Ready(Err(Error::Framing));
+            } else if sr.terr() {
+                return Poll::Ready(Err(Error::Timeout));
+            }
+
+            if sr.csrne() {
+                // Reading CSR clears CSRNE.
+                let csr = unsafe { r.csr().read() };
+                // Wait for the start of a block.
+                if csr.sob() {
+                    n = 0;
+                }
+                if csr.sob() || n > 0 {
+                    status[n] = csr.cs();
+                    n += 1;
+                    if n == status.len() {
+                        return Poll::Ready(Ok(ChannelStatus(status)));
+                    }
+                }
+            }
+
+            unsafe {
+                r.imr().write(|w| {
+                    w.set_csrneie(true);
+                    w.set_ifeie(true);
+                })
+            };
+            Poll::Pending
+        })
+        .await;
+
+        drop(on_drop);
+        if let Err(Error::Framing | Error::Timeout) = res {
+            self.locked = false;
+        }
+        res
+    }
+
+    unsafe fn on_interrupt(_: *mut ()) {
+        // Flags are handled by the task, mask them until it gets to run.
+        T::regs().imr().write(|_| {});
+        T::state().waker.wake();
+    }
+}
+
+impl<'d, T: Instance, Dma> Drop for SpdifRx<'d, T, Dma> {
+    fn drop(&mut self) {
+        unsafe {
+            T::regs().cr().write(|w| w.set_spdifen(SPDIFEN_IDLE));
+            self.pin.set_as_disconnected();
+        }
+        T::disable();
+    }
+}
+
+pub(crate) mod sealed {
+    use super::*;
+
+    pub struct State {
+        pub waker: AtomicWaker,
+    }
+
+    impl State {
+        pub const fn new() -> Self {
+            Self {
+                waker: AtomicWaker::new(),
+            }
+        }
+    }
+
+    pub trait Instance {
+        fn regs() -> crate::pac::spdifrx::Spdifrx;
+        fn state() -> &'static State;
+    }
+}
+
+pub trait Instance: sealed::Instance + crate::rcc::RccPeripheral + 'static {
+    type Interrupt: Interrupt;
+}
+
+pin_trait!(In0Pin, Instance);
+pin_trait!(In1Pin, Instance);
+pin_trait!(In2Pin, Instance);
+pin_trait!(In3Pin, Instance);
+dma_trait!(DataDma, Instance);
+
+foreach_interrupt!(
+    ($inst:ident, spdifrx, $block:ident, $signal_name:ident, $irq:ident) => {
+        impl sealed::Instance for peripherals::$inst {
+            fn regs() -> crate::pac::spdifrx::Spdifrx {
+                crate::pac::$inst
+            }
+
+            fn state() -> &'static sealed::State {
+                static STATE: sealed::State = sealed::State::new();
+                &STATE
+            }
+        }
+
+        impl Instance for peripherals::$inst {
+            type Interrupt = crate::interrupt::$irq;
+        }
+    };
+);
@@ -7,7 +7,7 @@ use embassy_hal_common::unborrow;
 use futures::future::join;
 
 use self::sealed::WordSize;
-use crate::dma::{slice_ptr_parts, NoDma, Transfer};
+use crate::dma::{slice_ptr_parts, NoDma, Transfer, MAX_TRANSFER};
 use crate::gpio::sealed::{AFType, Pin as _};
 use crate::gpio::AnyPin;
 use crate::pac::spi::Spi as Regs;
@@ -411,10 +411,17 @@ impl<'d, T: Instance, Tx, Rx> Spi<'d, T, Tx, Rx> {
     where
         Tx: TxDma<T>,
     {
-        if data.len() == 0 {
-            return Ok(());
+        // DMA channels move at most 65535 words at once.
+        for chunk in data.chunks(MAX_TRANSFER) {
+            self.write_chunk(chunk).await?;
         }
+        Ok(())
+    }
 
+    async fn write_chunk<W: Word>(&mut self, data: &[W]) -> Result<(), Error>
+    where
+        Tx: TxDma<T>,
+    {
         self.set_word_size(W::WORDSIZE);
         unsafe {
             T::REGS.cr1().modify(|w| {
@@ -453,10 +460,19 @@ impl<'d, T: Instance, Tx, Rx> Spi<'d, T, Tx, Rx> {
         Tx: TxDma<T>,
         Rx: RxDma<T>,
     {
-        if data.len() == 0 {
-            return Ok(());
+        // Both channels run one chunk at a time, so that the receiver never misses a word
+        // while its channel is restarted.
+        for chunk in data.chunks_mut(MAX_TRANSFER) {
+            self.read_chunk(chunk).await?;
         }
+        Ok(())
+    }
 
+    async fn read_chunk<W: Word>(&mut self, data: &mut [W]) -> Result<(), Error>
+    where
+        Tx: TxDma<T>,
+        Rx: RxDma<T>,
+    {
         self.set_word_size(W::WORDSIZE);
         unsafe {
             T::REGS.cr1().modify(|w| {
@@ -517,13 +533,30 @@ impl<'d, T: Instance, Tx, Rx> Spi<'d, T, Tx, Rx> {
         Tx: TxDma<T>,
         Rx: RxDma<T>,
     {
-        let (_, rx_len) = slice_ptr_parts(read);
-        let (_, tx_len) = slice_ptr_parts(write);
+        let (rx_ptr, rx_len) = slice_ptr_parts(read);
+        let (tx_ptr, tx_len) = slice_ptr_parts(write);
         assert_eq!(rx_len, tx_len);
-        if rx_len == 0 {
-            return Ok(());
+
+        let mut offset = 0;
+        while offset < rx_len {
+            let n = (rx_len - offset).min(MAX_TRANSFER);
+            let read = ptr::slice_from_raw_parts_mut((rx_ptr as *mut W).wrapping_add(offset), n);
+            let write = ptr::slice_from_raw_parts((tx_ptr as *const W).wrapping_add(offset), n);
+            self.transfer_chunk(read, write).await?;
+            offset += n;
         }
+        Ok(())
+    }
 
+    async fn transfer_chunk<W: Word>(
+        &mut self,
+        read: *mut [W],
+        write: *const [W],
+    ) -> Result<(), Error>
+    where
+        Tx: TxDma<T>,
+        Rx: RxDma<T>,
+    {
         self.set_word_size(W::WORDSIZE);
         unsafe {
             T::REGS.cr1().modify(|w| {
@@ -1038,6 +1071,14 @@ pub(crate) mod sealed {
 
 pub trait Word: Copy + 'static + sealed::Word + Default + crate::dma::Word {}
 
+#[cfg(feature = "nightly")]
+impl<'d, T: Instance, Tx, Rx> embassy_embedded_hal::SetConfig for Spi<'d, T, Tx, Rx> {
+    type Config = Config;
+    fn set_config(&mut self, config: &Self::Config) {
+        self.reconfigure(*config);
+    }
+}
+
 impl Word for u8 {}
 impl Word for u16 {}
 
@@ -1,4 +1,7 @@
+use core::marker::PhantomData;
 use embassy::interrupt::Interrupt;
+use embassy::util::Unborrow;
+use embassy_hal_common::unborrow;
 
 use crate::rcc::{sealed::RccPeripheral as __RccPeri, RccPeripheral};
 use crate::time::Hertz;
@@ -52,6 +55,94 @@ pub trait AdvancedControlInstance: sealed::AdvancedControlInstance + 'static {}
 
 pub trait Basic16bitInstance: sealed::Basic16bitInstance + 'static {}
 
+dma_trait!(UpDma, Basic16bitInstance);
+
+/// DMA transfers paced by a timer: one word is written at each update event.
+///
+/// This outputs a sequence with the timing precision of the timer, without the CPU: bit
+/// patterns on a GPIO port for stepper motors or IR protocols, or samples for a DAC. The
+/// first word is written one period after the transfer starts.
+pub struct TimerDma<'d, T: Basic16bitInstance, D> {
+    tim: T,
+    dma: D,
+    phantom: PhantomData<&'d mut T>,
+}
+
+impl<'d, T: Basic16bitInstance, D: UpDma<T>> TimerDma<'d, T, D> {
+    /// Create the driver, writing `freq` words per second.
+    pub fn new<F: Into<Hertz>>(
+        tim: impl Unborrow<Target = T> + 'd,
+        dma: impl Unborrow<Target = D> + 'd,
+        freq: F,
+    ) -> Self {
+        unborrow!(tim, dma);
+
+        T::enable();
+        <T as crate::rcc::sealed::RccPeripheral>::reset();
+
+        let mut this = Self {
+            tim,
+            dma,
+            phantom: PhantomData,
+        };
+        this.tim.set_frequency(freq);
+        this
+    }
+
+    pub fn set_freq<F: Into<Hertz>>(&mut self, freq: F) {
+        self.tim.set_frequency(freq);
+    }
+
+    /// Write `words` to the BSRR register of the GPIO port of `pin`: the low half of each
+    /// word sets pins of the port, the high half resets them.
+    ///
+    /// Only the bits of the pins configured as outputs have an effect.
+    pub async fn write_gpio(&mut self, pin: &impl crate::gpio::Pin, words: &[u32]) {
+        use crate::gpio::sealed::Pin;
+        let bsrr = pin.block().bsrr().ptr() as *mut u32;
+        self.write(words, bsrr).await
+    }
+
+    /// Write 12-bit samples, right-aligned, to a DAC channel.
+    ///
+    /// The channel must be enabled, without a trigger: each sample is output as soon as it's
+    /// written.
+    #[cfg(dac_v2)]
+    pub async fn write_dac<DAC: crate::dac::Instance>(
+        &mut self,
+        _dac: &mut crate::dac::Dac<'_, DAC>,
+        channel: crate::dac::Channel,
+        samples: &[u16],
+    ) {
+        let dhr = unsafe {
+            match channel {
+                crate::dac::Channel::Ch1 => DAC::regs().dhr12r1().ptr(),
+                crate::dac::Channel::Ch2 => DAC::regs().dhr12r2().ptr(),
+            }
+        };
+        self.write(samples, dhr as *mut u16).await
+    }
+
+    async fn write<W: crate::dma::Word>(&mut self, words: &[W], reg_addr: *mut W) {
+        let ch = &mut self.dma;
+        let request = ch.request();
+        let transfer = crate::dma::write(ch, request, words, reg_addr);
+
+        self.tim.reset();
+        unsafe {
+            T::regs().dier().modify(|w| w.set_ude(true));
+        }
+        self.tim.start();
+
+        transfer.await;
+
+        self.tim.stop();
+        unsafe {
+            T::regs().dier().modify(|w| w.set_ude(false));
+        }
+    }
+}
+
 #[allow(unused)]
 macro_rules! impl_basic_16bit_timer {
     ($inst:ident, $irq:ident) => {
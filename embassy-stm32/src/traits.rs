@@ -9,8 +9,8 @@ macro_rules! pin_trait {
 }
 
 macro_rules! pin_trait_impl {
-    (crate::$mod:ident::$trait:ident, $instance:ident, $pin:ident, $af:expr) => {
-        impl crate::$mod::$trait<crate::peripherals::$instance> for crate::peripherals::$pin {
+    (crate::$($path:ident)::+, $instance:ident, $pin:ident, $af:expr) => {
+        impl crate::$($path)::+<crate::peripherals::$instance> for crate::peripherals::$pin {
             fn af_num(&self) -> u8 {
                 $af
             }
@@ -31,8 +31,8 @@ macro_rules! dma_trait {
 #[allow(unused)]
 macro_rules! dma_trait_impl {
     // DMAMUX
-    (crate::$mod:ident::$trait:ident, $instance:ident, {dmamux: $dmamux:ident}, $request:expr) => {
-        impl<T> crate::$mod::$trait<crate::peripherals::$instance> for T
+    (crate::$($path:ident)::+, $instance:ident, {dmamux: $dmamux:ident}, $request:expr) => {
+        impl<T> crate::$($path)::+<crate::peripherals::$instance> for T
         where
             T: crate::dma::MuxChannel<Mux = crate::dma::$dmamux>,
         {
@@ -43,8 +43,8 @@ macro_rules! dma_trait_impl {
     };
 
     // No DMAMUX
-    (crate::$mod:ident::$trait:ident, $instance:ident, {channel: $channel:ident}, $request:expr) => {
-        impl crate::$mod::$trait<crate::peripherals::$instance> for crate::peripherals::$channel {
+    (crate::$($path:ident)::+, $instance:ident, {channel: $channel:ident}, $request:expr) => {
+        impl crate::$($path)::+<crate::peripherals::$instance> for crate::peripherals::$channel {
             fn request(&self) -> crate::dma::Request {
                 $request
             }
@@ -0,0 +1,241 @@
+//! IrDA SIR mode.
+//!
+//! The USART encodes each zero bit as a short infrared pulse, and decodes the pulses received,
+//! so it can drive an IrDA transceiver directly. IrDA is half-duplex: the decoder ignores the
+//! receive line while transmitting, so the transceiver echo doesn't need to be filtered out.
+//!
+//! On top of the raw bytes, [`Irda::write_frame`] and [`Irda::read_frame`] implement the
+//! asynchronous framing of IrLAP: frames are delimited by BOF and EOF flags, with escaping
+//! and a 16 bit frame check sequence.
+
+use super::*;
+
+const BOF: u8 = 0xC0;
+const EOF: u8 = 0xC1;
+const CE: u8 = 0x7D;
+
+/// Value of the frame check sequence computed over a frame and its own FCS.
+const FCS_GOOD: u16 = 0xF0B8;
+
+/// IrDA encoder mode.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Mode {
+    /// Pulses last 3/16 of a bit time.
+    Normal,
+    /// Pulses last 3 periods of a low-power clock around 1.8432 MHz, whatever the baud rate,
+    /// to save power at low baud rates. The maximum baud rate is lower than in normal mode.
+    LowPower,
+}
+
+/// IrDA error
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// Error of the underlying UART.
+    Uart(super::Error),
+    /// The frame check sequence of a received frame is wrong.
+    Fcs,
+    /// A received frame doesn't fit in the buffer.
+    BufferTooSmall,
+}
+
+impl From<super::Error> for Error {
+    fn from(e: super::Error) -> Self {
+        Self::Uart(e)
+    }
+}
+
+/// UART with an IrDA SIR transceiver.
+pub struct Irda<'d, T: Instance, TxDma = NoDma, RxDma = NoDma> {
+    uart: Uart<'d, T, TxDma, RxDma>,
+}
+
+impl<'d, T: Instance, TxDma, RxDma> Irda<'d, T, TxDma, RxDma> {
+    /// Switch `uart` to IrDA mode. The baud rate must be at most 115200 baud.
+    pub fn new(uart: Uart<'d, T, TxDma, RxDma>, mode: Mode) -> Self {
+        // The low-power pulses are generated from the kernel clock, divided down to a frequency
+        // between 1.42 and 2.12 MHz. In normal mode, the prescaler must be 1.
+        let psc = match mode {
+            Mode::Normal => 1,
+            Mode::LowPower => {
+                let psc = (T::frequency().0 + 921_600) / 1_843_200;
+                assert!(
+                    (1..=255).contains(&psc),
+                    "kernel clock too low for low-power IrDA"
+                );
+                psc
+            }
+        };
+
+        let r = T::regs();
+        unsafe {
+            // The IrDA mode can only be enabled with the USART disabled.
+            r.cr1().modify(|w| w.set_ue(false));
+            r.gtpr().modify(|w| w.set_psc(psc as u8));
+            r.cr2().modify(|w| {
+                w.set_clken(false);
+                w.set_stop(vals::Stop::STOP1);
+                w.set_linen(false);
+            });
+            r.cr3().modify(|w| {
+                w.set_scen(false);
+                w.set_hdsel(false);
+                w.set_iren(true);
+                w.set_irlp(mode == Mode::LowPower);
+            });
+            r.cr1().modify(|w| w.set_ue(true));
+        }
+
+        Self { uart }
+    }
+
+    /// Transmit `buffer` as raw bytes.
+    pub async fn write(&mut self, buffer: &[u8]) -> Result<(), Error>
+    where
+        TxDma: super::TxDma<T>,
+    {
+        self.uart.write(buffer).await?;
+        Ok(())
+    }
+
+    /// Receive enough raw bytes to fill `buffer`.
+    pub async fn read(&mut self, buffer: &mut [u8]) -> Result<(), Error>
+    where
+        RxDma: super::RxDma<T>,
+    {
+        self.uart.read(buffer).await?;
+        Ok(())
+    }
+
+    /// Send `data` as a frame: BOF, escaped data and FCS, EOF.
+    pub async fn write_frame(&mut self, data: &[u8]) -> Result<(), Error>
+    where
+        TxDma: super::TxDma<T>,
+    {
+        let fcs = !data.iter().fold(0xFFFF, |fcs, &b| fcs_update(fcs, b));
+        let fcs = fcs.to_le_bytes();
+
+        // Escaping at most doubles the size, send the frame in chunks.
+        let mut chunk = [0; 32];
+        let mut n = 0;
+        chunk[n] = BOF;
+        n += 1;
+        for &b in data.iter().chain(fcs.iter()) {
+            if n + 2 > chunk.len() {
+                self.uart.write(&chunk[..n]).await?;
+                n = 0;
+            }
+            if b == BOF || b == EOF || b == CE {
+                chunk[n] = CE;
+                chunk[n + 1] = b ^ 0x20;
+                n += 2;
+            } else {
+                chunk[n] = b;
+                n += 1;
+            }
+        }
+        if n == chunk.len() {
+            self.uart.write(&chunk[..n]).await?;
+            n = 0;
+        }
+        chunk[n] = EOF;
+        self.uart.write(&chunk[..n + 1]).await?;
+        Ok(())
+    }
+
+    /// Receive a frame into `buffer`, and return the length of its data.
+    ///
+    /// Anything received before the next BOF is discarded, as are aborted frames. This doesn't
+    /// time out: use `embassy::time::with_timeout` when waiting for a reply.
+    pub async fn read_frame(&mut self, buffer: &mut [u8]) -> Result<usize, Error>
+    where
+        RxDma: super::RxDma<T>,
+    {
+        let mut byte = [0];
+        'frame: loop {
+            // Wait for the start of a frame. Several BOFs may precede it.
+            loop {
+                self.uart.read(&mut byte).await?;
+                if byte[0] == BOF {
+                    break;
+                }
+            }
+
+            let mut len = 0;
+            let mut fcs = 0xFFFF;
+            let mut escaped = false;
+            loop {
+                self.uart.read(&mut byte).await?;
+                let b = match byte[0] {
+                    BOF => {
+                        len = 0;
+                        fcs = 0xFFFF;
+                        escaped = false;
+                        continue;
+                    }
+                    // An escaped EOF aborts the frame.
+                    EOF if escaped => continue 'frame,
+                    EOF => break,
+                    CE => {
+                        escaped = true;
+                        continue;
+                    }
+                    b if escaped => {
+                        escaped = false;
+                        b ^ 0x20
+                    }
+                    b => b,
+                };
+
+                // The FCS is received like data, it's only known to be the FCS at the EOF.
+                if len == buffer.len() + 2 {
+                    return Err(Error::BufferTooSmall);
+                }
+                if len < buffer.len() {
+                    buffer[len] = b;
+                }
+                len += 1;
+                fcs = fcs_update(fcs, b);
+            }
+
+            if len < 2 || fcs != FCS_GOOD {
+                return Err(Error::Fcs);
+            }
+            return Ok(len - 2);
+        }
+    }
+
+    /// Change the baud rate. See [`Uart::reconfigure`].
+    pub fn reconfigure(&mut self, config: Config) {
+        self.uart.reconfigure(config)
+    }
+
+    /// Leave the IrDA mode, and release the UART.
+    pub fn into_inner(self) -> Uart<'d, T, TxDma, RxDma> {
+        let r = T::regs();
+        unsafe {
+            r.cr1().modify(|w| w.set_ue(false));
+            r.cr3().modify(|w| {
+                w.set_iren(false);
+                w.set_irlp(false);
+            });
+            r.cr1().modify(|w| w.set_ue(true));
+        }
+        self.uart
+    }
+}
+
+/// Update the CRC-16/CCITT frame check sequence with one byte, least significant bit first.
+fn fcs_update(fcs: u16, byte: u8) -> u16 {
+    let mut fcs = fcs ^ byte as u16;
+    for _ in 0..8 {
+        fcs = if fcs & 1 != 0 {
+            (fcs >> 1) ^ 0x8408
+        } else {
+            fcs >> 1
+        };
+    }
+    fcs
+}
@@ -0,0 +1,335 @@
+//! LIN bus, on top of the UART.
+
+use super::*;
+
+/// Sync field, sent by the master after the break.
+const SYNC: u8 = 0x55;
+
+/// Maximum length of the data of a LIN frame.
+pub const MAX_DATA_LEN: usize = 8;
+
+/// Checksum model of the frames.
+///
+/// LIN 1.x uses the classic checksum, over the data only. LIN 2.x uses the enhanced checksum,
+/// which covers the protected identifier too, except for the diagnostic frames `0x3C` and
+/// `0x3D` which always use the classic one.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChecksumModel {
+    Classic,
+    Enhanced,
+}
+
+/// LIN error
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// Error of the underlying UART.
+    Uart(super::Error),
+    /// The byte following the break isn't the sync field.
+    Sync,
+    /// The parity bits of the protected identifier are wrong.
+    Parity,
+    /// The checksum of the response is wrong.
+    Checksum,
+}
+
+impl From<super::Error> for Error {
+    fn from(e: super::Error) -> Self {
+        Self::Uart(e)
+    }
+}
+
+/// Compute the protected identifier of a frame: the 6 bit identifier, and its two parity bits.
+pub fn protected_id(id: u8) -> u8 {
+    assert!(id < 64, "LIN identifiers are 6 bits");
+    let bit = |n: u8| (id >> n) & 1;
+    let p0 = bit(0) ^ bit(1) ^ bit(2) ^ bit(4);
+    let p1 = !(bit(1) ^ bit(3) ^ bit(4) ^ bit(5)) & 1;
+    id | p0 << 6 | p1 << 7
+}
+
+/// Compute the checksum of a frame with identifier `id`.
+pub fn checksum(model: ChecksumModel, id: u8, data: &[u8]) -> u8 {
+    let mut sum: u16 = match model {
+        ChecksumModel::Enhanced if id != 0x3C && id != 0x3D => protected_id(id) as u16,
+        _ => 0,
+    };
+    for &b in data {
+        // Sum with carry: add the carry back as soon as it appears.
+        sum += b as u16;
+        if sum > 0xFF {
+            sum -= 0xFF;
+        }
+    }
+    !(sum as u8)
+}
+
+/// UART in LIN mode.
+///
+/// The USART generates and detects the 13 bit breaks which start the frames, everything else
+/// is regular 8N1 data. A frame is a header sent by the master (break, sync field, protected
+/// identifier), followed by a response of up to 8 data bytes and a checksum, sent by the master
+/// or by one of the slaves depending on the identifier.
+///
+/// The LIN bus is a single wire: the transceiver echoes everything sent back to the receiver.
+/// The echo is discarded before receiving a response.
+///
+/// No operation times out, wrap them in `embassy::time::with_timeout` to handle slaves that
+/// don't answer.
+pub struct Lin<'d, T: Instance, TxDma = NoDma, RxDma = NoDma> {
+    uart: Uart<'d, T, TxDma, RxDma>,
+    model: ChecksumModel,
+}
+
+impl<'d, T: Instance, TxDma, RxDma> Lin<'d, T, TxDma, RxDma> {
+    /// Switch `uart` to LIN mode. Its configuration must be 8 data bits without parity.
+    pub fn new(uart: Uart<'d, T, TxDma, RxDma>, model: ChecksumModel) -> Self {
+        let r = T::regs();
+        unsafe {
+            // The LIN mode can only be enabled with the USART disabled.
+            r.cr1().modify(|w| w.set_ue(false));
+            r.cr2().modify(|w| {
+                w.set_clken(false);
+                w.set_stop(vals::Stop::STOP1);
+                w.set_linen(true);
+                w.set_lbdl(vals::Lbdl::BIT11);
+            });
+            r.cr3().modify(|w| {
+                w.set_scen(false);
+                w.set_hdsel(false);
+                w.set_iren(false);
+            });
+            r.cr1().modify(|w| w.set_ue(true));
+        }
+
+        Self { uart, model }
+    }
+
+    /// Send a break, after the byte being transmitted if any.
+    pub fn send_break(&mut self) {
+        let r = T::regs();
+        unsafe {
+            #[cfg(usart_v1)]
+            r.cr1().modify(|w| w.set_sbk(true));
+            #[cfg(usart_v2)]
+            r.rqr().write(|w| w.set_sbkrq(true));
+        }
+    }
+
+    /// Send a complete frame as the master: header, `data` and checksum.
+    pub async fn publish(&mut self, id: u8, data: &[u8]) -> Result<(), Error>
+    where
+        TxDma: super::TxDma<T>,
+    {
+        assert!(data.len() <= MAX_DATA_LEN);
+
+        let mut frame = [0; MAX_DATA_LEN + 3];
+        frame[0] = SYNC;
+        frame[1] = protected_id(id);
+        frame[2..][..data.len()].copy_from_slice(data);
+        frame[2 + data.len()] = checksum(self.model, id, data);
+
+        // The sync field is queued behind the break.
+        self.send_break();
+        self.uart.write(&frame[..data.len() + 3]).await?;
+        self.uart.blocking_flush()?;
+        Ok(())
+    }
+
+    /// Send a header as the master, and receive the response of a slave into `data`.
+    pub async fn request(&mut self, id: u8, data: &mut [u8]) -> Result<(), Error>
+    where
+        TxDma: super::TxDma<T>,
+        RxDma: super::RxDma<T>,
+    {
+        assert!(data.len() <= MAX_DATA_LEN);
+
+        self.send_break();
+        self.uart.write(&[SYNC, protected_id(id)]).await?;
+        self.uart.blocking_flush()?;
+        unsafe { clear_rx(T::regs()) };
+
+        self.receive(id, data).await
+    }
+
+    /// Wait for a header as a slave, and return the identifier of the frame.
+    ///
+    /// Anything received before the break is discarded. The caller then either sends the
+    /// response with [`Lin::respond`], receives it with [`Lin::receive`], or ignores the
+    /// frame.
+    pub async fn wait_header(&mut self) -> Result<u8, Error>
+    where
+        RxDma: super::RxDma<T>,
+    {
+        let r = T::regs();
+        unsafe {
+            clear_rx(r);
+            clear_break_flag(r);
+        }
+
+        // The break is received as a 0x00 byte with a framing error, which is ignored by DMA
+        // reads. The break flag is only raised after the break delimiter, so it may be seen
+        // when reading the break itself or the sync field.
+        let mut byte = [0];
+        loop {
+            self.uart.read(&mut byte).await?;
+            if unsafe { break_detected(r) } {
+                break;
+            }
+        }
+        unsafe { clear_break_flag(r) };
+        if byte[0] == 0x00 {
+            self.uart.read(&mut byte).await?;
+        }
+        if byte[0] != SYNC {
+            return Err(Error::Sync);
+        }
+
+        self.uart.read(&mut byte).await?;
+        let id = byte[0] & 0x3F;
+        if protected_id(id) != byte[0] {
+            return Err(Error::Parity);
+        }
+        Ok(id)
+    }
+
+    /// Send the response to the frame `id` as a slave: `data` and checksum.
+    pub async fn respond(&mut self, id: u8, data: &[u8]) -> Result<(), Error>
+    where
+        TxDma: super::TxDma<T>,
+    {
+        assert!(data.len() <= MAX_DATA_LEN);
+
+        let mut response = [0; MAX_DATA_LEN + 1];
+        response[..data.len()].copy_from_slice(data);
+        response[data.len()] = checksum(self.model, id, data);
+
+        self.uart.write(&response[..data.len() + 1]).await?;
+        self.uart.blocking_flush()?;
+        Ok(())
+    }
+
+    /// Receive the response to the frame `id` into `data`, and check its checksum.
+    ///
+    /// The length of the response is fixed for each identifier and isn't sent on the bus, it's
+    /// the length of `data`.
+    pub async fn receive(&mut self, id: u8, data: &mut [u8]) -> Result<(), Error>
+    where
+        RxDma: super::RxDma<T>,
+    {
+        assert!(data.len() <= MAX_DATA_LEN);
+
+        let mut response = [0; MAX_DATA_LEN + 1];
+        let response = &mut response[..data.len() + 1];
+        self.uart.read(response).await?;
+
+        let (payload, sum) = response.split_at(data.len());
+        if checksum(self.model, id, payload) != sum[0] {
+            return Err(Error::Checksum);
+        }
+        data.copy_from_slice(payload);
+        Ok(())
+    }
+
+    /// Change the baud rate. See [`Uart::reconfigure`].
+    pub fn reconfigure(&mut self, config: Config) {
+        self.uart.reconfigure(config)
+    }
+
+    /// Leave the LIN mode, and release the UART.
+    pub fn into_inner(self) -> Uart<'d, T, TxDma, RxDma> {
+        let r = T::regs();
+        unsafe {
+            r.cr1().modify(|w| w.set_ue(false));
+            r.cr2().modify(|w| w.set_linen(false));
+            r.cr1().modify(|w| w.set_ue(true));
+        }
+        self.uart
+    }
+}
+
+#[cfg(usart_v1)]
+unsafe fn break_detected(r: crate::pac::usart::Usart) -> bool {
+    r.sr().read().lbd()
+}
+
+#[cfg(usart_v1)]
+unsafe fn clear_break_flag(r: crate::pac::usart::Usart) {
+    r.sr().modify(|w| w.set_lbd(false));
+}
+
+#[cfg(usart_v2)]
+unsafe fn break_detected(r: crate::pac::usart::Usart) -> bool {
+    r.isr().read().lbdf()
+}
+
+#[cfg(usart_v2)]
+unsafe fn clear_break_flag(r: crate::pac::usart::Usart) {
+    r.icr().write(|w| w.set_lbdcf(true));
+}
+
+/// One slot of a LIN schedule table.
+#[cfg(feature = "_time-driver")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScheduleEntry {
+    /// Identifier of the frame sent in this slot.
+    pub id: u8,
+    /// Length of the slot, until the header of the next frame.
+    pub slot: embassy::time::Duration,
+}
+
+/// Master schedule: runs through a table of frame slots, forever.
+///
+/// The schedule only keeps the time, it doesn't know what the frames are: the master task
+/// gets the next entry, then publishes or requests the frame with the [`Lin`] driver. Slots
+/// are measured from the start of the previous one, so the time spent on a frame doesn't
+/// shift the schedule, as long as it fits in its slot.
+#[cfg(feature = "_time-driver")]
+pub struct Schedule<'a> {
+    table: &'a [ScheduleEntry],
+    index: usize,
+    next_slot: Option<embassy::time::Instant>,
+}
+
+#[cfg(feature = "_time-driver")]
+impl<'a> Schedule<'a> {
+    pub fn new(table: &'a [ScheduleEntry]) -> Self {
+        assert!(!table.is_empty());
+        Self {
+            table,
+            index: 0,
+            next_slot: None,
+        }
+    }
+
+    /// Wait for the start of the next slot, and return its entry.
+    ///
+    /// The first slot starts immediately. If a slot was overrun, the next one starts
+    /// immediately too, and the following ones are delayed accordingly.
+    pub async fn next(&mut self) -> &'a ScheduleEntry {
+        use embassy::time::{Instant, Timer};
+
+        let now = Instant::now();
+        let start = match self.next_slot {
+            Some(at) if at > now => {
+                Timer::at(at).await;
+                at
+            }
+            _ => now,
+        };
+
+        let entry = &self.table[self.index];
+        self.index = (self.index + 1) % self.table.len();
+        self.next_slot = Some(start + entry.slot);
+        entry
+    }
+
+    /// Restart from the first entry of the table.
+    pub fn reset(&mut self) {
+        self.index = 0;
+        self.next_slot = None;
+    }
+}
@@ -0,0 +1,442 @@
+//! Low-power UART.
+//!
+//! The LPUART keeps receiving in STOP mode when clocked from LSE or HSI16, and can wake up the
+//! chip on a start bit, a received byte, or a byte matching its address. This lets a battery
+//! powered device sleep while still answering a serial console, or the URCs of a modem.
+//!
+//! Clocked from the 32.768 kHz LSE, the baud rate is at most 9600.
+
+use core::marker::PhantomData;
+use core::task::Poll;
+use embassy::interrupt::{Interrupt, InterruptExt};
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::unborrow;
+use futures::future::poll_fn;
+
+use crate::dma::NoDma;
+use crate::gpio::sealed::AFType;
+use crate::pac::usart::vals;
+use crate::peripherals;
+use crate::rcc::RccPeripheral;
+use crate::time::Hertz;
+
+use super::{DataBits, Error, Parity, StopBits};
+
+const HSI16_FREQ: u32 = 16_000_000;
+const LSE_FREQ: u32 = 32_768;
+
+/// Kernel clock of the LPUART.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ClockSource {
+    /// The APB clock. It stops in STOP mode, so the LPUART can't wake the chip up.
+    Pclk,
+    /// The 16 MHz HSI, started on demand by the LPUART in STOP mode.
+    Hsi16,
+    /// The 32.768 kHz LSE, started by the driver. It runs in STOP mode.
+    Lse,
+}
+
+/// Event waking the chip up from STOP mode.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Wakeup {
+    /// A start bit is detected.
+    StartBit,
+    /// A byte is received.
+    RxNotEmpty,
+    /// A byte with this 7 bit address in its low bits is received.
+    AddressMatch(u8),
+}
+
+#[non_exhaustive]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Config {
+    pub baudrate: u32,
+    pub data_bits: DataBits,
+    pub stop_bits: StopBits,
+    pub parity: Parity,
+    pub clock_source: ClockSource,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            baudrate: 9600,
+            data_bits: DataBits::DataBits8,
+            stop_bits: StopBits::STOP1,
+            parity: Parity::ParityNone,
+            clock_source: ClockSource::Hsi16,
+        }
+    }
+}
+
+pub struct Lpuart<'d, T: Instance, TxDma = NoDma, RxDma = NoDma> {
+    phantom: PhantomData<&'d mut T>,
+    tx_dma: TxDma,
+    rx_dma: RxDma,
+}
+
+impl<'d, T: Instance, TxDma, RxDma> Lpuart<'d, T, TxDma, RxDma> {
+    pub fn new(
+        _inner: impl Unborrow<Target = T> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        rx: impl Unborrow<Target = impl RxPin<T>> + 'd,
+        tx: impl Unborrow<Target = impl TxPin<T>> + 'd,
+        tx_dma: impl Unborrow<Target = TxDma> + 'd,
+        rx_dma: impl Unborrow<Target = RxDma> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(_inner, irq, rx, tx, tx_dma, rx_dma);
+
+        T::enable();
+        T::reset();
+
+        let r = T::regs();
+
+        unsafe {
+            rx.set_as_af(rx.af_num(), AFType::Input);
+            tx.set_as_af(tx.af_num(), AFType::OutputPushPull);
+
+            r.cr2().write(|_w| {});
+            r.cr3().write(|_w| {});
+        }
+
+        configure::<T>(&config);
+
+        irq.set_handler(Self::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        Self {
+            phantom: PhantomData,
+            tx_dma,
+            rx_dma,
+        }
+    }
+
+    fn on_interrupt(_: *mut ()) {
+        let r = T::regs();
+        unsafe {
+            if r.isr().read().wuf() {
+                // Disabled until the next `wait_for_wakeup`, the flag is cleared there.
+                r.cr3().modify(|w| w.set_wufie(false));
+                T::state().wake();
+            }
+        }
+    }
+
+    /// Change the baud rate, frame format and kernel clock.
+    pub fn reconfigure(&mut self, config: Config) {
+        configure::<T>(&config);
+    }
+
+    pub async fn write(&mut self, buffer: &[u8]) -> Result<(), Error>
+    where
+        TxDma: crate::usart::lpuart::TxDma<T>,
+    {
+        let ch = &mut self.tx_dma;
+        let request = ch.request();
+        unsafe {
+            T::regs().cr3().modify(|reg| {
+                reg.set_dmat(true);
+            });
+        }
+        // If we don't assign future to a variable, the data register pointer
+        // is held across an await and makes the future non-Send.
+        let transfer = crate::dma::write(ch, request, buffer, T::regs().tdr().ptr() as *mut u8);
+        transfer.await;
+        Ok(())
+    }
+
+    pub async fn read(&mut self, buffer: &mut [u8]) -> Result<(), Error>
+    where
+        RxDma: crate::usart::lpuart::RxDma<T>,
+    {
+        let ch = &mut self.rx_dma;
+        let request = ch.request();
+        unsafe {
+            T::regs().cr3().modify(|reg| {
+                reg.set_dmar(true);
+            });
+        }
+        // If we don't assign future to a variable, the data register pointer
+        // is held across an await and makes the future non-Send.
+        let transfer = crate::dma::read(ch, request, T::regs().rdr().ptr() as *mut u8, buffer);
+        transfer.await;
+        Ok(())
+    }
+
+    pub fn blocking_write(&mut self, buffer: &[u8]) -> Result<(), Error> {
+        let r = T::regs();
+        unsafe {
+            for &b in buffer {
+                while !r.isr().read().txe() {}
+                r.tdr().write(|w| w.set_dr(b as _));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn blocking_flush(&mut self) -> Result<(), Error> {
+        let r = T::regs();
+        unsafe { while !r.isr().read().tc() {} }
+        Ok(())
+    }
+
+    pub fn blocking_read(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        let r = T::regs();
+        unsafe {
+            for b in buffer {
+                loop {
+                    let sr = r.isr().read();
+                    let err = if sr.pe() {
+                        Some(Error::Parity)
+                    } else if sr.fe() {
+                        Some(Error::Framing)
+                    } else if sr.ne() {
+                        Some(Error::Noise)
+                    } else if sr.ore() {
+                        Some(Error::Overrun)
+                    } else {
+                        None
+                    };
+                    if let Some(err) = err {
+                        r.icr().write(|w| *w = sr);
+                        r.rdr().read();
+                        return Err(err);
+                    }
+                    if sr.rxne() {
+                        break;
+                    }
+                }
+                *b = r.rdr().read().dr() as u8;
+            }
+        }
+        Ok(())
+    }
+
+    /// Let the LPUART wake the chip up from STOP mode on `wakeup`.
+    ///
+    /// The kernel clock must be [`ClockSource::Hsi16`] or [`ClockSource::Lse`]. The received
+    /// data is kept: the byte that woke the chip up can still be read.
+    pub fn enable_wakeup(&mut self, wakeup: Wakeup) {
+        let r = T::regs();
+        unsafe {
+            // The wakeup event and the address can only be changed with the LPUART disabled.
+            r.cr1().modify(|w| w.set_ue(false));
+            if let Wakeup::AddressMatch(addr) = wakeup {
+                assert!(addr < 0x80);
+                r.cr2().modify(|w| {
+                    w.set_add(addr);
+                    w.set_addm7(vals::Addm7::BIT7);
+                });
+            }
+            r.cr3().modify(|w| {
+                w.set_wus(match wakeup {
+                    Wakeup::AddressMatch(_) => vals::Wus::ADDRESS,
+                    Wakeup::StartBit => vals::Wus::START,
+                    Wakeup::RxNotEmpty => vals::Wus::RXNE,
+                });
+            });
+            r.cr1().modify(|w| {
+                w.set_uesm(true);
+                w.set_ue(true);
+            });
+        }
+    }
+
+    /// Stop waking the chip up from STOP mode.
+    pub fn disable_wakeup(&mut self) {
+        let r = T::regs();
+        unsafe {
+            r.cr3().modify(|w| w.set_wufie(false));
+            r.cr1().modify(|w| w.set_uesm(false));
+        }
+    }
+
+    /// Wait for the next wakeup event set with [`enable_wakeup`](Self::enable_wakeup).
+    ///
+    /// The event is signaled by an interrupt, so it also wakes the executor up when the chip
+    /// was put in STOP mode while waiting.
+    pub async fn wait_for_wakeup(&mut self) {
+        let r = T::regs();
+        unsafe {
+            r.icr().write(|w| w.set_wucf(true));
+        }
+        poll_fn(|cx| {
+            T::state().register(cx.waker());
+            unsafe {
+                if r.isr().read().wuf() {
+                    r.icr().write(|w| w.set_wucf(true));
+                    return Poll::Ready(());
+                }
+                r.cr3().modify(|w| w.set_wufie(true));
+            }
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+impl<'d, T: Instance, TxDma, RxDma> Drop for Lpuart<'d, T, TxDma, RxDma> {
+    fn drop(&mut self) {
+        T::Interrupt::steal().disable();
+        unsafe {
+            T::regs().cr1().modify(|w| w.set_ue(false));
+        }
+    }
+}
+
+fn configure<T: Instance>(config: &Config) {
+    let ker_freq = select_clock::<T>(config.clock_source).0 as u64;
+    let baudrate = config.baudrate as u64;
+
+    // The kernel clock must be between 3 and 4096 times the baud rate.
+    assert!(ker_freq >= 3 * baudrate && ker_freq <= 4096 * baudrate);
+    let div = (256 * ker_freq + baudrate / 2) / baudrate;
+    assert!(div >= 0x300 && div < 1 << 20);
+
+    let r = T::regs();
+
+    unsafe {
+        // The baud rate and frame format can only be changed with the LPUART disabled.
+        r.cr1().modify(|w| w.set_ue(false));
+        r.brr().write(|w| w.set_brr(div as u32));
+        r.cr2().modify(|w| {
+            w.set_stop(match config.stop_bits {
+                StopBits::STOP1 => vals::Stop::STOP1,
+                StopBits::STOP2 => vals::Stop::STOP2,
+                _ => panic!("LPUART supports only 1 or 2 stop bits"),
+            });
+        });
+        r.cr1().modify(|w| {
+            w.set_te(true);
+            w.set_re(true);
+            // With parity, the parity bit takes the place of the last data bit.
+            w.set_m0(match (config.data_bits, config.parity) {
+                (DataBits::DataBits8, Parity::ParityNone) => vals::M0::BIT8,
+                _ => vals::M0::BIT9,
+            });
+            w.set_pce(config.parity != Parity::ParityNone);
+            w.set_ps(match config.parity {
+                Parity::ParityOdd => vals::Ps::ODD,
+                _ => vals::Ps::EVEN,
+            });
+            w.set_ue(true);
+        });
+    }
+}
+
+/// Select the kernel clock of the LPUART, starting its oscillator. Returns its frequency.
+fn select_clock<T: Instance>(source: ClockSource) -> Hertz {
+    match source {
+        ClockSource::Pclk => {
+            unsafe { set_lpuart1sel(0) };
+            T::frequency()
+        }
+        ClockSource::Hsi16 => {
+            unsafe {
+                enable_hsi16();
+                set_lpuart1sel(2);
+            }
+            Hertz(HSI16_FREQ)
+        }
+        ClockSource::Lse => {
+            unsafe {
+                enable_lse();
+                set_lpuart1sel(3);
+            }
+            Hertz(LSE_FREQ)
+        }
+    }
+}
+
+#[cfg(any(rcc_l0, rcc_l4, rcc_g0, rcc_g4, rcc_wb, rcc_wl5, rcc_wle))]
+unsafe fn set_lpuart1sel(sel: u8) {
+    crate::pac::RCC.ccipr().modify(|w| w.set_lpuart1sel(sel));
+}
+
+#[cfg(rcc_l5)]
+unsafe fn set_lpuart1sel(sel: u8) {
+    crate::pac::RCC.ccipr1().modify(|w| w.set_lpuart1sel(sel));
+}
+
+#[cfg(not(any(rcc_l0, rcc_l4, rcc_l5, rcc_g0, rcc_g4, rcc_wb, rcc_wl5, rcc_wle)))]
+unsafe fn set_lpuart1sel(sel: u8) {
+    assert!(
+        sel == 0,
+        "Kernel clock selection isn't supported on this chip"
+    );
+}
+
+#[cfg(rcc_l0)]
+unsafe fn enable_hsi16() {
+    let rcc = crate::pac::RCC;
+    rcc.cr().modify(|w| w.set_hsi16on(true));
+    while !rcc.cr().read().hsi16rdyf() {}
+}
+
+#[cfg(not(rcc_l0))]
+unsafe fn enable_hsi16() {
+    let rcc = crate::pac::RCC;
+    rcc.cr().modify(|w| w.set_hsion(true));
+    while !rcc.cr().read().hsirdy() {}
+}
+
+#[cfg(rcc_l0)]
+unsafe fn enable_lse() {
+    let rcc = crate::pac::RCC;
+    // The LSE is in the backup domain, which is write protected.
+    crate::pac::PWR.cr().modify(|w| w.set_dbp(true));
+    rcc.csr().modify(|w| w.set_lseon(true));
+    while !rcc.csr().read().lserdy() {}
+}
+
+#[cfg(not(rcc_l0))]
+unsafe fn enable_lse() {
+    let rcc = crate::pac::RCC;
+    // The LSE is in the backup domain, which is write protected.
+    crate::pac::PWR.cr1().modify(|w| w.set_dbp(true));
+    rcc.bdcr().modify(|w| w.set_lseon(true));
+    while !rcc.bdcr().read().lserdy() {}
+}
+
+pub(crate) mod sealed {
+    use super::*;
+
+    pub trait Instance {
+        fn regs() -> crate::pac::usart::Lpuart;
+        fn state() -> &'static AtomicWaker;
+    }
+}
+
+pub trait Instance: sealed::Instance + RccPeripheral {
+    type Interrupt: Interrupt;
+}
+
+pin_trait!(RxPin, Instance);
+pin_trait!(TxPin, Instance);
+
+dma_trait!(TxDma, Instance);
+dma_trait!(RxDma, Instance);
+
+foreach_interrupt!(
+    ($inst:ident, lpuart, $block:ident, $signal_name:ident, $irq:ident) => {
+        impl sealed::Instance for peripherals::$inst {
+            fn regs() -> crate::pac::usart::Lpuart {
+                crate::pac::$inst
+            }
+
+            fn state() -> &'static AtomicWaker {
+                static WAKER: AtomicWaker = AtomicWaker::new();
+                &WAKER
+            }
+        }
+
+        impl Instance for peripherals::$inst {
+            type Interrupt = crate::interrupt::$irq;
+        }
+    };
+);
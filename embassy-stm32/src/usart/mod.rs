@@ -11,6 +11,14 @@ use crate::pac::usart::{regs, vals};
 use crate::peripherals;
 use crate::rcc::RccPeripheral;
 
+pub mod irda;
+pub mod lin;
+#[cfg(lpuart)]
+pub mod lpuart;
+mod rs485;
+pub mod smartcard;
+pub use rs485::*;
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum DataBits {
     DataBits8,
@@ -201,10 +209,6 @@ impl<'d, T: Instance, TxDma, RxDma> Uart<'d, T, TxDma, RxDma> {
 
         T::enable();
         T::reset();
-        let pclk_freq = T::frequency();
-
-        // TODO: better calculation, including error checking and OVER8 if possible.
-        let div = (pclk_freq.0 + (config.baudrate / 2)) / config.baudrate;
 
         let r = T::regs();
 
@@ -214,21 +218,10 @@ impl<'d, T: Instance, TxDma, RxDma> Uart<'d, T, TxDma, RxDma> {
 
             r.cr2().write(|_w| {});
             r.cr3().write(|_w| {});
-            r.brr().write_value(regs::Brr(div));
-            r.cr1().write(|w| {
-                w.set_ue(true);
-                w.set_te(true);
-                w.set_re(true);
-                w.set_m0(vals::M0::BIT8);
-                w.set_pce(config.parity != Parity::ParityNone);
-                w.set_ps(match config.parity {
-                    Parity::ParityOdd => vals::Ps::ODD,
-                    Parity::ParityEven => vals::Ps::EVEN,
-                    _ => vals::Ps::EVEN,
-                });
-            });
         }
 
+        configure::<T>(&config);
+
         Self {
             phantom: PhantomData,
             tx: UartTx::new(tx_dma),
@@ -268,6 +261,50 @@ impl<'d, T: Instance, TxDma, RxDma> Uart<'d, T, TxDma, RxDma> {
     pub fn split(self) -> (UartTx<'d, T, TxDma>, UartRx<'d, T, RxDma>) {
         (self.tx, self.rx)
     }
+
+    /// Change the baud rate and parity.
+    ///
+    /// No transfer is in progress, as they borrow the Uart, but the last byte written
+    /// with [`Uart::write`] may still be shifting out. Call [`Uart::blocking_flush`] first
+    /// to avoid garbling it.
+    pub fn reconfigure(&mut self, config: Config) {
+        configure::<T>(&config);
+    }
+}
+
+fn configure<T: Instance>(config: &Config) {
+    let pclk_freq = T::frequency();
+
+    // TODO: better calculation, including error checking and OVER8 if possible.
+    let div = (pclk_freq.0 + (config.baudrate / 2)) / config.baudrate;
+
+    let r = T::regs();
+
+    unsafe {
+        // The baud rate and frame format can only be changed with the USART disabled.
+        r.cr1().modify(|w| w.set_ue(false));
+        r.brr().write_value(regs::Brr(div));
+        r.cr1().write(|w| {
+            w.set_ue(true);
+            w.set_te(true);
+            w.set_re(true);
+            w.set_m0(vals::M0::BIT8);
+            w.set_pce(config.parity != Parity::ParityNone);
+            w.set_ps(match config.parity {
+                Parity::ParityOdd => vals::Ps::ODD,
+                Parity::ParityEven => vals::Ps::EVEN,
+                _ => vals::Ps::EVEN,
+            });
+        });
+    }
+}
+
+#[cfg(feature = "nightly")]
+impl<'d, T: Instance, TxDma, RxDma> embassy_embedded_hal::SetConfig for Uart<'d, T, TxDma, RxDma> {
+    type Config = Config;
+    fn set_config(&mut self, config: &Self::Config) {
+        self.reconfigure(*config);
+    }
 }
 
 mod eh02 {
@@ -686,6 +723,13 @@ unsafe fn clear_interrupt_flags(r: crate::pac::usart::Usart, sr: regs::Ixr) {
     r.icr().write(|w| *w = sr);
 }
 
+/// Discard the received data and the pending errors.
+unsafe fn clear_rx(r: crate::pac::usart::Usart) {
+    let sr = sr(r).read();
+    clear_interrupt_flags(r, sr);
+    rdr(r).read_volatile();
+}
+
 pub(crate) mod sealed {
     pub trait Instance {
         fn regs() -> crate::pac::usart::Usart;
@@ -701,6 +745,7 @@ pin_trait!(TxPin, Instance);
 pin_trait!(CtsPin, Instance);
 pin_trait!(RtsPin, Instance);
 pin_trait!(CkPin, Instance);
+pin_trait!(DePin, Instance);
 
 dma_trait!(TxDma, Instance);
 dma_trait!(RxDma, Instance);
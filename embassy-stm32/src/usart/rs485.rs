@@ -0,0 +1,168 @@
+use embassy_hal_common::drop::OnDrop;
+
+use super::*;
+use crate::gpio::{AnyPin, Level, Output, Pin, Speed};
+
+/// Polarity of the driver enable signal of a RS-485 transceiver.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DePolarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+struct GpioDe<'d> {
+    pin: Output<'d, AnyPin>,
+    polarity: DePolarity,
+}
+
+impl<'d> GpioDe<'d> {
+    fn set(&mut self, active: bool) {
+        if active == (self.polarity == DePolarity::ActiveHigh) {
+            self.pin.set_high();
+        } else {
+            self.pin.set_low();
+        }
+    }
+}
+
+/// UART driving a half-duplex RS-485 transceiver.
+///
+/// The driver enable (DE) signal of the transceiver is asserted while transmitting, and
+/// released as soon as the last stop bit is sent, so the bus is free for the answer.
+/// It's generated either by the USART itself, on parts which support it, or by toggling
+/// a GPIO.
+///
+/// With a GPIO, the DE signal is only released once the transmission is complete, after
+/// the DMA transfer is done: it's held for at most a few more bit times, the latency of
+/// the executor doesn't matter.
+pub struct Rs485<'d, T: Instance, TxDma = NoDma, RxDma = NoDma> {
+    uart: Uart<'d, T, TxDma, RxDma>,
+    de: Option<GpioDe<'d>>,
+}
+
+impl<'d, T: Instance, TxDma, RxDma> Rs485<'d, T, TxDma, RxDma> {
+    /// Create a RS-485 UART with the DE signal generated by the USART on `de`.
+    #[cfg(usart_v2)]
+    pub fn new(
+        inner: impl Unborrow<Target = T> + 'd,
+        rx: impl Unborrow<Target = impl RxPin<T>> + 'd,
+        tx: impl Unborrow<Target = impl TxPin<T>> + 'd,
+        de: impl Unborrow<Target = impl DePin<T>> + 'd,
+        tx_dma: impl Unborrow<Target = TxDma> + 'd,
+        rx_dma: impl Unborrow<Target = RxDma> + 'd,
+        polarity: DePolarity,
+        config: Config,
+    ) -> Self {
+        unborrow!(de);
+
+        let uart = Uart::new(inner, rx, tx, tx_dma, rx_dma, config);
+
+        let r = T::regs();
+        unsafe {
+            de.set_as_af(de.af_num(), AFType::OutputPushPull);
+
+            // The driver enable mode can only be changed with the USART disabled.
+            r.cr1().modify(|w| w.set_ue(false));
+            r.cr3().modify(|w| {
+                w.set_dem(true);
+                w.set_dep(polarity == DePolarity::ActiveLow);
+            });
+            r.cr1().modify(|w| w.set_ue(true));
+        }
+
+        Self { uart, de: None }
+    }
+
+    /// Create a RS-485 UART with the DE signal driven by a GPIO.
+    pub fn new_with_gpio_de(
+        uart: Uart<'d, T, TxDma, RxDma>,
+        de: impl Unborrow<Target = impl Pin> + 'd,
+        polarity: DePolarity,
+    ) -> Self {
+        unborrow!(de);
+
+        let mut de = GpioDe {
+            pin: Output::new(de.degrade(), Level::Low, Speed::VeryHigh),
+            polarity,
+        };
+        de.set(false);
+
+        Self { uart, de: Some(de) }
+    }
+
+    /// Transmit `buffer`, and wait until it has been completely sent on the bus.
+    pub async fn write(&mut self, buffer: &[u8]) -> Result<(), Error>
+    where
+        TxDma: super::TxDma<T>,
+    {
+        let de = &mut self.de;
+        if let Some(de) = de.as_mut() {
+            de.set(true);
+        }
+        // Release the bus even if the transfer is cancelled.
+        let _on_drop = OnDrop::new(|| {
+            if let Some(de) = de.as_mut() {
+                de.set(false);
+            }
+        });
+
+        self.uart.write(buffer).await?;
+        // The end of the DMA transfer only means the last byte is in the data register,
+        // keep driving the bus until it's shifted out.
+        self.uart.blocking_flush()
+    }
+
+    /// Transmit `buffer`, blocking until it has been completely sent on the bus.
+    pub fn blocking_write(&mut self, buffer: &[u8]) -> Result<(), Error> {
+        if let Some(de) = &mut self.de {
+            de.set(true);
+        }
+        let res = self
+            .uart
+            .blocking_write(buffer)
+            .and_then(|_| self.uart.blocking_flush());
+        if let Some(de) = &mut self.de {
+            de.set(false);
+        }
+        res
+    }
+
+    /// Receive enough bytes to fill `buffer`.
+    pub async fn read(&mut self, buffer: &mut [u8]) -> Result<(), Error>
+    where
+        RxDma: super::RxDma<T>,
+    {
+        self.uart.read(buffer).await
+    }
+
+    /// Receive enough bytes to fill `buffer`, blocking.
+    pub fn blocking_read(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        self.uart.blocking_read(buffer)
+    }
+
+    /// Send a request, then receive the response of the other end into `response`.
+    ///
+    /// Anything received while transmitting, like the echo of the request when the receiver
+    /// of the transceiver is always enabled, is discarded. This doesn't time out if the other
+    /// end doesn't answer: use `embassy::time::with_timeout` for that.
+    pub async fn transaction(&mut self, request: &[u8], response: &mut [u8]) -> Result<(), Error>
+    where
+        TxDma: super::TxDma<T>,
+        RxDma: super::RxDma<T>,
+    {
+        self.write(request).await?;
+        unsafe { clear_rx(T::regs()) };
+        self.uart.read(response).await
+    }
+
+    /// Change the baud rate and parity. See [`Uart::reconfigure`].
+    pub fn reconfigure(&mut self, config: Config) {
+        self.uart.reconfigure(config)
+    }
+
+    /// Release the UART, and the GPIO driving the DE signal if any.
+    pub fn into_inner(self) -> Uart<'d, T, TxDma, RxDma> {
+        self.uart
+    }
+}
@@ -0,0 +1,294 @@
+//! Smartcard (ISO 7816-3) mode.
+//!
+//! The USART clocks the card on its CK pin, and exchanges characters with it on the TX pin,
+//! used as a bidirectional open drain line with an external pull-up. The card reset and power
+//! are plain GPIOs, driven by the application.
+//!
+//! Characters are 8 data bits with even parity. A receiver which gets a parity error pulls
+//! the line low during the guard time (NACK), and the sender repeats the character.
+
+use super::*;
+use crate::time::Hertz;
+
+/// Smartcard configuration.
+#[non_exhaustive]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Config {
+    /// Frequency of the clock output to the card. The actual frequency is the closest one at
+    /// or below, obtained by dividing the USART kernel clock by an even number.
+    pub clock: Hertz,
+    /// Length of the elementary time unit (ETU), a bit time, in card clock cycles: the `F/D`
+    /// ratio of the ATR, 372 until the card and reader agree on another one.
+    pub etu: u16,
+    /// Guard time, in ETU, between the start of a character sent to the card and the next
+    /// one. ISO 7816-3 requires at least 12, plus the extra guard time from the ATR.
+    pub guard_time: u8,
+    /// Send a NACK on parity errors, so the card repeats the character.
+    pub nack: bool,
+    /// Number of times a character NACKed by the card is sent again, before failing with
+    /// [`Error::Nack`]. At most 7.
+    #[cfg(usart_v2)]
+    pub retries: u8,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            clock: Hertz(4_000_000),
+            etu: 372,
+            guard_time: 12,
+            nack: true,
+            #[cfg(usart_v2)]
+            retries: 3,
+        }
+    }
+}
+
+/// Smartcard error
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum Error {
+    /// Error of the underlying UART.
+    Uart(super::Error),
+    /// The card didn't accept a character.
+    Nack,
+    /// The card sent a procedure byte which doesn't fit the command.
+    Procedure(u8),
+    /// The response buffer is too small for the expected response.
+    BufferTooSmall,
+}
+
+impl From<super::Error> for Error {
+    fn from(e: super::Error) -> Self {
+        Self::Uart(e)
+    }
+}
+
+/// Response to a T=0 command.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Response {
+    /// Number of bytes received in the response buffer.
+    pub len: usize,
+    /// Status word, `SW1` and `SW2`. `0x9000` means success.
+    pub status: u16,
+}
+
+/// UART in smartcard mode.
+pub struct Smartcard<'d, T: Instance, TxDma = NoDma, RxDma = NoDma> {
+    uart: Uart<'d, T, TxDma, RxDma>,
+}
+
+impl<'d, T: Instance, TxDma, RxDma> Smartcard<'d, T, TxDma, RxDma> {
+    /// Create a smartcard interface, and start clocking the card.
+    ///
+    /// `io` is the TX pin of the USART, and `ck` its clock output.
+    pub fn new(
+        _inner: impl Unborrow<Target = T> + 'd,
+        io: impl Unborrow<Target = impl TxPin<T>> + 'd,
+        ck: impl Unborrow<Target = impl CkPin<T>> + 'd,
+        tx_dma: impl Unborrow<Target = TxDma> + 'd,
+        rx_dma: impl Unborrow<Target = RxDma> + 'd,
+        config: Config,
+    ) -> Self {
+        unborrow!(_inner, io, ck, tx_dma, rx_dma);
+
+        T::enable();
+        T::reset();
+
+        unsafe {
+            io.set_as_af(io.af_num(), AFType::OutputOpenDrain);
+            ck.set_as_af(ck.af_num(), AFType::OutputPushPull);
+        }
+
+        configure_smartcard::<T>(&config);
+
+        Self {
+            uart: Uart {
+                phantom: PhantomData,
+                tx: UartTx::new(tx_dma),
+                rx: UartRx::new(rx_dma),
+            },
+        }
+    }
+
+    /// Send `buffer` to the card.
+    ///
+    /// The line is shared, so the characters sent are received too: they're discarded.
+    pub async fn write(&mut self, buffer: &[u8]) -> Result<(), Error>
+    where
+        TxDma: super::TxDma<T>,
+    {
+        self.uart.write(buffer).await?;
+        self.finish_write()
+    }
+
+    /// Send `buffer` to the card, blocking.
+    pub fn blocking_write(&mut self, buffer: &[u8]) -> Result<(), Error> {
+        self.uart.blocking_write(buffer)?;
+        self.finish_write()
+    }
+
+    fn finish_write(&mut self) -> Result<(), Error> {
+        self.uart.blocking_flush()?;
+        let r = T::regs();
+        unsafe {
+            // The transmitter reports a character NACKed by the card (after the retries, on
+            // parts which repeat it by themselves) as a framing error.
+            let nack = sr(r).read().fe();
+            clear_rx(r);
+            if nack {
+                return Err(Error::Nack);
+            }
+        }
+        Ok(())
+    }
+
+    /// Receive enough bytes from the card to fill `buffer`.
+    ///
+    /// This doesn't time out if the card doesn't answer: use `embassy::time::with_timeout`
+    /// to enforce the waiting time given by the ATR.
+    pub async fn read(&mut self, buffer: &mut [u8]) -> Result<(), Error>
+    where
+        RxDma: super::RxDma<T>,
+    {
+        self.uart.read(buffer).await?;
+        Ok(())
+    }
+
+    /// Receive enough bytes from the card to fill `buffer`, blocking.
+    pub fn blocking_read(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        self.uart.blocking_read(buffer)?;
+        Ok(())
+    }
+
+    /// Send a command APDU with the T=0 protocol, and receive the response data into
+    /// `response`.
+    ///
+    /// `command` is the 5 byte header `CLA INS P1 P2 P3` followed by the command data, if
+    /// any. With data, `P3` is its length. Without, `P3` is the length of the expected
+    /// response, 0 meaning 256 bytes. T=0 doesn't carry data both ways: a card with response
+    /// data to a command with data answers with the status `61xx`, and the response is read
+    /// with a GET RESPONSE command.
+    pub async fn transfer_t0(
+        &mut self,
+        command: &[u8],
+        response: &mut [u8],
+    ) -> Result<Response, Error>
+    where
+        TxDma: super::TxDma<T>,
+        RxDma: super::RxDma<T>,
+    {
+        assert!(command.len() >= 5, "a command starts with a 5 byte header");
+        let (header, mut data) = command.split_at(5);
+        let ins = header[1];
+
+        let mut expected = if data.is_empty() {
+            match header[4] {
+                0 => 256,
+                n => n as usize,
+            }
+        } else {
+            assert_eq!(
+                data.len(),
+                header[4] as usize,
+                "P3 is the length of the data"
+            );
+            0
+        };
+        if response.len() < expected {
+            return Err(Error::BufferTooSmall);
+        }
+
+        self.write(header).await?;
+
+        let mut len = 0;
+        let mut byte = [0];
+        loop {
+            self.read(&mut byte).await?;
+            match byte[0] {
+                // NULL: the card needs more time.
+                0x60 => {}
+                // SW1, followed by SW2.
+                sw1 if sw1 & 0xF0 == 0x60 || sw1 & 0xF0 == 0x90 => {
+                    self.read(&mut byte).await?;
+                    return Ok(Response {
+                        len,
+                        status: (sw1 as u16) << 8 | byte[0] as u16,
+                    });
+                }
+                // ACK: all the remaining bytes.
+                b if b == ins => {
+                    if !data.is_empty() {
+                        self.write(data).await?;
+                        data = &[];
+                    } else if expected > 0 {
+                        self.read(&mut response[len..][..expected]).await?;
+                        len += expected;
+                        expected = 0;
+                    }
+                }
+                // ACK: only the next byte.
+                b if b == !ins => {
+                    if !data.is_empty() {
+                        self.write(&data[..1]).await?;
+                        data = &data[1..];
+                    } else if expected > 0 {
+                        self.read(&mut response[len..][..1]).await?;
+                        len += 1;
+                        expected -= 1;
+                    }
+                }
+                b => return Err(Error::Procedure(b)),
+            }
+        }
+    }
+
+    /// Change the clock, ETU and guard time, after the card and the reader agreed on new
+    /// parameters.
+    pub fn reconfigure(&mut self, config: Config) {
+        configure_smartcard::<T>(&config);
+    }
+}
+
+fn configure_smartcard<T: Instance>(config: &Config) {
+    let pclk_freq = T::frequency().0;
+
+    // The card clock is the kernel clock divided by twice the prescaler.
+    let psc = (pclk_freq + 2 * config.clock.0 - 1) / (2 * config.clock.0);
+    assert!((1..=31).contains(&psc), "card clock out of range");
+    // One ETU is `etu` card clock cycles, that is `2 * psc * etu` kernel clock cycles.
+    let div = 2 * psc * config.etu as u32;
+
+    let r = T::regs();
+
+    unsafe {
+        // The smartcard mode can only be changed with the USART disabled.
+        r.cr1().modify(|w| w.set_ue(false));
+        r.brr().write_value(regs::Brr(div));
+        r.gtpr().write(|w| {
+            w.set_psc(psc as u8);
+            w.set_gt(config.guard_time);
+        });
+        r.cr2().write(|w| {
+            w.set_clken(true);
+            w.set_stop(vals::Stop::STOP1P5);
+        });
+        r.cr3().write(|w| {
+            w.set_scen(true);
+            w.set_nack(config.nack);
+            #[cfg(usart_v2)]
+            w.set_scarcnt(config.retries);
+        });
+        r.cr1().write(|w| {
+            w.set_ue(true);
+            w.set_te(true);
+            w.set_re(true);
+            // 8 data bits and the parity bit.
+            w.set_m0(vals::M0::BIT9);
+            w.set_pce(true);
+            w.set_ps(vals::Ps::EVEN);
+        });
+    }
+}
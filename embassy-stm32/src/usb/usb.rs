@@ -0,0 +1,1061 @@
+use core::marker::PhantomData;
+use core::task::Poll;
+
+use atomic_polyfill::{AtomicBool, AtomicU8, Ordering};
+use embassy::interrupt::InterruptExt;
+use embassy::util::Unborrow;
+use embassy::waitqueue::AtomicWaker;
+use embassy_hal_common::unborrow;
+use embassy_usb::control::Request;
+use embassy_usb::driver::{self, EndpointAllocError, EndpointError, Event, Unsupported};
+use embassy_usb::types::{EndpointAddress, EndpointInfo, EndpointType, UsbDirection};
+use futures::future::poll_fn;
+use futures::Future;
+
+pub use embassy_usb;
+
+use super::{DmPin, DpPin, Instance};
+#[allow(unused_imports)]
+use crate::gpio::sealed::{AFType, Pin};
+use crate::pac::usb::regs;
+use crate::pac::usb::vals::{EpType, Stat};
+
+const EP_COUNT: usize = 8;
+
+const NEW_AW: AtomicWaker = AtomicWaker::new();
+static BUS_WAKER: AtomicWaker = NEW_AW;
+static EP_IN_WAKERS: [AtomicWaker; EP_COUNT] = [NEW_AW; EP_COUNT];
+static EP_OUT_WAKERS: [AtomicWaker; EP_COUNT] = [NEW_AW; EP_COUNT];
+static EP0_SETUP: AtomicBool = AtomicBool::new(false);
+/// Address to apply once the status stage of SET_ADDRESS is done, or 0.
+static PENDING_ADDRESS: AtomicU8 = AtomicU8::new(0);
+
+static IRQ_FLAGS: AtomicU8 = AtomicU8::new(0);
+const IRQ_FLAG_RESET: u8 = 0x01;
+const IRQ_FLAG_SUSPEND: u8 = 0x02;
+const IRQ_FLAG_RESUME: u8 = 0x04;
+
+/// Packet memory, shared by the CPU and the USB peripheral. It starts with the buffer
+/// descriptor table (BTABLE), followed by the buffers of the endpoints.
+///
+/// Each endpoint has two buffer descriptors: slot 0 is the TX buffer, and slot 1 the RX
+/// buffer. Double-buffered endpoints use both slots in their direction.
+///
+/// The peripheral sees the memory as 16-bit words, which the CPU accesses at a 32-bit stride
+/// (`16x1`) or at a 16-bit stride (`16x2`), depending on the family. The most recent
+/// families have a 32-bit memory, with 32-bit buffer descriptors.
+mod pma {
+    use super::EP_COUNT;
+    use crate::pac::USBRAM;
+
+    #[cfg(any(usbram_16x1_512, usbram_16x2_512))]
+    pub const SIZE: u16 = 512;
+    #[cfg(any(usbram_16x2_1024, usbram_32_1024))]
+    pub const SIZE: u16 = 1024;
+    #[cfg(usbram_32_2048)]
+    pub const SIZE: u16 = 2048;
+
+    #[cfg(not(any(usbram_32_1024, usbram_32_2048)))]
+    pub const ALIGN: u16 = 2;
+    #[cfg(any(usbram_32_1024, usbram_32_2048))]
+    pub const ALIGN: u16 = 4;
+
+    pub const BTABLE_SIZE: u16 = EP_COUNT as u16 * 8;
+
+    /// Offset of a buffer descriptor in the packet memory.
+    fn desc(index: usize, slot: usize) -> u16 {
+        (index * 8 + slot * 4) as u16
+    }
+
+    #[cfg(usbram_16x1_512)]
+    fn word(offset: u16) -> *mut u16 {
+        (USBRAM.0 as usize + offset as usize * 2) as *mut u16
+    }
+
+    #[cfg(any(usbram_16x2_512, usbram_16x2_1024))]
+    fn word(offset: u16) -> *mut u16 {
+        (USBRAM.0 as usize + offset as usize) as *mut u16
+    }
+
+    #[cfg(any(usbram_32_1024, usbram_32_2048))]
+    fn word(offset: u16) -> *mut u32 {
+        (USBRAM.0 as usize + offset as usize) as *mut u32
+    }
+
+    /// Sets the address of a buffer, and its COUNT field: the length to send for TX buffers,
+    /// the size of the buffer for RX buffers.
+    #[cfg(not(any(usbram_32_1024, usbram_32_2048)))]
+    pub unsafe fn set_desc(index: usize, slot: usize, addr: u16, count: u16) {
+        word(desc(index, slot)).write_volatile(addr);
+        word(desc(index, slot) + 2).write_volatile(count);
+    }
+
+    #[cfg(any(usbram_32_1024, usbram_32_2048))]
+    pub unsafe fn set_desc(index: usize, slot: usize, addr: u16, count: u16) {
+        word(desc(index, slot)).write_volatile(addr as u32 | (count as u32) << 16);
+    }
+
+    /// Sets the length of the data to send from a TX buffer.
+    #[cfg(not(any(usbram_32_1024, usbram_32_2048)))]
+    pub unsafe fn set_count(index: usize, slot: usize, count: u16) {
+        word(desc(index, slot) + 2).write_volatile(count);
+    }
+
+    #[cfg(any(usbram_32_1024, usbram_32_2048))]
+    pub unsafe fn set_count(index: usize, slot: usize, count: u16) {
+        let w = word(desc(index, slot));
+        w.write_volatile(w.read_volatile() & 0xFFFF | (count as u32) << 16);
+    }
+
+    /// Length of the data received in an RX buffer.
+    #[cfg(not(any(usbram_32_1024, usbram_32_2048)))]
+    pub unsafe fn count(index: usize, slot: usize) -> usize {
+        (word(desc(index, slot) + 2).read_volatile() & 0x3FF) as usize
+    }
+
+    #[cfg(any(usbram_32_1024, usbram_32_2048))]
+    pub unsafe fn count(index: usize, slot: usize) -> usize {
+        ((word(desc(index, slot)).read_volatile() >> 16) & 0x3FF) as usize
+    }
+
+    pub unsafe fn read(addr: u16, buf: &mut [u8]) {
+        const N: usize = ALIGN as usize;
+        for (i, chunk) in buf.chunks_mut(N).enumerate() {
+            let w = word(addr + (i * N) as u16).read_volatile().to_le_bytes();
+            chunk.copy_from_slice(&w[..chunk.len()]);
+        }
+    }
+
+    pub unsafe fn write(addr: u16, buf: &[u8]) {
+        const N: usize = ALIGN as usize;
+        for (i, chunk) in buf.chunks(N).enumerate() {
+            let mut w = [0; N];
+            w[..chunk.len()].copy_from_slice(chunk);
+            #[cfg(not(any(usbram_32_1024, usbram_32_2048)))]
+            let w = u16::from_le_bytes(w);
+            #[cfg(any(usbram_32_1024, usbram_32_2048))]
+            let w = u32::from_le_bytes(w);
+            word(addr + (i * N) as u16).write_volatile(w);
+        }
+    }
+}
+
+fn convert_type(t: EndpointType) -> EpType {
+    match t {
+        EndpointType::Bulk => EpType::BULK,
+        EndpointType::Control => EpType::CONTROL,
+        EndpointType::Interrupt => EpType::INTERRUPT,
+        EndpointType::Isochronous => EpType::ISO,
+    }
+}
+
+/// EPnR value which leaves everything unchanged when written back: the CTR bits are cleared
+/// by writing 0, and the DTOG and STAT bits are toggled by writing 1.
+fn invariant(mut r: regs::Epr) -> regs::Epr {
+    r.set_ctr_rx(true);
+    r.set_ctr_tx(true);
+    r.set_dtog_rx(false);
+    r.set_dtog_tx(false);
+    r.set_stat_rx(Stat(0));
+    r.set_stat_tx(Stat(0));
+    r
+}
+
+/// Size of an RX buffer for `max_packet_size`, and the matching COUNT_RX field.
+fn calc_out_len(max_packet_size: u16) -> (u16, u16) {
+    match max_packet_size {
+        // BL_SIZE = 0: blocks of 2 bytes.
+        0..=62 => {
+            let blocks = (max_packet_size + 1) / 2;
+            (blocks * 2, blocks << 10)
+        }
+        // BL_SIZE = 1: blocks of 32 bytes.
+        63..=1023 => {
+            let blocks = (max_packet_size + 31) / 32;
+            (blocks * 32, ((blocks - 1) << 10) | 0x8000)
+        }
+        _ => panic!("invalid max packet size {}", max_packet_size),
+    }
+}
+
+#[derive(Clone, Copy)]
+struct EndpointData {
+    ep_type: EndpointType,
+    used_in: bool,
+    used_out: bool,
+    double_buffered: bool,
+}
+
+impl EndpointData {
+    const fn new() -> Self {
+        Self {
+            ep_type: EndpointType::Bulk,
+            used_in: false,
+            used_out: false,
+            double_buffered: false,
+        }
+    }
+}
+
+/// Puts an endpoint in its initial state, or disables it.
+///
+/// Double-buffered endpoints hand the buffers back and forth with the DTOG bit of the
+/// unused direction, SW_BUF: it's the buffer the application holds. The hardware NAKs when
+/// the next buffer it would use, given by DTOG, is the one held by the application. OUT
+/// endpoints start with the application holding buffer 1, so the hardware can receive in
+/// buffer 0. IN endpoints start with the application holding buffer 0, to fill it.
+unsafe fn configure_endpoint<T: Instance>(index: usize, ep: &EndpointData, enabled: bool) {
+    let reg = T::regs().epr(index);
+    let r = reg.read();
+
+    let stat_rx = match ep.used_out && enabled {
+        true => Stat::VALID,
+        false => Stat::DISABLED,
+    };
+    let stat_tx = match (ep.used_in && enabled, ep.double_buffered) {
+        (true, true) => Stat::VALID,
+        (true, false) => Stat::NAK,
+        (false, _) => Stat::DISABLED,
+    };
+    let dtog_tx = ep.double_buffered && ep.used_out;
+
+    let mut w = regs::Epr(0);
+    w.set_ea(index as u8);
+    w.set_ep_type(convert_type(ep.ep_type));
+    w.set_ep_kind(ep.double_buffered);
+    w.set_stat_rx(Stat(r.stat_rx().0 ^ stat_rx.0));
+    w.set_stat_tx(Stat(r.stat_tx().0 ^ stat_tx.0));
+    w.set_dtog_rx(r.dtog_rx());
+    w.set_dtog_tx(r.dtog_tx() != dtog_tx);
+    reg.write_value(w);
+}
+
+unsafe fn set_stalled<T: Instance>(ep_addr: EndpointAddress, double_buffered: bool, stalled: bool) {
+    let index = ep_addr.index();
+    let reg = T::regs().epr(index);
+    let r = reg.read();
+    let mut w = invariant(r);
+
+    if index == 0 {
+        // A control endpoint stalls both stages. It's unstalled by the next SETUP.
+        let stat = if stalled { Stat::STALL } else { Stat::NAK };
+        w.set_stat_rx(Stat(r.stat_rx().0 ^ stat.0));
+        w.set_stat_tx(Stat(r.stat_tx().0 ^ stat.0));
+    } else {
+        match ep_addr.direction() {
+            UsbDirection::In => {
+                let stat = match (stalled, double_buffered) {
+                    (true, _) => Stat::STALL,
+                    (false, true) => Stat::VALID,
+                    (false, false) => Stat::NAK,
+                };
+                w.set_stat_tx(Stat(r.stat_tx().0 ^ stat.0));
+                if !stalled {
+                    // Reset the data toggle, and SW_BUF to its initial state.
+                    w.set_dtog_tx(r.dtog_tx());
+                    w.set_dtog_rx(double_buffered && r.dtog_rx());
+                }
+                EP_IN_WAKERS[index].wake();
+            }
+            UsbDirection::Out => {
+                let stat = if stalled { Stat::STALL } else { Stat::VALID };
+                w.set_stat_rx(Stat(r.stat_rx().0 ^ stat.0));
+                if !stalled {
+                    w.set_dtog_rx(r.dtog_rx());
+                    w.set_dtog_tx(double_buffered && !r.dtog_tx());
+                }
+                EP_OUT_WAKERS[index].wake();
+            }
+        }
+    }
+
+    reg.write_value(w);
+}
+
+fn is_stalled<T: Instance>(ep_addr: EndpointAddress) -> bool {
+    let r = unsafe { T::regs().epr(ep_addr.index()).read() };
+    match ep_addr.direction() {
+        UsbDirection::In => r.stat_tx() == Stat::STALL,
+        UsbDirection::Out => r.stat_rx() == Stat::STALL,
+    }
+}
+
+pub struct Driver<'d, T: Instance> {
+    phantom: PhantomData<&'d mut T>,
+    alloc: [EndpointData; EP_COUNT],
+    /// First free byte of the packet memory.
+    ep_mem_free: u16,
+}
+
+impl<'d, T: Instance> Driver<'d, T> {
+    /// Creates the driver.
+    ///
+    /// The peripheral needs a 48 MHz clock, which must be set up in the RCC config. On F1 and
+    /// F3, the board must have a pull-up resistor on D+, which this driver doesn't control.
+    pub fn new(
+        _usb: impl Unborrow<Target = T> + 'd,
+        irq: impl Unborrow<Target = T::Interrupt> + 'd,
+        dp: impl Unborrow<Target = impl DpPin<T>> + 'd,
+        dm: impl Unborrow<Target = impl DmPin<T>> + 'd,
+    ) -> Self {
+        unborrow!(irq, dp, dm);
+        irq.set_handler(Self::on_interrupt);
+        irq.unpend();
+        irq.enable();
+
+        let regs = T::regs();
+
+        #[cfg(any(stm32l4, stm32l5))]
+        unsafe {
+            // VDDUSB is an independent supply, tell the chip it's present.
+            <crate::peripherals::PWR as crate::rcc::sealed::RccPeripheral>::enable();
+            crate::pac::PWR.cr2().modify(|w| w.set_usv(true));
+        }
+
+        unsafe {
+            <T as crate::rcc::sealed::RccPeripheral>::enable();
+            <T as crate::rcc::sealed::RccPeripheral>::reset();
+
+            regs.cntr().write(|w| {
+                w.set_pdwn(false);
+                w.set_fres(true);
+            });
+            // Wait for the analog transceiver to start up, tSTARTUP is 1 µs.
+            cortex_m::asm::delay(1000);
+
+            regs.btable().write(|w| w.set_btable(0));
+
+            // On L1, the pins are connected to the peripheral without any alternate function.
+            #[cfg(not(stm32l1))]
+            critical_section::with(|_| {
+                dp.set_as_af(dp.af_num(), AFType::OutputPushPull);
+                dm.set_as_af(dm.af_num(), AFType::OutputPushPull);
+            });
+            #[cfg(stm32l1)]
+            let _ = (dp, dm);
+        }
+
+        Self {
+            phantom: PhantomData,
+            alloc: [EndpointData::new(); EP_COUNT],
+            ep_mem_free: pma::BTABLE_SIZE,
+        }
+    }
+
+    fn on_interrupt(_: *mut ()) {
+        unsafe {
+            let regs = T::regs();
+            let istr = regs.istr().read();
+
+            let mut flags: u8 = 0;
+            if istr.susp() {
+                // Enter low power right away, the WKUP interrupt brings the peripheral back.
+                regs.cntr().modify(|w| {
+                    w.set_fsusp(true);
+                    w.set_lpmode(true);
+                });
+                flags |= IRQ_FLAG_SUSPEND;
+            }
+            if istr.wkup() {
+                regs.cntr().modify(|w| {
+                    w.set_fsusp(false);
+                    w.set_lpmode(false);
+                });
+                flags |= IRQ_FLAG_RESUME;
+            }
+            if istr.reset() {
+                PENDING_ADDRESS.store(0, Ordering::Relaxed);
+                flags |= IRQ_FLAG_RESET;
+            }
+
+            // The ISTR flags are cleared by writing 0, writing 1 leaves them unchanged.
+            let mut clear = regs::Istr(!0);
+            clear.set_susp(!istr.susp());
+            clear.set_wkup(!istr.wkup());
+            clear.set_reset(!istr.reset());
+            regs.istr().write_value(clear);
+
+            if flags != 0 {
+                IRQ_FLAGS.fetch_or(flags, Ordering::AcqRel);
+                BUS_WAKER.wake();
+                if flags & IRQ_FLAG_RESET != 0 {
+                    for i in 0..EP_COUNT {
+                        EP_IN_WAKERS[i].wake();
+                        EP_OUT_WAKERS[i].wake();
+                    }
+                }
+            }
+
+            // CTR stays set as long as an endpoint has a completed transfer.
+            let mut istr = istr;
+            while istr.ctr() {
+                let index = istr.ep_id() as usize;
+                let r = regs.epr(index).read();
+                let mut w = invariant(r);
+
+                if r.ctr_rx() {
+                    if index == 0 && r.setup() {
+                        EP0_SETUP.store(true, Ordering::Release);
+                        // Abort the data stage of the previous request.
+                        EP_IN_WAKERS[0].wake();
+                    }
+                    w.set_ctr_rx(false);
+                    EP_OUT_WAKERS[index].wake();
+                }
+                if r.ctr_tx() {
+                    if index == 0 {
+                        // The device must keep address 0 until the end of the status stage.
+                        let addr = PENDING_ADDRESS.swap(0, Ordering::AcqRel);
+                        if addr != 0 {
+                            regs.daddr().write(|w| {
+                                w.set_ef(true);
+                                w.set_add(addr);
+                            });
+                        }
+                    }
+                    w.set_ctr_tx(false);
+                    EP_IN_WAKERS[index].wake();
+                }
+
+                regs.epr(index).write_value(w);
+                istr = regs.istr().read();
+            }
+        }
+    }
+
+    fn alloc_ep_mem(&mut self, len: u16) -> u16 {
+        let addr = self.ep_mem_free;
+        let len = (len + pma::ALIGN - 1) / pma::ALIGN * pma::ALIGN;
+        if addr + len > pma::SIZE {
+            panic!("Endpoint memory full");
+        }
+        self.ep_mem_free += len;
+        addr
+    }
+
+    fn alloc_endpoint<D: Dir>(
+        &mut self,
+        ep_addr: Option<EndpointAddress>,
+        ep_type: EndpointType,
+        max_packet_size: u16,
+        interval: u8,
+        double_buffered: bool,
+    ) -> Result<Endpoint<'d, T, D>, EndpointAllocError> {
+        // Double-buffered endpoints use the buffers of both directions.
+
+        let found = self.alloc.iter_mut().enumerate().find(|(i, ep)| {
+            if ep_addr.map_or(false, |addr| addr.index() != *i) {
+                return false;
+            }
+            if (*i == 0) != (ep_type == EndpointType::Control) {
+                return false;
+            }
+            if (ep.used_in || ep.used_out)
+                && (double_buffered || ep.double_buffered || ep.ep_type != ep_type)
+            {
+                return false;
+            }
+            match D::DIRECTION {
+                UsbDirection::Out => !ep.used_out,
+                UsbDirection::In => !ep.used_in,
+            }
+        });
+        let index = match found {
+            Some((index, ep)) => {
+                ep.ep_type = ep_type;
+                ep.double_buffered = double_buffered;
+                match D::DIRECTION {
+                    UsbDirection::Out => ep.used_out = true,
+                    UsbDirection::In => ep.used_in = true,
+                }
+                index
+            }
+            None => return Err(EndpointAllocError),
+        };
+
+        let (len, count) = match D::DIRECTION {
+            UsbDirection::Out => calc_out_len(max_packet_size),
+            UsbDirection::In => (max_packet_size, 0),
+        };
+        let mut buf_addr = [0; 2];
+        for slot in 0..2 {
+            let used = match D::DIRECTION {
+                _ if double_buffered => true,
+                UsbDirection::Out => slot == 1,
+                UsbDirection::In => slot == 0,
+            };
+            if used {
+                buf_addr[slot] = self.alloc_ep_mem(len);
+                unsafe { pma::set_desc(index, slot, buf_addr[slot], count) };
+            }
+        }
+
+        trace!(
+            "allocated endpoint {} {}, {} bytes at {:?}",
+            index,
+            D::DIRECTION,
+            len,
+            buf_addr
+        );
+
+        Ok(Endpoint {
+            _phantom: PhantomData,
+            info: EndpointInfo {
+                addr: EndpointAddress::from_parts(index, D::DIRECTION),
+                ep_type,
+                max_packet_size,
+                interval,
+            },
+            buf_addr,
+            double_buffered,
+        })
+    }
+}
+
+impl<'d, T: Instance> driver::Driver<'d> for Driver<'d, T> {
+    type EndpointOut = Endpoint<'d, T, Out>;
+    type EndpointIn = Endpoint<'d, T, In>;
+    type ControlPipe = ControlPipe<'d, T>;
+    type Bus = Bus<'d, T>;
+
+    fn alloc_endpoint_in(
+        &mut self,
+        ep_addr: Option<EndpointAddress>,
+        ep_type: EndpointType,
+        max_packet_size: u16,
+        interval: u8,
+    ) -> Result<Self::EndpointIn, EndpointAllocError> {
+        self.alloc_endpoint(ep_addr, ep_type, max_packet_size, interval, false)
+    }
+
+    fn alloc_endpoint_out(
+        &mut self,
+        ep_addr: Option<EndpointAddress>,
+        ep_type: EndpointType,
+        max_packet_size: u16,
+        interval: u8,
+    ) -> Result<Self::EndpointOut, EndpointAllocError> {
+        self.alloc_endpoint(ep_addr, ep_type, max_packet_size, interval, false)
+    }
+
+    /// Uses the buffers of both directions of the endpoint number, which can't be shared with
+    /// another endpoint.
+    fn alloc_endpoint_out_double_buffered(
+        &mut self,
+        ep_addr: Option<EndpointAddress>,
+        max_packet_size: u16,
+    ) -> Result<Self::EndpointOut, EndpointAllocError> {
+        self.alloc_endpoint(ep_addr, EndpointType::Bulk, max_packet_size, 0, true)
+    }
+
+    /// Uses the buffers of both directions of the endpoint number, which can't be shared with
+    /// another endpoint.
+    fn alloc_endpoint_in_double_buffered(
+        &mut self,
+        ep_addr: Option<EndpointAddress>,
+        max_packet_size: u16,
+    ) -> Result<Self::EndpointIn, EndpointAllocError> {
+        self.alloc_endpoint(ep_addr, EndpointType::Bulk, max_packet_size, 0, true)
+    }
+
+    fn alloc_control_pipe(
+        &mut self,
+        max_packet_size: u16,
+    ) -> Result<Self::ControlPipe, EndpointAllocError> {
+        let ep_out: Endpoint<'d, T, Out> = self.alloc_endpoint(
+            Some(0x00.into()),
+            EndpointType::Control,
+            max_packet_size,
+            0,
+            false,
+        )?;
+        let ep_in: Endpoint<'d, T, In> = self.alloc_endpoint(
+            Some(0x80.into()),
+            EndpointType::Control,
+            max_packet_size,
+            0,
+            false,
+        )?;
+        Ok(ControlPipe {
+            _phantom: PhantomData,
+            max_packet_size,
+            out_addr: ep_out.buf_addr[1],
+            in_addr: ep_in.buf_addr[0],
+            out_remaining: 0,
+        })
+    }
+
+    fn into_bus(self) -> Self::Bus {
+        Bus {
+            phantom: PhantomData,
+            alloc: self.alloc,
+        }
+    }
+}
+
+pub struct Bus<'d, T: Instance> {
+    phantom: PhantomData<&'d mut T>,
+    alloc: [EndpointData; EP_COUNT],
+}
+
+impl<'d, T: Instance> driver::Bus for Bus<'d, T> {
+    type EnableFuture<'a> = impl Future<Output = ()> + 'a where Self: 'a;
+    type DisableFuture<'a> = impl Future<Output = ()> + 'a where Self: 'a;
+    type PollFuture<'a> = impl Future<Output = Event> + 'a where Self: 'a;
+    type RemoteWakeupFuture<'a> = impl Future<Output = Result<(), Unsupported>> + 'a where Self: 'a;
+
+    fn enable(&mut self) -> Self::EnableFuture<'_> {
+        async move {
+            let regs = T::regs();
+            unsafe {
+                regs.istr().write_value(regs::Istr(0));
+                regs.cntr().write(|w| {
+                    w.set_ctrm(true);
+                    w.set_resetm(true);
+                    w.set_suspm(true);
+                    w.set_wkupm(true);
+                });
+
+                // Enable the USB pullup, allowing enumeration.
+                #[cfg(stm32l1)]
+                crate::pac::SYSCFG.pmc().modify(|w| w.set_usb_pu(true));
+                #[cfg(not(any(stm32f1, stm32f3, stm32l1)))]
+                regs.bcdr().write(|w| w.set_dppu(true));
+            }
+            trace!("enabled");
+        }
+    }
+
+    fn disable(&mut self) -> Self::DisableFuture<'_> {
+        async move {
+            let regs = T::regs();
+            unsafe {
+                #[cfg(stm32l1)]
+                crate::pac::SYSCFG.pmc().modify(|w| w.set_usb_pu(false));
+                #[cfg(not(any(stm32f1, stm32f3, stm32l1)))]
+                regs.bcdr().write(|w| w.set_dppu(false));
+
+                regs.cntr().write(|w| {
+                    w.set_pdwn(true);
+                    w.set_fres(true);
+                });
+            }
+        }
+    }
+
+    fn poll<'a>(&'a mut self) -> Self::PollFuture<'a> {
+        poll_fn(move |cx| {
+            BUS_WAKER.register(cx.waker());
+            let flags = IRQ_FLAGS.load(Ordering::Acquire);
+
+            if flags & IRQ_FLAG_RESET != 0 {
+                IRQ_FLAGS.fetch_and(!IRQ_FLAG_RESET, Ordering::AcqRel);
+                trace!("RESET");
+                EP0_SETUP.store(false, Ordering::Release);
+
+                let regs = T::regs();
+                unsafe {
+                    regs.daddr().write(|w| {
+                        w.set_ef(true);
+                        w.set_add(0);
+                    });
+                    for (i, ep) in self.alloc.iter().enumerate() {
+                        if ep.used_in || ep.used_out {
+                            configure_endpoint::<T>(i, ep, i == 0);
+                        }
+                    }
+                }
+                return Poll::Ready(Event::Reset);
+            }
+
+            if flags & IRQ_FLAG_RESUME != 0 {
+                IRQ_FLAGS.fetch_and(!IRQ_FLAG_RESUME, Ordering::AcqRel);
+                trace!("RESUME");
+                return Poll::Ready(Event::Resume);
+            }
+
+            if flags & IRQ_FLAG_SUSPEND != 0 {
+                IRQ_FLAGS.fetch_and(!IRQ_FLAG_SUSPEND, Ordering::AcqRel);
+                trace!("SUSPEND");
+                return Poll::Ready(Event::Suspend);
+            }
+
+            Poll::Pending
+        })
+    }
+
+    #[inline]
+    fn set_configured(&mut self, configured: bool) {
+        for (i, ep) in self.alloc.iter().enumerate().skip(1) {
+            if ep.used_in || ep.used_out {
+                unsafe { configure_endpoint::<T>(i, ep, configured) };
+            }
+            EP_IN_WAKERS[i].wake();
+            EP_OUT_WAKERS[i].wake();
+        }
+    }
+
+    #[inline]
+    fn set_device_address(&mut self, addr: u8) {
+        // Applied by the interrupt handler, after the status stage.
+        PENDING_ADDRESS.store(addr, Ordering::Release);
+    }
+
+    fn set_stalled(&mut self, ep_addr: EndpointAddress, stalled: bool) {
+        let double_buffered = self.alloc[ep_addr.index()].double_buffered;
+        unsafe { set_stalled::<T>(ep_addr, double_buffered, stalled) }
+    }
+
+    fn is_stalled(&mut self, ep_addr: EndpointAddress) -> bool {
+        is_stalled::<T>(ep_addr)
+    }
+
+    fn remote_wakeup(&mut self) -> Self::RemoteWakeupFuture<'_> {
+        async move {
+            // The resume signaling must last between 1 and 15 ms.
+            #[cfg(feature = "_time-driver")]
+            {
+                let regs = T::regs();
+                unsafe {
+                    regs.cntr().modify(|w| {
+                        w.set_fsusp(false);
+                        w.set_lpmode(false);
+                        w.set_resume(true);
+                    });
+                }
+                embassy::time::Timer::after(embassy::time::Duration::from_millis(2)).await;
+                unsafe { regs.cntr().modify(|w| w.set_resume(false)) };
+                Ok(())
+            }
+            #[cfg(not(feature = "_time-driver"))]
+            Err(Unsupported)
+        }
+    }
+}
+
+trait Dir {
+    const DIRECTION: UsbDirection;
+}
+
+pub enum In {}
+impl Dir for In {
+    const DIRECTION: UsbDirection = UsbDirection::In;
+}
+
+pub enum Out {}
+impl Dir for Out {
+    const DIRECTION: UsbDirection = UsbDirection::Out;
+}
+
+pub struct Endpoint<'d, T: Instance, D> {
+    _phantom: PhantomData<(&'d mut T, D)>,
+    info: EndpointInfo,
+    /// Packet memory addresses of the buffers, per slot.
+    buf_addr: [u16; 2],
+    double_buffered: bool,
+}
+
+impl<'d, T: Instance, D> driver::Endpoint for Endpoint<'d, T, D> {
+    type WaitEnabledFuture<'a> = impl Future<Output = ()> + 'a where Self: 'a;
+
+    fn info(&self) -> &EndpointInfo {
+        &self.info
+    }
+
+    fn set_stalled(&self, stalled: bool) {
+        unsafe { set_stalled::<T>(self.info.addr, self.double_buffered, stalled) }
+    }
+
+    fn is_stalled(&self) -> bool {
+        is_stalled::<T>(self.info.addr)
+    }
+
+    fn wait_enabled(&mut self) -> Self::WaitEnabledFuture<'_> {
+        let index = self.info.addr.index();
+        let direction = self.info.addr.direction();
+        poll_fn(move |cx| {
+            let r = unsafe { T::regs().epr(index).read() };
+            let stat = match direction {
+                UsbDirection::In => {
+                    EP_IN_WAKERS[index].register(cx.waker());
+                    r.stat_tx()
+                }
+                UsbDirection::Out => {
+                    EP_OUT_WAKERS[index].register(cx.waker());
+                    r.stat_rx()
+                }
+            };
+            if stat != Stat::DISABLED {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+    }
+}
+
+impl<'d, T: Instance> driver::EndpointOut for Endpoint<'d, T, Out> {
+    type ReadFuture<'a> = impl Future<Output = Result<usize, EndpointError>> + 'a where Self: 'a;
+
+    fn read<'a>(&'a mut self, buf: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        async move {
+            let index = self.info.addr.index();
+            let reg = T::regs().epr(index);
+
+            // Wait for a packet: single-buffered endpoints NAK after a reception, until the
+            // buffer is read. Double-buffered ones NAK when the hardware is done with the
+            // buffer the application doesn't hold.
+            let double_buffered = self.double_buffered;
+            let r = poll_fn(|cx| {
+                EP_OUT_WAKERS[index].register(cx.waker());
+                let r = unsafe { reg.read() };
+                if r.stat_rx() == Stat::DISABLED {
+                    Poll::Ready(Err(EndpointError::Disabled))
+                } else if double_buffered && r.dtog_rx() == r.dtog_tx() {
+                    Poll::Ready(Ok(r))
+                } else if !double_buffered && r.stat_rx() == Stat::NAK {
+                    Poll::Ready(Ok(r))
+                } else {
+                    Poll::Pending
+                }
+            })
+            .await?;
+
+            let slot = match double_buffered {
+                true => !r.dtog_tx() as usize,
+                false => 1,
+            };
+            let len = unsafe { pma::count(index, slot) };
+            if len > buf.len() {
+                return Err(EndpointError::BufferOverflow);
+            }
+
+            let mut w = invariant(r);
+            if double_buffered {
+                // Take the full buffer, which gives the previous one back to the hardware:
+                // it receives the next packet while this one is copied.
+                w.set_dtog_tx(true);
+                unsafe {
+                    reg.write_value(w);
+                    pma::read(self.buf_addr[slot], &mut buf[..len]);
+                }
+            } else {
+                unsafe {
+                    pma::read(self.buf_addr[slot], &mut buf[..len]);
+                    w.set_stat_rx(Stat(Stat::NAK.0 ^ Stat::VALID.0));
+                    reg.write_value(w);
+                }
+            }
+
+            Ok(len)
+        }
+    }
+}
+
+impl<'d, T: Instance> driver::EndpointIn for Endpoint<'d, T, In> {
+    type WriteFuture<'a> = impl Future<Output = Result<(), EndpointError>> + 'a where Self: 'a;
+
+    fn write<'a>(&'a mut self, buf: &'a [u8]) -> Self::WriteFuture<'a> {
+        async move {
+            if buf.len() > usize::from(self.info.max_packet_size) {
+                return Err(EndpointError::BufferOverflow);
+            }
+
+            let index = self.info.addr.index();
+            let reg = T::regs().epr(index);
+
+            if self.double_buffered {
+                // The buffer held by the application is never being sent: fill it while the
+                // previous packet is sent from the other one.
+                let r = unsafe { reg.read() };
+                if r.stat_tx() == Stat::DISABLED {
+                    return Err(EndpointError::Disabled);
+                }
+                let slot = r.dtog_rx() as usize;
+                unsafe {
+                    pma::write(self.buf_addr[slot], buf);
+                    pma::set_count(index, slot, buf.len() as u16);
+                }
+
+                let r = poll_fn(|cx| {
+                    EP_IN_WAKERS[index].register(cx.waker());
+                    let r = unsafe { reg.read() };
+                    if r.stat_tx() == Stat::DISABLED {
+                        Poll::Ready(Err(EndpointError::Disabled))
+                    } else if r.dtog_tx() == r.dtog_rx() {
+                        Poll::Ready(Ok(r))
+                    } else {
+                        Poll::Pending
+                    }
+                })
+                .await?;
+
+                // Hand the buffer over to the hardware.
+                let mut w = invariant(r);
+                w.set_dtog_rx(true);
+                unsafe { reg.write_value(w) };
+            } else {
+                let r = poll_fn(|cx| {
+                    EP_IN_WAKERS[index].register(cx.waker());
+                    let r = unsafe { reg.read() };
+                    match r.stat_tx() {
+                        Stat::DISABLED => Poll::Ready(Err(EndpointError::Disabled)),
+                        Stat::NAK => Poll::Ready(Ok(r)),
+                        _ => Poll::Pending,
+                    }
+                })
+                .await?;
+
+                unsafe {
+                    pma::write(self.buf_addr[0], buf);
+                    pma::set_count(index, 0, buf.len() as u16);
+                    let mut w = invariant(r);
+                    w.set_stat_tx(Stat(Stat::NAK.0 ^ Stat::VALID.0));
+                    reg.write_value(w);
+                }
+            }
+
+            Ok(())
+        }
+    }
+}
+
+pub struct ControlPipe<'d, T: Instance> {
+    _phantom: PhantomData<&'d mut T>,
+    max_packet_size: u16,
+    out_addr: u16,
+    in_addr: u16,
+    /// Length of the DATA OUT stage still to be received.
+    out_remaining: usize,
+}
+
+impl<'d, T: Instance> ControlPipe<'d, T> {
+    /// Sets the STAT_RX and STAT_TX bits of endpoint 0.
+    unsafe fn set_stat(&self, stat_rx: Option<Stat>, stat_tx: Option<Stat>) {
+        let reg = T::regs().epr(0);
+        let r = reg.read();
+        let mut w = invariant(r);
+        if let Some(stat) = stat_rx {
+            w.set_stat_rx(Stat(r.stat_rx().0 ^ stat.0));
+        }
+        if let Some(stat) = stat_tx {
+            w.set_stat_tx(Stat(r.stat_tx().0 ^ stat.0));
+        }
+        reg.write_value(w);
+    }
+}
+
+impl<'d, T: Instance> driver::ControlPipe for ControlPipe<'d, T> {
+    type SetupFuture<'a> = impl Future<Output = Request> + 'a where Self: 'a;
+    type DataOutFuture<'a> = impl Future<Output = Result<usize, EndpointError>> + 'a where Self: 'a;
+    type DataInFuture<'a> = impl Future<Output = Result<(), EndpointError>> + 'a where Self: 'a;
+
+    fn max_packet_size(&self) -> usize {
+        usize::from(self.max_packet_size)
+    }
+
+    fn setup<'a>(&'a mut self) -> Self::SetupFuture<'a> {
+        async move {
+            loop {
+                poll_fn(|cx| {
+                    EP_OUT_WAKERS[0].register(cx.waker());
+                    if EP0_SETUP.load(Ordering::Acquire) {
+                        Poll::Ready(())
+                    } else {
+                        Poll::Pending
+                    }
+                })
+                .await;
+                EP0_SETUP.store(false, Ordering::Release);
+
+                // The hardware NAKs both directions after a SETUP.
+                let mut buf = [0; 8];
+                let len = unsafe { pma::count(0, 1) };
+                if len != 8 {
+                    trace!("SETUP read failed: {} bytes", len);
+                    continue;
+                }
+                unsafe { pma::read(self.out_addr, &mut buf) };
+
+                let req = Request::parse(&buf);
+                if req.direction == UsbDirection::Out && req.length > 0 {
+                    self.out_remaining = usize::from(req.length);
+                    unsafe { self.set_stat(Some(Stat::VALID), None) };
+                }
+                return req;
+            }
+        }
+    }
+
+    fn data_out<'a>(&'a mut self, buf: &'a mut [u8]) -> Self::DataOutFuture<'a> {
+        async move {
+            poll_fn(|cx| {
+                EP_OUT_WAKERS[0].register(cx.waker());
+                let r = unsafe { T::regs().epr(0).read() };
+                if EP0_SETUP.load(Ordering::Acquire) {
+                    trace!("aborted control data_out: received another SETUP");
+                    Poll::Ready(Err(EndpointError::Disabled))
+                } else if r.stat_rx() == Stat::NAK {
+                    Poll::Ready(Ok(()))
+                } else {
+                    Poll::Pending
+                }
+            })
+            .await?;
+
+            let len = unsafe { pma::count(0, 1) };
+            if len > buf.len() {
+                return Err(EndpointError::BufferOverflow);
+            }
+            unsafe { pma::read(self.out_addr, &mut buf[..len]) };
+
+            self.out_remaining = self.out_remaining.saturating_sub(len);
+            if self.out_remaining > 0 && len == usize::from(self.max_packet_size) {
+                unsafe { self.set_stat(Some(Stat::VALID), None) };
+            }
+            Ok(len)
+        }
+    }
+
+    fn data_in<'a>(&'a mut self, buf: &'a [u8], last_packet: bool) -> Self::DataInFuture<'a> {
+        async move {
+            if buf.len() > usize::from(self.max_packet_size) {
+                return Err(EndpointError::BufferOverflow);
+            }
+
+            unsafe {
+                pma::write(self.in_addr, buf);
+                pma::set_count(0, 0, buf.len() as u16);
+                // After the last packet, accept the status stage from the host.
+                let stat_rx = last_packet.then(|| Stat::VALID);
+                self.set_stat(stat_rx, Some(Stat::VALID));
+            }
+
+            poll_fn(|cx| {
+                EP_IN_WAKERS[0].register(cx.waker());
+                let r = unsafe { T::regs().epr(0).read() };
+                if EP0_SETUP.load(Ordering::Acquire) {
+                    trace!("aborted control data_in: received another SETUP");
+                    Poll::Ready(Err(EndpointError::Disabled))
+                } else if r.stat_tx() == Stat::NAK {
+                    Poll::Ready(Ok(()))
+                } else {
+                    Poll::Pending
+                }
+            })
+            .await
+        }
+    }
+
+    fn accept(&mut self) {
+        // The status stage of OUT requests is a zero-length IN packet.
+        unsafe {
+            pma::set_count(0, 0, 0);
+            self.set_stat(None, Some(Stat::VALID));
+        }
+    }
+
+    fn reject(&mut self) {
+        unsafe { self.set_stat(Some(Stat::STALL), Some(Stat::STALL)) };
+    }
+}
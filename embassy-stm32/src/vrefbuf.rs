@@ -0,0 +1,156 @@
+//! Voltage reference buffer (VREFBUF).
+//!
+//! The buffer drives the VREF+ pin, the reference of the ADCs and DACs, from the internal
+//! voltage reference. Without it, VREF+ has to be supplied externally.
+
+use core::marker::PhantomData;
+
+use embassy::util::Unborrow;
+use embassy_hal_common::unborrow;
+
+use crate::pac::vrefbuf::regs;
+use crate::pac::{RCC, VREFBUF as PAC_VREFBUF};
+use crate::peripherals::VREFBUF;
+
+// CSR bits. The width of VRS depends on the number of scales of the chip.
+const ENVR: u32 = 1 << 0;
+const HIZ: u32 = 1 << 1;
+const VRR: u32 = 1 << 3;
+const VRS_POS: u32 = 4;
+
+/// Voltage of VREF+ driven by the buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Scale {
+    #[cfg(any(stm32h7, stm32u5))]
+    V1_5,
+    #[cfg(any(stm32h7, stm32u5))]
+    V1_8,
+    V2_048,
+    V2_5,
+    #[cfg(stm32g4)]
+    V2_9,
+}
+
+impl Scale {
+    #[cfg(stm32h7)]
+    fn vrs(self) -> u32 {
+        match self {
+            Scale::V2_5 => 0,
+            Scale::V2_048 => 1,
+            Scale::V1_8 => 2,
+            Scale::V1_5 => 3,
+        }
+    }
+
+    #[cfg(stm32u5)]
+    fn vrs(self) -> u32 {
+        match self {
+            Scale::V1_5 => 0,
+            Scale::V1_8 => 1,
+            Scale::V2_048 => 2,
+            Scale::V2_5 => 3,
+        }
+    }
+
+    #[cfg(not(any(stm32h7, stm32u5)))]
+    fn vrs(self) -> u32 {
+        match self {
+            Scale::V2_048 => 0,
+            Scale::V2_5 => 1,
+            #[cfg(stm32g4)]
+            Scale::V2_9 => 2,
+        }
+    }
+
+    /// The voltage, in millivolts.
+    pub fn millivolts(self) -> u16 {
+        match self {
+            #[cfg(any(stm32h7, stm32u5))]
+            Scale::V1_5 => 1500,
+            #[cfg(any(stm32h7, stm32u5))]
+            Scale::V1_8 => 1800,
+            Scale::V2_048 => 2048,
+            Scale::V2_5 => 2500,
+            #[cfg(stm32g4)]
+            Scale::V2_9 => 2900,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Mode {
+    /// The buffer is off, and VREF+ is pulled down to VSSA.
+    Off,
+    /// The buffer is off, and VREF+ is high impedance, to be supplied by an external
+    /// reference. This is the reset state.
+    External,
+    /// The buffer drives VREF+ with `Scale`.
+    Internal(Scale),
+    /// The buffer is on, but its output is high impedance: VREF+ is held by its decoupling
+    /// capacitor, and nothing may draw current from it.
+    Hold(Scale),
+}
+
+/// VREFBUF driver.
+///
+/// VREF+ must be at least the chosen scale plus the dropout of the buffer below VDDA, see
+/// the datasheet. Dropping the driver goes back to [`Mode::External`].
+pub struct VrefBuf<'d> {
+    phantom: PhantomData<&'d mut VREFBUF>,
+}
+
+impl<'d> VrefBuf<'d> {
+    pub fn new(_peri: impl Unborrow<Target = VREFBUF> + 'd, mode: Mode) -> Self {
+        unborrow!(_peri);
+
+        // The buffer is clocked by the SYSCFG clock, except on the H7 where it has its own.
+        unsafe {
+            #[cfg(stm32h7)]
+            RCC.apb4enr().modify(|w| w.set_vrefen(true));
+            #[cfg(stm32g0)]
+            RCC.apbenr2().modify(|w| w.set_syscfgen(true));
+            #[cfg(not(any(stm32h7, stm32g0)))]
+            RCC.apb2enr().modify(|w| w.set_syscfgen(true));
+        }
+
+        let mut this = Self {
+            phantom: PhantomData,
+        };
+        this.set_mode(mode);
+        this
+    }
+
+    /// Change the mode. When the buffer is enabled, VREF+ is stable once
+    /// [`is_ready`](Self::is_ready) returns `true`.
+    pub fn set_mode(&mut self, mode: Mode) {
+        let csr = match mode {
+            Mode::Off => 0,
+            Mode::External => HIZ,
+            Mode::Internal(scale) => ENVR | scale.vrs() << VRS_POS,
+            Mode::Hold(scale) => ENVR | HIZ | scale.vrs() << VRS_POS,
+        };
+        unsafe { PAC_VREFBUF.csr().write_value(regs::Csr(csr)) }
+    }
+
+    /// Whether VREF+ reached the voltage of the scale. Only meaningful when the buffer is
+    /// enabled.
+    ///
+    /// This takes a few milliseconds, depending on the decoupling capacitor of VREF+. Don't
+    /// start conversions before it's ready.
+    pub fn is_ready(&self) -> bool {
+        unsafe { PAC_VREFBUF.csr().read().0 & VRR != 0 }
+    }
+
+    /// Wait until VREF+ is ready.
+    pub fn blocking_wait_ready(&self) {
+        while !self.is_ready() {}
+    }
+}
+
+impl<'d> Drop for VrefBuf<'d> {
+    fn drop(&mut self) {
+        unsafe { PAC_VREFBUF.csr().write_value(regs::Csr(HIZ)) }
+    }
+}
@@ -8,6 +8,9 @@ use embedded_hal_02::serial;
 /// BlockingAsync will implement any async trait that maps to embedded-hal traits implemented for the wrapped driver.
 ///
 /// Driver users are then free to choose which implementation that is available to them.
+///
+/// This wraps embedded-hal 0.2 implementations. For embedded-hal 1.0 implementations, use
+/// `embassy_embedded_hal::adapter::BlockingAsync`.
 pub struct BlockingAsync<T> {
     wrapped: T,
 }
@@ -105,8 +108,11 @@ where
 
     type TransferInPlaceFuture<'a> = impl Future<Output = Result<(), Self::Error>> + 'a where Self: 'a;
 
-    fn transfer_in_place<'a>(&'a mut self, _: &'a mut [u8]) -> Self::TransferInPlaceFuture<'a> {
-        async move { todo!() }
+    fn transfer_in_place<'a>(&'a mut self, data: &'a mut [u8]) -> Self::TransferInPlaceFuture<'a> {
+        async move {
+            self.wrapped.transfer(data)?;
+            Ok(())
+        }
     }
 }
 
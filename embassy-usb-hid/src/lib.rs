@@ -4,8 +4,8 @@
 
 //! Implements HID functionality for a usb-device device.
 
-// This mod MUST go first, so that the others see its macros.
-pub(crate) mod fmt;
+#[macro_use]
+extern crate embassy_fmt;
 
 use core::mem::MaybeUninit;
 use core::ops::Range;
@@ -0,0 +1,53 @@
+//! USB to UART bridge helpers.
+//!
+//! A bridge forwards the data of a [`CdcAcmClass`](crate::CdcAcmClass) to a physical UART,
+//! and applies the port settings chosen by the host on the computer side (baud rate, parity,
+//! DTR and RTS lines) to it. Implement [`UartControl`] for the UART of your HAL, then feed it
+//! the [`ControlEvents`] of the class.
+
+use crate::{ControlEvent, ControlEvents, LineCoding};
+
+/// Port settings of the UART side of a bridge.
+pub trait UartControl {
+    /// Applies the line coding set by the host.
+    ///
+    /// Settings the UART doesn't support, like 1.5 stop bits on some chips, should be
+    /// approximated or ignored: the host has no way to know they were rejected.
+    fn set_line_coding(&mut self, coding: &LineCoding);
+
+    /// Sets the DTR (data terminal ready) output.
+    ///
+    /// The default implementation does nothing, for UARTs without a DTR pin.
+    fn set_dtr(&mut self, _dtr: bool) {}
+
+    /// Sets the RTS (request to send) output.
+    ///
+    /// The default implementation does nothing, for UARTs without a RTS pin or using
+    /// hardware flow control, where the UART drives RTS itself.
+    fn set_rts(&mut self, _rts: bool) {}
+}
+
+/// Applies a single event to the UART.
+pub fn apply<U: UartControl>(uart: &mut U, event: &ControlEvent) {
+    match event {
+        ControlEvent::LineCoding(coding) => uart.set_line_coding(coding),
+        ControlEvent::ControlLineState { dtr, rts } => {
+            uart.set_dtr(*dtr);
+            uart.set_rts(*rts);
+        }
+    }
+}
+
+/// Mirrors the port settings of the class onto the UART, forever.
+///
+/// The current settings are applied first, then every change made by the host.
+pub async fn mirror<U: UartControl>(events: &ControlEvents<'_>, uart: &mut U) -> ! {
+    uart.set_line_coding(&events.line_coding());
+    uart.set_dtr(events.dtr());
+    uart.set_rts(events.rts());
+
+    loop {
+        let event = events.next().await;
+        apply(uart, &event);
+    }
+}
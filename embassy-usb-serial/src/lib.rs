@@ -2,13 +2,19 @@
 #![feature(generic_associated_types)]
 #![feature(type_alias_impl_trait)]
 
-// This mod MUST go first, so that the others see its macros.
-pub(crate) mod fmt;
+#[macro_use]
+extern crate embassy_fmt;
 
-use core::cell::Cell;
+pub mod bridge;
+
+use core::cell::{Cell, RefCell};
+use core::future::Future;
 use core::mem::{self, MaybeUninit};
+use core::pin::Pin;
 use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll};
 use embassy::blocking_mutex::CriticalSectionMutex;
+use embassy::waitqueue::WakerRegistration;
 use embassy_usb::control::{self, ControlHandler, InResponse, OutResponse, Request};
 use embassy_usb::driver::{Endpoint, EndpointError, EndpointIn, EndpointOut};
 use embassy_usb::{driver::Driver, types::*, Builder};
@@ -76,6 +82,10 @@ struct ControlShared {
     line_coding: CriticalSectionMutex<Cell<LineCoding>>,
     dtr: AtomicBool,
     rts: AtomicBool,
+
+    line_coding_changed: AtomicBool,
+    control_line_state_changed: AtomicBool,
+    waker: CriticalSectionMutex<RefCell<WakerRegistration>>,
 }
 
 impl Default for ControlShared {
@@ -89,10 +99,49 @@ impl Default for ControlShared {
                 parity_type: ParityType::None,
                 data_rate: 8_000,
             })),
+            line_coding_changed: AtomicBool::new(false),
+            control_line_state_changed: AtomicBool::new(false),
+            waker: CriticalSectionMutex::new(RefCell::new(WakerRegistration::new())),
         }
     }
 }
 
+impl ControlShared {
+    fn changed(&self, line_coding: bool, control_line_state: bool) {
+        self.waker.lock(|w| {
+            if line_coding {
+                self.line_coding_changed.store(true, Ordering::Relaxed);
+            }
+            if control_line_state {
+                self.control_line_state_changed
+                    .store(true, Ordering::Relaxed);
+            }
+            w.borrow_mut().wake();
+        })
+    }
+
+    /// Takes a pending event, or registers `cx` to be woken when there's one.
+    fn poll_event(&self, cx: &mut Context<'_>) -> Poll<ControlEvent> {
+        // The flags are only modified with the waker mutex held, no need for atomic swaps.
+        self.waker.lock(|w| {
+            if self.line_coding_changed.load(Ordering::Relaxed) {
+                self.line_coding_changed.store(false, Ordering::Relaxed);
+                return Poll::Ready(ControlEvent::LineCoding(self.line_coding.lock(|x| x.get())));
+            }
+            if self.control_line_state_changed.load(Ordering::Relaxed) {
+                self.control_line_state_changed
+                    .store(false, Ordering::Relaxed);
+                return Poll::Ready(ControlEvent::ControlLineState {
+                    dtr: self.dtr.load(Ordering::Relaxed),
+                    rts: self.rts.load(Ordering::Relaxed),
+                });
+            }
+            w.borrow_mut().register(cx.waker());
+            Poll::Pending
+        })
+    }
+}
+
 impl<'a> Control<'a> {
     fn shared(&mut self) -> &'a ControlShared {
         self.shared
@@ -105,6 +154,7 @@ impl<'d> ControlHandler for Control<'d> {
         shared.line_coding.lock(|x| x.set(LineCoding::default()));
         shared.dtr.store(false, Ordering::Relaxed);
         shared.rts.store(false, Ordering::Relaxed);
+        shared.changed(true, true);
     }
 
     fn control_out(&mut self, req: control::Request, data: &[u8]) -> OutResponse {
@@ -121,7 +171,9 @@ impl<'d> ControlHandler for Control<'d> {
                     parity_type: data[5].into(),
                     data_bits: data[6],
                 };
-                self.shared().line_coding.lock(|x| x.set(coding));
+                let shared = self.shared();
+                shared.line_coding.lock(|x| x.set(coding));
+                shared.changed(true, false);
                 debug!("Set line coding to: {:?}", coding);
 
                 OutResponse::Accepted
@@ -133,6 +185,7 @@ impl<'d> ControlHandler for Control<'d> {
                 let shared = self.shared();
                 shared.dtr.store(dtr, Ordering::Relaxed);
                 shared.rts.store(rts, Ordering::Relaxed);
+                shared.changed(false, true);
                 debug!("Set dtr {}, rts {}", dtr, rts);
 
                 OutResponse::Accepted
@@ -255,6 +308,16 @@ impl<'d, D: Driver<'d>> CdcAcmClass<'d, D> {
         self.control.rts.load(Ordering::Relaxed)
     }
 
+    /// Gets a handle to wait for line coding and control line state changes.
+    ///
+    /// It doesn't borrow the class, so the changes can be followed while another
+    /// task or future reads and writes packets.
+    pub fn control_events(&self) -> ControlEvents<'d> {
+        ControlEvents {
+            control: self.control,
+        }
+    }
+
     /// Writes a single packet into the IN endpoint.
     pub async fn write_packet(&mut self, data: &[u8]) -> Result<(), EndpointError> {
         self.write_ep.write(data).await
@@ -271,6 +334,62 @@ impl<'d, D: Driver<'d>> CdcAcmClass<'d, D> {
     }
 }
 
+/// A change of the port settings by the host, returned by [`ControlEvents::next`].
+#[derive(Clone, Copy, defmt::Format)]
+pub enum ControlEvent {
+    /// The host has set the line coding, for example to change the baud rate.
+    LineCoding(LineCoding),
+
+    /// The host has set the DTR (data terminal ready) or RTS (request to send) lines.
+    ControlLineState { dtr: bool, rts: bool },
+}
+
+/// Line coding and control line state changes of a [`CdcAcmClass`].
+///
+/// Changes are not queued: if the host changes the line coding several times before
+/// [`next`](ControlEvents::next) is called, only the last one is returned. A USB reset
+/// reports both the line coding and the control line state, which are reset to defaults.
+pub struct ControlEvents<'d> {
+    control: &'d ControlShared,
+}
+
+impl<'d> ControlEvents<'d> {
+    /// Waits for the host to change the line coding or the control line state.
+    pub async fn next(&self) -> ControlEvent {
+        NextEvent {
+            control: self.control,
+        }
+        .await
+    }
+
+    /// Gets the current line coding.
+    pub fn line_coding(&self) -> LineCoding {
+        self.control.line_coding.lock(|x| x.get())
+    }
+
+    /// Gets the DTR (data terminal ready) state
+    pub fn dtr(&self) -> bool {
+        self.control.dtr.load(Ordering::Relaxed)
+    }
+
+    /// Gets the RTS (request to send) state
+    pub fn rts(&self) -> bool {
+        self.control.rts.load(Ordering::Relaxed)
+    }
+}
+
+struct NextEvent<'d> {
+    control: &'d ControlShared,
+}
+
+impl<'d> Future for NextEvent<'d> {
+    type Output = ControlEvent;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<ControlEvent> {
+        self.control.poll_event(cx)
+    }
+}
+
 /// Number of stop bits for LineCoding
 #[derive(Copy, Clone, PartialEq, Eq, defmt::Format)]
 pub enum StopBits {
@@ -4,6 +4,7 @@ use super::control::ControlHandler;
 use super::descriptor::{BosWriter, DescriptorWriter};
 use super::driver::{Driver, Endpoint};
 use super::types::*;
+use super::BusEvents;
 use super::DeviceStateHandler;
 use super::UsbDevice;
 use super::MAX_INTERFACE_COUNT;
@@ -81,6 +82,9 @@ pub struct Config<'a> {
     ///
     /// Default: `false`
     ///
+    /// This is the initial value of the self-powered status reported to the host, which can be
+    /// changed at runtime with [`UsbDevice::set_self_powered`].
+    ///
     /// See also: `max_power`
     pub self_powered: bool,
 
@@ -89,7 +93,7 @@ pub struct Config<'a> {
     /// The default is 100 mA. If your device always uses an external power source and never draws
     /// power from the USB bus, this can be set to 0.
     ///
-    /// See also: `self_powered`
+    /// See also: `self_powered`, [`UsbDevice::set_max_power`]
     ///
     /// Default: 100mA
     /// Max: 500mA
@@ -121,6 +125,7 @@ impl<'a> Config<'a> {
 pub struct Builder<'d, D: Driver<'d>> {
     config: Config<'d>,
     handler: Option<&'d dyn DeviceStateHandler>,
+    bus_events: Option<&'d BusEvents>,
     interfaces: Vec<(u8, &'d mut dyn ControlHandler), MAX_INTERFACE_COUNT>,
     control_buf: &'d mut [u8],
 
@@ -138,7 +143,10 @@ impl<'d, D: Driver<'d>> Builder<'d, D> {
     ///
     /// `control_buf` is a buffer used for USB control request data. It should be sized
     /// large enough for the length of the largest control request (in or out)
-    /// anticipated by any class added to the device.
+    /// anticipated by any class added to the device. Classes which handle longer requests
+    /// in chunks, with [`ControlHandler::control_out_start`] and
+    /// [`ControlHandler::control_in_chunk`], need it to hold at least one packet of the
+    /// control endpoint.
     pub fn new(
         driver: D,
         config: Config<'d>,
@@ -177,6 +185,7 @@ impl<'d, D: Driver<'d>> Builder<'d, D> {
         Builder {
             driver,
             handler,
+            bus_events: None,
             config,
             interfaces: Vec::new(),
             control_buf,
@@ -198,6 +207,7 @@ impl<'d, D: Driver<'d>> Builder<'d, D> {
             self.driver,
             self.config,
             self.handler,
+            self.bus_events,
             self.device_descriptor.into_buf(),
             self.config_descriptor.into_buf(),
             self.bos_descriptor.writer.into_buf(),
@@ -206,6 +216,12 @@ impl<'d, D: Driver<'d>> Builder<'d, D> {
         )
     }
 
+    /// Delivers suspend, resume and reset events to `events`, so the application can follow
+    /// them from another task. See [`UsbDevice::bus_events`].
+    pub fn bus_events(&mut self, events: &'d BusEvents) {
+        self.bus_events = Some(events);
+    }
+
     /// Returns the size of the control request data buffer. Can be used by
     /// classes to validate the buffer is large enough for their needs.
     pub fn control_buf_len(&self) -> usize {
@@ -416,6 +432,38 @@ impl<'a, 'd, D: Driver<'d>> InterfaceAltBuilder<'a, 'd, D> {
         self.endpoint_out(None, EndpointType::Bulk, max_packet_size, 0)
     }
 
+    /// Allocate a double-buffered BULK IN endpoint and write its descriptor.
+    ///
+    /// This keeps the bus busy on high-throughput endpoints, at the cost of more endpoint
+    /// resources on some drivers. See
+    /// [`Driver::alloc_endpoint_in_double_buffered`](crate::driver::Driver::alloc_endpoint_in_double_buffered).
+    pub fn endpoint_bulk_in_double_buffered(&mut self, max_packet_size: u16) -> D::EndpointIn {
+        let ep = self
+            .builder
+            .driver
+            .alloc_endpoint_in_double_buffered(None, max_packet_size)
+            .expect("alloc_endpoint_in_double_buffered failed");
+
+        self.builder.config_descriptor.endpoint(ep.info());
+
+        ep
+    }
+
+    /// Allocate a double-buffered BULK OUT endpoint and write its descriptor.
+    ///
+    /// See [`endpoint_bulk_in_double_buffered`](Self::endpoint_bulk_in_double_buffered).
+    pub fn endpoint_bulk_out_double_buffered(&mut self, max_packet_size: u16) -> D::EndpointOut {
+        let ep = self
+            .builder
+            .driver
+            .alloc_endpoint_out_double_buffered(None, max_packet_size)
+            .expect("alloc_endpoint_out_double_buffered failed");
+
+        self.builder.config_descriptor.endpoint(ep.info());
+
+        ep
+    }
+
     /// Allocate a INTERRUPT IN endpoint and write its descriptor.
     ///
     /// Descriptors are written in the order builder functions are called. Note that some
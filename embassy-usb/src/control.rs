@@ -150,6 +150,22 @@ pub trait ControlHandler {
     /// Called after a USB reset after the bus reset sequence is complete.
     fn reset(&mut self) {}
 
+    /// Called when the bus has entered or exited the suspend state.
+    ///
+    /// While suspended the host doesn't poll the endpoints, so classes should stop
+    /// queueing data, and may report their link as down.
+    fn suspended(&mut self, _suspended: bool) {}
+
+    /// Called when the host sets or clears the halt feature of an endpoint, with SET_FEATURE
+    /// or CLEAR_FEATURE ENDPOINT_HALT.
+    ///
+    /// This is called for all the endpoints, classes should check `ep_addr` is one of theirs.
+    /// When the halt is cleared, the data toggle of the endpoint is reset: classes should
+    /// reset the state of the transfers on the endpoint too, like the Bulk-Only Transport of
+    /// mass storage devices waiting for a new command after an error. This is called on
+    /// CLEAR_FEATURE even if the endpoint wasn't halted.
+    fn endpoint_halted(&mut self, _ep_addr: EndpointAddress, _halted: bool) {}
+
     /// Called when a control request is received with direction HostToDevice.
     ///
     /// # Arguments
@@ -174,6 +190,50 @@ pub trait ControlHandler {
         InResponse::Rejected
     }
 
+    /// Called when a control request with direction HostToDevice is received, and its data
+    /// stage doesn't fit in the control buffer.
+    ///
+    /// Return `OutResponse::Accepted` to receive the data in chunks with
+    /// [`control_out_chunk`](Self::control_out_chunk), as it arrives. This is needed for
+    /// requests carrying large payloads, like firmware blocks.
+    fn control_out_start(&mut self, req: Request) -> OutResponse {
+        let _ = req;
+        OutResponse::Rejected
+    }
+
+    /// Called with each chunk of the data stage of a request accepted by
+    /// [`control_out_start`](Self::control_out_start).
+    ///
+    /// `offset` is the position of `data` in the data stage. The chunks are at most as long as
+    /// the control buffer. The response to the last chunk is the response to the request;
+    /// returning `OutResponse::Rejected` for any chunk aborts the transfer.
+    fn control_out_chunk(&mut self, req: Request, offset: usize, data: &[u8]) -> OutResponse {
+        let _ = (req, offset, data);
+        OutResponse::Rejected
+    }
+
+    /// Called when a control request with direction DeviceToHost is received, and the host
+    /// accepts a longer response than the control buffer can hold.
+    ///
+    /// The response is sent in chunks: this is called with the `offset` of each chunk in the
+    /// response, until it returns a chunk shorter than `buf` or the response reaches
+    /// `req.length`. Chunks must fill `buf`, except the last one.
+    ///
+    /// By default, this calls [`control_in`](Self::control_in) for the first chunk, and ends
+    /// the response there.
+    fn control_in_chunk<'a>(
+        &'a mut self,
+        req: Request,
+        offset: usize,
+        buf: &'a mut [u8],
+    ) -> InResponse<'a> {
+        if offset == 0 {
+            self.control_in(req, buf)
+        } else {
+            InResponse::Accepted(&[])
+        }
+    }
+
     fn set_interface(&mut self, alternate_setting: u16) -> OutResponse {
         if alternate_setting == u16::from(DEFAULT_ALTERNATE_SETTING) {
             OutResponse::Accepted
@@ -205,7 +265,7 @@ pub(crate) struct DataInStage {
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub(crate) struct DataOutStage {
-    length: usize,
+    pub(crate) length: usize,
 }
 
 /// Typestate representing a ControlPipe in the STATUS stage
@@ -279,6 +339,70 @@ impl<C: driver::ControlPipe> ControlPipe<C> {
         }
     }
 
+    /// Reads the next chunk of a DATA OUT stage longer than `buf`: as many packets as fit in
+    /// `buf`. Returns the status stage with the last chunk.
+    ///
+    /// `buf` must be at least one packet long.
+    pub(crate) async fn data_out_chunk<'a>(
+        &mut self,
+        buf: &'a mut [u8],
+        stage: &mut DataOutStage,
+    ) -> Result<(&'a [u8], Option<StatusStage>), EndpointError> {
+        let max_packet_size = self.control.max_packet_size();
+        let len = buf.len() - buf.len() % max_packet_size;
+        let mut total = 0;
+        let mut short = false;
+
+        for chunk in buf[..len].chunks_mut(max_packet_size) {
+            let size = self.control.data_out(chunk).await?;
+            total += size;
+            short = size < max_packet_size;
+            if short || total >= stage.length {
+                break;
+            }
+        }
+        stage.length = stage.length.saturating_sub(total);
+
+        let res = &buf[0..total];
+        #[cfg(feature = "defmt")]
+        trace!("  control out data chunk: {:02x}", res);
+        #[cfg(not(feature = "defmt"))]
+        trace!("  control out data chunk: {:02x?}", res);
+
+        let status = (short || stage.length == 0).then(|| StatusStage {});
+        Ok((res, status))
+    }
+
+    /// Sends a chunk of a DATA IN stage. `data` must be a multiple of the max packet size,
+    /// unless it's the `last` chunk. `sent` is the length of the previous chunks.
+    pub(crate) async fn data_in_chunk(
+        &mut self,
+        data: &[u8],
+        sent: usize,
+        last: bool,
+        stage: &DataInStage,
+    ) -> Result<(), EndpointError> {
+        #[cfg(feature = "defmt")]
+        trace!("  control in chunk {:02x}", data);
+        #[cfg(not(feature = "defmt"))]
+        trace!("  control in chunk {:02x?}", data);
+
+        let max_packet_size = self.control.max_packet_size();
+        let need_zlp =
+            last && sent + data.len() != stage.length && data.len() % max_packet_size == 0;
+
+        let mut chunks = data
+            .chunks(max_packet_size)
+            .chain(need_zlp.then(|| -> &[u8] { &[] }));
+
+        while let Some(chunk) = chunks.next() {
+            self.control
+                .data_in(chunk, last && chunks.size_hint().0 == 0)
+                .await?;
+        }
+        Ok(())
+    }
+
     pub(crate) async fn accept_in(&mut self, buf: &[u8], stage: DataInStage) {
         #[cfg(feature = "defmt")]
         trace!("  control in accept {:02x}", buf);
@@ -39,6 +39,31 @@ pub trait Driver<'a> {
         interval: u8,
     ) -> Result<Self::EndpointIn, EndpointAllocError>;
 
+    /// Allocates a double-buffered BULK OUT endpoint: the next packet can be received while
+    /// the previous one is read, so the host isn't NAKed between packets.
+    ///
+    /// Double-buffering may use more endpoint resources than a regular endpoint. Drivers that
+    /// don't support it allocate a regular BULK endpoint, which is the default.
+    fn alloc_endpoint_out_double_buffered(
+        &mut self,
+        ep_addr: Option<EndpointAddress>,
+        max_packet_size: u16,
+    ) -> Result<Self::EndpointOut, EndpointAllocError> {
+        self.alloc_endpoint_out(ep_addr, EndpointType::Bulk, max_packet_size, 0)
+    }
+
+    /// Allocates a double-buffered BULK IN endpoint: the next packet can be written while the
+    /// previous one is sent.
+    ///
+    /// See [`alloc_endpoint_out_double_buffered`](Self::alloc_endpoint_out_double_buffered).
+    fn alloc_endpoint_in_double_buffered(
+        &mut self,
+        ep_addr: Option<EndpointAddress>,
+        max_packet_size: u16,
+    ) -> Result<Self::EndpointIn, EndpointAllocError> {
+        self.alloc_endpoint_in(ep_addr, EndpointType::Bulk, max_packet_size, 0)
+    }
+
     fn alloc_control_pipe(
         &mut self,
         max_packet_size: u16,
@@ -85,7 +110,8 @@ pub trait Bus {
     fn set_configured(&mut self, configured: bool);
 
     /// Sets or clears the STALL condition for an endpoint. If the endpoint is an OUT endpoint, it
-    /// should be prepared to receive data again. Only used during control transfers.
+    /// should be prepared to receive data again. Clearing it resets the data toggle of the
+    /// endpoint to DATA0. Only used during control transfers.
     fn set_stalled(&mut self, ep_addr: EndpointAddress, stalled: bool);
 
     /// Gets whether the STALL condition is set for an endpoint. Only used during control transfers.
@@ -194,6 +220,25 @@ pub trait EndpointIn: Endpoint {
     fn write<'a>(&'a mut self, buf: &'a [u8]) -> Self::WriteFuture<'a>;
 }
 
+/// Writes `data` to `ep` as a whole transfer of any length.
+///
+/// `data` is split in packets of the max packet size of the endpoint. The host only sees the
+/// end of a transfer on a short packet, so a zero-length packet is appended when the length of
+/// `data` is a multiple of the max packet size (including when it's empty).
+///
+/// Protocols where the host knows the length of the transfer in advance, and doesn't expect a
+/// zero-length packet after a transfer of exactly that length, must write packets themselves.
+pub async fn write_transfer<E: EndpointIn>(ep: &mut E, data: &[u8]) -> Result<(), EndpointError> {
+    let max_packet_size = usize::from(ep.info().max_packet_size);
+    for chunk in data.chunks(max_packet_size) {
+        ep.write(chunk).await?;
+    }
+    if data.len() % max_packet_size == 0 {
+        ep.write(&[]).await?;
+    }
+    Ok(())
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 /// Event returned by [`Bus::poll`].
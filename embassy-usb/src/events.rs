@@ -0,0 +1,98 @@
+use core::cell::RefCell;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use embassy::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy::blocking_mutex::Mutex;
+use embassy::waitqueue::WakerRegistration;
+use heapless::Deque;
+
+/// Number of events kept by [`BusEvents`] until the application reads them.
+const QUEUE_LEN: usize = 4;
+
+/// A USB bus event, as seen by the application.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BusEvent {
+    /// The host has reset the device.
+    Reset,
+
+    /// The bus has been suspended. The device should reduce its power consumption and stop
+    /// queueing data for the host.
+    Suspend,
+
+    /// The bus has resumed after being suspended.
+    Resume,
+}
+
+struct State {
+    queue: Deque<BusEvent, QUEUE_LEN>,
+    waker: WakerRegistration,
+}
+
+/// Queue of bus events, delivered to the application while [`UsbDevice`](crate::UsbDevice) runs
+/// in another task.
+///
+/// Pass it to [`Builder::bus_events`](crate::Builder::bus_events). If the application doesn't
+/// keep up, the oldest events are dropped.
+pub struct BusEvents {
+    state: Mutex<CriticalSectionRawMutex, RefCell<State>>,
+}
+
+impl BusEvents {
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::const_new(
+                CriticalSectionRawMutex::new(),
+                RefCell::new(State {
+                    queue: Deque::new(),
+                    waker: WakerRegistration::new(),
+                }),
+            ),
+        }
+    }
+
+    pub(crate) fn push(&self, event: BusEvent) {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            if s.queue.is_full() {
+                s.queue.pop_front();
+            }
+            // Can't fail, we just made room.
+            let _ = s.queue.push_back(event);
+            s.waker.wake();
+        })
+    }
+
+    /// Get the next event, if there is one.
+    pub fn try_next(&self) -> Option<BusEvent> {
+        self.state.lock(|s| s.borrow_mut().queue.pop_front())
+    }
+
+    /// Wait for the next event.
+    pub async fn next(&self) -> BusEvent {
+        NextFuture { events: self }.await
+    }
+}
+
+struct NextFuture<'a> {
+    events: &'a BusEvents,
+}
+
+impl<'a> Future for NextFuture<'a> {
+    type Output = BusEvent;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<BusEvent> {
+        self.events.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            match s.queue.pop_front() {
+                Some(event) => Poll::Ready(event),
+                None => {
+                    s.waker.register(cx.waker());
+                    Poll::Pending
+                }
+            }
+        })
+    }
+}
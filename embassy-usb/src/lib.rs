@@ -2,13 +2,14 @@
 #![feature(generic_associated_types)]
 #![feature(type_alias_impl_trait)]
 
-// This mod MUST go first, so that the others see its macros.
-pub(crate) mod fmt;
+#[macro_use]
+extern crate embassy_fmt;
 
 mod builder;
 pub mod control;
 pub mod descriptor;
 pub mod driver;
+mod events;
 pub mod types;
 
 use embassy::util::{select, Either};
@@ -21,6 +22,7 @@ use self::types::*;
 
 pub use self::builder::Builder;
 pub use self::builder::Config;
+pub use self::events::{BusEvent, BusEvents};
 
 /// The global state of the USB device.
 ///
@@ -80,6 +82,25 @@ pub trait DeviceStateHandler {
     /// Called when the host has enabled or disabled the configuration of the device.
     fn configured(&self, _configured: bool) {}
 
+    /// Called when the current the device may draw from the bus changes, in milliamps.
+    ///
+    /// When the host configures the device, it grants the current requested in the
+    /// configuration descriptor (see [`Config::max_power`]). Before that, and after a reset or
+    /// when the configuration is disabled, the device may only draw 100mA, which is reported as
+    /// `100`. While suspended, it may only draw 2.5mA, which is reported as `0`.
+    ///
+    /// Battery-powered devices can use this to decide how fast to charge.
+    fn power_granted(&self, _max_power: u16) {}
+
+    /// Called when the host asks for the device status, to tell whether the device
+    /// is currently self-powered.
+    ///
+    /// Return `None` to report [`Config::self_powered`], or the value given to
+    /// [`UsbDevice::set_self_powered`].
+    fn self_powered(&self) -> Option<bool> {
+        None
+    }
+
     /// Called when the bus has entered or exited the suspend state.
     fn suspended(&self, _suspended: bool) {}
 
@@ -90,11 +111,12 @@ pub trait DeviceStateHandler {
 pub struct UsbDevice<'d, D: Driver<'d>> {
     bus: D::Bus,
     handler: Option<&'d dyn DeviceStateHandler>,
+    bus_events: Option<&'d BusEvents>,
     control: ControlPipe<D::ControlPipe>,
 
     config: Config<'d>,
     device_descriptor: &'d [u8],
-    config_descriptor: &'d [u8],
+    config_descriptor: &'d mut [u8],
     bos_descriptor: &'d [u8],
     control_buf: &'d mut [u8],
 
@@ -112,8 +134,9 @@ impl<'d, D: Driver<'d>> UsbDevice<'d, D> {
         mut driver: D,
         config: Config<'d>,
         handler: Option<&'d dyn DeviceStateHandler>,
+        bus_events: Option<&'d BusEvents>,
         device_descriptor: &'d [u8],
-        config_descriptor: &'d [u8],
+        config_descriptor: &'d mut [u8],
         bos_descriptor: &'d [u8],
         interfaces: Vec<(u8, &'d mut dyn ControlHandler), MAX_INTERFACE_COUNT>,
         control_buf: &'d mut [u8],
@@ -125,11 +148,13 @@ impl<'d, D: Driver<'d>> UsbDevice<'d, D> {
         // Enable the USB bus.
         // This prevent further allocation by consuming the driver.
         let bus = driver.into_bus();
+        let self_powered = config.self_powered;
 
         Self {
             bus,
             config,
             handler,
+            bus_events,
             control: ControlPipe::new(control),
             device_descriptor,
             config_descriptor,
@@ -138,12 +163,61 @@ impl<'d, D: Driver<'d>> UsbDevice<'d, D> {
             device_state: UsbDeviceState::Disabled,
             suspended: false,
             remote_wakeup_enabled: false,
-            self_powered: false,
+            self_powered,
             pending_address: 0,
             interfaces,
         }
     }
 
+    /// Returns the queue of bus events given to [`Builder::bus_events`], if any.
+    ///
+    /// The queue outlives the borrow of the `UsbDevice`, so it can be read by another task
+    /// while this one runs [`UsbDevice::run`]:
+    ///
+    /// ```ignore
+    /// let events = usb.bus_events().unwrap();
+    /// join(usb.run(), async {
+    ///     loop {
+    ///         match events.next().await {
+    ///             BusEvent::Suspend => { /* enter low power */ }
+    ///             BusEvent::Resume | BusEvent::Reset => { /* leave low power */ }
+    ///         }
+    ///     }
+    /// })
+    /// .await;
+    /// ```
+    pub fn bus_events(&self) -> Option<&'d BusEvents> {
+        self.bus_events
+    }
+
+    /// Sets whether the device reports itself as self-powered to the host.
+    ///
+    /// This is the value returned by GET_STATUS, for devices whose power source changes
+    /// at runtime, like a battery-powered device plugged into a charger. The
+    /// [`DeviceStateHandler::self_powered`] callback, if it returns `Some`, takes precedence.
+    pub fn set_self_powered(&mut self, self_powered: bool) {
+        self.self_powered = self_powered;
+    }
+
+    /// Sets the maximum current drawn from the bus, in milliamps, as reported in the
+    /// configuration descriptor.
+    ///
+    /// The host only reads it when enumerating the device, so the new value is taken into
+    /// account after the next reset, for example after [`UsbDevice::disable()`] and
+    /// re-enabling the device.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_power` is greater than 500mA.
+    pub fn set_max_power(&mut self, max_power: u16) {
+        if max_power > 500 {
+            panic!("The maximum allowed value for `max_power` is 500mA");
+        }
+        self.config.max_power = max_power;
+        // bMaxPower, in 2mA units.
+        self.config_descriptor[8] = (max_power / 2) as u8;
+    }
+
     /// Runs the `UsbDevice` forever.
     ///
     /// This future may leave the bus in an invalid state if it is dropped.
@@ -226,11 +300,7 @@ impl<'d, D: Driver<'d>> UsbDevice<'d, D> {
     pub async fn remote_wakeup(&mut self) -> Result<(), RemoteWakeupError> {
         if self.suspended && self.remote_wakeup_enabled {
             self.bus.remote_wakeup().await?;
-            self.suspended = false;
-
-            if let Some(h) = &self.handler {
-                h.suspended(false);
-            }
+            self.set_suspended(false);
 
             Ok(())
         } else {
@@ -253,29 +323,61 @@ impl<'d, D: Driver<'d>> UsbDevice<'d, D> {
 
                 if let Some(h) = &self.handler {
                     h.reset();
+                    h.power_granted(100);
+                }
+
+                if let Some(e) = self.bus_events {
+                    e.push(BusEvent::Reset);
                 }
             }
             Event::Resume => {
                 trace!("usb: resume");
-                self.suspended = false;
-                if let Some(h) = &self.handler {
-                    h.suspended(false);
-                }
+                self.set_suspended(false);
             }
             Event::Suspend => {
                 trace!("usb: suspend");
-                self.suspended = true;
-                if let Some(h) = &self.handler {
-                    h.suspended(true);
-                }
+                self.set_suspended(true);
             }
         }
     }
 
+    /// Current the device may draw from the bus, in the current state.
+    fn granted_power(&self) -> u16 {
+        match (self.suspended, self.device_state) {
+            (true, _) => 0,
+            (false, UsbDeviceState::Configured) => self.config.max_power,
+            (false, _) => 100,
+        }
+    }
+
+    fn set_suspended(&mut self, suspended: bool) {
+        self.suspended = suspended;
+
+        for (_, h) in self.interfaces.iter_mut() {
+            h.suspended(suspended);
+        }
+
+        if let Some(h) = &self.handler {
+            h.suspended(suspended);
+            h.power_granted(self.granted_power());
+        }
+
+        if let Some(e) = self.bus_events {
+            e.push(match suspended {
+                true => BusEvent::Suspend,
+                false => BusEvent::Resume,
+            });
+        }
+    }
+
     async fn handle_control_out(&mut self, req: Request, stage: DataOutStage) {
         const CONFIGURATION_NONE_U16: u16 = CONFIGURATION_NONE as u16;
         const CONFIGURATION_VALUE_U16: u16 = CONFIGURATION_VALUE as u16;
 
+        if stage.length > self.control_buf.len() {
+            return self.handle_control_out_chunked(req, stage).await;
+        }
+
         let (data, stage) = match self.control.data_out(self.control_buf, stage).await {
             Ok(data) => data,
             Err(_) => {
@@ -314,6 +416,7 @@ impl<'d, D: Driver<'d>> UsbDevice<'d, D> {
                     self.bus.set_configured(true);
                     if let Some(h) = &self.handler {
                         h.configured(true);
+                        h.power_granted(self.config.max_power);
                     }
                     self.control.accept(stage)
                 }
@@ -324,6 +427,7 @@ impl<'d, D: Driver<'d>> UsbDevice<'d, D> {
                         self.bus.set_configured(false);
                         if let Some(h) = &self.handler {
                             h.configured(false);
+                            h.power_granted(100);
                         }
                         self.control.accept(stage)
                     }
@@ -334,11 +438,17 @@ impl<'d, D: Driver<'d>> UsbDevice<'d, D> {
                 (Request::SET_FEATURE, Request::FEATURE_ENDPOINT_HALT) => {
                     let ep_addr = ((req.index as u8) & 0x8f).into();
                     self.bus.set_stalled(ep_addr, true);
+                    for (_, h) in self.interfaces.iter_mut() {
+                        h.endpoint_halted(ep_addr, true);
+                    }
                     self.control.accept(stage)
                 }
                 (Request::CLEAR_FEATURE, Request::FEATURE_ENDPOINT_HALT) => {
                     let ep_addr = ((req.index as u8) & 0x8f).into();
                     self.bus.set_stalled(ep_addr, false);
+                    for (_, h) in self.interfaces.iter_mut() {
+                        h.endpoint_halted(ep_addr, false);
+                    }
                     self.control.accept(stage)
                 }
                 _ => self.control.reject(),
@@ -370,6 +480,96 @@ impl<'d, D: Driver<'d>> UsbDevice<'d, D> {
         }
     }
 
+    /// Handles a CONTROL OUT request with a data stage longer than `control_buf`, by passing
+    /// the data to the interface handler as it arrives.
+    async fn handle_control_out_chunked(&mut self, req: Request, mut stage: DataOutStage) {
+        // Standard requests never carry that much data.
+        if req.request_type == RequestType::Standard || req.recipient != Recipient::Interface {
+            return self.control.reject();
+        }
+        if self.control_buf.len() < usize::from(self.config.max_packet_size_0) {
+            warn!("usb: control_buf is shorter than a packet, can't receive a long CONTROL OUT request.");
+            return self.control.reject();
+        }
+
+        let handler = self
+            .interfaces
+            .iter_mut()
+            .find(|(i, _)| req.index == *i as _)
+            .map(|(_, h)| h);
+        let handler = match handler {
+            Some(handler) if handler.control_out_start(req) == OutResponse::Accepted => handler,
+            _ => return self.control.reject(),
+        };
+
+        let mut offset = 0;
+        loop {
+            let (data, status) = match self
+                .control
+                .data_out_chunk(self.control_buf, &mut stage)
+                .await
+            {
+                Ok(res) => res,
+                Err(_) => {
+                    warn!("usb: failed to read CONTROL OUT data stage.");
+                    return;
+                }
+            };
+
+            if handler.control_out_chunk(req, offset, data) == OutResponse::Rejected {
+                return self.control.reject();
+            }
+            offset += data.len();
+
+            if let Some(status) = status {
+                return self.control.accept(status);
+            }
+        }
+    }
+
+    /// Sends the response to a CONTROL IN request longer than `control_buf`, in chunks
+    /// written by the interface handler.
+    async fn handle_control_in_chunked(&mut self, req: Request, stage: DataInStage) {
+        let handler = self
+            .interfaces
+            .iter_mut()
+            .find(|(i, _)| req.index == *i as _)
+            .map(|(_, h)| h);
+        let handler = match handler {
+            Some(handler) => handler,
+            None => return self.control.reject(),
+        };
+
+        let max_packet_size = usize::from(self.config.max_packet_size_0);
+        let mut chunk_len = self.control_buf.len();
+        if chunk_len >= max_packet_size {
+            // Only the last chunk may end with a short packet.
+            chunk_len -= chunk_len % max_packet_size;
+        }
+
+        let mut offset = 0;
+        loop {
+            let len = chunk_len.min(stage.length - offset);
+            let data = match handler.control_in_chunk(req, offset, &mut self.control_buf[..len]) {
+                InResponse::Accepted(data) => &data[..data.len().min(len)],
+                // The host gets a stall instead of the rest of the data.
+                InResponse::Rejected => return self.control.reject(),
+            };
+            let last = data.len() < len
+                || data.len() % max_packet_size != 0
+                || offset + data.len() == stage.length;
+
+            if let Err(e) = self.control.data_in_chunk(data, offset, last, &stage).await {
+                warn!("control accept_in failed: {:?}", e);
+                return;
+            }
+            if last {
+                return;
+            }
+            offset += data.len();
+        }
+    }
+
     async fn handle_control_in(&mut self, req: Request, mut stage: DataInStage) {
         // If we don't have an address yet, respond with max 1 packet.
         // The host doesn't know our EP0 max packet size yet, and might assume
@@ -384,11 +584,22 @@ impl<'d, D: Driver<'d>> UsbDevice<'d, D> {
             stage.length = self.config.max_packet_size_0 as _;
         }
 
+        if req.request_type != RequestType::Standard
+            && req.recipient == Recipient::Interface
+            && stage.length > self.control_buf.len()
+        {
+            return self.handle_control_in_chunked(req, stage).await;
+        }
+
         match (req.request_type, req.recipient) {
             (RequestType::Standard, Recipient::Device) => match req.request {
                 Request::GET_STATUS => {
+                    let self_powered = self
+                        .handler
+                        .and_then(|h| h.self_powered())
+                        .unwrap_or(self.self_powered);
                     let mut status: u16 = 0x0000;
-                    if self.self_powered {
+                    if self_powered {
                         status |= 0x0001;
                     }
                     if self.remote_wakeup_enabled {
@@ -0,0 +1,156 @@
+/// Async barrier.
+///
+/// A barrier makes a fixed number of tasks wait for each other: each task calls
+/// [`Barrier::wait`], and they all continue once the last one arrives. This is useful for
+/// phased startup, for example to let several driver tasks initialize their hardware before
+/// any of them starts using a shared resource.
+///
+/// The barrier can be reused: once all tasks have passed it, it waits for the same number
+/// of tasks again.
+use core::cell::{RefCell, UnsafeCell};
+use core::future::Future;
+use core::marker::PhantomPinned;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::blocking_mutex::raw::RawMutex;
+use crate::blocking_mutex::Mutex as BlockingMutex;
+use crate::waitqueue::{Waiter, WaiterQueue};
+
+struct State {
+    /// Number of tasks waiting for the current generation.
+    arrived: usize,
+    waiters: WaiterQueue<()>,
+}
+
+pub struct Barrier<M>
+where
+    M: RawMutex,
+{
+    count: usize,
+    state: BlockingMutex<M, RefCell<State>>,
+}
+
+/// Returned by [`Barrier::wait`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BarrierWaitResult {
+    is_leader: bool,
+}
+
+impl BarrierWaitResult {
+    /// Returns true for exactly one of the tasks of each generation, the last one to arrive.
+    pub fn is_leader(&self) -> bool {
+        self.is_leader
+    }
+}
+
+impl<M> Barrier<M>
+where
+    M: RawMutex,
+{
+    /// Create a new barrier, waiting for `count` tasks.
+    #[cfg(feature = "nightly")]
+    pub const fn new(count: usize) -> Self {
+        Self {
+            count,
+            state: BlockingMutex::new(RefCell::new(State {
+                arrived: 0,
+                waiters: WaiterQueue::new(),
+            })),
+        }
+    }
+
+    /// Create a new barrier, waiting for `count` tasks.
+    #[cfg(not(feature = "nightly"))]
+    pub fn new(count: usize) -> Self {
+        Self {
+            count,
+            state: BlockingMutex::new(RefCell::new(State {
+                arrived: 0,
+                waiters: WaiterQueue::new(),
+            })),
+        }
+    }
+
+    /// Wait until `count` tasks are waiting on the barrier.
+    ///
+    /// If the future is dropped before all tasks arrived, the task no longer counts as arrived.
+    pub async fn wait(&self) -> BarrierWaitResult {
+        WaitFuture {
+            barrier: self,
+            waiter: UnsafeCell::new(Waiter::new(())),
+            done: false,
+            _pinned: PhantomPinned,
+        }
+        .await
+    }
+}
+
+struct WaitFuture<'a, M>
+where
+    M: RawMutex,
+{
+    barrier: &'a Barrier<M>,
+    waiter: UnsafeCell<Waiter<()>>,
+    done: bool,
+    _pinned: PhantomPinned,
+}
+
+impl<'a, M> Future for WaitFuture<'a, M>
+where
+    M: RawMutex,
+{
+    type Output = BarrierWaitResult;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Safety: the future is pinned, so the waiter doesn't move while queued.
+        let this = unsafe { self.get_unchecked_mut() };
+        let waiter = this.waiter.get();
+        let count = this.barrier.count;
+
+        let res = this.barrier.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            let w = unsafe { &mut *waiter };
+            if w.is_granted() {
+                return Poll::Ready(BarrierWaitResult { is_leader: false });
+            }
+            if !w.is_queued() {
+                if s.arrived + 1 >= count {
+                    // Last one in, release everybody and start the next generation.
+                    s.arrived = 0;
+                    while s.waiters.grant_front() {}
+                    return Poll::Ready(BarrierWaitResult { is_leader: true });
+                }
+                s.arrived += 1;
+                unsafe { s.waiters.push(waiter) };
+            }
+            w.set_waker(cx.waker());
+            Poll::Pending
+        });
+
+        if res.is_ready() {
+            this.done = true;
+        }
+        res
+    }
+}
+
+impl<'a, M> Drop for WaitFuture<'a, M>
+where
+    M: RawMutex,
+{
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let waiter = self.waiter.get();
+        self.barrier.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            if unsafe { (*waiter).is_queued() } {
+                s.arrived -= 1;
+                unsafe { s.waiters.remove(waiter) };
+            }
+        })
+    }
+}
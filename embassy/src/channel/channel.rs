@@ -20,11 +20,15 @@ use core::pin::Pin;
 use core::task::Context;
 use core::task::Poll;
 
+#[cfg(feature = "time")]
+use futures::future::poll_fn;
 use futures::Future;
 use heapless::Deque;
 
 use crate::blocking_mutex::raw::RawMutex;
 use crate::blocking_mutex::Mutex;
+#[cfg(feature = "time")]
+use crate::time::{with_timeout, Duration, TimeoutError};
 use crate::waitqueue::WakerRegistration;
 
 /// Send-only access to a [`Channel`].
@@ -64,6 +68,36 @@ where
     pub fn try_send(&self, message: T) -> Result<(), TrySendError<T>> {
         self.channel.try_send(message)
     }
+
+    /// Send a value, waiting at most `timeout` for capacity.
+    ///
+    /// See [`Channel::send_timeout()`]
+    #[cfg(feature = "time")]
+    pub async fn send_timeout(&self, message: T, timeout: Duration) -> Result<(), TrySendError<T>> {
+        self.channel.send_timeout(message, timeout).await
+    }
+
+    /// Poll for capacity to send a value.
+    ///
+    /// See [`Channel::poll_ready_to_send()`]
+    pub fn poll_ready_to_send(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.channel.poll_ready_to_send(cx)
+    }
+
+    /// Number of values in the channel.
+    pub fn len(&self) -> usize {
+        self.channel.len()
+    }
+
+    /// Returns true if the channel holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.channel.is_empty()
+    }
+
+    /// Returns true if the channel is full: sending would wait.
+    pub fn is_full(&self) -> bool {
+        self.channel.is_full()
+    }
 }
 
 /// Send-only access to a [`Channel`] without knowing channel size.
@@ -145,6 +179,31 @@ where
     pub fn try_recv(&self) -> Result<T, TryRecvError> {
         self.channel.try_recv()
     }
+
+    /// Receive the next value, waiting at most `timeout` for one.
+    ///
+    /// See [`Channel::recv_timeout()`]
+    #[cfg(feature = "time")]
+    pub async fn recv_timeout(&self, timeout: Duration) -> Result<T, TimeoutError> {
+        self.channel.recv_timeout(timeout).await
+    }
+
+    /// Poll for the next value.
+    ///
+    /// See [`Channel::poll_recv()`]
+    pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<T> {
+        self.channel.poll_recv(cx)
+    }
+
+    /// Number of values in the channel.
+    pub fn len(&self) -> usize {
+        self.channel.len()
+    }
+
+    /// Returns true if the channel holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.channel.is_empty()
+    }
 }
 
 /// Receive-only access to a [`Channel`] without knowing channel size.
@@ -343,6 +402,24 @@ impl<T, const N: usize> ChannelState<T, N> {
         self.try_send_with_context(message, None)
     }
 
+    fn poll_ready_to_send(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.queue.is_full() {
+            self.senders_waker.register(cx.waker());
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    }
+
+    fn poll_ready_to_recv(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.queue.is_empty() {
+            self.receiver_waker.register(cx.waker());
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    }
+
     fn try_send_with_context(
         &mut self,
         message: T,
@@ -480,6 +557,87 @@ where
     pub fn try_recv(&self) -> Result<T, TryRecvError> {
         self.lock(|c| c.try_recv())
     }
+
+    /// Send a value, waiting at most `timeout` for capacity.
+    ///
+    /// On timeout, the value is given back in [`TrySendError::Full`].
+    #[cfg(feature = "time")]
+    pub async fn send_timeout(&self, message: T, timeout: Duration) -> Result<(), TrySendError<T>> {
+        let mut message = Some(message);
+        let send =
+            poll_fn(
+                |cx| match self.try_send_with_context(unwrap!(message.take()), Some(cx)) {
+                    Ok(()) => Poll::Ready(()),
+                    Err(TrySendError::Full(m)) => {
+                        message = Some(m);
+                        Poll::Pending
+                    }
+                },
+            );
+        match with_timeout(timeout, send).await {
+            Ok(()) => Ok(()),
+            Err(_) => Err(TrySendError::Full(unwrap!(message.take()))),
+        }
+    }
+
+    /// Receive the next value, waiting at most `timeout` for one.
+    #[cfg(feature = "time")]
+    pub async fn recv_timeout(&self, timeout: Duration) -> Result<T, TimeoutError> {
+        with_timeout(timeout, self.recv()).await
+    }
+
+    /// Poll for the next value.
+    ///
+    /// When the channel is empty, the waker of `cx` is registered to be woken when a value is sent.
+    /// Like [`recv`](Self::recv), this never loses values: a value is only taken out of the
+    /// channel when it's returned. This allows receiving from non-async contexts like `poll`
+    /// implementations, or `select`-ing on several channels.
+    pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<T> {
+        match self.try_recv_with_context(Some(cx)) {
+            Ok(v) => Poll::Ready(v),
+            Err(TryRecvError::Empty) => Poll::Pending,
+        }
+    }
+
+    /// Poll for capacity to send a value.
+    ///
+    /// When the channel is full, the waker of `cx` is registered to be woken when a value is
+    /// received. Once this returns `Ready`, a [`try_send`](Self::try_send) succeeds, unless
+    /// another sender filled the channel in between. This gives backpressure to non-async
+    /// contexts that would otherwise have to drop values when `try_send` fails.
+    ///
+    /// Prefer this over `select`-ing on [`send`](Self::send): dropping a send future
+    /// drops the value it holds.
+    pub fn poll_ready_to_send(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.lock(|c| c.poll_ready_to_send(cx))
+    }
+
+    /// Poll for a value to be available, without receiving it.
+    ///
+    /// When the channel is empty, the waker of `cx` is registered to be woken when a value is sent.
+    pub fn poll_ready_to_recv(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.lock(|c| c.poll_ready_to_recv(cx))
+    }
+
+    /// Number of values in the channel.
+    pub fn len(&self) -> usize {
+        self.lock(|c| c.queue.len())
+    }
+
+    /// Maximum number of values the channel can hold, `N`.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns true if the channel holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.lock(|c| c.queue.is_empty())
+    }
+
+    /// Returns true if the channel is full: sending would wait.
+    pub fn is_full(&self) -> bool {
+        self.lock(|c| c.queue.is_full())
+    }
 }
 
 /// Implements the DynamicChannel to allow creating types that are unaware of the queue size with the
@@ -563,6 +721,19 @@ mod tests {
         assert_eq!(c.try_recv().unwrap(), 1);
     }
 
+    #[test]
+    fn len_and_full() {
+        let c = Channel::<NoopRawMutex, u32, 2>::new();
+        assert!(c.is_empty());
+        assert_eq!(c.capacity(), 2);
+        assert!(c.try_send(1).is_ok());
+        assert_eq!(c.len(), 1);
+        assert!(!c.is_full());
+        assert!(c.try_send(2).is_ok());
+        assert!(c.is_full());
+        assert_eq!(c.receiver().len(), 2);
+    }
+
     #[test]
     fn cloning() {
         let c = Channel::<NoopRawMutex, u32, 3>::new();
@@ -3,5 +3,14 @@
 pub mod channel;
 pub use channel::*;
 
+pub mod pipe;
+pub use pipe::Pipe;
+
+pub mod priority_channel;
+pub use priority_channel::PriorityChannel;
+
 pub mod signal;
 pub use signal::*;
+
+pub mod watch;
+pub use watch::*;
@@ -0,0 +1,422 @@
+//! Async byte stream pipe.
+//!
+//! A [`Pipe`] is a ring buffer of bytes with async reads and writes. It connects a producer
+//! of a byte stream with a consumer that doesn't follow the same chunking, for example a
+//! UART interrupt handler feeding a parser task, without allocating per message like a
+//! [`Channel`](super::channel::Channel) of buffers would.
+//!
+//! Like the other channels, it takes a [`RawMutex`] type: use a
+//! [`CriticalSectionRawMutex`](crate::blocking_mutex::raw::CriticalSectionRawMutex) to
+//! write to it from an interrupt handler with [`Pipe::try_write`].
+
+use core::cell::RefCell;
+use core::task::{Context, Poll};
+
+use futures::future::poll_fn;
+
+use crate::blocking_mutex::raw::RawMutex;
+use crate::blocking_mutex::Mutex;
+use crate::waitqueue::WakerRegistration;
+
+/// Error returned by [`try_write`](Pipe::try_write).
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TryWriteError {
+    /// No byte could be written because the pipe is full.
+    Full,
+}
+
+/// Error returned by [`try_read`](Pipe::try_read).
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TryReadError {
+    /// No byte could be read because the pipe is empty.
+    Empty,
+}
+
+struct PipeState<const N: usize> {
+    buffer: [u8; N],
+    /// Index of the first byte to read.
+    start: usize,
+    len: usize,
+    read_waker: WakerRegistration,
+    write_waker: WakerRegistration,
+}
+
+impl<const N: usize> PipeState<N> {
+    const fn new() -> Self {
+        PipeState {
+            buffer: [0; N],
+            start: 0,
+            len: 0,
+            read_waker: WakerRegistration::new(),
+            write_waker: WakerRegistration::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+        self.write_waker.wake();
+    }
+
+    fn try_write_with_context(
+        &mut self,
+        cx: Option<&mut Context<'_>>,
+        buf: &[u8],
+    ) -> Result<usize, TryWriteError> {
+        let n = buf.len().min(N - self.len);
+        if n == 0 && !buf.is_empty() {
+            if let Some(cx) = cx {
+                self.write_waker.register(cx.waker());
+            }
+            return Err(TryWriteError::Full);
+        }
+
+        // The free space may wrap around the end of the buffer.
+        let end = (self.start + self.len) % N.max(1);
+        let first = n.min(N - end);
+        self.buffer[end..end + first].copy_from_slice(&buf[..first]);
+        self.buffer[..n - first].copy_from_slice(&buf[first..n]);
+        self.len += n;
+
+        if n > 0 {
+            self.read_waker.wake();
+        }
+        Ok(n)
+    }
+
+    fn try_read_with_context(
+        &mut self,
+        cx: Option<&mut Context<'_>>,
+        buf: &mut [u8],
+    ) -> Result<usize, TryReadError> {
+        let n = buf.len().min(self.len);
+        if n == 0 && !buf.is_empty() {
+            if let Some(cx) = cx {
+                self.read_waker.register(cx.waker());
+            }
+            return Err(TryReadError::Empty);
+        }
+
+        // The data may wrap around the end of the buffer.
+        let first = n.min(N - self.start);
+        buf[..first].copy_from_slice(&self.buffer[self.start..self.start + first]);
+        buf[first..n].copy_from_slice(&self.buffer[..n - first]);
+        self.start = (self.start + n) % N.max(1);
+        self.len -= n;
+
+        if n > 0 {
+            self.write_waker.wake();
+        }
+        Ok(n)
+    }
+}
+
+/// A bounded pipe for a stream of bytes, see the [module documentation](self).
+///
+/// Written bytes become available to read in the same order. Reads and writes transfer as
+/// many bytes as possible at once, and wait only when nothing at all can be transferred.
+///
+/// ```
+/// use embassy::channel::pipe::Pipe;
+/// use embassy::blocking_mutex::raw::NoopRawMutex;
+///
+/// let pipe = Pipe::<NoopRawMutex, 8>::new();
+/// assert_eq!(pipe.try_write(b"hello"), Ok(5));
+/// let mut buf = [0; 3];
+/// assert_eq!(pipe.try_read(&mut buf), Ok(3));
+/// assert_eq!(&buf, b"hel");
+/// ```
+pub struct Pipe<M, const N: usize>
+where
+    M: RawMutex,
+{
+    inner: Mutex<M, RefCell<PipeState<N>>>,
+}
+
+impl<M, const N: usize> Pipe<M, N>
+where
+    M: RawMutex,
+{
+    /// Create a new empty pipe, holding up to `N` bytes.
+    #[cfg(feature = "nightly")]
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(PipeState::new())),
+        }
+    }
+
+    /// Create a new empty pipe, holding up to `N` bytes.
+    #[cfg(not(feature = "nightly"))]
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(PipeState::new())),
+        }
+    }
+
+    fn lock<R>(&self, f: impl FnOnce(&mut PipeState<N>) -> R) -> R {
+        self.inner.lock(|rc| f(&mut *rc.borrow_mut()))
+    }
+
+    /// Get a handle that can only write to the pipe.
+    pub fn writer(&self) -> Writer<'_, M, N> {
+        Writer { pipe: self }
+    }
+
+    /// Get a handle that can only read from the pipe.
+    pub fn reader(&self) -> Reader<'_, M, N> {
+        Reader { pipe: self }
+    }
+
+    /// Split the pipe into its reading and writing halves, for example to give them to
+    /// different tasks.
+    pub fn split(&self) -> (Reader<'_, M, N>, Writer<'_, M, N>) {
+        (self.reader(), self.writer())
+    }
+
+    /// Write some bytes of `buf`, waiting until there's free space. Returns the number of
+    /// bytes written, which is only less than `buf.len()` if the pipe filled up.
+    pub async fn write(&self, buf: &[u8]) -> usize {
+        poll_fn(|cx| self.poll_write(cx, buf)).await
+    }
+
+    /// Write all of `buf`, waiting for free space as many times as needed.
+    pub async fn write_all(&self, mut buf: &[u8]) {
+        while !buf.is_empty() {
+            let n = self.write(buf).await;
+            buf = &buf[n..];
+        }
+    }
+
+    /// Write some bytes of `buf` without waiting. Returns the number of bytes written.
+    ///
+    /// This can be called from an interrupt handler, if the mutex allows it.
+    pub fn try_write(&self, buf: &[u8]) -> Result<usize, TryWriteError> {
+        self.lock(|s| s.try_write_with_context(None, buf))
+    }
+
+    /// Poll writing some bytes of `buf`. This is useful for implementing your own futures.
+    pub fn poll_write(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<usize> {
+        match self.lock(|s| s.try_write_with_context(Some(cx), buf)) {
+            Ok(n) => Poll::Ready(n),
+            Err(TryWriteError::Full) => Poll::Pending,
+        }
+    }
+
+    /// Read some bytes into `buf`, waiting until there are some. Returns the number of bytes
+    /// read.
+    pub async fn read(&self, buf: &mut [u8]) -> usize {
+        poll_fn(|cx| self.poll_read(cx, buf)).await
+    }
+
+    /// Read some bytes into `buf` without waiting. Returns the number of bytes read.
+    ///
+    /// This can be called from an interrupt handler, if the mutex allows it.
+    pub fn try_read(&self, buf: &mut [u8]) -> Result<usize, TryReadError> {
+        self.lock(|s| s.try_read_with_context(None, buf))
+    }
+
+    /// Poll reading some bytes into `buf`. This is useful for implementing your own futures.
+    pub fn poll_read(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<usize> {
+        match self.lock(|s| s.try_read_with_context(Some(cx), buf)) {
+            Ok(n) => Poll::Ready(n),
+            Err(TryReadError::Empty) => Poll::Pending,
+        }
+    }
+
+    /// Discard all the bytes in the pipe.
+    pub fn clear(&self) {
+        self.lock(|s| s.clear())
+    }
+
+    /// The number of bytes that can be read.
+    pub fn len(&self) -> usize {
+        self.lock(|s| s.len)
+    }
+
+    /// Whether there is nothing to read.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether there is no space left to write.
+    pub fn is_full(&self) -> bool {
+        self.len() == N
+    }
+
+    /// The number of bytes that can be written.
+    pub fn free_capacity(&self) -> usize {
+        N - self.len()
+    }
+
+    /// The total number of bytes the pipe can hold.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+/// Write-only access to a [`Pipe`].
+///
+/// It's `Send` if the mutex of the pipe is `Sync`, like
+/// [`CriticalSectionRawMutex`](crate::blocking_mutex::raw::CriticalSectionRawMutex).
+#[derive(Copy)]
+pub struct Writer<'p, M, const N: usize>
+where
+    M: RawMutex,
+{
+    pipe: &'p Pipe<M, N>,
+}
+
+impl<'p, M, const N: usize> Clone for Writer<'p, M, N>
+where
+    M: RawMutex,
+{
+    fn clone(&self) -> Self {
+        Writer { pipe: self.pipe }
+    }
+}
+
+impl<'p, M, const N: usize> Writer<'p, M, N>
+where
+    M: RawMutex,
+{
+    /// See [`Pipe::write()`]
+    pub async fn write(&self, buf: &[u8]) -> usize {
+        self.pipe.write(buf).await
+    }
+
+    /// See [`Pipe::write_all()`]
+    pub async fn write_all(&self, buf: &[u8]) {
+        self.pipe.write_all(buf).await
+    }
+
+    /// See [`Pipe::try_write()`]
+    pub fn try_write(&self, buf: &[u8]) -> Result<usize, TryWriteError> {
+        self.pipe.try_write(buf)
+    }
+
+    /// See [`Pipe::poll_write()`]
+    pub fn poll_write(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<usize> {
+        self.pipe.poll_write(cx, buf)
+    }
+
+    /// See [`Pipe::free_capacity()`]
+    pub fn free_capacity(&self) -> usize {
+        self.pipe.free_capacity()
+    }
+}
+
+/// Read-only access to a [`Pipe`].
+///
+/// It's `Send` if the mutex of the pipe is `Sync`, like
+/// [`CriticalSectionRawMutex`](crate::blocking_mutex::raw::CriticalSectionRawMutex).
+#[derive(Copy)]
+pub struct Reader<'p, M, const N: usize>
+where
+    M: RawMutex,
+{
+    pipe: &'p Pipe<M, N>,
+}
+
+impl<'p, M, const N: usize> Clone for Reader<'p, M, N>
+where
+    M: RawMutex,
+{
+    fn clone(&self) -> Self {
+        Reader { pipe: self.pipe }
+    }
+}
+
+impl<'p, M, const N: usize> Reader<'p, M, N>
+where
+    M: RawMutex,
+{
+    /// See [`Pipe::read()`]
+    pub async fn read(&self, buf: &mut [u8]) -> usize {
+        self.pipe.read(buf).await
+    }
+
+    /// See [`Pipe::try_read()`]
+    pub fn try_read(&self, buf: &mut [u8]) -> Result<usize, TryReadError> {
+        self.pipe.try_read(buf)
+    }
+
+    /// See [`Pipe::poll_read()`]
+    pub fn poll_read(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<usize> {
+        self.pipe.poll_read(cx, buf)
+    }
+
+    /// See [`Pipe::len()`]
+    pub fn len(&self) -> usize {
+        self.pipe.len()
+    }
+
+    /// See [`Pipe::is_empty()`]
+    pub fn is_empty(&self) -> bool {
+        self.pipe.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::task::SpawnExt;
+    use futures_executor::ThreadPool;
+
+    use crate::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
+    use crate::util::Forever;
+
+    use super::*;
+
+    #[test]
+    fn writing_when_full() {
+        let mut s = PipeState::<4>::new();
+        assert_eq!(s.try_write_with_context(None, b"abcdef"), Ok(4));
+        assert_eq!(
+            s.try_write_with_context(None, b"g"),
+            Err(TryWriteError::Full)
+        );
+    }
+
+    #[test]
+    fn reading_when_empty() {
+        let mut s = PipeState::<4>::new();
+        let mut buf = [0; 2];
+        assert_eq!(
+            s.try_read_with_context(None, &mut buf),
+            Err(TryReadError::Empty)
+        );
+    }
+
+    #[test]
+    fn wrapping_around() {
+        let pipe = Pipe::<NoopRawMutex, 4>::new();
+        let mut buf = [0; 4];
+        assert_eq!(pipe.try_write(b"abc"), Ok(3));
+        assert_eq!(pipe.try_read(&mut buf[..2]), Ok(2));
+        assert_eq!(pipe.try_write(b"def"), Ok(3));
+        assert!(pipe.is_full());
+        assert_eq!(pipe.try_read(&mut buf), Ok(4));
+        assert_eq!(&buf, b"cdef");
+        assert!(pipe.is_empty());
+    }
+
+    #[futures_test::test]
+    async fn reader_and_writer_in_tasks() {
+        let executor = ThreadPool::new().unwrap();
+
+        static PIPE: Forever<Pipe<CriticalSectionRawMutex, 3>> = Forever::new();
+        let pipe = &*PIPE.put(Pipe::new());
+        let (reader, writer) = pipe.split();
+        assert!(executor
+            .spawn(async move { writer.write_all(b"hello world").await })
+            .is_ok());
+
+        let mut received = [0; 11];
+        let mut len = 0;
+        while len < received.len() {
+            len += reader.read(&mut received[len..]).await;
+        }
+        assert_eq!(&received, b"hello world");
+    }
+}
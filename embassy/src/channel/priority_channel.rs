@@ -0,0 +1,483 @@
+//! A queue for sending values between asynchronous tasks, received in priority order.
+//!
+//! Like [`Channel`](super::channel::Channel), it's a bounded MPMC channel. Values are ordered
+//! by their [`Ord`] implementation instead of the order they were sent in: with [`Max`], `recv()`
+//! returns the greatest value in the channel, and with [`Min`] the smallest one. Sending
+//! `(priority, message)` tuples with `Max`, or `(deadline, message)` tuples with `Min`, lets
+//! one consumer task serve urgent messages (like ARP replies on a network interface) before
+//! the bulk traffic queued ahead of them.
+//!
+//! Values that compare equal aren't guaranteed to be received in the order they were sent.
+
+use core::cell::RefCell;
+use core::pin::Pin;
+use core::task::Context;
+use core::task::Poll;
+
+use futures::Future;
+use heapless::binary_heap::Kind;
+use heapless::BinaryHeap;
+
+pub use heapless::binary_heap::{Max, Min};
+
+use super::channel::{TryRecvError, TrySendError};
+use crate::blocking_mutex::raw::RawMutex;
+use crate::blocking_mutex::Mutex;
+use crate::waitqueue::WakerRegistration;
+
+/// Send-only access to a [`PriorityChannel`].
+pub struct Sender<'ch, M, T, K, const N: usize>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    channel: &'ch PriorityChannel<M, T, K, N>,
+}
+
+impl<'ch, M, T, K, const N: usize> Clone for Sender<'ch, M, T, K, N>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    fn clone(&self) -> Self {
+        Sender {
+            channel: self.channel,
+        }
+    }
+}
+
+impl<'ch, M, T, K, const N: usize> Copy for Sender<'ch, M, T, K, N>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+}
+
+impl<'ch, M, T, K, const N: usize> Sender<'ch, M, T, K, N>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    /// Sends a value.
+    ///
+    /// See [`PriorityChannel::send()`]
+    pub fn send(&self, message: T) -> SendFuture<'ch, M, T, K, N> {
+        self.channel.send(message)
+    }
+
+    /// Attempt to immediately send a message.
+    ///
+    /// See [`PriorityChannel::try_send()`]
+    pub fn try_send(&self, message: T) -> Result<(), TrySendError<T>> {
+        self.channel.try_send(message)
+    }
+
+    /// Poll for capacity to send a value.
+    ///
+    /// See [`PriorityChannel::poll_ready_to_send()`]
+    pub fn poll_ready_to_send(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.channel.poll_ready_to_send(cx)
+    }
+
+    /// Number of values in the channel.
+    pub fn len(&self) -> usize {
+        self.channel.len()
+    }
+
+    /// Returns true if the channel holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.channel.is_empty()
+    }
+
+    /// Returns true if the channel is full: sending would wait.
+    pub fn is_full(&self) -> bool {
+        self.channel.is_full()
+    }
+}
+
+/// Receive-only access to a [`PriorityChannel`].
+pub struct Receiver<'ch, M, T, K, const N: usize>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    channel: &'ch PriorityChannel<M, T, K, N>,
+}
+
+impl<'ch, M, T, K, const N: usize> Clone for Receiver<'ch, M, T, K, N>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    fn clone(&self) -> Self {
+        Receiver {
+            channel: self.channel,
+        }
+    }
+}
+
+impl<'ch, M, T, K, const N: usize> Copy for Receiver<'ch, M, T, K, N>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+}
+
+impl<'ch, M, T, K, const N: usize> Receiver<'ch, M, T, K, N>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    /// Receive the most urgent value.
+    ///
+    /// See [`PriorityChannel::recv()`].
+    pub fn recv(&self) -> RecvFuture<'_, M, T, K, N> {
+        self.channel.recv()
+    }
+
+    /// Attempt to immediately receive the most urgent value.
+    ///
+    /// See [`PriorityChannel::try_recv()`]
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.channel.try_recv()
+    }
+
+    /// Poll for the most urgent value.
+    ///
+    /// See [`PriorityChannel::poll_recv()`]
+    pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<T> {
+        self.channel.poll_recv(cx)
+    }
+
+    /// Number of values in the channel.
+    pub fn len(&self) -> usize {
+        self.channel.len()
+    }
+
+    /// Returns true if the channel holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.channel.is_empty()
+    }
+}
+
+pub struct RecvFuture<'ch, M, T, K, const N: usize>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    channel: &'ch PriorityChannel<M, T, K, N>,
+}
+
+impl<'ch, M, T, K, const N: usize> Future for RecvFuture<'ch, M, T, K, N>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        self.channel.poll_recv(cx)
+    }
+}
+
+pub struct SendFuture<'ch, M, T, K, const N: usize>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    channel: &'ch PriorityChannel<M, T, K, N>,
+    message: Option<T>,
+}
+
+impl<'ch, M, T, K, const N: usize> Future for SendFuture<'ch, M, T, K, N>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.message.take() {
+            Some(m) => match self.channel.try_send_with_context(m, Some(cx)) {
+                Ok(..) => Poll::Ready(()),
+                Err(TrySendError::Full(m)) => {
+                    self.message = Some(m);
+                    Poll::Pending
+                }
+            },
+            None => panic!("Message cannot be None"),
+        }
+    }
+}
+
+impl<'ch, M, T, K, const N: usize> Unpin for SendFuture<'ch, M, T, K, N>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+}
+
+struct ChannelState<T, K, const N: usize> {
+    queue: BinaryHeap<T, K, N>,
+    receiver_waker: WakerRegistration,
+    senders_waker: WakerRegistration,
+}
+
+impl<T, K, const N: usize> ChannelState<T, K, N>
+where
+    T: Ord,
+    K: Kind,
+{
+    const fn new() -> Self {
+        ChannelState {
+            queue: BinaryHeap::new(),
+            receiver_waker: WakerRegistration::new(),
+            senders_waker: WakerRegistration::new(),
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.queue.len() == N
+    }
+
+    fn try_recv_with_context(&mut self, cx: Option<&mut Context<'_>>) -> Result<T, TryRecvError> {
+        if self.is_full() {
+            self.senders_waker.wake();
+        }
+
+        if let Some(message) = self.queue.pop() {
+            Ok(message)
+        } else {
+            if let Some(cx) = cx {
+                self.receiver_waker.register(cx.waker());
+            }
+            Err(TryRecvError::Empty)
+        }
+    }
+
+    fn try_send_with_context(
+        &mut self,
+        message: T,
+        cx: Option<&mut Context<'_>>,
+    ) -> Result<(), TrySendError<T>> {
+        match self.queue.push(message) {
+            Ok(()) => {
+                self.receiver_waker.wake();
+                Ok(())
+            }
+            Err(message) => {
+                if let Some(cx) = cx {
+                    self.senders_waker.register(cx.waker());
+                }
+                Err(TrySendError::Full(message))
+            }
+        }
+    }
+
+    fn poll_ready_to_send(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.is_full() {
+            self.senders_waker.register(cx.waker());
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    }
+}
+
+/// A bounded channel whose values are received most urgent first.
+///
+/// `K` is [`Max`] to receive the greatest value first, or [`Min`] to receive the smallest one
+/// first. Once the channel holds `N` values, sending waits until a value is received, whatever
+/// its priority.
+pub struct PriorityChannel<M, T, K, const N: usize>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    inner: Mutex<M, RefCell<ChannelState<T, K, N>>>,
+}
+
+impl<M, T, K, const N: usize> PriorityChannel<M, T, K, N>
+where
+    T: Ord,
+    K: Kind,
+    M: RawMutex,
+{
+    /// Establish a new priority channel. For example, to receive the most urgent deadline
+    /// first:
+    ///
+    /// ```
+    /// use embassy::channel::priority_channel::{Min, PriorityChannel};
+    /// use embassy::blocking_mutex::raw::NoopRawMutex;
+    ///
+    /// // Up to 4 (deadline, message) pairs, the earliest deadline received first.
+    /// let channel = PriorityChannel::<NoopRawMutex, (u64, u32), Min, 4>::new();
+    /// ```
+    #[cfg(feature = "nightly")]
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(ChannelState::new())),
+        }
+    }
+
+    /// Establish a new priority channel. For example, to receive the most urgent deadline
+    /// first:
+    ///
+    /// ```
+    /// use embassy::channel::priority_channel::{Min, PriorityChannel};
+    /// use embassy::blocking_mutex::raw::NoopRawMutex;
+    ///
+    /// // Up to 4 (deadline, message) pairs, the earliest deadline received first.
+    /// let channel = PriorityChannel::<NoopRawMutex, (u64, u32), Min, 4>::new();
+    /// ```
+    #[cfg(not(feature = "nightly"))]
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(ChannelState::new())),
+        }
+    }
+
+    fn lock<R>(&self, f: impl FnOnce(&mut ChannelState<T, K, N>) -> R) -> R {
+        self.inner.lock(|rc| f(&mut *rc.borrow_mut()))
+    }
+
+    fn try_send_with_context(
+        &self,
+        m: T,
+        cx: Option<&mut Context<'_>>,
+    ) -> Result<(), TrySendError<T>> {
+        self.lock(|c| c.try_send_with_context(m, cx))
+    }
+
+    /// Get a sender for this channel.
+    pub fn sender(&self) -> Sender<'_, M, T, K, N> {
+        Sender { channel: self }
+    }
+
+    /// Get a receiver for this channel.
+    pub fn receiver(&self) -> Receiver<'_, M, T, K, N> {
+        Receiver { channel: self }
+    }
+
+    /// Send a value, waiting until there is capacity.
+    pub fn send(&self, message: T) -> SendFuture<'_, M, T, K, N> {
+        SendFuture {
+            channel: self,
+            message: Some(message),
+        }
+    }
+
+    /// Attempt to immediately send a message, failing if the channel is full.
+    pub fn try_send(&self, message: T) -> Result<(), TrySendError<T>> {
+        self.try_send_with_context(message, None)
+    }
+
+    /// Receive the most urgent value, waiting until one is sent if the channel is empty.
+    pub fn recv(&self) -> RecvFuture<'_, M, T, K, N> {
+        RecvFuture { channel: self }
+    }
+
+    /// Attempt to immediately receive the most urgent value.
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.lock(|c| c.try_recv_with_context(None))
+    }
+
+    /// Poll for the most urgent value.
+    ///
+    /// When the channel is empty, the waker of `cx` is registered to be woken when a value is sent.
+    pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<T> {
+        match self.lock(|c| c.try_recv_with_context(Some(cx))) {
+            Ok(v) => Poll::Ready(v),
+            Err(TryRecvError::Empty) => Poll::Pending,
+        }
+    }
+
+    /// Poll for capacity to send a value.
+    ///
+    /// When the channel is full, the waker of `cx` is registered to be woken when a value is
+    /// received.
+    pub fn poll_ready_to_send(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.lock(|c| c.poll_ready_to_send(cx))
+    }
+
+    /// Number of values in the channel.
+    pub fn len(&self) -> usize {
+        self.lock(|c| c.queue.len())
+    }
+
+    /// Maximum number of values the channel can hold, `N`.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns true if the channel holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.lock(|c| c.queue.is_empty())
+    }
+
+    /// Returns true if the channel is full: sending would wait.
+    pub fn is_full(&self) -> bool {
+        self.lock(|c| c.is_full())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
+
+    use super::*;
+
+    #[test]
+    fn receives_greatest_first() {
+        let c = PriorityChannel::<NoopRawMutex, u32, Max, 4>::new();
+        assert!(c.try_send(1).is_ok());
+        assert!(c.try_send(3).is_ok());
+        assert!(c.try_send(2).is_ok());
+        assert_eq!(c.try_recv().unwrap(), 3);
+        assert_eq!(c.try_recv().unwrap(), 2);
+        assert_eq!(c.try_recv().unwrap(), 1);
+        assert_eq!(c.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn receives_earliest_deadline_first() {
+        let c = PriorityChannel::<NoopRawMutex, (u64, &str), Min, 4>::new();
+        assert!(c.try_send((300, "bulk")).is_ok());
+        assert!(c.try_send((100, "arp")).is_ok());
+        assert_eq!(c.try_recv().unwrap(), (100, "arp"));
+        assert_eq!(c.try_recv().unwrap(), (300, "bulk"));
+    }
+
+    #[test]
+    fn sending_when_full() {
+        let c = PriorityChannel::<NoopRawMutex, u32, Max, 2>::new();
+        assert!(c.try_send(1).is_ok());
+        assert!(c.try_send(2).is_ok());
+        assert!(c.is_full());
+        assert_eq!(c.try_send(3), Err(TrySendError::Full(3)));
+        assert_eq!(c.len(), 2);
+    }
+
+    #[futures_test::test]
+    async fn send_and_recv() {
+        let c = PriorityChannel::<CriticalSectionRawMutex, u32, Max, 2>::new();
+        c.sender().send(1).await;
+        c.sender().send(5).await;
+        assert_eq!(c.receiver().recv().await, 5);
+        assert_eq!(c.receiver().recv().await, 1);
+    }
+}
@@ -0,0 +1,238 @@
+//! A channel distributing the latest value of something to many receivers.
+//!
+//! A [`Watch`] holds a single value. Sending replaces it, and every receiver is notified
+//! of the change independently of the others: receiving doesn't consume the value, unlike
+//! with a [`Channel`](super::channel::Channel). Receivers that are too slow to see every
+//! value only see the latest one.
+//!
+//! This is a good fit for state that several tasks follow, like the network link state
+//! or the last reading of a sensor.
+
+use core::cell::{RefCell, UnsafeCell};
+use core::future::Future;
+use core::marker::PhantomPinned;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::blocking_mutex::raw::RawMutex;
+use crate::blocking_mutex::Mutex;
+use crate::waitqueue::{Waiter, WaiterQueue};
+
+struct WatchState<T> {
+    value: Option<T>,
+    /// Incremented on every send.
+    version: u32,
+    waiters: WaiterQueue<()>,
+}
+
+/// Latest-value channel, see the [module documentation](self).
+///
+/// ```
+/// use embassy::channel::watch::Watch;
+/// use embassy::blocking_mutex::raw::NoopRawMutex;
+///
+/// let watch = Watch::<NoopRawMutex, u32>::new();
+/// let mut receiver = watch.receiver();
+/// watch.send(1);
+/// watch.send(2);
+/// assert_eq!(receiver.try_changed(), Some(2));
+/// assert_eq!(receiver.try_changed(), None);
+/// ```
+pub struct Watch<M, T>
+where
+    M: RawMutex,
+    T: Clone,
+{
+    inner: Mutex<M, RefCell<WatchState<T>>>,
+}
+
+impl<M, T> Watch<M, T>
+where
+    M: RawMutex,
+    T: Clone,
+{
+    /// Create a new watch, without a value.
+    #[cfg(feature = "nightly")]
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(WatchState {
+                value: None,
+                version: 0,
+                waiters: WaiterQueue::new(),
+            })),
+        }
+    }
+
+    /// Create a new watch, without a value.
+    #[cfg(not(feature = "nightly"))]
+    pub fn new() -> Self {
+        Self {
+            inner: Mutex::new(RefCell::new(WatchState {
+                value: None,
+                version: 0,
+                waiters: WaiterQueue::new(),
+            })),
+        }
+    }
+
+    fn lock<R>(&self, f: impl FnOnce(&mut WatchState<T>) -> R) -> R {
+        self.inner.lock(|rc| f(&mut *rc.borrow_mut()))
+    }
+
+    /// Replace the value, and notify all the receivers.
+    pub fn send(&self, value: T) {
+        self.send_modify(|v| *v = Some(value))
+    }
+
+    /// Modify the value in place, and notify all the receivers.
+    pub fn send_modify(&self, f: impl FnOnce(&mut Option<T>)) {
+        self.lock(|s| {
+            f(&mut s.value);
+            s.version = s.version.wrapping_add(1);
+            while s.waiters.grant_front() {}
+        })
+    }
+
+    /// Remove the value. Receivers are not notified.
+    pub fn clear(&self) {
+        self.lock(|s| s.value = None)
+    }
+
+    /// Get a copy of the current value, if any.
+    pub fn get(&self) -> Option<T> {
+        self.lock(|s| s.value.clone())
+    }
+
+    /// Get a new receiver.
+    ///
+    /// The current value, if any, is considered already seen by the receiver: it's
+    /// notified of the values sent from now on.
+    pub fn receiver(&self) -> WatchReceiver<'_, M, T> {
+        WatchReceiver {
+            watch: self,
+            seen: self.lock(|s| s.version),
+        }
+    }
+}
+
+/// Receiving side of a [`Watch`].
+///
+/// Each receiver keeps track of the last version of the value it has seen, so
+/// receivers don't interfere with each other.
+pub struct WatchReceiver<'a, M, T>
+where
+    M: RawMutex,
+    T: Clone,
+{
+    watch: &'a Watch<M, T>,
+    seen: u32,
+}
+
+impl<'a, M, T> Clone for WatchReceiver<'a, M, T>
+where
+    M: RawMutex,
+    T: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            watch: self.watch,
+            seen: self.seen,
+        }
+    }
+}
+
+impl<'a, M, T> WatchReceiver<'a, M, T>
+where
+    M: RawMutex,
+    T: Clone,
+{
+    /// Get a copy of the current value, if any, and mark it as seen.
+    pub fn get(&mut self) -> Option<T> {
+        self.watch.lock(|s| {
+            self.seen = s.version;
+            s.value.clone()
+        })
+    }
+
+    /// Returns true if a value was sent since this receiver last saw one.
+    pub fn has_changed(&self) -> bool {
+        self.watch.lock(|s| s.version != self.seen)
+    }
+
+    /// Get the value if it changed since this receiver last saw one, without waiting.
+    ///
+    /// Returns `None` if it didn't change, or if it was changed to `None`
+    /// with [`Watch::send_modify`].
+    pub fn try_changed(&mut self) -> Option<T> {
+        self.watch.lock(|s| {
+            if s.version == self.seen {
+                return None;
+            }
+            self.seen = s.version;
+            s.value.clone()
+        })
+    }
+
+    /// Wait for the value to change, and return a copy of it.
+    ///
+    /// If it changed since this receiver last saw it, this returns immediately.
+    pub async fn changed(&mut self) -> Option<T> {
+        ChangedFuture {
+            receiver: self,
+            waiter: UnsafeCell::new(Waiter::new(())),
+            _pinned: PhantomPinned,
+        }
+        .await
+    }
+}
+
+struct ChangedFuture<'r, 'a, M, T>
+where
+    M: RawMutex,
+    T: Clone,
+{
+    receiver: &'r mut WatchReceiver<'a, M, T>,
+    waiter: UnsafeCell<Waiter<()>>,
+    _pinned: PhantomPinned,
+}
+
+impl<'r, 'a, M, T> Future for ChangedFuture<'r, 'a, M, T>
+where
+    M: RawMutex,
+    T: Clone,
+{
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Safety: the future is pinned, so the waiter doesn't move while queued.
+        let this = unsafe { self.get_unchecked_mut() };
+        let waiter = this.waiter.get();
+        let seen = &mut this.receiver.seen;
+
+        this.receiver.watch.lock(|s| {
+            if s.version != *seen {
+                *seen = s.version;
+                return Poll::Ready(s.value.clone());
+            }
+            let w = unsafe { &mut *waiter };
+            if !w.is_queued() {
+                unsafe { s.waiters.push(waiter) };
+            }
+            w.set_waker(cx.waker());
+            Poll::Pending
+        })
+    }
+}
+
+impl<'r, 'a, M, T> Drop for ChangedFuture<'r, 'a, M, T>
+where
+    M: RawMutex,
+    T: Clone,
+{
+    fn drop(&mut self) {
+        let waiter = self.waiter.get();
+        self.receiver
+            .watch
+            .lock(|s| unsafe { s.waiters.remove(waiter) })
+    }
+}
@@ -1,7 +1,7 @@
 use core::marker::PhantomData;
 use core::ptr;
 
-use super::{raw, Spawner};
+use super::{raw, SendSpawner, Spawner};
 use crate::interrupt::{Interrupt, InterruptExt};
 
 /// Thread mode executor, using WFE/SEV.
@@ -28,6 +28,13 @@ impl Executor {
         }
     }
 
+    /// Set the longest time a single poll of a task may take, see
+    /// [`raw::Executor::set_poll_budget`].
+    #[cfg(all(feature = "executor-poll-budget", feature = "time"))]
+    pub fn set_poll_budget(&self, budget: crate::time::Duration) {
+        self.inner.set_poll_budget(budget)
+    }
+
     /// Run the executor.
     ///
     /// The `init` closure is called with a [`Spawner`] that spawns tasks on
@@ -87,7 +94,8 @@ fn pend_by_number(n: u16) {
 /// If this is not the case, you may use an interrupt from any unused peripheral.
 ///
 /// It is somewhat more complex to use, it's recommended to use the thread-mode
-/// [`Executor`] instead, if it works for your use case.
+/// [`Executor`] instead, if it works for your use case. To set up several interrupt
+/// executors at once, see [`start_interrupt_executors!`](crate::start_interrupt_executors).
 pub struct InterruptExecutor<I: Interrupt> {
     irq: I,
     inner: raw::Executor,
@@ -105,15 +113,27 @@ impl<I: Interrupt> InterruptExecutor<I> {
         }
     }
 
+    /// Set the longest time a single poll of a task may take, see
+    /// [`raw::Executor::set_poll_budget`].
+    ///
+    /// Call this before [`start`](Self::start). Tasks running in the executor can change it
+    /// later with [`Spawner::set_poll_budget`].
+    #[cfg(all(feature = "executor-poll-budget", feature = "time"))]
+    pub fn set_poll_budget(&mut self, budget: crate::time::Duration) {
+        self.inner.set_poll_budget(budget)
+    }
+
     /// Start the executor.
     ///
     /// The `init` closure is called from interrupt mode, with a [`Spawner`] that spawns tasks on
     /// this executor. Use it to spawn the initial task(s). After `init` returns,
     /// the interrupt is configured so that the executor starts running the tasks.
-    /// Once the executor is started, `start` returns.
+    /// Once the executor is started, `start` returns a [`SendSpawner`] for it.
     ///
-    /// To spawn more tasks later, you may keep copies of the [`Spawner`] (it is `Copy`),
-    /// for example by passing it as an argument to the initial tasks.
+    /// The executor runs in interrupt mode, so anything spawned onto it from another
+    /// priority level is effectively sent to another thread. This is why `init` must be `Send`,
+    /// and why tasks spawned with the returned [`SendSpawner`] must be `Send` too. Tasks
+    /// running in the executor can still spawn non-`Send` tasks with their own [`Spawner`].
     ///
     /// This function requires `&'static mut self`. This means you have to store the
     /// Executor instance in a place where it'll live forever and grants you mutable
@@ -122,7 +142,7 @@ impl<I: Interrupt> InterruptExecutor<I> {
     /// - a [Forever](crate::util::Forever) (safe)
     /// - a `static mut` (unsafe)
     /// - a local variable in a function you know never returns (like `fn main() -> !`), upgrading its lifetime with `transmute`. (unsafe)
-    pub fn start(&'static mut self, init: impl FnOnce(Spawner) + Send) {
+    pub fn start(&'static mut self, init: impl FnOnce(Spawner) + Send) -> SendSpawner {
         self.irq.disable();
 
         init(self.inner.spawner());
@@ -133,5 +153,54 @@ impl<I: Interrupt> InterruptExecutor<I> {
         });
         self.irq.set_handler_context(&self.inner as *const _ as _);
         self.irq.enable();
+
+        self.inner.spawner().make_send()
     }
+
+    /// Set the interrupt priority, then [`start`](Self::start) the executor.
+    ///
+    /// Tasks in this executor preempt tasks of executors running at a lower priority
+    /// (and thread mode), and are preempted by tasks of executors running at a higher priority.
+    pub fn start_with_priority(
+        &'static mut self,
+        priority: I::Priority,
+        init: impl FnOnce(Spawner) + Send,
+    ) -> SendSpawner {
+        self.irq.set_priority(priority);
+        self.start(init)
+    }
+}
+
+/// Start one [`InterruptExecutor`](crate::executor::InterruptExecutor) per listed interrupt.
+///
+/// Each entry is the name of an unused interrupt, as in `interrupt::take!`, and the
+/// priority to run its executor at. The HAL's `interrupt` module must be in scope.
+/// The executors are stored in hidden `static`s, and a tuple with one
+/// [`SendSpawner`](crate::executor::SendSpawner) per executor is returned, in order.
+///
+/// Since the spawners are `SendSpawner`s, only `Send` tasks can be spawned onto the
+/// executors from the caller's context.
+///
+/// Each invocation can only run once, and panics if any of the interrupts was already taken.
+///
+/// ```ignore
+/// let (high, med) = embassy::start_interrupt_executors!(
+///     SWI1_EGU1 => interrupt::Priority::P6,
+///     SWI0_EGU0 => interrupt::Priority::P7,
+/// );
+/// unwrap!(high.spawn(run_high()));
+/// unwrap!(med.spawn(run_med()));
+/// ```
+#[macro_export]
+macro_rules! start_interrupt_executors {
+    ($($irq:ident => $priority:expr),+ $(,)?) => {
+        ($({
+            static EXECUTOR: $crate::util::Forever<$crate::executor::InterruptExecutor<interrupt::$irq>> =
+                $crate::util::Forever::new();
+            let irq = $crate::interrupt::take!($irq);
+            EXECUTOR
+                .put($crate::executor::InterruptExecutor::new(irq))
+                .start_with_priority($priority, |_| {})
+        },)+)
+    };
 }
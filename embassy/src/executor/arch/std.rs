@@ -27,6 +27,13 @@ impl Executor {
         }
     }
 
+    /// Set the longest time a single poll of a task may take, see
+    /// [`raw::Executor::set_poll_budget`].
+    #[cfg(all(feature = "executor-poll-budget", feature = "time"))]
+    pub fn set_poll_budget(&self, budget: crate::time::Duration) {
+        self.inner.set_poll_budget(budget)
+    }
+
     /// Run the executor.
     ///
     /// The `init` closure is called with a [`Spawner`] that spawns tasks on
@@ -34,3 +34,10 @@ pub mod raw;
 
 mod spawner;
 pub use spawner::*;
+
+#[cfg(feature = "executor-task-local")]
+mod task_local;
+#[cfg(feature = "executor-task-local")]
+pub use task_local::*;
+
+pub use crate::util::yield_now;
@@ -0,0 +1,129 @@
+use atomic_polyfill::{AtomicPtr, AtomicU32, AtomicUsize, Ordering};
+use core::ptr;
+use core::ptr::NonNull;
+
+use super::{Executor, TaskHeader};
+
+/// Executor-wide counters, see [`Executor::metrics`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ExecutorMetrics {
+    /// Number of calls to [`Executor::poll`].
+    pub poll_loops: u32,
+    /// Number of task polls, across all tasks.
+    pub task_polls: u32,
+    /// Largest number of tasks found in the run queue by a single `poll`.
+    pub max_run_queue_depth: u32,
+}
+
+/// Per-task counters, see [`Executor::for_each_task_metrics`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TaskMetrics {
+    /// Address of the task's poll function.
+    ///
+    /// There is one poll function per task function, so look this address up
+    /// in the firmware symbols (with `addr2line` or `nm`) to find out which
+    /// task it is.
+    pub id: usize,
+    /// Number of times the task was polled.
+    pub polls: u32,
+    /// Number of times the task was woken, including wakes while it was
+    /// already queued. A task with about as many wakes as polls, and a lot
+    /// of both, is waking itself in a busy loop.
+    pub wakes: u32,
+}
+
+pub(crate) struct ExecutorCounters {
+    poll_loops: AtomicU32,
+    task_polls: AtomicU32,
+    max_run_queue_depth: AtomicU32,
+    /// Intrusive list of all tasks spawned in this executor, through `TaskCounters::next`.
+    tasks: AtomicPtr<TaskHeader>,
+}
+
+impl ExecutorCounters {
+    pub const fn new() -> Self {
+        Self {
+            poll_loops: AtomicU32::new(0),
+            task_polls: AtomicU32::new(0),
+            max_run_queue_depth: AtomicU32::new(0),
+            tasks: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    pub fn on_poll_loop(&self, run_queue_depth: u32) {
+        // Only `Executor::poll` writes these, and it's never called reentrantly.
+        self.poll_loops.fetch_add(1, Ordering::Relaxed);
+        if run_queue_depth > self.max_run_queue_depth.load(Ordering::Relaxed) {
+            self.max_run_queue_depth
+                .store(run_queue_depth, Ordering::Relaxed);
+        }
+    }
+
+    pub fn on_task_poll(&self, task: &TaskHeader) {
+        self.task_polls.fetch_add(1, Ordering::Relaxed);
+        task.counters.polls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Add a task to the list of tasks of `executor`.
+    ///
+    /// A task storage is only listed in the first executor it's spawned on.
+    pub fn register(&self, executor: &Executor, task: &TaskHeader) {
+        critical_section::with(|_| {
+            let listed_in = task.counters.listed_in.load(Ordering::Relaxed);
+            if !listed_in.is_null() {
+                return;
+            }
+            task.counters
+                .listed_in
+                .store(executor as *const _ as *mut _, Ordering::Relaxed);
+            let head = self.tasks.load(Ordering::Relaxed);
+            task.counters.next.store(head, Ordering::Relaxed);
+            self.tasks
+                .store(task as *const _ as *mut _, Ordering::Relaxed);
+        })
+    }
+
+    pub fn get(&self) -> ExecutorMetrics {
+        ExecutorMetrics {
+            poll_loops: self.poll_loops.load(Ordering::Relaxed),
+            task_polls: self.task_polls.load(Ordering::Relaxed),
+            max_run_queue_depth: self.max_run_queue_depth.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn for_each_task(&self, mut f: impl FnMut(TaskMetrics)) {
+        let mut ptr = self.tasks.load(Ordering::Acquire);
+        while let Some(task) = NonNull::new(ptr) {
+            // Tasks are never removed from the list, and their storage lives forever.
+            let task = unsafe { task.as_ref() };
+            f(TaskMetrics {
+                id: task.counters.poll_fn_addr.load(Ordering::Relaxed),
+                polls: task.counters.polls.load(Ordering::Relaxed),
+                wakes: task.counters.wakes.load(Ordering::Relaxed),
+            });
+            ptr = task.counters.next.load(Ordering::Relaxed);
+        }
+    }
+}
+
+pub(crate) struct TaskCounters {
+    pub(crate) polls: AtomicU32,
+    pub(crate) wakes: AtomicU32,
+    pub(crate) poll_fn_addr: AtomicUsize,
+    listed_in: AtomicPtr<Executor>,
+    next: AtomicPtr<TaskHeader>,
+}
+
+impl TaskCounters {
+    pub const fn new() -> Self {
+        Self {
+            polls: AtomicU32::new(0),
+            wakes: AtomicU32::new(0),
+            poll_fn_addr: AtomicUsize::new(0),
+            listed_in: AtomicPtr::new(ptr::null_mut()),
+            next: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+}
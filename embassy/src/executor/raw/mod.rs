@@ -7,6 +7,8 @@
 //! Using this module requires respecting subtle safety contracts. If you can, prefer using the safe
 //! executor wrappers in [`crate::executor`] and the [`crate::task`] macro, which are fully safe.
 
+#[cfg(feature = "executor-metrics")]
+mod metrics;
 mod run_queue;
 #[cfg(feature = "time")]
 mod timer_queue;
@@ -14,7 +16,7 @@ pub(crate) mod util;
 mod waker;
 
 use atomic_polyfill::{AtomicU32, Ordering};
-use core::cell::Cell;
+use core::cell::{Cell, RefCell};
 use core::future::Future;
 use core::pin::Pin;
 use core::ptr::NonNull;
@@ -24,12 +26,18 @@ use critical_section::CriticalSection;
 
 use self::run_queue::{RunQueue, RunQueueItem};
 use self::util::UninitCell;
-use super::SpawnToken;
+use super::{PoolStatus, SpawnToken};
+use crate::blocking_mutex::CriticalSectionMutex;
 #[cfg(feature = "time")]
 use crate::time::driver::{self, AlarmHandle};
+#[cfg(all(feature = "executor-poll-budget", feature = "time"))]
+use crate::time::Duration;
 #[cfg(feature = "time")]
 use crate::time::Instant;
+use crate::waitqueue::WaiterQueue;
 
+#[cfg(feature = "executor-metrics")]
+pub use self::metrics::{ExecutorMetrics, TaskMetrics};
 pub use self::waker::task_from_waker;
 
 /// Task is spawned (has a future)
@@ -54,8 +62,17 @@ pub struct TaskHeader {
     pub(crate) expires_at: Cell<Instant>,
     #[cfg(feature = "time")]
     pub(crate) timer_queue_item: timer_queue::TimerQueueItem,
+
+    #[cfg(feature = "executor-metrics")]
+    pub(crate) counters: metrics::TaskCounters,
+
+    #[cfg(feature = "executor-task-local")]
+    pub(crate) locals: [Cell<*const ()>; super::TASK_LOCAL_SLOTS],
 }
 
+#[cfg(feature = "executor-task-local")]
+const NO_LOCAL: Cell<*const ()> = Cell::new(ptr::null());
+
 impl TaskHeader {
     #[cfg(feature = "nightly")]
     pub(crate) const fn new() -> Self {
@@ -69,6 +86,12 @@ impl TaskHeader {
             expires_at: Cell::new(Instant::from_ticks(0)),
             #[cfg(feature = "time")]
             timer_queue_item: timer_queue::TimerQueueItem::new(),
+
+            #[cfg(feature = "executor-metrics")]
+            counters: metrics::TaskCounters::new(),
+
+            #[cfg(feature = "executor-task-local")]
+            locals: [NO_LOCAL; super::TASK_LOCAL_SLOTS],
         }
     }
 
@@ -84,6 +107,12 @@ impl TaskHeader {
             expires_at: Cell::new(Instant::from_ticks(0)),
             #[cfg(feature = "time")]
             timer_queue_item: timer_queue::TimerQueueItem::new(),
+
+            #[cfg(feature = "executor-metrics")]
+            counters: metrics::TaskCounters::new(),
+
+            #[cfg(feature = "executor-task-local")]
+            locals: [NO_LOCAL; super::TASK_LOCAL_SLOTS],
         }
     }
 
@@ -91,6 +120,11 @@ impl TaskHeader {
         critical_section::with(|cs| {
             let state = self.state.load(Ordering::Relaxed);
 
+            #[cfg(feature = "executor-metrics")]
+            if state & STATE_SPAWNED != 0 {
+                self.counters.wakes.fetch_add(1, Ordering::Relaxed);
+            }
+
             // If already scheduled, or if not started,
             if (state & STATE_RUN_QUEUED != 0) || (state & STATE_SPAWNED == 0) {
                 return;
@@ -154,13 +188,48 @@ impl<F: Future + 'static> TaskStorage<F> {
     /// This will loop over the pool and spawn the task in the first storage that
     /// is currently free. If none is free,
     pub fn spawn_pool(pool: &'static [Self], future: impl FnOnce() -> F) -> SpawnToken<F> {
-        for task in pool {
+        Self::spawn_named_pool(None, pool, pool.len(), future)
+    }
+
+    /// Try to spawn a task in a pool, like [`Self::spawn_pool()`].
+    ///
+    /// Only the first `limit` storages of the pool are used, so the number of instances
+    /// that can run at the same time can be decided at runtime, up to `pool.len()`.
+    ///
+    /// `name` is reported in the [`PoolStatus`] of the token if spawning fails.
+    pub fn spawn_named_pool(
+        name: Option<&'static str>,
+        pool: &'static [Self],
+        limit: usize,
+        future: impl FnOnce() -> F,
+    ) -> SpawnToken<F> {
+        for task in pool.iter().take(limit) {
             if task.spawn_allocate() {
                 return unsafe { task.spawn_initialize(future) };
             }
         }
 
-        SpawnToken::new_failed()
+        SpawnToken::new_failed(Self::pool_status(name, pool, limit))
+    }
+
+    /// Get the number of tasks of `pool` that are spawned and not finished yet.
+    ///
+    /// `limit` is the number of storages of the pool in use, as passed to
+    /// [`Self::spawn_named_pool()`].
+    pub fn pool_status(
+        name: Option<&'static str>,
+        pool: &'static [Self],
+        limit: usize,
+    ) -> PoolStatus {
+        let in_use = pool
+            .iter()
+            .filter(|task| task.raw.state.load(Ordering::Relaxed) & STATE_SPAWNED != 0)
+            .count();
+        PoolStatus {
+            name,
+            size: limit.min(pool.len()),
+            in_use,
+        }
     }
 
     /// Try to spawn the task.
@@ -180,7 +249,11 @@ impl<F: Future + 'static> TaskStorage<F> {
         if self.spawn_allocate() {
             unsafe { self.spawn_initialize(future) }
         } else {
-            SpawnToken::new_failed()
+            SpawnToken::new_failed(PoolStatus {
+                name: None,
+                size: 1,
+                in_use: 1,
+            })
         }
     }
 
@@ -195,6 +268,15 @@ impl<F: Future + 'static> TaskStorage<F> {
     unsafe fn spawn_initialize(&'static self, future: impl FnOnce() -> F) -> SpawnToken<F> {
         // Initialize the task
         self.raw.poll_fn.write(Self::poll);
+        #[cfg(feature = "executor-metrics")]
+        self.raw
+            .counters
+            .poll_fn_addr
+            .store(Self::poll as usize, Ordering::Relaxed);
+        #[cfg(feature = "executor-task-local")]
+        for local in &self.raw.locals {
+            local.set(ptr::null());
+        }
         self.future.write(future());
 
         SpawnToken::new(NonNull::new_unchecked(&self.raw as *const TaskHeader as _))
@@ -250,8 +332,20 @@ pub struct Executor {
     pub(crate) timer_queue: timer_queue::TimerQueue,
     #[cfg(feature = "time")]
     alarm: AlarmHandle,
+
+    #[cfg(feature = "executor-metrics")]
+    counters: metrics::ExecutorCounters,
+
+    #[cfg(all(feature = "executor-poll-budget", feature = "time"))]
+    poll_budget: Cell<Duration>,
+
+    exit_waiters: CriticalSectionMutex<RefCell<WaiterQueue<()>>>,
 }
 
+/// Default for [`Executor::set_poll_budget`].
+#[cfg(all(feature = "executor-poll-budget", feature = "time"))]
+pub const DEFAULT_POLL_BUDGET: Duration = Duration::from_millis(10);
+
 impl Executor {
     /// Create a new executor.
     ///
@@ -274,9 +368,29 @@ impl Executor {
             timer_queue: timer_queue::TimerQueue::new(),
             #[cfg(feature = "time")]
             alarm,
+
+            #[cfg(feature = "executor-metrics")]
+            counters: metrics::ExecutorCounters::new(),
+
+            #[cfg(all(feature = "executor-poll-budget", feature = "time"))]
+            poll_budget: Cell::new(DEFAULT_POLL_BUDGET),
+
+            exit_waiters: CriticalSectionMutex::new(RefCell::new(WaiterQueue::new())),
         }
     }
 
+    /// Set the longest time a single poll of a task may take. Longer polls are logged with
+    /// the address of the poll function of the task: look it up in the firmware symbols
+    /// (with `addr2line` or `nm`) to find out which task it is.
+    ///
+    /// A task that takes long to poll is running blocking code, a flash erase or a busy
+    /// loop for example: all the other tasks of the executor wait for it. Only the polls are
+    /// measured, so the time a task spends awaiting doesn't count.
+    #[cfg(all(feature = "executor-poll-budget", feature = "time"))]
+    pub fn set_poll_budget(&self, budget: Duration) {
+        self.poll_budget.set(budget);
+    }
+
     /// Enqueue a task in the task queue
     ///
     /// # Safety
@@ -303,6 +417,9 @@ impl Executor {
         let task = task.as_ref();
         task.executor.set(self);
 
+        #[cfg(feature = "executor-metrics")]
+        self.counters.register(self, task);
+
         critical_section::with(|cs| {
             self.enqueue(cs, task as *const _ as _);
         })
@@ -331,9 +448,15 @@ impl Executor {
             p.as_ref().enqueue();
         });
 
+        #[cfg(feature = "executor-metrics")]
+        let run_queue_depth = Cell::new(0);
+
         self.run_queue.dequeue_all(|p| {
             let task = p.as_ref();
 
+            #[cfg(feature = "executor-metrics")]
+            run_queue_depth.set(run_queue_depth.get() + 1);
+
             #[cfg(feature = "time")]
             task.expires_at.set(Instant::MAX);
 
@@ -348,13 +471,31 @@ impl Executor {
             }
 
             // Run the task
-            task.poll_fn.read()(p as _);
+            #[cfg(feature = "executor-metrics")]
+            self.counters.on_task_poll(task);
+            let poll_fn = task.poll_fn.read();
+            #[cfg(all(feature = "executor-poll-budget", feature = "time"))]
+            let start = Instant::now();
+            poll_fn(p as _);
+            #[cfg(all(feature = "executor-poll-budget", feature = "time"))]
+            self.check_poll_budget(poll_fn as usize, start);
+
+            if task.state.load(Ordering::Relaxed) & STATE_SPAWNED == 0 {
+                // The slot may be in the pool of any of the waiters, so wake them all.
+                self.exit_waiters.lock(|q| {
+                    let mut q = q.borrow_mut();
+                    while q.grant_front() {}
+                });
+            }
 
             // Enqueue or update into timer_queue
             #[cfg(feature = "time")]
             self.timer_queue.update(p);
         });
 
+        #[cfg(feature = "executor-metrics")]
+        self.counters.on_poll_loop(run_queue_depth.get());
+
         #[cfg(feature = "time")]
         {
             // If this is already in the past, set_alarm will immediately trigger the alarm.
@@ -365,6 +506,44 @@ impl Executor {
         }
     }
 
+    #[cfg(all(feature = "executor-poll-budget", feature = "time"))]
+    fn check_poll_budget(&self, task_id: usize, start: Instant) {
+        let elapsed = Instant::now() - start;
+        let budget = self.poll_budget.get();
+        if elapsed > budget {
+            warn!(
+                "task {} polled for {} us, over the {} us budget: it's blocking the executor",
+                task_id,
+                elapsed.as_micros(),
+                budget.as_micros()
+            );
+        }
+    }
+
+    /// Tasks waiting for a task of this executor to finish.
+    pub(crate) fn exit_waiters(&self) -> &CriticalSectionMutex<RefCell<WaiterQueue<()>>> {
+        &self.exit_waiters
+    }
+
+    /// Get the executor-wide counters.
+    #[cfg(feature = "executor-metrics")]
+    pub fn metrics(&self) -> ExecutorMetrics {
+        self.counters.get()
+    }
+
+    /// Call `f` with the counters of each task spawned in this executor so far.
+    #[cfg(feature = "executor-metrics")]
+    pub fn for_each_task_metrics(&self, f: impl FnMut(TaskMetrics)) {
+        self.counters.for_each_task(f)
+    }
+
+    /// Log all the counters with defmt.
+    #[cfg(all(feature = "executor-metrics", feature = "defmt"))]
+    pub fn dump_metrics(&self) {
+        defmt::info!("executor: {}", self.metrics());
+        self.for_each_task_metrics(|m| defmt::info!("  task: {}", m));
+    }
+
     /// Get a spawner that spawns tasks in this executor.
     ///
     /// It is OK to call this method multiple times to obtain multiple
@@ -1,8 +1,13 @@
-use core::marker::PhantomData;
+use core::cell::UnsafeCell;
+use core::future::Future;
+use core::marker::{PhantomData, PhantomPinned};
 use core::mem;
+use core::pin::Pin;
 use core::ptr::NonNull;
+use core::task::{Context, Poll};
 
 use super::raw;
+use crate::waitqueue::Waiter;
 
 /// Token to spawn a newly-created task in an executor.
 ///
@@ -16,24 +21,43 @@ use super::raw;
 /// Once you've invoked a task function and obtained a SpawnToken, you *must* spawn it.
 #[must_use = "Calling a task function does nothing on its own. You must spawn the returned SpawnToken, typically with Spawner::spawn()"]
 pub struct SpawnToken<F> {
-    raw_task: Option<NonNull<raw::TaskHeader>>,
+    raw_task: Result<NonNull<raw::TaskHeader>, PoolStatus>,
     phantom: PhantomData<*mut F>,
 }
 
 impl<F> SpawnToken<F> {
     pub(crate) unsafe fn new(raw_task: NonNull<raw::TaskHeader>) -> Self {
         Self {
-            raw_task: Some(raw_task),
+            raw_task: Ok(raw_task),
             phantom: PhantomData,
         }
     }
 
-    pub(crate) fn new_failed() -> Self {
+    pub(crate) fn new_failed(status: PoolStatus) -> Self {
         Self {
-            raw_task: None,
+            raw_task: Err(status),
             phantom: PhantomData,
         }
     }
+
+    /// If the task couldn't be allocated, the state of its pool at that time.
+    ///
+    /// Spawning this token fails with [`SpawnError::Busy`].
+    pub fn pool_status(&self) -> Option<PoolStatus> {
+        self.raw_task.err()
+    }
+}
+
+/// State of the pool of a task, when spawning it failed.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PoolStatus {
+    /// Name of the task function, for tasks marked with `#[embassy::task]`.
+    pub name: Option<&'static str>,
+    /// Number of instances of the task that can run at the same time.
+    pub size: usize,
+    /// Number of instances running.
+    pub in_use: usize,
 }
 
 impl<F> Drop for SpawnToken<F> {
@@ -52,9 +76,50 @@ pub enum SpawnError {
     /// By default, a task marked with `#[embassy::task]` can only have one instance
     /// running at a time. You may allow multiple instances to run in parallel with
     /// `#[embassy::task(pool_size = 4)]`, at the cost of higher RAM usage.
+    ///
+    /// To pick the number of instances at runtime, add `pool_limit = "LIMIT"`, where
+    /// `LIMIT` is a `static AtomicUsize`. Up to `pool_size` instances then run at the same
+    /// time, and fewer if `LIMIT` is lower.
     Busy,
 }
 
+/// Future waiting for a task of an executor to finish.
+struct TaskExitFuture {
+    executor: &'static raw::Executor,
+    waiter: UnsafeCell<Waiter<()>>,
+    _pinned: PhantomPinned,
+}
+
+impl Future for TaskExitFuture {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // Safety: the future is pinned, so the waiter doesn't move while queued.
+        let waiter = self.waiter.get();
+        self.executor.exit_waiters().lock(|q| {
+            let mut q = q.borrow_mut();
+            let w = unsafe { &mut *waiter };
+            if w.is_granted() {
+                return Poll::Ready(());
+            }
+            if !w.is_queued() {
+                unsafe { q.push(waiter) };
+            }
+            w.set_waker(cx.waker());
+            Poll::Pending
+        })
+    }
+}
+
+impl Drop for TaskExitFuture {
+    fn drop(&mut self) {
+        let waiter = self.waiter.get();
+        self.executor
+            .exit_waiters()
+            .lock(|q| unsafe { q.borrow_mut().remove(waiter) })
+    }
+}
+
 /// Handle to spawn tasks into an executor.
 ///
 /// This Spawner can spawn any task (Send and non-Send ones), but it can
@@ -83,11 +148,48 @@ impl Spawner {
         mem::forget(token);
 
         match task {
-            Some(task) => {
+            Ok(task) => {
                 unsafe { self.executor.spawn(task) };
                 Ok(())
             }
-            None => Err(SpawnError::Busy),
+            Err(status) => {
+                warn!(
+                    "spawn failed: task {:?} has {} of {} instances running",
+                    status.name, status.in_use, status.size
+                );
+                Err(SpawnError::Busy)
+            }
+        }
+    }
+
+    /// Spawn a task into an executor, waiting for an instance of it to finish if too many
+    /// are already running.
+    ///
+    /// `f` calls the task function, like `|| handler(socket_id)`. It's called again each
+    /// time a task of this executor finishes, until spawning succeeds: the arguments it
+    /// passes to the task function must be copied or cloned. Only tasks finishing in this
+    /// executor are waited for, so the task must run in it too.
+    ///
+    /// Any number of tasks may wait at the same time.
+    pub async fn spawn_or_wait<F>(&self, mut f: impl FnMut() -> SpawnToken<F>) {
+        loop {
+            let token = f();
+            let task = token.raw_task;
+            mem::forget(token);
+
+            if let Ok(task) = task {
+                unsafe { self.executor.spawn(task) };
+                return;
+            }
+
+            // The executor polls one task at a time, so none can finish between the
+            // failed attempt and the start of the wait.
+            TaskExitFuture {
+                executor: self.executor,
+                waiter: UnsafeCell::new(Waiter::new(())),
+                _pinned: PhantomPinned,
+            }
+            .await
         }
     }
 
@@ -99,6 +201,31 @@ impl Spawner {
         unwrap!(self.spawn(token));
     }
 
+    /// Get the counters of the executor, see [`raw::Executor::metrics`].
+    #[cfg(feature = "executor-metrics")]
+    pub fn metrics(&self) -> raw::ExecutorMetrics {
+        self.executor.metrics()
+    }
+
+    /// Call `f` with the counters of each task spawned in the executor so far.
+    #[cfg(feature = "executor-metrics")]
+    pub fn for_each_task_metrics(&self, f: impl FnMut(raw::TaskMetrics)) {
+        self.executor.for_each_task_metrics(f)
+    }
+
+    /// Log the counters of the executor and of all its tasks with defmt.
+    #[cfg(all(feature = "executor-metrics", feature = "defmt"))]
+    pub fn dump_metrics(&self) {
+        self.executor.dump_metrics()
+    }
+
+    /// Set the longest time a single poll of a task may take, see
+    /// [`raw::Executor::set_poll_budget`].
+    #[cfg(all(feature = "executor-poll-budget", feature = "time"))]
+    pub fn set_poll_budget(&self, budget: crate::time::Duration) {
+        self.executor.set_poll_budget(budget)
+    }
+
     /// Convert this Spawner to a SendSpawner. This allows you to send the
     /// spawner to other threads, but the spawner loses the ability to spawn
     /// non-Send tasks.
@@ -135,11 +262,17 @@ impl SendSpawner {
         mem::forget(token);
 
         match header {
-            Some(header) => {
+            Ok(header) => {
                 unsafe { self.executor.spawn(header) };
                 Ok(())
             }
-            None => Err(SpawnError::Busy),
+            Err(status) => {
+                warn!(
+                    "spawn failed: task {:?} has {} of {} instances running",
+                    status.name, status.in_use, status.size
+                );
+                Err(SpawnError::Busy)
+            }
         }
     }
 }
@@ -0,0 +1,91 @@
+use atomic_polyfill::{AtomicUsize, Ordering};
+use core::marker::PhantomData;
+use core::ptr::{self, NonNull};
+use futures::future::poll_fn;
+
+use super::raw::{task_from_waker, TaskHeader};
+
+/// Number of task-local keys a program can use. Each task reserves a word for each of them.
+pub const TASK_LOCAL_SLOTS: usize = 4;
+
+/// Slots handed out to keys so far.
+static NEXT_SLOT: AtomicUsize = AtomicUsize::new(0);
+
+/// Key of a task-local value.
+///
+/// Each task has its own value for the key, set with [`set`](Self::set). This lets
+/// middleware keep per-task context, like the request a log line belongs to or the session
+/// of a connection, without passing it through every function.
+///
+/// Keys are `static`s. The value of a task is a `&'static T`, and it's cleared when the
+/// task is spawned:
+///
+/// ```ignore
+/// static REQUEST: TaskLocal<Request> = TaskLocal::new();
+///
+/// REQUEST.set(Some(request)).await;
+/// // ... later, in the same task:
+/// if let Some(request) = REQUEST.get().await {
+///     info!("handling request {}", request.id);
+/// }
+/// ```
+///
+/// The accessors are async only to get the current task: they complete immediately. They
+/// panic if not called from a task of the embassy executor, or if more than
+/// [`TASK_LOCAL_SLOTS`] keys are used.
+pub struct TaskLocal<T: 'static> {
+    /// Slot of the key plus one, or 0 until it's used for the first time.
+    slot: AtomicUsize,
+    phantom: PhantomData<&'static T>,
+}
+
+impl<T: 'static> TaskLocal<T> {
+    /// Create a new key.
+    pub const fn new() -> Self {
+        Self {
+            slot: AtomicUsize::new(0),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Set the value of the current task.
+    pub async fn set(&'static self, value: Option<&'static T>) {
+        let slot = self.slot();
+        let value = value.map_or(ptr::null(), |v| v as *const T as *const ());
+        let task = current_task().await;
+        unsafe { task.as_ref().locals[slot].set(value) }
+    }
+
+    /// Get the value of the current task.
+    pub async fn get(&'static self) -> Option<&'static T> {
+        let slot = self.slot();
+        let task = current_task().await;
+        let value = unsafe { task.as_ref().locals[slot].get() };
+        unsafe { (value as *const T).as_ref() }
+    }
+
+    fn slot(&self) -> usize {
+        let slot = self.slot.load(Ordering::Acquire);
+        if slot != 0 {
+            return slot - 1;
+        }
+
+        critical_section::with(|_| {
+            // Another task may have allocated it in the meantime.
+            let slot = self.slot.load(Ordering::Acquire);
+            if slot != 0 {
+                return slot - 1;
+            }
+            let slot = NEXT_SLOT.load(Ordering::Relaxed);
+            assert!(slot < TASK_LOCAL_SLOTS, "too many task-local keys");
+            NEXT_SLOT.store(slot + 1, Ordering::Relaxed);
+            self.slot.store(slot + 1, Ordering::Release);
+            slot
+        })
+    }
+}
+
+/// Get the task being polled.
+async fn current_task() -> NonNull<TaskHeader> {
+    poll_fn(|cx| core::task::Poll::Ready(unsafe { task_from_waker(cx.waker()) })).await
+}
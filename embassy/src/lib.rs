@@ -5,9 +5,10 @@
 )]
 #![allow(clippy::new_without_default)]
 
-// This mod MUST go first, so that the others see its macros.
-pub(crate) mod fmt;
+#[macro_use]
+extern crate embassy_fmt;
 
+pub mod barrier;
 pub mod blocking_mutex;
 pub mod channel;
 pub mod executor;
@@ -15,11 +16,16 @@ pub mod executor;
 pub mod interrupt;
 pub mod io;
 pub mod mutex;
+pub mod rwlock;
+pub mod semaphore;
 #[cfg(feature = "time")]
 pub mod time;
 pub mod util;
 pub mod waitqueue;
 
+#[cfg(feature = "defmt-timestamp-uptime")]
+defmt::timestamp! {"{=u64:us}", crate::time::Instant::now().as_micros() }
+
 #[cfg(feature = "nightly")]
 pub use embassy_macros::{main, task};
 
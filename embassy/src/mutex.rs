@@ -4,14 +4,23 @@
 /// The raw mutex is used to guard access to the internal "is locked" flag. It
 /// is held for very short periods only, while locking and unlocking. It is *not* held
 /// for the entire time the async Mutex is locked.
+///
+/// The mutex is fair: tasks waiting for it get it in the order they called [`Mutex::lock`].
+/// When the mutex is unlocked while tasks are waiting, it's handed over directly to the first
+/// one, so a task unlocking and relocking the mutex in a loop can't starve the others.
+/// This makes it suitable to share a bus (SPI, I2C...) between tasks.
+///
+/// There is no poisoning: if a task panics, the firmware panics.
 use core::cell::{RefCell, UnsafeCell};
+use core::future::Future;
+use core::marker::PhantomPinned;
 use core::ops::{Deref, DerefMut};
-use core::task::Poll;
-use futures::future::poll_fn;
+use core::pin::Pin;
+use core::task::{Context, Poll};
 
 use crate::blocking_mutex::raw::RawMutex;
 use crate::blocking_mutex::Mutex as BlockingMutex;
-use crate::waitqueue::WakerRegistration;
+use crate::waitqueue::{Waiter, WaiterQueue};
 
 /// Error returned by [`Mutex::try_lock`]
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
@@ -20,7 +29,23 @@ pub struct TryLockError;
 
 struct State {
     locked: bool,
-    waker: WakerRegistration,
+    waiters: WaiterQueue<()>,
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            locked: false,
+            waiters: WaiterQueue::new(),
+        }
+    }
+
+    fn unlock(&mut self) {
+        // Hand the mutex over to the first waiter, it stays locked.
+        if !self.waiters.grant_front() {
+            self.locked = false;
+        }
+    }
 }
 
 pub struct Mutex<M, T>
@@ -45,10 +70,7 @@ where
     pub const fn new(value: T) -> Self {
         Self {
             inner: UnsafeCell::new(value),
-            state: BlockingMutex::new(RefCell::new(State {
-                locked: false,
-                waker: WakerRegistration::new(),
-            })),
+            state: BlockingMutex::new(RefCell::new(State::new())),
         }
     }
 
@@ -57,10 +79,7 @@ where
     pub fn new(value: T) -> Self {
         Self {
             inner: UnsafeCell::new(value),
-            state: BlockingMutex::new(RefCell::new(State {
-                locked: false,
-                waker: WakerRegistration::new(),
-            })),
+            state: BlockingMutex::new(RefCell::new(State::new())),
         }
     }
 }
@@ -72,26 +91,15 @@ where
 {
     /// Lock the mutex.
     ///
-    /// This will wait for the mutex to be unlocked if it's already locked.
+    /// This will wait for the mutex to be unlocked if it's already locked. Waiting tasks
+    /// get the mutex in the order they called `lock`.
     pub async fn lock(&self) -> MutexGuard<'_, M, T> {
-        poll_fn(|cx| {
-            let ready = self.state.lock(|s| {
-                let mut s = s.borrow_mut();
-                if s.locked {
-                    s.waker.register(cx.waker());
-                    false
-                } else {
-                    s.locked = true;
-                    true
-                }
-            });
-
-            if ready {
-                Poll::Ready(MutexGuard { mutex: self })
-            } else {
-                Poll::Pending
-            }
-        })
+        LockFuture {
+            mutex: self,
+            waiter: UnsafeCell::new(Waiter::new(())),
+            done: false,
+            _pinned: PhantomPinned,
+        }
         .await
     }
 
@@ -113,6 +121,77 @@ where
     }
 }
 
+struct LockFuture<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    mutex: &'a Mutex<M, T>,
+    waiter: UnsafeCell<Waiter<()>>,
+    done: bool,
+    _pinned: PhantomPinned,
+}
+
+impl<'a, M, T> Future for LockFuture<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    type Output = MutexGuard<'a, M, T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Safety: the future is pinned, so the waiter doesn't move while queued.
+        let this = unsafe { self.get_unchecked_mut() };
+        let waiter = this.waiter.get();
+
+        let ready = this.mutex.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            let w = unsafe { &mut *waiter };
+            if w.is_granted() {
+                return true;
+            }
+            if !w.is_queued() {
+                if !s.locked {
+                    s.locked = true;
+                    return true;
+                }
+                unsafe { s.waiters.push(waiter) };
+            }
+            w.set_waker(cx.waker());
+            false
+        });
+
+        if ready {
+            this.done = true;
+            Poll::Ready(MutexGuard { mutex: this.mutex })
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<'a, M, T> Drop for LockFuture<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let waiter = self.waiter.get();
+        self.mutex.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            if unsafe { (*waiter).is_granted() } {
+                // The mutex was handed over to us, but we're no longer interested.
+                s.unlock();
+            } else {
+                unsafe { s.waiters.remove(waiter) };
+            }
+        })
+    }
+}
+
 /// Async mutex guard.
 ///
 /// Owning an instance of this type indicates having
@@ -134,9 +213,7 @@ where
 {
     fn drop(&mut self) {
         self.mutex.state.lock(|s| {
-            let mut s = s.borrow_mut();
-            s.locked = false;
-            s.waker.wake();
+            s.borrow_mut().unlock();
         })
     }
 }
@@ -165,3 +242,97 @@ where
         unsafe { &mut *(self.mutex.inner.get()) }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::boxed::Box;
+
+    use futures_test::task::noop_context;
+
+    use crate::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+
+    #[test]
+    fn waiters_get_the_lock_in_order() {
+        let mut cx = noop_context();
+        let m = Mutex::<NoopRawMutex, u32>::new(0);
+
+        let guard = m.try_lock().unwrap();
+        let mut a = Box::pin(m.lock());
+        let mut b = Box::pin(m.lock());
+        let mut c = Box::pin(m.lock());
+        assert!(a.as_mut().poll(&mut cx).is_pending());
+        assert!(b.as_mut().poll(&mut cx).is_pending());
+        assert!(c.as_mut().poll(&mut cx).is_pending());
+
+        drop(guard);
+        assert!(c.as_mut().poll(&mut cx).is_pending());
+        assert!(b.as_mut().poll(&mut cx).is_pending());
+        let guard = match a.as_mut().poll(&mut cx) {
+            Poll::Ready(g) => g,
+            Poll::Pending => panic!("first waiter didn't get the lock"),
+        };
+
+        drop(guard);
+        assert!(c.as_mut().poll(&mut cx).is_pending());
+        assert!(b.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn try_lock_does_not_overtake_waiters() {
+        let mut cx = noop_context();
+        let m = Mutex::<NoopRawMutex, u32>::new(0);
+
+        let guard = m.try_lock().unwrap();
+        let mut a = Box::pin(m.lock());
+        assert!(a.as_mut().poll(&mut cx).is_pending());
+
+        drop(guard);
+        assert!(m.try_lock().is_err());
+        assert!(a.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn cancelled_waiter_passes_the_lock_on() {
+        let mut cx = noop_context();
+        let m = Mutex::<NoopRawMutex, u32>::new(0);
+
+        let guard = m.try_lock().unwrap();
+        let mut a = Box::pin(m.lock());
+        let mut b = Box::pin(m.lock());
+        assert!(a.as_mut().poll(&mut cx).is_pending());
+        assert!(b.as_mut().poll(&mut cx).is_pending());
+
+        // `a` is granted the lock, but dropped before it's polled again.
+        drop(guard);
+        drop(a);
+        assert!(b.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn cancelled_waiter_leaves_the_queue() {
+        let mut cx = noop_context();
+        let m = Mutex::<NoopRawMutex, u32>::new(0);
+
+        let guard = m.try_lock().unwrap();
+        let mut a = Box::pin(m.lock());
+        let mut b = Box::pin(m.lock());
+        assert!(a.as_mut().poll(&mut cx).is_pending());
+        assert!(b.as_mut().poll(&mut cx).is_pending());
+
+        drop(a);
+        drop(guard);
+        assert!(b.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[futures_test::test]
+    async fn lock_and_modify() {
+        let m = Mutex::<NoopRawMutex, u32>::new(0);
+        *m.lock().await += 1;
+        *m.lock().await += 1;
+        assert_eq!(*m.lock().await, 2);
+    }
+}
@@ -0,0 +1,449 @@
+/// Async reader-writer lock.
+///
+/// Any number of readers, or a single writer, can hold the lock at a time. Like
+/// [`Mutex`](crate::mutex::Mutex), the lock is generic over a blocking
+/// [`RawMutex`](crate::blocking_mutex::raw::RawMutex), which is only held while locking
+/// and unlocking.
+///
+/// The lock is fair: tasks get it in the order they asked for it. A reader arriving
+/// while a writer waits queues behind the writer, so a stream of readers can't starve
+/// writers. When the lock becomes available, all the readers at the front of the queue
+/// get it at once.
+///
+/// There is no poisoning: if a task panics, the firmware panics.
+use core::cell::{RefCell, UnsafeCell};
+use core::future::Future;
+use core::marker::PhantomPinned;
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::blocking_mutex::raw::RawMutex;
+use crate::blocking_mutex::Mutex as BlockingMutex;
+use crate::waitqueue::{Waiter, WaiterQueue};
+
+/// Error returned by [`RwLock::try_read`] and [`RwLock::try_write`]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TryLockError;
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum Access {
+    Read,
+    Write,
+}
+
+struct State {
+    readers: usize,
+    writer: bool,
+    waiters: WaiterQueue<Access>,
+}
+
+impl State {
+    const fn new() -> Self {
+        Self {
+            readers: 0,
+            writer: false,
+            waiters: WaiterQueue::new(),
+        }
+    }
+
+    fn try_acquire(&mut self, access: Access) -> bool {
+        let free = match access {
+            Access::Read => !self.writer,
+            Access::Write => !self.writer && self.readers == 0,
+        };
+        // Don't overtake queued tasks.
+        if !free || !self.waiters.is_empty() {
+            return false;
+        }
+        self.acquire(access);
+        true
+    }
+
+    fn acquire(&mut self, access: Access) {
+        match access {
+            Access::Read => self.readers += 1,
+            Access::Write => self.writer = true,
+        }
+    }
+
+    fn release(&mut self, access: Access) {
+        match access {
+            Access::Read => self.readers -= 1,
+            Access::Write => self.writer = false,
+        }
+        self.grant_waiters();
+    }
+
+    /// Hand the lock over to the waiters at the front of the queue, if they can have it.
+    fn grant_waiters(&mut self) {
+        while let Some(&access) = self.waiters.front() {
+            let free = match access {
+                Access::Read => !self.writer,
+                Access::Write => !self.writer && self.readers == 0,
+            };
+            if !free {
+                break;
+            }
+            self.acquire(access);
+            self.waiters.grant_front();
+        }
+    }
+}
+
+pub struct RwLock<M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    state: BlockingMutex<M, RefCell<State>>,
+    inner: UnsafeCell<T>,
+}
+
+unsafe impl<M: RawMutex + Send, T: ?Sized + Send> Send for RwLock<M, T> {}
+unsafe impl<M: RawMutex + Sync, T: ?Sized + Send + Sync> Sync for RwLock<M, T> {}
+
+impl<M, T> RwLock<M, T>
+where
+    M: RawMutex,
+{
+    /// Create a new lock with the given value.
+    #[cfg(feature = "nightly")]
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: UnsafeCell::new(value),
+            state: BlockingMutex::new(RefCell::new(State::new())),
+        }
+    }
+
+    /// Create a new lock with the given value.
+    #[cfg(not(feature = "nightly"))]
+    pub fn new(value: T) -> Self {
+        Self {
+            inner: UnsafeCell::new(value),
+            state: BlockingMutex::new(RefCell::new(State::new())),
+        }
+    }
+}
+
+impl<M, T> RwLock<M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    /// Lock for reading.
+    ///
+    /// This will wait while a writer holds the lock, or is waiting for it.
+    pub async fn read(&self) -> RwLockReadGuard<'_, M, T> {
+        self.acquire(Access::Read).await;
+        RwLockReadGuard { lock: self }
+    }
+
+    /// Lock for writing.
+    ///
+    /// This will wait until no other task holds the lock.
+    pub async fn write(&self) -> RwLockWriteGuard<'_, M, T> {
+        self.acquire(Access::Write).await;
+        RwLockWriteGuard { lock: self }
+    }
+
+    /// Attempt to immediately lock for reading.
+    pub fn try_read(&self) -> Result<RwLockReadGuard<'_, M, T>, TryLockError> {
+        self.try_acquire(Access::Read)?;
+        Ok(RwLockReadGuard { lock: self })
+    }
+
+    /// Attempt to immediately lock for writing.
+    pub fn try_write(&self) -> Result<RwLockWriteGuard<'_, M, T>, TryLockError> {
+        self.try_acquire(Access::Write)?;
+        Ok(RwLockWriteGuard { lock: self })
+    }
+
+    fn try_acquire(&self, access: Access) -> Result<(), TryLockError> {
+        self.state.lock(|s| {
+            if s.borrow_mut().try_acquire(access) {
+                Ok(())
+            } else {
+                Err(TryLockError)
+            }
+        })
+    }
+
+    fn acquire(&self, access: Access) -> AcquireFuture<'_, M, T> {
+        AcquireFuture {
+            lock: self,
+            waiter: UnsafeCell::new(Waiter::new(access)),
+            done: false,
+            _pinned: PhantomPinned,
+        }
+    }
+
+    fn release(&self, access: Access) {
+        self.state.lock(|s| s.borrow_mut().release(access))
+    }
+}
+
+struct AcquireFuture<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    lock: &'a RwLock<M, T>,
+    waiter: UnsafeCell<Waiter<Access>>,
+    done: bool,
+    _pinned: PhantomPinned,
+}
+
+impl<'a, M, T> Future for AcquireFuture<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // Safety: the future is pinned, so the waiter doesn't move while queued.
+        let this = unsafe { self.get_unchecked_mut() };
+        let waiter = this.waiter.get();
+
+        let ready = this.lock.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            let w = unsafe { &mut *waiter };
+            if w.is_granted() {
+                return true;
+            }
+            if !w.is_queued() {
+                if s.try_acquire(w.kind) {
+                    return true;
+                }
+                unsafe { s.waiters.push(waiter) };
+            }
+            w.set_waker(cx.waker());
+            false
+        });
+
+        if ready {
+            this.done = true;
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<'a, M, T> Drop for AcquireFuture<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let waiter = self.waiter.get();
+        self.lock.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            let w = unsafe { &*waiter };
+            if w.is_granted() {
+                // The lock was handed over to us, but we're no longer interested.
+                s.release(w.kind);
+            } else {
+                unsafe { s.waiters.remove(waiter) };
+                // We may have been the writer holding back readers queued behind.
+                s.grant_waiters();
+            }
+        })
+    }
+}
+
+/// Async read lock guard.
+///
+/// Dropping it releases the read lock.
+pub struct RwLockReadGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    lock: &'a RwLock<M, T>,
+}
+
+impl<'a, M, T> Drop for RwLockReadGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        self.lock.release(Access::Read)
+    }
+}
+
+impl<'a, M, T> Deref for RwLockReadGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // Safety: while a read guard exists, there's no write guard.
+        unsafe { &*(self.lock.inner.get() as *const T) }
+    }
+}
+
+/// Async write lock guard.
+///
+/// Dropping it releases the write lock.
+pub struct RwLockWriteGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    lock: &'a RwLock<M, T>,
+}
+
+impl<'a, M, T> Drop for RwLockWriteGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        self.lock.release(Access::Write)
+    }
+}
+
+impl<'a, M, T> Deref for RwLockWriteGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // Safety: the write guard represents exclusive access to the contents.
+        unsafe { &*(self.lock.inner.get() as *const T) }
+    }
+}
+
+impl<'a, M, T> DerefMut for RwLockWriteGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // Safety: the write guard represents exclusive access to the contents.
+        unsafe { &mut *(self.lock.inner.get()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::boxed::Box;
+
+    use futures_test::task::noop_context;
+
+    use crate::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+
+    #[test]
+    fn readers_share_the_lock() {
+        let l = RwLock::<NoopRawMutex, u32>::new(0);
+
+        let r1 = l.try_read().unwrap();
+        let r2 = l.try_read().unwrap();
+        assert!(l.try_write().is_err());
+
+        drop(r1);
+        drop(r2);
+        assert!(l.try_write().is_ok());
+    }
+
+    #[test]
+    fn waiting_writer_blocks_new_readers() {
+        let mut cx = noop_context();
+        let l = RwLock::<NoopRawMutex, u32>::new(0);
+
+        let r1 = l.try_read().unwrap();
+        let mut w = Box::pin(l.write());
+        assert!(w.as_mut().poll(&mut cx).is_pending());
+
+        // The lock is only held for reading, but a writer is first in line.
+        let mut r2 = Box::pin(l.read());
+        assert!(r2.as_mut().poll(&mut cx).is_pending());
+        assert!(l.try_read().is_err());
+
+        drop(r1);
+        assert!(r2.as_mut().poll(&mut cx).is_pending());
+        let guard = match w.as_mut().poll(&mut cx) {
+            Poll::Ready(g) => g,
+            Poll::Pending => panic!("writer didn't get the lock"),
+        };
+
+        drop(guard);
+        assert!(r2.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn queued_readers_are_granted_together() {
+        let mut cx = noop_context();
+        let l = RwLock::<NoopRawMutex, u32>::new(0);
+
+        let guard = l.try_write().unwrap();
+        let mut r1 = Box::pin(l.read());
+        let mut r2 = Box::pin(l.read());
+        let mut w = Box::pin(l.write());
+        let mut r3 = Box::pin(l.read());
+        assert!(r1.as_mut().poll(&mut cx).is_pending());
+        assert!(r2.as_mut().poll(&mut cx).is_pending());
+        assert!(w.as_mut().poll(&mut cx).is_pending());
+        assert!(r3.as_mut().poll(&mut cx).is_pending());
+
+        // Readers behind the queued writer must wait for it.
+        drop(guard);
+        assert!(w.as_mut().poll(&mut cx).is_pending());
+        assert!(r3.as_mut().poll(&mut cx).is_pending());
+        assert!(r1.as_mut().poll(&mut cx).is_ready());
+        assert!(r2.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn cancelled_writer_lets_readers_through() {
+        let mut cx = noop_context();
+        let l = RwLock::<NoopRawMutex, u32>::new(0);
+
+        let r1 = l.try_read().unwrap();
+        let mut w = Box::pin(l.write());
+        let mut r2 = Box::pin(l.read());
+        assert!(w.as_mut().poll(&mut cx).is_pending());
+        assert!(r2.as_mut().poll(&mut cx).is_pending());
+
+        drop(w);
+        assert!(r2.as_mut().poll(&mut cx).is_ready());
+        drop(r1);
+    }
+
+    #[test]
+    fn cancelled_granted_writer_passes_the_lock_on() {
+        let mut cx = noop_context();
+        let l = RwLock::<NoopRawMutex, u32>::new(0);
+
+        let guard = l.try_write().unwrap();
+        let mut w = Box::pin(l.write());
+        let mut r = Box::pin(l.read());
+        assert!(w.as_mut().poll(&mut cx).is_pending());
+        assert!(r.as_mut().poll(&mut cx).is_pending());
+
+        // `w` is granted the lock, but dropped before it's polled again.
+        drop(guard);
+        drop(w);
+        assert!(r.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[futures_test::test]
+    async fn read_and_write() {
+        let l = RwLock::<NoopRawMutex, u32>::new(0);
+        *l.write().await += 1;
+        assert_eq!(*l.read().await, 1);
+    }
+}
@@ -0,0 +1,240 @@
+/// Async counting semaphore.
+///
+/// A semaphore holds a number of permits. Tasks acquire permits before doing something,
+/// and give them back when done, which limits how many tasks do it concurrently: for
+/// example how many DMA buffers are in flight, or how many flash operations are queued.
+///
+/// The semaphore is generic over a blocking [`RawMutex`](crate::blocking_mutex::raw::RawMutex),
+/// which is only held while acquiring and releasing permits. It's fair: tasks get their
+/// permits in the order they asked for them, so a task asking for many permits isn't
+/// starved by tasks asking for a few.
+use core::cell::{RefCell, UnsafeCell};
+use core::future::Future;
+use core::marker::PhantomPinned;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::blocking_mutex::raw::RawMutex;
+use crate::blocking_mutex::Mutex as BlockingMutex;
+use crate::waitqueue::{Waiter, WaiterQueue};
+
+/// Error returned by [`Semaphore::try_acquire`]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TryAcquireError;
+
+struct State {
+    permits: usize,
+    /// Waiters, with the number of permits they asked for.
+    waiters: WaiterQueue<usize>,
+}
+
+impl State {
+    fn try_acquire(&mut self, permits: usize) -> bool {
+        // Don't overtake queued tasks.
+        if self.permits < permits || !self.waiters.is_empty() {
+            return false;
+        }
+        self.permits -= permits;
+        true
+    }
+
+    fn release(&mut self, permits: usize) {
+        self.permits += permits;
+        self.grant_waiters();
+    }
+
+    fn grant_waiters(&mut self) {
+        while let Some(&wanted) = self.waiters.front() {
+            if self.permits < wanted {
+                break;
+            }
+            self.permits -= wanted;
+            self.waiters.grant_front();
+        }
+    }
+}
+
+pub struct Semaphore<M>
+where
+    M: RawMutex,
+{
+    state: BlockingMutex<M, RefCell<State>>,
+}
+
+impl<M> Semaphore<M>
+where
+    M: RawMutex,
+{
+    /// Create a new semaphore with the given number of permits.
+    #[cfg(feature = "nightly")]
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            state: BlockingMutex::new(RefCell::new(State {
+                permits,
+                waiters: WaiterQueue::new(),
+            })),
+        }
+    }
+
+    /// Create a new semaphore with the given number of permits.
+    #[cfg(not(feature = "nightly"))]
+    pub fn new(permits: usize) -> Self {
+        Self {
+            state: BlockingMutex::new(RefCell::new(State {
+                permits,
+                waiters: WaiterQueue::new(),
+            })),
+        }
+    }
+
+    /// Acquire `permits` permits.
+    ///
+    /// This waits until enough permits are available. The permits are given back when the
+    /// returned [`SemaphorePermit`] is dropped.
+    pub async fn acquire(&self, permits: usize) -> SemaphorePermit<'_, M> {
+        AcquireFuture {
+            semaphore: self,
+            waiter: UnsafeCell::new(Waiter::new(permits)),
+            done: false,
+            _pinned: PhantomPinned,
+        }
+        .await;
+        SemaphorePermit {
+            semaphore: self,
+            permits,
+        }
+    }
+
+    /// Attempt to immediately acquire `permits` permits.
+    pub fn try_acquire(&self, permits: usize) -> Result<SemaphorePermit<'_, M>, TryAcquireError> {
+        if self.state.lock(|s| s.borrow_mut().try_acquire(permits)) {
+            Ok(SemaphorePermit {
+                semaphore: self,
+                permits,
+            })
+        } else {
+            Err(TryAcquireError)
+        }
+    }
+
+    /// Add `permits` permits to the semaphore.
+    ///
+    /// Together with [`SemaphorePermit::forget`], this allows using the semaphore
+    /// to count events, like buffers filled by an interrupt.
+    pub fn release(&self, permits: usize) {
+        self.state.lock(|s| s.borrow_mut().release(permits))
+    }
+
+    /// Number of permits currently available.
+    pub fn available_permits(&self) -> usize {
+        self.state.lock(|s| s.borrow().permits)
+    }
+}
+
+struct AcquireFuture<'a, M>
+where
+    M: RawMutex,
+{
+    semaphore: &'a Semaphore<M>,
+    waiter: UnsafeCell<Waiter<usize>>,
+    done: bool,
+    _pinned: PhantomPinned,
+}
+
+impl<'a, M> Future for AcquireFuture<'a, M>
+where
+    M: RawMutex,
+{
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // Safety: the future is pinned, so the waiter doesn't move while queued.
+        let this = unsafe { self.get_unchecked_mut() };
+        let waiter = this.waiter.get();
+
+        let ready = this.semaphore.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            let w = unsafe { &mut *waiter };
+            if w.is_granted() {
+                return true;
+            }
+            if !w.is_queued() {
+                if s.try_acquire(w.kind) {
+                    return true;
+                }
+                unsafe { s.waiters.push(waiter) };
+            }
+            w.set_waker(cx.waker());
+            false
+        });
+
+        if ready {
+            this.done = true;
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<'a, M> Drop for AcquireFuture<'a, M>
+where
+    M: RawMutex,
+{
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let waiter = self.waiter.get();
+        self.semaphore.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            let w = unsafe { &*waiter };
+            if w.is_granted() {
+                // The permits were handed over to us, but we're no longer interested.
+                s.release(w.kind);
+            } else {
+                unsafe { s.waiters.remove(waiter) };
+                // We may have been holding back smaller requests queued behind.
+                s.grant_waiters();
+            }
+        })
+    }
+}
+
+/// Permits acquired from a [`Semaphore`].
+///
+/// Dropping it gives the permits back.
+pub struct SemaphorePermit<'a, M>
+where
+    M: RawMutex,
+{
+    semaphore: &'a Semaphore<M>,
+    permits: usize,
+}
+
+impl<'a, M> SemaphorePermit<'a, M>
+where
+    M: RawMutex,
+{
+    /// Number of permits held.
+    pub fn permits(&self) -> usize {
+        self.permits
+    }
+
+    /// Keep the permits out of the semaphore, instead of giving them back on drop.
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl<'a, M> Drop for SemaphorePermit<'a, M>
+where
+    M: RawMutex,
+{
+    fn drop(&mut self) {
+        if self.permits != 0 {
+            self.semaphore.release(self.permits)
+        }
+    }
+}
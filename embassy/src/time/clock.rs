@@ -0,0 +1,289 @@
+use core::cell::Cell;
+
+use super::Instant;
+use crate::blocking_mutex::raw::CriticalSectionRawMutex;
+use crate::blocking_mutex::Mutex;
+
+const MICROS_PER_SEC: u64 = 1_000_000;
+const SECS_PER_DAY: u64 = 86_400;
+/// Days between 0000-03-01 and 1970-01-01, in the proleptic Gregorian calendar.
+const DAYS_TO_UNIX_EPOCH: u64 = 719_468;
+
+/// Wall-clock time, kept as the offset between [`Instant`] and the Unix epoch.
+///
+/// The clock is not set until an epoch is given to it, for example from an RTC at boot
+/// or from an SNTP server. Until then, all conversions return `None`. Put it in a `static`
+/// to share it with the whole firmware:
+///
+/// ```ignore
+/// static CLOCK: Clock = Clock::new();
+///
+/// CLOCK.set_unix_secs(rtc.read_unix_secs());
+/// let now = CLOCK.now_datetime();
+/// ```
+///
+/// Conversions are done with checked arithmetic: instants that would fall outside of the
+/// `u64` microsecond range since the Unix epoch convert to `None` instead of wrapping.
+/// With the offset set to a present-day date, this can't happen for hundreds of thousands
+/// of years, whatever the tick rate of the time driver.
+pub struct Clock {
+    /// Unix time in microseconds at `Instant` 0.
+    offset: Mutex<CriticalSectionRawMutex, Cell<Option<u64>>>,
+}
+
+impl Clock {
+    /// Create a clock that is not set yet.
+    pub const fn new() -> Self {
+        Self {
+            offset: Mutex::const_new(CriticalSectionRawMutex::new(), Cell::new(None)),
+        }
+    }
+
+    /// Returns true once the clock has been set.
+    pub fn is_set(&self) -> bool {
+        self.offset.lock(|o| o.get().is_some())
+    }
+
+    /// Set the current time, in microseconds since the Unix epoch.
+    pub fn set_unix_micros(&self, unix_micros: u64) {
+        self.set_unix_micros_at(Instant::now(), unix_micros)
+    }
+
+    /// Set the current time, in seconds since the Unix epoch.
+    pub fn set_unix_secs(&self, unix_secs: u64) {
+        self.set_unix_micros(unix_secs.saturating_mul(MICROS_PER_SEC))
+    }
+
+    /// Set the time it was at `instant`, in microseconds since the Unix epoch.
+    ///
+    /// This is useful when the time was measured a while ago, like the receive
+    /// time of a network time packet. If `instant` is later than `unix_micros`
+    /// (the clock would be set before the Unix epoch), the offset saturates to zero.
+    pub fn set_unix_micros_at(&self, instant: Instant, unix_micros: u64) {
+        let offset = unix_micros.saturating_sub(instant.as_micros());
+        self.offset.lock(|o| o.set(Some(offset)))
+    }
+
+    /// Forget the current time. The clock must be set again before use.
+    pub fn reset(&self) {
+        self.offset.lock(|o| o.set(None))
+    }
+
+    /// Get the Unix time of `instant`, in microseconds.
+    ///
+    /// Returns `None` if the clock is not set, or on overflow.
+    pub fn unix_micros_at(&self, instant: Instant) -> Option<u64> {
+        self.offset
+            .lock(|o| o.get())?
+            .checked_add(instant.as_micros())
+    }
+
+    /// Get the current Unix time, in microseconds.
+    pub fn now_unix_micros(&self) -> Option<u64> {
+        self.unix_micros_at(Instant::now())
+    }
+
+    /// Get the current Unix time, in seconds.
+    pub fn now_unix_secs(&self) -> Option<u64> {
+        self.now_unix_micros().map(|t| t / MICROS_PER_SEC)
+    }
+
+    /// Get the [`Instant`] at which it is (or was) `unix_micros`.
+    ///
+    /// Returns `None` if the clock is not set, or if that time is before boot.
+    pub fn instant_at(&self, unix_micros: u64) -> Option<Instant> {
+        let offset = self.offset.lock(|o| o.get())?;
+        unix_micros.checked_sub(offset).map(Instant::from_micros)
+    }
+
+    /// Get the calendar time (UTC) of `instant`.
+    pub fn datetime_at(&self, instant: Instant) -> Option<DateTime> {
+        self.unix_micros_at(instant).map(DateTime::from_unix_micros)
+    }
+
+    /// Get the current calendar time (UTC).
+    pub fn now_datetime(&self) -> Option<DateTime> {
+        self.datetime_at(Instant::now())
+    }
+}
+
+/// Day of the week.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[allow(missing_docs)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+/// A calendar date and time, in UTC, from 1970 onwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DateTime {
+    /// Year, like 2022.
+    pub year: u32,
+    /// Month, 1 to 12.
+    pub month: u8,
+    /// Day of the month, 1 to 31.
+    pub day: u8,
+    /// Hour, 0 to 23.
+    pub hour: u8,
+    /// Minute, 0 to 59.
+    pub minute: u8,
+    /// Second, 0 to 59. Leap seconds are not represented, like in Unix time.
+    pub second: u8,
+    /// Microsecond, 0 to 999999.
+    pub micros: u32,
+}
+
+impl DateTime {
+    /// Convert microseconds since the Unix epoch to a calendar time.
+    pub fn from_unix_micros(unix_micros: u64) -> Self {
+        let secs = unix_micros / MICROS_PER_SEC;
+        let micros = (unix_micros % MICROS_PER_SEC) as u32;
+        let days = secs / SECS_PER_DAY;
+        let secs_of_day = secs % SECS_PER_DAY;
+
+        // Howard Hinnant's `civil_from_days`, with years starting on March 1st
+        // so that the leap day is the last day of the year.
+        let z = days + DAYS_TO_UNIX_EPOCH;
+        let era = z / 146_097;
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + (month <= 2) as u64;
+
+        Self {
+            year: year as u32,
+            month: month as u8,
+            day: day as u8,
+            hour: (secs_of_day / 3600) as u8,
+            minute: (secs_of_day / 60 % 60) as u8,
+            second: (secs_of_day % 60) as u8,
+            micros,
+        }
+    }
+
+    /// Convert to microseconds since the Unix epoch.
+    ///
+    /// Returns `None` if a field is out of range, or if the time is before 1970.
+    pub fn to_unix_micros(&self) -> Option<u64> {
+        if self.year < 1970
+            || !(1..=12).contains(&self.month)
+            || self.day < 1
+            || self.day > days_in_month(self.year, self.month)
+            || self.hour > 23
+            || self.minute > 59
+            || self.second > 59
+            || self.micros as u64 >= MICROS_PER_SEC
+        {
+            return None;
+        }
+
+        let month = self.month as u64;
+        let year = self.year as u64 - (month <= 2) as u64;
+        let era = year / 400;
+        let yoe = year - era * 400;
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + self.day as u64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146_097 + doe - DAYS_TO_UNIX_EPOCH;
+
+        let secs = days * SECS_PER_DAY
+            + self.hour as u64 * 3600
+            + self.minute as u64 * 60
+            + self.second as u64;
+        secs.checked_mul(MICROS_PER_SEC)?
+            .checked_add(self.micros as u64)
+    }
+
+    /// Day of the week. Only meaningful for valid dates.
+    pub fn weekday(&self) -> Weekday {
+        let days = self.to_unix_micros().unwrap_or(0) / MICROS_PER_SEC / SECS_PER_DAY;
+        // 1970-01-01 was a Thursday.
+        match (days + 3) % 7 {
+            0 => Weekday::Monday,
+            1 => Weekday::Tuesday,
+            2 => Weekday::Wednesday,
+            3 => Weekday::Thursday,
+            4 => Weekday::Friday,
+            5 => Weekday::Saturday,
+            _ => Weekday::Sunday,
+        }
+    }
+}
+
+fn days_in_month(year: u32, month: u8) -> u8 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dt(year: u32, month: u8, day: u8, hour: u8, minute: u8, second: u8) -> DateTime {
+        DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            micros: 0,
+        }
+    }
+
+    #[test]
+    fn epoch() {
+        assert_eq!(DateTime::from_unix_micros(0), dt(1970, 1, 1, 0, 0, 0));
+        assert_eq!(dt(1970, 1, 1, 0, 0, 0).weekday(), Weekday::Thursday);
+    }
+
+    #[test]
+    fn known_dates() {
+        let t = 1_646_092_800 * MICROS_PER_SEC; // 2022-03-01 00:00:00
+        assert_eq!(DateTime::from_unix_micros(t), dt(2022, 3, 1, 0, 0, 0));
+        assert_eq!(dt(2022, 3, 1, 0, 0, 0).weekday(), Weekday::Tuesday);
+
+        let t = 951_825_600 * MICROS_PER_SEC + 123; // 2000-02-29 12:00:00.000123
+        let d = DateTime::from_unix_micros(t);
+        assert_eq!(
+            d,
+            DateTime {
+                micros: 123,
+                ..dt(2000, 2, 29, 12, 0, 0)
+            }
+        );
+        assert_eq!(d.to_unix_micros(), Some(t));
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut t = 0;
+        while t < 5_000_000_000 * MICROS_PER_SEC {
+            assert_eq!(DateTime::from_unix_micros(t).to_unix_micros(), Some(t));
+            t += 86_399_999_937;
+        }
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(dt(1969, 12, 31, 23, 59, 59).to_unix_micros(), None);
+        assert_eq!(dt(2021, 2, 29, 0, 0, 0).to_unix_micros(), None);
+        assert_eq!(dt(2022, 13, 1, 0, 0, 0).to_unix_micros(), None);
+        assert_eq!(dt(2022, 1, 1, 24, 0, 0).to_unix_micros(), None);
+    }
+}
@@ -11,7 +11,16 @@
 //!   tick rate of your driver.
 //!
 //! If you wish to make the tick rate configurable by the end user, you should do so by exposing your own
-//! Cargo features and having each enable the corresponding `embassy/time-tick-*`.
+//! Cargo features and having each enable the corresponding `embassy/time-tick-*`. If your driver adapts to
+//! [`TICKS_PER_SECOND`](crate::time::TICKS_PER_SECOND), end users may also enable a faster
+//! `embassy/time-tick-*` feature than your default one: the fastest enabled tick rate is used.
+//!
+//! If your driver has a fixed tick rate, check it at compile time, since an application may have enabled a
+//! faster one:
+//!
+//! ```ignore
+//! const _: () = assert!(embassy::time::TICKS_PER_SECOND == 32_768, "this driver only supports time-tick-32768hz");
+//! ```
 //!
 //! # Linkage details
 //!
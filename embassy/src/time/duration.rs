@@ -55,14 +55,47 @@ impl Duration {
         }
     }
 
-    /// Creates a duration from the specified number of microseconds
-    /// NOTE: Delays this small may be inaccurate.
+    /// Creates a duration from the specified number of microseconds, rounding down
+    /// to a whole number of ticks.
+    ///
+    /// NOTE: Delays this small may be inaccurate, unless the tick rate is 1Mhz.
     pub const fn from_micros(micros: u64) -> Duration {
         Duration {
             ticks: micros * (TICKS_PER_SECOND / GCD_1M) / (1_000_000 / GCD_1M),
         }
     }
 
+    /// Creates a duration from the specified number of microseconds, rounding up
+    /// to a whole number of ticks.
+    ///
+    /// Use this for timeouts and delays that must not be shorter than asked.
+    pub const fn from_micros_ceil(micros: u64) -> Duration {
+        let num = micros * (TICKS_PER_SECOND / GCD_1M);
+        let den = 1_000_000 / GCD_1M;
+        Duration {
+            ticks: (num + den - 1) / den,
+        }
+    }
+
+    /// Creates a duration from the specified number of microseconds, which must be
+    /// a whole number of ticks.
+    ///
+    /// Panics otherwise. When used to initialize a `const`, this is a compile-time check
+    /// that the tick rate is fine enough:
+    ///
+    /// ```ignore
+    /// // Fails to compile unless the tick rate is a multiple of 10khz.
+    /// const PERIOD: Duration = Duration::from_micros_exact(100);
+    /// ```
+    pub const fn from_micros_exact(micros: u64) -> Duration {
+        let num = micros * (TICKS_PER_SECOND / GCD_1M);
+        let den = 1_000_000 / GCD_1M;
+        if num % den != 0 {
+            panic!("duration is not a whole number of ticks, the tick rate is too low");
+        }
+        Duration { ticks: num / den }
+    }
+
     /// Adds one Duration to another, returning a new Duration or None in the event of an overflow.
     pub fn checked_add(self, rhs: Duration) -> Option<Duration> {
         self.ticks
@@ -77,6 +110,27 @@ impl Duration {
             .map(|ticks| Duration { ticks })
     }
 
+    /// Adds one Duration to another, returning [`Duration::MAX`] in the event of an overflow.
+    pub fn saturating_add(self, rhs: Duration) -> Duration {
+        Duration {
+            ticks: self.ticks.saturating_add(rhs.ticks),
+        }
+    }
+
+    /// Subtracts one Duration to another, returning [`Duration::MIN`] in the event of an underflow.
+    pub fn saturating_sub(self, rhs: Duration) -> Duration {
+        Duration {
+            ticks: self.ticks.saturating_sub(rhs.ticks),
+        }
+    }
+
+    /// Multiplies one Duration by a scalar u32, returning [`Duration::MAX`] in the event of an overflow.
+    pub fn saturating_mul(self, rhs: u32) -> Duration {
+        Duration {
+            ticks: self.ticks.saturating_mul(rhs as _),
+        }
+    }
+
     /// Multiplies one Duration by a scalar u32, returning a new Duration or None in the event of an overflow.
     pub fn checked_mul(self, rhs: u32) -> Option<Duration> {
         self.ticks
@@ -100,9 +100,9 @@ impl Instant {
         }
     }
 
-    /// Duration elapsed since this Instant.
+    /// Duration elapsed since this Instant. Zero if this Instant is in the future.
     pub fn elapsed(&self) -> Duration {
-        Instant::now() - *self
+        Instant::now().saturating_duration_since(*self)
     }
 
     /// Adds one Duration to self, returning a new `Instant` or None in the event of an overflow.
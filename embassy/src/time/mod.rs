@@ -8,26 +8,33 @@
 //! representing time spans of up to ~584558 years, which is big enough for all practical
 //! purposes and allows not having to worry about overflows.
 //!
+//! Converting to and from microseconds multiplies the tick count before dividing it,
+//! so it has a lower limit: ~1140 years of uptime at 32768Hz, and ~584558 years at 1kHz and 1Mhz.
+//!
 //! [`Instant`] represents a given instant of time (relative to system boot), and [`Duration`]
 //! represents the duration of a span of time. They implement the math operations you'd expect,
 //! like addition and substraction.
 //!
 //! # Delays and timeouts
 //!
-//! [`Timer`] allows performing async delays. [`Ticker`] allows periodic delays without drifting over time.
+//! [`Timer`] allows performing async delays. [`Ticker`] allows periodic delays without drifting over time,
+//! with a choice of [`MissedTickBehavior`] for when the task falls behind.
 //!
 //! An implementation of the `embedded-hal` delay traits is provided by [`Delay`], for compatibility
 //! with libraries from the ecosystem.
 //!
-//! # Wall-clock time
+//! # Measurements
+//!
+//! [`Stopwatch`] measures the time spent in a code path, and [`Elapsed`] collects statistics
+//! over repeated measurements, for profiling. They saturate instead of panicking.
 //!
-//! The `time` module deals exclusively with a monotonically increasing tick count.
-//! Therefore it has no direct support for wall-clock time ("real life" datetimes
-//! like `2021-08-24 13:33:21`).
+//! # Wall-clock time
 //!
-//! If persistence across reboots is not needed, support can be built on top of
-//! `embassy::time` by storing the offset between "seconds elapsed since boot"
-//! and "seconds since unix epoch".
+//! Timekeeping deals exclusively with a monotonically increasing tick count.
+//! Wall-clock time ("real life" datetimes like `2021-08-24 13:33:21`) is provided
+//! on top of it by [`Clock`], which stores the offset between [`Instant`] and the Unix
+//! epoch once it's known (from an RTC, a network time server...), and converts instants
+//! to Unix time and to calendar [`DateTime`]s. The offset doesn't persist across reboots.
 //!
 //! # Time driver
 //!
@@ -42,10 +49,12 @@
 
 #![deny(missing_docs)]
 
+mod clock;
 mod delay;
 pub mod driver;
 mod duration;
 mod instant;
+mod stopwatch;
 mod timer;
 
 #[cfg(feature = "std")]
@@ -54,26 +63,37 @@ mod driver_std;
 #[cfg(feature = "wasm")]
 mod driver_wasm;
 
+pub use clock::{Clock, DateTime, Weekday};
 pub use delay::{block_for, Delay};
 pub use duration::Duration;
 pub use instant::Instant;
-pub use timer::{with_timeout, Ticker, TimeoutError, Timer};
+pub use stopwatch::{Elapsed, Stopwatch};
+pub use timer::{with_timeout, MissedTickBehavior, Ticker, TimeoutError, Timer};
 
-#[cfg(feature = "time-tick-1000hz")]
-const TPS: u64 = 1_000;
+// If several tick rates are enabled, the fastest one wins. This lets an application
+// ask for a faster tick rate than the default of its time driver, if the driver supports it.
+#[cfg(feature = "time-tick-1mhz")]
+const TPS: u64 = 1_000_000;
 
-#[cfg(feature = "time-tick-32768hz")]
+#[cfg(all(feature = "time-tick-32768hz", not(feature = "time-tick-1mhz")))]
 const TPS: u64 = 32_768;
 
-#[cfg(feature = "time-tick-1mhz")]
-const TPS: u64 = 1_000_000;
+#[cfg(all(
+    feature = "time-tick-1000hz",
+    not(any(feature = "time-tick-32768hz", feature = "time-tick-1mhz"))
+))]
+const TPS: u64 = 1_000;
 
 /// Ticks per second of the global timebase.
 ///
 /// This value is specified by the `time-tick-*` Cargo features, which
 /// should be set by the time driver. Some drivers support a fixed tick rate, others
-/// allow you to choose a tick rate with Cargo features of their own. You should not
-/// set the `time-tick-*` features for embassy yourself as an end user.
+/// allow you to choose a faster tick rate, by enabling another `time-tick-*` feature
+/// of embassy in your application: when several are enabled, the fastest one is used.
+/// Drivers with a fixed tick rate fail to compile if the tick rate they get is not theirs.
+///
+/// Timers have a resolution of one tick, so microsecond timers need `time-tick-1mhz`.
+/// Use [`Duration::from_micros_exact`] in a `const` to check the resolution at compile time.
 pub const TICKS_PER_SECOND: u64 = TPS;
 
 const fn gcd(a: u64, b: u64) -> u64 {
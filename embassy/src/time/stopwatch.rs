@@ -0,0 +1,221 @@
+use super::{Duration, Instant};
+
+/// Measures the time spent in a code path, over one or several runs.
+///
+/// Unlike subtracting [`Instant`]s by hand, this never panics: a stopwatch read before
+/// it's started reads zero.
+///
+/// ```ignore
+/// # use embassy::time::Stopwatch;
+/// let mut sw = Stopwatch::start_new();
+/// // ... code to measure ...
+/// let took = sw.stop();
+/// ```
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Stopwatch {
+    started_at: Option<Instant>,
+    accumulated: Duration,
+}
+
+impl Stopwatch {
+    /// Create a stopped stopwatch, reading zero.
+    pub const fn new() -> Self {
+        Self {
+            started_at: None,
+            accumulated: Duration::MIN,
+        }
+    }
+
+    /// Create a stopwatch, and start it.
+    pub fn start_new() -> Self {
+        let mut sw = Self::new();
+        sw.start();
+        sw
+    }
+
+    /// Start measuring, adding to the time already measured. Does nothing if it's running.
+    pub fn start(&mut self) {
+        if self.started_at.is_none() {
+            self.started_at = Some(Instant::now());
+        }
+    }
+
+    /// Stop measuring, and return the length of the run that just ended. Returns zero if it
+    /// wasn't running.
+    pub fn stop(&mut self) -> Duration {
+        match self.started_at.take() {
+            Some(started_at) => {
+                let run = Instant::now().saturating_duration_since(started_at);
+                self.accumulated = self.accumulated.saturating_add(run);
+                run
+            }
+            None => Duration::MIN,
+        }
+    }
+
+    /// Stop, and reset the time measured to zero.
+    pub fn reset(&mut self) {
+        self.started_at = None;
+        self.accumulated = Duration::MIN;
+    }
+
+    /// Reset the time measured to zero and start again, returning the time measured until now.
+    ///
+    /// Call it at the same point of a loop to measure each iteration.
+    pub fn restart(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = self.elapsed_at(now);
+        self.started_at = Some(now);
+        self.accumulated = Duration::MIN;
+        elapsed
+    }
+
+    /// Whether the stopwatch is running.
+    pub fn is_running(&self) -> bool {
+        self.started_at.is_some()
+    }
+
+    /// Total time measured, including the current run if it's running.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed_at(Instant::now())
+    }
+
+    /// Total time measured, in microseconds.
+    ///
+    /// The precision is limited by the tick rate: use `time-tick-1mhz` for microsecond
+    /// precision.
+    pub fn elapsed_micros(&self) -> u64 {
+        self.elapsed().as_micros()
+    }
+
+    fn elapsed_at(&self, now: Instant) -> Duration {
+        match self.started_at {
+            Some(started_at) => self
+                .accumulated
+                .saturating_add(now.saturating_duration_since(started_at)),
+            None => self.accumulated,
+        }
+    }
+}
+
+impl Default for Stopwatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Statistics of repeated measurements of a code path: count, total, minimum, maximum and
+/// mean.
+///
+/// ```ignore
+/// # use embassy::time::Elapsed;
+/// let mut stats = Elapsed::new();
+/// for _ in 0..100 {
+///     stats.measure(|| {
+///         // ... code to measure ...
+///     });
+/// }
+/// let mean = stats.mean();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Elapsed {
+    count: u32,
+    total: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+impl Elapsed {
+    /// Create empty statistics.
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            total: Duration::MIN,
+            min: Duration::MAX,
+            max: Duration::MIN,
+        }
+    }
+
+    /// Add a measurement.
+    ///
+    /// The total saturates instead of overflowing.
+    pub fn record(&mut self, duration: Duration) {
+        self.count = self.count.saturating_add(1);
+        self.total = self.total.saturating_add(duration);
+        self.min = self.min.min(duration);
+        self.max = self.max.max(duration);
+    }
+
+    /// Run `f`, and add the time it took.
+    pub fn measure<R>(&mut self, f: impl FnOnce() -> R) -> R {
+        let start = Instant::now();
+        let res = f();
+        self.record(Instant::now().saturating_duration_since(start));
+        res
+    }
+
+    /// Number of measurements.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Sum of all the measurements.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// Shortest measurement, `None` if there are none.
+    pub fn min(&self) -> Option<Duration> {
+        (self.count > 0).then(|| self.min)
+    }
+
+    /// Longest measurement, `None` if there are none.
+    pub fn max(&self) -> Option<Duration> {
+        (self.count > 0).then(|| self.max)
+    }
+
+    /// Mean of the measurements, `None` if there are none.
+    pub fn mean(&self) -> Option<Duration> {
+        self.total.checked_div(self.count)
+    }
+
+    /// Remove all measurements.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl Default for Elapsed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elapsed_stats() {
+        let mut stats = Elapsed::new();
+        assert_eq!(stats.min(), None);
+        assert_eq!(stats.mean(), None);
+
+        stats.record(Duration::from_ticks(10));
+        stats.record(Duration::from_ticks(30));
+        stats.record(Duration::from_ticks(20));
+        assert_eq!(stats.count(), 3);
+        assert_eq!(stats.total(), Duration::from_ticks(60));
+        assert_eq!(stats.min(), Some(Duration::from_ticks(10)));
+        assert_eq!(stats.max(), Some(Duration::from_ticks(30)));
+        assert_eq!(stats.mean(), Some(Duration::from_ticks(20)));
+
+        stats.record(Duration::MAX);
+        assert_eq!(stats.total(), Duration::MAX);
+
+        stats.reset();
+        assert_eq!(stats, Elapsed::new());
+    }
+}
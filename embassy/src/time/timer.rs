@@ -117,20 +117,74 @@ impl Future for Timer {
 ///     }
 /// }
 /// ```
+///
+/// The ticks are scheduled at absolute deadlines (start + N * period), so they don't drift.
+/// When the task falls behind by more than one period, [`MissedTickBehavior`] selects
+/// how the missed ticks are handled.
 pub struct Ticker {
     expires_at: Instant,
     duration: Duration,
+    missed_tick_behavior: MissedTickBehavior,
+}
+
+/// What a [`Ticker`] does when the task misses ticks, because it was busy for
+/// more than one period.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MissedTickBehavior {
+    /// Yield all the missed ticks immediately, one after the other, then carry on
+    /// with the original schedule. This is the default.
+    Burst,
+    /// Yield one tick immediately, then carry on with the original schedule, dropping
+    /// the other missed ticks.
+    Skip,
+    /// Yield one tick immediately, then restart the schedule from now: the next tick is
+    /// one period after this one.
+    Delay,
+}
+
+impl Default for MissedTickBehavior {
+    fn default() -> Self {
+        Self::Burst
+    }
 }
 
 impl Ticker {
     /// Creates a new ticker that ticks at the specified duration interval.
     pub fn every(duration: Duration) -> Self {
-        let expires_at = Instant::now() + duration;
+        Self::starting_at(Instant::now() + duration, duration)
+    }
+
+    /// Creates a new ticker whose first tick is at `start`, then every `duration`.
+    ///
+    /// This allows aligning several tickers, or a ticker and an external event.
+    pub fn starting_at(start: Instant, duration: Duration) -> Self {
         Self {
-            expires_at,
+            expires_at: start,
             duration,
+            missed_tick_behavior: MissedTickBehavior::default(),
         }
     }
+
+    /// Set how missed ticks are handled.
+    pub fn set_missed_tick_behavior(&mut self, behavior: MissedTickBehavior) {
+        self.missed_tick_behavior = behavior;
+    }
+
+    /// Get how missed ticks are handled.
+    pub fn missed_tick_behavior(&self) -> MissedTickBehavior {
+        self.missed_tick_behavior
+    }
+
+    /// Instant of the next tick.
+    pub fn next_deadline(&self) -> Instant {
+        self.expires_at
+    }
+
+    /// Restart the schedule from now: the next tick is one period from now.
+    pub fn reset(&mut self) {
+        self.expires_at = Instant::now() + self.duration;
+    }
 }
 
 impl Unpin for Ticker {}
@@ -138,9 +192,19 @@ impl Unpin for Ticker {}
 impl Stream for Ticker {
     type Item = ();
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        if self.expires_at <= Instant::now() {
+        let now = Instant::now();
+        if self.expires_at <= now {
             let dur = self.duration;
-            self.expires_at += dur;
+            match self.missed_tick_behavior {
+                MissedTickBehavior::Burst => self.expires_at += dur,
+                MissedTickBehavior::Skip => {
+                    // Next deadline of the original schedule strictly after now.
+                    let behind = (now - self.expires_at).as_ticks();
+                    let periods = behind / dur.as_ticks().max(1) + 1;
+                    self.expires_at += Duration::from_ticks(periods * dur.as_ticks());
+                }
+                MissedTickBehavior::Delay => self.expires_at = now + dur,
+            }
             Poll::Ready(Some(()))
         } else {
             unsafe { raw::register_timer(self.expires_at, cx.waker()) };
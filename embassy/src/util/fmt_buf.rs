@@ -0,0 +1,102 @@
+use core::fmt;
+
+/// Fixed-capacity string buffer implementing [`fmt::Write`].
+///
+/// Lets you use `write!` and friends without an allocator. Output that doesn't fit is
+/// truncated at a `char` boundary, instead of failing the whole write, so a too-small
+/// buffer still leaves you with a useful prefix.
+///
+/// ```
+/// use core::fmt::Write;
+/// use embassy::util::FmtBuf;
+///
+/// let mut buf = FmtBuf::<16>::new();
+/// write!(buf, "{} + {} = {}", 1, 2, 1 + 2).unwrap();
+/// assert_eq!(buf.as_str(), "1 + 2 = 3");
+///
+/// let buf = FmtBuf::<8>::format(format_args!("temperature: {}", 23));
+/// assert_eq!(buf.as_str(), "temperat");
+/// assert!(buf.truncated());
+/// ```
+pub struct FmtBuf<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    truncated: bool,
+}
+
+impl<const N: usize> FmtBuf<N> {
+    /// Create a new, empty buffer.
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    /// Create a buffer holding the formatted `args`, as returned by `format_args!`.
+    pub fn format(args: fmt::Arguments) -> Self {
+        let mut this = Self::new();
+        // Our `write_str` never fails, it truncates instead.
+        let _ = fmt::write(&mut this, args);
+        this
+    }
+
+    /// Get the contents written so far.
+    pub fn as_str(&self) -> &str {
+        // safety: `write_str` only ever copies whole `char`s in.
+        unsafe { core::str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+
+    /// Get the contents written so far, as bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Whether some output was dropped because the buffer was full.
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Empty the buffer.
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.truncated = false;
+    }
+}
+
+impl<const N: usize> fmt::Write for FmtBuf<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let free = N - self.len;
+        let mut n = s.len();
+        if n > free {
+            n = free;
+            while !s.is_char_boundary(n) {
+                n -= 1;
+            }
+            self.truncated = true;
+        }
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+impl<const N: usize> fmt::Display for FmtBuf<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<const N: usize> fmt::Debug for FmtBuf<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<const N: usize> defmt::Format for FmtBuf<N> {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "{=str}", self.as_str())
+    }
+}
@@ -11,6 +11,9 @@ use atomic_polyfill::{AtomicBool, Ordering};
 ///
 /// Note: IF a global mutable variable is desired, use a CriticalSectionMutex or ThreadModeMutex instead.
 ///
+/// Prefer [`StaticCell`](super::StaticCell) in new code: it has no `steal`, and it checks that
+/// its contents can be sent to other threads before being shared.
+///
 /// ```
 /// use embassy::util::Forever;
 /// // Using an integer for the sake of keeping this example self-contained,
@@ -0,0 +1,198 @@
+use core::future::Future;
+use core::mem;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+enum MaybeDone<Fut: Future> {
+    /// A not-yet-completed future
+    Future(Fut),
+    /// The output of the completed future
+    Done(Fut::Output),
+    /// The empty variant after the result of a [`MaybeDone`] has been
+    /// taken using the [`take_output`](MaybeDone::take_output) method.
+    Gone,
+}
+
+impl<Fut: Future> MaybeDone<Fut> {
+    /// Poll the future if it's not done yet. Returns whether it's done.
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> bool {
+        // Safety: the future is never moved out of `Future`, it's dropped in place when
+        // replaced by its output.
+        let this = unsafe { self.get_unchecked_mut() };
+        match &mut *this {
+            Self::Future(fut) => match unsafe { Pin::new_unchecked(fut) }.poll(cx) {
+                Poll::Ready(res) => {
+                    *this = Self::Done(res);
+                    true
+                }
+                Poll::Pending => false,
+            },
+            _ => true,
+        }
+    }
+
+    fn take_output(&mut self) -> Fut::Output {
+        match mem::replace(self, Self::Gone) {
+            MaybeDone::Done(out) => out,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl<Fut: Future + Unpin> Unpin for MaybeDone<Fut> {}
+
+/// Wait for two futures to complete, and return both results.
+///
+/// Both futures are polled concurrently, in the current task.
+pub fn join<A, B>(a: A, b: B) -> Join<A, B>
+where
+    A: Future,
+    B: Future,
+{
+    Join {
+        a: MaybeDone::Future(a),
+        b: MaybeDone::Future(b),
+    }
+}
+
+/// Future for the [`join`] function.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Join<A: Future, B: Future> {
+    a: MaybeDone<A>,
+    b: MaybeDone<B>,
+}
+
+impl<A: Future + Unpin, B: Future + Unpin> Unpin for Join<A, B> {}
+
+impl<A, B> Future for Join<A, B>
+where
+    A: Future,
+    B: Future,
+{
+    type Output = (A::Output, B::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let mut all_done = true;
+        all_done &= unsafe { Pin::new_unchecked(&mut this.a) }.poll(cx);
+        all_done &= unsafe { Pin::new_unchecked(&mut this.b) }.poll(cx);
+        if all_done {
+            Poll::Ready((this.a.take_output(), this.b.take_output()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+// ====================================================================
+
+/// Same as [`join`], but with more futures.
+pub fn join3<A, B, C>(a: A, b: B, c: C) -> Join3<A, B, C>
+where
+    A: Future,
+    B: Future,
+    C: Future,
+{
+    Join3 {
+        a: MaybeDone::Future(a),
+        b: MaybeDone::Future(b),
+        c: MaybeDone::Future(c),
+    }
+}
+
+/// Future for the [`join3`] function.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Join3<A: Future, B: Future, C: Future> {
+    a: MaybeDone<A>,
+    b: MaybeDone<B>,
+    c: MaybeDone<C>,
+}
+
+impl<A: Future + Unpin, B: Future + Unpin, C: Future + Unpin> Unpin for Join3<A, B, C> {}
+
+impl<A, B, C> Future for Join3<A, B, C>
+where
+    A: Future,
+    B: Future,
+    C: Future,
+{
+    type Output = (A::Output, B::Output, C::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let mut all_done = true;
+        all_done &= unsafe { Pin::new_unchecked(&mut this.a) }.poll(cx);
+        all_done &= unsafe { Pin::new_unchecked(&mut this.b) }.poll(cx);
+        all_done &= unsafe { Pin::new_unchecked(&mut this.c) }.poll(cx);
+        if all_done {
+            Poll::Ready((
+                this.a.take_output(),
+                this.b.take_output(),
+                this.c.take_output(),
+            ))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+// ====================================================================
+
+/// Same as [`join`], but with more futures.
+pub fn join4<A, B, C, D>(a: A, b: B, c: C, d: D) -> Join4<A, B, C, D>
+where
+    A: Future,
+    B: Future,
+    C: Future,
+    D: Future,
+{
+    Join4 {
+        a: MaybeDone::Future(a),
+        b: MaybeDone::Future(b),
+        c: MaybeDone::Future(c),
+        d: MaybeDone::Future(d),
+    }
+}
+
+/// Future for the [`join4`] function.
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct Join4<A: Future, B: Future, C: Future, D: Future> {
+    a: MaybeDone<A>,
+    b: MaybeDone<B>,
+    c: MaybeDone<C>,
+    d: MaybeDone<D>,
+}
+
+impl<A: Future + Unpin, B: Future + Unpin, C: Future + Unpin, D: Future + Unpin> Unpin
+    for Join4<A, B, C, D>
+{
+}
+
+impl<A, B, C, D> Future for Join4<A, B, C, D>
+where
+    A: Future,
+    B: Future,
+    C: Future,
+    D: Future,
+{
+    type Output = (A::Output, B::Output, C::Output, D::Output);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+        let mut all_done = true;
+        all_done &= unsafe { Pin::new_unchecked(&mut this.a) }.poll(cx);
+        all_done &= unsafe { Pin::new_unchecked(&mut this.b) }.poll(cx);
+        all_done &= unsafe { Pin::new_unchecked(&mut this.c) }.poll(cx);
+        all_done &= unsafe { Pin::new_unchecked(&mut this.d) }.poll(cx);
+        if all_done {
+            Poll::Ready((
+                this.a.take_output(),
+                this.b.take_output(),
+                this.c.take_output(),
+                this.d.take_output(),
+            ))
+        } else {
+            Poll::Pending
+        }
+    }
+}
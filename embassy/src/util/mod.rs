@@ -1,13 +1,19 @@
 //! Misc utilities
 
+mod fmt_buf;
 mod forever;
+mod join;
 mod select;
+mod static_cell;
 mod steal;
 mod unborrow;
 mod yield_now;
 
+pub use fmt_buf::*;
 pub use forever::*;
+pub use join::*;
 pub use select::*;
+pub use static_cell::*;
 pub use steal::*;
 pub use unborrow::*;
 pub use yield_now::*;
@@ -216,3 +216,50 @@ impl<Fut: Future, const N: usize> Future for SelectAll<Fut, N> {
         }
     }
 }
+
+// ====================================================================
+
+/// Future for the [`select_slice`] function.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct SelectSlice<'a, Fut> {
+    inner: Pin<&'a mut [Fut]>,
+}
+
+/// Same as [`select_all`], but with a slice of futures, for when their number is only known
+/// at runtime.
+///
+/// The futures are pinned where they are, without copying them: pin an array or a slice of
+/// `Unpin` futures with [`Pin::new`], or any slice with `pin_mut!`.
+///
+/// # Panics
+///
+/// This function will panic if the slice contains no items.
+pub fn select_slice<'a, Fut: Future>(slice: Pin<&'a mut [Fut]>) -> SelectSlice<'a, Fut> {
+    assert!(!slice.is_empty());
+    SelectSlice { inner: slice }
+}
+
+impl<'a, Fut: Future> Future for SelectSlice<'a, Fut> {
+    type Output = (Fut::Output, usize);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Safety: the slice is pinned, so are its elements.
+        let item = unsafe {
+            self.inner
+                .as_mut()
+                .get_unchecked_mut()
+                .iter_mut()
+                .enumerate()
+                .find_map(|(i, f)| match Pin::new_unchecked(f).poll(cx) {
+                    Poll::Pending => None,
+                    Poll::Ready(e) => Some((i, e)),
+                })
+        };
+
+        match item {
+            Some((idx, res)) => Poll::Ready((res, idx)),
+            None => Poll::Pending,
+        }
+    }
+}
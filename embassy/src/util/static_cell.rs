@@ -0,0 +1,115 @@
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+
+use atomic_polyfill::{AtomicBool, Ordering};
+
+/// Statically allocated, initialized at runtime cell.
+///
+/// It has two states: "empty" and "full". It is created "empty", and obtaining a reference
+/// to the contents permanently changes it to "full". This allows that reference to be valid
+/// forever, and to be a `&'static mut` since nobody else can ever get another one.
+///
+/// Unlike [`Forever`](super::Forever), it has no `steal`, and it's only `Sync` if its
+/// contents are `Send`, since the reference may be handed to another thread than the
+/// one that initialized it.
+///
+/// ```
+/// use embassy::util::StaticCell;
+///
+/// static SOME_INT: StaticCell<u32> = StaticCell::new();
+///
+/// let x: &'static mut u32 = SOME_INT.init(42);
+/// assert_eq!(*x, 42);
+/// ```
+///
+/// For big buffers, use [`uninit`](Self::uninit) and initialize them in place: a `StaticCell`
+/// lives in `.bss`, while the value given to [`init`](Self::init) is built on the stack first.
+pub struct StaticCell<T> {
+    used: AtomicBool,
+    val: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T> Send for StaticCell<T> {}
+unsafe impl<T: Send> Sync for StaticCell<T> {}
+
+impl<T> StaticCell<T> {
+    /// Create a new, empty `StaticCell`.
+    #[inline]
+    pub const fn new() -> Self {
+        Self {
+            used: AtomicBool::new(false),
+            val: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Initialize the `StaticCell` with a value, returning a mutable reference to it.
+    ///
+    /// Panics if the `StaticCell` is already full.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub fn init(&'static self, val: T) -> &'static mut T {
+        self.uninit().write(val)
+    }
+
+    /// Initialize the `StaticCell` with the value returned by `f`.
+    ///
+    /// `f` is only called if the `StaticCell` is empty. Panics if it's already full.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub fn init_with(&'static self, f: impl FnOnce() -> T) -> &'static mut T {
+        self.uninit().write(f())
+    }
+
+    /// Like [`init`](Self::init), but returns `None` instead of panicking if the
+    /// `StaticCell` is already full.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub fn try_init(&'static self, val: T) -> Option<&'static mut T> {
+        self.try_uninit().map(|p| p.write(val))
+    }
+
+    /// Mark the `StaticCell` as full, and return a mutable reference to its uninitialized contents.
+    ///
+    /// This allows initializing big values in place. Panics if the `StaticCell` is already full.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub fn uninit(&'static self) -> &'static mut MaybeUninit<T> {
+        match self.try_uninit() {
+            Some(p) => p,
+            None => panic!("StaticCell already initialized"),
+        }
+    }
+
+    /// Like [`uninit`](Self::uninit), but returns `None` instead of panicking if the
+    /// `StaticCell` is already full.
+    #[inline]
+    #[allow(clippy::mut_from_ref)]
+    pub fn try_uninit(&'static self) -> Option<&'static mut MaybeUninit<T>> {
+        if self
+            .used
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            return None;
+        }
+
+        // Safety: we just marked the cell as full, so this is the only reference ever handed out.
+        Some(unsafe { &mut *self.val.get() })
+    }
+}
+
+/// Put a value in a fresh `static` [`StaticCell`], and return a `&'static mut` to it.
+///
+/// The type of the value must be given, since statics can't be inferred. Each invocation
+/// site has its own `static`, so it panics if it runs more than once.
+///
+/// ```
+/// let x: &'static mut [u8; 4] = embassy::make_static!([u8; 4], [0; 4]);
+/// ```
+#[macro_export]
+macro_rules! make_static {
+    ($t:ty, $val:expr) => {{
+        static CELL: $crate::util::StaticCell<$t> = $crate::util::StaticCell::new();
+        CELL.init($val)
+    }};
+}
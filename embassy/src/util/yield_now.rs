@@ -3,6 +3,9 @@ use core::pin::Pin;
 use core::task::{Context, Poll};
 
 /// Yield from the current task once, allowing other tasks to run.
+///
+/// The executor doesn't preempt tasks: a long CPU-bound loop should yield now and then, so
+/// the other tasks of the executor don't wait for it to finish.
 pub fn yield_now() -> impl Future<Output = ()> {
     YieldNowFuture { yielded: false }
 }
@@ -3,3 +3,6 @@
 #[cfg_attr(feature = "executor-agnostic", path = "waker_agnostic.rs")]
 mod waker;
 pub use waker::*;
+
+mod waiter_queue;
+pub(crate) use waiter_queue::*;
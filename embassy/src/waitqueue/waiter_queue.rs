@@ -0,0 +1,220 @@
+use core::ptr;
+use core::task::Waker;
+
+/// A waiting task, stored in the future that waits.
+///
+/// The future must be pinned while the waiter is queued, and must remove it
+/// from the queue when dropped.
+pub(crate) struct Waiter<K> {
+    pub kind: K,
+    waker: Option<Waker>,
+    queued: bool,
+    granted: bool,
+    prev: *mut Waiter<K>,
+    next: *mut Waiter<K>,
+}
+
+// SAFETY: the links are only followed with the queue owner's mutex held.
+unsafe impl<K: Send> Send for Waiter<K> {}
+
+impl<K> Waiter<K> {
+    pub const fn new(kind: K) -> Self {
+        Self {
+            kind,
+            waker: None,
+            queued: false,
+            granted: false,
+            prev: ptr::null_mut(),
+            next: ptr::null_mut(),
+        }
+    }
+
+    /// Whether the waiter is still waiting in a queue.
+    pub fn is_queued(&self) -> bool {
+        self.queued
+    }
+
+    /// Whether the waiter was removed from the queue by [`WaiterQueue::grant_front`].
+    pub fn is_granted(&self) -> bool {
+        self.granted
+    }
+
+    pub fn set_waker(&mut self, waker: &Waker) {
+        match &self.waker {
+            Some(w) if w.will_wake(waker) => {}
+            _ => self.waker = Some(waker.clone()),
+        }
+    }
+}
+
+/// Intrusive FIFO queue of waiting tasks.
+///
+/// Unlike [`WakerRegistration`](super::WakerRegistration), it holds any number of
+/// waiters, and wakes them one by one in arrival order. It must always be accessed
+/// with a blocking mutex held.
+pub(crate) struct WaiterQueue<K> {
+    head: *mut Waiter<K>,
+    tail: *mut Waiter<K>,
+}
+
+// SAFETY: the waiters are only accessed through the queue, with the owner's mutex held.
+unsafe impl<K: Send> Send for WaiterQueue<K> {}
+
+impl<K> WaiterQueue<K> {
+    pub const fn new() -> Self {
+        Self {
+            head: ptr::null_mut(),
+            tail: ptr::null_mut(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_null()
+    }
+
+    /// Kind of the first waiter, if any.
+    pub fn front(&self) -> Option<&K> {
+        unsafe { self.head.as_ref().map(|w| &w.kind) }
+    }
+
+    /// Add a waiter at the back of the queue.
+    ///
+    /// # Safety
+    ///
+    /// `waiter` must stay valid and pinned until it's removed from the queue, with
+    /// [`remove`](Self::remove) or [`grant_front`](Self::grant_front).
+    pub unsafe fn push(&mut self, waiter: *mut Waiter<K>) {
+        let w = &mut *waiter;
+        w.queued = true;
+        w.granted = false;
+        w.prev = self.tail;
+        w.next = ptr::null_mut();
+        match self.tail.as_mut() {
+            Some(tail) => tail.next = waiter,
+            None => self.head = waiter,
+        }
+        self.tail = waiter;
+    }
+
+    /// Remove a waiter from the queue, if it's queued.
+    ///
+    /// # Safety
+    ///
+    /// `waiter` must be valid, and not queued in another queue.
+    pub unsafe fn remove(&mut self, waiter: *mut Waiter<K>) {
+        let w = &mut *waiter;
+        if !w.queued {
+            return;
+        }
+        match w.prev.as_mut() {
+            Some(prev) => prev.next = w.next,
+            None => self.head = w.next,
+        }
+        match w.next.as_mut() {
+            Some(next) => next.prev = w.prev,
+            None => self.tail = w.prev,
+        }
+        w.queued = false;
+        w.prev = ptr::null_mut();
+        w.next = ptr::null_mut();
+    }
+
+    /// Remove the first waiter, mark it granted and wake it.
+    ///
+    /// Returns false if the queue is empty.
+    pub fn grant_front(&mut self) -> bool {
+        let head = self.head;
+        if head.is_null() {
+            return false;
+        }
+        unsafe {
+            self.remove(head);
+            let w = &mut *head;
+            w.granted = true;
+            if let Some(waker) = w.waker.take() {
+                waker.wake();
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_test::task::new_count_waker;
+
+    use super::*;
+
+    #[test]
+    fn grants_in_order() {
+        let mut q = WaiterQueue::new();
+        let mut a = Waiter::new(1);
+        let mut b = Waiter::new(2);
+        let mut c = Waiter::new(3);
+        unsafe {
+            q.push(&mut a);
+            q.push(&mut b);
+            q.push(&mut c);
+        }
+
+        assert_eq!(q.front(), Some(&1));
+        assert!(q.grant_front());
+        assert_eq!(q.front(), Some(&2));
+        assert!(q.grant_front());
+        assert_eq!(q.front(), Some(&3));
+        assert!(q.grant_front());
+        assert!(q.is_empty());
+        assert!(!q.grant_front());
+
+        for w in [&a, &b, &c] {
+            assert!(w.is_granted());
+            assert!(!w.is_queued());
+        }
+    }
+
+    #[test]
+    fn remove_keeps_order() {
+        let mut q = WaiterQueue::new();
+        let mut a = Waiter::new(1);
+        let mut b = Waiter::new(2);
+        let mut c = Waiter::new(3);
+        unsafe {
+            q.push(&mut a);
+            q.push(&mut b);
+            q.push(&mut c);
+            q.remove(&mut b);
+            // Removing a waiter that isn't queued does nothing.
+            q.remove(&mut b);
+        }
+        assert!(!b.is_queued());
+        assert!(!b.is_granted());
+
+        assert!(q.grant_front());
+        assert_eq!(q.front(), Some(&3));
+        unsafe { q.remove(&mut c) };
+        assert!(q.is_empty());
+
+        // The queue is still usable once emptied by `remove`.
+        unsafe { q.push(&mut b) };
+        assert_eq!(q.front(), Some(&2));
+    }
+
+    #[test]
+    fn grant_wakes_the_waiter() {
+        let (waker, count) = new_count_waker();
+        let mut q = WaiterQueue::new();
+        let mut a = Waiter::new(());
+        let mut b = Waiter::new(());
+        a.set_waker(&waker);
+        b.set_waker(&waker);
+        unsafe {
+            q.push(&mut a);
+            q.push(&mut b);
+        }
+
+        assert!(q.grant_front());
+        assert_eq!(count, 1);
+        assert!(q.grant_front());
+        assert_eq!(count, 2);
+    }
+}
@@ -0,0 +1,93 @@
+#![no_std]
+#![no_main]
+#![macro_use]
+#![feature(generic_associated_types)]
+#![feature(type_alias_impl_trait)]
+
+//! Receives a new image over the UART and applies it with `FotaUpdater`.
+//!
+//! The host sends the length of the image as 4 bytes in little endian, the image, then its
+//! CRC-32 as 4 bytes in little endian. The device answers `OK` before resetting into the
+//! bootloader, or `ERR` if the image was rejected.
+
+use core::future::Future;
+
+use embassy_boot_nrf::{updater, Crc32, FirmwareSource, FotaUpdater};
+use embassy_nrf::{
+    gpio::{Level, Output, OutputDrive},
+    interrupt,
+    nvmc::Nvmc,
+    peripherals::UARTE0,
+    uarte::{self, Uarte},
+    Peripherals,
+};
+use embassy_traits::adapter::BlockingAsync;
+use panic_reset as _;
+
+/// The image part of the stream, which ends after the announced length.
+struct UartSource<'a, 'd> {
+    uart: &'a mut Uarte<'d, UARTE0>,
+    remaining: usize,
+}
+
+impl<'a, 'd> FirmwareSource for UartSource<'a, 'd> {
+    type Error = uarte::Error;
+
+    type ReadFuture<'b> = impl Future<Output = Result<usize, Self::Error>> + 'b where Self: 'b;
+
+    fn read<'b>(&'b mut self, buf: &'b mut [u8]) -> Self::ReadFuture<'b> {
+        async move {
+            let n = buf.len().min(self.remaining);
+            if n > 0 {
+                self.uart.read(&mut buf[..n]).await?;
+                self.remaining -= n;
+            }
+            Ok(n)
+        }
+    }
+}
+
+#[embassy::main]
+async fn main(_s: embassy::executor::Spawner, p: Peripherals) {
+    let mut led = Output::new(p.P0_13, Level::Low, OutputDrive::Standard);
+
+    let mut config = uarte::Config::default();
+    config.parity = uarte::Parity::EXCLUDED;
+    config.baudrate = uarte::Baudrate::BAUD115200;
+    let irq = interrupt::take!(UARTE0_UART0);
+    let mut uart = Uarte::new(p.UARTE0, irq, p.P0_08, p.P0_06, config);
+
+    let nvmc = Nvmc::new(p.NVMC);
+    let mut nvmc = BlockingAsync::new(nvmc);
+
+    loop {
+        let mut header = [0; 4];
+        uart.read(&mut header).await.unwrap();
+        let len = u32::from_le_bytes(header) as usize;
+
+        let mut fota = FotaUpdater::<_, 4096>::new(updater::new(), Crc32::new());
+        if fota.set_expected_len::<(), ()>(len).is_err() {
+            uart.write(b"ERR").await.unwrap();
+            continue;
+        }
+
+        led.set_high();
+        let mut source = UartSource {
+            uart: &mut uart,
+            remaining: len,
+        };
+        let mut buf = [0; 256];
+        let downloaded = fota.download(&mut source, &mut nvmc, &mut buf).await;
+        led.set_low();
+
+        let mut crc = [0; 4];
+        uart.read(&mut crc).await.unwrap();
+
+        if downloaded.is_ok() && fota.verify(&crc, &mut nvmc).await.is_ok() {
+            uart.write(b"OK").await.unwrap();
+            fota.apply(&mut nvmc).await.unwrap();
+            cortex_m::peripheral::SCB::sys_reset();
+        }
+        uart.write(b"ERR").await.unwrap();
+    }
+}
@@ -4,6 +4,7 @@
 
 use defmt::info;
 use embassy::executor::Spawner;
+use embassy::util::join4;
 use embassy_nrf::gpio::{Input, Pull};
 use embassy_nrf::gpiote::{InputChannel, InputChannelPolarity};
 use embassy_nrf::Peripherals;
@@ -64,5 +65,5 @@ async fn main(_spawner: Spawner, p: Peripherals) {
         }
     };
 
-    futures::join!(button1, button2, button3, button4);
+    join4(button1, button2, button3, button4).await;
 }
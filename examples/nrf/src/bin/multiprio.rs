@@ -59,8 +59,7 @@
 
 use cortex_m_rt::entry;
 use defmt::{info, unwrap};
-use embassy::executor::{Executor, InterruptExecutor};
-use embassy::interrupt::InterruptExt;
+use embassy::executor::Executor;
 use embassy::time::{Duration, Instant, Timer};
 use embassy::util::Forever;
 use embassy_nrf::interrupt;
@@ -110,8 +109,6 @@ async fn run_low() {
     }
 }
 
-static EXECUTOR_HIGH: Forever<InterruptExecutor<interrupt::SWI1_EGU1>> = Forever::new();
-static EXECUTOR_MED: Forever<InterruptExecutor<interrupt::SWI0_EGU0>> = Forever::new();
 static EXECUTOR_LOW: Forever<Executor> = Forever::new();
 
 #[entry]
@@ -121,20 +118,13 @@ fn main() -> ! {
     let _p = embassy_nrf::init(Default::default());
 
     // High-priority executor: SWI1_EGU1, priority level 6
-    let irq = interrupt::take!(SWI1_EGU1);
-    irq.set_priority(interrupt::Priority::P6);
-    let executor = EXECUTOR_HIGH.put(InterruptExecutor::new(irq));
-    executor.start(|spawner| {
-        unwrap!(spawner.spawn(run_high()));
-    });
-
     // Medium-priority executor: SWI0_EGU0, priority level 7
-    let irq = interrupt::take!(SWI0_EGU0);
-    irq.set_priority(interrupt::Priority::P7);
-    let executor = EXECUTOR_MED.put(InterruptExecutor::new(irq));
-    executor.start(|spawner| {
-        unwrap!(spawner.spawn(run_med()));
-    });
+    let (high, med) = embassy::start_interrupt_executors!(
+        SWI1_EGU1 => interrupt::Priority::P6,
+        SWI0_EGU0 => interrupt::Priority::P7,
+    );
+    unwrap!(high.spawn(run_high()));
+    unwrap!(med.spawn(run_med()));
 
     // Low priority executor: runs in thread mode, using WFE/SEV
     let executor = EXECUTOR_LOW.put(Executor::new());
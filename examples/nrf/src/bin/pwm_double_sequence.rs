@@ -9,7 +9,7 @@ use embassy_nrf::pwm::{
     Config, Prescaler, Sequence, SequenceConfig, SequenceMode, SequencePwm, Sequencer,
     StartSequence,
 };
-use embassy_nrf::Peripherals;
+use embassy_nrf::{interrupt, Peripherals};
 
 use defmt_rtt as _; // global logger
 use panic_probe as _;
@@ -30,10 +30,11 @@ async fn main(_spawner: Spawner, p: Peripherals) {
     // thus our sequence takes 5 * 5000ms or 25 seconds
 
     let mut pwm = unwrap!(SequencePwm::new_1ch(p.PWM0, p.P0_13, config));
+    let irq = interrupt::take!(PWM0);
 
     let sequence_0 = Sequence::new(&seq_words_0, seq_config.clone());
     let sequence_1 = Sequence::new(&seq_words_1, seq_config);
-    let sequencer = Sequencer::new(&mut pwm, sequence_0, Some(sequence_1));
+    let sequencer = Sequencer::new(&mut pwm, irq, sequence_0, Some(sequence_1));
     unwrap!(sequencer.start(StartSequence::Zero, SequenceMode::Loop(1)));
 
     // we can abort a sequence if we need to before its complete with pwm.stop()
@@ -8,7 +8,7 @@ use embassy::time::{Duration, Timer};
 use embassy_nrf::pwm::{
     Config, Prescaler, SequenceConfig, SequencePwm, SingleSequenceMode, SingleSequencer,
 };
-use embassy_nrf::Peripherals;
+use embassy_nrf::{interrupt, Peripherals};
 
 use defmt_rtt as _; // global logger
 use panic_probe as _;
@@ -28,8 +28,9 @@ async fn main(_spawner: Spawner, p: Peripherals) {
     // thus our sequence takes 5 * 5000ms or 25 seconds
 
     let mut pwm = unwrap!(SequencePwm::new_1ch(p.PWM0, p.P0_13, config,));
+    let irq = interrupt::take!(PWM0);
 
-    let sequencer = SingleSequencer::new(&mut pwm, &seq_words, seq_config);
+    let sequencer = SingleSequencer::new(&mut pwm, irq, &seq_words, seq_config);
     unwrap!(sequencer.start(SingleSequenceMode::Times(1)));
 
     // we can abort a sequence if we need to before its complete with pwm.stop()
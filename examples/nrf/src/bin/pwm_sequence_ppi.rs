@@ -12,7 +12,7 @@ use embassy_nrf::ppi::Ppi;
 use embassy_nrf::pwm::{
     Config, Prescaler, SequenceConfig, SequencePwm, SingleSequenceMode, SingleSequencer,
 };
-use embassy_nrf::Peripherals;
+use embassy_nrf::{interrupt, Peripherals};
 
 use defmt_rtt as _; // global logger
 use panic_probe as _;
@@ -31,6 +31,7 @@ async fn main(_spawner: Spawner, p: Peripherals) {
     seq_config.refresh = 30;
 
     let mut pwm = unwrap!(SequencePwm::new_1ch(p.PWM0, p.P0_13, config));
+    let irq = interrupt::take!(PWM0);
 
     // pwm.stop() deconfigures pins, and then the task_start_seq0 task cant work
     // so its going to have to start running in order load the configuration
@@ -52,7 +53,7 @@ async fn main(_spawner: Spawner, p: Peripherals) {
     let start = unsafe { pwm.task_start_seq0() };
     let stop = unsafe { pwm.task_stop() };
 
-    let sequencer = SingleSequencer::new(&mut pwm, &seq_words, seq_config);
+    let sequencer = SingleSequencer::new(&mut pwm, irq, &seq_words, seq_config);
     unwrap!(sequencer.start(SingleSequenceMode::Infinite));
 
     let mut ppi = Ppi::new_one_to_one(p.PPI_CH1, button1.event_in(), start);
@@ -0,0 +1,42 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+use defmt::info;
+use embassy::executor::Spawner;
+use embassy::interrupt::InterruptExt;
+use embassy::time::{Duration, Timer};
+use embassy_nrf::gpio::{Input, Level, Pull};
+use embassy_nrf::{interrupt, power, Peripherals};
+
+use defmt_rtt as _; // global logger
+use panic_probe as _;
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    let reason = power::reset_reason();
+    power::clear_reset_reason();
+    info!("Reset reason: {:?}", reason);
+
+    // POWER_CLOCK is also used by USB VBUS detection, so the application owns the handler.
+    let irq = interrupt::take!(POWER_CLOCK);
+    irq.set_handler(|_| power::on_interrupt());
+    irq.unpend();
+    irq.enable();
+
+    let mut pof = power::PowerFailComparator::new(power::PofThreshold::V27);
+    if pof.is_below_threshold() {
+        info!("Supply is low, going straight to sleep");
+    } else {
+        // Stay awake for a while, unless the supply drops.
+        let _ = embassy::util::select(pof.wait(), Timer::after(Duration::from_secs(5))).await;
+    }
+
+    // Button 1 on the nRF52840 DK.
+    let button = Input::new(p.P0_11, Pull::Up);
+    power::wake_on_pin(&button, Level::Low);
+
+    info!("Entering System OFF, press button 1 to wake up");
+    Timer::after(Duration::from_millis(10)).await;
+    power::system_off();
+}
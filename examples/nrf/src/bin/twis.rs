@@ -0,0 +1,44 @@
+//! TWIS example: acts as an i2c slave at address 0x55.
+//!
+//! Connect SDA to P0.03, SCL to P0.04
+
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+use defmt::*;
+use embassy::executor::Spawner;
+use embassy_nrf::twis::{self, Command, Twis};
+use embassy_nrf::{interrupt, Peripherals};
+
+use defmt_rtt as _; // global logger
+use panic_probe as _;
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    let irq = interrupt::take!(SPIM0_SPIS0_TWIM0_TWIS0_SPI0_TWI0);
+    let mut config = twis::Config::default();
+    config.address0 = 0x55;
+    let mut twis = Twis::new(p.TWISPI0, irq, p.P0_03, p.P0_04, config);
+
+    info!("Listening...");
+    loop {
+        let mut buf = [0u8; 16];
+        match twis.listen(&mut buf).await {
+            Ok(Command::Read) => {
+                info!("Got READ command. Respond with data...");
+                if let Err(e) = twis.respond_to_read(&[1, 2, 3, 4]).await {
+                    error!("{:?}", e);
+                }
+            }
+            Ok(Command::WriteRead(n)) => {
+                info!("Got WRITE/READ command with data {:x}", buf[..n]);
+                if let Err(e) = twis.respond_to_read(&buf[..n]).await {
+                    error!("{:?}", e);
+                }
+            }
+            Ok(Command::Write(n)) => info!("Got WRITE command with data {:x}", buf[..n]),
+            Err(e) => error!("{:?}", e),
+        }
+    }
+}
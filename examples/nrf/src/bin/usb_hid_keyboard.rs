@@ -10,16 +10,15 @@ use embassy::channel::Signal;
 use embassy::executor::Spawner;
 use embassy::interrupt::InterruptExt;
 use embassy::time::Duration;
-use embassy::util::{select, select3, Either, Either3};
+use embassy::util::{join, select, select3, Either, Either3};
 use embassy_nrf::gpio::{Input, Pin, Pull};
 use embassy_nrf::interrupt;
 use embassy_nrf::pac;
-use embassy_nrf::usb::Driver;
+use embassy_nrf::usb::{self, Driver};
 use embassy_nrf::Peripherals;
 use embassy_usb::control::OutResponse;
 use embassy_usb::{Builder, Config, DeviceStateHandler};
 use embassy_usb_hid::{HidReaderWriter, ReportId, RequestHandler, State};
-use futures::future::join;
 use usbd_hid::descriptor::{KeyboardReport, SerializedDescriptor};
 
 use defmt_rtt as _; // global logger
@@ -31,6 +30,9 @@ static SUSPENDED: AtomicBool = AtomicBool::new(false);
 fn on_power_interrupt(_: *mut ()) {
     let regs = unsafe { &*pac::POWER::ptr() };
 
+    // The driver waits for the USB regulator output when enabled.
+    usb::on_power_interrupt();
+
     if regs.events_usbdetected.read().bits() != 0 {
         regs.events_usbdetected.reset();
         info!("Vbus detected, enabling USB...");
@@ -6,14 +6,15 @@
 use core::mem;
 use defmt::{info, panic};
 use embassy::executor::Spawner;
+use embassy::interrupt::InterruptExt;
+use embassy::util::join;
 use embassy_nrf::interrupt;
 use embassy_nrf::pac;
-use embassy_nrf::usb::{Driver, Instance};
+use embassy_nrf::usb::{self, Driver, Instance};
 use embassy_nrf::Peripherals;
 use embassy_usb::driver::EndpointError;
 use embassy_usb::{Builder, Config};
 use embassy_usb_serial::{CdcAcmClass, State};
-use futures::future::join;
 
 use defmt_rtt as _; // global logger
 use panic_probe as _;
@@ -31,6 +32,12 @@ async fn main(_spawner: Spawner, p: Peripherals) {
     while !power.usbregstatus.read().vbusdetect().is_vbus_present() {}
     info!("vbus OK");
 
+    // The driver waits for the USB regulator through POWER_CLOCK, which the application owns.
+    let power_irq = interrupt::take!(POWER_CLOCK);
+    power_irq.set_handler(|_| usb::on_power_interrupt());
+    power_irq.unpend();
+    power_irq.enable();
+
     // Create the driver, from the HAL.
     let irq = interrupt::take!(USBD);
     let driver = Driver::new(p.USBD, irq);
@@ -0,0 +1,190 @@
+#![no_std]
+#![no_main]
+#![feature(generic_associated_types)]
+#![feature(type_alias_impl_trait)]
+
+use core::mem;
+use defmt::{info, panic, warn};
+use embassy::executor::Spawner;
+use embassy::interrupt::InterruptExt;
+use embassy::util::{join, select, Either};
+use embassy_nrf::gpio::{AnyPin, Level, Output, OutputDrive, Pin};
+use embassy_nrf::interrupt;
+use embassy_nrf::pac;
+use embassy_nrf::peripherals::{UARTE0, USBD};
+use embassy_nrf::uarte::{self, Baudrate, Parity, Uarte};
+use embassy_nrf::usb::{self, Driver};
+use embassy_nrf::Peripherals;
+use embassy_usb::driver::EndpointError;
+use embassy_usb::{Builder, Config};
+use embassy_usb_serial::bridge::{self, UartControl};
+use embassy_usb_serial::{CdcAcmClass, ControlEvents, LineCoding, ParityType, State};
+
+use defmt_rtt as _; // global logger
+use panic_probe as _;
+
+/// The UART side of the bridge, with DTR and RTS driven as plain GPIOs.
+struct BridgeUart<'d> {
+    uart: Uarte<'d, UARTE0>,
+    dtr: Output<'d, AnyPin>,
+    rts: Output<'d, AnyPin>,
+}
+
+impl<'d> UartControl for BridgeUart<'d> {
+    fn set_line_coding(&mut self, coding: &LineCoding) {
+        let baudrate = match coding.data_rate() {
+            9600 => Baudrate::BAUD9600,
+            19200 => Baudrate::BAUD19200,
+            38400 => Baudrate::BAUD38400,
+            57600 => Baudrate::BAUD57600,
+            115200 => Baudrate::BAUD115200,
+            230400 => Baudrate::BAUD230400,
+            460800 => Baudrate::BAUD460800,
+            921600 => Baudrate::BAUD921600,
+            1000000 => Baudrate::BAUD1M,
+            rate => {
+                warn!("unsupported baud rate {}, keeping the current one", rate);
+                return;
+            }
+        };
+        self.uart.set_baudrate(baudrate);
+
+        // The UARTE only supports even parity.
+        let parity = match coding.parity_type() {
+            ParityType::None => Parity::EXCLUDED,
+            ParityType::Event => Parity::INCLUDED,
+            _ => {
+                warn!("unsupported parity, disabling it");
+                Parity::EXCLUDED
+            }
+        };
+        self.uart.set_parity(parity);
+    }
+
+    // The signals are active low on the wire.
+    fn set_dtr(&mut self, dtr: bool) {
+        match dtr {
+            true => self.dtr.set_low(),
+            false => self.dtr.set_high(),
+        }
+    }
+
+    fn set_rts(&mut self, rts: bool) {
+        match rts {
+            true => self.rts.set_low(),
+            false => self.rts.set_high(),
+        }
+    }
+}
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    let clock: pac::CLOCK = unsafe { mem::transmute(()) };
+    let power: pac::POWER = unsafe { mem::transmute(()) };
+
+    info!("Enabling ext hfosc...");
+    clock.tasks_hfclkstart.write(|w| unsafe { w.bits(1) });
+    while clock.events_hfclkstarted.read().bits() != 1 {}
+
+    info!("Waiting for vbus...");
+    while !power.usbregstatus.read().vbusdetect().is_vbus_present() {}
+    info!("vbus OK");
+
+    // The driver waits for the USB regulator through POWER_CLOCK, which the application owns.
+    let power_irq = interrupt::take!(POWER_CLOCK);
+    power_irq.set_handler(|_| usb::on_power_interrupt());
+    power_irq.unpend();
+    power_irq.enable();
+
+    // Create the driver, from the HAL.
+    let irq = interrupt::take!(USBD);
+    let driver = Driver::new(p.USBD, irq);
+
+    // Create embassy-usb Config
+    let config = Config::new(0xc0de, 0xcafe);
+
+    // Create embassy-usb DeviceBuilder using the driver and config.
+    // It needs some buffers for building the descriptors.
+    let mut device_descriptor = [0; 256];
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut control_buf = [0; 7];
+
+    let mut state = State::new();
+
+    let mut builder = Builder::new(
+        driver,
+        config,
+        &mut device_descriptor,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut control_buf,
+        None,
+    );
+
+    // Create classes on the builder.
+    let mut class = CdcAcmClass::new(&mut builder, &mut state, 64);
+    let events = class.control_events();
+
+    // Build the builder.
+    let mut usb = builder.build();
+
+    // Run the USB device.
+    let usb_fut = usb.run();
+
+    let irq = interrupt::take!(UARTE0_UART0);
+    let mut uart = BridgeUart {
+        uart: Uarte::new(p.UARTE0, irq, p.P0_08, p.P0_06, uarte::Config::default()),
+        dtr: Output::new(p.P0_04.degrade(), Level::High, OutputDrive::Standard),
+        rts: Output::new(p.P0_05.degrade(), Level::High, OutputDrive::Standard),
+    };
+
+    // Forward the data from the host to the UART, and mirror the port settings.
+    // For brevity, data isn't forwarded in the other direction: that needs a UART
+    // which can read until the line is idle, like `UarteWithIdle` or `BufferedUarte`.
+    let bridge_fut = async {
+        loop {
+            class.wait_connection().await;
+            info!("Connected");
+            let _ = forward(&mut class, &events, &mut uart).await;
+            info!("Disconnected");
+        }
+    };
+
+    // Run everything concurrently.
+    // If we had made everything `'static` above instead, we could do this using separate tasks instead.
+    join(usb_fut, bridge_fut).await;
+}
+
+struct Disconnected {}
+
+impl From<EndpointError> for Disconnected {
+    fn from(val: EndpointError) -> Self {
+        match val {
+            EndpointError::BufferOverflow => panic!("Buffer overflow"),
+            EndpointError::Disabled => Disconnected {},
+        }
+    }
+}
+
+async fn forward<'d>(
+    class: &mut CdcAcmClass<'d, Driver<'d, USBD>>,
+    events: &ControlEvents<'d>,
+    uart: &mut BridgeUart<'_>,
+) -> Result<(), Disconnected> {
+    uart.set_line_coding(&events.line_coding());
+
+    let mut buf = [0; 64];
+    loop {
+        let res = select(class.read_packet(&mut buf), events.next()).await;
+        match res {
+            Either::First(n) => {
+                let n = n?;
+                if let Err(e) = uart.uart.write(&buf[..n]).await {
+                    warn!("uart write failed: {:?}", e);
+                }
+            }
+            Either::Second(event) => bridge::apply(uart, &event),
+        }
+    }
+}
@@ -6,9 +6,10 @@
 use core::mem;
 use defmt::{info, panic, unwrap};
 use embassy::executor::Spawner;
+use embassy::interrupt::InterruptExt;
 use embassy::util::Forever;
 use embassy_nrf::pac;
-use embassy_nrf::usb::Driver;
+use embassy_nrf::usb::{self, Driver};
 use embassy_nrf::Peripherals;
 use embassy_nrf::{interrupt, peripherals};
 use embassy_usb::driver::EndpointError;
@@ -48,6 +49,12 @@ async fn main(spawner: Spawner, p: Peripherals) {
     while !power.usbregstatus.read().vbusdetect().is_vbus_present() {}
     info!("vbus OK");
 
+    // The driver waits for the USB regulator through POWER_CLOCK, which the application owns.
+    let power_irq = interrupt::take!(POWER_CLOCK);
+    power_irq.set_handler(|_| usb::on_power_interrupt());
+    power_irq.unpend();
+    power_irq.enable();
+
     // Create the driver, from the HAL.
     let irq = interrupt::take!(USBD);
     let driver = Driver::new(p.USBD, irq);
@@ -11,7 +11,7 @@ use panic_probe as _;
 
 #[embassy::main]
 async fn main(_spawner: Spawner, p: Peripherals) {
-    let button = Input::new(p.PIN_28, Pull::Up);
+    let mut button = Input::new(p.PIN_28, Pull::Up);
     let mut led = Output::new(p.PIN_25, Level::Low);
 
     loop {
@@ -20,5 +20,6 @@ async fn main(_spawner: Spawner, p: Peripherals) {
         } else {
             led.set_low();
         }
+        button.wait_for_any_edge().await;
     }
 }
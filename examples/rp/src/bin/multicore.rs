@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+use cortex_m_rt::entry;
+use defmt::{info, unwrap};
+use embassy::executor::Executor;
+use embassy::time::{Duration, Timer};
+use embassy::util::Forever;
+use embassy_rp::gpio::{Level, Output};
+use embassy_rp::multicore::{fifo_read_async, fifo_write, spawn_core1, Stack};
+use embassy_rp::peripherals::PIN_25;
+
+use defmt_rtt as _; // global logger
+use panic_probe as _;
+
+static mut CORE1_STACK: Stack<4096> = Stack::new();
+static EXECUTOR0: Forever<Executor> = Forever::new();
+static EXECUTOR1: Forever<Executor> = Forever::new();
+
+#[embassy::task]
+async fn core0_task() {
+    info!("Hello from core 0");
+    loop {
+        fifo_write(1);
+        Timer::after(Duration::from_millis(100)).await;
+        fifo_write(0);
+        Timer::after(Duration::from_millis(400)).await;
+    }
+}
+
+#[embassy::task]
+async fn core1_task(mut led: Output<'static, PIN_25>) {
+    info!("Hello from core 1");
+    loop {
+        match fifo_read_async().await {
+            0 => led.set_low(),
+            _ => led.set_high(),
+        }
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    let p = embassy_rp::init(Default::default());
+    let led = Output::new(p.PIN_25, Level::Low);
+
+    spawn_core1(p.CORE1, unsafe { &mut CORE1_STACK }, move || {
+        let executor1 = EXECUTOR1.put(Executor::new());
+        executor1.run(|spawner| unwrap!(spawner.spawn(core1_task(led))));
+    });
+
+    let executor0 = EXECUTOR0.put(Executor::new());
+    executor0.run(|spawner| unwrap!(spawner.spawn(core0_task())));
+}
@@ -1,12 +1,14 @@
+#![feature(generic_associated_types)]
 #![feature(type_alias_impl_trait)]
 
 use clap::Parser;
+use embassy::blocking_mutex::raw::ThreadModeRawMutex;
 use embassy::executor::{Executor, Spawner};
 use embassy::io::AsyncWriteExt;
 use embassy::util::Forever;
 use embassy_net::{
-    Config, Configurator, DhcpConfigurator, Ipv4Address, Ipv4Cidr, StackResources,
-    StaticConfigurator, TcpSocket,
+    Config, Configurator, DhcpConfigurator, DhcpResources, Ipv4Address, Ipv4Cidr, Stack,
+    StackResources, StaticConfigurator, TcpSocket,
 };
 use heapless::Vec;
 use log::*;
@@ -19,7 +21,9 @@ use crate::tuntap::TunTapDevice;
 static DEVICE: Forever<TunTapDevice> = Forever::new();
 static CONFIG_STATIC: Forever<StaticConfigurator> = Forever::new();
 static CONFIG_DYNAMIC: Forever<DhcpConfigurator> = Forever::new();
+static DHCP_RESOURCES: Forever<DhcpResources> = Forever::new();
 static NET_RESOURCES: Forever<StackResources<1, 2, 8>> = Forever::new();
+static STACK: Forever<Stack<TunTapDevice, ThreadModeRawMutex>> = Forever::new();
 
 #[derive(Parser)]
 #[clap(version = "1.0")]
@@ -33,8 +37,8 @@ struct Opts {
 }
 
 #[embassy::task]
-async fn net_task() {
-    embassy_net::run().await
+async fn net_task(stack: &'static Stack<TunTapDevice, ThreadModeRawMutex>) {
+    stack.run().await
 }
 
 #[embassy::task]
@@ -45,28 +49,35 @@ async fn main_task(spawner: Spawner) {
     let device = TunTapDevice::new(&opts.tap).unwrap();
 
     // Choose between dhcp or static ip
-    let config: &'static mut dyn Configurator = if opts.static_ip {
+    let config: &'static mut (dyn Configurator<TunTapDevice> + Send) = if opts.static_ip {
         CONFIG_STATIC.put(StaticConfigurator::new(Config {
             address: Ipv4Cidr::new(Ipv4Address::new(192, 168, 69, 2), 24),
             dns_servers: Vec::new(),
             gateway: Some(Ipv4Address::new(192, 168, 69, 1)),
         }))
     } else {
-        CONFIG_DYNAMIC.put(DhcpConfigurator::new())
+        CONFIG_DYNAMIC.put(
+            DhcpConfigurator::new(DHCP_RESOURCES.put(DhcpResources::new()))
+                .with_hostname("embassy"),
+        )
     };
 
     let net_resources = StackResources::new();
 
     // Init network stack
-    embassy_net::init(DEVICE.put(device), config, NET_RESOURCES.put(net_resources));
+    let stack = STACK.put(Stack::new(
+        DEVICE.put(device),
+        config,
+        NET_RESOURCES.put(net_resources),
+    ));
 
     // Launch network task
-    spawner.spawn(net_task()).unwrap();
+    spawner.spawn(net_task(stack)).unwrap();
 
     // Then we can use it!
     let mut rx_buffer = [0; 4096];
     let mut tx_buffer = [0; 4096];
-    let mut socket = TcpSocket::new(&mut rx_buffer, &mut tx_buffer);
+    let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
 
     socket.set_timeout(Some(embassy_net::SmolDuration::from_secs(10)));
 
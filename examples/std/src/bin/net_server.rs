@@ -0,0 +1,145 @@
+#![feature(generic_associated_types)]
+#![feature(type_alias_impl_trait)]
+
+use clap::Parser;
+use embassy::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy::executor::{Executor, Spawner};
+use embassy::io::{AsyncBufReadExt, AsyncWriteExt};
+use embassy::time::{Duration, Timer};
+use embassy::util::Forever;
+use embassy_net::{
+    Config, Configurator, DhcpConfigurator, DhcpResources, Ipv4Address, Ipv4Cidr, Stack,
+    StackResources, StaticConfigurator, TcpSocket,
+};
+use heapless::Vec;
+use log::*;
+
+#[path = "../tuntap.rs"]
+mod tuntap;
+
+use crate::tuntap::TunTapDevice;
+
+static DEVICE: Forever<TunTapDevice> = Forever::new();
+static CONFIG_STATIC: Forever<StaticConfigurator> = Forever::new();
+static CONFIG_DYNAMIC: Forever<DhcpConfigurator> = Forever::new();
+static DHCP_RESOURCES: Forever<DhcpResources> = Forever::new();
+static NET_RESOURCES: Forever<StackResources<1, 4, 8, 2, 4096>> = Forever::new();
+static STACK: Forever<Stack<TunTapDevice, ThreadModeRawMutex>> = Forever::new();
+
+#[derive(Parser)]
+#[clap(version = "1.0")]
+struct Opts {
+    /// TAP device name
+    #[clap(long, default_value = "tap0")]
+    tap: String,
+    /// use a static IP instead of DHCP
+    #[clap(long)]
+    static_ip: bool,
+}
+
+#[embassy::task]
+async fn net_task(stack: &'static Stack<TunTapDevice, ThreadModeRawMutex>) {
+    stack.run().await
+}
+
+#[embassy::task]
+async fn main_task(spawner: Spawner) {
+    let opts: Opts = Opts::parse();
+
+    // Init network device
+    let device = TunTapDevice::new(&opts.tap).unwrap();
+
+    // Choose between dhcp or static ip
+    let config: &'static mut (dyn Configurator<TunTapDevice> + Send) = if opts.static_ip {
+        CONFIG_STATIC.put(StaticConfigurator::new(Config {
+            address: Ipv4Cidr::new(Ipv4Address::new(192, 168, 69, 2), 24),
+            dns_servers: Vec::new(),
+            gateway: Some(Ipv4Address::new(192, 168, 69, 1)),
+        }))
+    } else {
+        CONFIG_DYNAMIC.put(DhcpConfigurator::new(
+            DHCP_RESOURCES.put(DhcpResources::new()),
+        ))
+    };
+
+    let net_resources = StackResources::new();
+
+    // Init network stack
+    let stack = STACK.put(Stack::new(
+        DEVICE.put(device),
+        config,
+        NET_RESOURCES.put(net_resources),
+    ));
+
+    // Launch network task
+    spawner.spawn(net_task(stack)).unwrap();
+
+    // Then we can use it! Each connection is served by its own task, with a
+    // socket from the pool of the stack resources.
+    loop {
+        let mut socket = match TcpSocket::from_pool(stack) {
+            Ok(socket) => socket,
+            Err(e) => {
+                warn!("no free socket: {:?}", e);
+                Timer::after(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        socket.set_timeout(Some(embassy_net::SmolDuration::from_secs(10)));
+
+        info!("Listening on TCP:1234...");
+        if let Err(e) = socket.accept(1234).await {
+            warn!("accept error: {:?}", e);
+            continue;
+        }
+        info!("Received connection from {:?}", socket.remote_endpoint());
+
+        if spawner.spawn(echo_task(socket)).is_err() {
+            warn!("too many connections");
+        }
+    }
+}
+
+#[embassy::task(pool_size = 2)]
+async fn echo_task(mut socket: TcpSocket<'static>) {
+    let mut buf = [0; 1024];
+    loop {
+        let n = match socket.read(&mut buf).await {
+            Ok(0) => {
+                info!("read EOF");
+                break;
+            }
+            Ok(n) => n,
+            Err(e) => {
+                warn!("read error: {:?}", e);
+                break;
+            }
+        };
+
+        if let Err(e) = socket.write_all(&buf[..n]).await {
+            warn!("write error: {:?}", e);
+            break;
+        }
+    }
+}
+
+#[no_mangle]
+fn _embassy_rand(buf: &mut [u8]) {
+    use rand_core::{OsRng, RngCore};
+    OsRng.fill_bytes(buf);
+}
+
+static EXECUTOR: Forever<Executor> = Forever::new();
+
+fn main() {
+    env_logger::builder()
+        .filter_level(log::LevelFilter::Debug)
+        .filter_module("async_io", log::LevelFilter::Info)
+        .format_timestamp_nanos()
+        .init();
+
+    let executor = EXECUTOR.put(Executor::new());
+    executor.run(|spawner| {
+        spawner.spawn(main_task(spawner)).unwrap();
+    });
+}
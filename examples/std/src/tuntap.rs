@@ -129,45 +129,87 @@ impl io::Write for TunTap {
 pub struct TunTapDevice {
     device: Async<TunTap>,
     waker: Option<Waker>,
+    rx_buf: Vec<u8>,
+    tx_buf: Vec<u8>,
 }
 
 impl TunTapDevice {
     pub fn new(name: &str) -> io::Result<TunTapDevice> {
+        let device = TunTap::new(name)?;
+        let mtu = device.mtu;
         Ok(Self {
-            device: Async::new(TunTap::new(name)?)?,
+            device: Async::new(device)?,
             waker: None,
+            rx_buf: vec![0; mtu],
+            tx_buf: vec![0; mtu],
         })
     }
 }
 
 use core::task::Waker;
-use embassy_net::{
-    Device, DeviceCapabilities, LinkState, Packet, PacketBox, PacketBoxExt, PacketBuf,
-};
+use embassy_net::{Device, DeviceCapabilities, LinkState};
 use std::task::Context;
 
-impl Device for TunTapDevice {
-    fn is_transmit_ready(&mut self) -> bool {
-        true
+pub struct RxToken<'a> {
+    buf: &'a mut [u8],
+}
+
+impl<'a> embassy_net::RxToken for RxToken<'a> {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(self.buf)
     }
+}
+
+pub struct TxToken<'a> {
+    device: &'a mut Async<TunTap>,
+    buf: &'a mut [u8],
+}
+
+impl<'a> embassy_net::TxToken for TxToken<'a> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let buf = &mut self.buf[..len];
+        let r = f(buf);
 
-    fn transmit(&mut self, pkt: PacketBuf) {
         // todo handle WouldBlock
-        match self.device.get_mut().write(&pkt) {
+        match self.device.get_mut().write(buf) {
             Ok(_) => {}
             Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
                 info!("transmit WouldBlock");
             }
             Err(e) => panic!("transmit error: {:?}", e),
         }
+        r
     }
+}
 
-    fn receive(&mut self) -> Option<PacketBuf> {
-        let mut pkt = PacketBox::new(Packet::new()).unwrap();
+impl Device for TunTapDevice {
+    type RxToken<'a>
+        = RxToken<'a>
+    where
+        Self: 'a;
+    type TxToken<'a>
+        = TxToken<'a>
+    where
+        Self: 'a;
+
+    fn receive(&mut self) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
         loop {
-            match self.device.get_mut().read(&mut pkt[..]) {
+            match self.device.get_mut().read(&mut self.rx_buf) {
                 Ok(n) => {
-                    return Some(pkt.slice(0..n));
+                    let rx = RxToken {
+                        buf: &mut self.rx_buf[..n],
+                    };
+                    let tx = TxToken {
+                        device: &mut self.device,
+                        buf: &mut self.tx_buf,
+                    };
+                    return Some((rx, tx));
                 }
                 Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
                     let ready = if let Some(w) = self.waker.as_ref() {
@@ -185,6 +227,13 @@ impl Device for TunTapDevice {
         }
     }
 
+    fn transmit(&mut self) -> Option<Self::TxToken<'_>> {
+        Some(TxToken {
+            device: &mut self.device,
+            buf: &mut self.tx_buf,
+        })
+    }
+
     fn register_waker(&mut self, w: &Waker) {
         match self.waker {
             // Optimization: If both the old and new Wakers wake the same task, we can simply
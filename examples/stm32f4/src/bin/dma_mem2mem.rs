@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+use defmt::*;
+use defmt_rtt as _; // global logger
+use embassy::executor::Spawner;
+use embassy_stm32::dma::{self, Burst, FifoThreshold, TransferOptions};
+use embassy_stm32::Peripherals;
+use panic_probe as _;
+
+static mut SRC: [u32; 1024] = [0; 1024];
+static mut DST: [u32; 1024] = [0; 1024];
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    info!("Hello World!");
+
+    let (src, dst) = unsafe { (&mut SRC, &mut DST) };
+    for (i, w) in src.iter_mut().enumerate() {
+        *w = i as u32;
+    }
+
+    // Only DMA2 can copy from memory to memory.
+    let mut ch = p.DMA2_CH0;
+
+    dma::copy(&mut ch, &src[..], &mut dst[..]).await;
+    info!("copied: {}", &dst[..8]);
+
+    dst.fill(0);
+    let options = TransferOptions {
+        pburst: Burst::Incr4,
+        mburst: Burst::Incr4,
+        fifo_threshold: Some(FifoThreshold::Full),
+        ..Default::default()
+    };
+    dma::copy_with_options(&mut ch, &src[..], &mut dst[..], options).await;
+    info!("copied with bursts: {}", &dst[..8]);
+}
@@ -4,12 +4,14 @@
 
 use cortex_m_rt::entry;
 use defmt::*;
+use embassy::blocking_mutex::raw::ThreadModeRawMutex;
 use embassy::executor::{Executor, Spawner};
 use embassy::io::AsyncWriteExt;
 use embassy::time::{Duration, Timer};
 use embassy::util::Forever;
 use embassy_net::{
-    Config as NetConfig, Ipv4Address, Ipv4Cidr, StackResources, StaticConfigurator, TcpSocket,
+    Config as NetConfig, Ipv4Address, Ipv4Cidr, Stack, StackResources, StaticConfigurator,
+    TcpSocket,
 };
 use embassy_stm32::eth::lan8742a::LAN8742A;
 use embassy_stm32::eth::{Ethernet, State};
@@ -24,26 +26,28 @@ use heapless::Vec;
 use defmt_rtt as _; // global logger
 use panic_probe as _;
 
+type Device = Ethernet<'static, ETH, LAN8742A, 4, 4>;
+
 #[embassy::task]
 async fn main_task(
-    device: &'static mut Ethernet<'static, ETH, LAN8742A, 4, 4>,
+    device: &'static mut Device,
     config: &'static mut StaticConfigurator,
     spawner: Spawner,
 ) {
     let net_resources = NET_RESOURCES.put(StackResources::new());
 
     // Init network stack
-    embassy_net::init(device, config, net_resources);
+    let stack = STACK.put(Stack::new(device, config, net_resources));
 
     // Launch network task
-    unwrap!(spawner.spawn(net_task()));
+    unwrap!(spawner.spawn(net_task(stack)));
 
     info!("Network task initialized");
 
     // Then we can use it!
     let mut rx_buffer = [0; 1024];
     let mut tx_buffer = [0; 1024];
-    let mut socket = TcpSocket::new(&mut rx_buffer, &mut tx_buffer);
+    let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
 
     socket.set_timeout(Some(embassy_net::SmolDuration::from_secs(10)));
 
@@ -65,8 +69,8 @@ async fn main_task(
 }
 
 #[embassy::task]
-async fn net_task() {
-    embassy_net::run().await
+async fn net_task(stack: &'static Stack<Device, ThreadModeRawMutex>) {
+    stack.run().await
 }
 
 #[no_mangle]
@@ -82,9 +86,10 @@ static mut RNG_INST: Option<Rng<RNG>> = None;
 
 static EXECUTOR: Forever<Executor> = Forever::new();
 static STATE: Forever<State<'static, ETH, 4, 4>> = Forever::new();
-static ETH: Forever<Ethernet<'static, ETH, LAN8742A, 4, 4>> = Forever::new();
+static ETH: Forever<Device> = Forever::new();
 static CONFIG: Forever<StaticConfigurator> = Forever::new();
 static NET_RESOURCES: Forever<StackResources<1, 2, 8>> = Forever::new();
+static STACK: Forever<Stack<Device, ThreadModeRawMutex>> = Forever::new();
 
 fn config() -> Config {
     let mut config = Config::default();
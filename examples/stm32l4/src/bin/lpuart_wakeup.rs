@@ -0,0 +1,32 @@
+#![no_std]
+#![no_main]
+#![feature(type_alias_impl_trait)]
+
+use defmt::*;
+use defmt_rtt as _; // global logger
+use embassy::executor::Spawner;
+use embassy_stm32::dma::NoDma;
+use embassy_stm32::interrupt;
+use embassy_stm32::usart::lpuart::{ClockSource, Config, Lpuart, Wakeup};
+use embassy_stm32::Peripherals;
+use panic_probe as _;
+
+#[embassy::main]
+async fn main(_spawner: Spawner, p: Peripherals) {
+    info!("Hello World!");
+
+    let mut config = Config::default();
+    config.clock_source = ClockSource::Lse;
+    let irq = interrupt::take!(LPUART1);
+    let mut lpuart = Lpuart::new(p.LPUART1, irq, p.PA3, p.PA2, NoDma, NoDma, config);
+
+    lpuart.enable_wakeup(Wakeup::StartBit);
+
+    let mut buf = [0u8; 1];
+    loop {
+        lpuart.wait_for_wakeup().await;
+        unwrap!(lpuart.blocking_read(&mut buf));
+        info!("woken up by {:x}", buf[0]);
+        unwrap!(lpuart.blocking_write(&buf));
+    }
+}
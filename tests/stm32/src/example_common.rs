@@ -8,17 +8,6 @@ use panic_probe as _;
 
 pub use defmt::*;
 
-use core::sync::atomic::{AtomicUsize, Ordering};
-
-defmt::timestamp! {"{=u64}", {
-        static COUNT: AtomicUsize = AtomicUsize::new(0);
-        // NOTE(no-CAS) `timestamps` runs with interrupts disabled
-        let n = COUNT.load(Ordering::Relaxed);
-        COUNT.store(n + 1, Ordering::Relaxed);
-        n as u64
-    }
-}
-
 pub fn config() -> Config {
     #[allow(unused_mut)]
     let mut config = Config::default();